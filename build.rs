@@ -0,0 +1,16 @@
+// Build script
+//
+// Generates the gRPC services from proto/node.proto when the grpc feature is
+// enabled. The protocol is parsed in Rust, so no protoc binary is needed.
+
+fn main() {
+    println!("cargo:rerun-if-changed=proto/node.proto");
+
+    #[cfg(feature = "grpc")]
+    {
+        let descriptors = protox::compile(["node.proto"], ["proto"]).expect("failed to parse proto/node.proto");
+        tonic_build::configure()
+            .compile_fds(descriptors)
+            .expect("failed to generate the gRPC services");
+    }
+}
@@ -0,0 +1,75 @@
+use actix_web::{HttpRequest, HttpResponse};
+
+use super::errors::{ErrorCode, ErrorResponse};
+
+/// Header carrying the client API key
+pub const API_KEY_HEADER: &str = "X-API-Key";
+
+/// Gets the API key a client sent with a request
+///
+/// # Arguments
+///
+/// * `req` - The HTTP request
+///
+/// # Returns
+///
+/// The API key if the header is present and not empty
+pub fn api_key(req: &HttpRequest) -> Option<String> {
+    req.headers()
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+        .map(|value| value.to_string())
+}
+
+/// Guards administrative endpoints with a bearer token
+#[derive(Debug, Clone)]
+pub struct AdminAuth {
+    token: Option<String>,
+}
+
+impl AdminAuth {
+    /// Creates a new admin guard
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The admin token, or None to disable administrative endpoints
+    pub fn new(token: Option<String>) -> Self {
+        AdminAuth { token }
+    }
+
+    /// Checks that a request carries the admin token
+    ///
+    /// # Arguments
+    ///
+    /// * `req` - The HTTP request
+    ///
+    /// # Returns
+    ///
+    /// None if the request is authorized, or the error response to return
+    pub fn deny(&self, req: &HttpRequest) -> Option<HttpResponse> {
+        let token = match &self.token {
+            Some(token) => token,
+            None => {
+                return Some(HttpResponse::Forbidden().json(ErrorResponse::new(
+                    ErrorCode::NotPermitted,
+                    "Administrative endpoints are disabled (no admin token configured)",
+                )));
+            }
+        };
+
+        let provided = req
+            .headers()
+            .get("Authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+
+        match provided {
+            Some(provided) if provided == token => None,
+            _ => Some(HttpResponse::Unauthorized().json(ErrorResponse::new(
+                ErrorCode::Unauthorized,
+                "Missing or invalid admin token",
+            ))),
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use futures::future;
+use serde_json::json;
+use std::fmt;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+use crate::blockchain::validation::MINIMUM_FEE;
+use crate::blockchain::Wallet;
+
+/// Amount each benchmarked transaction sends
+const TRANSACTION_AMOUNT: f64 = 0.01;
+
+/// Longest a single request may take before it counts as an error
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Largest response body read, chain reads return the whole chain
+const MAX_RESPONSE_BYTES: usize = 512 * 1024 * 1024;
+
+/// Errors that can occur while benchmarking a node
+#[derive(Debug, Error)]
+pub enum BenchError {
+    #[error("Benchmark setup failed: {0}")]
+    Setup(String),
+}
+
+/// The workload to fire at a node
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchOptions {
+    /// Number of clients sending requests at once
+    pub concurrency: usize,
+
+    /// Number of transactions to submit
+    pub transactions: usize,
+
+    /// Number of times to read the chain
+    pub reads: usize,
+}
+
+/// A kind of request the benchmark sends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operation {
+    /// POST /api/v1/transactions/new
+    Submit,
+
+    /// GET /api/v1/chain
+    ReadChain,
+}
+
+/// Latencies of one kind of request
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyReport {
+    /// What was requested
+    pub name: String,
+
+    /// Requests sent
+    pub requests: usize,
+
+    /// Requests that failed or did not succeed, left out of the latencies
+    pub errors: usize,
+
+    /// Successful requests per second over the whole run
+    pub throughput: f64,
+
+    /// Median latency
+    pub p50: Duration,
+
+    /// 95th percentile latency
+    pub p95: Duration,
+
+    /// 99th percentile latency
+    pub p99: Duration,
+
+    /// Slowest request
+    pub max: Duration,
+}
+
+impl LatencyReport {
+    /// Summarizes the latencies of some requests
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - What was requested
+    /// * `latencies` - Latencies of the successful requests
+    /// * `errors` - Number of failed requests
+    /// * `elapsed` - Duration of the whole run
+    ///
+    /// # Returns
+    ///
+    /// The report
+    pub fn new(name: &str, mut latencies: Vec<Duration>, errors: usize, elapsed: Duration) -> Self {
+        latencies.sort();
+
+        LatencyReport {
+            name: name.to_string(),
+            requests: latencies.len() + errors,
+            errors,
+            throughput: latencies.len() as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+            p50: percentile(&latencies, 50.0),
+            p95: percentile(&latencies, 95.0),
+            p99: percentile(&latencies, 99.0),
+            max: latencies.last().copied().unwrap_or_default(),
+        }
+    }
+}
+
+impl fmt::Display for LatencyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |latency: Duration| latency.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "{}: {} requests, {} errors, {:.1} req/s, p50 {:.1} ms, p95 {:.1} ms, p99 {:.1} ms, max {:.1} ms",
+            self.name,
+            self.requests,
+            self.errors,
+            self.throughput,
+            ms(self.p50),
+            ms(self.p95),
+            ms(self.p99),
+            ms(self.max)
+        )
+    }
+}
+
+/// The outcome of a benchmark
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchReport {
+    /// Base URL of the node
+    pub node: String,
+
+    /// Number of clients that sent requests at once
+    pub concurrency: usize,
+
+    /// Duration of the run, setup excluded
+    pub elapsed: Duration,
+
+    /// Latencies of each kind of request that was sent
+    pub workloads: Vec<LatencyReport>,
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Benchmarked {} with {} concurrent clients in {:.2}s",
+            self.node,
+            self.concurrency,
+            self.elapsed.as_secs_f64()
+        )?;
+        for workload in &self.workloads {
+            writeln!(f, "  {}", workload)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Gets a percentile of sorted latencies by the nearest-rank method
+///
+/// # Arguments
+///
+/// * `sorted` - The latencies, fastest first
+/// * `percent` - The percentile, from 0 to 100
+pub fn percentile(sorted: &[Duration], percent: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+
+    let rank = (percent / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Fires transaction submissions and chain reads at a running node
+///
+/// Each client is given a funded wallet of its own, through the test
+/// funding endpoint, and sends its share of the requests one after the
+/// other, submissions and reads interleaved, so its nonces follow each
+/// other. Funding happens before the clock starts.
+///
+/// # Arguments
+///
+/// * `node` - Base URL of the node
+/// * `options` - The workload
+///
+/// # Returns
+///
+/// Result with the latencies of each kind of request
+pub async fn run(node: &str, options: &BenchOptions) -> Result<BenchReport, BenchError> {
+    let client = awc::Client::builder().timeout(REQUEST_TIMEOUT).finish();
+    let base_url = node.trim_end_matches('/');
+    let concurrency = options.concurrency.max(1);
+    let new_wallet = || Wallet::new().map_err(|err| BenchError::Setup(err.to_string()));
+
+    // Submissions are spread evenly among the reads, then dealt to the clients in turn
+    let total = options.transactions + options.reads;
+    let mut shares = vec![Vec::new(); concurrency];
+    for position in 0..total {
+        let submits = (position + 1) * options.transactions / total > position * options.transactions / total;
+        let operation = if submits { Operation::Submit } else { Operation::ReadChain };
+        shares[position % concurrency].push(operation);
+    }
+
+    let recipient = new_wallet()?;
+    let mut senders = Vec::new();
+    for share in &shares {
+        let sender = new_wallet()?;
+        let submissions = share.iter().filter(|operation| **operation == Operation::Submit).count();
+        let amount = (submissions as f64 + 1.0) * (TRANSACTION_AMOUNT + MINIMUM_FEE);
+        let url = format!("{}/api/v1/wallet/fund", base_url);
+        let funded = client
+            .post(&url)
+            .send_json(&json!({ "address": sender.address().0, "amount": amount }))
+            .await
+            .map_err(|err| BenchError::Setup(format!("Failed to fund a wallet at {}: {}", url, err)))?;
+        if !funded.status().is_success() {
+            return Err(BenchError::Setup(format!("Failed to fund a wallet at {}: status {}", url, funded.status())));
+        }
+        senders.push(sender);
+    }
+
+    let started = Instant::now();
+    let clients = shares.into_iter().zip(&senders).map(|(share, sender)| {
+        let client = client.clone();
+        let recipient = recipient.address().0.clone();
+        async move {
+            let mut results = Vec::new();
+            for operation in share {
+                let sent = Instant::now();
+                let succeeded = match operation {
+                    Operation::Submit => {
+                        let transaction = json!({
+                            "sender": sender.address().0,
+                            "recipient": recipient,
+                            "amount": TRANSACTION_AMOUNT,
+                            "fee": MINIMUM_FEE,
+                            "private_key": hex::encode(sender.export_secret_key()),
+                        });
+                        client.post(format!("{}/api/v1/transactions/new", base_url)).send_json(&transaction).await
+                    }
+                    Operation::ReadChain => client.get(format!("{}/api/v1/chain", base_url)).send().await,
+                };
+
+                // The latency includes reading the whole response
+                let succeeded = match succeeded {
+                    Ok(mut response) => {
+                        response.status().is_success() && response.body().limit(MAX_RESPONSE_BYTES).await.is_ok()
+                    }
+                    Err(_) => false,
+                };
+                results.push((operation, succeeded.then(|| sent.elapsed())));
+            }
+
+            results
+        }
+    });
+    let results: Vec<_> = future::join_all(clients).await.into_iter().flatten().collect();
+    let elapsed = started.elapsed();
+
+    let workload = |name: &str, kind: Operation, requested: usize| {
+        (requested > 0).then(|| {
+            let results = results.iter().filter(|(operation, _)| *operation == kind);
+            let latencies: Vec<Duration> = results.clone().filter_map(|(_, latency)| *latency).collect();
+            let errors = results.filter(|(_, latency)| latency.is_none()).count();
+            LatencyReport::new(name, latencies, errors, elapsed)
+        })
+    };
+
+    Ok(BenchReport {
+        node: base_url.to_string(),
+        concurrency,
+        elapsed,
+        workloads: [
+            workload("transaction submissions", Operation::Submit, options.transactions),
+            workload("chain reads", Operation::ReadChain, options.reads),
+        ]
+        .into_iter()
+        .flatten()
+        .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_percentiles() {
+        let latencies: Vec<Duration> = (1..=100).rev().map(Duration::from_millis).collect();
+        let report = LatencyReport::new("reads", latencies, 3, Duration::from_secs(2));
+
+        assert_eq!(report.requests, 103);
+        assert_eq!(report.throughput, 50.0);
+        assert_eq!(report.p50, Duration::from_millis(50));
+        assert_eq!(report.p95, Duration::from_millis(95));
+        assert_eq!(report.p99, Duration::from_millis(99));
+        assert_eq!(report.max, Duration::from_millis(100));
+        assert_eq!(percentile(&[Duration::from_millis(7)], 99.0), Duration::from_millis(7));
+        assert_eq!(percentile(&[], 50.0), Duration::ZERO);
+    }
+}
@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::blockchain::chain::BlockchainError;
+
+pub use crate::blockchain::errors::{ErrorCode, ErrorContext};
+
+impl From<&BlockchainError> for ErrorCode {
+    fn from(err: &BlockchainError) -> Self {
+        err.code()
+    }
+}
+
+/// Body of an error response
+///
+/// Some errors add fields, e.g. `reason` when the node is under maintenance
+/// or `required` and `available` for insufficient funds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ErrorResponse {
+    /// Machine-readable code of the error
+    pub code: ErrorCode,
+
+    /// Human-readable message
+    pub error: String,
+
+    /// Whether the same request may succeed later, unchanged
+    #[serde(default)]
+    pub retryable: bool,
+
+    /// The block, transaction or address the error is about
+    #[serde(flatten)]
+    pub context: ErrorContext,
+}
+
+impl ErrorResponse {
+    /// Creates an error body
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - Code of the error
+    /// * `error` - The message
+    pub fn new(code: ErrorCode, error: impl Into<String>) -> Self {
+        ErrorResponse {
+            code,
+            error: error.into(),
+            retryable: code.is_retryable(),
+            context: ErrorContext::default(),
+        }
+    }
+
+    /// Creates the error body of a blockchain error, with its code, retryability and context
+    ///
+    /// # Arguments
+    ///
+    /// * `err` - The error
+    /// * `error` - The message
+    pub fn from_error(err: &BlockchainError, error: impl Into<String>) -> Self {
+        ErrorResponse {
+            code: err.code(),
+            error: error.into(),
+            retryable: err.is_retryable(),
+            context: err.context(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::account::AccountError;
+    use crate::blockchain::names::NameError;
+    use crate::blockchain::transaction::TransactionError;
+
+    #[test]
+    fn test_codes_are_stable_strings() {
+        let err = BlockchainError::AccountError(AccountError::InvalidNonce { expected: 2, got: 1 });
+        let body = ErrorResponse::from_error(&err, err.to_string());
+        assert_eq!(
+            serde_json::to_value(&body).unwrap(),
+            serde_json::json!({
+                "code": "INVALID_NONCE",
+                "error": "Account error: Invalid nonce: expected 2, got 1",
+                "retryable": false
+            })
+        );
+
+        let fee = BlockchainError::TransactionError(TransactionError::FeeTooLow { fee: 0.0, minimum: 0.001 });
+        assert_eq!(ErrorCode::from(&fee), ErrorCode::FeeTooLow);
+        assert_eq!(serde_json::to_value(ErrorCode::StaleTip).unwrap(), "STALE_TIP");
+        assert_eq!(ErrorCode::StaleTip.to_string(), "STALE_TIP");
+        assert_eq!(serde_json::from_value::<ErrorCode>("INSUFFICIENT_FUNDS".into()).unwrap(), ErrorCode::InsufficientFunds);
+    }
+
+    #[test]
+    fn test_bodies_carry_retryability_and_context() {
+        let queued = BlockchainError::TransactionError(TransactionError::TooManyQueued("alice".to_string()));
+        let body = serde_json::to_value(ErrorResponse::from_error(&queued, queued.to_string())).unwrap();
+        assert_eq!(body["code"], "INVALID_NONCE");
+        assert_eq!(body["retryable"], true);
+        assert_eq!(body["address"], "alice");
+        assert!(body.get("height").is_none());
+
+        // A code alone decides the retryability of bodies built without an error
+        assert!(ErrorResponse::new(ErrorCode::Busy, "busy").retryable);
+        let pending = BlockchainError::NameError(NameError::Pending("alice".to_string()));
+        assert_eq!(pending.code(), ErrorCode::NameTaken);
+        assert!(pending.is_retryable() && !ErrorCode::NameTaken.is_retryable());
+
+        // Clients of older nodes read bodies without the new fields
+        let old: ErrorResponse = serde_json::from_str(r#"{"code": "BUSY", "error": "busy"}"#).unwrap();
+        assert!(!old.retryable);
+        assert_eq!(old.context, ErrorContext::default());
+    }
+}
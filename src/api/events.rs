@@ -0,0 +1,295 @@
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::time::{self, Interval};
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use super::errors::{ErrorCode, ErrorResponse};
+use super::handlers::BlockchainData;
+use crate::blockchain::events::{ChainEvent, ReorgEvent};
+use crate::blockchain::{Block, Blockchain};
+
+/// Most missed blocks a resuming client is sent, older ones it has to fetch itself
+pub const MAX_REPLAY_BLOCKS: usize = 1_000;
+
+/// How often an idle stream sends a comment, so proxies keep the connection open
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// An event of the stream, sent as JSON in the data of a Server-Sent Event named after it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum StreamEvent {
+    /// A block is the new tip, its transactions left the pending ones
+    Block {
+        index: u64,
+        hash: String,
+        previous_hash: String,
+        timestamp: DateTime<Utc>,
+        transactions: Vec<String>,
+    },
+
+    /// A transaction was admitted to the pending transactions
+    Transaction {
+        id: String,
+        sender: String,
+        recipient: String,
+        amount: f64,
+        fee: f64,
+    },
+
+    /// A pending transaction was evicted for waiting too long
+    Evicted {
+        id: String,
+        sender: String,
+        received_at: DateTime<Utc>,
+    },
+
+    /// The chain switched to another branch
+    Reorg(ReorgEvent),
+
+    /// The chain was reset or restored from a snapshot, clients should fetch it again
+    Reset { index: u64, hash: String },
+
+    /// The stream missed events, clients should fetch what they follow again
+    Lagged {},
+}
+
+impl StreamEvent {
+    /// Gets the name of the Server-Sent Event
+    pub fn name(&self) -> &'static str {
+        match self {
+            StreamEvent::Block { .. } => "block",
+            StreamEvent::Transaction { .. } => "transaction",
+            StreamEvent::Evicted { .. } => "evicted",
+            StreamEvent::Reorg(_) => "reorg",
+            StreamEvent::Reset { .. } => "reset",
+            StreamEvent::Lagged {} => "lagged",
+        }
+    }
+
+    /// Creates the event of a block
+    fn block(block: &Block) -> Self {
+        StreamEvent::Block {
+            index: block.index,
+            hash: block.hash.clone(),
+            previous_hash: block.previous_hash.clone(),
+            timestamp: block.timestamp,
+            transactions: block.transactions.iter().map(|transaction| transaction.id.clone()).collect(),
+        }
+    }
+}
+
+/// Formats an event as a Server-Sent Event
+///
+/// # Arguments
+///
+/// * `height` - Height of the tip when the event happened, its ID
+/// * `event` - The event
+pub fn format_event(height: u64, event: &StreamEvent) -> String {
+    let data = serde_json::to_string(event).unwrap_or_else(|_| "{}".to_string());
+    format!("id: {}\nevent: {}\ndata: {}\n\n", height, event.name(), data)
+}
+
+/// The events one client is streamed
+struct EventStream {
+    blockchain: BlockchainData,
+    events: broadcast::Receiver<ChainEvent>,
+    keep_alive: Interval,
+
+    /// Formatted events waiting to be sent
+    queued: VecDeque<String>,
+
+    /// Height of the last block sent, live blocks at or below it were replayed already
+    height: u64,
+}
+
+impl EventStream {
+    /// Starts a stream, replaying the blocks after the one a resuming client saw last
+    ///
+    /// Subscribes before reading the chain, so no block falls between the
+    /// replay and the live events.
+    ///
+    /// # Arguments
+    ///
+    /// * `blockchain` - The blockchain
+    /// * `last_event_id` - Height of the last event the client saw, None for a new client
+    fn new(blockchain: BlockchainData, last_event_id: Option<u64>) -> Self {
+        let events = blockchain.subscribe();
+        let chain = blockchain.get_chain();
+        let tip = chain.last().map(|block| block.index).unwrap_or(0);
+
+        let mut queued = VecDeque::new();
+        if let Some(seen) = last_event_id {
+            let missed = chain.get(seen as usize + 1..).unwrap_or_default();
+            if missed.len() > MAX_REPLAY_BLOCKS {
+                queued.push_back(format_event(tip, &StreamEvent::Lagged {}));
+            }
+            for block in &missed[missed.len().saturating_sub(MAX_REPLAY_BLOCKS)..] {
+                queued.push_back(format_event(block.index, &StreamEvent::block(block)));
+            }
+        }
+
+        let mut keep_alive = time::interval(KEEP_ALIVE_INTERVAL);
+        keep_alive.reset();
+        EventStream {
+            blockchain,
+            events,
+            keep_alive,
+            queued,
+            height: tip,
+        }
+    }
+
+    /// Queues the event of a chain event
+    fn on_event(&mut self, event: ChainEvent) {
+        let blockchain: &Blockchain = &self.blockchain;
+        let (height, event) = match event {
+            ChainEvent::BlockAdded { index, hash } => {
+                let block = match blockchain.get_blocks_by_hash(&[hash]).pop() {
+                    // Replayed already, or no longer on the chain, which the reorg that follows tells
+                    Some(block) if index > self.height => block,
+                    _ => return,
+                };
+                (index, StreamEvent::block(&block))
+            }
+            ChainEvent::TransactionAdmitted { id, sender, recipient, amount, fee } => (
+                self.height,
+                StreamEvent::Transaction {
+                    id,
+                    sender: sender.0,
+                    recipient: recipient.0,
+                    amount,
+                    fee,
+                },
+            ),
+            ChainEvent::TransactionEvicted { id, sender, received_at } => (
+                self.height,
+                StreamEvent::Evicted {
+                    id,
+                    sender: sender.0,
+                    received_at,
+                },
+            ),
+            ChainEvent::Reorg(reorg) => {
+                let height = blockchain
+                    .get_blocks_by_hash(std::slice::from_ref(&reorg.new_tip))
+                    .first()
+                    .map(|block| block.index)
+                    .unwrap_or_else(|| blockchain.get_last_block().index);
+                (height, StreamEvent::Reorg(reorg))
+            }
+            ChainEvent::Reset { genesis_hash } => (0, StreamEvent::Reset { index: 0, hash: genesis_hash }),
+            ChainEvent::Restored { index, hash } => (index, StreamEvent::Reset { index, hash }),
+        };
+
+        self.height = height;
+        self.queued.push_back(format_event(height, &event));
+    }
+
+    /// Waits for the next chunk of the stream
+    ///
+    /// # Returns
+    ///
+    /// The chunk, None once the blockchain stopped publishing events
+    async fn next(&mut self) -> Option<String> {
+        loop {
+            if let Some(chunk) = self.queued.pop_front() {
+                self.keep_alive.reset();
+                return Some(chunk);
+            }
+
+            tokio::select! {
+                event = self.events.recv() => match event {
+                    Ok(event) => self.on_event(event),
+                    Err(RecvError::Lagged(_)) => {
+                        let tip = self.blockchain.get_last_block().index;
+                        self.height = tip;
+                        self.queued.push_back(format_event(tip, &StreamEvent::Lagged {}));
+                    }
+                    Err(RecvError::Closed) => return None,
+                },
+                _ = self.keep_alive.tick() => return Some(": keep-alive\n\n".to_string()),
+            }
+        }
+    }
+}
+
+/// Stream chain events as Server-Sent Events
+///
+/// Streams `block`, `transaction`, `reorg`, `reset` and `lagged` events with
+/// JSON data, for clients that can't use WebSockets. The ID of each event is
+/// the height of the tip when it happened. A client reconnecting with the
+/// `Last-Event-ID` header is first sent the blocks after that height, up to
+/// the last 1,000 of them.
+#[utoipa::path(
+    get,
+    path = "/api/v1/events",
+    params(
+        ("Last-Event-ID" = Option<u64>, Header, description = "Height of the last event seen, to resume from")
+    ),
+    responses(
+        (status = 200, description = "Event stream opened", content_type = "text/event-stream"),
+        (status = 400, description = "Invalid Last-Event-ID", body = ErrorResponse)
+    )
+)]
+pub async fn stream(req: HttpRequest, blockchain: BlockchainData) -> HttpResponse {
+    let last_event_id = match req.headers().get("Last-Event-ID") {
+        None => None,
+        Some(value) => match value.to_str().ok().and_then(|value| value.trim().parse::<u64>().ok()) {
+            Some(height) => Some(height),
+            None => {
+                return HttpResponse::BadRequest().json(ErrorResponse::new(
+                    ErrorCode::InvalidRequest,
+                    "Last-Event-ID must be a block height",
+                ))
+            }
+        },
+    };
+
+    let events = EventStream::new(blockchain, last_event_id);
+    let body = futures::stream::unfold(events, |mut events| async move {
+        let chunk = events.next().await?;
+        Some((Ok::<_, actix_web::Error>(web::Bytes::from(chunk)), events))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .streaming(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Wallet;
+
+    #[actix_web::test]
+    async fn test_stream_resumes_after_the_last_event() {
+        let blockchain = web::Data::new(Blockchain::new());
+        let miner = Wallet::new().unwrap();
+        let first = blockchain.mine_block(&miner.address().0).unwrap();
+        let second = blockchain.mine_block(&miner.address().0).unwrap();
+
+        // A resuming client is sent the blocks it missed, a new one only live events
+        let mut resumed = EventStream::new(blockchain.clone(), Some(first.index));
+        let replayed = resumed.next().await.unwrap();
+        assert_eq!(replayed, format_event(2, &StreamEvent::block(&second)));
+        assert!(EventStream::new(blockchain.clone(), None).queued.is_empty());
+
+        // A block replayed already isn't sent again
+        resumed.on_event(ChainEvent::BlockAdded {
+            index: second.index,
+            hash: second.hash.clone(),
+        });
+        assert!(resumed.queued.is_empty());
+
+        let third = blockchain.mine_block(&miner.address().0).unwrap();
+        let event = resumed.next().await.unwrap();
+        assert!(event.starts_with("id: 3\nevent: block\ndata: {\"index\":3,"));
+        assert!(event.contains(&third.hash));
+    }
+}
@@ -0,0 +1,644 @@
+use chrono::SecondsFormat;
+use futures::Stream;
+use serde_json::{Map, Value};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use super::errors::ErrorCode;
+use super::handlers::{SubmissionStatus, MAX_BATCH_TRANSACTIONS};
+use super::limits::MiningLimiter;
+use crate::blockchain::chain::BlockchainError;
+use crate::blockchain::events::ChainEvent;
+use crate::blockchain::history::BlockRef;
+use crate::blockchain::mempool::Admission;
+use crate::blockchain::{self, Address, Blockchain};
+
+/// Types and services generated from `proto/node.proto`
+pub mod proto {
+    tonic::include_proto!("my_blockchain.v1");
+}
+
+use proto::account_service_server::{AccountService, AccountServiceServer};
+use proto::chain_service_server::{ChainService, ChainServiceServer};
+use proto::mining_service_server::{MiningService, MiningServiceServer};
+use proto::transaction_service_server::{TransactionService, TransactionServiceServer};
+
+/// Most blocks one `GetBlocks` call returns
+pub const MAX_BLOCKS_PER_REQUEST: usize = 100;
+
+/// Transaction fields with a field of their own in the protocol, the others go in `extensions`
+const TYPED_FIELDS: [&str; 14] = [
+    "version",
+    "id",
+    "sender",
+    "recipient",
+    "amount",
+    "fee",
+    "nonce",
+    "signature",
+    "timestamp",
+    "extra_nonce",
+    "message",
+    "name",
+    "data",
+    "fee_payer",
+];
+
+impl From<&blockchain::Transaction> for proto::Transaction {
+    fn from(transaction: &blockchain::Transaction) -> Self {
+        // Whatever has no field of its own keeps its JSON form
+        let extensions = match serde_json::to_value(transaction) {
+            Ok(Value::Object(fields)) => {
+                let rest: Map<String, Value> = fields
+                    .into_iter()
+                    .filter(|(key, value)| !TYPED_FIELDS.contains(&key.as_str()) && !value.is_null())
+                    .collect();
+                if rest.is_empty() {
+                    String::new()
+                } else {
+                    Value::Object(rest).to_string()
+                }
+            }
+            _ => String::new(),
+        };
+
+        proto::Transaction {
+            version: transaction.version,
+            id: transaction.id.clone(),
+            sender: transaction.sender.0.clone(),
+            recipient: transaction.recipient.0.clone(),
+            amount: transaction.amount,
+            fee: transaction.fee,
+            nonce: transaction.nonce,
+            signature: transaction.signature.as_ref().map(|signature| signature.0.clone()).unwrap_or_default(),
+            timestamp: transaction.timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            extra_nonce: transaction.extra_nonce,
+            message: transaction.message.clone(),
+            name: transaction.name.clone(),
+            data: transaction.data.clone(),
+            fee_payer: transaction.fee_payer.as_ref().map(|fee_payer| proto::FeePayer {
+                address: fee_payer.address.0.clone(),
+                signature: fee_payer.signature.as_ref().map(|signature| signature.0.clone()).unwrap_or_default(),
+            }),
+            extensions,
+        }
+    }
+}
+
+impl TryFrom<proto::Transaction> for blockchain::Transaction {
+    type Error = String;
+
+    fn try_from(transaction: proto::Transaction) -> Result<Self, Self::Error> {
+        let mut fields = match transaction.extensions.as_str() {
+            "" => Map::new(),
+            extensions => match serde_json::from_str(extensions) {
+                Ok(Value::Object(fields)) => fields,
+                _ => return Err("extensions must be a JSON object".to_string()),
+            },
+        };
+        if let Some(key) = fields.keys().find(|key| TYPED_FIELDS.contains(&key.as_str())) {
+            return Err(format!("{} has a field of its own, it can't be in extensions", key));
+        }
+
+        let signature = |signature: String| Some(signature).filter(|signature| !signature.is_empty());
+        let typed = serde_json::json!({
+            "version": transaction.version,
+            "id": transaction.id,
+            "sender": transaction.sender,
+            "recipient": transaction.recipient,
+            "amount": transaction.amount,
+            "fee": transaction.fee,
+            "nonce": transaction.nonce,
+            "signature": signature(transaction.signature),
+            "timestamp": transaction.timestamp,
+            "extra_nonce": transaction.extra_nonce,
+            "message": transaction.message,
+            "name": transaction.name,
+            "data": transaction.data,
+            "fee_payer": transaction.fee_payer.map(|fee_payer| serde_json::json!({
+                "address": fee_payer.address,
+                "signature": signature(fee_payer.signature),
+            })),
+        });
+        if let Value::Object(typed) = typed {
+            fields.extend(typed);
+        }
+
+        serde_json::from_value(Value::Object(fields)).map_err(|err| err.to_string())
+    }
+}
+
+impl From<&blockchain::Block> for proto::Block {
+    fn from(block: &blockchain::Block) -> Self {
+        proto::Block {
+            version: block.version,
+            index: block.index,
+            timestamp: block.timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            transactions: block.transactions.iter().map(proto::Transaction::from).collect(),
+            proof: block.proof,
+            previous_hash: block.previous_hash.clone(),
+            hash: block.hash.clone(),
+        }
+    }
+}
+
+/// Converts an error code into a gRPC status
+///
+/// The code of the REST API is sent along in the `error-code` metadata, so
+/// clients can tell apart errors that share a gRPC code, and whether the
+/// call may be retried unchanged in the `retryable` metadata.
+///
+/// # Arguments
+///
+/// * `code` - The error code
+/// * `message` - The message
+pub fn error_status(code: ErrorCode, message: impl Into<String>) -> Status {
+    retryable_status(code, code.is_retryable(), message)
+}
+
+/// Converts an error code into a gRPC status, saying whether the call may be retried
+fn retryable_status(code: ErrorCode, retryable: bool, message: impl Into<String>) -> Status {
+    let grpc_code = match code {
+        ErrorCode::InvalidRequest
+        | ErrorCode::InvalidKey
+        | ErrorCode::InvalidSignature
+        | ErrorCode::InvalidTransaction
+        | ErrorCode::InvalidBlock
+        | ErrorCode::InvalidChain
+        | ErrorCode::UnsupportedVersion
+        | ErrorCode::InvalidName
+        | ErrorCode::InvalidAnchor
+        | ErrorCode::InvalidProposal
+        | ErrorCode::InvalidScript
+        | ErrorCode::InvalidSession
+        | ErrorCode::InvalidInheritance
+        | ErrorCode::Rejected => tonic::Code::InvalidArgument,
+        ErrorCode::Unauthorized => tonic::Code::Unauthenticated,
+        ErrorCode::NotPermitted | ErrorCode::AccountFrozen | ErrorCode::ReadOnly => tonic::Code::PermissionDenied,
+        ErrorCode::NotFound => tonic::Code::NotFound,
+        ErrorCode::AlreadyExists | ErrorCode::NameTaken => tonic::Code::AlreadyExists,
+        ErrorCode::InsufficientFunds | ErrorCode::InvalidNonce | ErrorCode::FeeTooLow | ErrorCode::StaleTip => {
+            tonic::Code::FailedPrecondition
+        }
+        ErrorCode::Busy | ErrorCode::QuotaExceeded => tonic::Code::ResourceExhausted,
+        ErrorCode::Paused | ErrorCode::NetworkDisabled => tonic::Code::Unavailable,
+        ErrorCode::Internal => tonic::Code::Internal,
+    };
+
+    let mut status = Status::new(grpc_code, message);
+    if let Ok(name) = code.to_string().parse() {
+        status.metadata_mut().insert("error-code", name);
+    }
+    if let Ok(retryable) = retryable.to_string().parse() {
+        status.metadata_mut().insert("retryable", retryable);
+    }
+    status
+}
+
+/// Converts a blockchain error into a gRPC status
+fn blockchain_status(err: &BlockchainError) -> Status {
+    retryable_status(err.code(), err.is_retryable(), err.to_string())
+}
+
+/// The blocks one subscriber is streamed
+struct BlockStream {
+    blockchain: Arc<Blockchain>,
+    events: broadcast::Receiver<ChainEvent>,
+
+    /// Blocks read from the chain, waiting to be sent
+    queued: VecDeque<blockchain::Block>,
+
+    /// Height of the next block to send
+    next_height: u64,
+}
+
+impl BlockStream {
+    /// Starts a stream after a height, or after the tip
+    fn new(blockchain: Arc<Blockchain>, after_height: Option<u64>) -> Self {
+        // Subscribe before reading the tip, so no block falls in between
+        let events = blockchain.subscribe();
+        let tip = blockchain.get_last_block().index;
+
+        BlockStream {
+            blockchain,
+            events,
+            queued: VecDeque::new(),
+            next_height: after_height.unwrap_or(tip).saturating_add(1),
+        }
+    }
+
+    /// Waits for the next block to send
+    ///
+    /// # Returns
+    ///
+    /// The block, None once the blockchain stopped publishing events
+    async fn next(&mut self) -> Option<blockchain::Block> {
+        loop {
+            if let Some(block) = self.queued.pop_front() {
+                self.next_height = block.index + 1;
+                return Some(block);
+            }
+
+            // Catch up with the chain a page at a time
+            let tip = self.blockchain.get_last_block().index;
+            if self.next_height <= tip {
+                let last = tip.min(self.next_height + MAX_BLOCKS_PER_REQUEST as u64 - 1);
+                for height in self.next_height..=last {
+                    match self.blockchain.get_block_by_ref(&BlockRef::Height(height)) {
+                        Ok(block) => self.queued.push_back(block),
+                        Err(_) => break,
+                    }
+                }
+                if !self.queued.is_empty() {
+                    continue;
+                }
+            }
+
+            match self.events.recv().await {
+                Ok(ChainEvent::BlockAdded { .. }) | Err(RecvError::Lagged(_)) => {}
+                Ok(ChainEvent::Reorg(reorg)) => self.next_height = self.next_height.min(reorg.fork_height + 1),
+                Ok(ChainEvent::Reset { .. } | ChainEvent::Restored { .. }) => self.next_height = 0,
+                Ok(ChainEvent::TransactionAdmitted { .. } | ChainEvent::TransactionEvicted { .. }) => {}
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// The gRPC services of a node
+#[derive(Clone)]
+pub struct NodeService {
+    blockchain: Arc<Blockchain>,
+    limiter: MiningLimiter,
+}
+
+impl NodeService {
+    /// Creates the services
+    ///
+    /// # Arguments
+    ///
+    /// * `blockchain` - The blockchain to serve
+    /// * `limiter` - The mining limiter, shared with the REST API
+    pub fn new(blockchain: Arc<Blockchain>, limiter: MiningLimiter) -> Self {
+        NodeService { blockchain, limiter }
+    }
+
+    /// Serves the services until the server fails
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address to listen on
+    pub async fn serve(self, address: SocketAddr) -> Result<(), tonic::transport::Error> {
+        Server::builder()
+            .add_service(ChainServiceServer::new(self.clone()))
+            .add_service(TransactionServiceServer::new(self.clone()))
+            .add_service(MiningServiceServer::new(self.clone()))
+            .add_service(AccountServiceServer::new(self))
+            .serve(address)
+            .await
+    }
+}
+
+#[tonic::async_trait]
+impl ChainService for NodeService {
+    type SubscribeBlocksStream = Pin<Box<dyn Stream<Item = Result<proto::Block, Status>> + Send>>;
+
+    async fn get_chain_info(
+        &self,
+        _request: Request<proto::GetChainInfoRequest>,
+    ) -> Result<Response<proto::ChainInfo>, Status> {
+        let tip = self.blockchain.get_last_block();
+        let genesis = self
+            .blockchain
+            .get_block_by_ref(&BlockRef::Height(0))
+            .map_err(|err| blockchain_status(&err))?;
+
+        Ok(Response::new(proto::ChainInfo {
+            height: tip.index,
+            tip_hash: tip.hash,
+            genesis_hash: genesis.hash,
+            pending_transactions: self.blockchain.get_pending_transactions().len() as u64,
+            base_fee: self.blockchain.get_base_fee(),
+        }))
+    }
+
+    async fn get_block(&self, request: Request<proto::GetBlockRequest>) -> Result<Response<proto::Block>, Status> {
+        let at = match request.into_inner().block {
+            Some(proto::get_block_request::Block::Height(height)) => BlockRef::Height(height),
+            Some(proto::get_block_request::Block::Hash(hash)) => BlockRef::Hash(hash),
+            None => return Err(error_status(ErrorCode::InvalidRequest, "Give a height or a hash")),
+        };
+
+        match self.blockchain.get_block_by_ref(&at) {
+            Ok(block) => Ok(Response::new(proto::Block::from(&block))),
+            Err(err) => Err(blockchain_status(&err)),
+        }
+    }
+
+    async fn get_blocks(
+        &self,
+        request: Request<proto::GetBlocksRequest>,
+    ) -> Result<Response<proto::GetBlocksResponse>, Status> {
+        let request = request.into_inner();
+        let count = match request.count as usize {
+            0 => MAX_BLOCKS_PER_REQUEST,
+            count => count.min(MAX_BLOCKS_PER_REQUEST),
+        };
+
+        let blocks = self
+            .blockchain
+            .get_chain()
+            .iter()
+            .skip(request.from_height as usize)
+            .take(count)
+            .map(proto::Block::from)
+            .collect();
+        Ok(Response::new(proto::GetBlocksResponse { blocks }))
+    }
+
+    async fn subscribe_blocks(
+        &self,
+        request: Request<proto::SubscribeBlocksRequest>,
+    ) -> Result<Response<Self::SubscribeBlocksStream>, Status> {
+        let blocks = BlockStream::new(self.blockchain.clone(), request.into_inner().after_height);
+        let stream = futures::stream::unfold(blocks, |mut blocks| async move {
+            let block = blocks.next().await?;
+            Some((Ok(proto::Block::from(&block)), blocks))
+        });
+
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+#[tonic::async_trait]
+impl TransactionService for NodeService {
+    async fn submit_transactions(
+        &self,
+        request: Request<proto::SubmitTransactionsRequest>,
+    ) -> Result<Response<proto::SubmitTransactionsResponse>, Status> {
+        if self.blockchain.is_follower() {
+            return Err(blockchain_status(&BlockchainError::ReadOnly));
+        }
+
+        let transactions = request.into_inner().transactions;
+        if transactions.is_empty() || transactions.len() > MAX_BATCH_TRANSACTIONS {
+            return Err(error_status(
+                ErrorCode::InvalidRequest,
+                format!("A batch holds 1 to {} transactions", MAX_BATCH_TRANSACTIONS),
+            ));
+        }
+
+        let transactions = transactions
+            .into_iter()
+            .enumerate()
+            .map(|(position, transaction)| {
+                blockchain::Transaction::try_from(transaction).map_err(|err| format!("Transaction {}: {}", position, err))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| error_status(ErrorCode::InvalidRequest, err))?;
+
+        let ids: Vec<String> = transactions.iter().map(|transaction| transaction.id.clone()).collect();
+        let chain = self.blockchain.clone();
+        let outcomes = tokio::task::spawn_blocking(move || chain.submit_batch(transactions))
+            .await
+            .map_err(|err| error_status(ErrorCode::Internal, format!("Failed to submit the batch: {}", err)))?;
+
+        let results = ids
+            .into_iter()
+            .zip(outcomes)
+            .map(|(id, outcome)| {
+                let (status, replaced, error) = match outcome {
+                    Ok(Admission::Accepted) => (SubmissionStatus::Accepted, None, None),
+                    Ok(Admission::Replaced(replaced)) => (SubmissionStatus::Replaced, Some(replaced), None),
+                    Ok(Admission::Queued) => (SubmissionStatus::Queued, None, None),
+                    Ok(Admission::Known) => (SubmissionStatus::Known, None, None),
+                    Err(err) => (SubmissionStatus::Rejected, None, Some(err)),
+                };
+                proto::SubmissionResult {
+                    id,
+                    status: match serde_json::to_value(status) {
+                        Ok(Value::String(status)) => status,
+                        _ => format!("{:?}", status),
+                    },
+                    replaced,
+                    code: error.as_ref().map(|err| err.code().to_string()),
+                    error: error.map(|err| err.to_string()),
+                }
+            })
+            .collect();
+
+        Ok(Response::new(proto::SubmitTransactionsResponse { results }))
+    }
+
+    async fn get_pending_transactions(
+        &self,
+        _request: Request<proto::GetPendingTransactionsRequest>,
+    ) -> Result<Response<proto::GetPendingTransactionsResponse>, Status> {
+        let entries = self.blockchain.get_pending_entries();
+        let transactions = entries.iter().map(|entry| proto::Transaction::from(&entry.transaction)).collect();
+        let received_at = entries
+            .iter()
+            .map(|entry| entry.received_at.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+            .collect();
+
+        Ok(Response::new(proto::GetPendingTransactionsResponse { transactions, received_at }))
+    }
+}
+
+#[tonic::async_trait]
+impl MiningService for NodeService {
+    async fn mine(&self, request: Request<proto::MineRequest>) -> Result<Response<proto::Block>, Status> {
+        let permit = self
+            .limiter
+            .acquire()
+            .await
+            .ok_or_else(|| error_status(ErrorCode::Busy, "A block is already being mined, try again later"))?;
+
+        // Seal the block off the async workers, releasing the permit when done
+        let request = request.into_inner();
+        let chain = self.blockchain.clone();
+        let mined = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            chain.mine_block_with_coinbase(&request.miner_address, request.extra_nonce, request.message.as_deref())
+        })
+        .await
+        .map_err(|err| error_status(ErrorCode::Internal, format!("Failed to mine block: {}", err)))?;
+
+        match mined {
+            Ok(block) => Ok(Response::new(proto::Block::from(&block))),
+            Err(err) => Err(blockchain_status(&err)),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl AccountService for NodeService {
+    async fn get_account(&self, request: Request<proto::GetAccountRequest>) -> Result<Response<proto::Account>, Status> {
+        let address = Address(request.into_inner().address);
+        let account = self.blockchain.get_account_state().get_account(&address);
+
+        Ok(Response::new(proto::Account {
+            balance: account.balance,
+            nonce: account.nonce,
+            next_nonce: self.blockchain.get_next_nonce(&address),
+            frozen: self.blockchain.is_frozen(&address),
+            address: address.0,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Wallet;
+    use proto::account_service_client::AccountServiceClient;
+    use proto::chain_service_client::ChainServiceClient;
+    use proto::mining_service_client::MiningServiceClient;
+    use proto::transaction_service_client::TransactionServiceClient;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn test_services_mirror_the_rest_api() {
+        let blockchain = Arc::new(Blockchain::new());
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let address: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+        tokio::spawn(NodeService::new(blockchain.clone(), MiningLimiter::new(1)).serve(address));
+
+        let endpoint = format!("http://{}", address);
+        let mut chain = loop {
+            match ChainServiceClient::connect(endpoint.clone()).await {
+                Ok(client) => break client,
+                Err(_) => tokio::time::sleep(std::time::Duration::from_millis(20)).await,
+            }
+        };
+        let mut transactions = TransactionServiceClient::connect(endpoint.clone()).await.unwrap();
+        let mut mining = MiningServiceClient::connect(endpoint.clone()).await.unwrap();
+        let mut accounts = AccountServiceClient::connect(endpoint).await.unwrap();
+
+        let mut blocks = chain
+            .subscribe_blocks(proto::SubscribeBlocksRequest { after_height: None })
+            .await
+            .unwrap()
+            .into_inner();
+
+        // A transaction signed for the REST API is submitted as is
+        let alice = Wallet::new().unwrap();
+        let miner = Wallet::new().unwrap();
+        let mut account = blockchain.get_account_state().get_account(alice.address());
+        account.deposit(20.0).unwrap();
+        blockchain.get_account_state().update_account(account);
+        let mut transaction = blockchain::Transaction::new(
+            alice.address().clone(),
+            miner.address().clone(),
+            5.0,
+            blockchain.get_base_fee(),
+            0,
+        );
+        transaction.sign(&alice).unwrap();
+        let submitted = transactions
+            .submit_transactions(proto::SubmitTransactionsRequest {
+                transactions: vec![proto::Transaction::from(&transaction)],
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(submitted.results[0].status, "accepted");
+
+        // A tampered one is rejected with the REST error code
+        let tampered = proto::Transaction { amount: 15.0, ..proto::Transaction::from(&transaction) };
+        let rejected = transactions
+            .submit_transactions(proto::SubmitTransactionsRequest { transactions: vec![tampered] })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(rejected.results[0].status, "rejected");
+        assert!(rejected.results[0].code.is_some());
+
+        // So is a coinbase, only miners make those
+        let coinbase = blockchain::Transaction::new_coinbase(alice.address().clone(), 1_000_000.0);
+        let minted = transactions
+            .submit_transactions(proto::SubmitTransactionsRequest {
+                transactions: vec![proto::Transaction::from(&coinbase)],
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(minted.results[0].status, "rejected");
+        assert_eq!(minted.results[0].code.as_deref(), Some("INVALID_TRANSACTION"));
+
+        let mined = mining
+            .mine(proto::MineRequest {
+                miner_address: miner.address().0.clone(),
+                extra_nonce: None,
+                message: None,
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(mined.index, 1);
+        assert!(mined.transactions.iter().any(|mined| mined.id == transaction.id));
+
+        // New blocks are streamed
+        let streamed = blocks.next().await.unwrap().unwrap();
+        assert_eq!(streamed, mined);
+
+        let info = chain.get_chain_info(proto::GetChainInfoRequest {}).await.unwrap().into_inner();
+        assert_eq!(info.tip_hash, mined.hash);
+        let block = chain
+            .get_block(proto::GetBlockRequest {
+                block: Some(proto::get_block_request::Block::Hash(mined.hash.clone())),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(block, mined);
+
+        let account = accounts
+            .get_account(proto::GetAccountRequest { address: alice.address().0.clone() })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(account.nonce, 1);
+        assert!(account.balance < 15.0);
+
+        let missing = chain
+            .get_block(proto::GetBlockRequest { block: Some(proto::get_block_request::Block::Height(9)) })
+            .await
+            .unwrap_err();
+        assert_eq!(missing.code(), tonic::Code::NotFound);
+        assert_eq!(missing.metadata().get("error-code").unwrap(), "NOT_FOUND");
+        assert_eq!(missing.metadata().get("retryable").unwrap(), "false");
+    }
+
+    #[test]
+    fn test_transactions_convert_without_loss() {
+        let wallet = Wallet::new().unwrap();
+        let mut transaction = blockchain::Transaction::new(
+            wallet.address().clone(),
+            Address("recipient".to_string()),
+            1.5,
+            0.1,
+            3,
+        );
+        transaction.message = Some("rent".to_string());
+        transaction.sign(&wallet).unwrap();
+
+        let converted = proto::Transaction::from(&transaction);
+        assert!(converted.extensions.is_empty());
+        let back = blockchain::Transaction::try_from(converted).unwrap();
+        assert_eq!(serde_json::to_value(&back).unwrap(), serde_json::to_value(&transaction).unwrap());
+        assert!(back.verify_signature().unwrap());
+
+        let smuggled = proto::Transaction {
+            extensions: r#"{"amount": 100.0}"#.to_string(),
+            ..proto::Transaction::from(&transaction)
+        };
+        assert!(blockchain::Transaction::try_from(smuggled).is_err());
+    }
+}
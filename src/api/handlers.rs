@@ -1,13 +1,93 @@
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::Duration;
 use utoipa::ToSchema;
 
-use crate::blockchain::{Block, Blockchain, Transaction, Address, Wallet};
-use crate::blockchain::account::Account;
+use crate::blockchain::{Block, Blockchain, Transaction, Address, DigitalSignature, Wallet};
+use crate::blockchain::anchors;
+use crate::blockchain::attack::{self, AttackParameters, MAX_TRIALS};
+use crate::blockchain::audit::AuditLog;
+use crate::blockchain::canonical;
+use crate::blockchain::chain::BlockchainError;
+use crate::blockchain::economics::{DEFAULT_PERIOD_BLOCKS, DEFAULT_PROJECTED_PERIODS, MAX_PROJECTED_PERIODS};
+use crate::blockchain::governance::{self, ChainParameters, GovernanceAction, GovernanceError, Parameter, ParameterChange};
+use crate::blockchain::maintenance::MaintenanceStatus;
+use crate::blockchain::mempool::Admission;
+use crate::blockchain::index::TransactionFilter;
+use crate::blockchain::ledger::LedgerFormat;
+use crate::blockchain::inheritance::{self, InheritanceAction, InheritanceError};
+use crate::blockchain::graph::{GraphFormat, TransactionGraph};
+use crate::blockchain::history::BlockRef;
+use crate::blockchain::state::ChainState;
+use crate::blockchain::backup::{BackupError, BackupSecret, EncryptedBackup};
+use crate::blockchain::keystore::{Keystore, KeystoreError, WalletInfo};
+use crate::blockchain::labels::{LabelStore, TransactionLabel};
+use crate::blockchain::names::{self, NameError};
+use crate::blockchain::permissions::{self, FreezeOrder, PermissionChange, PermissionError};
+use crate::blockchain::node_keys::{KeyPurpose, KeyRingInfo, NodeKeys, MAX_KEY_OVERLAP_SECS};
+use crate::blockchain::reports::Granularity;
+use crate::blockchain::schedules::{self, ScheduleError, ScheduleTerms, Scheduler};
+use crate::blockchain::scripts::{self, Condition, ScriptAction, ScriptError, Witness, WitnessSignature};
+use crate::blockchain::sessions::{self, SessionAction};
+use crate::blockchain::signing::{self, SigningContext};
+use crate::blockchain::simulation::{self, ConsensusRules};
+use crate::blockchain::snapshots::{SnapshotError, SnapshotStore, SnapshotTrigger};
+use crate::blockchain::treasury::Disbursement;
+use crate::blockchain::usage::UsageMeter;
+use crate::blockchain::payments::{PaymentError, PaymentRegistry};
+use crate::blockchain::peers::PeerError;
+use crate::network::discovery;
+use crate::blockchain::withdrawals::{WithdrawalError, WithdrawalQueue, WithdrawalStatus, WithdrawalTerms};
+use super::auth::{self, AdminAuth};
+use super::errors::{ErrorCode, ErrorResponse};
+use super::limits::MiningLimiter;
+use super::mining::{BlockTemplates, MiningJobs};
+use super::usage;
 
 /// Data structure for the blockchain state
 pub type BlockchainData = web::Data<Blockchain>;
 
+/// Data structure for the payment registry
+pub type PaymentsData = web::Data<PaymentRegistry>;
+
+/// Data structure for the withdrawal queue
+pub type WithdrawalsData = web::Data<WithdrawalQueue>;
+
+/// Data structure for the payment scheduler
+pub type SchedulerData = web::Data<Scheduler>;
+
+/// Data structure for the audit log
+pub type AuditData = web::Data<AuditLog>;
+
+/// Data structure for the admin guard
+pub type AdminData = web::Data<AdminAuth>;
+
+/// Data structure for the keystore
+pub type KeystoreData = web::Data<Keystore>;
+
+/// Data structure for the transaction labels
+pub type LabelsData = web::Data<LabelStore>;
+
+/// Data structure for the node keys
+pub type NodeKeysData = web::Data<NodeKeys>;
+
+/// Data structure for the mining limiter
+pub type MiningLimiterData = web::Data<MiningLimiter>;
+
+/// Data structure for the background mining jobs
+pub type MiningJobsData = web::Data<MiningJobs>;
+
+/// Data structure for the block templates of external miners
+pub type BlockTemplatesData = web::Data<BlockTemplates>;
+
+/// Data structure for the storage snapshots
+pub type SnapshotsData = web::Data<SnapshotStore>;
+
+/// Data structure for the API usage meter
+pub type UsageData = web::Data<UsageMeter>;
+
 /// Response for the chain endpoint
 #[derive(Serialize, Deserialize, ToSchema)]
 pub struct ChainResponse {
@@ -27,7 +107,7 @@ pub struct TransactionRequest {
     /// The sender's address
     pub sender: String,
 
-    /// The recipient's address
+    /// The recipient's address or registered name
     pub recipient: String,
 
     /// The amount to transfer
@@ -38,6 +118,13 @@ pub struct TransactionRequest {
 
     /// The sender's private key (for signing)
     pub private_key: String,
+
+    /// Private key of a third party paying the fee instead of the sender, who then needs no more than the amount
+    pub fee_payer_private_key: Option<String>,
+
+    /// Whether `private_key` is a session key the sender authorized rather than the sender's own
+    #[serde(default)]
+    pub session: bool,
 }
 
 /// Response for the transaction endpoint
@@ -55,6 +142,19 @@ pub struct TransactionResponse {
 pub struct MineRequest {
     /// The miner's address
     pub miner_address: String,
+
+    /// Extra-nonce to put in the coinbase, random if omitted
+    pub extra_nonce: Option<u64>,
+
+    /// Message to embed in the coinbase (at most 100 bytes)
+    pub message: Option<String>,
+}
+
+/// Query parameter of the mine endpoint
+#[derive(Serialize, Deserialize)]
+pub struct MineQuery {
+    /// Whether to answer right away with a job to follow instead of waiting for the block (default false)
+    pub background: Option<bool>,
 }
 
 /// Response for the mine endpoint
@@ -67,19 +167,122 @@ pub struct MineResponse {
     pub block: Block,
 }
 
+/// Query parameters of the block template endpoint
+#[derive(Serialize, Deserialize)]
+pub struct BlockTemplateQuery {
+    /// The miner's address
+    pub miner_address: String,
+
+    /// Extra-nonce to put in the coinbase, random if omitted
+    pub extra_nonce: Option<u64>,
+
+    /// Message to embed in the coinbase (at most 100 bytes)
+    pub message: Option<String>,
+}
+
+/// Response for the block template endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BlockTemplateResponse {
+    /// ID to submit the solution with
+    pub template_id: String,
+
+    /// Number of leading zeros the hex hash of the block needs
+    pub difficulty: u8,
+
+    /// Prefix the hex hash of the block needs
+    pub target: String,
+
+    /// The block to seal: set its proof, then hash it like `Block::calculate_hash`
+    pub block: Block,
+}
+
+/// Request for the block submit endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SubmitBlockRequest {
+    /// ID of the template the block was sealed from
+    pub template_id: String,
+
+    /// The proof found
+    pub proof: u64,
+
+    /// Hash of the block with the proof
+    pub hash: String,
+}
+
+/// Query parameter of the read endpoints that can serve a past state
+#[derive(Serialize, Deserialize)]
+pub struct AtBlockQuery {
+    /// Hash or height of the block to read the state after, the tip if omitted
+    pub at_block: Option<String>,
+}
+
+/// Reads the at_block parameter
+///
+/// # Returns
+///
+/// The block, None for the tip
+fn parse_at_block(at_block: Option<&str>) -> Result<Option<BlockRef>, BlockchainError> {
+    at_block.map(str::parse).transpose().map_err(BlockchainError::InvalidBlock)
+}
+
+/// Gets the state after the at_block block
+///
+/// # Returns
+///
+/// The state, None to read the live state
+fn state_at(blockchain: &Blockchain, at_block: Option<&str>) -> Result<Option<ChainState>, BlockchainError> {
+    parse_at_block(at_block)?.map(|at| blockchain.state_at(&at)).transpose()
+}
+
+/// Gets the height of the at_block block
+///
+/// # Returns
+///
+/// The height, None to read up to the tip
+fn height_at(blockchain: &Blockchain, at_block: Option<&str>) -> Result<Option<u64>, BlockchainError> {
+    parse_at_block(at_block)?
+        .map(|at| blockchain.get_block_by_ref(&at).map(|block| block.index))
+        .transpose()
+}
+
+/// Converts a failed lookup of the at_block block into a response
+fn at_block_error_response(err: BlockchainError) -> HttpResponse {
+    match err {
+        BlockchainError::BlockNotFound(at) => HttpResponse::NotFound().json(ErrorResponse::new(
+            ErrorCode::NotFound,
+            format!("No block of the chain at {}", at),
+        )),
+        err => blockchain_error_response("Failed to read the state at the block", err),
+    }
+}
+
 /// Get the full blockchain
 ///
 /// Returns the entire blockchain and its validity status
 #[utoipa::path(
     get,
     path = "/api/v1/chain",
+    params(
+        ("at_block" = Option<String>, Query, description = "Hash or height of the block to read the state after, the tip if omitted")
+    ),
     responses(
-        (status = 200, description = "Blockchain retrieved successfully", body = ChainResponse)
+        (status = 200, description = "Blockchain retrieved successfully", body = ChainResponse),
+        (status = 400, description = "Invalid at_block", body = ErrorResponse),
+        (status = 404, description = "No block of the chain matches at_block", body = ErrorResponse)
     )
 )]
-pub async fn get_chain(blockchain: BlockchainData) -> impl Responder {
-    let chain = blockchain.get_chain();
+pub async fn get_chain(
+    req: HttpRequest,
+    blockchain: BlockchainData,
+    query: web::Query<AtBlockQuery>,
+) -> impl Responder {
+    let chain = match height_at(&blockchain, query.at_block.as_deref()) {
+        Ok(Some(height)) => blockchain.get_chain().into_iter().take(height as usize + 1).collect(),
+        Ok(None) => blockchain.get_chain(),
+        Err(err) => return at_block_error_response(err),
+    };
     let is_valid = blockchain.is_valid();
+    usage::served_blocks(&req, chain.len());
 
     let response = ChainResponse {
         length: chain.len(),
@@ -92,19 +295,45 @@ pub async fn get_chain(blockchain: BlockchainData) -> impl Responder {
 
 /// Get all pending transactions
 ///
-/// Returns all transactions waiting to be included in a block
+/// Returns all transactions waiting to be included in a block, each with when it entered the mempool
 #[utoipa::path(
     get,
     path = "/api/v1/transactions/pending",
     responses(
-        (status = 200, description = "Pending transactions retrieved successfully", body = Vec<Transaction>)
+        (status = 200, description = "Pending transactions retrieved successfully", body = Vec<PendingTransaction>)
     )
 )]
 pub async fn get_pending_transactions(blockchain: BlockchainData) -> impl Responder {
-    let transactions = blockchain.get_pending_transactions();
+    let transactions = blockchain.get_pending_entries();
     HttpResponse::Ok().json(transactions)
 }
 
+/// Rebroadcast a pending transaction
+///
+/// Announces a pending transaction to the peers again and restarts the interval after which a local
+/// transaction is announced again automatically. Peers that saw it within the last minute ignore the
+/// announcement
+#[utoipa::path(
+    post,
+    path = "/api/v1/transactions/{id}/rebroadcast",
+    params(
+        ("id" = String, Path, description = "ID of the pending transaction")
+    ),
+    responses(
+        (status = 200, description = "Transaction announced again", body = BroadcastRecord),
+        (status = 404, description = "The transaction is not pending", body = ErrorResponse)
+    )
+)]
+pub async fn rebroadcast_transaction(blockchain: BlockchainData, id: web::Path<String>) -> impl Responder {
+    match blockchain.rebroadcast_transaction(&id) {
+        Some(record) => HttpResponse::Ok().json(record),
+        None => HttpResponse::NotFound().json(ErrorResponse::new(
+            ErrorCode::NotFound,
+            format!("Transaction {} is not pending", id),
+        )),
+    }
+}
+
 /// Create a new transaction
 ///
 /// Adds a new transaction to the pending transactions
@@ -114,27 +343,50 @@ pub async fn get_pending_transactions(blockchain: BlockchainData) -> impl Respon
     request_body = TransactionRequest,
     responses(
         (status = 201, description = "Transaction created successfully", body = TransactionResponse),
-        (status = 400, description = "Invalid transaction data"),
-        (status = 500, description = "Internal server error")
+        (status = 400, description = "Invalid transaction data", body = ErrorResponse),
+        (status = 403, description = "The node is a read-only follower", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
     )
 )]
 pub async fn new_transaction(
+    req: HttpRequest,
     blockchain: BlockchainData,
     transaction_req: web::Json<TransactionRequest>,
 ) -> impl Responder {
-    // Create addresses from strings
+    // Create addresses from strings, the recipient may be a registered name
     let sender_address = Address(transaction_req.sender.clone());
-    let recipient_address = Address(transaction_req.recipient.clone());
+    let recipient_address = match blockchain.resolve_recipient(&transaction_req.recipient) {
+        Ok(address) => address,
+        Err(err) => return blockchain_error_response("Invalid recipient", err),
+    };
 
-    // Get the sender's account to get the current nonce and check balance
+    // A fee payer signs with its own key
+    let fee_payer = match &transaction_req.fee_payer_private_key {
+        Some(private_key) => match hex::decode(private_key)
+            .map_err(|_| "Invalid fee payer private key format. Must be a hex string.".to_string())
+            .and_then(|bytes| {
+                Wallet::from_secret_key(&bytes).map_err(|err| format!("Invalid fee payer private key: {}", err))
+            }) {
+            Ok(wallet) => Some(wallet),
+            Err(error) => return HttpResponse::BadRequest().json(ErrorResponse::new(ErrorCode::InvalidKey, error)),
+        },
+        None => None,
+    };
+
+    // Get the sender's account to check balance, and the next nonce including pending transactions
     let sender_account = blockchain.get_account_state().get_account(&sender_address);
-    let nonce = sender_account.nonce;
+    let nonce = blockchain.get_next_nonce(&sender_address);
 
-    // Check if the sender has enough balance for the transaction
-    let total_amount = transaction_req.amount + transaction_req.fee;
+    // Check if the sender has enough balance for the transaction, the fee payer's is checked on admission
+    let total_amount = match fee_payer {
+        Some(_) => transaction_req.amount,
+        None => transaction_req.amount + transaction_req.fee,
+    };
     if sender_account.balance < total_amount {
         return HttpResponse::BadRequest().json(serde_json::json!({
+            "code": ErrorCode::InsufficientFunds,
             "error": format!("Insufficient funds: required {}, available {}", total_amount, sender_account.balance),
+            "retryable": false,
             "required": total_amount,
             "available": sender_account.balance
         }));
@@ -148,38 +400,53 @@ pub async fn new_transaction(
         transaction_req.fee,
         nonce,
     );
+    if let Some(fee_payer) = &fee_payer {
+        transaction = transaction.with_fee_payer(fee_payer.address().clone());
+    }
 
     // Create a wallet from the private key
     let private_key_bytes = match hex::decode(&transaction_req.private_key) {
         Ok(bytes) => bytes,
         Err(_) => {
-            return HttpResponse::BadRequest().json(serde_json::json!({
-                "error": "Invalid private key format. Must be a hex string."
-            }));
+            return HttpResponse::BadRequest().json(ErrorResponse::new(
+                ErrorCode::InvalidKey,
+                "Invalid private key format. Must be a hex string.",
+            ));
         }
     };
 
     let wallet = match Wallet::from_secret_key(&private_key_bytes) {
         Ok(wallet) => wallet,
         Err(err) => {
-            return HttpResponse::BadRequest().json(serde_json::json!({
-                "error": format!("Invalid private key: {}", err)
-            }));
+            return HttpResponse::BadRequest().json(ErrorResponse::new(
+                ErrorCode::InvalidKey,
+                format!("Invalid private key: {}", err),
+            ));
         }
     };
 
-    // Check if the wallet address matches the sender address
-    if wallet.address().0 != transaction_req.sender {
-        return HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Private key does not match sender address"
-        }));
+    // Check if the wallet address matches the sender address, a session key signs for the sender instead
+    if transaction_req.session {
+        transaction = transaction.with_session(SessionAction::Spend {
+            key: wallet.address().0.clone(),
+        });
+    } else if wallet.address().0 != transaction_req.sender {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidKey,
+            "Private key does not match sender address",
+        ));
     }
 
-    // Sign the transaction
-    if let Err(err) = transaction.sign(&wallet) {
-        return HttpResponse::BadRequest().json(serde_json::json!({
-            "error": format!("Failed to sign transaction: {}", err)
-        }));
+    // Sign the transaction, as the fee payer too if there is one
+    let signed = transaction.sign(&wallet).and_then(|()| match &fee_payer {
+        Some(fee_payer) => transaction.sign_fee_payer(fee_payer),
+        None => Ok(()),
+    });
+    if let Err(err) = signed {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidTransaction,
+            format!("Failed to sign transaction: {}", err),
+        ));
     }
 
     // Add the transaction to the blockchain
@@ -189,35 +456,290 @@ pub async fn new_transaction(
                 message: "Transaction will be added to Block".to_string(),
                 block_index,
             };
+            usage::submitted_transactions(&req, 1);
 
             HttpResponse::Created().json(response)
         }
+        Err(err) => blockchain_error_response("Failed to add transaction", err),
+    }
+}
+
+/// Converts a blockchain error into an HTTP response
+///
+/// The body carries the code, whether the request may be retried unchanged
+/// and the block, transaction or address the error is about.
+fn blockchain_error_response(context: &str, err: BlockchainError) -> HttpResponse {
+    let body = ErrorResponse::from_error(&err, format!("{}: {}", context, err));
+    match err {
+        BlockchainError::Paused(reason) => HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "code": ErrorCode::Paused,
+            "error": format!("{}: node is under maintenance", context),
+            "retryable": true,
+            "reason": reason
+        })),
+        BlockchainError::PermissionError(PermissionError::NotPermitted { .. } | PermissionError::Frozen(_))
+        | BlockchainError::ReadOnly => HttpResponse::Forbidden().json(body),
+        BlockchainError::GovernanceError(GovernanceError::ProposalNotFound(_))
+        | BlockchainError::ScriptError(ScriptError::LockNotFound(_))
+        | BlockchainError::InheritanceError(InheritanceError::NotDesignated(_)) => HttpResponse::NotFound().json(body),
+        BlockchainError::ScriptError(ScriptError::Pending(_))
+        | BlockchainError::InheritanceError(InheritanceError::Pending(_))
+        | BlockchainError::MiningCancelled { .. } => HttpResponse::Conflict().json(body),
+        _ => HttpResponse::BadRequest().json(body),
+    }
+}
+
+/// Most transactions a batch submission may hold
+pub const MAX_BATCH_TRANSACTIONS: usize = 1000;
+
+/// Request for the batch submission endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BatchSubmitRequest {
+    /// Signed transactions, submitted in this order
+    pub transactions: Vec<Transaction>,
+}
+
+/// What happened to a transaction of a batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SubmissionStatus {
+    /// Added to the pending transactions
+    Accepted,
+
+    /// Took the place of the pending or queued transaction with the same nonce and a lower fee
+    Replaced,
+
+    /// Refused, see the code and the error
+    Rejected,
+
+    /// Waits for the sender's earlier nonces, in memory only
+    Queued,
+
+    /// Already pending or queued, nothing changed
+    Known,
+}
+
+/// Resolution of one transaction of a batch
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SubmissionResult {
+    /// ID of the transaction
+    pub id: String,
+
+    /// Nonce of the transaction
+    pub nonce: u64,
+
+    /// What happened to it
+    pub status: SubmissionStatus,
+
+    /// ID of the transaction it replaced
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replaced: Option<String>,
+
+    /// Why it was rejected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+
+    /// Message of the rejection
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Response for the batch submission endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BatchSubmitResponse {
+    /// The resolution of each transaction, in submission order
+    pub results: Vec<SubmissionResult>,
+
+    /// Number of transactions accepted or replacing another
+    pub admitted: usize,
+
+    /// Number of transactions waiting for an earlier nonce
+    pub queued: usize,
+
+    /// Number of transactions rejected
+    pub rejected: usize,
+}
+
+/// Submit a batch of signed transactions
+///
+/// Resolves nonce conflicts per transaction instead of failing the whole
+/// batch: a pending nonce is replaced by a higher fee, a nonce ahead of the
+/// sender's next one is queued until the gap is filled, and the others are
+/// rejected with an error code
+#[utoipa::path(
+    post,
+    path = "/api/v1/transactions/batch",
+    request_body = BatchSubmitRequest,
+    responses(
+        (status = 200, description = "The resolution of every transaction", body = BatchSubmitResponse),
+        (status = 400, description = "The batch is empty or too large", body = ErrorResponse),
+        (status = 403, description = "The node is a read-only follower", body = ErrorResponse)
+    )
+)]
+pub async fn submit_transaction_batch(
+    req: HttpRequest,
+    blockchain: BlockchainData,
+    batch: web::Json<BatchSubmitRequest>,
+) -> impl Responder {
+    if blockchain.is_follower() {
+        return blockchain_error_response("Failed to submit the batch", BlockchainError::ReadOnly);
+    }
+
+    let transactions = batch.into_inner().transactions;
+    if transactions.is_empty() || transactions.len() > MAX_BATCH_TRANSACTIONS {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidRequest,
+            format!("A batch holds 1 to {} transactions", MAX_BATCH_TRANSACTIONS),
+        ));
+    }
+
+    let keys: Vec<(String, u64)> = transactions.iter().map(|tx| (tx.id.clone(), tx.nonce)).collect();
+    let outcomes = web::block(move || blockchain.submit_batch(transactions)).await;
+    let outcomes = match outcomes {
+        Ok(outcomes) => outcomes,
         Err(err) => {
-            HttpResponse::BadRequest().json(serde_json::json!({
-                "error": format!("Failed to add transaction: {}", err)
-            }))
+            return HttpResponse::InternalServerError().json(ErrorResponse::new(
+                ErrorCode::Internal,
+                format!("Failed to submit the batch: {}", err),
+            ));
         }
-    }
+    };
+
+    let results: Vec<SubmissionResult> = keys
+        .into_iter()
+        .zip(outcomes)
+        .map(|((id, nonce), outcome)| {
+            let (status, replaced, error) = match outcome {
+                Ok(Admission::Accepted) => (SubmissionStatus::Accepted, None, None),
+                Ok(Admission::Replaced(replaced)) => (SubmissionStatus::Replaced, Some(replaced), None),
+                Ok(Admission::Queued) => (SubmissionStatus::Queued, None, None),
+                Ok(Admission::Known) => (SubmissionStatus::Known, None, None),
+                Err(err) => (SubmissionStatus::Rejected, None, Some(err)),
+            };
+            SubmissionResult {
+                id,
+                nonce,
+                status,
+                replaced,
+                code: error.as_ref().map(ErrorCode::from),
+                error: error.map(|err| err.to_string()),
+            }
+        })
+        .collect();
+    let count = |status: &[SubmissionStatus]| results.iter().filter(|result| status.contains(&result.status)).count();
+    let admitted = count(&[SubmissionStatus::Accepted, SubmissionStatus::Replaced]);
+    let queued = count(&[SubmissionStatus::Queued]);
+    usage::submitted_transactions(&req, admitted + queued);
+
+    HttpResponse::Ok().json(BatchSubmitResponse {
+        admitted,
+        queued,
+        rejected: count(&[SubmissionStatus::Rejected]),
+        results,
+    })
 }
 
 /// Mine a new block
 ///
-/// Creates a new block with all pending transactions
+/// Creates a new block with all pending transactions. The proof of work runs on a blocking
+/// thread, so the node keeps serving other requests meanwhile. With `background=true` the
+/// node answers right away with a job whose status `GET /api/v1/mine/status/{id}` reports.
 #[utoipa::path(
     post,
     path = "/api/v1/mine",
+    params(
+        ("background" = Option<bool>, Query, description = "Answer right away with a job to follow (default false)")
+    ),
     request_body = MineRequest,
     responses(
         (status = 200, description = "Block mined successfully", body = MineResponse),
-        (status = 400, description = "Invalid mining request"),
-        (status = 500, description = "Internal server error")
+        (status = 202, description = "Block is being mined in the background", body = MiningJob),
+        (status = 400, description = "Invalid mining request", body = ErrorResponse),
+        (status = 403, description = "The node is a read-only follower", body = ErrorResponse),
+        (status = 409, description = "Another block took the height first", body = ErrorResponse),
+        (status = 429, description = "Too many mining requests are already waiting", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
     )
 )]
 pub async fn mine_block(
     blockchain: BlockchainData,
+    limiter: MiningLimiterData,
+    jobs: MiningJobsData,
+    query: web::Query<MineQuery>,
     mine_req: web::Json<MineRequest>,
 ) -> impl Responder {
-    match blockchain.mine_block(&mine_req.miner_address) {
+    let ticket = match limiter.reserve() {
+        Some(ticket) => ticket,
+        None => {
+            return HttpResponse::TooManyRequests()
+                .insert_header(("Retry-After", "1"))
+                .json(ErrorResponse::new(ErrorCode::Busy, "A block is already being mined, try again later"));
+        }
+    };
+    let mine_req = mine_req.into_inner();
+
+    if query.background.unwrap_or(false) {
+        let job = jobs.submit(&mine_req.miner_address);
+        let id = job.id.clone();
+        actix_web::rt::spawn(async move {
+            let permit = match ticket.wait().await {
+                Some(permit) => permit,
+                None => {
+                    jobs.finish(
+                        &id,
+                        Err(ErrorResponse::new(ErrorCode::Internal, "Failed to mine block: the mining queue is closed")),
+                    );
+                    return;
+                }
+            };
+            jobs.start(&id);
+            let mined = web::block(move || {
+                let _permit = permit;
+                blockchain.mine_block_with_coinbase(&mine_req.miner_address, mine_req.extra_nonce, mine_req.message.as_deref())
+            })
+            .await;
+            match mined {
+                Ok(Ok(block)) => jobs.finish(&id, Ok(&block)),
+                Ok(Err(err)) => jobs.finish(
+                    &id,
+                    Err(ErrorResponse::new(ErrorCode::from(&err), format!("Failed to mine block: {}", err))),
+                ),
+                Err(err) => jobs.finish(
+                    &id,
+                    Err(ErrorResponse::new(ErrorCode::Internal, format!("Failed to mine block: {}", err))),
+                ),
+            }
+        });
+
+        return HttpResponse::Accepted().json(job);
+    }
+
+    let permit = match ticket.wait().await {
+        Some(permit) => permit,
+        None => {
+            return HttpResponse::InternalServerError()
+                .json(ErrorResponse::new(ErrorCode::Internal, "Failed to mine block: the mining queue is closed"));
+        }
+    };
+
+    // Seal the block off the async workers, releasing the permit when done
+    let mined = web::block(move || {
+        let _permit = permit;
+        blockchain.mine_block_with_coinbase(&mine_req.miner_address, mine_req.extra_nonce, mine_req.message.as_deref())
+    })
+    .await;
+
+    let mined = match mined {
+        Ok(mined) => mined,
+        Err(err) => {
+            return HttpResponse::InternalServerError().json(ErrorResponse::new(
+                ErrorCode::Internal,
+                format!("Failed to mine block: {}", err),
+            ));
+        }
+    };
+
+    match mined {
         Ok(block) => {
             let response = MineResponse {
                 message: "New Block Mined".to_string(),
@@ -226,11 +748,140 @@ pub async fn mine_block(
 
             HttpResponse::Ok().json(response)
         }
-        Err(err) => {
-            HttpResponse::BadRequest().json(serde_json::json!({
-                "error": format!("Failed to mine block: {}", err)
-            }))
+        Err(err) => blockchain_error_response("Failed to mine block", err),
+    }
+}
+
+/// Get a block template
+///
+/// Assembles the next block from the pending transactions, paying the miner, for a miner
+/// outside the node to find the proof of work of. Nothing leaves the mempool until a solution
+/// is submitted, and the template is stale as soon as another block takes the tip.
+#[utoipa::path(
+    get,
+    path = "/api/v1/mining/template",
+    params(
+        ("miner_address" = String, Query, description = "Address the coinbase pays"),
+        ("extra_nonce" = Option<u64>, Query, description = "Extra-nonce to put in the coinbase, random if omitted"),
+        ("message" = Option<String>, Query, description = "Message to embed in the coinbase (at most 100 bytes)")
+    ),
+    responses(
+        (status = 200, description = "Template assembled successfully", body = BlockTemplateResponse),
+        (status = 400, description = "Invalid template request", body = ErrorResponse),
+        (status = 403, description = "The node is a read-only follower", body = ErrorResponse),
+        (status = 503, description = "Mining is paused for maintenance", body = ErrorResponse)
+    )
+)]
+pub async fn get_block_template(
+    blockchain: BlockchainData,
+    templates: BlockTemplatesData,
+    query: web::Query<BlockTemplateQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    match blockchain.get_block_template(&query.miner_address, query.extra_nonce, query.message.as_deref()) {
+        Ok(block) => {
+            let difficulty = blockchain.get_difficulty();
+            HttpResponse::Ok().json(BlockTemplateResponse {
+                template_id: templates.add(block.clone()),
+                difficulty,
+                target: "0".repeat(difficulty as usize),
+                block,
+            })
+        }
+        Err(err) => blockchain_error_response("Failed to assemble block template", err),
+    }
+}
+
+/// Submit a sealed block
+///
+/// Takes the proof and hash an external miner found for a template and appends the block,
+/// validated like a block from a peer
+#[utoipa::path(
+    post,
+    path = "/api/v1/mining/submit",
+    request_body = SubmitBlockRequest,
+    responses(
+        (status = 200, description = "Block appended successfully", body = MineResponse),
+        (status = 400, description = "The hash doesn't match, or the block is invalid", body = ErrorResponse),
+        (status = 404, description = "No such template, or it was forgotten", body = ErrorResponse),
+        (status = 409, description = "Another block took the tip since the template was made", body = ErrorResponse)
+    )
+)]
+pub async fn submit_block(
+    blockchain: BlockchainData,
+    templates: BlockTemplatesData,
+    submit_req: web::Json<SubmitBlockRequest>,
+) -> impl Responder {
+    let submit_req = submit_req.into_inner();
+    let template = match templates.get(&submit_req.template_id) {
+        Some(template) => template,
+        None => {
+            return HttpResponse::NotFound().json(ErrorResponse::new(
+                ErrorCode::NotFound,
+                format!("No block template {}, or it was forgotten", submit_req.template_id),
+            ));
         }
+    };
+
+    let block = Block {
+        proof: submit_req.proof,
+        hash: submit_req.hash,
+        ..template
+    };
+    if block.calculate_hash() != block.hash {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidBlock,
+            format!("The hash of block {} with proof {} is not {}", block.index, block.proof, block.hash),
+        ));
+    }
+    let tip = blockchain.get_last_block();
+    if tip.hash != block.previous_hash {
+        return HttpResponse::Conflict().json(ErrorResponse::new(
+            ErrorCode::StaleTip,
+            format!("The template of block {} is stale, block {} took the tip", block.index, tip.hash),
+        ));
+    }
+
+    // Appending waits for the chain lock, keep it off the async workers
+    let appended = {
+        let block = block.clone();
+        web::block(move || blockchain.append_block(block)).await
+    };
+    match appended {
+        Ok(Ok(())) => HttpResponse::Ok().json(MineResponse {
+            message: "Block Accepted".to_string(),
+            block,
+        }),
+        Ok(Err(err)) => blockchain_error_response("Failed to submit block", err),
+        Err(err) => HttpResponse::InternalServerError().json(ErrorResponse::new(
+            ErrorCode::Internal,
+            format!("Failed to submit block: {}", err),
+        )),
+    }
+}
+
+/// Get a mining job
+///
+/// Returns whether a block requested with `background=true` is still queued, being mined,
+/// mined or failed, with the block's height and hash or the error
+#[utoipa::path(
+    get,
+    path = "/api/v1/mine/status/{id}",
+    params(
+        ("id" = String, Path, description = "ID of the job")
+    ),
+    responses(
+        (status = 200, description = "Job retrieved successfully", body = MiningJob),
+        (status = 404, description = "No such job, or it was forgotten", body = ErrorResponse)
+    )
+)]
+pub async fn get_mining_job(jobs: MiningJobsData, id: web::Path<String>) -> impl Responder {
+    match jobs.get(&id) {
+        Some(job) => HttpResponse::Ok().json(job),
+        None => HttpResponse::NotFound().json(ErrorResponse::new(
+            ErrorCode::NotFound,
+            format!("Mining job {} not found", id),
+        )),
     }
 }
 
@@ -269,7 +920,7 @@ pub struct WalletResponse {
     path = "/api/v1/wallet/new",
     responses(
         (status = 201, description = "Wallet created successfully", body = WalletResponse),
-        (status = 500, description = "Internal server error")
+        (status = 500, description = "Internal server error", body = ErrorResponse)
     )
 )]
 pub async fn create_wallet() -> impl Responder {
@@ -286,9 +937,10 @@ pub async fn create_wallet() -> impl Responder {
             HttpResponse::Created().json(response)
         },
         Err(err) => {
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": format!("Failed to create wallet: {}", err)
-            }))
+            HttpResponse::InternalServerError().json(ErrorResponse::new(
+                ErrorCode::Internal,
+                format!("Failed to create wallet: {}", err),
+            ))
         }
     }
 }
@@ -303,6 +955,16 @@ pub struct FundWalletRequest {
     pub amount: f64,
 }
 
+/// Response for the fund wallet endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct FundWalletResponse {
+    /// The message
+    pub message: String,
+
+    /// The balance after funding
+    pub new_balance: f64,
+}
+
 /// Fund a wallet
 ///
 /// Adds funds to a wallet for testing
@@ -311,9 +973,10 @@ pub struct FundWalletRequest {
     path = "/api/v1/wallet/fund",
     request_body = FundWalletRequest,
     responses(
-        (status = 200, description = "Wallet funded successfully"),
-        (status = 400, description = "Invalid address"),
-        (status = 500, description = "Internal server error")
+        (status = 200, description = "Wallet funded successfully", body = FundWalletResponse),
+        (status = 400, description = "Invalid address", body = ErrorResponse),
+        (status = 403, description = "The node is a read-only follower", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
     )
 )]
 pub async fn fund_wallet(
@@ -323,55 +986,133 @@ pub async fn fund_wallet(
     // Create address from string
     let address = Address(fund_req.address.clone());
 
-    // Get the account
-    let mut account = blockchain.get_account_state().get_account(&address);
-
-    // Add funds
-    match account.deposit(fund_req.amount) {
-        Ok(_) => {
-            // Update the account
-            blockchain.get_account_state().update_account(account);
-
-            HttpResponse::Ok().json(serde_json::json!({
-                "message": format!("Added {} coins to wallet {}", fund_req.amount, fund_req.address),
-                "new_balance": blockchain.get_account_state().get_account(&address).balance
-            }))
-        },
-        Err(err) => {
-            HttpResponse::BadRequest().json(serde_json::json!({
-                "error": format!("Failed to fund wallet: {}", err)
-            }))
-        }
+    // Funds added outside the chain would not reach the node a follower follows
+    match blockchain.fund_account(&address, fund_req.amount) {
+        Ok(account) => HttpResponse::Ok().json(FundWalletResponse {
+            message: format!("Added {} coins to wallet {}", fund_req.amount, fund_req.address),
+            new_balance: account.balance,
+        }),
+        Err(err) => blockchain_error_response("Failed to fund wallet", err),
     }
 }
 
+/// Response for the wallet balance endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BalanceResponse {
+    /// The wallet's address
+    pub address: String,
+
+    /// The balance after the block asked for
+    pub balance: f64,
+
+    /// The nonce of the account after the block asked for
+    pub nonce: u64,
+
+    /// Whether a freeze order stops the account from sending
+    pub frozen: bool,
+}
+
 /// Get wallet balance
 ///
 /// Returns the balance of a wallet
 #[utoipa::path(
     get,
     path = "/api/v1/wallet/balance/{address}",
+    params(
+        ("address" = String, Path, description = "The wallet address"),
+        ("at_block" = Option<String>, Query, description = "Hash or height of the block to read the state after, the tip if omitted")
+    ),
     responses(
-        (status = 200, description = "Wallet balance retrieved successfully"),
-        (status = 400, description = "Invalid address"),
-        (status = 500, description = "Internal server error")
+        (status = 200, description = "Wallet balance retrieved successfully", body = BalanceResponse),
+        (status = 400, description = "Invalid address or at_block", body = ErrorResponse),
+        (status = 404, description = "No block of the chain matches at_block", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
     )
 )]
 pub async fn get_wallet_balance(
     blockchain: BlockchainData,
     address: web::Path<String>,
+    query: web::Query<AtBlockQuery>,
 ) -> impl Responder {
     // Create address from string
     let wallet_address = Address(address.into_inner());
 
     // Get the account
-    let account = blockchain.get_account_state().get_account(&wallet_address);
+    let account = match state_at(&blockchain, query.at_block.as_deref()) {
+        Ok(Some(state)) => state.accounts().get_account(&wallet_address),
+        Ok(None) => blockchain.get_account_state().get_account(&wallet_address),
+        Err(err) => return at_block_error_response(err),
+    };
+
+    HttpResponse::Ok().json(BalanceResponse {
+        frozen: blockchain.is_frozen(&wallet_address),
+        address: wallet_address.0,
+        balance: account.balance,
+        nonce: account.nonce,
+    })
+}
+
+/// Response for the wallet nonce endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct NonceResponse {
+    /// The wallet's address
+    pub address: String,
+
+    /// The nonce of the account in the confirmed state
+    pub confirmed_nonce: u64,
+
+    /// The number of pending transactions from this address
+    pub pending_count: u64,
+
+    /// The nonce to use for the next transaction
+    pub next_nonce: u64,
+}
+
+/// Get the next nonce of a wallet
+///
+/// Returns the next usable nonce, taking pending transactions from this address into account.
+/// At a past block, pending transactions are not counted.
+#[utoipa::path(
+    get,
+    path = "/api/v1/wallet/nonce/{address}",
+    params(
+        ("address" = String, Path, description = "The wallet address"),
+        ("at_block" = Option<String>, Query, description = "Hash or height of the block to read the state after, the tip if omitted")
+    ),
+    responses(
+        (status = 200, description = "Wallet nonce retrieved successfully", body = NonceResponse),
+        (status = 400, description = "Invalid at_block", body = ErrorResponse),
+        (status = 404, description = "No block of the chain matches at_block", body = ErrorResponse)
+    )
+)]
+pub async fn get_wallet_nonce(
+    blockchain: BlockchainData,
+    address: web::Path<String>,
+    query: web::Query<AtBlockQuery>,
+) -> impl Responder {
+    // Create address from string
+    let wallet_address = Address(address.into_inner());
+
+    let (confirmed_nonce, next_nonce) = match state_at(&blockchain, query.at_block.as_deref()) {
+        Ok(Some(state)) => {
+            let nonce = state.accounts().get_account(&wallet_address).nonce;
+            (nonce, nonce)
+        }
+        Ok(None) => (
+            blockchain.get_account_state().get_account(&wallet_address).nonce,
+            blockchain.get_next_nonce(&wallet_address),
+        ),
+        Err(err) => return at_block_error_response(err),
+    };
+
+    let response = NonceResponse {
+        address: wallet_address.0,
+        confirmed_nonce,
+        pending_count: next_nonce - confirmed_nonce,
+        next_nonce,
+    };
 
-    HttpResponse::Ok().json(serde_json::json!({
-        "address": wallet_address.0,
-        "balance": account.balance,
-        "nonce": account.nonce
-    }))
+    HttpResponse::Ok().json(response)
 }
 
 /// Response for the get accounts endpoint
@@ -386,6 +1127,8 @@ pub struct AccountResponse {
     /// The nonce of the account
     pub nonce: u64,
 
+    /// Whether the account is frozen and can't send transactions
+    pub frozen: bool,
 }
 
 /// Get all accounts
@@ -394,15 +1137,25 @@ pub struct AccountResponse {
 #[utoipa::path(
     get,
     path = "/api/v1/accounts",
+    params(
+        ("at_block" = Option<String>, Query, description = "Hash or height of the block to read the state after, the tip if omitted")
+    ),
     responses(
-        (status = 200, description = "Accounts retrieved successfully", body = Vec<AccountResponse>)
+        (status = 200, description = "Accounts retrieved successfully", body = Vec<AccountResponse>),
+        (status = 400, description = "Invalid at_block", body = ErrorResponse),
+        (status = 404, description = "No block of the chain matches at_block", body = ErrorResponse)
     )
 )]
-pub async fn get_all_accounts(blockchain: BlockchainData) -> impl Responder {
-    let accounts = blockchain.get_account_state().get_all_accounts();
+pub async fn get_all_accounts(blockchain: BlockchainData, query: web::Query<AtBlockQuery>) -> impl Responder {
+    let accounts = match state_at(&blockchain, query.at_block.as_deref()) {
+        Ok(Some(state)) => state.accounts().get_all_accounts(),
+        Ok(None) => blockchain.get_account_state().get_all_accounts(),
+        Err(err) => return at_block_error_response(err),
+    };
 
     let account_responses: Vec<AccountResponse> = accounts.into_iter()
         .map(|account| AccountResponse {
+            frozen: blockchain.is_frozen(&account.address),
             address: account.address.0,
             balance: account.balance,
             nonce: account.nonce,
@@ -411,3 +1164,4308 @@ pub async fn get_all_accounts(blockchain: BlockchainData) -> impl Responder {
 
     HttpResponse::Ok().json(account_responses)
 }
+
+/// Request for the create payment endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PaymentRequest {
+    /// The address the payment must be sent to
+    pub recipient: String,
+
+    /// The expected amount
+    pub amount: f64,
+
+    /// The merchant reference (order number, invoice id, ...)
+    pub reference: String,
+
+    /// The number of confirmations after which the payment is final
+    pub confirmations: Option<u64>,
+}
+
+/// Register an expected payment
+///
+/// Registers a payment the merchant expects to receive so its status can be tracked
+#[utoipa::path(
+    post,
+    path = "/api/v1/payments",
+    request_body = PaymentRequest,
+    responses(
+        (status = 201, description = "Payment registered successfully", body = Payment),
+        (status = 400, description = "Invalid payment data", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+pub async fn create_payment(
+    payments: PaymentsData,
+    payment_req: web::Json<PaymentRequest>,
+) -> impl Responder {
+    let payment_req = payment_req.into_inner();
+
+    match payments.register(
+        Address(payment_req.recipient),
+        payment_req.amount,
+        payment_req.reference,
+        payment_req.confirmations,
+    ) {
+        Ok(payment) => HttpResponse::Created().json(payment),
+        Err(PaymentError::StorageError(err)) => {
+            HttpResponse::InternalServerError().json(ErrorResponse::new(
+                ErrorCode::Internal,
+                format!("Failed to register payment: {}", err),
+            ))
+        }
+        Err(err) => {
+            HttpResponse::BadRequest().json(ErrorResponse::new(
+                ErrorCode::InvalidRequest,
+                format!("Failed to register payment: {}", err),
+            ))
+        }
+    }
+}
+
+/// Request for the create invoice endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct InvoiceRequest {
+    /// Name of the keystore wallet of the merchant the invoice address is derived from
+    pub merchant_wallet: String,
+
+    /// The expected amount
+    pub amount: f64,
+
+    /// The merchant reference (order number, invoice id, ...)
+    pub reference: String,
+
+    /// The number of confirmations after which the payment is final
+    pub confirmations: Option<u64>,
+
+    /// The address to sweep funds to (defaults to the merchant's address)
+    pub sweep_address: Option<String>,
+}
+
+/// Create an invoice
+///
+/// Registers an expected payment to a fresh single-use address derived from a merchant wallet held
+/// in the keystore, watch-only wallets included. Funds are swept to the merchant once the payment
+/// is final and the keystore holds the merchant's private key.
+#[utoipa::path(
+    post,
+    path = "/api/v1/payments/invoice",
+    request_body = InvoiceRequest,
+    responses(
+        (status = 201, description = "Invoice created successfully", body = Payment),
+        (status = 400, description = "Invalid invoice data", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "Merchant wallet not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn create_invoice(
+    req: HttpRequest,
+    admin: AdminData,
+    payments: PaymentsData,
+    keystore: KeystoreData,
+    invoice_req: web::Json<InvoiceRequest>,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    let invoice_req = invoice_req.into_inner();
+
+    let merchant = match keystore.get(&invoice_req.merchant_wallet) {
+        Ok(entry) => entry.address,
+        Err(err) => return keystore_error_response(err),
+    };
+
+    match payments.create_invoice(
+        &merchant,
+        invoice_req.amount,
+        invoice_req.reference,
+        invoice_req.confirmations,
+        invoice_req.sweep_address.map(Address),
+    ) {
+        Ok(payment) => HttpResponse::Created().json(payment),
+        Err(PaymentError::StorageError(err)) => {
+            HttpResponse::InternalServerError().json(ErrorResponse::new(
+                ErrorCode::Internal,
+                format!("Failed to create invoice: {}", err),
+            ))
+        }
+        Err(err) => {
+            HttpResponse::BadRequest().json(ErrorResponse::new(
+                ErrorCode::InvalidRequest,
+                format!("Failed to create invoice: {}", err),
+            ))
+        }
+    }
+}
+
+/// Get the status of a payment
+///
+/// Reports whether a payment is unpaid, seen in the mempool, confirmed or final
+#[utoipa::path(
+    get,
+    path = "/api/v1/payments/{id}",
+    params(
+        ("id" = String, Path, description = "The payment ID")
+    ),
+    responses(
+        (status = 200, description = "Payment status retrieved successfully", body = PaymentReport),
+        (status = 404, description = "Payment not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+pub async fn get_payment(
+    blockchain: BlockchainData,
+    payments: PaymentsData,
+    id: web::Path<String>,
+) -> impl Responder {
+    match payments.check(&id, &blockchain) {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(PaymentError::NotFound(_)) => {
+            HttpResponse::NotFound().json(ErrorResponse::new(
+                ErrorCode::NotFound,
+                format!("Payment {} not found", id),
+            ))
+        }
+        Err(err) => {
+            HttpResponse::InternalServerError().json(ErrorResponse::new(
+                ErrorCode::Internal,
+                format!("Failed to check payment: {}", err),
+            ))
+        }
+    }
+}
+
+/// Request for the withdrawal endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct WithdrawalRequest {
+    /// The address whose deposits pay for the withdrawal
+    pub account: String,
+
+    /// The address to pay
+    pub recipient: String,
+
+    /// The amount to pay
+    pub amount: f64,
+
+    /// Any number telling otherwise identical withdrawals apart
+    #[serde(default)]
+    pub nonce: u64,
+
+    /// The account's signature of the terms made elsewhere, see the README
+    pub signature: Option<String>,
+
+    /// The account's private key, for the node to sign the terms with instead
+    pub private_key: Option<String>,
+}
+
+/// Request for the withdrawal decision endpoints
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct WithdrawalDecisionRequest {
+    /// The operator taking the decision
+    pub operator: String,
+
+    /// The reason for the decision
+    pub reason: Option<String>,
+}
+
+/// Query parameters for listing withdrawals
+#[derive(Serialize, Deserialize)]
+pub struct WithdrawalListQuery {
+    /// Only list withdrawals with this status
+    pub status: Option<WithdrawalStatus>,
+}
+
+/// Converts a withdrawal error into an HTTP response
+fn withdrawal_error_response(err: WithdrawalError) -> HttpResponse {
+    match err {
+        WithdrawalError::NotFound(_) => HttpResponse::NotFound().json(ErrorResponse::new(
+            ErrorCode::NotFound,
+            err.to_string(),
+        )),
+        WithdrawalError::AlreadyExists(_) | WithdrawalError::Conflict(_) => HttpResponse::Conflict().json(
+            ErrorResponse::new(ErrorCode::AlreadyExists, err.to_string()),
+        ),
+        WithdrawalError::InvalidSignature => HttpResponse::Unauthorized().json(ErrorResponse::new(
+            ErrorCode::InvalidSignature,
+            err.to_string(),
+        )),
+        WithdrawalError::InsufficientBalance { .. } => HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InsufficientFunds,
+            err.to_string(),
+        )),
+        WithdrawalError::StorageError(_) => HttpResponse::InternalServerError().json(ErrorResponse::new(
+            ErrorCode::Internal,
+            err.to_string(),
+        )),
+        WithdrawalError::Paused(reason) => HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "code": ErrorCode::Paused,
+            "error": "Node is under maintenance",
+            "retryable": true,
+            "reason": reason
+        })),
+        _ => HttpResponse::BadRequest().json(ErrorResponse::new(ErrorCode::InvalidRequest, err.to_string())),
+    }
+}
+
+/// Request a withdrawal
+///
+/// Queues a payout from the hot wallet, signed by the account whose deposits to the hot wallet pay
+/// for it. Withdrawals above the auto-approve limit wait for an operator.
+#[utoipa::path(
+    post,
+    path = "/api/v1/withdrawals",
+    request_body = WithdrawalRequest,
+    responses(
+        (status = 201, description = "Withdrawal queued successfully", body = Withdrawal),
+        (status = 400, description = "Invalid withdrawal data or insufficient balance", body = ErrorResponse),
+        (status = 401, description = "The signature is not the account's", body = ErrorResponse),
+        (status = 409, description = "The withdrawal is already requested", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+pub async fn request_withdrawal(
+    blockchain: BlockchainData,
+    withdrawals: WithdrawalsData,
+    withdrawal_req: web::Json<WithdrawalRequest>,
+) -> impl Responder {
+    let withdrawal_req = withdrawal_req.into_inner();
+    let terms = WithdrawalTerms {
+        account: Address(withdrawal_req.account),
+        recipient: Address(withdrawal_req.recipient),
+        amount: withdrawal_req.amount,
+        nonce: withdrawal_req.nonce,
+    };
+
+    let signature = match owner_signature(
+        SigningContext::Withdrawal,
+        &terms.payload(),
+        withdrawal_req.signature,
+        withdrawal_req.private_key,
+    ) {
+        Ok(signature) => signature,
+        Err(error) => return HttpResponse::BadRequest().json(error),
+    };
+
+    match withdrawals.request(terms, &signature, &blockchain) {
+        Ok(withdrawal) => HttpResponse::Created().json(withdrawal),
+        Err(err) => withdrawal_error_response(err),
+    }
+}
+
+/// Get the withdrawal balance of an account
+///
+/// Returns what an account deposited to the hot wallet, what its open withdrawals reserve and what
+/// is left to withdraw
+#[utoipa::path(
+    get,
+    path = "/api/v1/withdrawals/accounts/{address}",
+    params(
+        ("address" = String, Path, description = "The account address")
+    ),
+    responses(
+        (status = 200, description = "Balance retrieved successfully", body = WithdrawalBalance)
+    )
+)]
+pub async fn get_withdrawal_balance(
+    blockchain: BlockchainData,
+    withdrawals: WithdrawalsData,
+    address: web::Path<String>,
+) -> impl Responder {
+    HttpResponse::Ok().json(withdrawals.balance(&Address(address.into_inner()), &blockchain))
+}
+
+/// Get a withdrawal
+///
+/// Returns the status of a withdrawal request
+#[utoipa::path(
+    get,
+    path = "/api/v1/withdrawals/{id}",
+    params(
+        ("id" = String, Path, description = "The withdrawal ID")
+    ),
+    responses(
+        (status = 200, description = "Withdrawal retrieved successfully", body = Withdrawal),
+        (status = 404, description = "Withdrawal not found", body = ErrorResponse)
+    )
+)]
+pub async fn get_withdrawal(
+    withdrawals: WithdrawalsData,
+    id: web::Path<String>,
+) -> impl Responder {
+    match withdrawals.get(&id) {
+        Some(withdrawal) => HttpResponse::Ok().json(withdrawal),
+        None => withdrawal_error_response(WithdrawalError::NotFound(id.into_inner())),
+    }
+}
+
+/// List withdrawals
+///
+/// Returns all withdrawal requests, optionally filtered by status
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/withdrawals",
+    params(
+        ("status" = Option<WithdrawalStatus>, Query, description = "Only list withdrawals with this status")
+    ),
+    responses(
+        (status = 200, description = "Withdrawals retrieved successfully", body = Vec<Withdrawal>),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn list_withdrawals(
+    req: HttpRequest,
+    admin: AdminData,
+    withdrawals: WithdrawalsData,
+    query: web::Query<WithdrawalListQuery>,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    HttpResponse::Ok().json(withdrawals.list(query.status))
+}
+
+/// Approve a withdrawal
+///
+/// Approves a pending withdrawal so it is paid out with the next batch
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/withdrawals/{id}/approve",
+    params(
+        ("id" = String, Path, description = "The withdrawal ID")
+    ),
+    request_body = WithdrawalDecisionRequest,
+    responses(
+        (status = 200, description = "Withdrawal approved", body = Withdrawal),
+        (status = 400, description = "Withdrawal is not pending", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "Withdrawal not found", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn approve_withdrawal(
+    req: HttpRequest,
+    admin: AdminData,
+    withdrawals: WithdrawalsData,
+    id: web::Path<String>,
+    decision: web::Json<WithdrawalDecisionRequest>,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    match withdrawals.approve(&id, &decision.operator) {
+        Ok(withdrawal) => HttpResponse::Ok().json(withdrawal),
+        Err(err) => withdrawal_error_response(err),
+    }
+}
+
+/// Reject a withdrawal
+///
+/// Rejects a pending withdrawal
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/withdrawals/{id}/reject",
+    params(
+        ("id" = String, Path, description = "The withdrawal ID")
+    ),
+    request_body = WithdrawalDecisionRequest,
+    responses(
+        (status = 200, description = "Withdrawal rejected", body = Withdrawal),
+        (status = 400, description = "Withdrawal is not pending", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "Withdrawal not found", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn reject_withdrawal(
+    req: HttpRequest,
+    admin: AdminData,
+    withdrawals: WithdrawalsData,
+    id: web::Path<String>,
+    decision: web::Json<WithdrawalDecisionRequest>,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    let reason = decision.reason.clone().unwrap_or_default();
+    match withdrawals.reject(&id, &decision.operator, &reason) {
+        Ok(withdrawal) => HttpResponse::Ok().json(withdrawal),
+        Err(err) => withdrawal_error_response(err),
+    }
+}
+
+/// Submit approved withdrawals
+///
+/// Submits all approved withdrawals as transactions from the hot wallet
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/withdrawals/submit",
+    responses(
+        (status = 200, description = "Batch processed", body = Vec<Withdrawal>),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn submit_withdrawals(
+    req: HttpRequest,
+    admin: AdminData,
+    blockchain: BlockchainData,
+    withdrawals: WithdrawalsData,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    match withdrawals.submit_approved(&blockchain) {
+        Ok(batch) => HttpResponse::Ok().json(batch),
+        Err(err) => withdrawal_error_response(err),
+    }
+}
+
+/// Request for the schedule registration endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ScheduleRequest {
+    /// The address the payments are authorized by
+    pub owner: String,
+
+    /// The address to pay
+    pub recipient: String,
+
+    /// The amount of each payment
+    pub amount: f64,
+
+    /// Blocks between two payments
+    pub interval: u64,
+
+    /// Number of payments, 0 for payments until the schedule is cancelled
+    #[serde(default)]
+    pub payments: u32,
+
+    /// Height from which the first payment is due
+    #[serde(default)]
+    pub start_height: u64,
+
+    /// The owner's signature of the terms made elsewhere, see the README
+    pub signature: Option<String>,
+
+    /// The owner's private key, for the node to sign the terms with instead
+    pub private_key: Option<String>,
+}
+
+/// Request for the schedule cancellation endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CancelScheduleRequest {
+    /// The owner's signature of the cancellation made elsewhere, see the README
+    pub signature: Option<String>,
+
+    /// The owner's private key, for the node to sign the cancellation with instead
+    pub private_key: Option<String>,
+}
+
+/// Query parameters for listing schedules
+#[derive(Serialize, Deserialize)]
+pub struct ScheduleListQuery {
+    /// Only list the schedules of this owner
+    pub owner: Option<String>,
+}
+
+/// Converts a schedule error into an HTTP response
+fn schedule_error_response(err: ScheduleError) -> HttpResponse {
+    match err {
+        ScheduleError::NotFound(_) => HttpResponse::NotFound().json(ErrorResponse::new(
+            ErrorCode::NotFound,
+            err.to_string(),
+        )),
+        ScheduleError::AlreadyExists(_) => HttpResponse::Conflict().json(ErrorResponse::new(
+            ErrorCode::AlreadyExists,
+            err.to_string(),
+        )),
+        ScheduleError::InvalidSignature => HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidSignature,
+            err.to_string(),
+        )),
+        ScheduleError::CryptoError(_) | ScheduleError::StorageError(_) => HttpResponse::InternalServerError().json(
+            ErrorResponse::new(ErrorCode::Internal, err.to_string()),
+        ),
+        _ => HttpResponse::BadRequest().json(ErrorResponse::new(ErrorCode::InvalidRequest, err.to_string())),
+    }
+}
+
+/// Gets the owner's signature of a payload, given or made with the owner's key
+///
+/// # Arguments
+///
+/// * `context` - What the signature is for
+/// * `payload` - The signed payload, e.g. a schedule's terms or the cancel payload
+/// * `signature` - A signature made elsewhere
+/// * `private_key` - The owner's private key
+///
+/// # Returns
+///
+/// The signature, or the error to answer with when neither or both are given or the key is invalid
+fn owner_signature(
+    context: SigningContext,
+    payload: &[u8],
+    signature: Option<String>,
+    private_key: Option<String>,
+) -> Result<DigitalSignature, ErrorResponse> {
+    match (signature, private_key) {
+        (Some(signature), None) => Ok(DigitalSignature(signature)),
+        (None, Some(key)) => hex::decode(&key)
+            .map_err(|_| "Invalid private key format. Must be a hex string.".to_string())
+            .and_then(|bytes| Wallet::from_secret_key(&bytes).map_err(|err| format!("Invalid private key: {}", err)))
+            .and_then(|wallet| signing::sign(&wallet, context, payload).map_err(|err| err.to_string()))
+            .map_err(|error| ErrorResponse::new(ErrorCode::InvalidKey, error)),
+        _ => Err(ErrorResponse::new(
+            ErrorCode::InvalidRequest,
+            "Give either a signature or a private key",
+        )),
+    }
+}
+
+/// Register a schedule
+///
+/// Registers recurring payments the owner authorizes with one signature. The node
+/// pays them from a new session key, which the owner funds with an ordinary transfer.
+#[utoipa::path(
+    post,
+    path = "/api/v1/schedules",
+    request_body = ScheduleRequest,
+    responses(
+        (status = 201, description = "Schedule registered successfully", body = Schedule),
+        (status = 400, description = "Invalid terms or signature", body = ErrorResponse),
+        (status = 409, description = "The schedule is already registered", body = ErrorResponse)
+    )
+)]
+pub async fn register_schedule(
+    scheduler: SchedulerData,
+    request: web::Json<ScheduleRequest>,
+) -> impl Responder {
+    let request = request.into_inner();
+    let terms = ScheduleTerms {
+        owner: Address(request.owner),
+        recipient: Address(request.recipient),
+        amount: request.amount,
+        interval: request.interval,
+        payments: request.payments,
+        start_height: request.start_height,
+    };
+
+    let signature = match owner_signature(
+        SigningContext::Schedule,
+        &terms.payload(),
+        request.signature,
+        request.private_key,
+    ) {
+        Ok(signature) => signature,
+        Err(error) => return HttpResponse::BadRequest().json(error),
+    };
+
+    match scheduler.register(terms, signature) {
+        Ok(schedule) => HttpResponse::Created().json(schedule),
+        Err(err) => schedule_error_response(err),
+    }
+}
+
+/// List schedules
+///
+/// Returns the registered schedules, optionally only those of an owner
+#[utoipa::path(
+    get,
+    path = "/api/v1/schedules",
+    params(
+        ("owner" = Option<String>, Query, description = "Only list the schedules of this owner")
+    ),
+    responses(
+        (status = 200, description = "Schedules retrieved successfully", body = Vec<Schedule>)
+    )
+)]
+pub async fn list_schedules(
+    scheduler: SchedulerData,
+    query: web::Query<ScheduleListQuery>,
+) -> impl Responder {
+    let owner = query.into_inner().owner.map(Address);
+    HttpResponse::Ok().json(scheduler.list(owner.as_ref()))
+}
+
+/// Get a schedule
+///
+/// Returns a schedule with the payments it made so far
+#[utoipa::path(
+    get,
+    path = "/api/v1/schedules/{id}",
+    params(
+        ("id" = String, Path, description = "The schedule ID")
+    ),
+    responses(
+        (status = 200, description = "Schedule retrieved successfully", body = Schedule),
+        (status = 404, description = "Schedule not found", body = ErrorResponse)
+    )
+)]
+pub async fn get_schedule(
+    scheduler: SchedulerData,
+    id: web::Path<String>,
+) -> impl Responder {
+    match scheduler.get(&id) {
+        Some(schedule) => HttpResponse::Ok().json(schedule),
+        None => schedule_error_response(ScheduleError::NotFound(id.into_inner())),
+    }
+}
+
+/// Cancel a schedule
+///
+/// Stops the payments of a schedule and returns what is left on its session key to the owner
+#[utoipa::path(
+    post,
+    path = "/api/v1/schedules/{id}/cancel",
+    params(
+        ("id" = String, Path, description = "The schedule ID")
+    ),
+    request_body = CancelScheduleRequest,
+    responses(
+        (status = 200, description = "Schedule cancelled", body = Schedule),
+        (status = 400, description = "Invalid signature or the schedule is not active", body = ErrorResponse),
+        (status = 404, description = "Schedule not found", body = ErrorResponse)
+    )
+)]
+pub async fn cancel_schedule(
+    blockchain: BlockchainData,
+    scheduler: SchedulerData,
+    id: web::Path<String>,
+    request: web::Json<CancelScheduleRequest>,
+) -> impl Responder {
+    let request = request.into_inner();
+    let signature = match owner_signature(
+        SigningContext::Schedule,
+        &schedules::cancel_payload(&id),
+        request.signature,
+        request.private_key,
+    ) {
+        Ok(signature) => signature,
+        Err(error) => return HttpResponse::BadRequest().json(error),
+    };
+
+    match scheduler.cancel(&id, &signature, &blockchain) {
+        Ok(schedule) => HttpResponse::Ok().json(schedule),
+        Err(err) => schedule_error_response(err),
+    }
+}
+
+/// Get the audit log
+///
+/// Returns all recorded operator and system actions
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/audit",
+    responses(
+        (status = 200, description = "Audit log retrieved successfully", body = Vec<AuditEntry>),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn get_audit_log(
+    req: HttpRequest,
+    admin: AdminData,
+    audit: AuditData,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    HttpResponse::Ok().json(audit.entries())
+}
+
+/// Query parameters for the usage endpoint
+#[derive(Serialize, Deserialize)]
+pub struct UsageQuery {
+    /// First day to report, YYYY-MM-DD (default the first day of the current month)
+    pub from: Option<NaiveDate>,
+
+    /// Last day to report, YYYY-MM-DD (default today)
+    pub to: Option<NaiveDate>,
+
+    /// Only report the API key with this ID
+    pub key_id: Option<String>,
+}
+
+/// Get the API usage per API key
+///
+/// Returns the requests, blocks served and transactions submitted of every API key per day (UTC), with
+/// the totals of the range and how much of its monthly quota each key used. Keys are identified by the
+/// first 16 hex digits of their SHA-256 hash
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/usage",
+    params(
+        ("from" = Option<String>, Query, description = "First day, YYYY-MM-DD (default the first day of the current month)"),
+        ("to" = Option<String>, Query, description = "Last day, YYYY-MM-DD (default today)"),
+        ("key_id" = Option<String>, Query, description = "Only report the API key with this ID")
+    ),
+    responses(
+        (status = 200, description = "Usage retrieved successfully", body = Vec<KeyUsage>),
+        (status = 400, description = "The range ends before it starts", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn get_usage(
+    req: HttpRequest,
+    admin: AdminData,
+    usage: UsageData,
+    query: web::Query<UsageQuery>,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    let today = Utc::now().date_naive();
+    let from = query.from.unwrap_or_else(|| today.with_day(1).expect("every month has a first day"));
+    let to = query.to.unwrap_or(today);
+    if from > to {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidRequest,
+            format!("The range ends on {} before it starts on {}", to, from),
+        ));
+    }
+
+    HttpResponse::Ok().json(usage.report(from, to, query.key_id.as_deref(), today))
+}
+
+/// Request for the add keystore wallet endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct KeystoreWalletRequest {
+    /// The name of the wallet
+    pub name: String,
+
+    /// The private key to import (hex encoded), or None to generate a new one
+    pub private_key: Option<String>,
+}
+
+/// Request for the add watch-only wallet endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct WatchOnlyWalletRequest {
+    /// The name of the wallet
+    pub name: String,
+
+    /// The address to watch
+    pub address: String,
+}
+
+/// Request for the keystore send endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct KeystoreSendRequest {
+    /// The recipient's address or registered name
+    pub recipient: String,
+
+    /// The amount to transfer
+    pub amount: f64,
+
+    /// The transaction fee
+    pub fee: f64,
+}
+
+/// A confirmed transaction involving an address
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct HistoryEntry {
+    /// The index of the block containing the transaction
+    pub block_index: u64,
+
+    /// The transaction
+    pub transaction: Transaction,
+}
+
+/// Response for the keystore wallet endpoints
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct KeystoreWalletResponse {
+    /// The wallet
+    pub wallet: WalletInfo,
+
+    /// The balance of the wallet
+    pub balance: f64,
+
+    /// The nonce of the wallet
+    pub nonce: u64,
+
+    /// The confirmed transactions of the wallet, when requested individually
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub history: Option<Vec<HistoryEntry>>,
+}
+
+/// Converts a keystore error into an HTTP response
+fn keystore_error_response(err: KeystoreError) -> HttpResponse {
+    let body = |code| ErrorResponse::new(code, err.to_string());
+
+    match err {
+        KeystoreError::NotFound(_) => HttpResponse::NotFound().json(body(ErrorCode::NotFound)),
+        KeystoreError::AlreadyExists(_) => HttpResponse::Conflict().json(body(ErrorCode::AlreadyExists)),
+        KeystoreError::WatchOnly(_) => HttpResponse::Forbidden().json(serde_json::json!({
+            "code": ErrorCode::NotPermitted,
+            "error": err.to_string(),
+            "retryable": false,
+            "watch_only": true
+        })),
+        KeystoreError::BackupError(BackupError::WrongSecret) => HttpResponse::Forbidden().json(body(ErrorCode::NotPermitted)),
+        KeystoreError::CryptoError(_) => HttpResponse::BadRequest().json(body(ErrorCode::InvalidKey)),
+        KeystoreError::StorageError(_) => HttpResponse::InternalServerError().json(body(ErrorCode::Internal)),
+        _ => HttpResponse::BadRequest().json(body(ErrorCode::InvalidRequest)),
+    }
+}
+
+/// Builds the keystore response for a wallet
+fn keystore_wallet_response(
+    blockchain: &Blockchain,
+    wallet: WalletInfo,
+    with_history: bool,
+) -> KeystoreWalletResponse {
+    let account = blockchain.get_account_state().get_account(&wallet.address);
+    let history = with_history.then(|| {
+        blockchain
+            .get_address_history(&wallet.address)
+            .into_iter()
+            .map(|(block_index, transaction)| HistoryEntry {
+                block_index,
+                transaction,
+            })
+            .collect()
+    });
+
+    KeystoreWalletResponse {
+        wallet,
+        balance: account.balance,
+        nonce: account.nonce,
+        history,
+    }
+}
+
+/// List keystore wallets
+///
+/// Returns all wallets held by the node, including watch-only wallets, with their balances
+#[utoipa::path(
+    get,
+    path = "/api/v1/keystore/wallets",
+    responses(
+        (status = 200, description = "Wallets retrieved successfully", body = Vec<KeystoreWalletResponse>),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn list_keystore_wallets(
+    req: HttpRequest,
+    admin: AdminData,
+    blockchain: BlockchainData,
+    keystore: KeystoreData,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    let wallets: Vec<KeystoreWalletResponse> = keystore
+        .list()
+        .into_iter()
+        .map(|wallet| keystore_wallet_response(&blockchain, wallet, false))
+        .collect();
+
+    HttpResponse::Ok().json(wallets)
+}
+
+/// Add a wallet to the keystore
+///
+/// Imports a private key, or generates a new one, and holds it on the node
+#[utoipa::path(
+    post,
+    path = "/api/v1/keystore/wallets",
+    request_body = KeystoreWalletRequest,
+    responses(
+        (status = 201, description = "Wallet added successfully", body = WalletInfo),
+        (status = 400, description = "Invalid private key", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 409, description = "A wallet with this name already exists", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn add_keystore_wallet(
+    req: HttpRequest,
+    admin: AdminData,
+    keystore: KeystoreData,
+    wallet_req: web::Json<KeystoreWalletRequest>,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    let wallet = match &wallet_req.private_key {
+        Some(private_key) => match hex::decode(private_key) {
+            Ok(bytes) => Wallet::from_secret_key(&bytes),
+            Err(_) => {
+                return HttpResponse::BadRequest().json(ErrorResponse::new(
+                    ErrorCode::InvalidKey,
+                    "Invalid private key format. Must be a hex string.",
+                ));
+            }
+        },
+        None => Wallet::new(),
+    };
+
+    let result = wallet
+        .map_err(KeystoreError::from)
+        .and_then(|wallet| keystore.add_wallet(&wallet_req.name, &wallet));
+
+    match result {
+        Ok(info) => HttpResponse::Created().json(info),
+        Err(err) => keystore_error_response(err),
+    }
+}
+
+/// Add a watch-only wallet to the keystore
+///
+/// Watches an address without holding its private key, e.g. for cold storage
+#[utoipa::path(
+    post,
+    path = "/api/v1/keystore/watch",
+    request_body = WatchOnlyWalletRequest,
+    responses(
+        (status = 201, description = "Watch-only wallet added successfully", body = WalletInfo),
+        (status = 400, description = "Invalid address", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 409, description = "A wallet with this name already exists", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn add_watch_only_wallet(
+    req: HttpRequest,
+    admin: AdminData,
+    keystore: KeystoreData,
+    wallet_req: web::Json<WatchOnlyWalletRequest>,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    match keystore.add_watch_only(&wallet_req.name, Address(wallet_req.address.clone())) {
+        Ok(info) => HttpResponse::Created().json(info),
+        Err(err) => keystore_error_response(err),
+    }
+}
+
+/// Get a keystore wallet
+///
+/// Returns a wallet held by the node with its balance and transaction history
+#[utoipa::path(
+    get,
+    path = "/api/v1/keystore/wallets/{name}",
+    params(
+        ("name" = String, Path, description = "The wallet name")
+    ),
+    responses(
+        (status = 200, description = "Wallet retrieved successfully", body = KeystoreWalletResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "Wallet not found", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn get_keystore_wallet(
+    req: HttpRequest,
+    admin: AdminData,
+    blockchain: BlockchainData,
+    keystore: KeystoreData,
+    name: web::Path<String>,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    match keystore.get(&name) {
+        Ok(entry) => HttpResponse::Ok().json(keystore_wallet_response(&blockchain, entry.info(), true)),
+        Err(err) => keystore_error_response(err),
+    }
+}
+
+/// Send from a keystore wallet
+///
+/// Signs and submits a transaction with a wallet held by the node. Watch-only wallets are rejected.
+#[utoipa::path(
+    post,
+    path = "/api/v1/keystore/wallets/{name}/send",
+    params(
+        ("name" = String, Path, description = "The wallet name")
+    ),
+    request_body = KeystoreSendRequest,
+    responses(
+        (status = 201, description = "Transaction created successfully", body = TransactionResponse),
+        (status = 400, description = "Invalid transaction data", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 403, description = "The wallet is watch-only", body = ErrorResponse),
+        (status = 404, description = "Wallet not found", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn send_from_keystore_wallet(
+    req: HttpRequest,
+    admin: AdminData,
+    blockchain: BlockchainData,
+    keystore: KeystoreData,
+    name: web::Path<String>,
+    send_req: web::Json<KeystoreSendRequest>,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    let wallet = match keystore.signer(&name) {
+        Ok(wallet) => wallet,
+        Err(err) => return keystore_error_response(err),
+    };
+
+    let recipient = match blockchain.resolve_recipient(&send_req.recipient) {
+        Ok(address) => address,
+        Err(err) => return blockchain_error_response("Invalid recipient", err),
+    };
+
+    let mut transaction = Transaction::new(
+        wallet.address().clone(),
+        recipient,
+        send_req.amount,
+        send_req.fee,
+        blockchain.get_next_nonce(wallet.address()),
+    );
+
+    if let Err(err) = transaction.sign(&wallet) {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidTransaction,
+            format!("Failed to sign transaction: {}", err),
+        ));
+    }
+
+    match blockchain.add_transaction(transaction) {
+        Ok(block_index) => HttpResponse::Created().json(TransactionResponse {
+            message: "Transaction will be added to Block".to_string(),
+            block_index,
+        }),
+        Err(err) => blockchain_error_response("Failed to add transaction", err),
+    }
+}
+
+/// Request for the keystore backup endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct KeystoreBackupRequest {
+    /// The passphrase that unlocks the backup
+    pub passphrase: String,
+
+    /// Number of recovery codes that unlock the backup without the passphrase
+    pub threshold: u8,
+
+    /// Number of recovery codes to generate
+    pub recovery_codes: u8,
+}
+
+/// Response for the keystore backup endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct KeystoreBackupResponse {
+    /// The encrypted backup
+    pub backup: EncryptedBackup,
+
+    /// The recovery codes, shown only once
+    pub recovery_codes: Vec<String>,
+}
+
+/// Request for the keystore restore endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct KeystoreRestoreRequest {
+    /// The encrypted backup
+    pub backup: EncryptedBackup,
+
+    /// The passphrase of the backup
+    pub passphrase: Option<String>,
+
+    /// Recovery codes of the backup, when the passphrase is not given
+    pub recovery_codes: Option<Vec<String>>,
+}
+
+/// Back up the keystore
+///
+/// Exports all wallets encrypted under a passphrase and generates recovery
+/// codes, any `threshold` of which also unlock the backup
+#[utoipa::path(
+    post,
+    path = "/api/v1/keystore/backup",
+    request_body = KeystoreBackupRequest,
+    responses(
+        (status = 200, description = "Backup created successfully", body = KeystoreBackupResponse),
+        (status = 400, description = "Empty passphrase or invalid threshold", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn backup_keystore(
+    req: HttpRequest,
+    admin: AdminData,
+    keystore: KeystoreData,
+    backup_req: web::Json<KeystoreBackupRequest>,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    match keystore.export_backup(&backup_req.passphrase, backup_req.threshold, backup_req.recovery_codes) {
+        Ok((backup, recovery_codes)) => HttpResponse::Ok().json(KeystoreBackupResponse {
+            backup,
+            recovery_codes,
+        }),
+        Err(err) => keystore_error_response(err),
+    }
+}
+
+/// Restore the keystore from a backup
+///
+/// Unlocks the backup with its passphrase or with enough recovery codes and
+/// adds the wallets the keystore does not hold yet
+#[utoipa::path(
+    post,
+    path = "/api/v1/keystore/restore",
+    request_body = KeystoreRestoreRequest,
+    responses(
+        (status = 200, description = "Wallets restored successfully", body = Vec<WalletInfo>),
+        (status = 400, description = "Invalid backup or recovery codes", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 403, description = "Wrong passphrase or recovery codes", body = ErrorResponse),
+        (status = 409, description = "A wallet name is taken by another address", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn restore_keystore(
+    req: HttpRequest,
+    admin: AdminData,
+    keystore: KeystoreData,
+    restore_req: web::Json<KeystoreRestoreRequest>,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    let restore_req = restore_req.into_inner();
+    let secret = match (restore_req.passphrase, restore_req.recovery_codes) {
+        (Some(passphrase), None) => BackupSecret::Passphrase(passphrase),
+        (None, Some(codes)) => BackupSecret::RecoveryCodes(codes),
+        _ => {
+            return HttpResponse::BadRequest().json(ErrorResponse::new(
+                ErrorCode::InvalidRequest,
+                "Provide either the passphrase or the recovery codes",
+            ));
+        }
+    };
+
+    match keystore.restore_backup(&restore_req.backup, &secret) {
+        Ok(wallets) => HttpResponse::Ok().json(wallets),
+        Err(err) => keystore_error_response(err),
+    }
+}
+
+/// Request for the label transaction endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct LabelRequest {
+    /// Free-form label
+    pub label: String,
+
+    /// Accounting category (e.g. "income", "fees")
+    pub category: Option<String>,
+}
+
+/// Returns the response for requests missing an API key
+fn missing_api_key_response() -> HttpResponse {
+    HttpResponse::Unauthorized().json(ErrorResponse::new(
+        ErrorCode::Unauthorized,
+        format!("Missing {} header", auth::API_KEY_HEADER),
+    ))
+}
+
+/// Label a transaction
+///
+/// Attaches a private label and category to a transaction, visible only with the same API key
+#[utoipa::path(
+    put,
+    path = "/api/v1/transactions/{id}/label",
+    params(
+        ("id" = String, Path, description = "The transaction ID"),
+        ("X-API-Key" = String, Header, description = "The API key owning the label")
+    ),
+    request_body = LabelRequest,
+    responses(
+        (status = 200, description = "Transaction labeled successfully", body = TransactionLabel),
+        (status = 401, description = "Missing API key", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+pub async fn set_transaction_label(
+    req: HttpRequest,
+    labels: LabelsData,
+    id: web::Path<String>,
+    label_req: web::Json<LabelRequest>,
+) -> impl Responder {
+    let api_key = match auth::api_key(&req) {
+        Some(api_key) => api_key,
+        None => return missing_api_key_response(),
+    };
+
+    let label_req = label_req.into_inner();
+    let label = TransactionLabel {
+        transaction_id: id.into_inner(),
+        label: label_req.label,
+        category: label_req.category,
+    };
+
+    match labels.set(&api_key, label.clone()) {
+        Ok(_) => HttpResponse::Ok().json(label),
+        Err(err) => HttpResponse::InternalServerError().json(ErrorResponse::new(
+            ErrorCode::Internal,
+            format!("Failed to save label: {}", err),
+        )),
+    }
+}
+
+/// Get the label of a transaction
+///
+/// Returns the private label attached to a transaction with this API key
+#[utoipa::path(
+    get,
+    path = "/api/v1/transactions/{id}/label",
+    params(
+        ("id" = String, Path, description = "The transaction ID"),
+        ("X-API-Key" = String, Header, description = "The API key owning the label")
+    ),
+    responses(
+        (status = 200, description = "Label retrieved successfully", body = TransactionLabel),
+        (status = 401, description = "Missing API key", body = ErrorResponse),
+        (status = 404, description = "Transaction has no label", body = ErrorResponse)
+    )
+)]
+pub async fn get_transaction_label(
+    req: HttpRequest,
+    labels: LabelsData,
+    id: web::Path<String>,
+) -> impl Responder {
+    let api_key = match auth::api_key(&req) {
+        Some(api_key) => api_key,
+        None => return missing_api_key_response(),
+    };
+
+    match labels.get(&api_key, &id) {
+        Some(label) => HttpResponse::Ok().json(label),
+        None => HttpResponse::NotFound().json(ErrorResponse::new(
+            ErrorCode::NotFound,
+            format!("Transaction {} has no label", id),
+        )),
+    }
+}
+
+/// Escapes a value for a CSV field
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Export the transactions of an address as CSV
+///
+/// Produces a CSV of all confirmed transactions of an address for bookkeeping, including the
+/// private labels of the calling API key when one is provided
+#[utoipa::path(
+    get,
+    path = "/api/v1/address/{address}/transactions/export.csv",
+    params(
+        ("address" = String, Path, description = "The address"),
+        ("X-API-Key" = Option<String>, Header, description = "The API key whose labels to include")
+    ),
+    responses(
+        (status = 200, description = "CSV export", content_type = "text/csv", body = String)
+    )
+)]
+pub async fn export_address_transactions(
+    req: HttpRequest,
+    blockchain: BlockchainData,
+    labels: LabelsData,
+    address: web::Path<String>,
+) -> impl Responder {
+    let address = Address(address.into_inner());
+    let api_key = auth::api_key(&req);
+
+    let mut csv = String::from(
+        "timestamp,block_index,transaction_id,direction,counterparty,amount,fee,label,category\n",
+    );
+
+    for (block_index, transaction) in blockchain.get_address_history(&address) {
+        let outgoing = transaction.sender == address;
+        let counterparty = if outgoing {
+            &transaction.recipient
+        } else {
+            &transaction.sender
+        };
+        let label = api_key
+            .as_ref()
+            .and_then(|api_key| labels.get(api_key, &transaction.id));
+
+        let fields = [
+            transaction.timestamp.to_rfc3339(),
+            block_index.to_string(),
+            transaction.id.clone(),
+            if outgoing { "out" } else { "in" }.to_string(),
+            counterparty.0.clone(),
+            transaction.amount.to_string(),
+            if outgoing { transaction.fee } else { 0.0 }.to_string(),
+            label.as_ref().map(|l| l.label.clone()).unwrap_or_default(),
+            label.and_then(|l| l.category).unwrap_or_default(),
+        ];
+
+        let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+
+    HttpResponse::Ok()
+        .content_type("text/csv")
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}-transactions.csv\"", address),
+        ))
+        .body(csv)
+}
+
+/// Get the counterparties of an address
+///
+/// Returns how much an address sent to and received from each other address, with the first and
+/// last interaction, for interaction graph views
+#[utoipa::path(
+    get,
+    path = "/api/v1/address/{address}/counterparties",
+    params(
+        ("address" = String, Path, description = "The address"),
+        ("at_block" = Option<String>, Query, description = "Hash or height of the block to read the state after, the tip if omitted")
+    ),
+    responses(
+        (status = 200, description = "Counterparties retrieved successfully", body = Vec<CounterpartySummary>),
+        (status = 400, description = "Invalid at_block", body = ErrorResponse),
+        (status = 404, description = "No block of the chain matches at_block", body = ErrorResponse)
+    )
+)]
+pub async fn get_address_counterparties(
+    blockchain: BlockchainData,
+    address: web::Path<String>,
+    query: web::Query<AtBlockQuery>,
+) -> impl Responder {
+    let at_height = match height_at(&blockchain, query.at_block.as_deref()) {
+        Ok(height) => height,
+        Err(err) => return at_block_error_response(err),
+    };
+
+    HttpResponse::Ok().json(blockchain.get_counterparties(&Address(address.into_inner()), at_height))
+}
+
+/// Query parameters for the transaction graph export
+#[derive(Serialize, Deserialize)]
+pub struct GraphQuery {
+    /// The file format, defaults to JSON
+    pub format: Option<GraphFormat>,
+
+    /// The first block height to include, defaults to the genesis block
+    pub from_height: Option<u64>,
+
+    /// The last block height to include, defaults to the tip
+    pub to_height: Option<u64>,
+}
+
+/// Export the transaction graph
+///
+/// Exports the network of transfers over a range of blocks, with addresses as nodes and the
+/// aggregated transfers between two addresses as edges weighted by amount
+#[utoipa::path(
+    get,
+    path = "/api/v1/graph",
+    params(
+        ("format" = Option<GraphFormat>, Query, description = "The file format (graphml, dot or json), defaults to json"),
+        ("from_height" = Option<u64>, Query, description = "The first block height to include"),
+        ("to_height" = Option<u64>, Query, description = "The last block height to include")
+    ),
+    responses(
+        (status = 200, description = "Graph exported successfully", body = TransactionGraph),
+        (status = 400, description = "Invalid format or height range", body = ErrorResponse)
+    )
+)]
+pub async fn export_transaction_graph(
+    blockchain: BlockchainData,
+    query: web::Query<GraphQuery>,
+) -> impl Responder {
+    let format = query.format.unwrap_or(GraphFormat::Json);
+    let from_height = query.from_height.unwrap_or(0);
+    let to_height = query.to_height.unwrap_or(u64::MAX);
+
+    if from_height > to_height {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidRequest,
+            "from_height must not be above to_height",
+        ));
+    }
+
+    let chain = blockchain.get_chain();
+    let blocks = chain
+        .iter()
+        .filter(|block| (from_height..=to_height).contains(&block.index));
+    let graph = TransactionGraph::from_blocks(blocks);
+
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"transactions.{}\"", format.extension()),
+        ))
+        .body(graph.render(format))
+}
+
+/// Query parameters for the activity report
+#[derive(Serialize, Deserialize)]
+pub struct ActivityReportQuery {
+    /// The length of each period, defaults to day
+    pub granularity: Option<Granularity>,
+
+    /// Only return the most recent periods
+    pub limit: Option<usize>,
+}
+
+/// Get the chain activity report
+///
+/// Returns per-period transaction counts, volume, fees, new addresses and blocks mined
+#[utoipa::path(
+    get,
+    path = "/api/v1/reports/activity",
+    params(
+        ("granularity" = Option<Granularity>, Query, description = "The length of each period (hour, day or week), defaults to day"),
+        ("limit" = Option<usize>, Query, description = "Only return the most recent periods")
+    ),
+    responses(
+        (status = 200, description = "Activity report retrieved successfully", body = Vec<ActivityPeriod>),
+        (status = 400, description = "Invalid granularity", body = ErrorResponse)
+    )
+)]
+pub async fn get_activity_report(
+    blockchain: BlockchainData,
+    query: web::Query<ActivityReportQuery>,
+) -> impl Responder {
+    let granularity = query.granularity.unwrap_or(Granularity::Day);
+
+    HttpResponse::Ok().json(blockchain.get_activity_report(granularity, query.limit))
+}
+
+/// List chain reorganizations
+///
+/// Returns all past reorganizations, oldest first
+#[utoipa::path(
+    get,
+    path = "/api/v1/reorgs",
+    responses(
+        (status = 200, description = "Reorganizations retrieved successfully", body = Vec<ReorgEvent>)
+    )
+)]
+pub async fn get_reorgs(blockchain: BlockchainData) -> impl Responder {
+    HttpResponse::Ok().json(blockchain.get_reorgs())
+}
+
+/// Request for the pause endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PauseRequest {
+    /// The operator pausing the node
+    pub operator: String,
+
+    /// Why the node is paused, reported to rejected writers
+    pub reason: String,
+
+    /// Whether to pause transaction admission, defaults to true
+    pub transactions: Option<bool>,
+
+    /// Whether to pause block production, defaults to true
+    pub mining: Option<bool>,
+}
+
+/// Request for the resume endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ResumeRequest {
+    /// The operator resuming the node
+    pub operator: String,
+}
+
+/// Get the maintenance status
+///
+/// Returns which writers are currently paused
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/maintenance",
+    responses(
+        (status = 200, description = "Maintenance status retrieved successfully", body = MaintenanceStatus),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn get_maintenance(
+    req: HttpRequest,
+    admin: AdminData,
+    blockchain: BlockchainData,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    HttpResponse::Ok().json(blockchain.get_maintenance_status())
+}
+
+/// Pause the node for maintenance
+///
+/// Rejects new transactions and/or block production with 503 until resumed.
+/// Returns once in-flight writes have finished.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/maintenance/pause",
+    request_body = PauseRequest,
+    responses(
+        (status = 200, description = "Node paused", body = MaintenanceStatus),
+        (status = 400, description = "Nothing to pause", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn pause_node(
+    req: HttpRequest,
+    admin: AdminData,
+    audit: AuditData,
+    blockchain: BlockchainData,
+    pause_req: web::Json<PauseRequest>,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    let status = MaintenanceStatus::paused(
+        pause_req.transactions.unwrap_or(true),
+        pause_req.mining.unwrap_or(true),
+        &pause_req.reason,
+    );
+    if !status.is_active() {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidRequest,
+            "Pause at least transactions or mining",
+        ));
+    }
+
+    blockchain.set_maintenance(status.clone());
+    audit.record(
+        &pause_req.operator,
+        "maintenance.paused",
+        "node",
+        &format!(
+            "transactions: {}, mining: {}, reason: {}",
+            status.transactions_paused, status.mining_paused, pause_req.reason
+        ),
+    );
+
+    HttpResponse::Ok().json(status)
+}
+
+/// Resume the node after maintenance
+///
+/// Accepts transactions and produces blocks again
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/maintenance/resume",
+    request_body = ResumeRequest,
+    responses(
+        (status = 200, description = "Node resumed", body = MaintenanceStatus),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn resume_node(
+    req: HttpRequest,
+    admin: AdminData,
+    audit: AuditData,
+    blockchain: BlockchainData,
+    resume_req: web::Json<ResumeRequest>,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    blockchain.set_maintenance(MaintenanceStatus::default());
+    audit.record(&resume_req.operator, "maintenance.resumed", "node", "");
+
+    HttpResponse::Ok().json(blockchain.get_maintenance_status())
+}
+
+/// Request for the reset endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ResetRequest {
+    /// The operator resetting the chain
+    pub operator: String,
+}
+
+/// Reset the chain to genesis
+///
+/// Drops all blocks, pending transactions and balances and starts over from
+/// a new genesis block. Meant for test networks that need a clean slate.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/reset",
+    request_body = ResetRequest,
+    responses(
+        (status = 200, description = "Chain reset, returns the new genesis block", body = Block),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn reset_chain(
+    req: HttpRequest,
+    admin: AdminData,
+    audit: AuditData,
+    blockchain: BlockchainData,
+    reset_req: web::Json<ResetRequest>,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    let chain = blockchain.clone();
+    let reset = match web::block(move || chain.reset()).await {
+        Ok(reset) => reset,
+        Err(err) => {
+            return HttpResponse::InternalServerError().json(ErrorResponse::new(
+                ErrorCode::Internal,
+                format!("Failed to reset chain: {}", err),
+            ));
+        }
+    };
+
+    match reset {
+        Ok(genesis) => {
+            audit.record(&reset_req.operator, "chain.reset", "node", &format!("genesis: {}", genesis.hash));
+            HttpResponse::Ok().json(genesis)
+        }
+        Err(err) => blockchain_error_response("Failed to reset chain", err),
+    }
+}
+
+/// Append a block mined by another node
+///
+/// Validates the block against the tip of the chain and applies it, which
+/// is how a follower, or any node, takes in blocks it did not mine.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/blocks",
+    request_body = Block,
+    responses(
+        (status = 201, description = "Block appended, returns the new height", body = u64),
+        (status = 400, description = "The block is invalid or does not link to the tip", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn append_block(
+    req: HttpRequest,
+    admin: AdminData,
+    blockchain: BlockchainData,
+    block: web::Json<Block>,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    let block = block.into_inner();
+    let height = block.index;
+    let chain = blockchain.clone();
+    match web::block(move || chain.append_block(block)).await {
+        Ok(Ok(())) => HttpResponse::Created().json(height),
+        Ok(Err(err)) => blockchain_error_response("Failed to append block", err),
+        Err(err) => HttpResponse::InternalServerError().json(ErrorResponse::new(
+            ErrorCode::Internal,
+            format!("Failed to append block: {}", err),
+        )),
+    }
+}
+
+/// Request for the snapshot endpoints
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SnapshotRequest {
+    /// The operator taking or restoring the snapshot
+    pub operator: String,
+}
+
+/// Builds the error response for a snapshot error
+fn snapshot_error_response(err: SnapshotError) -> HttpResponse {
+    match err {
+        SnapshotError::NotFound(_) => HttpResponse::NotFound().json(ErrorResponse::new(ErrorCode::NotFound, err.to_string())),
+        _ => HttpResponse::InternalServerError().json(ErrorResponse::new(ErrorCode::Internal, err.to_string())),
+    }
+}
+
+/// List the storage snapshots
+///
+/// Returns the kept snapshots, newest first
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/snapshots",
+    responses(
+        (status = 200, description = "Snapshots retrieved successfully", body = Vec<SnapshotInfo>),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn list_snapshots(
+    req: HttpRequest,
+    admin: AdminData,
+    snapshots: SnapshotsData,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    match snapshots.list() {
+        Ok(list) => HttpResponse::Ok().json(list),
+        Err(err) => snapshot_error_response(err),
+    }
+}
+
+/// Take a storage snapshot
+///
+/// Copies the node's storage right away. The oldest snapshots beyond the
+/// retention are removed.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/snapshots",
+    request_body = SnapshotRequest,
+    responses(
+        (status = 200, description = "Snapshot taken", body = SnapshotInfo),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn take_snapshot(
+    req: HttpRequest,
+    admin: AdminData,
+    audit: AuditData,
+    blockchain: BlockchainData,
+    snapshots: SnapshotsData,
+    snapshot_req: web::Json<SnapshotRequest>,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    let taken = web::block(move || snapshots.take(&blockchain, SnapshotTrigger::Manual)).await;
+    match taken {
+        Ok(Ok(info)) => {
+            audit.record(&snapshot_req.operator, "snapshot.created", &info.id, &format!("height: {}", info.height));
+            HttpResponse::Ok().json(info)
+        }
+        Ok(Err(err)) => snapshot_error_response(err),
+        Err(err) => HttpResponse::InternalServerError().json(ErrorResponse::new(
+            ErrorCode::Internal,
+            format!("Failed to take snapshot: {}", err),
+        )),
+    }
+}
+
+/// Restore a storage snapshot
+///
+/// Replaces the blocks, balances and pending transactions with those of the
+/// snapshot. The keystore, keys and audit log are left as they are.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/snapshots/{id}/restore",
+    params(
+        ("id" = String, Path, description = "The ID of the snapshot")
+    ),
+    request_body = SnapshotRequest,
+    responses(
+        (status = 200, description = "Snapshot restored", body = SnapshotInfo),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "Snapshot not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn restore_snapshot(
+    req: HttpRequest,
+    admin: AdminData,
+    audit: AuditData,
+    blockchain: BlockchainData,
+    snapshots: SnapshotsData,
+    path: web::Path<String>,
+    snapshot_req: web::Json<SnapshotRequest>,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    let id = path.into_inner();
+    let restored = web::block(move || snapshots.restore(&id, &blockchain)).await;
+    match restored {
+        Ok(Ok(info)) => {
+            audit.record(&snapshot_req.operator, "snapshot.restored", &info.id, &format!("height: {}", info.height));
+            HttpResponse::Ok().json(info)
+        }
+        Ok(Err(err)) => snapshot_error_response(err),
+        Err(err) => HttpResponse::InternalServerError().json(ErrorResponse::new(
+            ErrorCode::Internal,
+            format!("Failed to restore snapshot: {}", err),
+        )),
+    }
+}
+
+/// Get storage statistics
+///
+/// Reports the size of the database and of each of its trees, the hit rate
+/// of the raw block cache, and when the storage was last flushed and
+/// compacted. Every tree is read, so this is slow on large chains.
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/storage/stats",
+    responses(
+        (status = 200, description = "Storage statistics retrieved successfully", body = StorageStats),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "The node keeps its chain in memory", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn get_storage_stats(req: HttpRequest, admin: AdminData, blockchain: BlockchainData) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    let storage = match blockchain.get_storage() {
+        Some(storage) => storage,
+        None => {
+            return HttpResponse::NotFound()
+                .json(ErrorResponse::new(ErrorCode::NotFound, "The node has no persistent storage"));
+        }
+    };
+
+    match web::block(move || storage.stats()).await {
+        Ok(Ok(stats)) => HttpResponse::Ok().json(stats),
+        Ok(Err(err)) => HttpResponse::InternalServerError().json(ErrorResponse::new(
+            ErrorCode::Internal,
+            format!("Failed to read storage statistics: {}", err),
+        )),
+        Err(err) => HttpResponse::InternalServerError().json(ErrorResponse::new(
+            ErrorCode::Internal,
+            format!("Failed to read storage statistics: {}", err),
+        )),
+    }
+}
+
+/// Request for the rotate key endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RotateKeyRequest {
+    /// The operator rotating the key
+    pub operator: String,
+
+    /// Seconds the old key stays valid, the node's default overlap if omitted
+    pub overlap_secs: Option<u64>,
+}
+
+/// List the node keys
+///
+/// Returns the public keys the node signs with and the rotated out keys that still validate
+#[utoipa::path(
+    get,
+    path = "/api/v1/admin/keys",
+    responses(
+        (status = 200, description = "Node keys retrieved successfully", body = Vec<KeyRingInfo>),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn list_node_keys(
+    req: HttpRequest,
+    admin: AdminData,
+    node_keys: NodeKeysData,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    let keys: Vec<KeyRingInfo> = node_keys.list();
+    HttpResponse::Ok().json(keys)
+}
+
+/// Rotate a node key
+///
+/// Replaces the key with a new one. The old key keeps validating signatures
+/// until the overlap window ends.
+#[utoipa::path(
+    post,
+    path = "/api/v1/admin/keys/{purpose}/rotate",
+    params(
+        ("purpose" = KeyPurpose, Path, description = "The key to rotate (identity or webhook)")
+    ),
+    request_body = RotateKeyRequest,
+    responses(
+        (status = 200, description = "Key rotated", body = KeyRingInfo),
+        (status = 400, description = "Overlap too long", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "Unknown key purpose", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn rotate_node_key(
+    req: HttpRequest,
+    admin: AdminData,
+    node_keys: NodeKeysData,
+    path: web::Path<String>,
+    rotate_req: web::Json<RotateKeyRequest>,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    let purpose: KeyPurpose = match path.parse() {
+        Ok(purpose) => purpose,
+        Err(err) => {
+            return HttpResponse::NotFound().json(ErrorResponse::new(ErrorCode::NotFound, err.to_string()));
+        }
+    };
+
+    if rotate_req.overlap_secs.is_some_and(|secs| secs > MAX_KEY_OVERLAP_SECS) {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidRequest,
+            format!("overlap_secs must be at most {}", MAX_KEY_OVERLAP_SECS),
+        ));
+    }
+    let overlap = rotate_req.overlap_secs.map(|secs| chrono::Duration::seconds(secs as i64));
+
+    match node_keys.rotate(purpose, &rotate_req.operator, overlap) {
+        Ok(info) => HttpResponse::Ok().json(info),
+        Err(err) => HttpResponse::InternalServerError().json(ErrorResponse::new(ErrorCode::Internal, err.to_string())),
+    }
+}
+
+/// Query parameters for the block at time endpoint
+#[derive(Serialize, Deserialize)]
+pub struct BlockAtQuery {
+    /// The moment to look up (RFC 3339)
+    pub time: DateTime<Utc>,
+}
+
+/// Get the block at a moment
+///
+/// Returns the latest block created at or before the given time
+#[utoipa::path(
+    get,
+    path = "/api/v1/blocks/at",
+    params(
+        ("time" = String, Query, description = "The moment to look up (RFC 3339), e.g. 2024-01-01T00:00:00Z")
+    ),
+    responses(
+        (status = 200, description = "Block retrieved successfully", body = Block),
+        (status = 400, description = "Invalid time", body = ErrorResponse),
+        (status = 404, description = "The time is before the genesis block", body = ErrorResponse)
+    )
+)]
+pub async fn get_block_at(
+    req: HttpRequest,
+    blockchain: BlockchainData,
+    query: web::Query<BlockAtQuery>,
+) -> impl Responder {
+    match blockchain.get_block_at(query.time) {
+        Some(block) => {
+            usage::served_blocks(&req, 1);
+            HttpResponse::Ok().json(block)
+        }
+        None => HttpResponse::NotFound().json(ErrorResponse::new(
+            ErrorCode::NotFound,
+            format!("No block exists at or before {}", query.time.to_rfc3339()),
+        )),
+    }
+}
+
+/// How long the next block endpoint waits when no timeout is given
+const DEFAULT_NEXT_BLOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest the next block endpoint waits
+const MAX_NEXT_BLOCK_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Query parameters for the next block endpoint
+#[derive(Serialize, Deserialize)]
+pub struct NextBlockQuery {
+    /// Hash of the last block the client has, the current tip if omitted
+    pub after_hash: Option<String>,
+
+    /// How long to wait, e.g. "30s", "500ms" or "2m" (plain numbers are seconds)
+    pub timeout: Option<String>,
+}
+
+/// Parses the timeout of the next block endpoint
+fn parse_timeout(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (number, unit) = value.split_at(value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len()));
+    let number: u64 = number.parse().ok()?;
+
+    match unit {
+        "ms" => Some(Duration::from_millis(number)),
+        "" | "s" => Some(Duration::from_secs(number)),
+        "m" => Some(Duration::from_secs(number.checked_mul(60)?)),
+        _ => None,
+    }
+}
+
+/// Wait for the next block
+///
+/// Holds the request until a block follows `after_hash` or the timeout
+/// elapses, so clients get new blocks without polling in a tight loop
+#[utoipa::path(
+    get,
+    path = "/api/v1/blocks/next",
+    params(
+        ("after_hash" = Option<String>, Query, description = "Hash of the last block the client has, the current tip if omitted"),
+        ("timeout" = Option<String>, Query, description = "How long to wait, e.g. 30s or 500ms (default 30s, at most 120s)")
+    ),
+    responses(
+        (status = 200, description = "The block following after_hash", body = Block),
+        (status = 204, description = "No block was appended before the timeout"),
+        (status = 400, description = "Invalid timeout", body = ErrorResponse),
+        (status = 404, description = "No block of the chain has this hash, e.g. after a reorganization (STALE_TIP)", body = ErrorResponse)
+    )
+)]
+pub async fn get_next_block(
+    req: HttpRequest,
+    blockchain: BlockchainData,
+    query: web::Query<NextBlockQuery>,
+) -> impl Responder {
+    let timeout = match query.timeout.as_deref().map(parse_timeout) {
+        None => DEFAULT_NEXT_BLOCK_TIMEOUT,
+        Some(Some(timeout)) if timeout <= MAX_NEXT_BLOCK_TIMEOUT => timeout,
+        Some(_) => {
+            return HttpResponse::BadRequest().json(ErrorResponse::new(
+                ErrorCode::InvalidRequest,
+                format!("timeout must be a duration like 30s of at most {}s", MAX_NEXT_BLOCK_TIMEOUT.as_secs()),
+            ));
+        }
+    };
+
+    let after_hash = query
+        .after_hash
+        .clone()
+        .unwrap_or_else(|| blockchain.get_last_block().hash);
+
+    match blockchain.wait_for_block_after(&after_hash, timeout).await {
+        Ok(Some(block)) => {
+            usage::served_blocks(&req, 1);
+            HttpResponse::Ok().json(block)
+        }
+        Ok(None) => HttpResponse::NoContent().finish(),
+        Err(BlockchainError::BlockNotFound(hash)) => HttpResponse::NotFound().json(ErrorResponse::new(
+            ErrorCode::StaleTip,
+            format!("No block with hash {}", hash),
+        )),
+        Err(err) => blockchain_error_response("Failed to wait for the next block", err),
+    }
+}
+
+/// The bytes a transaction ID was hashed from
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RawTransactionResponse {
+    /// ID of the transaction
+    pub id: String,
+
+    /// Hex encoded canonical JSON the ID is the SHA-256 hash of, null for random version 1 IDs
+    pub raw: Option<String>,
+}
+
+/// Response for the raw block endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RawBlockResponse {
+    /// Hash of the block
+    pub hash: String,
+
+    /// Hex encoded canonical JSON the hash is the SHA-256 hash of
+    pub raw: String,
+
+    /// The bytes of every transaction, in block order
+    pub transactions: Vec<RawTransactionResponse>,
+}
+
+/// Get the raw bytes of a block
+///
+/// Returns the exact canonical bytes the block hash and the transaction IDs
+/// were computed from, so anyone can hash them and compare
+#[utoipa::path(
+    get,
+    path = "/api/v1/blocks/{hash}/raw",
+    params(
+        ("hash" = String, Path, description = "Hash of the block")
+    ),
+    responses(
+        (status = 200, description = "The raw bytes of the block", body = RawBlockResponse),
+        (status = 404, description = "No block of the chain has this hash", body = ErrorResponse)
+    )
+)]
+pub async fn get_raw_block(
+    req: HttpRequest,
+    blockchain: BlockchainData,
+    hash: web::Path<String>,
+) -> impl Responder {
+    match blockchain.get_raw_block(&hash) {
+        Ok(raw) => {
+            usage::served_blocks(&req, 1);
+            HttpResponse::Ok().json(RawBlockResponse {
+                hash: raw.hash,
+                raw: hex::encode(raw.bytes),
+                transactions: raw
+                    .transactions
+                    .into_iter()
+                    .map(|transaction| RawTransactionResponse {
+                        id: transaction.id,
+                        raw: transaction.bytes.map(hex::encode),
+                    })
+                    .collect(),
+            })
+        }
+        Err(BlockchainError::BlockNotFound(hash)) => HttpResponse::NotFound().json(ErrorResponse::new(
+            ErrorCode::NotFound,
+            format!("No block with hash {}", hash),
+        )),
+        Err(err) => blockchain_error_response("Failed to get the raw block", err),
+    }
+}
+
+/// Default number of blocks the block headers endpoint reads
+const DEFAULT_HEADER_COUNT: usize = 100;
+
+/// Maximum number of blocks the block headers endpoint reads
+const MAX_HEADER_COUNT: usize = 1000;
+
+/// Query parameters for the block headers endpoint
+#[derive(Serialize, Deserialize)]
+pub struct BlockHeadersQuery {
+    /// Height of the first block, 0 if omitted
+    pub from: Option<u64>,
+
+    /// How many blocks to read
+    pub count: Option<usize>,
+
+    /// Only blocks that may involve this address
+    pub address: Option<String>,
+}
+
+/// Get block headers
+///
+/// Returns the headers of a range of blocks with a bloom filter of the addresses each
+/// block touches. A client watching addresses only needs to fetch the blocks whose
+/// filter may contain one of them.
+#[utoipa::path(
+    get,
+    path = "/api/v1/blocks/headers",
+    params(
+        ("from" = Option<u64>, Query, description = "Height of the first block, 0 if omitted"),
+        ("count" = Option<usize>, Query, description = "How many blocks to read, defaults to 100, at most 1000"),
+        ("address" = Option<String>, Query, description = "Only return the blocks whose filter may contain this address")
+    ),
+    responses(
+        (status = 200, description = "Headers of the blocks in chain order", body = Vec<BlockHeader>),
+        (status = 400, description = "Invalid count", body = ErrorResponse)
+    )
+)]
+pub async fn get_block_headers(
+    blockchain: BlockchainData,
+    query: web::Query<BlockHeadersQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    let count = query.count.unwrap_or(DEFAULT_HEADER_COUNT);
+    if count == 0 || count > MAX_HEADER_COUNT {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidRequest,
+            format!("Count must be between 1 and {}", MAX_HEADER_COUNT),
+        ));
+    }
+
+    let address = query.address.map(Address);
+    match web::block(move || blockchain.get_block_headers(query.from.unwrap_or(0), count, address.as_ref())).await {
+        Ok(Ok(headers)) => HttpResponse::Ok().json(headers),
+        Ok(Err(err)) => blockchain_error_response("Failed to get the block headers", err),
+        Err(err) => HttpResponse::InternalServerError().json(ErrorResponse::new(ErrorCode::Internal, err.to_string())),
+    }
+}
+
+/// Request for the hash verification endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct VerifyHashRequest {
+    /// Hex encoded bytes to hash, e.g. the raw bytes of a block
+    pub raw: String,
+
+    /// Hash the bytes should have
+    pub expected_hash: Option<String>,
+}
+
+/// Response for the hash verification endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct VerifyHashResponse {
+    /// SHA-256 hash of the bytes
+    pub hash: String,
+
+    /// Whether the hash is the expected one, null if none was given
+    pub matches: Option<bool>,
+
+    /// Whether the bytes are JSON in canonical form
+    pub canonical: bool,
+
+    /// Hash of the same JSON in canonical form, null if the bytes are canonical or not JSON
+    pub canonical_hash: Option<String>,
+}
+
+/// Verify a hash
+///
+/// Recomputes the SHA-256 hash of submitted bytes and tells whether they are
+/// in the canonical form hashes are computed from. For bytes that are not,
+/// the hash of their canonical form shows whether only the encoding differs
+#[utoipa::path(
+    post,
+    path = "/api/v1/verify/hash",
+    request_body = VerifyHashRequest,
+    responses(
+        (status = 200, description = "The recomputed hash", body = VerifyHashResponse),
+        (status = 400, description = "The bytes are not valid hex", body = ErrorResponse)
+    )
+)]
+pub async fn verify_hash(request: web::Json<VerifyHashRequest>) -> impl Responder {
+    let raw = match hex::decode(request.raw.trim()) {
+        Ok(raw) => raw,
+        Err(e) => {
+            return HttpResponse::BadRequest().json(ErrorResponse::new(
+                ErrorCode::InvalidRequest,
+                format!("Invalid hex in raw: {}", e),
+            ));
+        }
+    };
+
+    let hash = format!("{:x}", Sha256::digest(&raw));
+    let canonical = canonical::canonicalize(&raw);
+
+    HttpResponse::Ok().json(VerifyHashResponse {
+        matches: request
+            .expected_hash
+            .as_ref()
+            .map(|expected| expected.trim().eq_ignore_ascii_case(&hash)),
+        canonical: canonical.as_deref() == Some(raw.as_slice()),
+        canonical_hash: canonical
+            .filter(|bytes| *bytes != raw)
+            .map(|bytes| format!("{:x}", Sha256::digest(bytes))),
+        hash,
+    })
+}
+
+/// Blocks simulated when the difficulty simulation is not told how many
+const DEFAULT_SIMULATED_BLOCKS: u64 = 1000;
+
+/// Most blocks the difficulty simulation runs for
+const MAX_SIMULATED_BLOCKS: u64 = 1_000_000;
+
+/// Evenly spaced steps the difficulty simulation reports besides the changes
+const SIMULATION_SAMPLES: u64 = 50;
+
+/// Query parameters for the difficulty simulation endpoint
+#[derive(Serialize, Deserialize)]
+pub struct SimulationQuery {
+    /// Assumed hashes per second of the whole network
+    pub hashrate: f64,
+
+    /// Seconds a block should take (default 60)
+    pub target_interval: Option<f64>,
+
+    /// Number of blocks to simulate
+    pub blocks: Option<u64>,
+
+    /// Blocks between difficulty adjustments, the node's fixed difficulty if omitted
+    pub retarget_interval: Option<u64>,
+
+    /// Blocks between reward halvings, the node's fixed reward if omitted
+    pub halving_interval: Option<u64>,
+}
+
+/// Simulate difficulty and emission
+///
+/// Predicts the difficulty trajectory and coin emission for an assumed
+/// network hash rate. The node mines at a fixed difficulty and reward;
+/// retarget and halving intervals simulate what Bitcoin-style rules would do
+#[utoipa::path(
+    get,
+    path = "/api/v1/simulate/difficulty",
+    params(
+        ("hashrate" = f64, Query, description = "Assumed hashes per second of the whole network"),
+        ("target_interval" = Option<f64>, Query, description = "Seconds a block should take (default 60)"),
+        ("blocks" = Option<u64>, Query, description = "Number of blocks to simulate (default 1000, at most 1000000)"),
+        ("retarget_interval" = Option<u64>, Query, description = "Blocks between difficulty adjustments"),
+        ("halving_interval" = Option<u64>, Query, description = "Blocks between reward halvings")
+    ),
+    responses(
+        (status = 200, description = "Simulation completed", body = SimulationReport),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse)
+    )
+)]
+pub async fn simulate_difficulty(query: web::Query<SimulationQuery>) -> impl Responder {
+    let target_interval = query.target_interval.unwrap_or(simulation::DEFAULT_TARGET_INTERVAL_SECS);
+    let blocks = query.blocks.unwrap_or(DEFAULT_SIMULATED_BLOCKS);
+
+    let invalid = if !(query.hashrate.is_finite() && query.hashrate > 0.0) {
+        Some("hashrate must be a positive number".to_string())
+    } else if !(target_interval.is_finite() && target_interval > 0.0) {
+        Some("target_interval must be a positive number of seconds".to_string())
+    } else if blocks == 0 || blocks > MAX_SIMULATED_BLOCKS {
+        Some(format!("blocks must be between 1 and {}", MAX_SIMULATED_BLOCKS))
+    } else if query.retarget_interval == Some(0) || query.halving_interval == Some(0) {
+        Some("intervals must be at least one block".to_string())
+    } else {
+        None
+    };
+    if let Some(error) = invalid {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(ErrorCode::InvalidRequest, error));
+    }
+
+    let rules = ConsensusRules {
+        retarget_interval: query.retarget_interval,
+        halving_interval: query.halving_interval,
+        ..ConsensusRules::node(target_interval)
+    };
+
+    let report = web::block(move || simulation::simulate(&rules, query.hashrate, blocks, SIMULATION_SAMPLES)).await;
+    match report {
+        Ok(report) => HttpResponse::Ok().json(report),
+        Err(err) => HttpResponse::InternalServerError().json(ErrorResponse::new(
+            ErrorCode::Internal,
+            format!("Simulation failed: {}", err),
+        )),
+    }
+}
+
+/// Races simulated when the attack simulation is not told how many
+const DEFAULT_ATTACK_TRIALS: u64 = 1000;
+
+/// Most confirmations the attack simulation accepts
+const MAX_ATTACK_CONFIRMATIONS: u64 = 100;
+
+/// Query parameters for the attack simulation endpoint
+#[derive(Serialize, Deserialize)]
+pub struct AttackQuery {
+    /// Share of the network hash rate the attacker controls
+    pub attacker_share: f64,
+
+    /// Blocks the merchant waits for, counting the one with the payment (default 6)
+    pub confirmations: Option<u64>,
+
+    /// Blocks the attacker may fall behind before giving up (default 20)
+    pub max_deficit: Option<u64>,
+
+    /// Number of races (default 1000)
+    pub trials: Option<u64>,
+
+    /// Seed of the races, random if omitted
+    pub seed: Option<u64>,
+
+    /// Whether to replay an example race against a copy of the chain
+    pub demonstrate: Option<bool>,
+}
+
+/// Simulate a double-spend attack
+///
+/// Races an attacker with a share of the hash rate against the honest miners
+/// many times and reports how often it reverses a confirmed payment. With
+/// `demonstrate`, one of the races is replayed with real blocks against a
+/// copy of the local chain, which reorganizes if the attacker wins. The
+/// node's own chain is never touched
+#[utoipa::path(
+    get,
+    path = "/api/v1/simulate/attack",
+    params(
+        ("attacker_share" = f64, Query, description = "Share of the network hash rate the attacker controls"),
+        ("confirmations" = Option<u64>, Query, description = "Blocks the merchant waits for (default 6, at most 100)"),
+        ("max_deficit" = Option<u64>, Query, description = "Blocks the attacker may fall behind before giving up (default 20)"),
+        ("trials" = Option<u64>, Query, description = "Number of races (default 1000, at most 10000)"),
+        ("seed" = Option<u64>, Query, description = "Seed of the races, random if omitted"),
+        ("demonstrate" = Option<bool>, Query, description = "Replay an example race against a copy of the chain")
+    ),
+    responses(
+        (status = 200, description = "Simulation completed", body = AttackReport),
+        (status = 400, description = "Invalid parameters", body = ErrorResponse)
+    )
+)]
+pub async fn simulate_attack(blockchain: BlockchainData, query: web::Query<AttackQuery>) -> impl Responder {
+    let parameters = AttackParameters {
+        attacker_share: query.attacker_share,
+        confirmations: query.confirmations.unwrap_or(6),
+        max_deficit: query.max_deficit.unwrap_or(20),
+        trials: query.trials.unwrap_or(DEFAULT_ATTACK_TRIALS),
+        seed: query.seed.unwrap_or_else(rand::random),
+    };
+
+    let invalid = if !(parameters.attacker_share > 0.0 && parameters.attacker_share < 1.0) {
+        Some("attacker_share must be above 0 and below 1".to_string())
+    } else if parameters.confirmations == 0 || parameters.confirmations > MAX_ATTACK_CONFIRMATIONS {
+        Some(format!("confirmations must be between 1 and {}", MAX_ATTACK_CONFIRMATIONS))
+    } else if parameters.max_deficit == 0 {
+        Some("max_deficit must be at least one block".to_string())
+    } else if parameters.trials == 0 || parameters.trials > MAX_TRIALS {
+        Some(format!("trials must be between 1 and {}", MAX_TRIALS))
+    } else {
+        None
+    };
+    if let Some(error) = invalid {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(ErrorCode::InvalidRequest, error));
+    }
+
+    let demonstrate = query.demonstrate.unwrap_or(false);
+    let report = web::block(move || {
+        let mut report = attack::simulate(&parameters);
+        if let (true, Some(race)) = (demonstrate, report.example_race) {
+            report.demonstration = Some(attack::demonstrate(&blockchain, race)?);
+        }
+        Ok::<_, BlockchainError>(report)
+    })
+    .await;
+
+    match report {
+        Ok(Ok(report)) => HttpResponse::Ok().json(report),
+        Ok(Err(err)) => HttpResponse::InternalServerError().json(ErrorResponse::new(
+            ErrorCode::Internal,
+            format!("Demonstration failed: {}", err),
+        )),
+        Err(err) => HttpResponse::InternalServerError().json(ErrorResponse::new(
+            ErrorCode::Internal,
+            format!("Simulation failed: {}", err),
+        )),
+    }
+}
+
+/// Default number of transactions per page of a transaction query
+const DEFAULT_PAGE_SIZE: usize = 50;
+
+/// Maximum number of transactions per page of a transaction query
+const MAX_PAGE_SIZE: usize = 500;
+
+/// Query parameters for the transaction query endpoint
+#[derive(Serialize, Deserialize)]
+pub struct TransactionQuery {
+    /// Only transactions sent by this address
+    pub from: Option<String>,
+
+    /// Only transactions received by this address
+    pub to: Option<String>,
+
+    /// Only transactions of at least this amount
+    pub min_amount: Option<f64>,
+
+    /// Only transactions confirmed at or after this moment (RFC 3339)
+    pub after: Option<DateTime<Utc>>,
+
+    /// Only transactions confirmed before this moment (RFC 3339)
+    pub before: Option<DateTime<Utc>>,
+
+    /// The page to return, starting at 1
+    pub page: Option<usize>,
+
+    /// The maximum number of transactions per page
+    pub page_size: Option<usize>,
+
+    /// Hash or height of the block to read the state after, the tip if omitted
+    pub at_block: Option<String>,
+}
+
+/// Query confirmed transactions
+///
+/// Finds confirmed transactions by sender, recipient, minimum amount and confirmation time,
+/// newest first. All filters are optional and combined.
+#[utoipa::path(
+    get,
+    path = "/api/v1/transactions",
+    params(
+        ("from" = Option<String>, Query, description = "Only transactions sent by this address"),
+        ("to" = Option<String>, Query, description = "Only transactions received by this address"),
+        ("min_amount" = Option<f64>, Query, description = "Only transactions of at least this amount"),
+        ("after" = Option<String>, Query, description = "Only transactions confirmed at or after this moment (RFC 3339)"),
+        ("before" = Option<String>, Query, description = "Only transactions confirmed before this moment (RFC 3339)"),
+        ("page" = Option<usize>, Query, description = "The page to return, starting at 1"),
+        ("page_size" = Option<usize>, Query, description = "Transactions per page, defaults to 50, at most 500"),
+        ("at_block" = Option<String>, Query, description = "Hash or height of the block to read the state after, the tip if omitted")
+    ),
+    responses(
+        (status = 200, description = "Transactions retrieved successfully", body = TransactionPage),
+        (status = 400, description = "Invalid query", body = ErrorResponse),
+        (status = 404, description = "No block of the chain matches at_block", body = ErrorResponse)
+    )
+)]
+pub async fn query_transactions(
+    blockchain: BlockchainData,
+    query: web::Query<TransactionQuery>,
+) -> impl Responder {
+    let query = query.into_inner();
+    let page = query.page.unwrap_or(1);
+    let page_size = query.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+
+    if page == 0 {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(ErrorCode::InvalidRequest, "Pages start at 1"));
+    }
+
+    if page_size == 0 || page_size > MAX_PAGE_SIZE {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidRequest,
+            format!("Page size must be between 1 and {}", MAX_PAGE_SIZE),
+        ));
+    }
+
+    if query.min_amount.is_some_and(|amount| !amount.is_finite()) {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidRequest,
+            "Minimum amount must be a number",
+        ));
+    }
+
+    let at_height = match height_at(&blockchain, query.at_block.as_deref()) {
+        Ok(height) => height,
+        Err(err) => return at_block_error_response(err),
+    };
+
+    let filter = TransactionFilter {
+        from: query.from.map(Address),
+        to: query.to.map(Address),
+        min_amount: query.min_amount,
+        after: query.after,
+        before: query.before,
+        at_height,
+    };
+
+    HttpResponse::Ok().json(blockchain.query_transactions(&filter, page, page_size))
+}
+
+/// Request for the name registration endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct NameRegistrationRequest {
+    /// The name to register or renew
+    pub name: String,
+
+    /// The address the name will point to, which pays for it
+    pub owner: String,
+
+    /// The transaction fee, on top of the registration fee
+    pub fee: f64,
+
+    /// The owner's private key (for signing)
+    pub private_key: String,
+}
+
+/// Get a registered name
+///
+/// Returns the address a name points to and until when it is registered
+#[utoipa::path(
+    get,
+    path = "/api/v1/names/{name}",
+    params(
+        ("name" = String, Path, description = "The name"),
+        ("at_block" = Option<String>, Query, description = "Hash or height of the block to read the state after, the tip if omitted")
+    ),
+    responses(
+        (status = 200, description = "Name retrieved successfully", body = NameRecord),
+        (status = 400, description = "Invalid name or at_block", body = ErrorResponse),
+        (status = 404, description = "The name is not registered, or no block of the chain matches at_block", body = ErrorResponse)
+    )
+)]
+pub async fn get_name(
+    blockchain: BlockchainData,
+    name: web::Path<String>,
+    query: web::Query<AtBlockQuery>,
+) -> impl Responder {
+    if let Err(err) = names::validate_name(&name) {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(ErrorCode::InvalidName, err.to_string()));
+    }
+
+    let at = match parse_at_block(query.at_block.as_deref()) {
+        Ok(at) => at,
+        Err(err) => return at_block_error_response(err),
+    };
+
+    match blockchain.get_name(&name, at.as_ref()) {
+        Ok(Some(record)) => HttpResponse::Ok().json(record),
+        Ok(None) => HttpResponse::NotFound().json(ErrorResponse::new(
+            ErrorCode::NotFound,
+            format!("Name {} is not registered", name),
+        )),
+        Err(err) => at_block_error_response(err),
+    }
+}
+
+/// Register or renew a name
+///
+/// Submits a transaction paying the registration fee that points the name to the owner's
+/// address for a number of blocks. The owner renews it the same way.
+#[utoipa::path(
+    post,
+    path = "/api/v1/names",
+    request_body = NameRegistrationRequest,
+    responses(
+        (status = 201, description = "Registration submitted successfully", body = TransactionResponse),
+        (status = 400, description = "Invalid name or registration data", body = ErrorResponse),
+        (status = 409, description = "The name is registered or being registered by another address", body = ErrorResponse)
+    )
+)]
+pub async fn register_name(
+    blockchain: BlockchainData,
+    registration: web::Json<NameRegistrationRequest>,
+) -> impl Responder {
+    let wallet = match hex::decode(&registration.private_key)
+        .map_err(|_| "Invalid private key format. Must be a hex string.".to_string())
+        .and_then(|bytes| Wallet::from_secret_key(&bytes).map_err(|err| format!("Invalid private key: {}", err)))
+    {
+        Ok(wallet) => wallet,
+        Err(error) => return HttpResponse::BadRequest().json(ErrorResponse::new(ErrorCode::InvalidKey, error)),
+    };
+
+    if wallet.address().0 != registration.owner {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidKey,
+            "Private key does not match owner address",
+        ));
+    }
+
+    let mut transaction = Transaction::new(
+        wallet.address().clone(),
+        Address(names::NAME_REGISTRY_ADDRESS.to_string()),
+        names::NAME_FEE,
+        registration.fee,
+        blockchain.get_next_nonce(wallet.address()),
+    )
+    .with_name(&registration.name);
+
+    if let Err(err) = transaction.sign(&wallet) {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidTransaction,
+            format!("Failed to sign transaction: {}", err),
+        ));
+    }
+
+    match blockchain.add_transaction(transaction) {
+        Ok(block_index) => HttpResponse::Created().json(TransactionResponse {
+            message: format!("Registration of {} will be added to Block", registration.name),
+            block_index,
+        }),
+        Err(BlockchainError::NameError(err @ (NameError::Taken { .. } | NameError::Pending(_)))) => {
+            HttpResponse::Conflict().json(ErrorResponse::new(ErrorCode::NameTaken, err.to_string()))
+        }
+        Err(err) => blockchain_error_response("Failed to register name", err),
+    }
+}
+
+/// Request for the anchoring endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AnchorRequest {
+    /// SHA-256 hash of the document, 64 hex digits
+    pub hash: String,
+
+    /// The address that pays for the anchor
+    pub sender: String,
+
+    /// The transaction fee, on top of the anchor amount
+    pub fee: f64,
+
+    /// The sender's private key (for signing)
+    pub private_key: String,
+}
+
+/// Anchor a document hash
+///
+/// Submits a transaction carrying the hash, so once mined the chain proves the document
+/// existed by the time of the block. Only the hash is stored, never the document.
+#[utoipa::path(
+    post,
+    path = "/api/v1/anchor",
+    request_body = AnchorRequest,
+    responses(
+        (status = 201, description = "Anchor submitted successfully", body = TransactionResponse),
+        (status = 400, description = "Invalid hash or anchor data", body = ErrorResponse)
+    )
+)]
+pub async fn anchor_document(
+    blockchain: BlockchainData,
+    anchor: web::Json<AnchorRequest>,
+) -> impl Responder {
+    let hash = match anchors::validate_hash(&anchor.hash) {
+        Ok(hash) => hash,
+        Err(err) => return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidAnchor,
+            err.to_string(),
+        )),
+    };
+
+    let wallet = match hex::decode(&anchor.private_key)
+        .map_err(|_| "Invalid private key format. Must be a hex string.".to_string())
+        .and_then(|bytes| Wallet::from_secret_key(&bytes).map_err(|err| format!("Invalid private key: {}", err)))
+    {
+        Ok(wallet) => wallet,
+        Err(error) => return HttpResponse::BadRequest().json(ErrorResponse::new(ErrorCode::InvalidKey, error)),
+    };
+
+    if wallet.address().0 != anchor.sender {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidKey,
+            "Private key does not match sender address",
+        ));
+    }
+
+    let mut transaction = Transaction::new(
+        wallet.address().clone(),
+        Address(anchors::ANCHOR_ADDRESS.to_string()),
+        anchors::ANCHOR_AMOUNT,
+        anchor.fee,
+        blockchain.get_next_nonce(wallet.address()),
+    )
+    .with_data(&hash);
+
+    if let Err(err) = transaction.sign(&wallet) {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidTransaction,
+            format!("Failed to sign transaction: {}", err),
+        ));
+    }
+
+    match blockchain.add_transaction(transaction) {
+        Ok(block_index) => HttpResponse::Created().json(TransactionResponse {
+            message: format!("Anchor of {} will be added to Block", hash),
+            block_index,
+        }),
+        Err(err) => blockchain_error_response("Failed to anchor document", err),
+    }
+}
+
+/// Get the proof of an anchored hash
+///
+/// Returns the block that first anchored the hash, its timestamp and a merkle proof that the
+/// anchoring transaction is part of the block
+#[utoipa::path(
+    get,
+    path = "/api/v1/anchor/{hash}",
+    params(
+        ("hash" = String, Path, description = "SHA-256 hash of the document, 64 hex digits")
+    ),
+    responses(
+        (status = 200, description = "Anchor proof retrieved successfully", body = AnchorProof),
+        (status = 202, description = "The anchor is still pending"),
+        (status = 400, description = "Invalid hash", body = ErrorResponse),
+        (status = 404, description = "The hash was never anchored", body = ErrorResponse)
+    )
+)]
+pub async fn get_anchor(
+    blockchain: BlockchainData,
+    hash: web::Path<String>,
+) -> impl Responder {
+    let hash = match anchors::validate_hash(&hash) {
+        Ok(hash) => hash,
+        Err(err) => return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidAnchor,
+            err.to_string(),
+        )),
+    };
+
+    if let Some(proof) = blockchain.get_anchor(&hash) {
+        return HttpResponse::Ok().json(proof);
+    }
+
+    match blockchain
+        .get_pending_transactions()
+        .into_iter()
+        .find(|transaction| transaction.data.as_ref() == Some(&hash))
+    {
+        Some(transaction) => HttpResponse::Accepted().json(serde_json::json!({
+            "message": format!("Anchor of {} is waiting to be mined", hash),
+            "transaction_id": transaction.id
+        })),
+        None => HttpResponse::NotFound().json(ErrorResponse::new(
+            ErrorCode::NotFound,
+            format!("Hash {} was never anchored", hash),
+        )),
+    }
+}
+
+/// Request for the lock endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct LockRequest {
+    /// The address that locks the amount
+    pub sender: String,
+
+    /// The address that may spend the amount once the condition is met
+    pub recipient: String,
+
+    /// The amount to lock
+    pub amount: f64,
+
+    /// The transaction fee
+    pub fee: f64,
+
+    /// What the recipient must meet to spend the amount
+    pub condition: Condition,
+
+    /// The sender's private key (for signing)
+    pub private_key: String,
+}
+
+/// Response of the lock endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct LockResponse {
+    /// ID of the lock, the ID of the transaction that makes it
+    pub lock: String,
+
+    /// The index of the block that will include the lock
+    pub block_index: u64,
+}
+
+/// Lock an amount under a condition
+///
+/// Submits a transaction paying the amount to the script address. Once mined, only the
+/// recipient can spend it, and only with a witness that meets the condition.
+#[utoipa::path(
+    post,
+    path = "/api/v1/locks",
+    request_body = LockRequest,
+    responses(
+        (status = 201, description = "Lock submitted successfully", body = LockResponse),
+        (status = 400, description = "Invalid condition or lock data", body = ErrorResponse)
+    )
+)]
+pub async fn create_lock(
+    blockchain: BlockchainData,
+    request: web::Json<LockRequest>,
+) -> impl Responder {
+    let request = request.into_inner();
+    if let Err(err) = request.condition.validate() {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(ErrorCode::InvalidScript, err.to_string()));
+    }
+
+    let wallet = match hex::decode(&request.private_key)
+        .map_err(|_| "Invalid private key format. Must be a hex string.".to_string())
+        .and_then(|bytes| Wallet::from_secret_key(&bytes).map_err(|err| format!("Invalid private key: {}", err)))
+    {
+        Ok(wallet) => wallet,
+        Err(error) => return HttpResponse::BadRequest().json(ErrorResponse::new(ErrorCode::InvalidKey, error)),
+    };
+
+    if wallet.address().0 != request.sender {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidKey,
+            "Private key does not match sender address",
+        ));
+    }
+
+    let mut transaction = Transaction::new(
+        wallet.address().clone(),
+        Address(scripts::SCRIPT_ADDRESS.to_string()),
+        request.amount,
+        request.fee,
+        blockchain.get_next_nonce(wallet.address()),
+    )
+    .with_script(ScriptAction::Lock {
+        recipient: request.recipient,
+        condition: request.condition,
+    });
+
+    if let Err(err) = transaction.sign(&wallet) {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidTransaction,
+            format!("Failed to sign transaction: {}", err),
+        ));
+    }
+
+    let lock = transaction.id.clone();
+    match blockchain.add_transaction(transaction) {
+        Ok(block_index) => HttpResponse::Created().json(LockResponse { lock, block_index }),
+        Err(err) => blockchain_error_response("Failed to lock amount", err),
+    }
+}
+
+/// Request for the lock spending endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SpendLockRequest {
+    /// The address the locked amount is sent to
+    pub recipient: String,
+
+    /// The transaction fee, paid by the lock's recipient
+    pub fee: f64,
+
+    /// The private key of the lock's recipient (for signing)
+    pub private_key: String,
+
+    /// Signatures of the lock ID made elsewhere, see the README
+    #[serde(default)]
+    pub signatures: Vec<WitnessSignature>,
+
+    /// Private keys the node signs the lock ID with, for keys the condition lists
+    #[serde(default)]
+    pub cosigner_keys: Vec<String>,
+
+    /// Preimage of a hashlock
+    #[serde(default)]
+    pub preimage: Option<String>,
+}
+
+/// Spend a lock
+///
+/// Submits a transaction of the lock's recipient that sends the locked amount on, with a
+/// witness of the given signatures and preimage. The recipient only pays the fee.
+#[utoipa::path(
+    post,
+    path = "/api/v1/locks/{id}/spend",
+    params(
+        ("id" = String, Path, description = "ID of the lock")
+    ),
+    request_body = SpendLockRequest,
+    responses(
+        (status = 201, description = "Spend submitted successfully", body = TransactionResponse),
+        (status = 400, description = "Invalid key or the witness doesn't meet the condition", body = ErrorResponse),
+        (status = 404, description = "The lock is not open", body = ErrorResponse),
+        (status = 409, description = "The lock is already being spent", body = ErrorResponse)
+    )
+)]
+pub async fn spend_lock(
+    blockchain: BlockchainData,
+    id: web::Path<String>,
+    request: web::Json<SpendLockRequest>,
+) -> impl Responder {
+    let request = request.into_inner();
+    let parse_wallet = |key: &str| {
+        hex::decode(key)
+            .map_err(|_| "Invalid private key format. Must be a hex string.".to_string())
+            .and_then(|bytes| Wallet::from_secret_key(&bytes).map_err(|err| format!("Invalid private key: {}", err)))
+    };
+
+    let wallet = match parse_wallet(&request.private_key) {
+        Ok(wallet) => wallet,
+        Err(error) => return HttpResponse::BadRequest().json(ErrorResponse::new(ErrorCode::InvalidKey, error)),
+    };
+
+    let lock = match blockchain.get_lock(&id) {
+        Some(lock) => lock,
+        None => return HttpResponse::NotFound().json(ErrorResponse::new(
+            ErrorCode::NotFound,
+            format!("Lock {} is not open", id),
+        )),
+    };
+
+    if wallet.address() != &lock.recipient {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidKey,
+            "Private key does not match the recipient of the lock",
+        ));
+    }
+
+    let recipient = match blockchain.resolve_recipient(&request.recipient) {
+        Ok(recipient) => recipient,
+        Err(err) => return blockchain_error_response("Failed to resolve recipient", err),
+    };
+
+    let mut witness = Witness {
+        signatures: request.signatures,
+        preimage: request.preimage,
+    };
+    for key in &request.cosigner_keys {
+        let signature = parse_wallet(key)
+            .and_then(|cosigner| scripts::sign_witness(&cosigner, &lock.id).map_err(|err| err.to_string()));
+        match signature {
+            Ok(signature) => witness.signatures.push(signature),
+            Err(error) => return HttpResponse::BadRequest().json(ErrorResponse::new(ErrorCode::InvalidKey, error)),
+        }
+    }
+
+    let mut transaction = Transaction::new(
+        wallet.address().clone(),
+        recipient,
+        lock.amount,
+        request.fee,
+        blockchain.get_next_nonce(wallet.address()),
+    )
+    .with_script(ScriptAction::Unlock {
+        lock: lock.id.clone(),
+        witness,
+    });
+
+    if let Err(err) = transaction.sign(&wallet) {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidTransaction,
+            format!("Failed to sign transaction: {}", err),
+        ));
+    }
+
+    match blockchain.add_transaction(transaction) {
+        Ok(block_index) => HttpResponse::Created().json(TransactionResponse {
+            message: format!("Spend of lock {} will be added to Block", lock.id),
+            block_index,
+        }),
+        Err(err) => blockchain_error_response("Failed to spend lock", err),
+    }
+}
+
+/// Get an open lock
+///
+/// Returns the amount, recipient and condition of a lock that is not spent yet
+#[utoipa::path(
+    get,
+    path = "/api/v1/locks/{id}",
+    params(
+        ("id" = String, Path, description = "ID of the lock")
+    ),
+    responses(
+        (status = 200, description = "Lock retrieved successfully", body = ScriptLock),
+        (status = 404, description = "The lock was never made or is spent", body = ErrorResponse)
+    )
+)]
+pub async fn get_lock(
+    blockchain: BlockchainData,
+    id: web::Path<String>,
+) -> impl Responder {
+    match blockchain.get_lock(&id) {
+        Some(lock) => HttpResponse::Ok().json(lock),
+        None => HttpResponse::NotFound().json(ErrorResponse::new(
+            ErrorCode::NotFound,
+            format!("Lock {} is not open", id),
+        )),
+    }
+}
+
+/// Get the open locks of an address
+///
+/// Returns the locks the address may spend once their conditions are met, oldest first
+#[utoipa::path(
+    get,
+    path = "/api/v1/address/{address}/locks",
+    params(
+        ("address" = String, Path, description = "The address")
+    ),
+    responses(
+        (status = 200, description = "Locks retrieved successfully", body = [ScriptLock])
+    )
+)]
+pub async fn get_address_locks(
+    blockchain: BlockchainData,
+    address: web::Path<String>,
+) -> impl Responder {
+    HttpResponse::Ok().json(blockchain.get_locks_for(&Address(address.into_inner())))
+}
+
+/// Request for the session key authorization endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct SessionKeyRequest {
+    /// The account the key signs for
+    pub owner: String,
+
+    /// Public key of the session key
+    pub key: String,
+
+    /// Most the key may spend in all, amounts and fees together
+    pub max_total: f64,
+
+    /// Most one transaction signed by the key may spend
+    pub max_per_transaction: f64,
+
+    /// Last height a transaction signed by the key may be mined at
+    pub expires_at: u64,
+
+    /// The transaction fee
+    pub fee: f64,
+
+    /// The owner's private key (for signing)
+    pub private_key: String,
+}
+
+/// Request for the session key revocation endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct RevokeSessionKeyRequest {
+    /// The account the key signs for
+    pub owner: String,
+
+    /// The transaction fee
+    pub fee: f64,
+
+    /// The owner's private key (for signing)
+    pub private_key: String,
+}
+
+/// Signs and submits a session key authorization or revocation of an owner
+fn submit_session_action(
+    blockchain: &Blockchain,
+    owner: &str,
+    private_key: &str,
+    fee: f64,
+    action: SessionAction,
+) -> HttpResponse {
+    let wallet = match hex::decode(private_key)
+        .map_err(|_| "Invalid private key format. Must be a hex string.".to_string())
+        .and_then(|bytes| Wallet::from_secret_key(&bytes).map_err(|err| format!("Invalid private key: {}", err)))
+    {
+        Ok(wallet) => wallet,
+        Err(error) => return HttpResponse::BadRequest().json(ErrorResponse::new(ErrorCode::InvalidKey, error)),
+    };
+
+    if wallet.address().0 != owner {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidKey,
+            "Private key does not match owner address",
+        ));
+    }
+
+    let mut transaction = Transaction::new(
+        wallet.address().clone(),
+        Address(sessions::SESSIONS_ADDRESS.to_string()),
+        sessions::SESSION_CHANGE_AMOUNT,
+        fee,
+        blockchain.get_next_nonce(wallet.address()),
+    )
+    .with_session(action);
+
+    if let Err(err) = transaction.sign(&wallet) {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidTransaction,
+            format!("Failed to sign transaction: {}", err),
+        ));
+    }
+
+    match blockchain.add_transaction(transaction) {
+        Ok(block_index) => HttpResponse::Created().json(TransactionResponse {
+            message: "Session key change will be added to Block".to_string(),
+            block_index,
+        }),
+        Err(err) => blockchain_error_response("Failed to change session key", err),
+    }
+}
+
+/// Authorize a session key
+///
+/// Submits a transaction of the owner to the sessions address authorizing the key to sign
+/// its transfers within the limits until the expiry height. Once mined, transactions from
+/// the owner signed by the key are accepted, see `session` of the transaction endpoint.
+/// Authorizing a key again replaces its limits and starts its spending over.
+#[utoipa::path(
+    post,
+    path = "/api/v1/sessions",
+    request_body = SessionKeyRequest,
+    responses(
+        (status = 201, description = "Authorization submitted successfully", body = TransactionResponse),
+        (status = 400, description = "Invalid key, limits or authorization", body = ErrorResponse)
+    )
+)]
+pub async fn authorize_session_key(
+    blockchain: BlockchainData,
+    request: web::Json<SessionKeyRequest>,
+) -> impl Responder {
+    let request = request.into_inner();
+    let action = SessionAction::Authorize {
+        key: request.key,
+        max_total: request.max_total,
+        max_per_transaction: request.max_per_transaction,
+        expires_at: request.expires_at,
+    };
+
+    submit_session_action(&blockchain, &request.owner, &request.private_key, request.fee, action)
+}
+
+/// Revoke a session key
+///
+/// Submits a transaction of the owner to the sessions address revoking the key. Once mined,
+/// transactions signed by the key are refused, pending ones are dropped.
+#[utoipa::path(
+    post,
+    path = "/api/v1/sessions/{key}/revoke",
+    params(
+        ("key" = String, Path, description = "Public key of the session key")
+    ),
+    request_body = RevokeSessionKeyRequest,
+    responses(
+        (status = 201, description = "Revocation submitted successfully", body = TransactionResponse),
+        (status = 400, description = "Invalid key or revocation", body = ErrorResponse)
+    )
+)]
+pub async fn revoke_session_key(
+    blockchain: BlockchainData,
+    key: web::Path<String>,
+    request: web::Json<RevokeSessionKeyRequest>,
+) -> impl Responder {
+    let request = request.into_inner();
+    let action = SessionAction::Revoke { key: key.into_inner() };
+
+    submit_session_action(&blockchain, &request.owner, &request.private_key, request.fee, action)
+}
+
+/// Get the session keys of an address
+///
+/// Returns the keys the address authorized with their limits and what they spent, oldest first
+#[utoipa::path(
+    get,
+    path = "/api/v1/address/{address}/sessions",
+    params(
+        ("address" = String, Path, description = "The address")
+    ),
+    responses(
+        (status = 200, description = "Session keys retrieved successfully", body = [SessionKey])
+    )
+)]
+pub async fn get_address_session_keys(
+    blockchain: BlockchainData,
+    address: web::Path<String>,
+) -> impl Responder {
+    HttpResponse::Ok().json(blockchain.get_session_keys(&Address(address.into_inner())))
+}
+
+/// Request for the inheritance designation endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct InheritanceRequest {
+    /// The account whose funds the beneficiary may claim
+    pub owner: String,
+
+    /// The address that may claim them
+    pub beneficiary: String,
+
+    /// Blocks without a transaction of the owner before its funds may be claimed
+    pub inactivity_blocks: u64,
+
+    /// The transaction fee
+    pub fee: f64,
+
+    /// The owner's private key (for signing)
+    pub private_key: String,
+}
+
+/// Request for the inheritance cancellation endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct CancelInheritanceRequest {
+    /// The account whose designation is cancelled
+    pub owner: String,
+
+    /// The transaction fee
+    pub fee: f64,
+
+    /// The owner's private key (for signing)
+    pub private_key: String,
+}
+
+/// Request for the inheritance claim endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ClaimInheritanceRequest {
+    /// The beneficiary the owner designated
+    pub beneficiary: String,
+
+    /// Where the claimed amount goes
+    pub recipient: String,
+
+    /// Amount to claim, the owner's whole balance if not given
+    #[serde(default)]
+    pub amount: Option<f64>,
+
+    /// The transaction fee, paid by the beneficiary
+    pub fee: f64,
+
+    /// The beneficiary's private key (for signing)
+    pub private_key: String,
+}
+
+/// Signs and submits an inheritance transaction of a sender
+fn submit_inheritance_action(
+    blockchain: &Blockchain,
+    sender: &str,
+    private_key: &str,
+    recipient: Address,
+    amount: f64,
+    fee: f64,
+    action: InheritanceAction,
+) -> HttpResponse {
+    let wallet = match hex::decode(private_key)
+        .map_err(|_| "Invalid private key format. Must be a hex string.".to_string())
+        .and_then(|bytes| Wallet::from_secret_key(&bytes).map_err(|err| format!("Invalid private key: {}", err)))
+    {
+        Ok(wallet) => wallet,
+        Err(error) => return HttpResponse::BadRequest().json(ErrorResponse::new(ErrorCode::InvalidKey, error)),
+    };
+
+    if wallet.address().0 != sender {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidKey,
+            "Private key does not match sender address",
+        ));
+    }
+
+    let mut transaction = Transaction::new(
+        wallet.address().clone(),
+        recipient,
+        amount,
+        fee,
+        blockchain.get_next_nonce(wallet.address()),
+    )
+    .with_inheritance(action);
+
+    if let Err(err) = transaction.sign(&wallet) {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidTransaction,
+            format!("Failed to sign transaction: {}", err),
+        ));
+    }
+
+    match blockchain.add_transaction(transaction) {
+        Ok(block_index) => HttpResponse::Created().json(TransactionResponse {
+            message: "Inheritance transaction will be added to Block".to_string(),
+            block_index,
+        }),
+        Err(err) => blockchain_error_response("Failed to submit inheritance transaction", err),
+    }
+}
+
+/// Designate a beneficiary
+///
+/// Submits a transaction of the owner to the inheritance address designating the beneficiary.
+/// Once mined, the beneficiary may claim the owner's funds after the owner sent no transaction
+/// for the inactivity period. Any transaction of the owner restarts the period, designating
+/// again replaces the beneficiary and the period.
+#[utoipa::path(
+    post,
+    path = "/api/v1/inheritance",
+    request_body = InheritanceRequest,
+    responses(
+        (status = 201, description = "Designation submitted successfully", body = TransactionResponse),
+        (status = 400, description = "Invalid key or designation", body = ErrorResponse)
+    )
+)]
+pub async fn designate_beneficiary(
+    blockchain: BlockchainData,
+    request: web::Json<InheritanceRequest>,
+) -> impl Responder {
+    let request = request.into_inner();
+    let action = InheritanceAction::Designate {
+        beneficiary: request.beneficiary,
+        inactivity_blocks: request.inactivity_blocks,
+    };
+
+    submit_inheritance_action(
+        &blockchain,
+        &request.owner,
+        &request.private_key,
+        Address(inheritance::INHERITANCE_ADDRESS.to_string()),
+        inheritance::INHERITANCE_CHANGE_AMOUNT,
+        request.fee,
+        action,
+    )
+}
+
+/// Cancel a designation
+///
+/// Submits a transaction of the owner to the inheritance address cancelling its designation.
+/// Once mined, the beneficiary can no longer claim, pending claims are dropped.
+#[utoipa::path(
+    post,
+    path = "/api/v1/inheritance/cancel",
+    request_body = CancelInheritanceRequest,
+    responses(
+        (status = 201, description = "Cancellation submitted successfully", body = TransactionResponse),
+        (status = 400, description = "Invalid key or cancellation", body = ErrorResponse)
+    )
+)]
+pub async fn cancel_inheritance(
+    blockchain: BlockchainData,
+    request: web::Json<CancelInheritanceRequest>,
+) -> impl Responder {
+    let request = request.into_inner();
+
+    submit_inheritance_action(
+        &blockchain,
+        &request.owner,
+        &request.private_key,
+        Address(inheritance::INHERITANCE_ADDRESS.to_string()),
+        inheritance::INHERITANCE_CHANGE_AMOUNT,
+        request.fee,
+        InheritanceAction::Cancel,
+    )
+}
+
+/// Claim the funds of an inactive owner
+///
+/// Submits a transaction of the beneficiary that moves the amount from the owner to the
+/// recipient. Refused until the owner sent no transaction for its inactivity period.
+#[utoipa::path(
+    post,
+    path = "/api/v1/inheritance/{owner}/claim",
+    params(
+        ("owner" = String, Path, description = "The inactive account")
+    ),
+    request_body = ClaimInheritanceRequest,
+    responses(
+        (status = 201, description = "Claim submitted successfully", body = TransactionResponse),
+        (status = 400, description = "Invalid key or claim, or the owner is still active", body = ErrorResponse),
+        (status = 404, description = "The owner designated no beneficiary", body = ErrorResponse),
+        (status = 409, description = "Another claim on the owner is pending", body = ErrorResponse)
+    )
+)]
+pub async fn claim_inheritance(
+    blockchain: BlockchainData,
+    owner: web::Path<String>,
+    request: web::Json<ClaimInheritanceRequest>,
+) -> impl Responder {
+    let owner = Address(owner.into_inner());
+    let request = request.into_inner();
+    let amount = request
+        .amount
+        .unwrap_or_else(|| blockchain.get_account_state().get_account(&owner).balance);
+
+    submit_inheritance_action(
+        &blockchain,
+        &request.beneficiary,
+        &request.private_key,
+        Address(request.recipient),
+        amount,
+        request.fee,
+        InheritanceAction::Claim { owner: owner.0 },
+    )
+}
+
+/// Get the inheritance switch of an address
+///
+/// Returns the beneficiary the address designated, its inactivity period, when the address
+/// was last active and the first height its funds can be claimed at
+#[utoipa::path(
+    get,
+    path = "/api/v1/inheritance/{owner}",
+    params(
+        ("owner" = String, Path, description = "The address")
+    ),
+    responses(
+        (status = 200, description = "Switch retrieved successfully", body = InheritanceSwitch),
+        (status = 404, description = "The address designated no beneficiary", body = ErrorResponse)
+    )
+)]
+pub async fn get_inheritance(
+    blockchain: BlockchainData,
+    owner: web::Path<String>,
+) -> impl Responder {
+    let owner = Address(owner.into_inner());
+    match blockchain.get_inheritance(&owner) {
+        Some(switch) => HttpResponse::Ok().json(switch),
+        None => blockchain_error_response(
+            "Failed to get inheritance",
+            BlockchainError::InheritanceError(InheritanceError::NotDesignated(owner.0)),
+        ),
+    }
+}
+
+/// Get the inheritance switches naming an address as beneficiary
+///
+/// Returns the switches of the owners whose funds the address may claim, oldest first
+#[utoipa::path(
+    get,
+    path = "/api/v1/address/{address}/inheritances",
+    params(
+        ("address" = String, Path, description = "The beneficiary")
+    ),
+    responses(
+        (status = 200, description = "Switches retrieved successfully", body = [InheritanceSwitch])
+    )
+)]
+pub async fn get_address_inheritances(
+    blockchain: BlockchainData,
+    address: web::Path<String>,
+) -> impl Responder {
+    HttpResponse::Ok().json(blockchain.get_inheritances_for(&Address(address.into_inner())))
+}
+
+/// Request for the permission change endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct PermissionChangeRequest {
+    /// The address to approve or revoke
+    pub address: String,
+
+    /// Whether to approve the address or revoke it
+    pub allowed: bool,
+
+    /// The governor sending the change
+    pub governor: String,
+
+    /// The transaction fee
+    pub fee: f64,
+
+    /// The governor's private key (for signing)
+    pub private_key: String,
+}
+
+/// Get the permissions
+///
+/// Returns whether the chain is permissioned and which addresses may send transactions and mine
+#[utoipa::path(
+    get,
+    path = "/api/v1/permissions",
+    responses(
+        (status = 200, description = "Permissions retrieved successfully", body = PermissionStatus)
+    )
+)]
+pub async fn get_permissions(blockchain: BlockchainData) -> impl Responder {
+    HttpResponse::Ok().json(blockchain.get_permissions())
+}
+
+/// Approve or revoke an address
+///
+/// Submits a permission change signed by a governor. The change applies once its block is mined.
+#[utoipa::path(
+    post,
+    path = "/api/v1/permissions",
+    request_body = PermissionChangeRequest,
+    responses(
+        (status = 201, description = "Permission change submitted successfully", body = TransactionResponse),
+        (status = 400, description = "Invalid permission change", body = ErrorResponse),
+        (status = 403, description = "The sender is not a governor", body = ErrorResponse)
+    )
+)]
+pub async fn change_permission(
+    blockchain: BlockchainData,
+    change: web::Json<PermissionChangeRequest>,
+) -> impl Responder {
+    let wallet = match hex::decode(&change.private_key)
+        .map_err(|_| "Invalid private key format. Must be a hex string.".to_string())
+        .and_then(|bytes| Wallet::from_secret_key(&bytes).map_err(|err| format!("Invalid private key: {}", err)))
+    {
+        Ok(wallet) => wallet,
+        Err(error) => return HttpResponse::BadRequest().json(ErrorResponse::new(ErrorCode::InvalidKey, error)),
+    };
+
+    if wallet.address().0 != change.governor {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidKey,
+            "Private key does not match governor address",
+        ));
+    }
+
+    let mut transaction = Transaction::new(
+        wallet.address().clone(),
+        Address(permissions::PERMISSIONS_ADDRESS.to_string()),
+        permissions::PERMISSION_CHANGE_AMOUNT,
+        change.fee,
+        blockchain.get_next_nonce(wallet.address()),
+    )
+    .with_permission(PermissionChange {
+        address: change.address.clone(),
+        allowed: change.allowed,
+    });
+
+    if let Err(err) = transaction.sign(&wallet) {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidTransaction,
+            format!("Failed to sign transaction: {}", err),
+        ));
+    }
+
+    match blockchain.add_transaction(transaction) {
+        Ok(block_index) => HttpResponse::Created().json(TransactionResponse {
+            message: format!(
+                "{} of {} will be added to Block",
+                if change.allowed { "Approval" } else { "Revocation" },
+                change.address
+            ),
+            block_index,
+        }),
+        Err(err) => blockchain_error_response("Failed to change permission", err),
+    }
+}
+
+/// Query parameters for listing freeze orders
+#[derive(Serialize, Deserialize)]
+pub struct FreezeListQuery {
+    /// Only list the orders about this address
+    pub address: Option<String>,
+}
+
+/// Request for the freeze endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct FreezeRequest {
+    /// The address to freeze or unfreeze
+    pub address: String,
+
+    /// Whether to freeze the address or unfreeze it
+    pub frozen: bool,
+
+    /// Why the account is frozen or unfrozen, recorded on the chain
+    pub reason: String,
+
+    /// The governor sending the order
+    pub governor: String,
+
+    /// The transaction fee
+    pub fee: f64,
+
+    /// The governor's private key (for signing)
+    pub private_key: String,
+}
+
+/// List the freeze orders
+///
+/// Returns every freeze and unfreeze that took effect, oldest first
+#[utoipa::path(
+    get,
+    path = "/api/v1/permissions/freezes",
+    params(
+        ("address" = Option<String>, Query, description = "Only list the orders about this address")
+    ),
+    responses(
+        (status = 200, description = "Freeze orders retrieved successfully", body = [FreezeRecord])
+    )
+)]
+pub async fn list_freezes(blockchain: BlockchainData, query: web::Query<FreezeListQuery>) -> impl Responder {
+    HttpResponse::Ok().json(blockchain.get_freezes(query.address.as_deref()))
+}
+
+/// Freeze or unfreeze an account
+///
+/// Submits a freeze order signed by a governor. Once its block is mined the
+/// account can't send transactions until it is unfrozen.
+#[utoipa::path(
+    post,
+    path = "/api/v1/permissions/freezes",
+    request_body = FreezeRequest,
+    responses(
+        (status = 201, description = "Freeze order submitted successfully", body = TransactionResponse),
+        (status = 400, description = "Invalid freeze order", body = ErrorResponse),
+        (status = 403, description = "The sender is not a governor", body = ErrorResponse)
+    )
+)]
+pub async fn freeze_account(
+    blockchain: BlockchainData,
+    order: web::Json<FreezeRequest>,
+) -> impl Responder {
+    let wallet = match hex::decode(&order.private_key)
+        .map_err(|_| "Invalid private key format. Must be a hex string.".to_string())
+        .and_then(|bytes| Wallet::from_secret_key(&bytes).map_err(|err| format!("Invalid private key: {}", err)))
+    {
+        Ok(wallet) => wallet,
+        Err(error) => return HttpResponse::BadRequest().json(ErrorResponse::new(ErrorCode::InvalidKey, error)),
+    };
+
+    if wallet.address().0 != order.governor {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidKey,
+            "Private key does not match governor address",
+        ));
+    }
+
+    let mut transaction = Transaction::new(
+        wallet.address().clone(),
+        Address(permissions::PERMISSIONS_ADDRESS.to_string()),
+        permissions::PERMISSION_CHANGE_AMOUNT,
+        order.fee,
+        blockchain.get_next_nonce(wallet.address()),
+    )
+    .with_freeze(FreezeOrder {
+        address: order.address.clone(),
+        frozen: order.frozen,
+        reason: order.reason.clone(),
+    });
+
+    if let Err(err) = transaction.sign(&wallet) {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidTransaction,
+            format!("Failed to sign transaction: {}", err),
+        ));
+    }
+
+    match blockchain.add_transaction(transaction) {
+        Ok(block_index) => HttpResponse::Created().json(TransactionResponse {
+            message: format!(
+                "{} of {} will be added to Block",
+                if order.frozen { "Freeze" } else { "Unfreeze" },
+                order.address
+            ),
+            block_index,
+        }),
+        Err(err) => blockchain_error_response("Failed to freeze account", err),
+    }
+}
+
+/// Parameters in force and how governance changed them
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ParametersResponse {
+    /// Height of the next block, the one the parameters apply to
+    pub height: u64,
+
+    /// The parameters in force
+    pub parameters: ChainParameters,
+
+    /// The parameter changes that passed, oldest first
+    pub changes: Vec<ParameterChange>,
+}
+
+/// Request for the proposal endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ProposalRequest {
+    /// The parameter to change
+    pub parameter: Parameter,
+
+    /// The value to change it to
+    pub value: f64,
+
+    /// Height of the block the votes are tallied at
+    pub deadline: u64,
+
+    /// The address making the proposal
+    pub proposer: String,
+
+    /// The transaction fee
+    pub fee: f64,
+
+    /// The proposer's private key (for signing)
+    pub private_key: String,
+}
+
+/// Request for the disbursement proposal endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct DisbursementRequest {
+    /// Address that receives the installments
+    pub recipient: String,
+
+    /// Amount of each installment
+    pub amount: f64,
+
+    /// Number of installments
+    pub installments: u32,
+
+    /// Blocks between two installments
+    pub interval: u64,
+
+    /// Height of the block the votes are tallied at, the first installment is paid right after
+    pub deadline: u64,
+
+    /// The address making the proposal
+    pub proposer: String,
+
+    /// The transaction fee
+    pub fee: f64,
+
+    /// The proposer's private key (for signing)
+    pub private_key: String,
+}
+
+/// Request for the vote endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct VoteRequest {
+    /// Whether to approve the proposal
+    pub approve: bool,
+
+    /// The address voting, weighed by its balance at the deadline
+    pub voter: String,
+
+    /// The transaction fee
+    pub fee: f64,
+
+    /// The voter's private key (for signing)
+    pub private_key: String,
+}
+
+/// Signs and submits a governance transaction
+///
+/// # Arguments
+///
+/// * `blockchain` - The blockchain
+/// * `action` - The proposal or vote
+/// * `sender` - The address expected to send it
+/// * `fee` - The transaction fee
+/// * `private_key` - The sender's private key as hex
+/// * `message` - The message of the response once submitted
+///
+/// # Returns
+///
+/// 201 with the height of the block the transaction is meant for, or the error response
+fn submit_governance(
+    blockchain: &Blockchain,
+    action: GovernanceAction,
+    sender: &str,
+    fee: f64,
+    private_key: &str,
+    message: String,
+) -> HttpResponse {
+    let wallet = match hex::decode(private_key)
+        .map_err(|_| "Invalid private key format. Must be a hex string.".to_string())
+        .and_then(|bytes| Wallet::from_secret_key(&bytes).map_err(|err| format!("Invalid private key: {}", err)))
+    {
+        Ok(wallet) => wallet,
+        Err(error) => return HttpResponse::BadRequest().json(ErrorResponse::new(ErrorCode::InvalidKey, error)),
+    };
+
+    if wallet.address().0 != sender {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidKey,
+            "Private key does not match sender address",
+        ));
+    }
+
+    let mut transaction = Transaction::new(
+        wallet.address().clone(),
+        Address(governance::GOVERNANCE_ADDRESS.to_string()),
+        governance::GOVERNANCE_AMOUNT,
+        fee,
+        blockchain.get_next_nonce(wallet.address()),
+    )
+    .with_governance(action);
+
+    if let Err(err) = transaction.sign(&wallet) {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidTransaction,
+            format!("Failed to sign transaction: {}", err),
+        ));
+    }
+
+    match blockchain.add_transaction(transaction) {
+        Ok(block_index) => HttpResponse::Created().json(TransactionResponse { message, block_index }),
+        Err(err) => blockchain_error_response("Failed to submit governance transaction", err),
+    }
+}
+
+/// Get the chain parameters
+///
+/// Returns the parameters the next block is validated with and the changes voted in so far
+#[utoipa::path(
+    get,
+    path = "/api/v1/governance/parameters",
+    responses(
+        (status = 200, description = "Parameters retrieved successfully", body = ParametersResponse)
+    )
+)]
+pub async fn get_chain_parameters(blockchain: BlockchainData) -> impl Responder {
+    HttpResponse::Ok().json(ParametersResponse {
+        height: blockchain.get_last_block().index + 1,
+        parameters: blockchain.get_parameters(),
+        changes: blockchain.get_parameter_changes(),
+    })
+}
+
+/// List the proposals
+///
+/// Returns every proposal on the chain, newest deadline first
+#[utoipa::path(
+    get,
+    path = "/api/v1/governance/proposals",
+    responses(
+        (status = 200, description = "Proposals retrieved successfully", body = [Proposal])
+    )
+)]
+pub async fn list_proposals(blockchain: BlockchainData) -> impl Responder {
+    HttpResponse::Ok().json(blockchain.get_proposals())
+}
+
+/// Get a proposal
+///
+/// Returns a proposal with its votes, and the tally once its deadline is mined
+#[utoipa::path(
+    get,
+    path = "/api/v1/governance/proposals/{id}",
+    params(
+        ("id" = String, Path, description = "ID of the transaction that made the proposal")
+    ),
+    responses(
+        (status = 200, description = "Proposal retrieved successfully", body = Proposal),
+        (status = 404, description = "Proposal not found", body = ErrorResponse)
+    )
+)]
+pub async fn get_proposal(blockchain: BlockchainData, path: web::Path<String>) -> impl Responder {
+    let id = path.into_inner();
+
+    match blockchain.get_proposal(&id) {
+        Some(proposal) => HttpResponse::Ok().json(proposal),
+        None => HttpResponse::NotFound().json(ErrorResponse::new(
+            ErrorCode::NotFound,
+            format!("Proposal {} not found", id),
+        )),
+    }
+}
+
+/// Propose a parameter change
+///
+/// Submits a proposal that stakeholders vote on until the deadline. The
+/// proposal is identified by the ID of its transaction once mined.
+#[utoipa::path(
+    post,
+    path = "/api/v1/governance/proposals",
+    request_body = ProposalRequest,
+    responses(
+        (status = 201, description = "Proposal submitted successfully", body = TransactionResponse),
+        (status = 400, description = "Invalid proposal", body = ErrorResponse)
+    )
+)]
+pub async fn create_proposal(
+    blockchain: BlockchainData,
+    proposal: web::Json<ProposalRequest>,
+) -> impl Responder {
+    let action = GovernanceAction::Propose {
+        parameter: proposal.parameter,
+        value: proposal.value,
+        deadline: proposal.deadline,
+    };
+
+    let message = format!(
+        "Proposal to set {} to {} will be added to Block",
+        proposal.parameter, proposal.value
+    );
+
+    submit_governance(&blockchain, action, &proposal.proposer, proposal.fee, &proposal.private_key, message)
+}
+
+/// Propose a treasury disbursement
+///
+/// Submits a proposal to pay the recipient from the treasury in installments.
+/// It is voted on like a parameter change, and once it passes miners add the
+/// installments as they fall due.
+#[utoipa::path(
+    post,
+    path = "/api/v1/governance/disbursements",
+    request_body = DisbursementRequest,
+    responses(
+        (status = 201, description = "Proposal submitted successfully", body = TransactionResponse),
+        (status = 400, description = "Invalid proposal", body = ErrorResponse)
+    )
+)]
+pub async fn propose_disbursement(
+    blockchain: BlockchainData,
+    request: web::Json<DisbursementRequest>,
+) -> impl Responder {
+    let action = GovernanceAction::ProposeDisbursement {
+        disbursement: Disbursement {
+            recipient: request.recipient.clone(),
+            amount: request.amount,
+            installments: request.installments,
+            interval: request.interval,
+        },
+        deadline: request.deadline,
+    };
+
+    let message = format!(
+        "Proposal to pay {} {} times {} from the treasury will be added to Block",
+        request.recipient, request.installments, request.amount
+    );
+
+    submit_governance(&blockchain, action, &request.proposer, request.fee, &request.private_key, message)
+}
+
+/// Vote on a proposal
+///
+/// Submits a vote weighed by the voter's balance at the deadline. A later
+/// vote of the same address replaces its earlier one.
+#[utoipa::path(
+    post,
+    path = "/api/v1/governance/proposals/{id}/votes",
+    params(
+        ("id" = String, Path, description = "ID of the transaction that made the proposal")
+    ),
+    request_body = VoteRequest,
+    responses(
+        (status = 201, description = "Vote submitted successfully", body = TransactionResponse),
+        (status = 400, description = "Invalid vote or voting ended", body = ErrorResponse),
+        (status = 404, description = "Proposal not found", body = ErrorResponse)
+    )
+)]
+pub async fn vote_on_proposal(
+    blockchain: BlockchainData,
+    path: web::Path<String>,
+    vote: web::Json<VoteRequest>,
+) -> impl Responder {
+    let id = path.into_inner();
+    let action = GovernanceAction::Vote {
+        proposal: id.clone(),
+        approve: vote.approve,
+    };
+
+    let message = format!(
+        "Vote {} proposal {} will be added to Block",
+        if vote.approve { "for" } else { "against" },
+        id
+    );
+
+    submit_governance(&blockchain, action, &vote.voter, vote.fee, &vote.private_key, message)
+}
+
+/// Estimate the fee
+///
+/// Returns the base fee of the next block, how full blocks are allowed to get
+/// and a tip to add to the base fee, the median tip of the last blocks
+#[utoipa::path(
+    get,
+    path = "/api/v1/fees",
+    responses(
+        (status = 200, description = "Fee estimated successfully", body = FeeEstimate)
+    )
+)]
+pub async fn get_fee_estimate(blockchain: BlockchainData) -> impl Responder {
+    HttpResponse::Ok().json(blockchain.get_fee_estimate())
+}
+
+/// Audit the supply
+///
+/// Returns the coins minted by rewards, where the fees went and how the balances compare
+#[utoipa::path(
+    get,
+    path = "/api/v1/supply",
+    responses(
+        (status = 200, description = "Supply audited successfully", body = SupplyReport)
+    )
+)]
+pub async fn get_supply(blockchain: BlockchainData) -> impl Responder {
+    HttpResponse::Ok().json(blockchain.get_supply_report())
+}
+
+/// Query parameters for the ledger export
+#[derive(Serialize, Deserialize)]
+pub struct LedgerQuery {
+    /// The file format, defaults to CSV
+    pub format: Option<LedgerFormat>,
+}
+
+/// Export the ledger
+///
+/// Exports every balance change of the chain, transfers, fees, rewards, burns and funds credited
+/// outside the chain, as double-entry lines referencing their blocks, so conservation of value can
+/// be checked independently
+#[utoipa::path(
+    get,
+    path = "/api/v1/ledger/export",
+    params(
+        ("format" = Option<LedgerFormat>, Query, description = "The file format (csv or jsonl), defaults to csv")
+    ),
+    responses(
+        (status = 200, description = "Ledger exported successfully", content_type = "text/csv", body = String),
+        (status = 400, description = "Invalid format", body = ErrorResponse)
+    )
+)]
+pub async fn export_ledger(blockchain: BlockchainData, query: web::Query<LedgerQuery>) -> impl Responder {
+    let format = query.format.unwrap_or(LedgerFormat::Csv);
+    let ledger = blockchain.get_ledger();
+
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"ledger.{}\"", format.extension()),
+        ))
+        .body(ledger.render(format))
+}
+
+/// Get the dashboard
+///
+/// Returns the tip, mempool, recent block intervals, issuance rate, fee trend, peers and
+/// storage health of the node in one payload, so monitoring UIs poll one endpoint
+#[utoipa::path(
+    get,
+    path = "/api/v1/dashboard",
+    responses(
+        (status = 200, description = "Dashboard retrieved successfully", body = Dashboard)
+    )
+)]
+pub async fn get_dashboard(blockchain: BlockchainData) -> impl Responder {
+    // Sizing the database touches the disk
+    match web::block(move || blockchain.get_dashboard()).await {
+        Ok(dashboard) => HttpResponse::Ok().json(dashboard),
+        Err(err) => HttpResponse::InternalServerError().json(ErrorResponse::new(
+            ErrorCode::Internal,
+            format!("Failed to gather the dashboard: {}", err),
+        )),
+    }
+}
+
+/// Get the treasury
+///
+/// Returns the treasury's balance, what it gets from each block and the disbursements governance approved
+#[utoipa::path(
+    get,
+    path = "/api/v1/treasury",
+    responses(
+        (status = 200, description = "Treasury retrieved successfully", body = TreasuryStatus)
+    )
+)]
+pub async fn get_treasury(blockchain: BlockchainData) -> impl Responder {
+    HttpResponse::Ok().json(blockchain.get_treasury_status())
+}
+
+/// Get the network
+///
+/// Returns the network the node belongs to, its genesis hash and magic bytes, which peers compare before exchanging blocks
+#[utoipa::path(
+    get,
+    path = "/api/v1/network",
+    responses(
+        (status = 200, description = "Network retrieved successfully", body = NetworkInfo)
+    )
+)]
+pub async fn get_network(blockchain: BlockchainData) -> impl Responder {
+    HttpResponse::Ok().json(blockchain.get_network())
+}
+
+/// List the peers of the node
+///
+/// Returns the peers the P2P network found, through the bootstrap peers of the configuration, mDNS or
+/// by connecting to the node, and whether the node is connected to them
+#[utoipa::path(
+    get,
+    path = "/api/v1/peers",
+    responses(
+        (status = 200, description = "Peers retrieved successfully", body = Vec<PeerInfo>)
+    )
+)]
+pub async fn get_peers(blockchain: BlockchainData) -> impl Responder {
+    HttpResponse::Ok().json(blockchain.get_peers())
+}
+
+/// Request for the add peer endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AddPeerRequest {
+    /// Multiaddress of the peer, e.g. /ip4/10.0.0.1/tcp/9000, or host:port
+    pub address: String,
+}
+
+/// Response of the add peer endpoint
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct AddPeerResponse {
+    /// The multiaddress the node dials
+    pub address: String,
+}
+
+/// Query parameters for the remove peer endpoint
+#[derive(Serialize, Deserialize)]
+pub struct RemovePeerQuery {
+    /// Whether to also refuse the peer's connections until the node restarts (default false)
+    pub ban: Option<bool>,
+}
+
+/// Add a peer
+///
+/// Dials a peer at a multiaddress or host:port. The node connects in the background; the peer is
+/// listed with the manual source once connected
+#[utoipa::path(
+    post,
+    path = "/api/v1/peers",
+    request_body = AddPeerRequest,
+    responses(
+        (status = 202, description = "The node dials the peer", body = AddPeerResponse),
+        (status = 400, description = "Invalid address", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 503, description = "The P2P network is not running", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn add_peer(
+    req: HttpRequest,
+    admin: AdminData,
+    blockchain: BlockchainData,
+    peer_req: web::Json<AddPeerRequest>,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    let address = match discovery::parse_peer_address(peer_req.address.trim()) {
+        Ok(address) => address.to_string(),
+        Err(err) => {
+            return HttpResponse::BadRequest().json(ErrorResponse::new(ErrorCode::InvalidRequest, err.to_string()))
+        }
+    };
+
+    match blockchain.peer_set().dial(&address) {
+        Ok(()) => HttpResponse::Accepted().json(AddPeerResponse { address }),
+        Err(err) => peer_error_response(err),
+    }
+}
+
+/// Disconnect or ban a peer
+///
+/// Closes the connections to a peer. A banned peer is refused and never dialed until the node restarts;
+/// peers not known yet may be banned too
+#[utoipa::path(
+    delete,
+    path = "/api/v1/peers/{peer_id}",
+    params(
+        ("peer_id" = String, Path, description = "ID of the peer"),
+        ("ban" = Option<bool>, Query, description = "Whether to ban the peer (default false)")
+    ),
+    responses(
+        (status = 204, description = "The node disconnects from the peer"),
+        (status = 400, description = "Invalid peer ID", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorResponse),
+        (status = 404, description = "Unknown peer", body = ErrorResponse),
+        (status = 503, description = "The P2P network is not running", body = ErrorResponse)
+    ),
+    security(("admin_token" = []))
+)]
+pub async fn remove_peer(
+    req: HttpRequest,
+    admin: AdminData,
+    blockchain: BlockchainData,
+    peer_id: web::Path<String>,
+    query: web::Query<RemovePeerQuery>,
+) -> impl Responder {
+    if let Some(response) = admin.deny(&req) {
+        return response;
+    }
+
+    if let Err(err) = discovery::parse_peer_id(&peer_id) {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(ErrorCode::InvalidRequest, err.to_string()));
+    }
+
+    match blockchain.peer_set().disconnect(&peer_id, query.ban.unwrap_or(false)) {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(err) => peer_error_response(err),
+    }
+}
+
+/// Maps a peer management error to a response
+fn peer_error_response(err: PeerError) -> HttpResponse {
+    match err {
+        PeerError::NotFound(_) => HttpResponse::NotFound().json(ErrorResponse::new(ErrorCode::NotFound, err.to_string())),
+        PeerError::NetworkDisabled => HttpResponse::ServiceUnavailable()
+            .json(ErrorResponse::new(ErrorCode::NetworkDisabled, err.to_string())),
+    }
+}
+
+/// Query parameters for the economics endpoint
+#[derive(Serialize, Deserialize)]
+pub struct EconomicsQuery {
+    /// Blocks per period (default 100)
+    pub period: Option<u64>,
+
+    /// Number of periods to project (default 10)
+    pub periods: Option<u64>,
+}
+
+/// Get the emission schedule
+///
+/// Returns the coins issued per period so far, the issuance projected under the
+/// reward in force and the halving rules, and how much of the supply cap is issued
+#[utoipa::path(
+    get,
+    path = "/api/v1/economics",
+    params(
+        ("period" = Option<u64>, Query, description = "Blocks per period (default 100)"),
+        ("periods" = Option<u64>, Query, description = "Number of periods to project (default 10, at most 1000)")
+    ),
+    responses(
+        (status = 200, description = "Emission reported successfully", body = EconomicsReport),
+        (status = 400, description = "Invalid period", body = ErrorResponse)
+    )
+)]
+pub async fn get_economics(blockchain: BlockchainData, query: web::Query<EconomicsQuery>) -> impl Responder {
+    let period = query.period.unwrap_or(DEFAULT_PERIOD_BLOCKS);
+    let periods = query.periods.unwrap_or(DEFAULT_PROJECTED_PERIODS);
+
+    if period == 0 || periods > MAX_PROJECTED_PERIODS {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            ErrorCode::InvalidRequest,
+            format!("period must be at least 1 block and periods at most {}", MAX_PROJECTED_PERIODS),
+        ));
+    }
+
+    HttpResponse::Ok().json(blockchain.get_economics_report(period, periods))
+}
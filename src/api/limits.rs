@@ -0,0 +1,125 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Lets one sealing operation run at a time and queues a bounded number of others
+///
+/// Proof of work keeps a CPU busy until the block is sealed, so concurrent
+/// mining requests would only compete for the same cores. Requests beyond
+/// the queue are turned away instead of piling up.
+#[derive(Debug, Clone)]
+pub struct MiningLimiter {
+    permits: Arc<Semaphore>,
+    queued: Arc<AtomicUsize>,
+    max_queued: usize,
+}
+
+/// Counts a request as queued until it is dropped
+pub struct QueueSlot(Arc<AtomicUsize>);
+
+impl Drop for QueueSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl MiningLimiter {
+    /// Creates a new mining limiter
+    ///
+    /// # Arguments
+    ///
+    /// * `max_queued` - How many requests may wait for the running one, 0 to reject them right away
+    pub fn new(max_queued: usize) -> Self {
+        MiningLimiter {
+            permits: Arc::new(Semaphore::new(1)),
+            queued: Arc::new(AtomicUsize::new(0)),
+            max_queued,
+        }
+    }
+
+    /// Waits for the turn to mine
+    ///
+    /// The permit is owned, so it can move into the blocking task that seals
+    /// the block and is released when that task finishes.
+    ///
+    /// # Returns
+    ///
+    /// The permit, or None if the queue is full
+    pub async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        self.reserve()?.wait().await
+    }
+
+    /// Takes a place in the queue without waiting for the turn
+    ///
+    /// Lets a request that mines in the background be turned away right
+    /// away when the queue is full, then wait for its turn later.
+    ///
+    /// # Returns
+    ///
+    /// The place, or None if the queue is full
+    pub fn reserve(&self) -> Option<MiningTicket> {
+        if let Ok(permit) = self.permits.clone().try_acquire_owned() {
+            return Some(MiningTicket::Ready(permit));
+        }
+
+        // Released when the ticket is dropped, even while it waits
+        let slot = QueueSlot(self.queued.clone());
+        if self.queued.fetch_add(1, Ordering::SeqCst) >= self.max_queued {
+            return None;
+        }
+
+        Some(MiningTicket::Queued(slot, self.permits.clone()))
+    }
+}
+
+/// A place in the mining queue, see `MiningLimiter::reserve`
+pub enum MiningTicket {
+    /// The turn to mine, nothing was being mined
+    Ready(OwnedSemaphorePermit),
+
+    /// A place behind the block being mined
+    Queued(QueueSlot, Arc<Semaphore>),
+}
+
+impl MiningTicket {
+    /// Waits for the turn to mine
+    ///
+    /// # Returns
+    ///
+    /// The permit, None only if the limiter was closed
+    pub async fn wait(self) -> Option<OwnedSemaphorePermit> {
+        match self {
+            MiningTicket::Ready(permit) => Some(permit),
+            MiningTicket::Queued(_slot, permits) => permits.acquire_owned().await.ok(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_one_miner_at_a_time_with_bounded_queue() {
+        let limiter = MiningLimiter::new(1);
+        let running = limiter.acquire().await.unwrap();
+
+        // The second request queues, the third is turned away
+        let queued = {
+            let limiter = limiter.clone();
+            tokio::spawn(async move { limiter.acquire().await })
+        };
+        while limiter.queued.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert!(limiter.acquire().await.is_none());
+
+        // The queued request gets its turn once the running one finishes
+        drop(running);
+        let permit = queued.await.unwrap().unwrap();
+        assert_eq!(limiter.queued.load(Ordering::SeqCst), 0);
+        assert!(limiter.permits.clone().try_acquire_owned().is_err());
+        drop(permit);
+    }
+}
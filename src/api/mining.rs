@@ -0,0 +1,259 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use utoipa::ToSchema;
+
+use crate::blockchain::Block;
+use super::errors::ErrorResponse;
+
+/// Most mining jobs remembered, the oldest are forgotten first
+pub const MAX_MINING_JOBS: usize = 1_000;
+
+/// Most block templates remembered, the oldest are forgotten first
+pub const MAX_BLOCK_TEMPLATES: usize = 100;
+
+/// Where a mining job is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MiningJobStatus {
+    /// Waiting for the block being mined to be sealed
+    Queued,
+
+    /// Searching for the proof of work
+    Running,
+
+    /// The block was mined and added to the chain
+    Mined,
+
+    /// The block could not be mined, see the error
+    Failed,
+}
+
+/// A block mined in the background, see `POST /api/v1/mine?background=true`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct MiningJob {
+    /// ID to ask for the status with
+    pub id: String,
+
+    /// Where the job is
+    pub status: MiningJobStatus,
+
+    /// The address the coinbase pays
+    pub miner_address: String,
+
+    /// When the job was submitted
+    #[schema(value_type = String, example = "2023-01-01T12:00:00Z")]
+    pub submitted_at: DateTime<Utc>,
+
+    /// When the block was mined or mining failed
+    #[schema(value_type = Option<String>, example = "2023-01-01T12:00:00Z")]
+    pub finished_at: Option<DateTime<Utc>>,
+
+    /// Height of the mined block
+    pub block_index: Option<u64>,
+
+    /// Hash of the mined block
+    pub block_hash: Option<String>,
+
+    /// Why mining failed
+    pub error: Option<ErrorResponse>,
+}
+
+/// The mining jobs of the node, kept in memory
+///
+/// A node restart forgets them, and so does a job whose block is mined
+/// `MAX_MINING_JOBS` jobs later.
+#[derive(Debug, Clone, Default)]
+pub struct MiningJobs {
+    jobs: Arc<Mutex<Table<MiningJob>>>,
+}
+
+/// Remembered entries by ID and the order they were added in
+#[derive(Debug)]
+struct Table<T> {
+    by_id: HashMap<String, T>,
+    order: VecDeque<String>,
+}
+
+impl<T> Default for Table<T> {
+    fn default() -> Self {
+        Table {
+            by_id: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+}
+
+impl<T> Table<T> {
+    /// Adds an entry, forgetting the oldest beyond a number of entries
+    fn insert(&mut self, id: String, entry: T, max: usize) {
+        self.by_id.insert(id.clone(), entry);
+        self.order.push_back(id);
+        while self.order.len() > max {
+            if let Some(oldest) = self.order.pop_front() {
+                self.by_id.remove(&oldest);
+            }
+        }
+    }
+}
+
+impl MiningJobs {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a queued job
+    ///
+    /// # Arguments
+    ///
+    /// * `miner_address` - The address the coinbase pays
+    ///
+    /// # Returns
+    ///
+    /// The job
+    pub fn submit(&self, miner_address: &str) -> MiningJob {
+        let job = MiningJob {
+            id: uuid::Uuid::new_v4().to_string(),
+            status: MiningJobStatus::Queued,
+            miner_address: miner_address.to_string(),
+            submitted_at: Utc::now(),
+            finished_at: None,
+            block_index: None,
+            block_hash: None,
+            error: None,
+        };
+
+        self.jobs
+            .lock()
+            .unwrap()
+            .insert(job.id.clone(), job.clone(), MAX_MINING_JOBS);
+
+        job
+    }
+
+    /// Marks a job as searching for the proof of work
+    pub fn start(&self, id: &str) {
+        self.update(id, |job| job.status = MiningJobStatus::Running);
+    }
+
+    /// Records how a job ended
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - ID of the job
+    /// * `result` - The mined block, or why mining failed
+    pub fn finish(&self, id: &str, result: Result<&Block, ErrorResponse>) {
+        self.update(id, |job| {
+            job.finished_at = Some(Utc::now());
+            match result {
+                Ok(block) => {
+                    job.status = MiningJobStatus::Mined;
+                    job.block_index = Some(block.index);
+                    job.block_hash = Some(block.hash.clone());
+                }
+                Err(error) => {
+                    job.status = MiningJobStatus::Failed;
+                    job.error = Some(error);
+                }
+            }
+        });
+    }
+
+    /// Gets a job
+    pub fn get(&self, id: &str) -> Option<MiningJob> {
+        self.jobs.lock().unwrap().by_id.get(id).cloned()
+    }
+
+    /// Changes a job, if it is still remembered
+    fn update(&self, id: &str, change: impl FnOnce(&mut MiningJob)) {
+        if let Some(job) = self.jobs.lock().unwrap().by_id.get_mut(id) {
+            change(job);
+        }
+    }
+}
+
+/// The blocks handed to external miners, see `GET /api/v1/mining/template`
+///
+/// Kept in memory so a miner submits only the proof and hash it found. A
+/// node restart forgets them, and so does a template handed out
+/// `MAX_BLOCK_TEMPLATES` templates later.
+#[derive(Debug, Clone, Default)]
+pub struct BlockTemplates {
+    templates: Arc<Mutex<Table<Block>>>,
+}
+
+impl BlockTemplates {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remembers a block handed out for sealing
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block, with a proof of 0
+    ///
+    /// # Returns
+    ///
+    /// ID of the template
+    pub fn add(&self, block: Block) -> String {
+        let id = uuid::Uuid::new_v4().to_string();
+        self.templates
+            .lock()
+            .unwrap()
+            .insert(id.clone(), block, MAX_BLOCK_TEMPLATES);
+        id
+    }
+
+    /// Gets the block of a template
+    pub fn get(&self, id: &str) -> Option<Block> {
+        self.templates.lock().unwrap().by_id.get(id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::errors::ErrorCode;
+
+    #[test]
+    fn test_jobs_follow_their_block_and_old_ones_are_forgotten() {
+        let jobs = MiningJobs::new();
+        let mined = jobs.submit("miner");
+        let failed = jobs.submit("miner");
+        assert_eq!(jobs.get(&mined.id).unwrap().status, MiningJobStatus::Queued);
+
+        jobs.start(&mined.id);
+        assert_eq!(jobs.get(&mined.id).unwrap().status, MiningJobStatus::Running);
+        let block = Block::new(1, vec![], 0, "0".to_string());
+        jobs.finish(&mined.id, Ok(&block));
+        let job = jobs.get(&mined.id).unwrap();
+        assert_eq!(job.status, MiningJobStatus::Mined);
+        assert_eq!(job.block_hash, Some(block.hash));
+        assert!(job.finished_at.is_some());
+
+        jobs.finish(&failed.id, Err(ErrorResponse::new(ErrorCode::Paused, "paused")));
+        assert_eq!(jobs.get(&failed.id).unwrap().status, MiningJobStatus::Failed);
+
+        for _ in 0..MAX_MINING_JOBS {
+            jobs.submit("miner");
+        }
+        assert!(jobs.get(&mined.id).is_none());
+    }
+
+    #[test]
+    fn test_old_templates_are_forgotten() {
+        let templates = BlockTemplates::new();
+        let block = Block::new(1, vec![], 0, "0".to_string());
+        let first = templates.add(block.clone());
+        assert_eq!(templates.get(&first).map(|template| template.hash), Some(block.hash.clone()));
+
+        for _ in 0..MAX_BLOCK_TEMPLATES {
+            templates.add(block.clone());
+        }
+        assert!(templates.get(&first).is_none());
+    }
+}
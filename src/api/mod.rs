@@ -1,10 +1,26 @@
 // API module
 //
-// This module contains the API implementation for the blockchain
+// This module contains the API implementation for the blockchain, a load
+// generator that benchmarks a running node through it, a replica that
+// follows a primary node through it, the metering of API keys, the jobs
+// mining blocks in the background, the WebSocket subscriptions to chain
+// events and their Server-Sent Events stream, and with the grpc feature the
+// same operations over gRPC
 
+pub mod auth;
+pub mod bench;
+pub mod errors;
+pub mod events;
+#[cfg(feature = "grpc")]
+pub mod grpc;
 pub mod handlers;
+pub mod limits;
+pub mod mining;
+pub mod replication;
 pub mod routes;
 pub mod schema;
+pub mod subscriptions;
+pub mod usage;
 
 // Re-export main components for easier access
 pub use routes::configure_routes;
@@ -0,0 +1,340 @@
+use actix_web::web;
+use log::{info, warn};
+use serde::de::DeserializeOwned;
+use std::time::Duration;
+use thiserror::Error;
+
+use super::handlers::{AccountResponse, ChainResponse};
+use crate::blockchain::account::Account;
+use crate::blockchain::genesis::NetworkInfo;
+use crate::blockchain::{Address, Block, Blockchain};
+
+/// How long the primary holds a request for the next block before answering that none came
+const POLL_TIMEOUT: &str = "30s";
+
+/// Longest a single request to the primary may take, above the poll timeout
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long to wait before retrying after the primary failed or could not be reached
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Largest response read from the primary, the chain endpoint returns the whole chain
+const MAX_RESPONSE_BYTES: usize = 512 * 1024 * 1024;
+
+/// Errors that can occur while replicating a primary
+#[derive(Debug, Error)]
+pub enum ReplicationError {
+    #[error("Primary request failed: {0}")]
+    Http(String),
+
+    /// The local chain can't follow the primary's any more and must be copied again
+    #[error("Replica diverged from the primary: {0}")]
+    Diverged(String),
+
+    /// The primary belongs to another network, nothing of it may be copied
+    #[error("Primary belongs to another network: {0}")]
+    WrongNetwork(String),
+
+    #[error("System error: {0}")]
+    SystemError(String),
+}
+
+/// A read replica of a primary node, kept up to date over its REST API
+///
+/// The replica copies the primary's chain and balances once, then
+/// long-polls GET /api/v1/blocks/next and validates every new block before
+/// appending it. A reorganization on the primary shows as a stale tip, and
+/// the replica switches to the primary's branch. When the chains can't be
+/// reconciled, e.g. because the primary funded an account outside the
+/// chain, the replica copies the primary again.
+pub struct Replica {
+    /// The HTTP client
+    client: awc::Client,
+
+    /// Base URL of the primary, without a trailing slash
+    primary: String,
+
+    /// The local node, a follower
+    blockchain: web::Data<Blockchain>,
+}
+
+impl Replica {
+    /// Creates a replica of a primary
+    ///
+    /// # Arguments
+    ///
+    /// * `primary` - Base URL of the primary, e.g. http://primary:8080
+    /// * `blockchain` - The local node
+    pub fn new(primary: &str, blockchain: web::Data<Blockchain>) -> Self {
+        Replica {
+            client: awc::Client::builder().timeout(REQUEST_TIMEOUT).finish(),
+            primary: primary.trim_end_matches('/').to_string(),
+            blockchain,
+        }
+    }
+
+    /// Follows the primary until the task is dropped
+    ///
+    /// A local chain that already holds blocks, e.g. after a restart, is
+    /// resumed from its tip; a new one is copied from the primary first.
+    pub async fn run(self) {
+        // Never take blocks from a node of another network
+        while let Err(err) = self.handshake().await {
+            warn!("{}, retrying in {}s", err, RETRY_DELAY.as_secs());
+            actix_web::rt::time::sleep(RETRY_DELAY).await;
+        }
+
+        let mut synced = self.blockchain.get_last_block().index > 0;
+
+        loop {
+            let result = if synced {
+                self.poll().await.map(|_| ())
+            } else {
+                self.resync().await.map(|height| info!("Copied the chain of {} up to height {}", self.primary, height))
+            };
+
+            match result {
+                Ok(()) => synced = true,
+                Err(err @ ReplicationError::Diverged(_)) => {
+                    warn!("{}, copying the primary again", err);
+                    synced = false;
+                }
+                Err(err) => {
+                    warn!("{}, retrying in {}s", err, RETRY_DELAY.as_secs());
+                    actix_web::rt::time::sleep(RETRY_DELAY).await;
+                }
+            }
+        }
+    }
+
+    /// Copies the chain and the balances of the primary
+    ///
+    /// The balances are only taken when no block was appended on the primary
+    /// while copying, so they match the copied chain.
+    ///
+    /// # Returns
+    ///
+    /// Result with the height of the copied chain
+    pub async fn resync(&self) -> Result<u64, ReplicationError> {
+        self.handshake().await?;
+
+        let chain = self.get::<ChainResponse>("/api/v1/chain").await?.chain;
+        let accounts = self.get::<Vec<AccountResponse>>("/api/v1/accounts").await?;
+        let tip = chain
+            .last()
+            .ok_or_else(|| ReplicationError::Http("the primary returned no blocks".to_string()))?
+            .clone();
+        if self.next_block(&tip.hash, "0ms").await?.is_some() {
+            return Err(ReplicationError::Http("a block was appended while copying".to_string()));
+        }
+
+        let accounts: Vec<Account> = accounts
+            .into_iter()
+            .map(|account| Account {
+                balance: account.balance,
+                nonce: account.nonce,
+                ..Account::new(Address(account.address))
+            })
+            .collect();
+        let blockchain = self.blockchain.clone();
+        web::block(move || blockchain.replace_chain(chain, accounts))
+            .await
+            .map_err(|err| ReplicationError::SystemError(err.to_string()))?
+            .map_err(|err| ReplicationError::Diverged(format!("the primary's chain can not be copied: {}", err)))?;
+
+        Ok(tip.index)
+    }
+
+    /// Checks that the primary belongs to the network of the local node
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if the network magic of both nodes is the same
+    pub async fn handshake(&self) -> Result<(), ReplicationError> {
+        let remote = self.get::<NetworkInfo>("/api/v1/network").await?;
+        let local = self.blockchain.get_network();
+        if remote.magic != local.magic {
+            return Err(ReplicationError::WrongNetwork(format!(
+                "{} ({}), this node is on {} ({})",
+                remote.network, remote.magic, local.network, local.magic
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Waits for the primary's next block and applies it
+    ///
+    /// # Returns
+    ///
+    /// Result with the new local height, None if no block came before the timeout
+    pub async fn poll(&self) -> Result<Option<u64>, ReplicationError> {
+        let tip = self.blockchain.get_last_block();
+        let block = match self.next_block(&tip.hash, POLL_TIMEOUT).await {
+            Ok(Some(block)) => block,
+            Ok(None) => return Ok(None),
+            // The primary no longer has the local tip, it reorganized
+            Err(ReplicationError::Diverged(_)) => return self.follow_branch().await.map(Some),
+            Err(err) => return Err(err),
+        };
+
+        let index = block.index;
+        let blockchain = self.blockchain.clone();
+        web::block(move || blockchain.append_block(block))
+            .await
+            .map_err(|err| ReplicationError::SystemError(err.to_string()))?
+            .map_err(|err| ReplicationError::Diverged(format!("block {} can not be applied: {}", index, err)))?;
+
+        Ok(Some(index))
+    }
+
+    /// Switches to the primary's chain after it reorganized
+    ///
+    /// # Returns
+    ///
+    /// Result with the new local height
+    async fn follow_branch(&self) -> Result<u64, ReplicationError> {
+        let remote = self.get::<ChainResponse>("/api/v1/chain").await?.chain;
+        let local = self.blockchain.get_chain();
+        let shared = local
+            .iter()
+            .zip(&remote)
+            .take_while(|(local, remote)| local.hash == remote.hash)
+            .count();
+        if shared == 0 {
+            return Err(ReplicationError::Diverged("the primary has another genesis block".to_string()));
+        }
+
+        let branch = remote[shared..].to_vec();
+        let height = remote.len() as u64 - 1;
+        let blockchain = self.blockchain.clone();
+        web::block(move || {
+            if shared < local.len() {
+                blockchain.reorganize(branch).map(|_| ())
+            } else {
+                branch.into_iter().try_for_each(|block| blockchain.append_block(block))
+            }
+        })
+        .await
+        .map_err(|err| ReplicationError::SystemError(err.to_string()))?
+        .map_err(|err| ReplicationError::Diverged(format!("the primary's branch can not be applied: {}", err)))?;
+
+        info!("Followed the primary to height {} from block {}", height, shared - 1);
+        Ok(height)
+    }
+
+    /// Asks the primary for the block after a hash
+    ///
+    /// # Returns
+    ///
+    /// Result with the block, None if none came before the timeout, or
+    /// ReplicationError::Diverged if the primary has no block with this hash
+    async fn next_block(&self, after_hash: &str, timeout: &str) -> Result<Option<Block>, ReplicationError> {
+        let url = format!("{}/api/v1/blocks/next", self.primary);
+        let mut response = self
+            .client
+            .get(&url)
+            .query(&[("after_hash", after_hash), ("timeout", timeout)])
+            .map_err(|err| ReplicationError::Http(err.to_string()))?
+            .send()
+            .await
+            .map_err(|err| ReplicationError::Http(format!("{}: {}", url, err)))?;
+
+        match response.status().as_u16() {
+            200 => response
+                .json()
+                .limit(MAX_RESPONSE_BYTES)
+                .await
+                .map(Some)
+                .map_err(|err| ReplicationError::Http(format!("{}: {}", url, err))),
+            204 => Ok(None),
+            404 => Err(ReplicationError::Diverged(format!("the primary has no block {}", after_hash))),
+            status => Err(ReplicationError::Http(format!("{}: status {}", url, status))),
+        }
+    }
+
+    /// Sends a GET request to the primary and decodes the JSON response
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, ReplicationError> {
+        let url = format!("{}{}", self.primary, path);
+        let mut response = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .map_err(|err| ReplicationError::Http(format!("{}: {}", url, err)))?;
+        if !response.status().is_success() {
+            return Err(ReplicationError::Http(format!("{}: status {}", url, response.status())));
+        }
+
+        response
+            .json()
+            .limit(MAX_RESPONSE_BYTES)
+            .await
+            .map_err(|err| ReplicationError::Http(format!("{}: {}", url, err)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::genesis::GenesisConfig;
+    use actix_web::{App, HttpServer};
+
+    #[actix_web::test]
+    async fn test_replica_follows_primary() {
+        let primary = web::Data::new(Blockchain::new());
+        let limiter = web::Data::new(crate::api::limits::MiningLimiter::new(1));
+        let jobs = web::Data::new(crate::api::mining::MiningJobs::new());
+        let data = primary.clone();
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(data.clone())
+                .app_data(limiter.clone())
+                .app_data(jobs.clone())
+                .configure(crate::api::configure_routes)
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let port = server.addrs()[0].port();
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        // Balances funded outside the chain come with the copy
+        let miner = crate::blockchain::Wallet::new().unwrap();
+        let mut funded = primary.get_account_state().get_account(miner.address());
+        funded.deposit(7.0).unwrap();
+        primary.get_account_state().update_account(funded);
+        primary.mine_block(&miner.address().0).unwrap();
+
+        let mut local = Blockchain::new();
+        local.set_follower(true);
+        let replica = Replica::new(&format!("http://127.0.0.1:{}/", port), web::Data::new(local));
+        assert_eq!(replica.resync().await.unwrap(), 1);
+
+        // A node of another network copies nothing
+        let classroom = GenesisConfig {
+            network: "classroom".to_string(),
+            ..GenesisConfig::default()
+        };
+        let stranger = Replica::new(&format!("http://127.0.0.1:{}", port), web::Data::new(Blockchain::with_genesis(classroom)));
+        assert!(matches!(stranger.resync().await, Err(ReplicationError::WrongNetwork(_))));
+        assert_eq!(stranger.blockchain.get_last_block().index, 0);
+
+        let mined = primary.clone();
+        let miner_address = miner.address().0.clone();
+        actix_web::rt::spawn(async move {
+            actix_web::rt::time::sleep(Duration::from_millis(100)).await;
+            web::block(move || mined.mine_block(&miner_address)).await.unwrap().unwrap();
+        });
+        assert_eq!(replica.poll().await.unwrap(), Some(2));
+
+        let follower = &replica.blockchain;
+        assert_eq!(follower.get_last_block().hash, primary.get_last_block().hash);
+        assert_eq!(follower.get_account_state().get_account(miner.address()).balance, 107.0);
+        assert!(follower.is_valid());
+
+        handle.stop(true).await;
+    }
+}
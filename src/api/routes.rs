@@ -1,6 +1,6 @@
 use actix_web::web;
 
-use super::handlers;
+use super::{events, handlers, subscriptions};
 
 /// Configures the API routes
 ///
@@ -11,13 +11,106 @@ pub fn configure_routes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/api/v1")
             .route("/chain", web::get().to(handlers::get_chain))
+            .route("/network", web::get().to(handlers::get_network))
+            .route("/peers", web::get().to(handlers::get_peers))
+            .route("/peers", web::post().to(handlers::add_peer))
+            .route("/peers/{peer_id}", web::delete().to(handlers::remove_peer))
+            .route("/blocks/at", web::get().to(handlers::get_block_at))
+            .route("/blocks/next", web::get().to(handlers::get_next_block))
+            .route("/ws", web::get().to(subscriptions::subscribe))
+            .route("/events", web::get().to(events::stream))
+            .route("/blocks/headers", web::get().to(handlers::get_block_headers))
+            .route("/blocks/{hash}/raw", web::get().to(handlers::get_raw_block))
+            .route("/verify/hash", web::post().to(handlers::verify_hash))
+            .route("/simulate/difficulty", web::get().to(handlers::simulate_difficulty))
+            .route("/simulate/attack", web::get().to(handlers::simulate_attack))
             .route("/transactions/pending", web::get().to(handlers::get_pending_transactions))
             .route("/transactions/new", web::post().to(handlers::new_transaction))
+            .route("/transactions/batch", web::post().to(handlers::submit_transaction_batch))
+            .route("/transactions/{id}/rebroadcast", web::post().to(handlers::rebroadcast_transaction))
+            .route("/transactions", web::get().to(handlers::query_transactions))
             .route("/mine", web::post().to(handlers::mine_block))
+            .route("/mine/status/{id}", web::get().to(handlers::get_mining_job))
+            .route("/mining/template", web::get().to(handlers::get_block_template))
+            .route("/mining/submit", web::post().to(handlers::submit_block))
             .route("/validate", web::get().to(handlers::validate_chain))
             .route("/wallet/new", web::post().to(handlers::create_wallet))
             .route("/wallet/fund", web::post().to(handlers::fund_wallet))
             .route("/wallet/balance/{address}", web::get().to(handlers::get_wallet_balance))
+            .route("/wallet/nonce/{address}", web::get().to(handlers::get_wallet_nonce))
             .route("/accounts", web::get().to(handlers::get_all_accounts))
+            .route("/names", web::post().to(handlers::register_name))
+            .route("/names/{name}", web::get().to(handlers::get_name))
+            .route("/anchor", web::post().to(handlers::anchor_document))
+            .route("/anchor/{hash}", web::get().to(handlers::get_anchor))
+            .route("/locks", web::post().to(handlers::create_lock))
+            .route("/locks/{id}", web::get().to(handlers::get_lock))
+            .route("/locks/{id}/spend", web::post().to(handlers::spend_lock))
+            .route("/sessions", web::post().to(handlers::authorize_session_key))
+            .route("/sessions/{key}/revoke", web::post().to(handlers::revoke_session_key))
+            .route("/inheritance", web::post().to(handlers::designate_beneficiary))
+            .route("/inheritance/cancel", web::post().to(handlers::cancel_inheritance))
+            .route("/inheritance/{owner}/claim", web::post().to(handlers::claim_inheritance))
+            .route("/inheritance/{owner}", web::get().to(handlers::get_inheritance))
+            .route("/permissions", web::get().to(handlers::get_permissions))
+            .route("/permissions", web::post().to(handlers::change_permission))
+            .route("/permissions/freezes", web::get().to(handlers::list_freezes))
+            .route("/permissions/freezes", web::post().to(handlers::freeze_account))
+            .route("/governance/parameters", web::get().to(handlers::get_chain_parameters))
+            .route("/fees", web::get().to(handlers::get_fee_estimate))
+            .route("/supply", web::get().to(handlers::get_supply))
+            .route("/ledger/export", web::get().to(handlers::export_ledger))
+            .route("/economics", web::get().to(handlers::get_economics))
+            .route("/dashboard", web::get().to(handlers::get_dashboard))
+            .route("/treasury", web::get().to(handlers::get_treasury))
+            .route("/governance/disbursements", web::post().to(handlers::propose_disbursement))
+            .route("/governance/proposals", web::get().to(handlers::list_proposals))
+            .route("/governance/proposals", web::post().to(handlers::create_proposal))
+            .route("/governance/proposals/{id}", web::get().to(handlers::get_proposal))
+            .route("/governance/proposals/{id}/votes", web::post().to(handlers::vote_on_proposal))
+            .route("/payments", web::post().to(handlers::create_payment))
+            .route("/payments/invoice", web::post().to(handlers::create_invoice))
+            .route("/payments/{id}", web::get().to(handlers::get_payment))
+            .route("/withdrawals", web::post().to(handlers::request_withdrawal))
+            .route("/withdrawals/{id}", web::get().to(handlers::get_withdrawal))
+            .route("/withdrawals/accounts/{address}", web::get().to(handlers::get_withdrawal_balance))
+            .route("/admin/withdrawals", web::get().to(handlers::list_withdrawals))
+            .route("/admin/withdrawals/submit", web::post().to(handlers::submit_withdrawals))
+            .route("/admin/withdrawals/{id}/approve", web::post().to(handlers::approve_withdrawal))
+            .route("/admin/withdrawals/{id}/reject", web::post().to(handlers::reject_withdrawal))
+            .route("/schedules", web::post().to(handlers::register_schedule))
+            .route("/schedules", web::get().to(handlers::list_schedules))
+            .route("/schedules/{id}", web::get().to(handlers::get_schedule))
+            .route("/schedules/{id}/cancel", web::post().to(handlers::cancel_schedule))
+            .route("/admin/audit", web::get().to(handlers::get_audit_log))
+            .route("/admin/usage", web::get().to(handlers::get_usage))
+            .route("/keystore/wallets", web::get().to(handlers::list_keystore_wallets))
+            .route("/keystore/wallets", web::post().to(handlers::add_keystore_wallet))
+            .route("/keystore/watch", web::post().to(handlers::add_watch_only_wallet))
+            .route("/keystore/wallets/{name}", web::get().to(handlers::get_keystore_wallet))
+            .route("/keystore/wallets/{name}/send", web::post().to(handlers::send_from_keystore_wallet))
+            .route("/keystore/backup", web::post().to(handlers::backup_keystore))
+            .route("/keystore/restore", web::post().to(handlers::restore_keystore))
+            .route("/transactions/{id}/label", web::put().to(handlers::set_transaction_label))
+            .route("/transactions/{id}/label", web::get().to(handlers::get_transaction_label))
+            .route("/address/{address}/transactions/export.csv", web::get().to(handlers::export_address_transactions))
+            .route("/address/{address}/counterparties", web::get().to(handlers::get_address_counterparties))
+            .route("/address/{address}/locks", web::get().to(handlers::get_address_locks))
+            .route("/address/{address}/sessions", web::get().to(handlers::get_address_session_keys))
+            .route("/address/{address}/inheritances", web::get().to(handlers::get_address_inheritances))
+            .route("/graph", web::get().to(handlers::export_transaction_graph))
+            .route("/reports/activity", web::get().to(handlers::get_activity_report))
+            .route("/reorgs", web::get().to(handlers::get_reorgs))
+            .route("/admin/maintenance", web::get().to(handlers::get_maintenance))
+            .route("/admin/maintenance/pause", web::post().to(handlers::pause_node))
+            .route("/admin/maintenance/resume", web::post().to(handlers::resume_node))
+            .route("/admin/reset", web::post().to(handlers::reset_chain))
+            .route("/admin/blocks", web::post().to(handlers::append_block))
+            .route("/admin/snapshots", web::get().to(handlers::list_snapshots))
+            .route("/admin/snapshots", web::post().to(handlers::take_snapshot))
+            .route("/admin/snapshots/{id}/restore", web::post().to(handlers::restore_snapshot))
+            .route("/admin/storage/stats", web::get().to(handlers::get_storage_stats))
+            .route("/admin/keys", web::get().to(handlers::list_node_keys))
+            .route("/admin/keys/{purpose}/rotate", web::post().to(handlers::rotate_node_key))
     );
 }
@@ -0,0 +1,358 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_ws::{AggregatedMessage, Session};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+
+use std::collections::{HashMap, HashSet};
+
+use super::handlers::BlockchainData;
+use crate::blockchain::events::ChainEvent;
+use crate::blockchain::{Address, Blockchain};
+
+/// Most topics one connection may subscribe to
+pub const MAX_SUBSCRIPTIONS: usize = 64;
+
+/// Largest message a client may send, in bytes
+const MAX_CLIENT_MESSAGE_BYTES: usize = 16 * 1024;
+
+/// What a client can subscribe to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Topic {
+    /// Blocks appended to the chain, and the new tip after a reorganization
+    NewBlock,
+
+    /// Transactions admitted to the pending transactions
+    NewTransaction,
+
+    /// The balance of an address, after each block that changed it
+    BalanceChanged(Address),
+}
+
+impl Topic {
+    /// Parses a topic, e.g. `new_block` or `balance_changed:ADDRESS`
+    ///
+    /// # Returns
+    ///
+    /// The topic, None if there is no such topic
+    pub fn parse(topic: &str) -> Option<Self> {
+        match topic {
+            "new_block" => Some(Topic::NewBlock),
+            "new_transaction" => Some(Topic::NewTransaction),
+            _ => match topic.strip_prefix("balance_changed:") {
+                Some(address) if !address.is_empty() => Some(Topic::BalanceChanged(Address(address.to_string()))),
+                _ => None,
+            },
+        }
+    }
+
+    /// Gets the name the topic is subscribed by
+    pub fn name(&self) -> String {
+        match self {
+            Topic::NewBlock => "new_block".to_string(),
+            Topic::NewTransaction => "new_transaction".to_string(),
+            Topic::BalanceChanged(address) => format!("balance_changed:{}", address.0),
+        }
+    }
+}
+
+/// A message of a client, changing its subscriptions
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ClientMessage {
+    /// Topics to subscribe to
+    subscribe: Vec<String>,
+
+    /// Topics to unsubscribe from
+    unsubscribe: Vec<String>,
+}
+
+/// A message to a client
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// The topics the connection is subscribed to now
+    Subscribed { topics: Vec<String> },
+
+    /// A block is the new tip
+    NewBlock { index: u64, hash: String },
+
+    /// A transaction was admitted
+    NewTransaction {
+        id: String,
+        sender: String,
+        recipient: String,
+        amount: f64,
+        fee: f64,
+    },
+
+    /// The balance of a subscribed address, when subscribing and after it changed
+    BalanceChanged { address: String, balance: f64 },
+
+    /// A client message was refused, the subscriptions are unchanged
+    Error { message: String },
+}
+
+/// The subscriptions of one connection
+#[derive(Debug, Default)]
+pub struct Subscriptions {
+    topics: HashSet<Topic>,
+
+    /// Last balance sent per subscribed address
+    balances: HashMap<Address, f64>,
+}
+
+impl Subscriptions {
+    /// Applies a client message
+    ///
+    /// Either all of its topics are valid and applied, or none is.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The message, JSON like `{"subscribe": ["new_block"]}`
+    /// * `blockchain` - The blockchain, for the balances of new balance topics
+    ///
+    /// # Returns
+    ///
+    /// The messages to send back
+    fn apply(&mut self, text: &str, blockchain: &Blockchain) -> Vec<ServerMessage> {
+        let message: ClientMessage = match serde_json::from_str(text) {
+            Ok(message) => message,
+            Err(err) => return vec![error(format!("Invalid message: {}", err))],
+        };
+        let parse = |topics: &[String]| -> Result<Vec<Topic>, ServerMessage> {
+            topics
+                .iter()
+                .map(|topic| Topic::parse(topic).ok_or_else(|| error(format!("Unknown topic {}", topic))))
+                .collect()
+        };
+        let (subscribe, unsubscribe) = match (parse(&message.subscribe), parse(&message.unsubscribe)) {
+            (Ok(subscribe), Ok(unsubscribe)) => (subscribe, unsubscribe),
+            (Err(err), _) | (_, Err(err)) => return vec![err],
+        };
+
+        let mut topics = self.topics.clone();
+        for topic in &unsubscribe {
+            topics.remove(topic);
+        }
+        topics.extend(subscribe);
+        if topics.len() > MAX_SUBSCRIPTIONS {
+            return vec![error(format!("A connection subscribes to at most {} topics", MAX_SUBSCRIPTIONS))];
+        }
+
+        self.topics = topics;
+        self.balances.retain(|address, _| self.topics.contains(&Topic::BalanceChanged(address.clone())));
+
+        let mut names: Vec<String> = self.topics.iter().map(Topic::name).collect();
+        names.sort();
+        let mut messages = vec![ServerMessage::Subscribed { topics: names }];
+        // New balance topics start with the current balance
+        for topic in &self.topics {
+            if let Topic::BalanceChanged(address) = topic {
+                if !self.balances.contains_key(address) {
+                    let balance = blockchain.get_account_state().get_account(address).balance;
+                    self.balances.insert(address.clone(), balance);
+                    messages.push(ServerMessage::BalanceChanged {
+                        address: address.0.clone(),
+                        balance,
+                    });
+                }
+            }
+        }
+        messages
+    }
+
+    /// Turns a chain event into the messages the subscriptions ask for
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event, None if the connection missed events
+    /// * `blockchain` - The blockchain, for the new tip and the balances
+    ///
+    /// # Returns
+    ///
+    /// The messages to send
+    fn on_event(&mut self, event: Option<&ChainEvent>, blockchain: &Blockchain) -> Vec<ServerMessage> {
+        let mut messages = Vec::new();
+
+        let tip = match event {
+            Some(ChainEvent::TransactionAdmitted { id, sender, recipient, amount, fee }) => {
+                if self.topics.contains(&Topic::NewTransaction) {
+                    messages.push(ServerMessage::NewTransaction {
+                        id: id.clone(),
+                        sender: sender.0.clone(),
+                        recipient: recipient.0.clone(),
+                        amount: *amount,
+                        fee: *fee,
+                    });
+                }
+                // Balances only change with blocks
+                return messages;
+            }
+            Some(ChainEvent::TransactionEvicted { .. }) => return messages,
+            Some(ChainEvent::BlockAdded { index, hash }) | Some(ChainEvent::Restored { index, hash }) => {
+                Some((*index, hash.clone()))
+            }
+            Some(ChainEvent::Reset { genesis_hash }) => Some((0, genesis_hash.clone())),
+            Some(ChainEvent::Reorg(reorg)) => blockchain
+                .get_blocks_by_hash(std::slice::from_ref(&reorg.new_tip))
+                .first()
+                .map(|block| (block.index, block.hash.clone())),
+            None => None,
+        };
+        if let (Some((index, hash)), true) = (tip, self.topics.contains(&Topic::NewBlock)) {
+            messages.push(ServerMessage::NewBlock { index, hash });
+        }
+
+        let accounts = blockchain.get_account_state();
+        for (address, last) in self.balances.iter_mut() {
+            let balance = accounts.get_account(address).balance;
+            if balance != *last {
+                *last = balance;
+                messages.push(ServerMessage::BalanceChanged {
+                    address: address.0.clone(),
+                    balance,
+                });
+            }
+        }
+
+        messages
+    }
+}
+
+/// Creates an error message
+fn error(message: String) -> ServerMessage {
+    ServerMessage::Error { message }
+}
+
+/// Sends messages to a client
+///
+/// # Returns
+///
+/// Whether the client is still connected
+async fn send(session: &mut Session, messages: Vec<ServerMessage>) -> bool {
+    for message in messages {
+        let text = match serde_json::to_string(&message) {
+            Ok(text) => text,
+            Err(_) => continue,
+        };
+        if session.text(text).await.is_err() {
+            return false;
+        }
+    }
+    true
+}
+
+/// Subscribe to chain events over a WebSocket
+///
+/// Upgrades the connection and streams the events of the subscribed topics
+/// as JSON text messages until the client closes it. Clients change their
+/// subscriptions with messages like `{"subscribe": ["new_block", "balance_changed:ADDRESS"]}`
+/// and `{"unsubscribe": ["new_block"]}`.
+#[utoipa::path(
+    get,
+    path = "/api/v1/ws",
+    responses(
+        (status = 101, description = "Switched to the WebSocket protocol"),
+        (status = 400, description = "Not a WebSocket handshake")
+    )
+)]
+pub async fn subscribe(
+    req: HttpRequest,
+    body: web::Payload,
+    blockchain: BlockchainData,
+) -> actix_web::Result<HttpResponse> {
+    let (response, mut session, stream) = actix_ws::handle(&req, body)?;
+    let mut stream = stream
+        .max_frame_size(MAX_CLIENT_MESSAGE_BYTES)
+        .aggregate_continuations()
+        .max_continuation_size(MAX_CLIENT_MESSAGE_BYTES);
+    let mut events = blockchain.subscribe();
+
+    actix_web::rt::spawn(async move {
+        let mut subscriptions = Subscriptions::default();
+
+        loop {
+            let messages = tokio::select! {
+                event = events.recv() => match event {
+                    Ok(event) => subscriptions.on_event(Some(&event), &blockchain),
+                    // Missed blocks may have changed balances, so they are looked at again
+                    Err(RecvError::Lagged(_)) => subscriptions.on_event(None, &blockchain),
+                    Err(RecvError::Closed) => break,
+                },
+                message = stream.recv() => match message {
+                    Some(Ok(AggregatedMessage::Text(text))) => subscriptions.apply(&text, &blockchain),
+                    Some(Ok(AggregatedMessage::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            return;
+                        }
+                        continue;
+                    }
+                    Some(Ok(AggregatedMessage::Binary(_))) => vec![error("Messages must be JSON text".to_string())],
+                    Some(Ok(AggregatedMessage::Pong(_))) => continue,
+                    Some(Ok(AggregatedMessage::Close(_))) | None => break,
+                    Some(Err(err)) => {
+                        debug!("Closing a subscription after a protocol error: {}", err);
+                        break;
+                    }
+                },
+            };
+
+            if !send(&mut session, messages).await {
+                return;
+            }
+        }
+
+        let _ = session.close(None).await;
+    });
+
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Wallet;
+
+    #[test]
+    fn test_subscriptions_follow_blocks_and_balances() {
+        let blockchain = Blockchain::new();
+        let miner = Wallet::new().unwrap();
+        let mut subscriptions = Subscriptions::default();
+
+        assert!(Topic::parse("balance_changed:").is_none());
+        let refused = subscriptions.apply(r#"{"subscribe": ["new_block", "new_blocks"]}"#, &blockchain);
+        assert!(matches!(refused.as_slice(), [ServerMessage::Error { .. }]));
+
+        let topic = format!("balance_changed:{}", miner.address().0);
+        let subscribed = subscriptions.apply(&format!(r#"{{"subscribe": ["new_block", "{}"]}}"#, topic), &blockchain);
+        assert_eq!(
+            subscribed[1],
+            ServerMessage::BalanceChanged {
+                address: miner.address().0.clone(),
+                balance: 0.0,
+            }
+        );
+
+        let block = blockchain.mine_block(&miner.address().0).unwrap();
+        let event = ChainEvent::BlockAdded {
+            index: block.index,
+            hash: block.hash.clone(),
+        };
+        let messages = subscriptions.on_event(Some(&event), &blockchain);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0], ServerMessage::NewBlock { index: 1, hash: block.hash });
+
+        // Unchanged balances and unsubscribed topics send nothing
+        subscriptions.apply(r#"{"unsubscribe": ["new_block"]}"#, &blockchain);
+        let admitted = ChainEvent::TransactionAdmitted {
+            id: "id".to_string(),
+            sender: miner.address().clone(),
+            recipient: miner.address().clone(),
+            amount: 1.0,
+            fee: 0.1,
+        };
+        assert!(subscriptions.on_event(Some(&admitted), &blockchain).is_empty());
+        assert!(subscriptions.on_event(Some(&event), &blockchain).is_empty());
+    }
+}
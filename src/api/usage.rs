@@ -0,0 +1,86 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, HttpRequest, HttpResponse};
+use chrono::Utc;
+
+use super::auth;
+use super::errors::{ErrorCode, ErrorResponse};
+use crate::blockchain::usage::UsageMeter;
+
+/// Resources a request used, noted by its handler for the meter
+#[derive(Debug, Clone, Copy)]
+struct Served {
+    blocks: u64,
+    transactions: u64,
+}
+
+/// Notes that a handler returned blocks
+///
+/// # Arguments
+///
+/// * `req` - The HTTP request
+/// * `blocks` - How many blocks the response holds
+pub fn served_blocks(req: &HttpRequest, blocks: usize) {
+    note(req, blocks as u64, 0);
+}
+
+/// Notes that a handler submitted transactions
+///
+/// # Arguments
+///
+/// * `req` - The HTTP request
+/// * `transactions` - How many transactions entered the mempool
+pub fn submitted_transactions(req: &HttpRequest, transactions: usize) {
+    note(req, 0, transactions as u64);
+}
+
+/// Adds resources to those the request used so far
+fn note(req: &HttpRequest, blocks: u64, transactions: u64) {
+    let mut extensions = req.extensions_mut();
+    match extensions.get_mut::<Served>() {
+        Some(served) => {
+            served.blocks += blocks;
+            served.transactions += transactions;
+        }
+        None => {
+            extensions.insert(Served { blocks, transactions });
+        }
+    }
+}
+
+/// Meters the requests that carry an API key and turns away those over their key's monthly quota
+///
+/// Wrapped around the app with `middleware::from_fn`. Requests without a key,
+/// and every request when no meter is registered, pass through unmetered.
+pub async fn meter(req: ServiceRequest, next: Next<impl MessageBody + 'static>) -> Result<ServiceResponse<BoxBody>, Error> {
+    let meter = req.app_data::<web::Data<UsageMeter>>().cloned();
+    let (meter, api_key) = match (meter, auth::api_key(req.request())) {
+        (Some(meter), Some(api_key)) => (meter, api_key),
+        _ => return Ok(next.call(req).await?.map_into_boxed_body()),
+    };
+
+    let now = Utc::now();
+    if let Err(exceeded) = meter.admit(&api_key, now) {
+        let retry_after = (exceeded.resets_at - now).num_seconds().max(1);
+        let response = HttpResponse::TooManyRequests()
+            .insert_header(("Retry-After", retry_after.to_string()))
+            .json(ErrorResponse::new(
+                ErrorCode::QuotaExceeded,
+                format!(
+                    "The API key used up its quota of {} requests this month, it resets at {}",
+                    exceeded.quota,
+                    exceeded.resets_at.to_rfc3339()
+                ),
+            ));
+        return Ok(req.into_response(response));
+    }
+
+    let response = next.call(req).await?;
+    let served = response.request().extensions().get::<Served>().copied();
+    if let Some(served) = served {
+        meter.record(&api_key, served.blocks, served.transactions, Utc::now());
+    }
+
+    Ok(response.map_into_boxed_body())
+}
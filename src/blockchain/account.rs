@@ -1,4 +1,4 @@
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use utoipa::ToSchema;
@@ -152,6 +152,9 @@ impl Account {
 #[derive(Debug, Clone)]
 pub struct AccountState {
     accounts: Arc<DashMap<Address, Account>>,
+
+    /// Addresses whose accounts changed since they were last taken for storage
+    changed: Arc<DashSet<Address>>,
 }
 
 impl AccountState {
@@ -163,6 +166,7 @@ impl AccountState {
     pub fn new() -> Self {
         AccountState {
             accounts: Arc::new(DashMap::new()),
+            changed: Arc::new(DashSet::new()),
         }
     }
 
@@ -189,7 +193,10 @@ impl AccountState {
     ///
     /// * `account` - The account to update
     pub fn update_account(&self, account: Account) {
-        self.accounts.insert(account.address.clone(), account);
+        let address = account.address.clone();
+        self.accounts.insert(address.clone(), account);
+        // Marked after the insert, so whoever takes the mark reads the new account
+        self.changed.insert(address);
     }
 
     /// Transfers funds between accounts
@@ -269,6 +276,19 @@ impl AccountState {
         Ok(())
     }
 
+    /// Replaces all accounts
+    ///
+    /// # Arguments
+    ///
+    /// * `accounts` - The accounts to keep
+    pub fn replace_all(&self, accounts: Vec<Account>) {
+        self.accounts.clear();
+
+        for account in accounts {
+            self.update_account(account);
+        }
+    }
+
     /// Gets all accounts
     ///
     /// # Returns
@@ -277,6 +297,26 @@ impl AccountState {
     pub fn get_all_accounts(&self) -> Vec<Account> {
         self.accounts.iter().map(|entry| entry.value().clone()).collect()
     }
+
+    /// Takes the accounts that changed since the last call, so only they are written to storage
+    ///
+    /// # Returns
+    ///
+    /// The changed accounts, each once however often it changed
+    pub fn take_changed_accounts(&self) -> Vec<Account> {
+        let addresses: Vec<Address> = self.changed.iter().map(|address| address.key().clone()).collect();
+        addresses
+            .into_iter()
+            .filter_map(|address| self.changed.remove(&address))
+            .map(|address| self.get_account(&address))
+            .collect()
+    }
+}
+
+impl Default for AccountState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[cfg(test)]
@@ -293,6 +333,25 @@ mod tests {
         assert_eq!(account.nonce, 0);
     }
 
+    #[test]
+    fn test_changed_accounts_are_taken_once() {
+        let state = AccountState::new();
+        let alice = Address("alice".to_string());
+        let bob = Address("bob".to_string());
+        state.process_mining_reward(&alice, 10.0).unwrap();
+        state.process_mining_reward(&bob, 10.0).unwrap();
+        state.take_changed_accounts();
+
+        state.transfer(&alice, &bob, 1.0, 0.5, 0).unwrap();
+        state.transfer(&alice, &bob, 1.0, 0.5, 1).unwrap();
+        let mut changed = state.take_changed_accounts();
+        changed.sort_by(|a, b| a.address.0.cmp(&b.address.0));
+        assert_eq!(changed.len(), 2);
+        assert_eq!(changed[0].balance, 7.0);
+        assert_eq!(changed[1].balance, 12.0);
+        assert!(state.take_changed_accounts().is_empty());
+    }
+
     #[test]
     fn test_deposit_and_withdraw() {
         let address = Address("test_address".to_string());
@@ -337,4 +396,19 @@ mod tests {
         assert_eq!(sender_after.nonce, 1);
         assert_eq!(recipient_after.balance, 50.0);
     }
+
+    #[test]
+    fn test_account_wire_format_is_stable() {
+        use crate::blockchain::golden;
+        use crate::blockchain::testnet::TestNet;
+
+        let testnet = TestNet::generate(1, 2, 3, 1).unwrap();
+        let account = testnet.accounts.iter().find(|account| account.nonce > 0).unwrap();
+        golden::assert_text("account.json", &serde_json::to_string_pretty(account).unwrap());
+        golden::assert_bytes("account.bin.hex", &bincode::serialize(account).unwrap());
+
+        let stored: Account = bincode::deserialize(&golden::read_bytes("account.bin.hex")).unwrap();
+        assert_eq!(stored.address, account.address);
+        assert_eq!((stored.balance, stored.nonce), (account.balance, account.nonce));
+    }
 }
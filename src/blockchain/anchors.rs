@@ -0,0 +1,158 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use super::block::Block;
+use super::merkle::{self, MerkleProof};
+use super::transaction::Transaction;
+
+/// Address anchors are paid to, which has no key so the amount is burned
+pub const ANCHOR_ADDRESS: &str = "anchors";
+
+/// Amount an anchor pays to the anchor address
+pub const ANCHOR_AMOUNT: f64 = 0.01;
+
+/// Length of an anchored hash in hex digits
+pub const ANCHOR_HASH_LENGTH: usize = 64;
+
+/// Errors that can occur with anchors
+#[derive(Debug, Error)]
+pub enum AnchorError {
+    #[error("Invalid hash: {0}")]
+    InvalidHash(String),
+
+    #[error("Invalid anchor: {0}")]
+    InvalidAnchor(String),
+}
+
+/// Proof of when a document hash was anchored
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AnchorProof {
+    /// The anchored hash
+    pub hash: String,
+
+    /// ID of the transaction that anchored it
+    pub transaction_id: String,
+
+    /// Height of the block that includes the transaction
+    pub block_index: u64,
+
+    /// Hash of the block that includes the transaction
+    pub block_hash: String,
+
+    /// Time the block was mined, the latest time the document existed by
+    #[schema(value_type = String, example = "2023-01-01T12:00:00Z")]
+    pub block_timestamp: DateTime<Utc>,
+
+    /// Number of blocks from the block to the tip, itself included
+    pub confirmations: u64,
+
+    /// Inclusion proof of the transaction in the transactions of the block
+    pub proof: MerkleProof,
+}
+
+impl AnchorProof {
+    /// Builds the proof for a transaction of a block
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block that includes the transaction
+    /// * `position` - Position of the transaction in the block
+    /// * `height` - Height of the tip of the chain
+    ///
+    /// # Returns
+    ///
+    /// The proof, None if the transaction anchors nothing
+    pub fn new(block: &Block, position: usize, height: u64) -> Option<Self> {
+        let transaction = block.transactions.get(position)?;
+        let hash = transaction.data.clone()?;
+        let proof = merkle::prove(&merkle::transaction_leaves(block), position)?;
+
+        Some(AnchorProof {
+            hash,
+            transaction_id: transaction.id.clone(),
+            block_index: block.index,
+            block_hash: block.hash.clone(),
+            block_timestamp: block.timestamp,
+            confirmations: height.saturating_sub(block.index) + 1,
+            proof,
+        })
+    }
+}
+
+/// Checks that a document hash can be anchored
+///
+/// # Arguments
+///
+/// * `hash` - The hash, 64 hex digits in either case
+///
+/// # Returns
+///
+/// The hash in lowercase, the form it is anchored and looked up in
+pub fn validate_hash(hash: &str) -> Result<String, AnchorError> {
+    if hash.len() != ANCHOR_HASH_LENGTH || !hash.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(AnchorError::InvalidHash(format!(
+            "{} is not {} hex digits",
+            hash, ANCHOR_HASH_LENGTH
+        )));
+    }
+
+    Ok(hash.to_ascii_lowercase())
+}
+
+/// Checks that a transaction carrying data is a well formed anchor
+///
+/// # Arguments
+///
+/// * `transaction` - The transaction to check
+///
+/// # Returns
+///
+/// Ok(()) if the transaction carries no data or anchors a lowercase hash
+pub fn check_anchor(transaction: &Transaction) -> Result<(), AnchorError> {
+    let data = match &transaction.data {
+        Some(data) => data,
+        None => return Ok(()),
+    };
+
+    if &validate_hash(data)? != data {
+        return Err(AnchorError::InvalidHash(format!("{} must be lowercase", data)));
+    }
+    if transaction.is_coinbase() {
+        return Err(AnchorError::InvalidAnchor("A coinbase transaction can't anchor data".to_string()));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::crypto::Address;
+
+    #[test]
+    fn test_anchors_prove_inclusion() {
+        let hash = "AB".repeat(32);
+        assert_eq!(validate_hash(&hash).unwrap(), "ab".repeat(32));
+        assert!(validate_hash("abc").is_err());
+        assert!(validate_hash(&"zz".repeat(32)).is_err());
+
+        let anchor = |nonce: u64, data: &str| {
+            Transaction::new(Address("alice".to_string()), Address(ANCHOR_ADDRESS.to_string()), ANCHOR_AMOUNT, 0.1, nonce)
+                .with_data(data)
+        };
+        assert!(check_anchor(&anchor(0, &hash)).is_err());
+        let coinbase = Transaction::new_coinbase(Address("miner".to_string()), 50.0).with_data(&"ab".repeat(32));
+        assert!(matches!(check_anchor(&coinbase), Err(AnchorError::InvalidAnchor(_))));
+
+        let transactions = vec![anchor(0, &"01".repeat(32)), anchor(1, &"02".repeat(32)), anchor(2, &"03".repeat(32))];
+        let block = Block::new(4, transactions, 0, "0".to_string());
+
+        let proof = AnchorProof::new(&block, 2, 9).unwrap();
+        assert_eq!(proof.hash, "03".repeat(32));
+        assert_eq!(proof.confirmations, 6);
+        assert!(proof.proof.verify());
+        assert!(AnchorProof::new(&block, 3, 9).is_none());
+    }
+}
@@ -0,0 +1,331 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::chain::{Blockchain, BlockchainError};
+use super::crypto::Wallet;
+use super::events::ReorgEvent;
+use super::transaction::{Transaction, TransactionError};
+
+/// Most races one simulation runs
+pub const MAX_TRIALS: u64 = 10_000;
+
+/// Most blocks both branches of a demonstrated attack may hold together
+pub const MAX_DEMONSTRATION_BLOCKS: u64 = 24;
+
+/// Most blocks a race runs before the attacker is taken to give up
+const MAX_RACE_BLOCKS: u64 = 100_000;
+
+/// A double-spend attack to simulate
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct AttackParameters {
+    /// Share of the network hash rate the attacker controls, above 0 and below 1
+    pub attacker_share: f64,
+
+    /// Blocks the merchant waits for, counting the one with the payment, before handing over the goods
+    pub confirmations: u64,
+
+    /// Blocks the attacker's branch may fall behind before the attacker gives up
+    pub max_deficit: u64,
+
+    /// Number of races to run
+    pub trials: u64,
+
+    /// Seed of the races, the same seed gives the same outcome
+    pub seed: u64,
+}
+
+/// How a race between the attacker and the honest miners ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Race {
+    /// Blocks the honest miners found after the fork, the first holds the payment
+    pub honest_blocks: u64,
+
+    /// Blocks the attacker found on its private branch, the first holds the double spend
+    pub attacker_blocks: u64,
+
+    /// Whether the attacker's branch became longer once the payment was confirmed
+    pub succeeded: bool,
+}
+
+impl Race {
+    /// Runs a race block by block, each found by the attacker with a chance of its hash-rate share
+    ///
+    /// # Arguments
+    ///
+    /// * `parameters` - The attack
+    /// * `rng` - The source of randomness
+    pub fn run(parameters: &AttackParameters, rng: &mut impl Rng) -> Self {
+        let mut race = Race {
+            honest_blocks: 0,
+            attacker_blocks: 0,
+            succeeded: false,
+        };
+
+        while race.honest_blocks + race.attacker_blocks < MAX_RACE_BLOCKS {
+            if rng.gen_bool(parameters.attacker_share) {
+                race.attacker_blocks += 1;
+            } else {
+                race.honest_blocks += 1;
+            }
+
+            // A branch replaces the chain only if it is longer
+            if race.honest_blocks >= parameters.confirmations && race.attacker_blocks > race.honest_blocks {
+                race.succeeded = true;
+                break;
+            }
+            if race.honest_blocks >= race.attacker_blocks + parameters.max_deficit {
+                break;
+            }
+        }
+
+        race
+    }
+}
+
+/// A race replayed against a copy of the local chain with real blocks
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ReorgDemonstration {
+    /// The race that was replayed
+    pub race: Race,
+
+    /// Height of the block the attacker mined to fund itself, where the branches fork
+    pub fork_height: u64,
+
+    /// ID of the payment to the merchant on the honest branch
+    pub payment: String,
+
+    /// ID of the transaction spending the same coins on the attacker's branch
+    pub double_spend: String,
+
+    /// Balance of the merchant before the attacker published its branch
+    pub merchant_balance_before: f64,
+
+    /// Balance of the merchant afterwards
+    pub merchant_balance_after: f64,
+
+    /// The reorganization, None if the node rejected the attacker's branch
+    pub reorganization: Option<ReorgEvent>,
+
+    /// Why the node rejected the attacker's branch
+    pub rejected: Option<String>,
+}
+
+/// The outcome of a double-spend attack simulation
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AttackReport {
+    /// The attack that was simulated
+    pub parameters: AttackParameters,
+
+    /// Races the attacker won
+    pub successes: u64,
+
+    /// Share of the races the attacker won
+    pub success_probability: f64,
+
+    /// The whitepaper's probability that an attacker who never gives up catches up
+    pub whitepaper_probability: f64,
+
+    /// Average blocks found by both sides per race
+    pub average_race_blocks: f64,
+
+    /// The shortest race, preferably a won one, that is short enough to replay
+    pub example_race: Option<Race>,
+
+    /// The example race replayed against a copy of the chain, if asked for
+    pub demonstration: Option<ReorgDemonstration>,
+}
+
+/// Gets the probability that an attacker ever catches up from behind
+///
+/// This is the calculation of section 11 of the Bitcoin whitepaper: while
+/// the honest miners find `confirmations` blocks, the attacker's progress is
+/// Poisson distributed, and from each deficit it catches up with the
+/// gambler's ruin probability.
+///
+/// # Arguments
+///
+/// * `attacker_share` - Share of the hash rate the attacker controls
+/// * `confirmations` - Blocks the honest miners are ahead by
+pub fn whitepaper_probability(attacker_share: f64, confirmations: u64) -> f64 {
+    let q = attacker_share;
+    let p = 1.0 - q;
+    if q >= p {
+        return 1.0;
+    }
+
+    let lambda = confirmations as f64 * q / p;
+    let mut poisson = (-lambda).exp();
+    let mut probability = 1.0;
+    for k in 0..=confirmations {
+        if k > 0 {
+            poisson *= lambda / k as f64;
+        }
+        probability -= poisson * (1.0 - (q / p).powi((confirmations - k) as i32));
+    }
+
+    probability.max(0.0)
+}
+
+/// Runs the races of an attack
+///
+/// # Arguments
+///
+/// * `parameters` - The attack, checked by the caller
+///
+/// # Returns
+///
+/// The report, without a demonstration
+pub fn simulate(parameters: &AttackParameters) -> AttackReport {
+    let mut rng = StdRng::seed_from_u64(parameters.seed);
+    let mut successes = 0;
+    let mut race_blocks = 0;
+    let mut example_race: Option<Race> = None;
+
+    for _ in 0..parameters.trials {
+        let race = Race::run(parameters, &mut rng);
+        if race.succeeded {
+            successes += 1;
+        }
+        race_blocks += race.honest_blocks + race.attacker_blocks;
+
+        let rank = |race: &Race| (!race.succeeded, race.honest_blocks + race.attacker_blocks);
+        let replayable = race.honest_blocks + race.attacker_blocks <= MAX_DEMONSTRATION_BLOCKS;
+        if replayable && example_race.is_none_or(|example| rank(&race) < rank(&example)) {
+            example_race = Some(race);
+        }
+    }
+
+    let trials = parameters.trials.max(1) as f64;
+    AttackReport {
+        parameters: parameters.clone(),
+        successes,
+        success_probability: successes as f64 / trials,
+        whitepaper_probability: whitepaper_probability(parameters.attacker_share, parameters.confirmations),
+        average_race_blocks: race_blocks as f64 / trials,
+        example_race,
+        demonstration: None,
+    }
+}
+
+/// Replays a race against a copy of a chain
+///
+/// An attacker mines a block to fund itself. The honest miners then mine the
+/// race's blocks with a payment from the attacker to a merchant, while the
+/// attacker privately mines its own blocks with a transaction sending the
+/// same coins back to itself. Finally the attacker publishes its branch and
+/// the copy reorganizes to it if it is longer, exactly as the node would.
+///
+/// # Arguments
+///
+/// * `blockchain` - The node whose chain is copied, it is left untouched
+/// * `race` - The race to replay
+///
+/// # Returns
+///
+/// Result with what happened to the merchant's payment
+pub fn demonstrate(blockchain: &Blockchain, race: Race) -> Result<ReorgDemonstration, BlockchainError> {
+    let new_wallet = || Wallet::new().map_err(TransactionError::from);
+    let attacker = new_wallet()?;
+    let merchant = new_wallet()?;
+    let stash = new_wallet()?;
+    let honest_miner = new_wallet()?;
+
+    let honest = blockchain.scratch_copy()?;
+    honest.mine_block(&attacker.address().0)?;
+    let fork = honest.get_last_block();
+    let private = honest.scratch_copy()?;
+
+    // Both transactions spend the same coins with the same nonce, only one can stay
+    let amount = honest.get_account_state().get_account(attacker.address()).balance / 2.0;
+    let nonce = honest.get_next_nonce(attacker.address());
+    let spend = |recipient: &Wallet| -> Result<Transaction, BlockchainError> {
+        let mut transaction =
+            Transaction::new(attacker.address().clone(), recipient.address().clone(), amount, honest.get_base_fee(), nonce);
+        transaction.sign(&attacker)?;
+        Ok(transaction)
+    };
+    let payment = spend(&merchant)?;
+    let double_spend = spend(&stash)?;
+
+    honest.add_transaction(payment.clone())?;
+    for _ in 0..race.honest_blocks {
+        honest.mine_block(&honest_miner.address().0)?;
+    }
+    private.add_transaction(double_spend.clone())?;
+    for _ in 0..race.attacker_blocks {
+        private.mine_block(&attacker.address().0)?;
+    }
+
+    let merchant_balance = |node: &Blockchain| node.get_account_state().get_account(merchant.address()).balance;
+    let merchant_balance_before = merchant_balance(&honest);
+    let branch = private.get_chain().split_off(fork.index as usize + 1);
+    let (reorganization, rejected) = match honest.reorganize(branch) {
+        Ok(reorganization) => (Some(reorganization), None),
+        Err(err) => (None, Some(err.to_string())),
+    };
+
+    Ok(ReorgDemonstration {
+        race,
+        fork_height: fork.index,
+        payment: payment.id,
+        double_spend: double_spend.id,
+        merchant_balance_before,
+        merchant_balance_after: merchant_balance(&honest),
+        reorganization,
+        rejected,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attacks_succeed_as_often_as_the_whitepaper_predicts() {
+        // Values from the table in section 11 of the whitepaper
+        assert!((whitepaper_probability(0.1, 5) - 0.0009137).abs() < 1e-7);
+        assert!((whitepaper_probability(0.3, 5) - 0.1773523).abs() < 1e-7);
+        assert_eq!(whitepaper_probability(0.5, 5), 1.0);
+
+        let parameters = AttackParameters {
+            attacker_share: 0.3,
+            confirmations: 2,
+            max_deficit: 20,
+            trials: 2_000,
+            seed: 7,
+        };
+        let report = simulate(&parameters);
+        assert_eq!(report.successes, simulate(&parameters).successes);
+        assert!(report.success_probability > 0.1 && report.success_probability < 0.5);
+        let weak = simulate(&AttackParameters { attacker_share: 0.05, ..parameters.clone() });
+        assert!(weak.success_probability < report.success_probability);
+    }
+
+    #[test]
+    fn test_a_won_race_reverses_the_payment() {
+        let blockchain = Blockchain::new();
+        let race = Race {
+            honest_blocks: 1,
+            attacker_blocks: 2,
+            succeeded: true,
+        };
+
+        let demonstration = demonstrate(&blockchain, race).unwrap();
+        assert!(demonstration.merchant_balance_before > 0.0);
+        assert_eq!(demonstration.merchant_balance_after, 0.0);
+        let reorganization = demonstration.reorganization.unwrap();
+        assert!(reorganization.affected_transactions.contains(&demonstration.payment));
+        assert_eq!(blockchain.get_chain().len(), 1);
+
+        let lost = Race {
+            honest_blocks: 1,
+            attacker_blocks: 1,
+            succeeded: false,
+        };
+        let demonstration = demonstrate(&blockchain, lost).unwrap();
+        assert!(demonstration.rejected.is_some());
+        assert!(demonstration.merchant_balance_after > 0.0);
+    }
+}
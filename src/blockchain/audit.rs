@@ -0,0 +1,102 @@
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use std::sync::{Arc, Mutex};
+
+use super::storage::{BlockchainStorage, StorageError};
+
+/// A single entry of the audit log
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AuditEntry {
+    /// Timestamp when the action happened
+    #[schema(value_type = String, example = "2023-01-01T12:00:00Z")]
+    pub timestamp: DateTime<Utc>,
+
+    /// Who performed the action
+    pub actor: String,
+
+    /// What was done (e.g. "withdrawal.approved")
+    pub action: String,
+
+    /// The object the action was performed on
+    pub subject: String,
+
+    /// Free-form details
+    pub detail: String,
+}
+
+/// Append-only log of operator and system actions
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    entries: Arc<Mutex<Vec<AuditEntry>>>,
+    storage: Option<Arc<BlockchainStorage>>,
+}
+
+impl AuditLog {
+    /// Creates a new audit log
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - Optional storage to persist entries in
+    ///
+    /// # Returns
+    ///
+    /// A new AuditLog instance with the entries loaded from storage
+    pub fn new(storage: Option<Arc<BlockchainStorage>>) -> Result<Self, StorageError> {
+        let entries = match &storage {
+            Some(storage) => storage.get_audit_entries()?,
+            None => Vec::new(),
+        };
+
+        Ok(AuditLog {
+            entries: Arc::new(Mutex::new(entries)),
+            storage,
+        })
+    }
+
+    /// Records an action
+    ///
+    /// # Arguments
+    ///
+    /// * `actor` - Who performed the action
+    /// * `action` - What was done
+    /// * `subject` - The object the action was performed on
+    /// * `detail` - Free-form details
+    pub fn record(&self, actor: &str, action: &str, subject: &str, detail: &str) {
+        let entry = AuditEntry {
+            timestamp: Utc::now(),
+            actor: actor.to_string(),
+            action: action.to_string(),
+            subject: subject.to_string(),
+            detail: detail.to_string(),
+        };
+
+        info!("Audit: {} {} {} ({})", entry.actor, entry.action, entry.subject, entry.detail);
+
+        if let Some(storage) = &self.storage {
+            if let Err(err) = storage.append_audit_entry(&entry) {
+                warn!("Failed to persist audit entry: {}", err);
+            }
+        }
+
+        self.entries.lock().unwrap().push(entry);
+    }
+
+    /// Gets all entries in the order they were recorded
+    pub fn entries(&self) -> Vec<AuditEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    /// Gets the entries concerning a subject
+    pub fn entries_for(&self, subject: &str) -> Vec<AuditEntry> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|entry| entry.subject == subject)
+            .cloned()
+            .collect()
+    }
+}
@@ -0,0 +1,402 @@
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use chrono::{DateTime, Utc};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use super::keystore::KeystoreEntry;
+
+/// Current version of the backup format
+pub const BACKUP_VERSION: u32 = 1;
+
+/// Length of the data key, passphrase keys and recovery shares
+const KEY_LENGTH: usize = 32;
+
+/// Length of the passphrase salt
+const SALT_LENGTH: usize = 16;
+
+/// Length of the nonce prefixed to every ciphertext
+const NONCE_LENGTH: usize = 12;
+
+/// Length of the checksum at the end of a recovery code
+const CHECKSUM_LENGTH: usize = 2;
+
+/// Errors that can occur while creating or restoring a backup
+#[derive(Debug, Error)]
+pub enum BackupError {
+    #[error("Invalid backup parameters: {0}")]
+    InvalidParameters(String),
+
+    #[error("Invalid recovery code: {0}")]
+    InvalidRecoveryCode(String),
+
+    #[error("Unsupported backup version: {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("Malformed backup: {0}")]
+    Malformed(String),
+
+    #[error("Wrong passphrase or recovery codes")]
+    WrongSecret,
+}
+
+/// A keystore backup encrypted under a passphrase and a set of recovery codes
+///
+/// The wallets are encrypted under a random data key. The data key is stored
+/// encrypted under a key derived from the passphrase, and split into recovery
+/// codes so that any `threshold` of them rebuild it without the passphrase.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct EncryptedBackup {
+    /// Version of the backup format
+    pub version: u32,
+
+    /// Timestamp when the backup was created
+    #[schema(value_type = String, example = "2023-01-01T12:00:00Z")]
+    pub created_at: DateTime<Utc>,
+
+    /// Number of wallets in the backup
+    pub wallet_count: usize,
+
+    /// Number of recovery codes needed to restore without the passphrase
+    pub threshold: u8,
+
+    /// Number of recovery codes generated
+    pub share_count: u8,
+
+    /// Salt of the passphrase key derivation (hex encoded)
+    pub salt: String,
+
+    /// The data key encrypted under the passphrase key (hex encoded)
+    pub wrapped_key: String,
+
+    /// The wallets encrypted under the data key (hex encoded)
+    pub payload: String,
+}
+
+/// What a backup is unlocked with
+#[derive(Debug, Clone)]
+pub enum BackupSecret {
+    /// The passphrase the backup was created with
+    Passphrase(String),
+
+    /// At least `threshold` of the recovery codes generated with the backup
+    RecoveryCodes(Vec<String>),
+}
+
+/// Encrypts keystore wallets into a backup
+///
+/// # Arguments
+///
+/// * `entries` - The wallets to back up
+/// * `passphrase` - The passphrase that unlocks the backup
+/// * `threshold` - Number of recovery codes that unlock the backup
+/// * `share_count` - Number of recovery codes to generate
+///
+/// # Returns
+///
+/// The backup and its recovery codes, which are not stored anywhere else
+pub fn create_backup(
+    entries: &[KeystoreEntry],
+    passphrase: &str,
+    threshold: u8,
+    share_count: u8,
+) -> Result<(EncryptedBackup, Vec<String>), BackupError> {
+    if passphrase.is_empty() {
+        return Err(BackupError::InvalidParameters("the passphrase is empty".to_string()));
+    }
+    if threshold == 0 || threshold > share_count {
+        return Err(BackupError::InvalidParameters(format!(
+            "threshold must be between 1 and the number of recovery codes, got {} of {}",
+            threshold, share_count
+        )));
+    }
+
+    let mut data_key = [0u8; KEY_LENGTH];
+    OsRng.fill_bytes(&mut data_key);
+    let mut salt = [0u8; SALT_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+
+    let plaintext = serde_json::to_vec(entries).map_err(|e| BackupError::Malformed(e.to_string()))?;
+    let passphrase_key = derive_passphrase_key(passphrase, &salt)?;
+
+    let backup = EncryptedBackup {
+        version: BACKUP_VERSION,
+        created_at: Utc::now(),
+        wallet_count: entries.len(),
+        threshold,
+        share_count,
+        salt: hex::encode(salt),
+        wrapped_key: hex::encode(seal(&passphrase_key, &data_key, b"wrapped_key")),
+        payload: hex::encode(seal(&data_key, &plaintext, b"payload")),
+    };
+
+    let codes = split_secret(&data_key, threshold, share_count)
+        .iter()
+        .map(|share| encode_recovery_code(share))
+        .collect();
+
+    Ok((backup, codes))
+}
+
+/// Decrypts the wallets of a backup
+///
+/// # Arguments
+///
+/// * `backup` - The backup
+/// * `secret` - The passphrase or recovery codes
+///
+/// # Returns
+///
+/// The wallets, or BackupError::WrongSecret if the secret does not unlock the backup
+pub fn restore_backup(backup: &EncryptedBackup, secret: &BackupSecret) -> Result<Vec<KeystoreEntry>, BackupError> {
+    if backup.version != BACKUP_VERSION {
+        return Err(BackupError::UnsupportedVersion(backup.version));
+    }
+
+    let data_key = match secret {
+        BackupSecret::Passphrase(passphrase) => {
+            let passphrase_key = derive_passphrase_key(passphrase, &decode_hex("salt", &backup.salt)?)?;
+            open(&passphrase_key, &decode_hex("wrapped_key", &backup.wrapped_key)?, b"wrapped_key")?
+        }
+        BackupSecret::RecoveryCodes(codes) => {
+            let mut shares: Vec<Vec<u8>> = Vec::new();
+            for code in codes {
+                let share = decode_recovery_code(code)?;
+                if !shares.iter().any(|known| known[0] == share[0]) {
+                    shares.push(share);
+                }
+            }
+            if shares.len() < backup.threshold as usize {
+                return Err(BackupError::InvalidRecoveryCode(format!(
+                    "{} different codes given, {} needed",
+                    shares.len(),
+                    backup.threshold
+                )));
+            }
+            combine_shares(&shares)
+        }
+    };
+
+    let plaintext = open(&data_key, &decode_hex("payload", &backup.payload)?, b"payload")?;
+    serde_json::from_slice(&plaintext).map_err(|e| BackupError::Malformed(e.to_string()))
+}
+
+/// Derives the key that wraps the data key from a passphrase
+fn derive_passphrase_key(passphrase: &str, salt: &[u8]) -> Result<Vec<u8>, BackupError> {
+    let mut key = vec![0u8; KEY_LENGTH];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| BackupError::Malformed(e.to_string()))?;
+    Ok(key)
+}
+
+/// Encrypts bytes, prefixing the random nonce to the ciphertext
+///
+/// The label is authenticated, so a ciphertext can not be swapped for
+/// another field of the backup.
+fn seal(key: &[u8], plaintext: &[u8], label: &[u8]) -> Vec<u8> {
+    let mut nonce = [0u8; NONCE_LENGTH];
+    OsRng.fill_bytes(&mut nonce);
+
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key))
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad: &aad(label) })
+        .expect("encrypting in memory cannot fail");
+
+    [nonce.as_slice(), &ciphertext].concat()
+}
+
+/// Decrypts bytes sealed with `seal`
+fn open(key: &[u8], sealed: &[u8], label: &[u8]) -> Result<Vec<u8>, BackupError> {
+    if sealed.len() < NONCE_LENGTH {
+        return Err(BackupError::Malformed("ciphertext is too short".to_string()));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+
+    ChaCha20Poly1305::new(Key::from_slice(key))
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: &aad(label) })
+        .map_err(|_| BackupError::WrongSecret)
+}
+
+/// Gets the associated data of a field of the backup
+fn aad(label: &[u8]) -> Vec<u8> {
+    [format!("my_blockchain/keystore-backup/v{}/", BACKUP_VERSION).as_bytes(), label].concat()
+}
+
+/// Decodes a hex field of the backup
+fn decode_hex(field: &str, value: &str) -> Result<Vec<u8>, BackupError> {
+    hex::decode(value).map_err(|e| BackupError::Malformed(format!("{}: {}", field, e)))
+}
+
+/// Splits a secret into shares, any `threshold` of which rebuild it
+///
+/// This is Shamir's secret sharing over GF(256), byte by byte. Every share
+/// starts with its x coordinate (1 to `share_count`) followed by the value
+/// of the random polynomial at that point for every byte of the secret.
+fn split_secret(secret: &[u8], threshold: u8, share_count: u8) -> Vec<Vec<u8>> {
+    let mut shares: Vec<Vec<u8>> = (1..=share_count).map(|x| vec![x]).collect();
+    let mut coefficients = vec![0u8; threshold as usize];
+
+    for &byte in secret {
+        coefficients[0] = byte;
+        OsRng.fill_bytes(&mut coefficients[1..]);
+
+        for share in shares.iter_mut() {
+            let x = share[0];
+            // Horner's method, highest coefficient first
+            let y = coefficients.iter().rev().fold(0u8, |acc, &c| gf_mul(acc, x) ^ c);
+            share.push(y);
+        }
+    }
+
+    shares
+}
+
+/// Rebuilds a secret from shares with distinct x coordinates
+///
+/// Interpolates the polynomial of every byte at x = 0. Too few or foreign
+/// shares give a wrong secret, which the authenticated decryption rejects.
+fn combine_shares(shares: &[Vec<u8>]) -> Vec<u8> {
+    let length = shares[0].len() - 1;
+
+    (1..=length)
+        .map(|position| {
+            shares.iter().enumerate().fold(0u8, |secret, (j, share)| {
+                let basis = shares.iter().enumerate().filter(|(m, _)| *m != j).fold(1u8, |basis, (_, other)| {
+                    gf_mul(basis, gf_div(other[0], other[0] ^ share[0]))
+                });
+                secret ^ gf_mul(share[position], basis)
+            })
+        })
+        .collect()
+}
+
+/// Multiplies two elements of GF(256) with the AES polynomial
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+/// Divides two elements of GF(256), the divisor must not be zero
+fn gf_div(a: u8, b: u8) -> u8 {
+    // b^254 is the inverse of b since every non-zero element satisfies b^255 = 1
+    let mut inverse = 1;
+    for _ in 0..254 {
+        inverse = gf_mul(inverse, b);
+    }
+    gf_mul(a, inverse)
+}
+
+/// Formats a share as a recovery code
+///
+/// The code is the hex encoded share followed by a short checksum, in dash
+/// separated groups of five characters so it can be written down.
+fn encode_recovery_code(share: &[u8]) -> String {
+    let checksum = Sha256::digest(share);
+    let encoded = hex::encode([share, &checksum[..CHECKSUM_LENGTH]].concat());
+
+    encoded
+        .as_bytes()
+        .chunks(5)
+        .map(|group| std::str::from_utf8(group).expect("hex is ASCII"))
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Parses a recovery code back into its share
+fn decode_recovery_code(code: &str) -> Result<Vec<u8>, BackupError> {
+    let cleaned: String = code
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>()
+        .to_lowercase();
+
+    let bytes = hex::decode(&cleaned).map_err(|e| BackupError::InvalidRecoveryCode(format!("{}: {}", code, e)))?;
+    if bytes.len() != 1 + KEY_LENGTH + CHECKSUM_LENGTH || bytes[0] == 0 {
+        return Err(BackupError::InvalidRecoveryCode(format!("{}: wrong length", code)));
+    }
+
+    let (share, checksum) = bytes.split_at(1 + KEY_LENGTH);
+    if Sha256::digest(share)[..CHECKSUM_LENGTH] != *checksum {
+        return Err(BackupError::InvalidRecoveryCode(format!("{}: checksum mismatch, check for typos", code)));
+    }
+
+    Ok(share.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::crypto::Wallet;
+
+    fn entries() -> Vec<KeystoreEntry> {
+        let wallet = Wallet::new().unwrap();
+        vec![KeystoreEntry {
+            name: "hot".to_string(),
+            address: wallet.address().clone(),
+            secret_key: Some(wallet.export_secret_key()),
+            created_at: Utc::now(),
+        }]
+    }
+
+    #[test]
+    fn test_restore_with_passphrase_or_recovery_codes() {
+        let entries = entries();
+        let (backup, codes) = create_backup(&entries, "correct horse", 3, 5).unwrap();
+        assert_eq!(codes.len(), 5);
+
+        let restored = restore_backup(&backup, &BackupSecret::Passphrase("correct horse".to_string())).unwrap();
+        assert_eq!(restored[0].secret_key, entries[0].secret_key);
+
+        // Any three codes work, in any order
+        let some_codes = vec![codes[4].clone(), codes[0].clone(), codes[2].to_uppercase()];
+        let restored = restore_backup(&backup, &BackupSecret::RecoveryCodes(some_codes)).unwrap();
+        assert_eq!(restored[0].address, entries[0].address);
+
+        assert!(matches!(
+            restore_backup(&backup, &BackupSecret::Passphrase("wrong".to_string())),
+            Err(BackupError::WrongSecret)
+        ));
+        assert!(matches!(
+            restore_backup(&backup, &BackupSecret::RecoveryCodes(vec![codes[0].clone(), codes[1].clone(), codes[1].clone()])),
+            Err(BackupError::InvalidRecoveryCode(_))
+        ));
+    }
+
+    #[test]
+    fn test_foreign_or_mistyped_codes_are_rejected() {
+        let (backup, codes) = create_backup(&entries(), "passphrase", 2, 3).unwrap();
+        let (_, other_codes) = create_backup(&entries(), "passphrase", 2, 3).unwrap();
+
+        // Codes of another backup rebuild a different key
+        let mixed = vec![codes[0].clone(), other_codes[1].clone()];
+        assert!(matches!(
+            restore_backup(&backup, &BackupSecret::RecoveryCodes(mixed)),
+            Err(BackupError::WrongSecret)
+        ));
+
+        // A typo is caught by the checksum
+        let mut typo = codes[0].clone().into_bytes();
+        typo[3] = if typo[3] == b'0' { b'1' } else { b'0' };
+        let typo = String::from_utf8(typo).unwrap();
+        assert!(matches!(
+            restore_backup(&backup, &BackupSecret::RecoveryCodes(vec![typo, codes[1].clone()])),
+            Err(BackupError::InvalidRecoveryCode(_))
+        ));
+    }
+}
@@ -3,6 +3,8 @@ use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use utoipa::ToSchema;
 
+use super::bloom::AddressBloom;
+use super::canonical;
 use super::transaction::Transaction;
 
 /// Represents a block in the blockchain
@@ -38,6 +40,59 @@ fn default_version() -> u32 {
     1
 }
 
+/// The exact bytes a block and its transactions were hashed from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RawBlock {
+    /// Hash of the block
+    pub hash: String,
+
+    /// Canonical JSON the block hash is the SHA-256 hash of
+    pub bytes: Vec<u8>,
+
+    /// The bytes of every transaction, in block order
+    pub transactions: Vec<RawTransaction>,
+}
+
+/// The exact bytes a transaction ID was hashed from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RawTransaction {
+    /// ID of the transaction
+    pub id: String,
+
+    /// Canonical JSON the ID is the SHA-256 hash of, None for random version 1 IDs
+    pub bytes: Option<Vec<u8>>,
+}
+
+/// A block without its transactions, for clients that only follow the chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct BlockHeader {
+    /// Version of the block structure
+    pub version: u32,
+
+    /// Index of the block in the chain
+    pub index: u64,
+
+    /// Timestamp when the block was created
+    #[schema(value_type = String, example = "2023-01-01T12:00:00Z")]
+    pub timestamp: DateTime<Utc>,
+
+    /// Number of transactions in the block
+    pub transaction_count: usize,
+
+    /// Proof of work (nonce)
+    pub proof: u64,
+
+    /// Hash of the previous block
+    pub previous_hash: String,
+
+    /// Hash of the block
+    pub hash: String,
+
+    /// Hex encoded bloom filter of the addresses the block's transactions send from or to
+    #[schema(value_type = String)]
+    pub address_bloom: AddressBloom,
+}
+
 impl Block {
     /// Creates a new block
     ///
@@ -78,23 +133,71 @@ impl Block {
     pub fn calculate_hash(&self) -> String {
         let mut hasher = Sha256::new();
 
-        // Convert the block to a JSON string
+        // Update the hasher with the block data
+        hasher.update(self.canonical_bytes());
+
+        // Return the hash as a hexadecimal string
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Gets the bytes the hash of the block covers
+    ///
+    /// # Returns
+    ///
+    /// The block as canonical JSON
+    pub fn canonical_bytes(&self) -> Vec<u8> {
         let block_data = serde_json::json!({
             "version": self.version,
             "index": self.index,
-            "timestamp": self.timestamp,
-            "transactions": self.transactions,
+            "timestamp": canonical::timestamp(&self.timestamp),
+            "transactions": self.transactions.iter().map(Transaction::hash_json).collect::<Vec<_>>(),
             "proof": self.proof,
             "previous_hash": self.previous_hash,
         });
 
-        let block_string = serde_json::to_string(&block_data).unwrap();
+        canonical::to_vec(&block_data)
+    }
 
-        // Update the hasher with the block data
-        hasher.update(block_string.as_bytes());
+    /// Gets the header of the block
+    ///
+    /// # Arguments
+    ///
+    /// * `address_bloom` - The bloom filter of the block's addresses
+    ///
+    /// # Returns
+    ///
+    /// The header
+    pub fn header(&self, address_bloom: AddressBloom) -> BlockHeader {
+        BlockHeader {
+            version: self.version,
+            index: self.index,
+            timestamp: self.timestamp,
+            transaction_count: self.transactions.len(),
+            proof: self.proof,
+            previous_hash: self.previous_hash.clone(),
+            hash: self.hash.clone(),
+            address_bloom,
+        }
+    }
 
-        // Return the hash as a hexadecimal string
-        format!("{:x}", hasher.finalize())
+    /// Gets the bytes the block and its transactions were hashed from
+    ///
+    /// # Returns
+    ///
+    /// The raw bytes of the block
+    pub fn raw(&self) -> RawBlock {
+        RawBlock {
+            hash: self.hash.clone(),
+            bytes: self.canonical_bytes(),
+            transactions: self
+                .transactions
+                .iter()
+                .map(|transaction| RawTransaction {
+                    id: transaction.id.clone(),
+                    bytes: transaction.id_bytes(),
+                })
+                .collect(),
+        }
     }
 }
 
@@ -130,4 +233,18 @@ mod tests {
         assert!(!hash.is_empty());
         assert_eq!(hash.len(), 64); // SHA-256 hash is 64 characters in hex
     }
+
+    #[test]
+    fn test_block_wire_format_is_stable() {
+        use crate::blockchain::golden;
+
+        let block = golden::sample_block();
+        golden::assert_text("block.json", &serde_json::to_string_pretty(&block).unwrap());
+        golden::assert_text("block.canonical.json", &String::from_utf8(block.canonical_bytes()).unwrap());
+
+        // Blocks served and stored before still decode to the same hash
+        let decoded: Block = serde_json::from_str(&golden::read_text("block.json")).unwrap();
+        assert_eq!(decoded.calculate_hash(), decoded.hash);
+        assert_eq!(decoded.hash, block.hash);
+    }
 }
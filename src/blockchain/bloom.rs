@@ -0,0 +1,185 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+use super::block::Block;
+use super::crypto::Address;
+use super::inheritance;
+
+/// Size of a bloom filter in bytes
+pub const BLOOM_BYTES: usize = 256;
+
+/// Bits set per address
+const BLOOM_HASHES: usize = 3;
+
+/// A bloom filter of the addresses a block touches
+///
+/// Every sender and recipient of the block's transactions sets three of the
+/// 2048 bits, chosen by the SHA-256 hash of the address. A clear bit proves
+/// the block doesn't involve an address, so light clients and watchers can
+/// skip it; all bits set only means it may, and the block has to be fetched
+/// to be sure. The filter is derived from the block and not part of its
+/// hash, so it can be recomputed from any copy of the chain.
+#[derive(Clone, PartialEq, Eq)]
+pub struct AddressBloom([u8; BLOOM_BYTES]);
+
+impl AddressBloom {
+    /// Creates a filter no address is in
+    pub fn new() -> Self {
+        AddressBloom([0; BLOOM_BYTES])
+    }
+
+    /// Builds the filter of a block
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block
+    ///
+    /// # Returns
+    ///
+    /// The filter of the senders, fee payers, owners claimed from and recipients of its transactions
+    pub fn for_block(block: &Block) -> Self {
+        let mut bloom = Self::new();
+        for transaction in &block.transactions {
+            bloom.insert(&transaction.sender);
+            bloom.insert(&transaction.recipient);
+            if let Some(fee_payer) = &transaction.fee_payer {
+                bloom.insert(&fee_payer.address);
+            }
+            if let Some(owner) = inheritance::claimed_owner(transaction) {
+                bloom.insert(&owner);
+            }
+        }
+        bloom
+    }
+
+    /// Adds an address to the filter
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address
+    pub fn insert(&mut self, address: &Address) {
+        for bit in Self::bits(address) {
+            self.0[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    /// Checks if the filter may contain an address
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address
+    ///
+    /// # Returns
+    ///
+    /// false if the address is certainly not in the filter
+    pub fn may_contain(&self, address: &Address) -> bool {
+        Self::bits(address).iter().all(|bit| self.0[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Decodes a filter from hex
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The hex encoded filter
+    ///
+    /// # Returns
+    ///
+    /// The filter, or an error if the value is not hex of the filter's size
+    pub fn from_hex(value: &str) -> Result<Self, String> {
+        let bytes = hex::decode(value).map_err(|err| format!("Invalid bloom filter: {}", err))?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Reads a filter from its raw bytes
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - The bytes, as returned by `as_bytes`
+    ///
+    /// # Returns
+    ///
+    /// The filter, or an error if the bytes are not of the filter's size
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        let bytes: [u8; BLOOM_BYTES] = bytes
+            .try_into()
+            .map_err(|_| format!("Bloom filter has {} bytes, expected {}", bytes.len(), BLOOM_BYTES))?;
+        Ok(AddressBloom(bytes))
+    }
+
+    /// Gets the raw bytes of the filter
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// The bits an address sets, from the first bytes of its hash
+    fn bits(address: &Address) -> [usize; BLOOM_HASHES] {
+        let hash = Sha256::digest(address.0.as_bytes());
+        let mut bits = [0; BLOOM_HASHES];
+        for (i, bit) in bits.iter_mut().enumerate() {
+            *bit = u16::from_be_bytes([hash[2 * i], hash[2 * i + 1]]) as usize % (BLOOM_BYTES * 8);
+        }
+        bits
+    }
+}
+
+impl Default for AddressBloom {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for AddressBloom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+impl fmt::Debug for AddressBloom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "AddressBloom({})", self)
+    }
+}
+
+impl Serialize for AddressBloom {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for AddressBloom {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        Self::from_hex(&value).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::transaction::Transaction;
+
+    #[test]
+    fn test_bloom_has_no_false_negatives() {
+        let transactions = vec![
+            Transaction::new(Address("alice".to_string()), Address("bob".to_string()), 1.0, 0.1, 0),
+            Transaction::new(Address("carol".to_string()), Address("alice".to_string()), 2.0, 0.1, 0),
+        ];
+        let block = Block::new(1, transactions, 0, "0".to_string());
+        let bloom = AddressBloom::for_block(&block);
+
+        for name in ["alice", "bob", "carol"] {
+            assert!(bloom.may_contain(&Address(name.to_string())));
+        }
+        let misses = (0..100)
+            .filter(|i| bloom.may_contain(&Address(format!("stranger-{}", i))))
+            .count();
+        assert!(misses < 5, "{} false positives", misses);
+        assert!(!AddressBloom::new().may_contain(&Address("alice".to_string())));
+
+        let json = serde_json::to_string(&bloom).unwrap();
+        assert_eq!(json.len(), BLOOM_BYTES * 2 + 2);
+        assert_eq!(serde_json::from_str::<AddressBloom>(&json).unwrap(), bloom);
+        assert!(AddressBloom::from_hex("00ff").is_err());
+    }
+}
@@ -0,0 +1,282 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use super::chain::{Blockchain, BlockchainError, DIFFICULTY};
+use super::crypto::Address;
+use super::fees::FeePolicy;
+use super::genesis::GenesisConfig;
+use super::hooks::ValidationHook;
+use super::observers::ChainObserver;
+use super::permissions::PermissionPolicy;
+use super::storage::StorageOptions;
+
+/// How the blocks of a node are sealed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Consensus {
+    /// Proof of work, the hex hash of a block starts with `difficulty` zeros
+    PoW { difficulty: u8 },
+}
+
+impl Default for Consensus {
+    fn default() -> Self {
+        Consensus::PoW { difficulty: DIFFICULTY }
+    }
+}
+
+/// Assembles a node from its parts
+///
+/// Anything not set is what `Blockchain::new` would use: no storage, the
+/// default network, proof of work at `DIFFICULTY` on one thread per CPU
+/// core, the default fee policy, an open permission policy and no miner
+/// address.
+///
+/// ```no_run
+/// use my_blockchain::blockchain::builder::{Consensus, NodeBuilder};
+/// use my_blockchain::blockchain::genesis::GenesisConfig;
+/// use my_blockchain::blockchain::Address;
+///
+/// let node = NodeBuilder::new()
+///     .with_storage("data")
+///     .with_consensus(Consensus::PoW { difficulty: 4 })
+///     .with_genesis(GenesisConfig::from_file("genesis.json").unwrap())
+///     .with_miner(Address("miner".to_string()))
+///     .build()
+///     .unwrap();
+/// node.mine().unwrap();
+/// ```
+#[derive(Clone)]
+pub struct NodeBuilder {
+    storage: Option<(PathBuf, StorageOptions)>,
+    consensus: Consensus,
+    genesis: GenesisConfig,
+    miner: Option<Address>,
+    fee_policy: FeePolicy,
+    permission_policy: PermissionPolicy,
+    follower: bool,
+    randomize_proof_of_work: bool,
+    mining_threads: usize,
+    observers: Vec<Arc<dyn ChainObserver>>,
+    hooks: Vec<Arc<dyn ValidationHook>>,
+}
+
+impl Default for NodeBuilder {
+    fn default() -> Self {
+        NodeBuilder {
+            storage: None,
+            consensus: Consensus::default(),
+            genesis: GenesisConfig::default(),
+            miner: None,
+            fee_policy: FeePolicy::default(),
+            permission_policy: PermissionPolicy::default(),
+            follower: false,
+            randomize_proof_of_work: true,
+            mining_threads: 0,
+            observers: Vec::new(),
+            hooks: Vec::new(),
+        }
+    }
+}
+
+impl NodeBuilder {
+    /// Starts a node with the defaults of `Blockchain::new`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps the chain in a data directory, see `Blockchain::open`
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The data directory
+    pub fn with_storage(self, path: impl Into<PathBuf>) -> Self {
+        self.with_storage_options(path, StorageOptions::default())
+    }
+
+    /// Keeps the chain in a data directory opened with options
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The data directory
+    /// * `options` - The storage options
+    pub fn with_storage_options(mut self, path: impl Into<PathBuf>, options: StorageOptions) -> Self {
+        self.storage = Some((path.into(), options));
+        self
+    }
+
+    /// Sets how blocks are sealed, see `Blockchain::set_difficulty`
+    ///
+    /// # Arguments
+    ///
+    /// * `consensus` - The consensus
+    pub fn with_consensus(mut self, consensus: Consensus) -> Self {
+        self.consensus = consensus;
+        self
+    }
+
+    /// Sets the network the node belongs to
+    ///
+    /// # Arguments
+    ///
+    /// * `genesis` - The network, e.g. read with `GenesisConfig::from_file`
+    pub fn with_genesis(mut self, genesis: GenesisConfig) -> Self {
+        self.genesis = genesis;
+        self
+    }
+
+    /// Sets the address the blocks mined with `Blockchain::mine` pay
+    ///
+    /// # Arguments
+    ///
+    /// * `miner` - The address
+    pub fn with_miner(mut self, miner: Address) -> Self {
+        self.miner = Some(miner);
+        self
+    }
+
+    /// Sets where the fees of new blocks go, see `Blockchain::set_fee_policy`
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The fee policy
+    pub fn with_fee_policy(mut self, policy: FeePolicy) -> Self {
+        self.fee_policy = policy;
+        self
+    }
+
+    /// Restricts who may send transactions and mine, see `Blockchain::set_permission_policy`
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The permission policy
+    pub fn with_permission_policy(mut self, policy: PermissionPolicy) -> Self {
+        self.permission_policy = policy;
+        self
+    }
+
+    /// Makes the node a read-only follower, see `Blockchain::set_follower`
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether the node only follows
+    pub fn as_follower(mut self, enabled: bool) -> Self {
+        self.follower = enabled;
+        self
+    }
+
+    /// Sets whether proofs are searched from a random start, see `Blockchain::set_randomized_proof_of_work`
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether to randomize the search
+    pub fn with_randomized_proof_of_work(mut self, enabled: bool) -> Self {
+        self.randomize_proof_of_work = enabled;
+        self
+    }
+
+    /// Sets how many threads search for a proof of work, see `Blockchain::set_mining_threads`
+    ///
+    /// # Arguments
+    ///
+    /// * `threads` - Number of threads, 0 for one per CPU core
+    pub fn with_mining_threads(mut self, threads: usize) -> Self {
+        self.mining_threads = threads;
+        self
+    }
+
+    /// Adds a component to follow the head of the chain, see `Blockchain::add_observer`
+    ///
+    /// # Arguments
+    ///
+    /// * `observer` - The observer, called after the ones added before
+    pub fn with_observer(mut self, observer: Arc<dyn ChainObserver>) -> Self {
+        self.observers.push(observer);
+        self
+    }
+
+    /// Adds a custom admission rule, see `Blockchain::add_validation_hook`
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - The hook, run after the ones added before
+    pub fn with_validation_hook(mut self, hook: Arc<dyn ValidationHook>) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    /// Creates the node, opening or creating its storage
+    ///
+    /// # Returns
+    ///
+    /// Result with the node, a StorageError::NetworkMismatch if the data directory belongs to another network
+    pub fn build(self) -> Result<Blockchain, BlockchainError> {
+        let mut blockchain = match self.storage {
+            Some((path, options)) => Blockchain::open(path, options, self.genesis)?,
+            None => Blockchain::with_genesis(self.genesis),
+        };
+
+        match self.consensus {
+            Consensus::PoW { difficulty } => blockchain.set_difficulty(difficulty),
+        }
+        blockchain.set_randomized_proof_of_work(self.randomize_proof_of_work);
+        blockchain.set_mining_threads(self.mining_threads);
+        blockchain.set_follower(self.follower);
+        blockchain.set_fee_policy(self.fee_policy);
+        blockchain.set_miner_address(self.miner);
+        blockchain.set_permission_policy(self.permission_policy);
+        for observer in self.observers {
+            blockchain.add_observer(observer);
+        }
+        for hook in self.hooks {
+            blockchain.add_validation_hook(hook);
+        }
+
+        Ok(blockchain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Wallet;
+
+    #[test]
+    fn test_builder_assembles_the_node() {
+        let miner = Wallet::new().unwrap();
+        let genesis = GenesisConfig {
+            network: "classroom".to_string(),
+            ..GenesisConfig::default()
+        };
+        let node = NodeBuilder::new()
+            .with_consensus(Consensus::PoW { difficulty: 1 })
+            .with_genesis(genesis.clone())
+            .with_miner(miner.address().clone())
+            .with_fee_policy(FeePolicy::Burn)
+            .build()
+            .unwrap();
+
+        assert_eq!(node.get_network(), genesis.info());
+        let block = node.mine().unwrap();
+        assert!(block.hash.starts_with('0'));
+        assert_eq!(block.transactions[0].recipient, *miner.address());
+        assert!(node.is_valid());
+
+        // Without a miner address only mine_block mines
+        let node = NodeBuilder::new().as_follower(true).build().unwrap();
+        assert!(node.is_follower());
+        assert!(matches!(node.mine(), Err(BlockchainError::InvalidBlock(_))));
+    }
+
+    #[test]
+    fn test_builder_refuses_the_storage_of_another_network() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let node = NodeBuilder::new().with_storage(data_dir.path()).build().unwrap();
+        drop(node);
+
+        let classroom = GenesisConfig {
+            network: "classroom".to_string(),
+            ..GenesisConfig::default()
+        };
+        let reopened = NodeBuilder::new().with_storage(data_dir.path()).with_genesis(classroom).build();
+        assert!(matches!(reopened, Err(BlockchainError::StorageError(_))));
+    }
+}
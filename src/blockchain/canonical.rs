@@ -0,0 +1,266 @@
+// Canonical JSON for hashing and signing
+//
+// Block hashes, transaction IDs and transaction signatures cover JSON text,
+// so the exact bytes must never change, whatever serde_json does in a later
+// release or with other features enabled. The canonical form is:
+//
+// - No whitespace between tokens
+// - Object keys sorted by their UTF-8 bytes, each key once
+// - Array elements in their given order
+// - `null`, `true` and `false` as is
+// - Integers in decimal without leading zeros or a plus sign
+// - Other numbers as the shortest text that reads back as the same f64
+//   (the Ryu algorithm): at least one digit after the point (`50.0`), an
+//   exponent from 1e16 or below 1e-5 (`1e16`, `1.5e-7`), and `-0.0`
+// - Strings in double quotes, escaping `"` and `\`, writing `\b`, `\f`,
+//   `\n`, `\r` and `\t` for those control characters and `\u00xx` with
+//   lowercase hex for the other ones below 0x20; everything else, including
+//   non-ASCII characters, is written as UTF-8
+// - Timestamps as RFC 3339 strings in UTC with a `Z`, with 0, 3, 6 or 9
+//   digits of fractional seconds, whichever is the fewest without losing
+//   precision
+//
+// This is the form serde_json produced when hashes were first computed, so
+// existing hashes stay the same. Later serde_json releases write exponents
+// as `1e+16`, which is why the form is written out here.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{Number, Value};
+
+/// Writes a JSON value in canonical form
+///
+/// # Arguments
+///
+/// * `value` - The value to write
+///
+/// # Returns
+///
+/// The canonical JSON text
+pub fn to_string(value: &Value) -> String {
+    let mut out = String::new();
+    write_value(&mut out, value);
+    out
+}
+
+/// Writes a JSON value in canonical form as bytes
+///
+/// # Arguments
+///
+/// * `value` - The value to write
+///
+/// # Returns
+///
+/// The UTF-8 bytes of the canonical JSON text
+pub fn to_vec(value: &Value) -> Vec<u8> {
+    to_string(value).into_bytes()
+}
+
+/// Rewrites JSON text in canonical form
+///
+/// Helps to tell whether bytes that do not hash as expected are the right
+/// data written differently.
+///
+/// # Arguments
+///
+/// * `bytes` - The JSON text
+///
+/// # Returns
+///
+/// The canonical bytes of the same value, or None if the bytes are not JSON
+pub fn canonicalize(bytes: &[u8]) -> Option<Vec<u8>> {
+    serde_json::from_slice::<Value>(bytes).ok().map(|value| to_vec(&value))
+}
+
+/// Converts a timestamp to its canonical JSON string
+///
+/// # Arguments
+///
+/// * `timestamp` - The timestamp to convert
+///
+/// # Returns
+///
+/// The timestamp as a JSON string
+pub fn timestamp(timestamp: &DateTime<Utc>) -> Value {
+    Value::String(timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+}
+
+fn write_value(out: &mut String, value: &Value) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(true) => out.push_str("true"),
+        Value::Bool(false) => out.push_str("false"),
+        Value::Number(number) => write_number(out, number),
+        Value::String(string) => write_string(out, string),
+        Value::Array(values) => {
+            out.push('[');
+            for (position, value) in values.iter().enumerate() {
+                if position > 0 {
+                    out.push(',');
+                }
+                write_value(out, value);
+            }
+            out.push(']');
+        }
+        Value::Object(fields) => {
+            let mut fields: Vec<(&String, &Value)> = fields.iter().collect();
+            fields.sort_by(|(a, _), (b, _)| a.as_bytes().cmp(b.as_bytes()));
+
+            out.push('{');
+            for (position, (key, value)) in fields.into_iter().enumerate() {
+                if position > 0 {
+                    out.push(',');
+                }
+                write_string(out, key);
+                out.push(':');
+                write_value(out, value);
+            }
+            out.push('}');
+        }
+    }
+}
+
+fn write_number(out: &mut String, number: &Number) {
+    if let Some(integer) = number.as_u64() {
+        out.push_str(&integer.to_string());
+    } else if let Some(integer) = number.as_i64() {
+        out.push_str(&integer.to_string());
+    } else {
+        match number.as_f64() {
+            Some(float) if float.is_finite() => out.push_str(ryu::Buffer::new().format_finite(float)),
+            // JSON has no infinities or NaN
+            _ => out.push_str("null"),
+        }
+    }
+}
+
+fn write_string(out: &mut String, string: &str) {
+    const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+    out.push('"');
+    for character in string.chars() {
+        match character {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{08}' => out.push_str("\\b"),
+            '\u{0c}' => out.push_str("\\f"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            control if (control as u32) < 0x20 => {
+                let byte = control as usize;
+                out.push_str("\\u00");
+                out.push(HEX_DIGITS[byte >> 4] as char);
+                out.push(HEX_DIGITS[byte & 0xf] as char);
+            }
+            other => out.push(other),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::{Address, Block, Transaction, Wallet};
+    use serde_json::json;
+
+    #[test]
+    fn test_canonical_form() {
+        // Keys are sorted however the map was built, and nested values follow
+        let mut fields = serde_json::Map::new();
+        fields.insert("b".to_string(), json!([true, false, null, [], {}]));
+        fields.insert("a".to_string(), json!({"z": 1, "Z": -1, "é": 0, "aa": "x"}));
+        fields.insert("".to_string(), json!("empty key"));
+        assert_eq!(
+            to_string(&Value::Object(fields)),
+            r#"{"":"empty key","a":{"Z":-1,"aa":"x","z":1,"é":0},"b":[true,false,null,[],{}]}"#
+        );
+
+        // Integers keep every digit, other numbers take the shortest form
+        let numbers = [
+            (json!(0), "0"),
+            (json!(u64::MAX), "18446744073709551615"),
+            (json!(i64::MIN), "-9223372036854775808"),
+            (json!(0.0), "0.0"),
+            (json!(-0.0), "-0.0"),
+            (json!(50.0), "50.0"),
+            (json!(0.1), "0.1"),
+            (json!(0.1 + 0.2), "0.30000000000000004"),
+            (json!(1.5e-7), "1.5e-7"),
+            (json!(0.0001), "0.0001"),
+            (json!(1e15), "1000000000000000.0"),
+            (json!(1e16), "1e16"),
+            (json!(f64::MAX), "1.7976931348623157e308"),
+            (json!(f64::MIN_POSITIVE), "2.2250738585072014e-308"),
+            (json!(5e-324), "5e-324"),
+        ];
+        for (value, expected) in &numbers {
+            assert_eq!(to_string(value), *expected);
+        }
+
+        // Every control character, the escaped characters and non-ASCII text
+        let mut text: String = (0u8..0x20).map(char::from).collect();
+        text.push_str("\"\\/\u{7f}é☕𝄞");
+        assert_eq!(
+            to_string(&json!(text)),
+            concat!(
+                r#""\u0000\u0001\u0002\u0003\u0004\u0005\u0006\u0007\b\t\n\u000b\f\r\u000e\u000f"#,
+                r#"\u0010\u0011\u0012\u0013\u0014\u0015\u0016\u0017\u0018\u0019\u001a\u001b\u001c\u001d\u001e\u001f"#,
+                "\\\"\\\\/\u{7f}é☕𝄞\""
+            )
+        );
+
+        // Timestamps keep only the fractional digits they need
+        for (time, expected) in [
+            ("2024-05-01T12:34:56Z", "2024-05-01T12:34:56Z"),
+            ("2024-05-01T12:34:56.780Z", "2024-05-01T12:34:56.780Z"),
+            ("2024-05-01T12:34:56.000001Z", "2024-05-01T12:34:56.000001Z"),
+            ("2024-05-01T14:34:56.123456789+02:00", "2024-05-01T12:34:56.123456789Z"),
+        ] {
+            let time = DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc);
+            assert_eq!(timestamp(&time), json!(expected));
+            assert_eq!(timestamp(&time), serde_json::to_value(time).unwrap());
+        }
+
+        // The same text any serde_json release writes, apart from positive exponents
+        let plain: Vec<&Value> = numbers
+            .iter()
+            .filter(|(_, text)| !text.contains('e') || text.contains("e-"))
+            .map(|(value, _)| value)
+            .collect();
+        let everything = json!({"numbers": plain, "text": text});
+        assert_eq!(to_string(&everything), serde_json::to_string(&everything).unwrap());
+
+        // Pretty or reordered text of the same value canonicalizes to the same bytes
+        let pretty = serde_json::to_vec_pretty(&everything).unwrap();
+        assert_eq!(canonicalize(&pretty), Some(to_vec(&everything)));
+        assert_eq!(canonicalize(b"{\"b\": 1.50, \"a\": [ 1e2 ]}"), Some(b"{\"a\":[100.0],\"b\":1.5}".to_vec()));
+        assert_eq!(canonicalize(b"not json"), None);
+    }
+
+    #[test]
+    fn test_hashes_are_pinned() {
+        let timestamp = DateTime::parse_from_rfc3339("2024-05-01T12:34:56.789Z").unwrap().with_timezone(&Utc);
+        let wallet = Wallet::from_secret_key(&[7u8; 32]).unwrap();
+
+        let mut transfer = Transaction::new(wallet.address().clone(), Address("bob \"b\"\n".to_string()), 1.5, 0.1, 4);
+        transfer.timestamp = timestamp;
+        transfer.sign(&wallet).unwrap();
+
+        let mut coinbase = Transaction::new_coinbase(Address("miner".to_string()), 50.0);
+        coinbase.timestamp = timestamp;
+        let coinbase = coinbase.with_extra_nonce(9).with_message("gm ☕");
+
+        let mut block = Block::new(3, vec![transfer.clone(), coinbase.clone()], 12345, "00ab".to_string());
+        block.timestamp = timestamp;
+
+        // Computed before hashing moved to this module, these must never change
+        assert_eq!(transfer.id, "d41175d94fdfa5fce5284556d9141679264a332d24ba2e9a230c9a5a39ecef37");
+        assert_eq!(
+            transfer.signature.unwrap().0,
+            "dwFhi1s7jWg2CsPAfCHE2pyGw7HB3MpT2XkKgV6ii4Neg5837DN8iudZXVTViBiZMkAQFPsabeWJ7g38dAg3YDU"
+        );
+        assert_eq!(coinbase.id, "c88f128eba4f858090fc761c5e52d8cfb244a06df5f636a1db8b93dd8c081bbc");
+        assert_eq!(block.calculate_hash(), "79fcffea9629e39d79deb6f67d79a4012b000367139e9d8fb7cad1d92445e713");
+    }
+}
@@ -1,12 +1,67 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use tokio::sync::broadcast;
 use thiserror::Error;
-use log::{error, info, warn};
+use log::{info, warn};
 
-use super::account::{AccountState, AccountError};
-use super::block::Block;
+use super::account::{Account, AccountState, AccountError};
+use super::anchors::{AnchorError, AnchorProof};
+use super::block::{Block, BlockHeader, RawBlock};
+use super::bloom::AddressBloom;
 use super::crypto::Address;
-use super::transaction::{Transaction, TransactionError};
-use super::storage::{BlockchainStorage, StorageError};
+use super::economics::EconomicsReport;
+use super::errors::{ErrorCode, ErrorContext};
+use super::events::{ChainEvent, EventBus, ReorgEvent};
+use super::faucet::{FaucetGrant, FaucetGrants};
+use super::governance::{ChainParameters, Governance, GovernanceError, ParameterChange, Proposal};
+use super::history::{BlockRef, StateCheckpoints};
+use super::hooks::{HookError, ValidationHook, ValidationHooks};
+use super::mining::{CancellationToken, MiningSearches};
+use super::index::{CounterpartySummary, TransactionFilter, TransactionIndex, TransactionPage};
+use super::maintenance::MaintenanceStatus;
+use super::mempool::{
+    self, Admission, Arrivals, MempoolJournalEntry, MiningTemplate, PendingTransaction, SeenTransactions,
+    MAX_QUEUED_PER_SENDER,
+};
+use super::names::{self, NameError, NameRecord, NameRegistry};
+use super::observers::{ChainObserver, GovernanceObserver, Observers, StorageObserver};
+use super::fees::{self, BlockFees, FeeEstimate, FeePolicy, SupplyReport};
+use super::ledger::Ledger;
+use super::forks::{BlockOutcome, ForkStore, MAX_FORK_BLOCKS, MAX_FORK_DEPTH};
+use super::dashboard::{BlockIntervals, Dashboard, FeeTrend, IssuanceRate, MempoolStatus, NetworkStatus, TipStatus};
+use super::genesis::{GenesisConfig, NetworkInfo, NetworkMagic};
+use super::peers::{PeerInfo, PeerSet};
+use super::permissions::{FreezeRecord, PermissionError, PermissionPolicy, PermissionStatus, Permissions};
+use super::rebroadcast::{BroadcastRecord, Broadcasts, RebroadcastPolicy};
+use super::reports::{ActivityPeriod, ActivityReports, Granularity};
+use super::scripts::{ScriptError, ScriptLock, ScriptLocks};
+use super::sessions::{self, SessionAction, SessionError, SessionKey, SessionKeys};
+use super::inheritance::{self, InheritanceError, InheritanceSwitch, Inheritances};
+use super::simulation::{ConsensusRules, DEFAULT_TARGET_INTERVAL_SECS};
+use super::state::ChainState;
+use super::transaction::{Transaction, TransactionError, MAX_COINBASE_MESSAGE_BYTES};
+use super::treasury::{TreasuryStatus, TREASURY_ADDRESS};
+use super::storage::{BlockchainStorage, StorageError, StorageHealth, StorageOptions};
+use super::storage_writer::{StorageWriter, WRITE_QUEUE_CAPACITY};
+use super::validation::{self, ValidationError, ValidationRules};
+use super::versioning::VersionError;
+
+/// Number of leading zeros required in the hash of a mined block
+pub const DIFFICULTY: u8 = 4;
+
+/// Coins paid to the miner of a block
+pub const MINING_REWARD: f64 = 50.0;
+
+/// Largest number of headers asked from a peer at once while syncing
+pub const MAX_SYNC_HEADERS: usize = 512;
+
+/// Largest number of blocks asked from a peer at once while syncing
+pub const MAX_SYNC_BLOCKS: usize = 64;
 
 /// Errors that can occur during blockchain operations
 #[derive(Debug, Error)]
@@ -26,11 +81,227 @@ pub enum BlockchainError {
     #[error("Invalid chain: {0}")]
     InvalidChain(String),
 
+    #[error("Block not found: {0}")]
+    BlockNotFound(String),
+
+    #[error("Version error: {0}")]
+    VersionError(#[from] VersionError),
+
+    #[error("Name error: {0}")]
+    NameError(#[from] NameError),
+
+    #[error("Anchor error: {0}")]
+    AnchorError(#[from] AnchorError),
+
+    #[error("Permission error: {0}")]
+    PermissionError(#[from] PermissionError),
+
+    #[error("Governance error: {0}")]
+    GovernanceError(#[from] GovernanceError),
+
+    #[error("Script error: {0}")]
+    ScriptError(#[from] ScriptError),
+
+    #[error("Session error: {0}")]
+    SessionError(#[from] SessionError),
+
+    #[error("Inheritance error: {0}")]
+    InheritanceError(#[from] InheritanceError),
+
+    #[error("Hook error: {0}")]
+    HookError(#[from] HookError),
+
+    #[error("Node is under maintenance: {0}")]
+    Paused(String),
+
+    #[error("Node is a read-only follower")]
+    ReadOnly,
+
+    #[error("Mining of block {height} was cancelled, block {tip} took the tip first")]
+    MiningCancelled { height: u64, tip: String },
+
     #[error("System error: {0}")]
     SystemError(String),
 }
 
-/// Represents the blockchain
+impl From<ValidationError> for BlockchainError {
+    fn from(err: ValidationError) -> Self {
+        match err {
+            ValidationError::TransactionError(err) => BlockchainError::TransactionError(err),
+            ValidationError::AccountError(err) => BlockchainError::AccountError(err),
+            ValidationError::VersionError(err) => BlockchainError::VersionError(err),
+            ValidationError::NameError(err) => BlockchainError::NameError(err),
+            ValidationError::AnchorError(err) => BlockchainError::AnchorError(err),
+            ValidationError::PermissionError(err) => BlockchainError::PermissionError(err),
+            ValidationError::GovernanceError(err) => BlockchainError::GovernanceError(err),
+            ValidationError::ScriptError(err) => BlockchainError::ScriptError(err),
+            ValidationError::SessionError(err) => BlockchainError::SessionError(err),
+            ValidationError::InheritanceError(err) => BlockchainError::InheritanceError(err),
+            ValidationError::InvalidBlock(message) => BlockchainError::InvalidBlock(message),
+            ValidationError::InvalidChain(message) => BlockchainError::InvalidChain(message),
+        }
+    }
+}
+
+impl BlockchainError {
+    /// Gets the stable code of the error, the one clients branch on
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            BlockchainError::TransactionError(err) => match err {
+                TransactionError::InvalidSignature | TransactionError::InvalidFeePayerSignature => {
+                    ErrorCode::InvalidSignature
+                }
+                TransactionError::InsufficientFunds { .. } => ErrorCode::InsufficientFunds,
+                TransactionError::FeeTooLow { .. } | TransactionError::ReplacementUnderpriced { .. } => {
+                    ErrorCode::FeeTooLow
+                }
+                TransactionError::TooManyQueued(_) => ErrorCode::InvalidNonce,
+                TransactionError::SystemError(_) => ErrorCode::Internal,
+                _ => ErrorCode::InvalidTransaction,
+            },
+            BlockchainError::AccountError(err) => match err {
+                AccountError::InsufficientFunds { .. } => ErrorCode::InsufficientFunds,
+                AccountError::InvalidNonce { .. } => ErrorCode::InvalidNonce,
+                AccountError::AccountNotFound(_) => ErrorCode::NotFound,
+                AccountError::SystemError(_) => ErrorCode::Internal,
+                AccountError::InvalidAmount(_) => ErrorCode::InvalidTransaction,
+            },
+            BlockchainError::StorageError(err) => err.code(),
+            BlockchainError::SystemError(_) => ErrorCode::Internal,
+            BlockchainError::InvalidBlock(_) => ErrorCode::InvalidBlock,
+            BlockchainError::InvalidChain(_) => ErrorCode::InvalidChain,
+            BlockchainError::BlockNotFound(_) => ErrorCode::NotFound,
+            BlockchainError::VersionError(_) => ErrorCode::UnsupportedVersion,
+            BlockchainError::NameError(err) => match err {
+                NameError::Taken { .. } | NameError::Pending(_) => ErrorCode::NameTaken,
+                NameError::NotRegistered(_) => ErrorCode::NotFound,
+                NameError::InvalidName(_) | NameError::InvalidRegistration(_) => ErrorCode::InvalidName,
+            },
+            BlockchainError::AnchorError(_) => ErrorCode::InvalidAnchor,
+            BlockchainError::PermissionError(err) => match err {
+                PermissionError::NotPermitted { .. } => ErrorCode::NotPermitted,
+                PermissionError::Frozen(_) => ErrorCode::AccountFrozen,
+                PermissionError::InvalidChange(_) => ErrorCode::InvalidRequest,
+            },
+            BlockchainError::GovernanceError(err) => match err {
+                GovernanceError::ProposalNotFound(_) => ErrorCode::NotFound,
+                _ => ErrorCode::InvalidProposal,
+            },
+            BlockchainError::ScriptError(err) => match err {
+                ScriptError::LockNotFound(_) => ErrorCode::NotFound,
+                _ => ErrorCode::InvalidScript,
+            },
+            BlockchainError::SessionError(_) => ErrorCode::InvalidSession,
+            BlockchainError::InheritanceError(err) => match err {
+                InheritanceError::NotDesignated(_) => ErrorCode::NotFound,
+                _ => ErrorCode::InvalidInheritance,
+            },
+            BlockchainError::HookError(_) => ErrorCode::Rejected,
+            BlockchainError::Paused(_) => ErrorCode::Paused,
+            BlockchainError::ReadOnly => ErrorCode::ReadOnly,
+            BlockchainError::MiningCancelled { .. } => ErrorCode::StaleTip,
+        }
+    }
+
+    /// Checks whether the same call may succeed later, unchanged
+    ///
+    /// Besides the codes that are retryable, errors waiting on another
+    /// transaction to be mined or on the storage are.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            BlockchainError::TransactionError(TransactionError::TooManyQueued(_))
+            | BlockchainError::NameError(NameError::Pending(_))
+            | BlockchainError::ScriptError(ScriptError::Pending(_))
+            | BlockchainError::InheritanceError(InheritanceError::Pending(_)) => true,
+            BlockchainError::StorageError(err) => err.is_retryable(),
+            err => err.code().is_retryable(),
+        }
+    }
+
+    /// Gets the block, transaction or address the error is about, for the fields the error knows
+    pub fn context(&self) -> ErrorContext {
+        match self {
+            BlockchainError::TransactionError(
+                TransactionError::TooManyQueued(address)
+                | TransactionError::InvalidSenderAddress(address)
+                | TransactionError::InvalidRecipientAddress(address)
+                | TransactionError::InvalidFeePayer(address),
+            )
+            | BlockchainError::AccountError(AccountError::AccountNotFound(address))
+            | BlockchainError::PermissionError(
+                PermissionError::NotPermitted { address, .. } | PermissionError::Frozen(address),
+            )
+            | BlockchainError::SessionError(
+                SessionError::NotAuthorized { owner: address, .. } | SessionError::Expired { owner: address, .. },
+            )
+            | BlockchainError::InheritanceError(
+                InheritanceError::NotDesignated(address)
+                | InheritanceError::OwnerActive { owner: address, .. }
+                | InheritanceError::Pending(address),
+            ) => ErrorContext::address(address.clone()),
+            BlockchainError::VersionError(VersionError::Block { height, .. }) => ErrorContext::height(*height),
+            BlockchainError::VersionError(VersionError::Transaction { id, height, .. }) => {
+                ErrorContext::transaction(id.clone(), Some(*height))
+            }
+            BlockchainError::HookError(HookError::TransactionRejected { transaction, .. })
+            | BlockchainError::TransactionError(TransactionError::MinerOnly(transaction)) => {
+                ErrorContext::transaction(transaction.clone(), None)
+            }
+            BlockchainError::HookError(HookError::BlockRejected { index, .. })
+            | BlockchainError::MiningCancelled { height: index, .. } => ErrorContext::height(*index),
+            _ => ErrorContext::default(),
+        }
+    }
+}
+
+/// A peer the node catches up from, see `Blockchain::sync_from`
+#[async_trait]
+pub trait SyncPeer: Send + Sync {
+    /// Gets the headers after the most recent block of a locator the peer has
+    ///
+    /// # Arguments
+    ///
+    /// * `locator` - Hashes of the node's chain, tip first, see `Blockchain::get_block_locator`
+    /// * `limit` - The maximum number of headers to return
+    ///
+    /// # Returns
+    ///
+    /// The headers oldest first, empty if the peer shares no block of the locator or has none after it
+    async fn get_headers(&self, locator: Vec<String>, limit: usize) -> Result<Vec<BlockHeader>, BlockchainError>;
+
+    /// Gets blocks of the peer's chain by their hashes
+    ///
+    /// # Arguments
+    ///
+    /// * `hashes` - The hashes of the blocks
+    ///
+    /// # Returns
+    ///
+    /// The blocks in the order of the hashes
+    async fn get_blocks(&self, hashes: Vec<String>) -> Result<Vec<Block>, BlockchainError>;
+}
+
+/// What a sync with a peer changed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyncReport {
+    /// Headers the peer sent that passed validation
+    pub headers: usize,
+
+    /// Blocks applied to the chain
+    pub blocks: usize,
+
+    /// Reorganizations onto the peer's branch
+    pub reorgs: Vec<ReorgEvent>,
+
+    /// Height of the chain after the sync
+    pub height: u64,
+}
+
+/// The node: the live chain with its storage, mempool, miner and observers
+///
+/// The consensus rules themselves live in `validation` as free functions,
+/// and a new chain state is built with `ChainState`, neither of which needs
+/// storage or a running server.
 #[derive(Debug, Clone)]
 pub struct Blockchain {
     /// The chain of blocks
@@ -39,38 +310,164 @@ pub struct Blockchain {
     /// Pending transactions to be included in the next block
     pending_transactions: Arc<Mutex<Vec<Transaction>>>,
 
+    /// Transactions waiting for an earlier nonce of their sender, locked after the pending ones
+    queued_transactions: Arc<Mutex<Vec<Transaction>>>,
+
     /// Account state
     account_state: Arc<AccountState>,
 
-    /// Mining difficulty (number of leading zeros required in hash)
-    difficulty: u8,
-
-    /// Mining reward
-    mining_reward: f64,
-
-    /// Minimum transaction fee
-    minimum_fee: f64,
+    /// Rules blocks and transactions are validated with, before parameter changes
+    rules: ValidationRules,
 
     /// Storage for blockchain data
     storage: Option<Arc<BlockchainStorage>>,
+
+    /// Writes blocks, accounts and the mempool to the storage off the chain locks
+    storage_writer: Option<StorageWriter>,
+
+    /// Activity aggregates, updated as blocks are committed
+    activity: Arc<ActivityReports>,
+
+    /// Secondary indexes over the confirmed transactions
+    index: Arc<TransactionIndex>,
+
+    /// Names registered on the chain
+    names: Arc<NameRegistry>,
+
+    /// Addresses allowed to send transactions and mine
+    permissions: Arc<Permissions>,
+
+    /// Proposals and votes on chain parameters
+    governance: Arc<Governance>,
+
+    /// Deposits of the faucet, which replays of the chain credit
+    faucet: Arc<FaucetGrants>,
+
+    /// Amounts locked under spending conditions
+    scripts: Arc<ScriptLocks>,
+
+    /// Keys accounts authorized to sign their transfers within limits
+    sessions: Arc<SessionKeys>,
+
+    /// Beneficiaries that may claim the funds of accounts gone quiet
+    inheritances: Arc<Inheritances>,
+
+    /// Accounts at checkpoint heights, to rebuild historical states from
+    checkpoints: Arc<StateCheckpoints>,
+
+    /// Base fee of the block after the tip, with the hash of that tip
+    base_fee: Arc<Mutex<Option<(String, f64)>>>,
+
+    /// Past chain reorganizations
+    reorgs: Arc<Mutex<Vec<ReorgEvent>>>,
+
+    /// Blocks of branches competing with the chain
+    forks: ForkStore,
+
+    /// Bus for chain events
+    events: EventBus,
+
+    /// Which writers are paused for maintenance
+    maintenance: Arc<Mutex<MaintenanceStatus>>,
+
+    /// Mempool changes not yet written to the storage journal
+    mempool_journal: Arc<Mutex<Vec<MempoolJournalEntry>>>,
+
+    /// When the pending transactions were last announced to the peers
+    broadcasts: Arc<Broadcasts>,
+
+    /// Transactions recently taken into the mempool, so relayed copies are dropped
+    seen_transactions: Arc<SeenTransactions>,
+
+    /// When the pending transactions entered the mempool
+    arrivals: Arc<Arrivals>,
+
+    /// Whether proofs are searched from a random start with a random coinbase extra-nonce
+    randomize_proof_of_work: bool,
+
+    /// Threads searching for a proof of work, 0 for one per CPU core
+    mining_threads: usize,
+
+    /// Whether the node only follows blocks mined elsewhere, refusing transactions and mining
+    follower: bool,
+
+    /// Address the blocks mined with `mine` pay
+    miner: Option<Address>,
+
+    /// Components following the head of the chain
+    observers: Observers,
+
+    /// Custom admission rules of the application embedding the node
+    hooks: ValidationHooks,
+
+    /// Proof of work searches in progress, cancelled when another block takes the tip
+    mining: MiningSearches,
+
+    /// The network, which the genesis block is built from
+    genesis: GenesisConfig,
+
+    /// The peers the network service found
+    peers: PeerSet,
 }
 
 impl Blockchain {
-    /// Creates a new blockchain with a genesis block
+    /// Creates a new blockchain with the genesis block of the default network
     ///
     /// # Returns
     ///
     /// A new Blockchain instance
     pub fn new() -> Self {
+        Self::with_genesis(GenesisConfig::default())
+    }
+
+    /// Creates a new blockchain with the genesis block of a network
+    ///
+    /// # Arguments
+    ///
+    /// * `genesis` - The network
+    ///
+    /// # Returns
+    ///
+    /// A new Blockchain instance
+    pub fn with_genesis(genesis: GenesisConfig) -> Self {
         let mut blockchain = Blockchain {
             chain: Arc::new(Mutex::new(Vec::new())),
             pending_transactions: Arc::new(Mutex::new(Vec::new())),
+            queued_transactions: Arc::new(Mutex::new(Vec::new())),
             account_state: Arc::new(AccountState::new()),
-            difficulty: 4,
-            mining_reward: 50.0,
-            minimum_fee: 0.01,
+            rules: ValidationRules::node(),
             storage: None,
+            storage_writer: None,
+            activity: Arc::new(ActivityReports::new()),
+            index: Arc::new(TransactionIndex::new()),
+            names: Arc::new(NameRegistry::new()),
+            permissions: Arc::new(Permissions::default()),
+            scripts: Arc::new(ScriptLocks::new()),
+            sessions: Arc::new(SessionKeys::new()),
+            inheritances: Arc::new(Inheritances::new()),
+            governance: Arc::new(Governance::default()),
+            faucet: Arc::new(FaucetGrants::new()),
+            checkpoints: Arc::new(StateCheckpoints::new()),
+            base_fee: Arc::new(Mutex::new(None)),
+            reorgs: Arc::new(Mutex::new(Vec::new())),
+            forks: ForkStore::new(),
+            events: EventBus::new(),
+            maintenance: Arc::new(Mutex::new(MaintenanceStatus::default())),
+            mempool_journal: Arc::new(Mutex::new(Vec::new())),
+            broadcasts: Arc::new(Broadcasts::new()),
+            arrivals: Arc::new(Arrivals::new()),
+            seen_transactions: Arc::new(SeenTransactions::new()),
+            randomize_proof_of_work: true,
+            mining_threads: 0,
+            follower: false,
+            miner: None,
+            observers: Observers::new(),
+            hooks: ValidationHooks::new(),
+            mining: MiningSearches::new(),
+            genesis,
+            peers: PeerSet::new(),
         };
+        blockchain.register_builtin_observers();
 
         // Create the genesis block
         blockchain.create_genesis_block();
@@ -88,18 +485,105 @@ impl Blockchain {
     ///
     /// A new Blockchain instance with persistent storage
     pub fn with_storage<P: AsRef<std::path::Path>>(storage_path: P) -> Result<Self, BlockchainError> {
+        Self::with_storage_options(storage_path, StorageOptions::default())
+    }
+
+    /// Creates a new blockchain with persistent storage opened with options
+    ///
+    /// # Arguments
+    ///
+    /// * `storage_path` - The path to the storage directory
+    /// * `options` - The storage options
+    ///
+    /// # Returns
+    ///
+    /// A new Blockchain instance with persistent storage
+    pub fn with_storage_options<P: AsRef<std::path::Path>>(
+        storage_path: P,
+        options: StorageOptions,
+    ) -> Result<Self, BlockchainError> {
+        Self::open(storage_path, options, GenesisConfig::default())
+    }
+
+    /// Opens the persistent storage of a network
+    ///
+    /// A data directory records the magic of its network when it is first
+    /// opened, and is refused from then on by nodes of any other network.
+    /// Directories written before networks were recorded are taken to
+    /// belong to the configured one.
+    ///
+    /// # Arguments
+    ///
+    /// * `storage_path` - The path to the storage directory
+    /// * `options` - The storage options
+    /// * `genesis` - The network
+    ///
+    /// # Returns
+    ///
+    /// A new Blockchain instance with persistent storage, or a StorageError::NetworkMismatch
+    pub fn open<P: AsRef<std::path::Path>>(
+        storage_path: P,
+        options: StorageOptions,
+        genesis: GenesisConfig,
+    ) -> Result<Self, BlockchainError> {
         // Create storage
-        let storage = BlockchainStorage::new(storage_path)?;
+        let storage = Arc::new(BlockchainStorage::open(storage_path, options)?);
+
+        // Refuse the data of another network before touching it
+        let magic = genesis.magic();
+        let recorded = storage.get_network_magic()?;
+        if let Some(found) = recorded {
+            if found != magic {
+                return Err(StorageError::NetworkMismatch { expected: magic, found }.into());
+            }
+        }
+
+        let storage_writer = StorageWriter::start(storage.clone(), WRITE_QUEUE_CAPACITY)?;
 
         let mut blockchain = Blockchain {
             chain: Arc::new(Mutex::new(Vec::new())),
             pending_transactions: Arc::new(Mutex::new(Vec::new())),
+            queued_transactions: Arc::new(Mutex::new(Vec::new())),
             account_state: Arc::new(AccountState::new()),
-            difficulty: 4,
-            mining_reward: 50.0,
-            minimum_fee: 0.01,
-            storage: Some(Arc::new(storage)),
+            rules: ValidationRules::node(),
+            storage: Some(storage.clone()),
+            storage_writer: Some(storage_writer),
+            activity: Arc::new(ActivityReports::new()),
+            index: Arc::new(TransactionIndex::new()),
+            names: Arc::new(NameRegistry::new()),
+            permissions: Arc::new(Permissions::default()),
+            scripts: Arc::new(ScriptLocks::new()),
+            sessions: Arc::new(SessionKeys::new()),
+            inheritances: Arc::new(Inheritances::new()),
+            governance: Arc::new(Governance::default()),
+            faucet: Arc::new(FaucetGrants::new()),
+            checkpoints: Arc::new(StateCheckpoints::new()),
+            base_fee: Arc::new(Mutex::new(None)),
+            reorgs: Arc::new(Mutex::new(Vec::new())),
+            forks: ForkStore::new(),
+            events: EventBus::new(),
+            maintenance: Arc::new(Mutex::new(MaintenanceStatus::default())),
+            mempool_journal: Arc::new(Mutex::new(Vec::new())),
+            broadcasts: Arc::new(Broadcasts::new()),
+            arrivals: Arc::new(Arrivals::new()),
+            seen_transactions: Arc::new(SeenTransactions::new()),
+            randomize_proof_of_work: true,
+            mining_threads: 0,
+            follower: false,
+            miner: None,
+            observers: Observers::new(),
+            hooks: ValidationHooks::new(),
+            mining: MiningSearches::new(),
+            genesis,
+            peers: PeerSet::new(),
         };
+        blockchain.register_builtin_observers();
+
+        // Load past reorganizations and the deposits the replays credit
+        if let Some(storage) = &blockchain.storage {
+            *blockchain.reorgs.lock().unwrap() = storage.get_all_reorgs()?;
+            blockchain.faucet.replace(storage.get_faucet_grants()?);
+        }
 
         // Try to load existing chain from storage
         match blockchain.load_from_storage() {
@@ -118,21 +602,111 @@ impl Blockchain {
             }
         }
 
+        if recorded.is_none() {
+            info!("Data directory recorded as network {} ({})", blockchain.genesis.network, magic);
+            storage.set_network_magic(magic)?;
+        }
+
+        // Restore the pending transactions lost with the last shutdown
+        blockchain.recover_mempool()?;
+
         Ok(blockchain)
     }
 
+    /// Registers the indexes, the storage and the event bus as observers
+    ///
+    /// With storage, the storage observer tells the event bus once a block is
+    /// written, so subscribers only hear about blocks that were persisted.
+    fn register_builtin_observers(&self) {
+        self.observers.register(self.activity.clone());
+        self.observers.register(self.index.clone());
+        self.observers.register(self.names.clone());
+        self.observers.register(self.permissions.clone());
+        self.observers.register(self.scripts.clone());
+        self.observers.register(self.sessions.clone());
+        self.observers.register(self.inheritances.clone());
+        self.observers.register(Arc::new(GovernanceObserver::new(
+            self.governance.clone(),
+            self.account_state.clone(),
+            self.faucet.clone(),
+        )));
+        self.observers.register(self.checkpoints.clone());
+        self.observers.register(self.broadcasts.clone());
+        self.observers.register(self.arrivals.clone());
+        match &self.storage_writer {
+            Some(writer) => self.observers.register(Arc::new(StorageObserver::new(
+                writer.clone(),
+                self.account_state.clone(),
+                self.events.clone(),
+            ))),
+            None => self.observers.register(Arc::new(self.events.clone())),
+        }
+    }
+
+    /// Waits until the storage writer wrote everything handed to it
+    ///
+    /// Called after the chain locks are released, so a slow disk only holds
+    /// up the caller. Called with the pending transactions and the mempool
+    /// journal locked before writing to the storage directly, so no queued
+    /// write lands after.
+    ///
+    /// # Returns
+    ///
+    /// Result with the first error of the writes, if any
+    fn wait_for_storage(&self) -> Result<(), BlockchainError> {
+        if let Some(writer) = &self.storage_writer {
+            writer.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Registers a component to follow the head of the chain
+    ///
+    /// # Arguments
+    ///
+    /// * `observer` - The observer, called after the ones already registered
+    pub fn add_observer(&self, observer: Arc<dyn ChainObserver>) {
+        self.observers.register(observer);
+    }
+
+    /// Registers a custom admission rule for transactions and blocks
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - The hook, run after the node's own rules and the hooks already registered
+    pub fn add_validation_hook(&self, hook: Arc<dyn ValidationHook>) {
+        self.hooks.register(hook);
+    }
+
     /// Creates the genesis block (first block in the chain)
     fn create_genesis_block(&mut self) {
-        let genesis_block = Block::new(
-            0,
-            Vec::new(),
-            1,
-            "0".to_string(),
-        );
+        let genesis_block = self.genesis.block();
 
+        self.activity.record_block(&genesis_block);
+        self.index.record_block(&genesis_block);
         self.chain.lock().unwrap().push(genesis_block);
     }
 
+    /// Gets the network the node belongs to
+    pub fn get_network(&self) -> NetworkInfo {
+        self.genesis.info()
+    }
+
+    /// Gets the magic bytes of the network the node belongs to
+    pub fn get_network_magic(&self) -> NetworkMagic {
+        self.genesis.magic()
+    }
+
+    /// Gets the peers the network service found, ordered by peer ID
+    pub fn get_peers(&self) -> Vec<PeerInfo> {
+        self.peers.list()
+    }
+
+    /// Gets the peer set the network service records the peers it finds in
+    pub fn peer_set(&self) -> PeerSet {
+        self.peers.clone()
+    }
+
     /// Gets the last block in the chain
     ///
     /// # Returns
@@ -145,6 +719,9 @@ impl Blockchain {
 
     /// Adds a new transaction to the pending transactions
     ///
+    /// Adding a transaction that is already pending or queued changes nothing
+    /// and succeeds again.
+    ///
     /// # Arguments
     ///
     /// * `transaction` - The transaction to add
@@ -153,447 +730,3936 @@ impl Blockchain {
     ///
     /// Result with the index of the block that will include this transaction
     pub fn add_transaction(&self, transaction: Transaction) -> Result<u64, BlockchainError> {
-        // Verify the transaction signature
-        if !transaction.is_coinbase() && !transaction.verify_signature()? {
-            return Err(BlockchainError::TransactionError(
-                TransactionError::InvalidSignature,
-            ));
-        }
+        self.check_writable()?;
+        validation::check_submittable(&transaction)?;
+        let height = self.get_last_block().index + 1;
+        validation::check_transaction(&transaction, height, &self.rules_after(&self.chain.lock().unwrap()))?;
+        self.permissions.check_transaction(&transaction)?;
+        self.governance.check_admission(&transaction, height)?;
 
-        // Check if the transaction fee is sufficient
-        if !transaction.is_coinbase() && transaction.fee < self.minimum_fee {
-            return Err(BlockchainError::TransactionError(
-                TransactionError::InvalidAmount(format!(
-                    "Transaction fee too low: {} (minimum: {})",
-                    transaction.fee, self.minimum_fee
-                )),
-            ));
+        let mut pending = self.pending_transactions.lock().unwrap();
+        if self.is_known(&pending, &transaction.id) {
+            return Ok(height);
         }
 
-        // Check if the sender has sufficient funds
-        if !transaction.is_coinbase() {
-            let sender_account = self.account_state.get_account(&transaction.sender);
-
-            if !sender_account.has_sufficient_funds(transaction.total_amount()) {
-                return Err(BlockchainError::AccountError(
-                    AccountError::InsufficientFunds {
-                        required: transaction.total_amount(),
-                        available: sender_account.balance,
-                    },
-                ));
-            }
-
-            // Check if the nonce is valid
-            if !sender_account.is_valid_nonce(transaction.nonce) {
-                return Err(BlockchainError::AccountError(
-                    AccountError::InvalidNonce {
-                        expected: sender_account.nonce,
-                        got: transaction.nonce,
-                    },
-                ));
-            }
+        // Checked under the pending lock so a pause waits for this admission
+        let maintenance = self.get_maintenance_status();
+        if maintenance.transactions_paused {
+            return Err(BlockchainError::Paused(maintenance.reason()));
         }
 
-        // Add the transaction to pending transactions
-        self.pending_transactions.lock().unwrap().push(transaction);
+        self.admit(&mut pending, transaction, height)?;
+        drop(pending);
 
         Ok(self.get_last_block().index + 1)
     }
 
-    /// Mines a new block with the pending transactions
+    /// Submits a signed transaction, resolving a nonce that is not next in line
+    ///
+    /// A nonce already taken by a pending transaction of the sender replaces
+    /// it if the fee is higher. A nonce beyond the next one waits in a queue
+    /// until the transactions before it are admitted, then goes through the
+    /// admission checks. Queued transactions are not persisted. A transaction
+    /// that is already pending or queued is known and changes nothing.
     ///
     /// # Arguments
     ///
-    /// * `miner_address` - The address of the miner (to receive mining reward)
+    /// * `transaction` - The transaction to submit
     ///
     /// # Returns
     ///
-    /// Result with the newly mined block
-    pub fn mine_block(&self, miner_address: &str) -> Result<Block, BlockchainError> {
-        // Parse miner address
-        let miner_address = Address(miner_address.to_string());
-
-        // Add mining reward transaction
-        let reward_transaction = Transaction::new_coinbase(
-            miner_address.clone(),
-            self.mining_reward,
-        );
+    /// Result with how the transaction entered the mempool
+    pub fn submit_transaction(&self, transaction: Transaction) -> Result<Admission, BlockchainError> {
+        self.check_writable()?;
+        validation::check_submittable(&transaction)?;
+        let height = self.get_last_block().index + 1;
+        validation::check_transaction(&transaction, height, &self.rules_after(&self.chain.lock().unwrap()))?;
+        self.permissions.check_transaction(&transaction)?;
+        self.governance.check_admission(&transaction, height)?;
 
-        // Get pending transactions and add reward
         let mut pending = self.pending_transactions.lock().unwrap();
-
-        // Process all transactions
-        for transaction in pending.iter() {
-            if !transaction.is_coinbase() {
-                // Transfer funds
-                self.account_state.transfer(
-                    &transaction.sender,
-                    &transaction.recipient,
-                    transaction.amount,
-                    transaction.fee,
-                    transaction.nonce,
-                )?;
-            }
+        if self.is_known(&pending, &transaction.id) {
+            return Ok(Admission::Known);
         }
 
-        // Process mining reward
-        self.account_state.process_mining_reward(&miner_address, self.mining_reward)?;
-
-        // Add reward transaction to pending transactions
-        pending.push(reward_transaction);
-        let transactions = pending.clone();
-
-        // Clear pending transactions
-        pending.clear();
-
-        // Get the last block
-        let last_block = self.get_last_block();
-
-        // Mine the new block
-        let new_block = self.proof_of_work(
-            last_block.index + 1,
-            transactions,
-            last_block.hash,
-        );
-
-        // Add the new block to the chain
-        self.chain.lock().unwrap().push(new_block.clone());
+        let maintenance = self.get_maintenance_status();
+        if maintenance.transactions_paused {
+            return Err(BlockchainError::Paused(maintenance.reason()));
+        }
 
-        // Save to storage if available
-        if let Some(storage) = &self.storage {
-            // Save the block
-            storage.save_block(&new_block)?;
+        let confirmed_nonce = self.account_state.get_account(&transaction.sender).nonce;
+        let expected = validation::next_nonce(&pending, &transaction.sender, confirmed_nonce);
+        if transaction.nonce == expected {
+            self.admit(&mut pending, transaction, height)?;
+            return Ok(Admission::Accepted);
+        }
 
-            // Save all transactions in the block
-            for transaction in &new_block.transactions {
-                storage.save_transaction(transaction)?;
+        if transaction.nonce > expected {
+            let mut queued = self.queued_transactions.lock().unwrap();
+            if let Some(position) = queued
+                .iter()
+                .position(|tx| tx.sender == transaction.sender && tx.nonce == transaction.nonce)
+            {
+                if transaction.fee <= queued[position].fee {
+                    return Err(TransactionError::ReplacementUnderpriced {
+                        fee: transaction.fee,
+                        replaced_fee: queued[position].fee,
+                    }
+                    .into());
+                }
+                self.seen_transactions.insert(&transaction.id);
+                let replaced = std::mem::replace(&mut queued[position], transaction);
+                return Ok(Admission::Replaced(replaced.id));
             }
-
-            // Save account state
-            for account in self.account_state.get_all_accounts() {
-                storage.save_account(&account)?;
+            if queued.iter().filter(|tx| tx.sender == transaction.sender).count() >= MAX_QUEUED_PER_SENDER {
+                return Err(TransactionError::TooManyQueued(transaction.sender.0.clone()).into());
             }
 
-            // Flush storage to disk
-            storage.flush()?;
-
-            info!("Saved block {} to storage", new_block.index);
+            self.seen_transactions.insert(&transaction.id);
+            queued.push(transaction);
+            return Ok(Admission::Queued);
         }
 
-        Ok(new_block)
+        // An earlier nonce can only replace a pending transaction, a confirmed one is final
+        let position = pending
+            .iter()
+            .position(|tx| tx.sender == transaction.sender && tx.nonce == transaction.nonce)
+            .ok_or(AccountError::InvalidNonce {
+                expected,
+                got: transaction.nonce,
+            })?;
+        validation::check_replacement(&self.account_state, &pending, &pending[position], &transaction)?;
+        let others: Vec<Transaction> = pending
+            .iter()
+            .filter(|tx| tx.id != pending[position].id)
+            .cloned()
+            .collect();
+        validation::check_name_admission(&self.names, &others, &transaction, height)?;
+        validation::check_lock_admission(&self.scripts, &others, &transaction, height)?;
+        validation::check_session_admission(&self.sessions, &others, &transaction, height)?;
+        validation::check_claim_admission(&self.inheritances, &others, &transaction, height)?;
+        self.hooks.check_transaction(&transaction, height)?;
+
+        self.journal(MempoolJournalEntry::Replaced(pending[position].id.clone(), Box::new(transaction.clone())));
+        self.seen_transactions.insert(&transaction.id);
+        self.observers.tx_admitted(&transaction);
+        let replaced = std::mem::replace(&mut pending[position], transaction);
+        info!("Transaction {} replaced pending transaction {}", pending[position].id, replaced.id);
+
+        Ok(Admission::Replaced(replaced.id))
     }
 
-    /// Performs proof of work to find a valid hash
+    /// Submits a transaction a peer relayed, see `submit_transaction`
+    ///
+    /// The peer that announced the transaction announces it again if it gets
+    /// stuck, so the node doesn't. A transaction the node took into its
+    /// mempool recently is known without being checked again, even if it was
+    /// mined or replaced since, so relays of it die out.
     ///
     /// # Arguments
     ///
-    /// * `index` - The index of the new block
-    /// * `transactions` - The transactions to include in the block
-    /// * `previous_hash` - The hash of the previous block
+    /// * `transaction` - The transaction to submit
     ///
     /// # Returns
     ///
-    /// The newly mined block with a valid proof
-    fn proof_of_work(&self, index: u64, transactions: Vec<Transaction>, previous_hash: String) -> Block {
-        let mut proof = 0;
-        let target = "0".repeat(self.difficulty as usize);
-
-        loop {
-            let block = Block::new(index, transactions.clone(), proof, previous_hash.clone());
-            let hash = block.calculate_hash();
-
-            if hash.starts_with(&target) {
-                return Block {
-                    hash,
-                    ..block
-                };
-            }
-
-            proof += 1;
+    /// Result with how the transaction entered the mempool
+    pub fn submit_relayed_transaction(&self, transaction: Transaction) -> Result<Admission, BlockchainError> {
+        if self.seen_transactions.contains(&transaction.id) {
+            return Ok(Admission::Known);
         }
+        self.broadcasts.mark_relayed(&transaction.id);
+        self.submit_transaction(transaction)
     }
 
-    /// Gets the entire blockchain
+    /// Submits signed transactions one after the other, see `submit_transaction`
+    ///
+    /// A failing transaction doesn't stop the others, so a sender's later
+    /// nonces are queued behind a rejected one until it is resubmitted.
+    ///
+    /// # Arguments
+    ///
+    /// * `transactions` - The transactions in submission order
     ///
     /// # Returns
     ///
-    /// A vector of all blocks in the chain
-    pub fn get_chain(&self) -> Vec<Block> {
-        self.chain.lock().unwrap().clone()
+    /// The outcome of each transaction, in the same order
+    pub fn submit_batch(&self, transactions: Vec<Transaction>) -> Vec<Result<Admission, BlockchainError>> {
+        transactions
+            .into_iter()
+            .map(|transaction| self.submit_transaction(transaction))
+            .collect()
     }
 
-    /// Gets all pending transactions
+    /// Gets the transactions waiting for an earlier nonce of their sender
+    pub fn get_queued_transactions(&self) -> Vec<Transaction> {
+        self.queued_transactions.lock().unwrap().clone()
+    }
+
+    /// Announces a pending transaction to the peers again
+    ///
+    /// Restarts the interval after which a local transaction is announced
+    /// again automatically, see `rebroadcast_due`.
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction_id` - ID of the pending transaction
     ///
     /// # Returns
     ///
-    /// A vector of all pending transactions
-    pub fn get_pending_transactions(&self) -> Vec<Transaction> {
-        self.pending_transactions.lock().unwrap().clone()
+    /// When the transaction was announced, None if it is not pending
+    pub fn rebroadcast_transaction(&self, transaction_id: &str) -> Option<BroadcastRecord> {
+        let pending = self.pending_transactions.lock().unwrap();
+        let transaction = pending.iter().find(|transaction| transaction.id == transaction_id)?;
+
+        let record = self.broadcasts.rebroadcast(transaction_id, Utc::now());
+        self.observers.tx_rebroadcast(transaction);
+        Some(record)
     }
 
-    /// Gets the account state
+    /// Announces the local pending transactions that have waited too long to the peers again
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - When transactions are announced again
     ///
     /// # Returns
     ///
-    /// The account state
-    pub fn get_account_state(&self) -> Arc<AccountState> {
-        self.account_state.clone()
+    /// When the transactions announced again were announced
+    pub fn rebroadcast_due(&self, policy: &RebroadcastPolicy) -> Vec<BroadcastRecord> {
+        let pending = self.pending_transactions.lock().unwrap();
+        let due = self.broadcasts.due(&pending, policy, Utc::now());
+
+        for record in &due {
+            if let Some(transaction) = pending.iter().find(|transaction| transaction.id == record.transaction_id) {
+                self.observers.tx_rebroadcast(transaction);
+            }
+        }
+        due
     }
 
-    /// Validates the blockchain
+    /// Evicts the pending transactions that have waited longer than a maximum age
     ///
-    /// # Returns
+    /// The later transactions of an evicted transaction's sender can't be
+    /// mined without it, so they are evicted along with it. Copies peers
+    /// relay again are dropped as known, the sender may submit it again.
     ///
-    /// true if the blockchain is valid, false otherwise
+    /// # Arguments
+    ///
+    /// * `max_age` - How long a transaction may wait in the mempool
+    ///
+    /// # Returns
+    ///
+    /// The evicted transactions with when they were received, in admission order
+    pub fn evict_expired(&self, max_age: chrono::Duration) -> Vec<PendingTransaction> {
+        let mut pending = self.pending_transactions.lock().unwrap();
+        let now = Utc::now();
+        let expired: HashSet<String> = self
+            .arrivals
+            .expired(&pending, max_age, now)
+            .into_iter()
+            .map(|entry| entry.transaction.id)
+            .collect();
+        if expired.is_empty() {
+            return Vec::new();
+        }
+
+        let entries = self.arrivals.entries(&pending, now);
+        pending.clear();
+        let mut evicted = Vec::new();
+        for entry in entries {
+            let transaction = &entry.transaction;
+            let kept = !expired.contains(&transaction.id)
+                && validation::check_admission(&self.account_state, &pending, transaction).is_ok();
+            if kept {
+                pending.push(entry.transaction);
+                continue;
+            }
+            info!("Evicting transaction {} received at {}", transaction.id, entry.received_at);
+            self.journal(MempoolJournalEntry::Removed(transaction.id.clone()));
+            self.observers.tx_evicted(&entry);
+            evicted.push(entry);
+        }
+
+        evicted
+    }
+
+    /// Adds a checked transaction to the pending ones, then the queued transactions it unblocks
+    ///
+    /// # Arguments
+    ///
+    /// * `pending` - The locked pending transactions
+    /// * `transaction` - The transaction, already checked without the state
+    /// * `height` - Height of the block the transaction is meant for
+    ///
+    /// # Returns
+    ///
+    /// Result with () if the transaction was admitted
+    fn admit(
+        &self,
+        pending: &mut Vec<Transaction>,
+        transaction: Transaction,
+        height: u64,
+    ) -> Result<(), BlockchainError> {
+        validation::check_admission(&self.account_state, pending, &transaction)?;
+        validation::check_name_admission(&self.names, pending, &transaction, height)?;
+        validation::check_lock_admission(&self.scripts, pending, &transaction, height)?;
+        validation::check_session_admission(&self.sessions, pending, &transaction, height)?;
+        validation::check_claim_admission(&self.inheritances, pending, &transaction, height)?;
+        self.hooks.check_transaction(&transaction, height)?;
+
+        // Add the transaction to pending transactions
+        let sender = transaction.sender.clone();
+        self.journal(MempoolJournalEntry::Admitted(Box::new(transaction.clone())));
+        self.seen_transactions.insert(&transaction.id);
+        self.observers.tx_admitted(&transaction);
+        pending.push(transaction);
+
+        let unblocked = {
+            let mut queued = self.queued_transactions.lock().unwrap();
+            let next = validation::next_nonce(pending, &sender, self.account_state.get_account(&sender).nonce);
+
+            // Queued nonces that were taken in the meantime can never be admitted
+            queued.retain(|tx| tx.sender != sender || tx.nonce >= next);
+            queued
+                .iter()
+                .position(|tx| tx.sender == sender && tx.nonce == next)
+                .map(|position| queued.remove(position))
+        };
+        if let Some(transaction) = unblocked {
+            let id = transaction.id.clone();
+            if let Err(err) = self.admit(pending, transaction, height) {
+                warn!("Dropping queued transaction {}: {} ({})", id, err, err.code());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Gets whether a transaction is pending or queued
+    fn is_known(&self, pending: &[Transaction], transaction_id: &str) -> bool {
+        pending.iter().any(|tx| tx.id == transaction_id)
+            || self
+                .queued_transactions
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|tx| tx.id == transaction_id)
+    }
+
+    /// Gets the next usable nonce for an address
+    ///
+    /// Takes both the confirmed account nonce and the sender's pending
+    /// transactions into account, so clients can build several transactions
+    /// in a row without waiting for a block in between.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address to get the nonce for
+    ///
+    /// # Returns
+    ///
+    /// The nonce the next transaction from this address must use
+    pub fn get_next_nonce(&self, address: &Address) -> u64 {
+        let confirmed_nonce = self.account_state.get_account(address).nonce;
+        let pending = self.pending_transactions.lock().unwrap();
+
+        validation::next_nonce(&pending, address, confirmed_nonce)
+    }
+
+    /// Mines a new block with the pending transactions
+    ///
+    /// # Arguments
+    ///
+    /// * `miner_address` - The address of the miner (to receive mining reward)
+    ///
+    /// # Returns
+    ///
+    /// Result with the newly mined block
+    pub fn mine_block(&self, miner_address: &str) -> Result<Block, BlockchainError> {
+        self.mine_block_with_coinbase(miner_address, None, None)
+    }
+
+    /// Mines a new block with the pending transactions, paying the node's miner address
+    ///
+    /// # Returns
+    ///
+    /// Result with the newly mined block, an error if the node has no miner address, see `set_miner_address`
+    pub fn mine(&self) -> Result<Block, BlockchainError> {
+        let miner = self
+            .miner
+            .clone()
+            .ok_or_else(|| BlockchainError::InvalidBlock("The node has no miner address".to_string()))?;
+        self.mine_block(&miner.0)
+    }
+
+    /// Mines a new block with the pending transactions and a custom coinbase
+    ///
+    /// # Arguments
+    ///
+    /// * `miner_address` - The address of the miner (to receive mining reward)
+    /// * `extra_nonce` - The extra-nonce of the coinbase, random or none if not given
+    /// * `message` - A message to embed in the coinbase
+    ///
+    /// # Returns
+    ///
+    /// Result with the newly mined block
+    pub fn mine_block_with_coinbase(
+        &self,
+        miner_address: &str,
+        extra_nonce: Option<u64>,
+        message: Option<&str>,
+    ) -> Result<Block, BlockchainError> {
+        let mut pending = self.pending_transactions.lock().unwrap();
+        let BlockCandidate {
+            block: candidate,
+            disbursements,
+            included,
+            reward: reward_transaction,
+        } = self.assemble_block(&mut pending, miner_address, extra_nonce, message)?;
+        let height = candidate.index;
+        let previous_hash = candidate.previous_hash.clone();
+
+        // Record the block before sealing it, so its transactions survive a crash while sealing
+        self.save_mining_template(MiningTemplate {
+            height,
+            previous_hash: previous_hash.clone(),
+            transactions: candidate.transactions.clone(),
+            taken: included.iter().map(|transaction| transaction.id.clone()).collect(),
+        })?;
+
+        // Search for the proof without the mempool, so blocks from peers and other mining calls
+        // are not held up, and give up as soon as one of them takes the tip
+        let search = self.mining.start(&previous_hash);
+        drop(pending);
+        let sealed = self.proof_of_work(height, candidate.transactions, previous_hash.clone(), search.token());
+        drop(search);
+
+        let mut pending = self.pending_transactions.lock().unwrap();
+        let tip = self.get_last_block();
+        let new_block = match sealed {
+            Some(block) if tip.hash == previous_hash => block,
+            _ => {
+                info!("Mining of block {} cancelled, block {} took the tip", height, tip.hash);
+                if let Some(writer) = &self.storage_writer {
+                    writer.write(|storage| storage.clear_mining_template())?;
+                }
+                drop(pending);
+                self.wait_for_storage()?;
+                return Err(BlockchainError::MiningCancelled { height, tip: tip.hash });
+            }
+        };
+
+        // Process all transactions
+        for transaction in disbursements.iter().chain(included.iter()) {
+            if !transaction.is_coinbase() {
+                // Transfer funds, out of the lock first if the transaction spends one
+                validation::apply_transfer(&self.account_state, transaction)?;
+            }
+        }
+
+        // Process mining reward
+        self.account_state
+            .process_mining_reward(&reward_transaction.recipient, reward_transaction.amount)?;
+        if let Some(payout) = &reward_transaction.treasury {
+            self.account_state
+                .process_mining_reward(&Address(payout.address.clone()), payout.amount)?;
+        }
+
+        // Take the transactions out of the pending ones; the mempool changed while sealing,
+        // so the others are checked again like after a block from a peer
+        let taken: HashSet<&str> = included.iter().map(|transaction| transaction.id.as_str()).collect();
+        let candidates: Vec<Transaction> = pending.drain(..).collect();
+        for transaction in candidates {
+            if taken.contains(transaction.id.as_str()) {
+                self.journal(MempoolJournalEntry::Removed(transaction.id.clone()));
+                continue;
+            }
+            match validation::check_admission(&self.account_state, &pending, &transaction).map_err(BlockchainError::from) {
+                Ok(()) => pending.push(transaction),
+                Err(err) => {
+                    warn!("Dropping transaction {} after block {}: {} ({})", transaction.id, height, err, err.code());
+                    self.journal(MempoolJournalEntry::Removed(transaction.id.clone()));
+                }
+            }
+        }
+
+        // Add the new block to the chain
+        self.chain.lock().unwrap().push(new_block.clone());
+        self.mining.tip_changed(&new_block.hash);
+        self.observers.block_applied(&new_block)?;
+        if let Some(writer) = &self.storage_writer {
+            writer.write(|storage| storage.clear_mining_template())?;
+        }
+        drop(pending);
+        self.wait_for_storage()?;
+
+        Ok(new_block)
+    }
+
+    /// Gets the next block for a miner outside the node to seal
+    ///
+    /// The block is assembled like `mine_block_with_coinbase` would, with a
+    /// proof of 0, but nothing leaves the mempool: once a proof is found the
+    /// block goes through `append_block` like a block from a peer. The
+    /// template is stale as soon as another block takes the tip.
+    ///
+    /// # Arguments
+    ///
+    /// * `miner_address` - The address of the miner (to receive mining reward)
+    /// * `extra_nonce` - The extra-nonce of the coinbase, random or none if not given
+    /// * `message` - A message to embed in the coinbase
+    ///
+    /// # Returns
+    ///
+    /// Result with the block to seal, its hash needs `get_difficulty` leading zeros
+    pub fn get_block_template(
+        &self,
+        miner_address: &str,
+        extra_nonce: Option<u64>,
+        message: Option<&str>,
+    ) -> Result<Block, BlockchainError> {
+        let mut pending = self.pending_transactions.lock().unwrap();
+        Ok(self.assemble_block(&mut pending, miner_address, extra_nonce, message)?.block)
+    }
+
+    /// Assembles the next block from the pending transactions, before its proof of work
+    ///
+    /// Pending transactions that can no longer be mined leave the mempool.
+    ///
+    /// # Arguments
+    ///
+    /// * `pending` - The pending transactions, locked by the caller
+    /// * `miner_address` - The address of the miner (to receive mining reward)
+    /// * `extra_nonce` - The extra-nonce of the coinbase, random or none if not given
+    /// * `message` - A message to embed in the coinbase
+    ///
+    /// # Returns
+    ///
+    /// Result with the block and the transactions it is made of
+    fn assemble_block(
+        &self,
+        pending: &mut Vec<Transaction>,
+        miner_address: &str,
+        extra_nonce: Option<u64>,
+        message: Option<&str>,
+    ) -> Result<BlockCandidate, BlockchainError> {
+        self.check_writable()?;
+
+        // Parse miner address
+        let miner_address = Address(miner_address.to_string());
+        self.permissions.check_miner(&miner_address)?;
+        let last_block = self.get_last_block();
+        let height = last_block.index + 1;
+        let parameters = self.governance.parameters_at(height);
+        if message.is_some_and(|message| message.len() > MAX_COINBASE_MESSAGE_BYTES) {
+            return Err(BlockchainError::InvalidBlock(format!(
+                "coinbase message is longer than {} bytes",
+                MAX_COINBASE_MESSAGE_BYTES
+            )));
+        }
+
+        let maintenance = self.get_maintenance_status();
+        if maintenance.mining_paused {
+            return Err(BlockchainError::Paused(maintenance.reason()));
+        }
+
+        // Senders revoked or frozen since their transactions were admitted can't be included,
+        // nor spends of locks a reorganization closed or of session keys revoked, expired or used up since,
+        // nor claims on owners that are active again, nor transactions the hooks reject now
+        let mut session_spent: HashMap<(Address, String), f64> = HashMap::new();
+        let active: HashSet<Address> = pending.iter().map(|transaction| transaction.sender.clone()).collect();
+        pending.retain(|transaction| match self
+            .permissions
+            .check_transaction(transaction)
+            .map_err(BlockchainError::from)
+            .and_then(|()| self.scripts.check_spend(transaction, height).map_err(BlockchainError::from))
+            .and_then(|()| match &transaction.session {
+                Some(SessionAction::Spend { key }) => {
+                    let spent = session_spent.entry((transaction.sender.clone(), key.clone())).or_insert(0.0);
+                    self.sessions.check_spend(transaction, height, *spent)?;
+                    *spent += sessions::session_spend(transaction);
+                    Ok(())
+                }
+                _ => Ok(()),
+            })
+            .and_then(|()| match inheritance::claimed_owner(transaction) {
+                Some(owner) if active.contains(&owner) => Err(BlockchainError::InheritanceError(InheritanceError::InvalidClaim(
+                    format!("{} has a pending transaction", owner),
+                ))),
+                _ => self.inheritances.check_claim(transaction, height).map_err(BlockchainError::from),
+            })
+            .and_then(|()| self.hooks.check_transaction(transaction, height).map_err(BlockchainError::from))
+        {
+            Ok(()) => true,
+            Err(err) => {
+                warn!("Dropping transaction {}: {} ({})", transaction.id, err, err.code());
+                self.journal(MempoolJournalEntry::Removed(transaction.id.clone()));
+                false
+            }
+        });
+
+        // Installments due from the treasury come first, paid from its balance after the last block
+        let treasury = self.account_state.get_account(&Address(TREASURY_ADDRESS.to_string()));
+        let disbursements: Vec<Transaction> = self
+            .governance
+            .due_installments(height, treasury.balance)
+            .iter()
+            .enumerate()
+            .map(|(position, installment)| installment.transaction(treasury.nonce + position as u64))
+            .collect();
+
+        // Transactions below the base fee, and those of their sender after them, wait for it to drop,
+        // and transactions beyond the block size wait for the next block
+        let base_fee = self.get_base_fee();
+        let limit = parameters.max_block_transactions.saturating_sub(disbursements.len());
+        let mut priced_out: HashSet<Address> = HashSet::new();
+        let mut included: Vec<Transaction> = Vec::new();
+        for transaction in pending.iter() {
+            if included.len() == limit {
+                break;
+            }
+            if transaction.fee < base_fee || priced_out.contains(&transaction.sender) {
+                priced_out.insert(transaction.sender.clone());
+                continue;
+            }
+            included.push(transaction.clone());
+        }
+
+        // Add mining reward transaction, with the base fees the policy gives the miner and the treasury and the tips
+        let fees = BlockFees::new(&included, base_fee);
+        let mut reward_transaction = Transaction::new_coinbase(
+            miner_address.clone(),
+            self.rules.fee_policy.miner_amount(parameters.mining_reward, &fees),
+        );
+        if let Some(payout) = self.rules.fee_policy.payout(parameters.mining_reward, fees.base) {
+            reward_transaction = reward_transaction.with_treasury(payout);
+        }
+        let extra_nonce = extra_nonce.or_else(|| self.randomize_proof_of_work.then(rand::random));
+        if let Some(extra_nonce) = extra_nonce {
+            reward_transaction = reward_transaction.with_extra_nonce(extra_nonce);
+        }
+        if let Some(message) = message {
+            reward_transaction = reward_transaction.with_message(message);
+        }
+
+        // The hooks check the block before its proof of work is searched
+        let block = Block::new(
+            height,
+            disbursements
+                .iter()
+                .chain(included.iter())
+                .chain(std::iter::once(&reward_transaction))
+                .cloned()
+                .collect(),
+            0,
+            last_block.hash.clone(),
+        );
+        self.hooks.check_block(&block)?;
+
+        Ok(BlockCandidate {
+            block,
+            disbursements,
+            included,
+            reward: reward_transaction,
+        })
+    }
+
+    /// Appends a block mined by another node on top of the chain
+    ///
+    /// The block is checked like a block of a competing branch: it must link
+    /// to the tip, follow the rules and parameters at its height and apply to
+    /// the accounts. Pending transactions it includes, or no longer valid
+    /// after it, leave the mempool.
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block to append
+    ///
+    /// # Returns
+    ///
+    /// Result with () if the block was valid and appended, otherwise nothing changed
+    pub fn append_block(&self, block: Block) -> Result<(), BlockchainError> {
+        let mut pending = self.pending_transactions.lock().unwrap();
+        let mut chain = self.chain.lock().unwrap();
+
+        let treasury = self.account_state.get_account(&Address(TREASURY_ADDRESS.to_string()));
+        self.governance.check_disbursements(&block, treasury.balance)?;
+        validation::check_block(chain.last().unwrap(), &block, &self.rules_after(&chain))?;
+        self.permissions.check_block(&block)?;
+        self.scripts.check_block(&block)?;
+        self.sessions.check_block(&block)?;
+        self.inheritances.check_block(&block)?;
+
+        // Apply to a copy, so a transaction failing halfway leaves nothing behind
+        let accounts = AccountState::new();
+        accounts.replace_all(self.account_state.get_all_accounts());
+        accounts.take_changed_accounts();
+        validation::apply_block(&accounts, &block).map_err(|err| {
+            BlockchainError::InvalidBlock(format!("Block {} can not be applied: {}", block.index, err))
+        })?;
+        self.hooks.check_block(&block)?;
+        for account in accounts.take_changed_accounts() {
+            self.account_state.update_account(account);
+        }
+
+        let included: HashSet<&str> = block.transactions.iter().map(|tx| tx.id.as_str()).collect();
+        let candidates: Vec<Transaction> = pending.drain(..).collect();
+        for transaction in candidates {
+            if included.contains(transaction.id.as_str()) {
+                self.journal(MempoolJournalEntry::Removed(transaction.id.clone()));
+                continue;
+            }
+            match validation::check_admission(&self.account_state, &pending, &transaction).map_err(BlockchainError::from) {
+                Ok(()) => pending.push(transaction),
+                Err(err) => {
+                    warn!("Dropping transaction {} after block {}: {} ({})", transaction.id, block.index, err, err.code());
+                    self.journal(MempoolJournalEntry::Removed(transaction.id.clone()));
+                }
+            }
+        }
+
+        chain.push(block.clone());
+        drop(chain);
+        if self.mining.tip_changed(&block.hash) > 0 {
+            info!("Block {} arrived, cancelling the mining of the same height", block.index);
+        }
+        self.observers.block_applied(&block)?;
+        drop(pending);
+        self.wait_for_storage()?;
+
+        Ok(())
+    }
+
+    /// Switches the chain to a competing branch with more work
+    ///
+    /// The branch must attach to a block of the current chain and have more
+    /// cumulative work than the blocks it replaces, see
+    /// `ValidationRules::block_work`. The replaced blocks are kept as a
+    /// competing branch. Transactions of the removed blocks that are not part
+    /// of the branch go back to the pending transactions if they are still
+    /// valid. Deposits of the faucet are credited on the branch after the
+    /// height they were made at, or after its tip if it has fewer blocks. The
+    /// reorganization is persisted and published on the event bus.
+    ///
+    /// # Arguments
+    ///
+    /// * `branch` - The blocks of the competing branch, starting right after the fork point
+    ///
+    /// # Returns
+    ///
+    /// Result with the recorded reorganization
+    pub fn reorganize(&self, branch: Vec<Block>) -> Result<ReorgEvent, BlockchainError> {
+        let first = branch
+            .first()
+            .ok_or_else(|| BlockchainError::InvalidChain("Branch is empty".to_string()))?;
+        let fork_index = first.index as usize;
+
+        let mut pending = self.pending_transactions.lock().unwrap();
+        let mut chain = self.chain.lock().unwrap();
+
+        if fork_index == 0 || fork_index >= chain.len() {
+            return Err(BlockchainError::InvalidChain(format!(
+                "Branch must replace blocks of the current chain, starts at {}",
+                fork_index
+            )));
+        }
+        if self.chain_work(&branch) <= self.chain_work(&chain[fork_index..]) {
+            return Err(BlockchainError::InvalidChain(
+                "Branch does not have more work than the current chain".to_string(),
+            ));
+        }
+
+        // Build the state of the new branch on top of the common ancestor
+        let grants = self.faucet.all();
+        let mut state = ChainState::replay(&chain[..fork_index], &grants)?;
+        let permissions = self.permissions.replay(&chain[..fork_index]);
+        let locks = ScriptLocks::replay(&chain[..fork_index]);
+        let session_keys = SessionKeys::replay(&chain[..fork_index]);
+        let switches = Inheritances::replay(&chain[..fork_index]);
+        let governance = self.governance.replay(&chain[..fork_index], &grants)?;
+        let mut base_fee = fees::base_fee_after(&chain[..fork_index], |height| governance.parameters_at(height));
+        for block in &branch {
+            let rules = self.rules.with_parameters(&governance.parameters_at(block.index)).with_base_fee(base_fee);
+            let treasury = state.accounts().get_account(&Address(TREASURY_ADDRESS.to_string()));
+            governance.check_disbursements(block, treasury.balance)?;
+            state.connect(block.clone(), &rules)?;
+            base_fee = fees::next_base_fee(
+                base_fee,
+                block,
+                rules.max_block_transactions,
+                governance.parameters_at(block.index + 1).minimum_fee,
+            );
+            permissions.check_block(block)?;
+            permissions.record_block(block);
+            locks.check_block(block)?;
+            locks.record_block(block);
+            session_keys.check_block(block)?;
+            session_keys.record_block(block);
+            switches.check_block(block)?;
+            switches.record_block(block);
+            governance.record_block(block, state.accounts());
+            state.credit(&grants)?;
+            self.hooks.check_block(block)?;
+        }
+
+        // Deposits made above the tip of a branch with fewer blocks count after its tip
+        let new_height = state.tip().index;
+        let lowered: Vec<FaucetGrant> = grants
+            .iter()
+            .filter(|grant| grant.height > new_height)
+            .map(|grant| FaucetGrant {
+                height: new_height,
+                ..grant.clone()
+            })
+            .collect();
+        state.credit(&lowered)?;
+
+        // Switch to the new branch
+        let old_tip = chain.last().unwrap().hash.clone();
+        let orphaned = chain.split_off(fork_index);
+        chain.extend(branch.iter().cloned());
+        self.account_state.replace_all(state.accounts().get_all_accounts());
+        self.forks.remove(&branch.iter().map(|block| block.hash.clone()).collect::<Vec<String>>());
+        for block in &orphaned {
+            self.forks.insert(block.clone());
+        }
+
+        let included: HashSet<&str> = branch
+            .iter()
+            .flat_map(|block| block.transactions.iter())
+            .map(|tx| tx.id.as_str())
+            .collect();
+        let dropped: Vec<&Transaction> = orphaned
+            .iter()
+            .flat_map(|block| block.transactions.iter())
+            .filter(|tx| !included.contains(tx.id.as_str()))
+            .collect();
+
+        // Requeue the dropped transactions ahead of the ones still pending
+        let candidates: Vec<Transaction> = dropped
+            .iter()
+            .filter(|tx| !tx.is_coinbase() && !tx.is_disbursement())
+            .map(|tx| (*tx).clone())
+            .chain(pending.drain(..))
+            .filter(|tx| !included.contains(tx.id.as_str()))
+            .collect();
+        for transaction in candidates {
+            match validation::check_admission(&self.account_state, &pending, &transaction).map_err(BlockchainError::from) {
+                Ok(()) => pending.push(transaction),
+                Err(err) => warn!("Dropping transaction {} after reorg: {} ({})", transaction.id, err, err.code()),
+            }
+        }
+
+        let reorg = ReorgEvent {
+            timestamp: Utc::now(),
+            old_tip,
+            new_tip: chain.last().unwrap().hash.clone(),
+            fork_height: (fork_index - 1) as u64,
+            depth: orphaned.len() as u64,
+            affected_transactions: dropped.iter().map(|tx| tx.id.clone()).collect(),
+        };
+
+        let lowered = self.faucet.lower_to(new_height);
+
+        if let Some(writer) = &self.storage_writer {
+            // The pending transactions changed wholesale, a snapshot is simpler than a journal
+            let mut journal = self.mempool_journal.lock().unwrap();
+            let snapshot = pending.clone();
+            writer.write(move |storage| storage.save_mempool_snapshot(&snapshot))?;
+            journal.clear();
+
+            if lowered {
+                let grants = self.faucet.all();
+                writer.write(move |storage| storage.replace_faucet_grants(&grants))?;
+            }
+        }
+
+        warn!(
+            "Chain reorganized at height {}: {} blocks replaced, new tip {}",
+            reorg.fork_height, reorg.depth, reorg.new_tip
+        );
+        self.mining.tip_changed(&reorg.new_tip);
+
+        self.reorgs.lock().unwrap().push(reorg.clone());
+        self.observers.reorg(&reorg, &orphaned, &chain)?;
+        drop(chain);
+        drop(pending);
+        self.wait_for_storage()?;
+
+        Ok(reorg)
+    }
+
+    /// Takes a block mined elsewhere, choosing the branch with the most work
+    ///
+    /// A block following the tip is appended. A block of a competing branch
+    /// is kept, and once its branch has more cumulative work than the chain
+    /// since their last shared block, the chain reorganizes onto it. Either
+    /// way the blocks are validated in full. A block whose height doesn't
+    /// follow its parent's is refused, and so is a competing block once
+    /// `MAX_FORK_BLOCKS` are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block
+    ///
+    /// # Returns
+    ///
+    /// Result with what became of the block, or the error of the invalid block or branch
+    pub fn receive_block(&self, block: Block) -> Result<BlockOutcome, BlockchainError> {
+        let tip = self.get_last_block();
+        if block.previous_hash == tip.hash {
+            self.append_block(block)?;
+            self.forks.prune(tip.index + 1);
+            return Ok(BlockOutcome::Extended);
+        }
+
+        let on_chain: HashMap<String, u64> = self
+            .chain
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|known| (known.hash.clone(), known.index))
+            .collect();
+        if on_chain.contains_key(&block.hash) || self.forks.contains(&block.hash) {
+            return Ok(BlockOutcome::Known);
+        }
+        let parent_index = match on_chain.get(&block.previous_hash) {
+            Some(index) => *index,
+            None => match self.forks.get(&block.previous_hash) {
+                Some(parent) => parent.index,
+                None => return Ok(BlockOutcome::Orphan),
+            },
+        };
+
+        // The height comes from the peer, the rest of the fork choice relies on it
+        if block.index != parent_index + 1 {
+            return Err(BlockchainError::InvalidBlock(format!(
+                "Block {} does not follow its parent at height {}",
+                block.index, parent_index
+            )));
+        }
+        if block.index + MAX_FORK_DEPTH <= tip.index {
+            return Err(BlockchainError::InvalidChain(format!(
+                "Block {} forks more than {} blocks below the tip",
+                block.index, MAX_FORK_DEPTH
+            )));
+        }
+
+        let (hash, index) = (block.hash.clone(), block.index);
+        if !self.forks.insert(block) {
+            return Err(BlockchainError::InvalidChain(format!(
+                "Block {} can't be kept, there are {} blocks of competing branches already",
+                index, MAX_FORK_BLOCKS
+            )));
+        }
+        let branch = match self.forks.branch(&hash, |hash| on_chain.contains_key(hash)) {
+            Some(branch) => branch,
+            None => return Ok(BlockOutcome::Orphan),
+        };
+        let hashes: Vec<String> = branch.iter().map(|block| block.hash.clone()).collect();
+
+        // A branch kept earlier may follow the tip after a reorganization
+        if branch[0].previous_hash == tip.hash {
+            self.forks.remove(&hashes);
+            for block in branch {
+                self.append_block(block)?;
+            }
+            return Ok(BlockOutcome::Extended);
+        }
+
+        // The chain may have changed since, so its length is checked again
+        let chain = self.get_chain();
+        let replaced = chain.get(branch[0].index as usize..).ok_or_else(|| {
+            BlockchainError::InvalidChain(format!("Branch starts at {}, past the tip of the chain", branch[0].index))
+        })?;
+        if self.chain_work(&branch) <= self.chain_work(replaced) {
+            return Ok(BlockOutcome::Stored);
+        }
+
+        match self.reorganize(branch) {
+            Ok(reorg) => Ok(BlockOutcome::Reorganized(reorg)),
+            Err(err) => {
+                // The branch can't be applied, however long it grows
+                self.forks.remove(&hashes);
+                Err(err)
+            }
+        }
+    }
+
+    /// Takes a block a peer announced
+    ///
+    /// The block must hash to its hash and meet the difficulty of its height
+    /// before anything else is done with it, so peers can't fill the store of
+    /// competing branches with blocks that cost nothing to make. It then goes
+    /// to the fork choice, see `receive_block`, which validates its link to
+    /// the previous block and its transactions. Blocks the node already has
+    /// come back as known, so the network service doesn't relay them again.
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block
+    ///
+    /// # Returns
+    ///
+    /// Result with what became of the block, or the error of the invalid block or branch
+    pub fn handle_incoming_block(&self, block: Block) -> Result<BlockOutcome, BlockchainError> {
+        if self.forks.contains(&block.hash) {
+            return Ok(BlockOutcome::Known);
+        }
+        validation::check_proof_of_work(&block, &self.rules_at(block.index))?;
+        self.receive_block(block)
+    }
+
+    /// Gets the cumulative work of blocks, see `ValidationRules::block_work`
+    fn chain_work(&self, blocks: &[Block]) -> u128 {
+        blocks
+            .iter()
+            .map(|block| self.rules_at(block.index).block_work())
+            .fold(0, u128::saturating_add)
+    }
+
+    /// Records a mempool change for the storage journal
+    ///
+    /// Must be called while holding the pending transactions lock so the
+    /// journal keeps the admission order.
+    fn journal(&self, entry: MempoolJournalEntry) {
+        if self.storage.is_some() {
+            self.mempool_journal.lock().unwrap().push(entry);
+        }
+    }
+
+    /// Records the block being mined in the storage and waits until it is written
+    ///
+    /// Must be called while holding the pending transactions lock, before
+    /// the transactions taken for the block are journaled as removed.
+    fn save_mining_template(&self, template: MiningTemplate) -> Result<(), BlockchainError> {
+        if let Some(writer) = &self.storage_writer {
+            writer.write(move |storage| storage.save_mining_template(&template))?;
+            writer.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered mempool changes to the storage journal
+    ///
+    /// # Returns
+    ///
+    /// Result with the number of journal entries written
+    pub fn flush_mempool_journal(&self) -> Result<usize, BlockchainError> {
+        let writer = match &self.storage_writer {
+            Some(writer) => writer,
+            None => return Ok(0),
+        };
+
+        // Hand the entries over while holding the buffer, so a concurrent snapshot can't be overtaken
+        let mut journal = self.mempool_journal.lock().unwrap();
+        if journal.is_empty() {
+            return Ok(0);
+        }
+
+        let entries = std::mem::take(&mut *journal);
+        let written = entries.len();
+        writer.write(move |storage| {
+            storage.append_mempool_journal(&entries)?;
+            storage.flush()
+        })?;
+        drop(journal);
+
+        self.wait_for_storage()?;
+        Ok(written)
+    }
+
+    /// Writes a full snapshot of the pending transactions and truncates the journal
+    ///
+    /// # Returns
+    ///
+    /// Result with the number of pending transactions saved
+    pub fn persist_mempool(&self) -> Result<usize, BlockchainError> {
+        let writer = match &self.storage_writer {
+            Some(writer) => writer,
+            None => return Ok(0),
+        };
+
+        let pending = self.pending_transactions.lock().unwrap();
+        let mut journal = self.mempool_journal.lock().unwrap();
+
+        let snapshot = pending.clone();
+        let saved = snapshot.len();
+        writer.write(move |storage| {
+            storage.save_mempool_snapshot(&snapshot)?;
+            storage.flush()
+        })?;
+        journal.clear();
+        drop(journal);
+        drop(pending);
+
+        self.wait_for_storage()?;
+        Ok(saved)
+    }
+
+    /// Restores the pending transactions from the mempool snapshot and journal
+    ///
+    /// The transactions taken for a block the node stopped mining before it
+    /// was on the chain come back first, being the oldest. Transactions that
+    /// were mined in the meantime or are no longer valid against the loaded
+    /// account state are dropped.
+    ///
+    /// # Returns
+    ///
+    /// Result with () if successful
+    fn recover_mempool(&self) -> Result<(), BlockchainError> {
+        let storage = match &self.storage {
+            Some(storage) => storage,
+            None => return Ok(()),
+        };
+
+        let template = storage.get_mining_template()?;
+        let mut recovered = template
+            .as_ref()
+            .map(MiningTemplate::taken_transactions)
+            .unwrap_or_default();
+        for transaction in mempool::replay(storage.get_mempool_snapshot()?, storage.get_mempool_journal()?) {
+            if !recovered.iter().any(|tx| tx.id == transaction.id) {
+                recovered.push(transaction);
+            }
+        }
+        if let Some(template) = &template {
+            info!(
+                "Block {} was still being mined, returning its transactions not on the chain to the mempool",
+                template.height
+            );
+        }
+        if recovered.is_empty() {
+            storage.clear_mining_template()?;
+            return Ok(());
+        }
+
+        let mined: HashSet<String> = self
+            .chain
+            .lock()
+            .unwrap()
+            .iter()
+            .flat_map(|block| block.transactions.iter())
+            .map(|tx| tx.id.clone())
+            .collect();
+
+        let mut pending = self.pending_transactions.lock().unwrap();
+        let total = recovered.len();
+
+        for transaction in recovered {
+            if mined.contains(&transaction.id) {
+                continue;
+            }
+
+            match validation::check_admission(&self.account_state, &pending, &transaction).map_err(BlockchainError::from) {
+                Ok(()) => pending.push(transaction),
+                Err(err) => warn!("Dropping recovered transaction {}: {} ({})", transaction.id, err, err.code()),
+            }
+        }
+
+        info!("Recovered {} of {} pending transactions from the mempool journal", pending.len(), total);
+
+        storage.save_mempool_snapshot(&pending)?;
+        storage.clear_mining_template()?;
+        Ok(())
+    }
+
+    /// Performs proof of work to find a valid hash
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the new block
+    /// * `transactions` - The transactions to include in the block
+    /// * `previous_hash` - The hash of the previous block
+    /// * `cancel` - Token stopping the search
+    ///
+    /// # Returns
+    ///
+    /// The newly mined block with a valid proof, None if the search was cancelled
+    fn proof_of_work(
+        &self,
+        index: u64,
+        transactions: Vec<Transaction>,
+        previous_hash: String,
+        cancel: &CancellationToken,
+    ) -> Option<Block> {
+        // Miners starting from the same proof would try the same hashes first
+        let start: u64 = if self.randomize_proof_of_work { rand::random() } else { 0 };
+        let threads = match self.mining_threads {
+            0 => std::thread::available_parallelism().map_or(1, |threads| threads.get()),
+            threads => threads,
+        };
+
+        search_proof(
+            &Block::new(index, transactions, start, previous_hash),
+            start,
+            self.rules.difficulty,
+            threads,
+            cancel,
+        )
+    }
+
+    /// Gets the entire blockchain
+    ///
+    /// # Returns
+    ///
+    /// A vector of all blocks in the chain
+    pub fn get_chain(&self) -> Vec<Block> {
+        self.chain.lock().unwrap().clone()
+    }
+
+    /// Gets the latest block created at or before a moment
+    ///
+    /// Binary-searches the chain by timestamp, relying on validation keeping
+    /// block timestamps from decreasing with height.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - The moment to look up
+    ///
+    /// # Returns
+    ///
+    /// The block, or None if the moment is before the genesis block
+    pub fn get_block_at(&self, time: DateTime<Utc>) -> Option<Block> {
+        let chain = self.chain.lock().unwrap();
+        let after = chain.partition_point(|block| block.timestamp <= time);
+
+        after.checked_sub(1).map(|index| chain[index].clone())
+    }
+
+    /// Gets the block following a block
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The hash of the block
+    ///
+    /// # Returns
+    ///
+    /// The next block, None if the block is the tip, or BlockchainError::BlockNotFound
+    /// if no block of the chain has the hash
+    pub fn get_block_after(&self, hash: &str) -> Result<Option<Block>, BlockchainError> {
+        let chain = self.chain.lock().unwrap();
+
+        match chain.iter().position(|block| block.hash == hash) {
+            Some(position) => Ok(chain.get(position + 1).cloned()),
+            None => Err(BlockchainError::BlockNotFound(hash.to_string())),
+        }
+    }
+
+    /// Gets the state of the chain right after one of its blocks
+    ///
+    /// # Arguments
+    ///
+    /// * `at` - The block, by hash or height
+    ///
+    /// # Returns
+    ///
+    /// The blocks up to and including that block with the accounts they lead to,
+    /// or BlockchainError::BlockNotFound if the chain has no such block
+    pub fn state_at(&self, at: &BlockRef) -> Result<ChainState, BlockchainError> {
+        let blocks = self.blocks_up_to(at)?;
+        Ok(self.checkpoints.state_at(&blocks, &self.faucet.all())?)
+    }
+
+    /// Looks up a registered name
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name to look up
+    /// * `at` - The block to look it up at, the tip if None
+    ///
+    /// # Returns
+    ///
+    /// The registration, None if the name is free, or BlockchainError::BlockNotFound
+    /// if the chain has no such block
+    pub fn get_name(&self, name: &str, at: Option<&BlockRef>) -> Result<Option<NameRecord>, BlockchainError> {
+        match at {
+            Some(at) => {
+                let blocks = self.blocks_up_to(at)?;
+                let registry = NameRegistry::new();
+                registry.rebuild(&blocks);
+                Ok(registry.lookup(name, blocks.len() as u64 - 1))
+            }
+            None => Ok(self.names.lookup(name, self.get_last_block().index)),
+        }
+    }
+
+    /// Resolves the recipient of a new transaction, which may be a registered name
+    ///
+    /// # Arguments
+    ///
+    /// * `recipient` - An address or a name
+    ///
+    /// # Returns
+    ///
+    /// The address, or BlockchainError::NameError if the name is not registered
+    pub fn resolve_recipient(&self, recipient: &str) -> Result<Address, BlockchainError> {
+        if names::validate_name(recipient).is_err() {
+            return Ok(Address(recipient.to_string()));
+        }
+
+        self.get_name(recipient, None)?
+            .map(|record| record.owner)
+            .ok_or_else(|| BlockchainError::NameError(NameError::NotRegistered(recipient.to_string())))
+    }
+
+    /// Gets an open lock
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - ID of the transaction that made the lock
+    ///
+    /// # Returns
+    ///
+    /// The lock, None if it was never made or is spent
+    pub fn get_lock(&self, id: &str) -> Option<ScriptLock> {
+        self.scripts.get(id)
+    }
+
+    /// Gets the open locks an address may spend
+    ///
+    /// # Arguments
+    ///
+    /// * `recipient` - The address
+    ///
+    /// # Returns
+    ///
+    /// The locks, oldest first
+    pub fn get_locks_for(&self, recipient: &Address) -> Vec<ScriptLock> {
+        self.scripts.for_recipient(recipient)
+    }
+
+    /// Gets the session keys an account authorized
+    ///
+    /// # Arguments
+    ///
+    /// * `owner` - The account
+    ///
+    /// # Returns
+    ///
+    /// The keys, expired and used up ones included, oldest first
+    pub fn get_session_keys(&self, owner: &Address) -> Vec<SessionKey> {
+        self.sessions.for_owner(owner)
+    }
+
+    /// Gets the inheritance switch of an account
+    ///
+    /// # Arguments
+    ///
+    /// * `owner` - The account
+    ///
+    /// # Returns
+    ///
+    /// The switch, if the account designated a beneficiary
+    pub fn get_inheritance(&self, owner: &Address) -> Option<InheritanceSwitch> {
+        self.inheritances.get(owner)
+    }
+
+    /// Gets the inheritance switches naming an address as beneficiary
+    ///
+    /// # Arguments
+    ///
+    /// * `beneficiary` - The address
+    ///
+    /// # Returns
+    ///
+    /// The switches, oldest first
+    pub fn get_inheritances_for(&self, beneficiary: &Address) -> Vec<InheritanceSwitch> {
+        self.inheritances.for_beneficiary(beneficiary)
+    }
+
+    /// Gets the blocks of the chain up to and including one of them
+    fn blocks_up_to(&self, at: &BlockRef) -> Result<Vec<Block>, BlockchainError> {
+        let chain = self.chain.lock().unwrap();
+        let position = Self::position_of(&chain, at)?;
+        Ok(chain[..=position].to_vec())
+    }
+
+    /// Gets a block of the chain by hash or height
+    ///
+    /// # Arguments
+    ///
+    /// * `at` - The block, by hash or height
+    ///
+    /// # Returns
+    ///
+    /// The block, or BlockchainError::BlockNotFound if the chain has no such block
+    pub fn get_block_by_ref(&self, at: &BlockRef) -> Result<Block, BlockchainError> {
+        let chain = self.chain.lock().unwrap();
+        let position = Self::position_of(&chain, at)?;
+        Ok(chain[position].clone())
+    }
+
+    /// Finds the position of a block in the chain
+    fn position_of(chain: &[Block], at: &BlockRef) -> Result<usize, BlockchainError> {
+        let position = match at {
+            BlockRef::Hash(hash) => chain.iter().position(|block| &block.hash == hash),
+            BlockRef::Height(height) => Some(*height as usize).filter(|&height| height < chain.len()),
+        };
+
+        position.ok_or_else(|| BlockchainError::BlockNotFound(at.to_string()))
+    }
+
+    /// Gets the bytes a block of the chain and its transactions were hashed from
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The hash of the block
+    ///
+    /// # Returns
+    ///
+    /// The raw bytes, as stored when the chain has storage, or BlockchainError::BlockNotFound
+    /// if no block of the chain has the hash
+    pub fn get_raw_block(&self, hash: &str) -> Result<RawBlock, BlockchainError> {
+        let block = self
+            .chain
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|block| block.hash == hash)
+            .cloned()
+            .ok_or_else(|| BlockchainError::BlockNotFound(hash.to_string()))?;
+
+        match &self.storage {
+            Some(storage) => Ok(storage.get_raw_block(hash)?),
+            None => Ok(block.raw()),
+        }
+    }
+
+    /// Gets the headers of a range of blocks, with the bloom filters of their addresses
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - Height of the first block
+    /// * `count` - How many blocks to read at most
+    /// * `address` - Only blocks whose filter may contain this address, when set
+    ///
+    /// # Returns
+    ///
+    /// The headers in chain order, with the filters as stored when the chain has storage
+    pub fn get_block_headers(
+        &self,
+        from: u64,
+        count: usize,
+        address: Option<&Address>,
+    ) -> Result<Vec<BlockHeader>, BlockchainError> {
+        let blocks: Vec<Block> = self
+            .chain
+            .lock()
+            .unwrap()
+            .iter()
+            .skip(usize::try_from(from).unwrap_or(usize::MAX))
+            .take(count)
+            .cloned()
+            .collect();
+
+        let mut headers = Vec::new();
+        for block in blocks {
+            let bloom = self.address_bloom(&block)?;
+            if address.is_none_or(|address| bloom.may_contain(address)) {
+                headers.push(block.header(bloom));
+            }
+        }
+
+        Ok(headers)
+    }
+
+    /// Gets the bloom filter of the addresses of a block, as stored when the chain has storage
+    fn address_bloom(&self, block: &Block) -> Result<AddressBloom, BlockchainError> {
+        match &self.storage {
+            Some(storage) => Ok(storage.get_address_bloom(&block.hash)?),
+            None => Ok(AddressBloom::for_block(block)),
+        }
+    }
+
+    /// Gets hashes of the chain a peer can find the last shared block by
+    ///
+    /// The most recent blocks come one by one, older ones ever sparser, down
+    /// to the genesis block, so the locator stays short on a long chain.
+    ///
+    /// # Returns
+    ///
+    /// The hashes, tip first
+    pub fn get_block_locator(&self) -> Vec<String> {
+        let chain = self.chain.lock().unwrap();
+        let mut locator = Vec::new();
+        let mut index = chain.len() - 1;
+        let mut step = 1;
+        loop {
+            locator.push(chain[index].hash.clone());
+            if index == 0 {
+                return locator;
+            }
+            if locator.len() >= 10 {
+                step *= 2;
+            }
+            index = index.saturating_sub(step);
+        }
+    }
+
+    /// Gets the blocks after the most recent block of a locator the chain has
+    ///
+    /// # Arguments
+    ///
+    /// * `locator` - Hashes of a peer's chain, tip first, see `get_block_locator`
+    /// * `limit` - The maximum number of blocks to return
+    ///
+    /// # Returns
+    ///
+    /// The blocks following the shared block, empty if none is shared or the peer is up to date
+    pub fn get_blocks_after_locator(&self, locator: &[String], limit: usize) -> Vec<Block> {
+        let chain = self.chain.lock().unwrap();
+        let shared = locator
+            .iter()
+            .find_map(|hash| chain.iter().rposition(|block| &block.hash == hash));
+
+        match shared {
+            Some(index) => chain.iter().skip(index + 1).take(limit).cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Gets the headers after the most recent block of a locator the chain has
+    ///
+    /// # Arguments
+    ///
+    /// * `locator` - Hashes of a peer's chain, tip first, see `get_block_locator`
+    /// * `limit` - The maximum number of headers to return
+    ///
+    /// # Returns
+    ///
+    /// The headers following the shared block, empty if none is shared or the peer is up to date
+    pub fn get_headers_after_locator(&self, locator: &[String], limit: usize) -> Result<Vec<BlockHeader>, BlockchainError> {
+        self.get_blocks_after_locator(locator, limit)
+            .into_iter()
+            .map(|block| Ok(block.header(self.address_bloom(&block)?)))
+            .collect()
+    }
+
+    /// Gets blocks of the chain by their hashes
+    ///
+    /// # Arguments
+    ///
+    /// * `hashes` - The hashes of the blocks
+    ///
+    /// # Returns
+    ///
+    /// The blocks in the order of the hashes, skipping hashes no block of the chain has
+    pub fn get_blocks_by_hash(&self, hashes: &[String]) -> Vec<Block> {
+        let wanted: HashSet<&str> = hashes.iter().map(String::as_str).collect();
+        let chain = self.chain.lock().unwrap();
+        let found: HashMap<&str, &Block> = chain
+            .iter()
+            .filter(|block| wanted.contains(block.hash.as_str()))
+            .map(|block| (block.hash.as_str(), block))
+            .collect();
+
+        hashes
+            .iter()
+            .filter_map(|hash| found.get(hash.as_str()).map(|block| (*block).clone()))
+            .collect()
+    }
+
+    /// Catches up with a peer whose chain is longer, headers first
+    ///
+    /// The node asks the peer for the headers after the last block both
+    /// chains share and checks that they link up and meet the difficulty.
+    /// Only then are their blocks fetched, in batches, and applied through
+    /// `append_block`, or through `reorganize` when the peer is on a longer
+    /// competing branch, so every block is validated like a mined one. A
+    /// peer whose chain is not longer changes nothing.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer` - The peer to sync from
+    ///
+    /// # Returns
+    ///
+    /// Result with what the sync changed, or the first error. Blocks applied
+    /// before an invalid one stay applied.
+    pub async fn sync_from<P: SyncPeer + ?Sized>(&self, peer: &P) -> Result<SyncReport, BlockchainError> {
+        let mut report = SyncReport::default();
+
+        loop {
+            let (headers, more) = self.fetch_headers(peer).await?;
+            let first = match headers.first() {
+                Some(first) => first.clone(),
+                None => break,
+            };
+            report.headers += headers.len();
+
+            if first.previous_hash == self.get_last_block().hash {
+                // The peer is ahead on the same branch
+                for batch in headers.chunks(MAX_SYNC_BLOCKS) {
+                    let blocks = self.fetch_blocks(peer, batch).await?;
+                    let count = blocks.len();
+                    self.run_blocking(move |blockchain| {
+                        blocks.into_iter().try_for_each(|block| blockchain.append_block(block))
+                    })
+                    .await?;
+                    report.blocks += count;
+                }
+            } else {
+                // The peer is on a longer competing branch
+                let mut branch = Vec::with_capacity(headers.len());
+                for batch in headers.chunks(MAX_SYNC_BLOCKS) {
+                    branch.extend(self.fetch_blocks(peer, batch).await?);
+                }
+                let count = branch.len();
+                let reorg = self.run_blocking(move |blockchain| blockchain.reorganize(branch)).await?;
+                report.blocks += count;
+                report.reorgs.push(reorg);
+            }
+
+            if !more {
+                break;
+            }
+        }
+
+        report.height = self.get_last_block().index;
+        Ok(report)
+    }
+
+    /// Gets the validated headers of a peer's chain after the last block it shares with the chain
+    ///
+    /// A competing branch is followed until it is longer than the chain.
+    ///
+    /// # Returns
+    ///
+    /// The headers oldest first, empty if the peer's chain is not longer, and
+    /// whether the peer may have more
+    async fn fetch_headers<P: SyncPeer + ?Sized>(&self, peer: &P) -> Result<(Vec<BlockHeader>, bool), BlockchainError> {
+        let mut headers = peer.get_headers(self.get_block_locator(), MAX_SYNC_HEADERS).await?;
+        headers.truncate(MAX_SYNC_HEADERS);
+        let first = match headers.first() {
+            Some(first) => first.clone(),
+            None => return Ok((Vec::new(), false)),
+        };
+
+        let parent = self
+            .get_block_by_ref(&BlockRef::Hash(first.previous_hash.clone()))
+            .map_err(|_| {
+                BlockchainError::InvalidChain(format!("Header of block {} does not attach to the chain", first.index))
+            })?;
+        self.check_headers(&parent.header(AddressBloom::new()), &headers)?;
+
+        let height = self.get_last_block().index;
+        let mut more = headers.len() == MAX_SYNC_HEADERS;
+        while more && headers.last().is_some_and(|last| last.index <= height) {
+            let last = headers.last().unwrap().clone();
+            let mut next = peer.get_headers(vec![last.hash.clone()], MAX_SYNC_HEADERS).await?;
+            next.truncate(MAX_SYNC_HEADERS);
+            self.check_headers(&last, &next)?;
+            more = next.len() == MAX_SYNC_HEADERS;
+            headers.extend(next);
+        }
+
+        if headers.last().is_none_or(|last| last.index <= height) {
+            return Ok((Vec::new(), false));
+        }
+        Ok((headers, more))
+    }
+
+    /// Checks that headers follow each other, starting from a parent
+    fn check_headers(&self, parent: &BlockHeader, headers: &[BlockHeader]) -> Result<(), BlockchainError> {
+        let mut parent = parent;
+        for header in headers {
+            validation::check_header(parent, header, &self.rules_at(header.index))?;
+            parent = header;
+        }
+        Ok(())
+    }
+
+    /// Fetches the blocks of validated headers from a peer
+    async fn fetch_blocks<P: SyncPeer + ?Sized>(&self, peer: &P, headers: &[BlockHeader]) -> Result<Vec<Block>, BlockchainError> {
+        let hashes = headers.iter().map(|header| header.hash.clone()).collect();
+        let blocks = peer.get_blocks(hashes).await?;
+
+        // A block that claims the hash but differs in content fails check_block
+        if blocks.len() != headers.len() || blocks.iter().zip(headers).any(|(block, header)| block.hash != header.hash) {
+            return Err(BlockchainError::InvalidChain(
+                "Peer sent blocks that don't match their headers".to_string(),
+            ));
+        }
+        Ok(blocks)
+    }
+
+    /// Runs a blocking call of the blockchain off the async runtime
+    async fn run_blocking<T, F>(&self, call: F) -> Result<T, BlockchainError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Blockchain) -> Result<T, BlockchainError> + Send + 'static,
+    {
+        let blockchain = self.clone();
+        tokio::task::spawn_blocking(move || call(&blockchain))
+            .await
+            .map_err(|err| BlockchainError::SystemError(err.to_string()))?
+    }
+
+    /// Waits until a block follows a block
+    ///
+    /// Returns right away if the block is not the tip anymore, otherwise as
+    /// soon as a block is appended or the chain reorganizes.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The hash of the block
+    /// * `timeout` - How long to wait at most
+    ///
+    /// # Returns
+    ///
+    /// The next block, None if none was appended in time, or BlockchainError::BlockNotFound
+    /// if no block of the chain has the hash
+    pub async fn wait_for_block_after(&self, hash: &str, timeout: Duration) -> Result<Option<Block>, BlockchainError> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        // Subscribe before looking, so a block appended in between is not missed
+        let mut events = self.subscribe();
+
+        loop {
+            if let Some(block) = self.get_block_after(hash)? {
+                return Ok(Some(block));
+            }
+
+            // Lagging behind only means there is news, so any outcome but the deadline checks again
+            if tokio::time::timeout_at(deadline, events.recv()).await.is_err() {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Gets all pending transactions
+    ///
+    /// # Returns
+    ///
+    /// A vector of all pending transactions
+    pub fn get_pending_transactions(&self) -> Vec<Transaction> {
+        self.pending_transactions.lock().unwrap().clone()
+    }
+
+    /// Gets the pending transactions with when they entered the mempool
+    pub fn get_pending_entries(&self) -> Vec<PendingTransaction> {
+        self.arrivals.entries(&self.pending_transactions.lock().unwrap(), Utc::now())
+    }
+
+    /// Gets the number of pending transactions, without copying them
+    pub fn pending_count(&self) -> usize {
+        self.pending_transactions.lock().unwrap().len()
+    }
+
+    /// Gets the number of confirmations of a transaction
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction_id` - The ID of the transaction
+    ///
+    /// # Returns
+    ///
+    /// The number of blocks on top of and including the block containing the
+    /// transaction, or None if the transaction is not in the chain
+    pub fn get_transaction_confirmations(&self, transaction_id: &str) -> Option<u64> {
+        let chain = self.chain.lock().unwrap();
+        let tip_index = chain.last()?.index;
+
+        chain
+            .iter()
+            .rev()
+            .find(|block| block.transactions.iter().any(|tx| tx.id == transaction_id))
+            .map(|block| tip_index - block.index + 1)
+    }
+
+    /// Gets the confirmed transactions sent or received by an address
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address to get the history for
+    ///
+    /// # Returns
+    ///
+    /// The index of the containing block and the transaction, oldest first
+    pub fn get_address_history(&self, address: &Address) -> Vec<(u64, Transaction)> {
+        let chain = self.chain.lock().unwrap();
+
+        chain
+            .iter()
+            .flat_map(|block| {
+                block
+                    .transactions
+                    .iter()
+                    .filter(|tx| &tx.sender == address || &tx.recipient == address)
+                    .map(move |tx| (block.index, tx.clone()))
+            })
+            .collect()
+    }
+
+    /// Finds the confirmed transactions matching a filter
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The criteria to match
+    /// * `page` - The page to return, starting at 1
+    /// * `page_size` - The maximum number of transactions per page
+    ///
+    /// # Returns
+    ///
+    /// The requested page of matching transactions, newest first
+    pub fn query_transactions(&self, filter: &TransactionFilter, page: usize, page_size: usize) -> TransactionPage {
+        self.index.query(filter, page, page_size)
+    }
+
+    /// Proves when a document hash was first anchored
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The anchored hash, in lowercase
+    ///
+    /// # Returns
+    ///
+    /// The block of the first transaction anchoring the hash with its inclusion
+    /// proof, None if no confirmed transaction anchors it
+    pub fn get_anchor(&self, hash: &str) -> Option<AnchorProof> {
+        let entry = self.index.find_data(hash)?;
+
+        let chain = self.chain.lock().unwrap();
+        let block = chain.get(entry.block_index as usize)?;
+        let position = block
+            .transactions
+            .iter()
+            .position(|transaction| transaction.id == entry.transaction.id)?;
+        let height = chain.last()?.index;
+
+        AnchorProof::new(block, position, height)
+    }
+
+    /// Gets the totals of an address per counterparty
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address to summarize
+    /// * `at_height` - Only count transactions confirmed at or below this height
+    ///
+    /// # Returns
+    ///
+    /// One summary per counterparty, largest total volume first
+    pub fn get_counterparties(&self, address: &Address, at_height: Option<u64>) -> Vec<CounterpartySummary> {
+        self.index.counterparties(address, at_height)
+    }
+
+    /// Gets the chain activity per period
+    ///
+    /// # Arguments
+    ///
+    /// * `granularity` - The length of each period
+    /// * `limit` - Only return the most recent periods if set
+    ///
+    /// # Returns
+    ///
+    /// The periods with activity, oldest first
+    pub fn get_activity_report(&self, granularity: Granularity, limit: Option<usize>) -> Vec<ActivityPeriod> {
+        self.activity.report(granularity, limit)
+    }
+
+    /// Sets whether mining randomizes its search
+    ///
+    /// When enabled, the proof search starts at a random value and the coinbase
+    /// carries a random extra-nonce, so several miners working on the same
+    /// chain tip do not duplicate each other's work. Disabling it makes mining
+    /// deterministic, which is easier to follow when teaching.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether to randomize the search
+    pub fn set_randomized_proof_of_work(&mut self, enabled: bool) {
+        self.randomize_proof_of_work = enabled;
+    }
+
+    /// Sets how many threads search for a proof of work
+    ///
+    /// The threads split the proofs between them and the lowest proof past
+    /// the start that meets the difficulty wins, so the block is the same
+    /// whatever the number of threads.
+    ///
+    /// # Arguments
+    ///
+    /// * `threads` - Number of threads, 0 for one per CPU core
+    pub fn set_mining_threads(&mut self, threads: usize) {
+        self.mining_threads = threads;
+    }
+
+    /// Makes the node a read-only follower
+    ///
+    /// A follower takes its blocks from other nodes through `append_block`
+    /// and `reorganize` and serves the reads. Transactions, mining and
+    /// resets are refused, so its mempool stays empty.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Whether the node only follows
+    pub fn set_follower(&mut self, enabled: bool) {
+        self.follower = enabled;
+    }
+
+    /// Checks whether the node is a read-only follower
+    pub fn is_follower(&self) -> bool {
+        self.follower
+    }
+
+    /// Sets the address the blocks mined with `mine` pay
+    ///
+    /// # Arguments
+    ///
+    /// * `miner` - The address, none to only mine with `mine_block`
+    pub fn set_miner_address(&mut self, miner: Option<Address>) {
+        self.miner = miner;
+    }
+
+    /// Gets the address the blocks mined with `mine` pay
+    pub fn get_miner_address(&self) -> Option<Address> {
+        self.miner.clone()
+    }
+
+    /// Sets the number of leading zeros the hash of a block needs
+    ///
+    /// New blocks are mined at the difficulty and blocks of other nodes are
+    /// checked against it, so every node of the network must use the same
+    /// one. The blocks already on the chain are not checked again.
+    ///
+    /// # Arguments
+    ///
+    /// * `difficulty` - Leading zeros of the hex hash, `DIFFICULTY` by default
+    pub fn set_difficulty(&mut self, difficulty: u8) {
+        self.rules.difficulty = difficulty;
+    }
+
+    /// Gets the number of leading zeros the hash of a block needs
+    pub fn get_difficulty(&self) -> u8 {
+        self.rules.difficulty
+    }
+
+    /// Refuses a write of the node's own when it is a follower
+    fn check_writable(&self) -> Result<(), BlockchainError> {
+        if self.follower {
+            return Err(BlockchainError::ReadOnly);
+        }
+
+        Ok(())
+    }
+
+    /// Sets where the fees of new blocks go
+    ///
+    /// Blocks of a competing branch are checked against the policy as well,
+    /// so every node of the network must use the same one.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The fee policy
+    pub fn set_fee_policy(&mut self, policy: FeePolicy) {
+        self.rules.fee_policy = policy;
+    }
+
+    /// Audits the supply of the chain and where its fees went
+    pub fn get_supply_report(&self) -> SupplyReport {
+        let chain = self.chain.lock().unwrap();
+
+        SupplyReport::new(
+            &chain,
+            &self.account_state.get_all_accounts(),
+            self.rules.fee_policy.clone(),
+            |height| self.governance.parameters_at(height).mining_reward,
+        )
+    }
+
+    /// Books every balance change of the chain as double-entry ledger lines
+    pub fn get_ledger(&self) -> Ledger {
+        let chain = self.chain.lock().unwrap();
+
+        Ledger::new(
+            &chain,
+            &self.faucet.all(),
+            &self.account_state.get_all_accounts(),
+            |height| self.governance.parameters_at(height).mining_reward,
+        )
+    }
+
+    /// Gathers the health of the node for monitoring UIs
+    ///
+    /// # Returns
+    ///
+    /// The tip, mempool, recent block intervals, issuance, fee trend, peers and storage health
+    pub fn get_dashboard(&self) -> Dashboard {
+        let now = Utc::now();
+        let queued = self.queued_transactions.lock().unwrap().len();
+        let mempool = MempoolStatus::new(&self.pending_transactions.lock().unwrap(), queued, now);
+        let chain = self.chain.lock().unwrap();
+        let peers = self.peers.list();
+
+        Dashboard {
+            generated_at: now,
+            tip: TipStatus::new(chain.last().unwrap(), now),
+            mempool,
+            block_intervals: BlockIntervals::new(&chain),
+            issuance: IssuanceRate::new(&chain, |height| self.governance.parameters_at(height).mining_reward),
+            fees: FeeTrend::new(&chain, |height| self.governance.parameters_at(height)),
+            network: NetworkStatus {
+                network: self.genesis.info().network,
+                peers: peers.len(),
+                connected_peers: peers.iter().filter(|peer| peer.connected).count(),
+                banned_peers: peers.iter().filter(|peer| peer.banned).count(),
+                follower: self.follower,
+                side_blocks: self.forks.len(),
+                reorgs: self.reorgs.lock().unwrap().len(),
+                maintenance: self.get_maintenance_status(),
+            },
+            storage: match &self.storage {
+                Some(storage) => storage.health(),
+                None => StorageHealth::in_memory(),
+            },
+        }
+    }
+
+    /// Describes the emission of the chain, past and projected
+    ///
+    /// # Arguments
+    ///
+    /// * `period_blocks` - Blocks per period, at least 1
+    /// * `projected_periods` - Number of periods to project
+    pub fn get_economics_report(&self, period_blocks: u64, projected_periods: u64) -> EconomicsReport {
+        let chain = self.chain.lock().unwrap();
+
+        EconomicsReport::new(
+            &chain,
+            |height| self.governance.parameters_at(height).mining_reward,
+            &ConsensusRules::node(DEFAULT_TARGET_INTERVAL_SECS),
+            period_blocks,
+            projected_periods,
+        )
+    }
+
+    /// Gets the treasury's balance and the disbursements governance approved
+    pub fn get_treasury_status(&self) -> TreasuryStatus {
+        let disbursements = self.governance.disbursements();
+
+        TreasuryStatus {
+            address: TREASURY_ADDRESS.to_string(),
+            balance: self.account_state.get_account(&Address(TREASURY_ADDRESS.to_string())).balance,
+            fee_policy: self.rules.fee_policy.clone(),
+            disbursed: disbursements.iter().fold(0.0, |total, status| total + status.disbursed),
+            disbursements,
+        }
+    }
+
+    /// Gets the rules blocks at a height are validated with
+    fn rules_at(&self, height: u64) -> ValidationRules {
+        self.rules.with_parameters(&self.governance.parameters_at(height))
+    }
+
+    /// Gets the rules the block after some blocks is validated with, its base fee included
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks of the chain, genesis first
+    fn rules_after(&self, blocks: &[Block]) -> ValidationRules {
+        let height = blocks.last().map(|block| block.index + 1).unwrap_or(0);
+        self.rules_at(height).with_base_fee(self.base_fee_after(blocks))
+    }
+
+    /// Gets the base fee of the block after some blocks
+    ///
+    /// The base fee after the tip is kept, so appending a block takes one step
+    /// from it instead of replaying the fees of the whole chain.
+    fn base_fee_after(&self, blocks: &[Block]) -> f64 {
+        let parameters_at = |height| self.governance.parameters_at(height);
+        let Some(tip) = blocks.last() else {
+            return fees::base_fee_after(blocks, parameters_at);
+        };
+
+        let mut cached = self.base_fee.lock().unwrap();
+        let base_fee = match cached.as_ref() {
+            Some((hash, base_fee)) if *hash == tip.hash => return *base_fee,
+            Some((hash, base_fee)) if blocks.len() > 1 && *hash == blocks[blocks.len() - 2].hash => {
+                fees::next_base_fee(
+                    *base_fee,
+                    tip,
+                    parameters_at(tip.index).max_block_transactions,
+                    parameters_at(tip.index + 1).minimum_fee,
+                )
+            }
+            _ => fees::base_fee_after(blocks, parameters_at),
+        };
+
+        *cached = Some((tip.hash.clone(), base_fee));
+        base_fee
+    }
+
+    /// Gets the parameters in force for the next block
+    pub fn get_parameters(&self) -> ChainParameters {
+        self.governance.parameters_at(self.get_last_block().index + 1)
+    }
+
+    /// Gets the parameter changes that passed, oldest first
+    pub fn get_parameter_changes(&self) -> Vec<ParameterChange> {
+        self.governance.changes()
+    }
+
+    /// Gets the proposals, newest deadline first
+    pub fn get_proposals(&self) -> Vec<Proposal> {
+        self.governance.proposals()
+    }
+
+    /// Gets a proposal by the ID of the transaction that made it
+    pub fn get_proposal(&self, id: &str) -> Option<Proposal> {
+        self.governance.proposal(id)
+    }
+
+    /// Restricts who may send transactions and mine
+    ///
+    /// The changes already on the chain are applied under the new policy.
+    /// Blocks of a competing branch are checked against it as well.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The policy, an open policy lets everyone in
+    pub fn set_permission_policy(&self, policy: PermissionPolicy) {
+        let chain = self.chain.lock().unwrap();
+        self.permissions.set_policy(policy, &chain);
+    }
+
+    /// Gets the addresses allowed to send transactions and mine
+    pub fn get_permissions(&self) -> PermissionStatus {
+        self.permissions.status()
+    }
+
+    /// Checks if an address is frozen and can't send transactions
+    pub fn is_frozen(&self, address: &Address) -> bool {
+        self.permissions.is_frozen(address)
+    }
+
+    /// Gets the freeze orders on the chain, oldest first
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Only the orders about this address, all of them if None
+    pub fn get_freezes(&self, address: Option<&str>) -> Vec<FreezeRecord> {
+        self.permissions.freezes(address)
+    }
+
+    /// Sets which writers are paused for maintenance
+    ///
+    /// Waits for in-flight transaction admissions and mining to finish, so
+    /// nothing writes to the chain once a pause returns.
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - The new maintenance status, the default status resumes everything
+    pub fn set_maintenance(&self, status: MaintenanceStatus) {
+        let _pending = self.pending_transactions.lock().unwrap();
+
+        if status.is_active() {
+            warn!("Entering maintenance mode: {:?}", status);
+        } else {
+            info!("Leaving maintenance mode");
+        }
+
+        *self.maintenance.lock().unwrap() = status;
+    }
+
+    /// Gets the current maintenance status
+    pub fn get_maintenance_status(&self) -> MaintenanceStatus {
+        self.maintenance.lock().unwrap().clone()
+    }
+
+    /// Resets the chain to a new genesis block
+    ///
+    /// Drops every block, pending transaction, account, faucet deposit and
+    /// past reorganization, in memory and in storage. Records of the node itself,
+    /// like the keystore and the audit log, are kept.
+    ///
+    /// # Returns
+    ///
+    /// Result with the new genesis block
+    pub fn reset(&self) -> Result<Block, BlockchainError> {
+        self.check_writable()?;
+        let genesis_block = self.genesis.block();
+        let dropped = self.replace_state(vec![genesis_block.clone()], Vec::new(), Vec::new(), Vec::new(), Vec::new())?;
+
+        warn!("Chain reset to genesis {}, dropped {} blocks", genesis_block.hash, dropped.saturating_sub(1));
+
+        self.events.publish(ChainEvent::Reset {
+            genesis_hash: genesis_block.hash.clone(),
+        });
+
+        Ok(genesis_block)
+    }
+
+    /// Copies the storage into a snapshot
+    ///
+    /// Writes the pending transactions first and holds them while copying,
+    /// so no block is mined or transaction admitted halfway through.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The empty storage to copy into
+    ///
+    /// # Returns
+    ///
+    /// Result with the last block at the time of the snapshot
+    pub fn snapshot_storage(&self, target: &BlockchainStorage) -> Result<Block, BlockchainError> {
+        let storage = match &self.storage {
+            Some(storage) => storage,
+            None => return Err(BlockchainError::SystemError("No storage configured".to_string())),
+        };
+
+        let pending = self.pending_transactions.lock().unwrap();
+        let mut journal = self.mempool_journal.lock().unwrap();
+        self.wait_for_storage()?;
+        storage.save_mempool_snapshot(&pending)?;
+        storage.flush()?;
+        journal.clear();
+
+        storage.copy_into(target)?;
+        Ok(self.get_last_block())
+    }
+
+    /// Replaces the chain with the chain data of a snapshot
+    ///
+    /// Blocks, accounts, pending transactions, past reorganizations and
+    /// faucet deposits are taken from the snapshot. Records of the node itself stay as they are.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The storage of the snapshot
+    ///
+    /// # Returns
+    ///
+    /// Result with the last block of the restored chain
+    pub fn restore_storage(&self, source: &BlockchainStorage) -> Result<Block, BlockchainError> {
+        let blocks = source.get_all_blocks()?;
+        Self::check_links(&blocks)?;
+
+        let pending = mempool::replay(source.get_mempool_snapshot()?, source.get_mempool_journal()?);
+        let tip = blocks.last().unwrap().clone();
+        let grants = source.get_faucet_grants()?;
+        self.replace_state(blocks, source.get_all_accounts()?, pending, source.get_all_reorgs()?, grants)?;
+
+        warn!("Chain restored to block {} ({})", tip.index, tip.hash);
+
+        self.events.publish(ChainEvent::Restored {
+            index: tip.index,
+            hash: tip.hash.clone(),
+        });
+
+        Ok(tip)
+    }
+
+    /// Copies the chain into an in-memory node that can be experimented on
+    ///
+    /// The copy has the same blocks, accounts and validation rules but no
+    /// storage, no pending transactions and no permission policy, so nothing
+    /// done to it reaches this node.
+    ///
+    /// # Returns
+    ///
+    /// Result with the copy
+    pub fn scratch_copy(&self) -> Result<Blockchain, BlockchainError> {
+        let mut copy = Blockchain::with_genesis(self.genesis.clone());
+        copy.rules = self.rules.clone();
+        copy.replace_state(
+            self.get_chain(),
+            self.account_state.get_all_accounts(),
+            Vec::new(),
+            Vec::new(),
+            self.faucet.all(),
+        )?;
+
+        Ok(copy)
+    }
+
+    /// Replaces the chain and the accounts with those of another node
+    ///
+    /// Used by a replica to copy its primary, whose balances may include
+    /// funds added outside the chain. The blocks must link from a genesis
+    /// block, the accounts are trusted as they are.
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks of the other node, genesis first
+    /// * `accounts` - The accounts after the last block
+    ///
+    /// # Returns
+    ///
+    /// Result with the number of blocks replaced
+    pub fn replace_chain(&self, blocks: Vec<Block>, accounts: Vec<Account>) -> Result<usize, BlockchainError> {
+        Self::check_links(&blocks)?;
+        self.replace_state(blocks, accounts, Vec::new(), Vec::new(), Vec::new())
+    }
+
+    /// Checks that blocks form a chain from a genesis block
+    ///
+    /// Balances are not replayed, since accounts may have been funded outside
+    /// the chain.
+    fn check_links(blocks: &[Block]) -> Result<(), BlockchainError> {
+        let genesis = blocks
+            .first()
+            .ok_or_else(|| BlockchainError::InvalidChain("No blocks found".to_string()))?;
+        if genesis.index != 0 {
+            return Err(BlockchainError::InvalidChain("Chain does not start at genesis".to_string()));
+        }
+
+        for (previous, block) in blocks.iter().zip(blocks.iter().skip(1)) {
+            if block.index != previous.index + 1 || block.previous_hash != previous.hash {
+                return Err(BlockchainError::InvalidBlock(format!(
+                    "Block {} does not link to block {}",
+                    block.index, previous.index
+                )));
+            }
+        }
+        for block in blocks {
+            if block.hash != block.calculate_hash() {
+                return Err(BlockchainError::InvalidBlock(format!("Block {} has an invalid hash", block.index)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replaces all chain state, in memory and in storage
+    ///
+    /// # Returns
+    ///
+    /// Result with the number of blocks replaced
+    fn replace_state(
+        &self,
+        blocks: Vec<Block>,
+        accounts: Vec<Account>,
+        transactions: Vec<Transaction>,
+        reorgs: Vec<ReorgEvent>,
+        grants: Vec<FaucetGrant>,
+    ) -> Result<usize, BlockchainError> {
+        let mut pending = self.pending_transactions.lock().unwrap();
+        let mut chain = self.chain.lock().unwrap();
+
+        let replaced = chain.len();
+        *chain = blocks;
+        self.mining.tip_changed(&chain.last().unwrap().hash);
+        *pending = transactions;
+        self.queued_transactions.lock().unwrap().clear();
+        self.account_state.replace_all(accounts);
+        self.activity.rebuild(&chain);
+        self.index.rebuild(&chain);
+        self.names.rebuild(&chain);
+        self.permissions.rebuild(&chain);
+        self.scripts.rebuild(&chain);
+        self.sessions.rebuild(&chain);
+        self.inheritances.rebuild(&chain);
+        self.governance.rebuild(&chain, &grants)?;
+        *self.base_fee.lock().unwrap() = None;
+        *self.reorgs.lock().unwrap() = reorgs;
+        self.faucet.replace(grants);
+
+        if let Some(storage) = &self.storage {
+            // Hold the buffer so a concurrent flush can't write stale entries back
+            let mut journal = self.mempool_journal.lock().unwrap();
+            journal.clear();
+            self.wait_for_storage()?;
+            storage.clear_chain_data()?;
+            for block in chain.iter() {
+                storage.save_block(block)?;
+            }
+            storage.replace_accounts(&self.account_state.get_all_accounts())?;
+            self.account_state.take_changed_accounts();
+            for reorg in self.reorgs.lock().unwrap().iter() {
+                storage.append_reorg(reorg)?;
+            }
+            for grant in self.faucet.all() {
+                storage.append_faucet_grant(&grant)?;
+            }
+            storage.save_mempool_snapshot(&pending)?;
+            drop(journal);
+
+            storage.flush()?;
+        }
+
+        Ok(replaced)
+    }
+
+    /// Gets all past chain reorganizations, oldest first
+    pub fn get_reorgs(&self) -> Vec<ReorgEvent> {
+        self.reorgs.lock().unwrap().clone()
+    }
+
+    /// Subscribes to the chain events published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<ChainEvent> {
+        self.events.subscribe()
+    }
+
+    /// Gets the minimum transaction fee
+    pub fn get_minimum_fee(&self) -> f64 {
+        self.governance.parameters_at(self.get_last_block().index + 1).minimum_fee
+    }
+
+    /// Gets the base fee of the next block, the lowest fee a transaction may pay now
+    pub fn get_base_fee(&self) -> f64 {
+        self.base_fee_after(&self.chain.lock().unwrap())
+    }
+
+    /// Estimates what a transaction should pay to make the next block
+    pub fn get_fee_estimate(&self) -> FeeEstimate {
+        let pending = self.pending_transactions.lock().unwrap().len();
+        let chain = self.chain.lock().unwrap();
+
+        FeeEstimate::new(&chain, |height| self.governance.parameters_at(height), pending)
+    }
+
+    /// Gets the storage backing the blockchain, if any
+    ///
+    /// # Returns
+    ///
+    /// The storage if the blockchain is persistent
+    pub fn get_storage(&self) -> Option<Arc<BlockchainStorage>> {
+        self.storage.clone()
+    }
+
+    /// Gets the account state
+    ///
+    /// # Returns
+    ///
+    /// The account state
+    pub fn get_account_state(&self) -> Arc<AccountState> {
+        self.account_state.clone()
+    }
+
+    /// Deposits funds to an account outside the chain, as the faucet
+    ///
+    /// The deposit is recorded with the height of the tip, so replays of the
+    /// chain credit it after the same block.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The account to fund
+    /// * `amount` - The amount to deposit
+    ///
+    /// # Returns
+    ///
+    /// Result with the funded account
+    pub fn fund_account(&self, address: &Address, amount: f64) -> Result<Account, BlockchainError> {
+        self.check_writable()?;
+
+        // Hold the chain so no block is applied between the height and the deposit
+        let chain = self.chain.lock().unwrap();
+        let mut account = self.account_state.get_account(address);
+        account.deposit(amount)?;
+
+        let grant = FaucetGrant {
+            address: address.clone(),
+            amount,
+            height: chain.last().unwrap().index,
+            granted_at: Utc::now(),
+        };
+        if let Some(writer) = &self.storage_writer {
+            let (grant, account) = (grant.clone(), account.clone());
+            writer.write(move |storage| {
+                storage.append_faucet_grant(&grant)?;
+                storage.save_account(&account)
+            })?;
+        }
+        self.faucet.record(grant);
+        self.account_state.update_account(account.clone());
+
+        // A checkpoint of the tip was taken before the deposit
+        self.checkpoints.retain(&chain[..chain.len() - 1]);
+        drop(chain);
+        self.wait_for_storage()?;
+
+        Ok(account)
+    }
+
+    /// Validates the blockchain
+    ///
+    /// # Returns
+    ///
+    /// true if the blockchain is valid, false otherwise
     pub fn is_valid(&self) -> bool {
         let chain = self.chain.lock().unwrap();
 
-        for i in 1..chain.len() {
-            let current_block = &chain[i];
-            let previous_block = &chain[i - 1];
+        for i in 1..chain.len() {
+            let current_block = &chain[i];
+            let previous_block = &chain[i - 1];
+
+            // Check if the hash is correct
+            if current_block.hash != current_block.calculate_hash() {
+                return false;
+            }
+
+            // Check if the previous hash is correct
+            if current_block.previous_hash != previous_block.hash {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Loads the blockchain from storage
+    ///
+    /// # Returns
+    ///
+    /// Result with () if successful
+    fn load_from_storage(&mut self) -> Result<(), BlockchainError> {
+        let storage = match &self.storage {
+            Some(storage) => storage,
+            None => return Err(BlockchainError::SystemError("No storage configured".to_string())),
+        };
+
+        // Get all blocks from storage
+        let blocks = storage.get_all_blocks()?;
+
+        if blocks.is_empty() {
+            return Err(BlockchainError::StorageError(StorageError::NotFound("No blocks found in storage".to_string())));
+        }
+
+        // Aggregates and indexes are not persisted, so replay the loaded blocks once
+        for block in &blocks {
+            self.activity.record_block(block);
+            self.index.record_block(block);
+            self.names.record_block(block);
+            self.permissions.record_block(block);
+            self.scripts.record_block(block);
+            self.sessions.record_block(block);
+            self.inheritances.record_block(block);
+        }
+
+        // Votes weigh past balances, so governance replays the accounts itself
+        self.governance.rebuild(&blocks, &self.faucet.all())?;
+        *self.base_fee.lock().unwrap() = None;
+
+        // Replace the chain with the loaded blocks
+        *self.chain.lock().unwrap() = blocks;
+
+        // Load account state from storage
+        info!("Loading account state from storage");
+        match storage.get_all_accounts() {
+            Ok(accounts) => {
+                info!("Loaded {} accounts from storage", accounts.len());
+                for account in accounts {
+                    info!("Loaded account {} with balance {}", account.address.0, account.balance);
+                    self.account_state.update_account(account);
+                }
+                // Already stored, the next block only writes what it changes
+                self.account_state.take_changed_accounts();
+            },
+            Err(err) => {
+                warn!("Failed to load accounts from storage: {}", err);
+                warn!("Account state may be incomplete");
+
+                // Rebuild account state from transactions in the chain
+                info!("Rebuilding account state from blockchain transactions");
+                self.rebuild_account_state()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Rebuilds the account state from transactions in the chain
+    ///
+    /// # Returns
+    ///
+    /// Result with () if successful
+    fn rebuild_account_state(&self) -> Result<(), BlockchainError> {
+        // Get all blocks
+        let chain = self.chain.lock().unwrap();
+
+        // Process all transactions in all blocks
+        for block in chain.iter() {
+            validation::apply_block(&self.account_state, block)?;
+        }
+
+        info!("Account state rebuilt from {} blocks", chain.len());
+        Ok(())
+    }
+
+    /// Saves the blockchain to storage
+    ///
+    /// # Returns
+    ///
+    /// Result with () if successful
+    fn save_to_storage(&self) -> Result<(), BlockchainError> {
+        let storage = match &self.storage {
+            Some(storage) => storage,
+            None => return Err(BlockchainError::SystemError("No storage configured".to_string())),
+        };
+
+        // Save all blocks to storage
+        for block in self.chain.lock().unwrap().iter() {
+            storage.save_block(block)?;
+        }
+
+        // Save account state
+        for account in self.account_state.get_all_accounts() {
+            storage.save_account(&account)?;
+        }
+        self.account_state.take_changed_accounts();
+
+        // Flush storage to disk
+        storage.flush()?;
+
+        Ok(())
+    }
+}
+
+impl Default for Blockchain {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A block assembled from the mempool, before its proof of work
+struct BlockCandidate {
+    /// The block, with a proof of 0
+    block: Block,
+
+    /// Installments paid by the treasury, first in the block
+    disbursements: Vec<Transaction>,
+
+    /// Pending transactions the block takes
+    included: Vec<Transaction>,
+
+    /// The coinbase, last in the block
+    reward: Transaction,
+}
+
+/// Searches the proofs from a start on for the first that meets the difficulty
+///
+/// Each thread tries every `threads`-th proof. A thread that finds one
+/// records how far past the start it is, and the others stop once they are
+/// past the lowest found, so the proof is the lowest past the start
+/// whatever the number of threads.
+///
+/// # Arguments
+///
+/// * `template` - The block to seal, its proof and hash are replaced
+/// * `start` - The first proof to try
+/// * `difficulty` - Number of leading zeros the hex hash needs
+/// * `threads` - Number of threads searching, at least 1
+/// * `cancel` - Token stopping the search, see `MiningSearches`
+///
+/// # Returns
+///
+/// The sealed block, None if the search was cancelled first
+fn search_proof(template: &Block, start: u64, difficulty: u8, threads: usize, cancel: &CancellationToken) -> Option<Block> {
+    let target = "0".repeat(difficulty as usize);
+    let threads = threads.max(1) as u64;
+
+    // How far past the start the lowest proof found so far is
+    let found = AtomicU64::new(u64::MAX);
+
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|worker| {
+                let (target, found) = (&target, &found);
+                scope.spawn(move || {
+                    let mut block = template.clone();
+                    let mut offset = worker;
+                    while offset < found.load(Ordering::Relaxed) {
+                        if cancel.is_cancelled() {
+                            return None;
+                        }
+                        block.proof = start.wrapping_add(offset);
+                        let hash = block.calculate_hash();
+                        if hash.starts_with(target.as_str()) {
+                            found.fetch_min(offset, Ordering::Relaxed);
+                            return Some((offset, Block { hash, ..block }));
+                        }
+                        offset += threads;
+                    }
+                    None
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .filter_map(|worker| worker.join().expect("a proof of work thread panicked"))
+            .min_by_key(|(offset, _)| *offset)
+            .map(|(_, block)| block)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::crypto::Wallet;
+    use crate::blockchain::governance::GovernanceAction;
+
+    #[test]
+    fn test_new_blockchain() {
+        let blockchain = Blockchain::new();
+        let chain = blockchain.get_chain();
+
+        assert_eq!(chain.len(), 1);
+        assert_eq!(chain[0].index, 0);
+    }
+
+    #[test]
+    fn test_add_transaction() {
+        let blockchain = Blockchain::new();
+        let sender_wallet = Wallet::new().unwrap();
+        let recipient_wallet = Wallet::new().unwrap();
+
+        // Create a transaction
+        let mut transaction = Transaction::new(
+            sender_wallet.address().clone(),
+            recipient_wallet.address().clone(),
+            10.0,
+            0.1,
+            0,
+        );
+
+        // Sign the transaction
+        transaction.sign(&sender_wallet).unwrap();
+
+        // Add funds to sender's account
+        let mut sender_account = blockchain.account_state.get_account(sender_wallet.address());
+        sender_account.deposit(100.0).unwrap();
+        blockchain.account_state.update_account(sender_account);
+
+        // Add the transaction
+        let block_index = blockchain.add_transaction(transaction).unwrap();
+        assert_eq!(block_index, 1);
+
+        let pending = blockchain.get_pending_transactions();
+        assert_eq!(pending.len(), 1);
+    }
+
+    #[test]
+    fn test_next_nonce_includes_pending() {
+        let blockchain = Blockchain::new();
+        let sender_wallet = Wallet::new().unwrap();
+        let recipient_wallet = Wallet::new().unwrap();
+
+        // Add funds to sender's account
+        let mut sender_account = blockchain.account_state.get_account(sender_wallet.address());
+        sender_account.deposit(100.0).unwrap();
+        blockchain.account_state.update_account(sender_account);
+
+        assert_eq!(blockchain.get_next_nonce(sender_wallet.address()), 0);
+
+        // Submit two transactions back to back without mining in between
+        for _ in 0..2 {
+            let nonce = blockchain.get_next_nonce(sender_wallet.address());
+            let mut transaction = Transaction::new(
+                sender_wallet.address().clone(),
+                recipient_wallet.address().clone(),
+                10.0,
+                0.1,
+                nonce,
+            );
+            transaction.sign(&sender_wallet).unwrap();
+            blockchain.add_transaction(transaction).unwrap();
+        }
+
+        assert_eq!(blockchain.get_next_nonce(sender_wallet.address()), 2);
+
+        // Reusing a nonce that is already pending is rejected
+        let mut duplicate = Transaction::new(
+            sender_wallet.address().clone(),
+            recipient_wallet.address().clone(),
+            10.0,
+            0.1,
+            1,
+        );
+        duplicate.sign(&sender_wallet).unwrap();
+        assert!(blockchain.add_transaction(duplicate).is_err());
+    }
+
+    #[test]
+    fn test_batch_resolves_nonce_conflicts() {
+        let blockchain = Blockchain::new();
+        let sender = Wallet::new().unwrap();
+        let recipient = Address("recipient".to_string());
+        let mut account = blockchain.account_state.get_account(sender.address());
+        account.deposit(10.0).unwrap();
+        blockchain.account_state.update_account(account);
+
+        let sign = |nonce: u64, amount: f64, fee: f64| {
+            let mut transaction = Transaction::new(sender.address().clone(), recipient.clone(), amount, fee, nonce);
+            transaction.sign(&sender).unwrap();
+            transaction
+        };
+        let first = sign(0, 1.0, 0.1);
+        let bumped = sign(0, 1.0, 0.2);
+
+        let outcomes = blockchain.submit_batch(vec![
+            first.clone(),
+            sign(2, 1.0, 0.1),
+            bumped.clone(),
+            sign(0, 1.0, 0.2),
+            sign(1, 20.0, 0.1),
+            sign(3, 1.0, 0.1),
+        ]);
+        assert_eq!(outcomes[0].as_ref().unwrap(), &Admission::Accepted);
+        assert_eq!(outcomes[1].as_ref().unwrap(), &Admission::Queued);
+        assert_eq!(outcomes[2].as_ref().unwrap(), &Admission::Replaced(first.id));
+        assert!(matches!(
+            outcomes[3],
+            Err(BlockchainError::TransactionError(TransactionError::ReplacementUnderpriced { .. }))
+        ));
+        assert!(matches!(
+            outcomes[4],
+            Err(BlockchainError::AccountError(AccountError::InsufficientFunds { .. }))
+        ));
+        assert_eq!(outcomes[5].as_ref().unwrap(), &Admission::Queued);
+        assert_eq!(blockchain.get_queued_transactions().len(), 2);
+
+        // Filling the gap admits the queued transactions in nonce order
+        assert_eq!(blockchain.submit_transaction(sign(1, 1.0, 0.1)).unwrap(), Admission::Accepted);
+        let nonces: Vec<u64> = blockchain.get_pending_transactions().iter().map(|tx| tx.nonce).collect();
+        assert_eq!(nonces, vec![0, 1, 2, 3]);
+        assert_eq!(blockchain.get_pending_transactions()[0].id, bumped.id);
+        assert!(blockchain.get_queued_transactions().is_empty());
+
+        // A confirmed nonce can't be replaced
+        blockchain.mine_block(&recipient.0).unwrap();
+        assert!(matches!(
+            blockchain.submit_transaction(sign(0, 1.0, 0.5)),
+            Err(BlockchainError::AccountError(AccountError::InvalidNonce { expected: 4, got: 0 }))
+        ));
+    }
+
+    #[test]
+    fn test_known_transactions_are_not_admitted_twice() {
+        let blockchain = Blockchain::new();
+        let sender = Wallet::new().unwrap();
+        let recipient = Address("recipient".to_string());
+        let mut account = blockchain.account_state.get_account(sender.address());
+        account.deposit(10.0).unwrap();
+        blockchain.account_state.update_account(account);
+
+        let sign = |nonce: u64| {
+            let mut transaction = Transaction::new(sender.address().clone(), recipient.clone(), 1.0, 0.1, nonce);
+            transaction.sign(&sender).unwrap();
+            transaction
+        };
+        let first = sign(0);
+        let queued = sign(2);
+
+        assert_eq!(blockchain.add_transaction(first.clone()).unwrap(), 1);
+        assert_eq!(blockchain.add_transaction(first.clone()).unwrap(), 1);
+        assert_eq!(blockchain.submit_transaction(first.clone()).unwrap(), Admission::Known);
+        assert_eq!(blockchain.submit_transaction(queued.clone()).unwrap(), Admission::Queued);
+        assert_eq!(blockchain.submit_transaction(queued.clone()).unwrap(), Admission::Known);
+        assert_eq!(blockchain.get_pending_transactions().len(), 1);
+        assert_eq!(blockchain.get_queued_transactions().len(), 1);
+
+        // Relayed copies of mined transactions are still known
+        blockchain.mine_block(&recipient.0).unwrap();
+        assert_eq!(blockchain.submit_relayed_transaction(first).unwrap(), Admission::Known);
+        assert!(blockchain.submit_transaction(sign(0)).is_err());
+    }
+
+    #[test]
+    fn test_expired_transactions_are_evicted() {
+        let blockchain = Blockchain::new();
+        let mut events = blockchain.subscribe();
+        let recipient = Address("recipient".to_string());
+        let old = Wallet::new().unwrap();
+        let new = Wallet::new().unwrap();
+        for wallet in [&old, &new] {
+            let mut account = blockchain.account_state.get_account(wallet.address());
+            account.deposit(10.0).unwrap();
+            blockchain.account_state.update_account(account);
+        }
+        let sign = |wallet: &Wallet, nonce: u64| {
+            let mut transaction = Transaction::new(wallet.address().clone(), recipient.clone(), 1.0, 0.1, nonce);
+            transaction.sign(wallet).unwrap();
+            transaction
+        };
+
+        let expired = sign(&old, 0);
+        blockchain.add_transaction(expired.clone()).unwrap();
+        std::thread::sleep(Duration::from_millis(300));
+        let dependent = sign(&old, 1);
+        blockchain.add_transaction(dependent.clone()).unwrap();
+        let kept = sign(&new, 0);
+        blockchain.add_transaction(kept.clone()).unwrap();
+
+        let entries = blockchain.get_pending_entries();
+        assert_eq!(entries.len(), 3);
+        assert!(entries[0].received_at < entries[1].received_at);
+        assert!(blockchain.evict_expired(chrono::Duration::hours(1)).is_empty());
+
+        // The later nonce of the same sender goes with the expired transaction
+        let evicted = blockchain.evict_expired(chrono::Duration::milliseconds(150));
+        let ids: Vec<&str> = evicted.iter().map(|entry| entry.transaction.id.as_str()).collect();
+        assert_eq!(ids, vec![expired.id.as_str(), dependent.id.as_str()]);
+        assert_eq!(blockchain.get_pending_transactions()[0].id, kept.id);
+        let evicted_events = std::iter::from_fn(|| events.try_recv().ok())
+            .filter(|event| matches!(event, ChainEvent::TransactionEvicted { .. }))
+            .count();
+        assert_eq!(evicted_events, 2);
+
+        // Relayed copies don't bring it back, the sender may submit it again
+        assert_eq!(blockchain.submit_relayed_transaction(expired.clone()).unwrap(), Admission::Known);
+        assert_eq!(blockchain.submit_transaction(expired).unwrap(), Admission::Accepted);
+    }
+
+    #[test]
+    fn test_mine_block() {
+        let blockchain = Blockchain::new();
+        let sender_wallet = Wallet::new().unwrap();
+        let recipient_wallet = Wallet::new().unwrap();
+
+        // Create a transaction
+        let mut transaction = Transaction::new(
+            sender_wallet.address().clone(),
+            recipient_wallet.address().clone(),
+            10.0,
+            0.1,
+            0,
+        );
+
+        // Sign the transaction
+        transaction.sign(&sender_wallet).unwrap();
+
+        // Add funds to sender's account
+        let mut sender_account = blockchain.account_state.get_account(sender_wallet.address());
+        sender_account.deposit(100.0).unwrap();
+        blockchain.account_state.update_account(sender_account);
+
+        // Add the transaction
+        blockchain.add_transaction(transaction).unwrap();
+
+        // Mine a block
+        let miner_address = sender_wallet.address().0.clone();
+        let block = blockchain.mine_block(&miner_address).unwrap();
+
+        assert_eq!(block.index, 1);
+        assert_eq!(block.transactions.len(), 2); // Original transaction + mining reward
+
+        // Check that the pending transactions are cleared
+        let pending = blockchain.get_pending_transactions();
+        assert_eq!(pending.len(), 0);
+
+        // Check that the recipient received the funds
+        let recipient_account = blockchain.account_state.get_account(recipient_wallet.address());
+        assert_eq!(recipient_account.balance, 10.0);
+
+        // Check that the miner received the reward
+        let miner_account = blockchain.account_state.get_account(sender_wallet.address());
+        // 100 - 10 - 0.1 + 50 (mining reward) + 0.09 (the fee above the base fee)
+        assert!((miner_account.balance - 139.99).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_locked_amounts_are_spent_under_their_condition() {
+        use crate::blockchain::scripts::{self, Condition, ScriptAction, Witness};
+        use crate::blockchain::verify;
+        use sha2::{Digest, Sha256};
+
+        let blockchain = Blockchain::new();
+        let owner = Wallet::new().unwrap();
+        let recipient = Wallet::new().unwrap();
+        let payee = Address("payee".to_string());
+        blockchain.mine_block(&owner.address().0).unwrap();
+        blockchain.mine_block(&recipient.address().0).unwrap();
+
+        let mut lock = Transaction::new(owner.address().clone(), Address(scripts::SCRIPT_ADDRESS.to_string()), 20.0, 0.1, 0)
+            .with_script(ScriptAction::Lock {
+                recipient: recipient.address().0.clone(),
+                condition: Condition::Hashlock {
+                    hash: format!("{:x}", Sha256::digest(b"secret")),
+                },
+            });
+        lock.sign(&owner).unwrap();
+        blockchain.add_transaction(lock.clone()).unwrap();
+        blockchain.mine_block(&owner.address().0).unwrap();
+        assert_eq!(blockchain.get_locks_for(recipient.address()).len(), 1);
+
+        let spend = |preimage: &str, nonce: u64| {
+            let unlock = ScriptAction::Unlock {
+                lock: lock.id.clone(),
+                witness: Witness {
+                    signatures: vec![],
+                    preimage: Some(preimage.to_string()),
+                },
+            };
+            let mut transaction =
+                Transaction::new(recipient.address().clone(), payee.clone(), 20.0, 0.1, nonce).with_script(unlock);
+            transaction.sign(&recipient).unwrap();
+            transaction
+        };
+
+        assert!(matches!(
+            blockchain.add_transaction(spend("guess", 0)),
+            Err(BlockchainError::ScriptError(ScriptError::Unsatisfied { .. }))
+        ));
+        blockchain.add_transaction(spend("secret", 0)).unwrap();
+        assert!(matches!(
+            blockchain.add_transaction(spend("secret", 1)),
+            Err(BlockchainError::ScriptError(ScriptError::Pending(_)))
+        ));
+
+        // The recipient only pays the fee, the amount comes out of the lock
+        blockchain.mine_block(&owner.address().0).unwrap();
+        assert!(blockchain.get_lock(&lock.id).is_none());
+        assert_eq!(blockchain.account_state.get_account(&payee).balance, 20.0);
+        assert_eq!(blockchain.account_state.get_account(recipient.address()).balance, MINING_REWARD - 0.1);
+        assert_eq!(blockchain.account_state.get_account(&Address(scripts::SCRIPT_ADDRESS.to_string())).balance, 0.0);
+
+        let blocks = blockchain.get_chain();
+        verify::verify_chain(&blocks, DIFFICULTY, MINING_REWARD, &FeePolicy::default()).unwrap();
+    }
+
+    #[test]
+    fn test_session_keys_sign_transfers_within_their_limits() {
+        use crate::blockchain::verify;
+
+        let blockchain = Blockchain::new();
+        let owner = Wallet::new().unwrap();
+        let session = Wallet::new().unwrap();
+        let payee = Address("payee".to_string());
+        blockchain.mine_block(&owner.address().0).unwrap();
+
+        let spend = |amount: f64, nonce: u64| {
+            let mut transaction = Transaction::new(owner.address().clone(), payee.clone(), amount, 0.1, nonce)
+                .with_session(SessionAction::Spend { key: session.address().0.clone() });
+            transaction.sign(&session).unwrap();
+            transaction
+        };
+
+        // Nothing is signed by the key until its authorization is mined
+        assert!(matches!(
+            blockchain.add_transaction(spend(1.0, 0)),
+            Err(BlockchainError::SessionError(SessionError::NotAuthorized { .. }))
+        ));
+        let mut authorization = Transaction::new(owner.address().clone(), Address(sessions::SESSIONS_ADDRESS.to_string()), sessions::SESSION_CHANGE_AMOUNT, 0.1, 0)
+            .with_session(SessionAction::Authorize {
+                key: session.address().0.clone(),
+                max_total: 5.0,
+                max_per_transaction: 3.0,
+                expires_at: 10,
+            });
+        authorization.sign(&owner).unwrap();
+        blockchain.add_transaction(authorization).unwrap();
+        blockchain.mine_block(&owner.address().0).unwrap();
+
+        // Pending spends of the key count against its total
+        blockchain.add_transaction(spend(2.9, 1)).unwrap();
+        assert!(matches!(
+            blockchain.add_transaction(spend(3.5, 2)),
+            Err(BlockchainError::SessionError(SessionError::LimitExceeded { .. }))
+        ));
+        assert!(matches!(
+            blockchain.add_transaction(spend(2.0, 2)),
+            Err(BlockchainError::SessionError(SessionError::LimitExceeded { .. }))
+        ));
+        blockchain.add_transaction(spend(1.5, 2)).unwrap();
+        blockchain.mine_block(&owner.address().0).unwrap();
+
+        assert!((blockchain.account_state.get_account(&payee).balance - 4.4).abs() < 1e-9);
+        let keys = blockchain.get_session_keys(owner.address());
+        assert_eq!(keys.len(), 1);
+        assert!((keys[0].remaining() - 0.4).abs() < 1e-9);
+
+        let blocks = blockchain.get_chain();
+        verify::verify_chain(&blocks, DIFFICULTY, MINING_REWARD, &FeePolicy::default()).unwrap();
+    }
+
+    #[test]
+    fn test_beneficiaries_claim_the_funds_of_inactive_owners() {
+        use crate::blockchain::verify;
+
+        let blockchain = Blockchain::new();
+        let owner = Wallet::new().unwrap();
+        let heir = Wallet::new().unwrap();
+        let payee = Address("payee".to_string());
+        blockchain.mine_block(&owner.address().0).unwrap();
+        blockchain.mine_block(&heir.address().0).unwrap();
+
+        let claim = |amount: f64, nonce: u64| {
+            let mut transaction = Transaction::new(heir.address().clone(), payee.clone(), amount, 0.1, nonce)
+                .with_inheritance(inheritance::InheritanceAction::Claim { owner: owner.address().0.clone() });
+            transaction.sign(&heir).unwrap();
+            transaction
+        };
+
+        assert!(matches!(
+            blockchain.add_transaction(claim(10.0, 0)),
+            Err(BlockchainError::InheritanceError(InheritanceError::NotDesignated(_)))
+        ));
+
+        // Designated in block 3, so claimable from block 6 after 2 quiet blocks
+        let mut designation = Transaction::new(owner.address().clone(), Address(inheritance::INHERITANCE_ADDRESS.to_string()), inheritance::INHERITANCE_CHANGE_AMOUNT, 0.1, 0)
+            .with_inheritance(inheritance::InheritanceAction::Designate {
+                beneficiary: heir.address().0.clone(),
+                inactivity_blocks: 2,
+            });
+        designation.sign(&owner).unwrap();
+        blockchain.add_transaction(designation).unwrap();
+        blockchain.mine_block(&heir.address().0).unwrap();
+        assert_eq!(blockchain.get_inheritance(owner.address()).unwrap().claimable_at(), 6);
+        assert_eq!(blockchain.get_inheritances_for(heir.address()).len(), 1);
+
+        assert!(matches!(
+            blockchain.add_transaction(claim(10.0, 0)),
+            Err(BlockchainError::InheritanceError(InheritanceError::OwnerActive { claimable_at: 6, .. }))
+        ));
+        blockchain.mine_block(&heir.address().0).unwrap();
+        blockchain.mine_block(&heir.address().0).unwrap();
+
+        // One claim on an owner may be pending at a time
+        let owner_balance = blockchain.account_state.get_account(owner.address()).balance;
+        blockchain.add_transaction(claim(10.0, 0)).unwrap();
+        assert!(matches!(
+            blockchain.add_transaction(claim(1.0, 1)),
+            Err(BlockchainError::InheritanceError(InheritanceError::Pending(_)))
+        ));
+        blockchain.mine_block(&heir.address().0).unwrap();
+
+        assert!((blockchain.account_state.get_account(&payee).balance - 10.0).abs() < 1e-9);
+        assert!((blockchain.account_state.get_account(owner.address()).balance - (owner_balance - 10.0)).abs() < 1e-9);
+
+        let blocks = blockchain.get_chain();
+        verify::verify_chain(&blocks, DIFFICULTY, MINING_REWARD, &FeePolicy::default()).unwrap();
+    }
+
+    #[test]
+    fn test_blockchain_validity() {
+        let blockchain = Blockchain::new();
+        let sender_wallet = Wallet::new().unwrap();
+        let recipient_wallet = Wallet::new().unwrap();
+
+        // Create a transaction
+        let mut transaction = Transaction::new(
+            sender_wallet.address().clone(),
+            recipient_wallet.address().clone(),
+            10.0,
+            0.1,
+            0,
+        );
+
+        // Sign the transaction
+        transaction.sign(&sender_wallet).unwrap();
+
+        // Add funds to sender's account
+        let mut sender_account = blockchain.account_state.get_account(sender_wallet.address());
+        sender_account.deposit(100.0).unwrap();
+        blockchain.account_state.update_account(sender_account);
+
+        // Add the transaction and mine a block
+        blockchain.add_transaction(transaction).unwrap();
+        blockchain.mine_block(&sender_wallet.address().0).unwrap();
+
+        // The blockchain should be valid
+        assert!(blockchain.is_valid());
+    }
+
+    /// Mines a block with a single coinbase transaction on top of another block
+    fn mine_on(blockchain: &Blockchain, previous: &Block, miner: &Address) -> Block {
+        blockchain
+            .proof_of_work(
+                previous.index + 1,
+                vec![Transaction::new_coinbase(miner.clone(), MINING_REWARD)],
+                previous.hash.clone(),
+                &CancellationToken::new(),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_reorganize_to_longer_branch() {
+        let blockchain = Blockchain::new();
+        let miner = Wallet::new().unwrap();
+        let recipient = Wallet::new().unwrap();
+        let rival = Address("rival".to_string());
+
+        // The miner earns a reward and spends part of it
+        blockchain.mine_block(&miner.address().0).unwrap();
+        let mut transaction = Transaction::new(
+            miner.address().clone(),
+            recipient.address().clone(),
+            10.0,
+            0.1,
+            0,
+        );
+        transaction.sign(&miner).unwrap();
+        let transaction_id = transaction.id.clone();
+        blockchain.add_transaction(transaction).unwrap();
+        blockchain.mine_block(&miner.address().0).unwrap();
+        let old_tip = blockchain.get_last_block().hash;
+
+        // A rival branch from genesis that is one block longer
+        let genesis = blockchain.get_chain()[0].clone();
+        let mut branch = vec![mine_on(&blockchain, &genesis, &rival)];
+        for _ in 0..2 {
+            let next = mine_on(&blockchain, branch.last().unwrap(), &rival);
+            branch.push(next);
+        }
+
+        // A branch that is not longer is rejected
+        assert!(blockchain.reorganize(branch[..2].to_vec()).is_err());
+
+        let mut events = blockchain.subscribe();
+
+        let reorg = blockchain.reorganize(branch).unwrap();
+        assert_eq!(reorg.old_tip, old_tip);
+        assert_eq!(reorg.fork_height, 0);
+        assert_eq!(reorg.depth, 2);
+        assert!(reorg.affected_transactions.contains(&transaction_id));
+
+        assert_eq!(blockchain.get_chain().len(), 4);
+        assert!(blockchain.is_valid());
+        assert_eq!(blockchain.account_state.get_account(&rival).balance, 150.0);
+        assert_eq!(blockchain.account_state.get_account(miner.address()).balance, 0.0);
+
+        // The transfer is no longer affordable, so it is not requeued
+        assert!(blockchain.get_pending_transactions().is_empty());
+
+        assert_eq!(blockchain.get_reorgs(), vec![reorg.clone()]);
+        assert_eq!(events.try_recv().unwrap(), ChainEvent::Reorg(reorg));
+    }
+
+    #[test]
+    fn test_reorganization_keeps_faucet_deposits() {
+        let blockchain = Blockchain::new();
+        let sender = Wallet::new().unwrap();
+        let rival = Address("rival".to_string());
+
+        // Funds deposited before the fork are spent on the part both branches share
+        blockchain.fund_account(sender.address(), 100.0).unwrap();
+        let mut transaction = Transaction::new(sender.address().clone(), Address("recipient".to_string()), 60.0, 0.1, 0);
+        transaction.sign(&sender).unwrap();
+        blockchain.add_transaction(transaction).unwrap();
+        blockchain.mine_block("miner").unwrap();
+        let fork_point = blockchain.get_last_block();
+        let spent = blockchain.account_state.get_account(sender.address()).balance;
+
+        // Funds deposited while a block the branch replaces was the tip
+        blockchain.mine_block("miner").unwrap();
+        blockchain.fund_account(sender.address(), 25.0).unwrap();
+
+        let mut branch = vec![mine_on(&blockchain, &fork_point, &rival)];
+        let next = mine_on(&blockchain, &branch[0], &rival);
+        branch.push(next);
+        blockchain.reorganize(branch).unwrap();
+
+        let balance = |state: &AccountState| state.get_account(sender.address()).balance;
+        assert_eq!(balance(&blockchain.account_state), spent + 25.0);
+        assert_eq!(balance(blockchain.state_at(&BlockRef::Height(1)).unwrap().accounts()), spent);
+        assert_eq!(balance(blockchain.state_at(&BlockRef::Height(2)).unwrap().accounts()), spent + 25.0);
+    }
+
+    #[test]
+    fn test_fork_choice_follows_the_most_work() {
+        let blockchain = Blockchain::new();
+        let miner = Address("miner".to_string());
+        let rival = Address("rival".to_string());
+        let genesis = blockchain.get_last_block();
+
+        let first = mine_on(&blockchain, &genesis, &miner);
+        assert_eq!(blockchain.receive_block(first.clone()).unwrap(), BlockOutcome::Extended);
+        assert_eq!(blockchain.receive_block(first.clone()).unwrap(), BlockOutcome::Known);
+
+        // A competing block with as much work is kept but doesn't win
+        let competing = mine_on(&blockchain, &genesis, &rival);
+        assert_eq!(blockchain.receive_block(competing.clone()).unwrap(), BlockOutcome::Stored);
+        assert_eq!(blockchain.get_last_block().hash, first.hash);
+
+        // A block whose parent is unknown can't be placed yet
+        let ahead = mine_on(&blockchain, &mine_on(&blockchain, &competing, &rival), &rival);
+        assert_eq!(blockchain.receive_block(ahead).unwrap(), BlockOutcome::Orphan);
+
+        // Once the competing branch has more work the chain switches to it
+        let mut events = blockchain.subscribe();
+        let next = mine_on(&blockchain, &competing, &rival);
+        let reorg = match blockchain.receive_block(next.clone()).unwrap() {
+            BlockOutcome::Reorganized(reorg) => reorg,
+            other => panic!("expected a reorganization, got {:?}", other),
+        };
+        assert_eq!((reorg.fork_height, reorg.depth), (0, 1));
+        assert_eq!(events.try_recv().unwrap(), ChainEvent::Reorg(reorg));
+        assert_eq!(blockchain.get_last_block().hash, next.hash);
+        assert_eq!(blockchain.account_state.get_account(&miner).balance, 0.0);
+
+        // The replaced block is kept, so its branch can win back
+        let back = mine_on(&blockchain, &first, &miner);
+        assert_eq!(blockchain.receive_block(back.clone()).unwrap(), BlockOutcome::Stored);
+        let winner = mine_on(&blockchain, &back, &miner);
+        assert!(matches!(blockchain.receive_block(winner.clone()).unwrap(), BlockOutcome::Reorganized(_)));
+        assert_eq!(blockchain.get_last_block().hash, winner.hash);
+        assert!(blockchain.is_valid());
+    }
+
+    #[test]
+    fn test_competing_blocks_must_fit_the_fork_store() {
+        let blockchain = Blockchain::new();
+        let genesis = blockchain.get_last_block();
+        let rival = Address("rival".to_string());
+        blockchain.mine_block("miner").unwrap();
+        blockchain.mine_block("miner").unwrap();
+
+        // A height far above the chain must not be trusted to slice it
+        let stray = blockchain
+            .proof_of_work(
+                500,
+                vec![Transaction::new_coinbase(rival.clone(), MINING_REWARD)],
+                genesis.hash.clone(),
+                &CancellationToken::new(),
+            )
+            .unwrap();
+        assert!(matches!(blockchain.receive_block(stray.clone()), Err(BlockchainError::InvalidBlock(_))));
+        assert!(!blockchain.forks.contains(&stray.hash));
+
+        // Once the store is full, competing blocks are refused rather than dropped
+        for filler in 0..MAX_FORK_BLOCKS {
+            blockchain.forks.insert(Block::new(1, Vec::new(), filler as u64, "filler".to_string()));
+        }
+        let competing = mine_on(&blockchain, &genesis, &rival);
+        assert!(matches!(blockchain.receive_block(competing.clone()), Err(BlockchainError::InvalidChain(_))));
+        assert!(!blockchain.forks.contains(&competing.hash));
+    }
+
+    #[test]
+    fn test_blocks_must_end_with_one_coinbase() {
+        let blockchain = Blockchain::new();
+        let genesis = blockchain.get_last_block();
+        let coinbase = |recipient: &str| Transaction::new_coinbase(Address(recipient.to_string()), MINING_REWARD);
+        let seal = |transactions: Vec<Transaction>| {
+            blockchain
+                .proof_of_work(1, transactions, genesis.hash.clone(), &CancellationToken::new())
+                .unwrap()
+        };
+
+        // A second coinbase would mint the reward twice
+        let doubled = seal(vec![coinbase("rival"), coinbase("rival-2")]);
+        assert!(matches!(blockchain.receive_block(doubled), Err(BlockchainError::InvalidBlock(_))));
+
+        let missing = seal(Vec::new());
+        assert!(matches!(blockchain.receive_block(missing), Err(BlockchainError::InvalidBlock(_))));
+
+        assert_eq!(blockchain.get_chain().len(), 1);
+        assert_eq!(blockchain.get_account_state().get_account(&Address("rival".to_string())).balance, 0.0);
+
+        assert_eq!(blockchain.receive_block(seal(vec![coinbase("rival")])).unwrap(), BlockOutcome::Extended);
+    }
+
+    #[test]
+    fn test_coinbases_and_disbursements_cant_be_submitted() {
+        let blockchain = Blockchain::new();
+        let rich = Address("rich".to_string());
+        let coinbase = Transaction::new_coinbase(rich.clone(), 1_000_000.0);
+        let disbursement = Transaction::new(Address(TREASURY_ADDRESS.to_string()), rich.clone(), 1_000.0, 0.0, 0)
+            .with_governance(GovernanceAction::Disburse { proposal: "proposal".to_string(), installment: 0 });
+
+        for transaction in [coinbase, disbursement] {
+            assert!(matches!(
+                blockchain.add_transaction(transaction.clone()),
+                Err(BlockchainError::TransactionError(TransactionError::MinerOnly(_)))
+            ));
+            assert!(matches!(
+                blockchain.submit_batch(vec![transaction.clone()]).remove(0),
+                Err(BlockchainError::TransactionError(TransactionError::MinerOnly(_)))
+            ));
+
+            // Peers relay what they received from anyone
+            assert!(matches!(
+                blockchain.submit_relayed_transaction(transaction),
+                Err(BlockchainError::TransactionError(TransactionError::MinerOnly(_)))
+            ));
+        }
+        assert!(blockchain.get_pending_transactions().is_empty());
+
+        // Mined blocks keep paying the reward the peers expect
+        let block = blockchain.mine_block("miner").unwrap();
+        assert_eq!(block.transactions.len(), 1);
+        assert!(blockchain.is_valid());
+        assert_eq!(blockchain.get_account_state().get_account(&rich).balance, 0.0);
+    }
+
+    #[test]
+    fn test_incoming_blocks_need_proof_of_work() {
+        let blockchain = Blockchain::new();
+        let genesis = blockchain.get_last_block();
+        let rival = Address("rival".to_string());
+        blockchain.mine_block("miner").unwrap();
+
+        // A competing block that costs nothing to make is never kept
+        let mut forged = mine_on(&blockchain, &genesis, &rival);
+        forged.transactions[0].amount += 1.0;
+        assert!(blockchain.handle_incoming_block(forged.clone()).is_err());
+        assert!(!blockchain.forks.contains(&forged.hash));
+
+        let competing = mine_on(&blockchain, &genesis, &rival);
+        assert_eq!(blockchain.handle_incoming_block(competing.clone()).unwrap(), BlockOutcome::Stored);
+        assert_eq!(blockchain.handle_incoming_block(competing).unwrap(), BlockOutcome::Known);
+        let tip = blockchain.get_last_block();
+        assert_eq!(blockchain.handle_incoming_block(tip).unwrap(), BlockOutcome::Known);
+    }
+
+    #[test]
+    fn test_follower_appends_blocks_mined_elsewhere() {
+        let primary = Blockchain::new();
+        let mut follower = primary.scratch_copy().unwrap();
+        follower.set_follower(true);
+        let miner = Wallet::new().unwrap();
+
+        primary.mine_block(&miner.address().0).unwrap();
+        let mut transaction = Transaction::new(miner.address().clone(), Address("bob".to_string()), 10.0, 0.1, 0);
+        transaction.sign(&miner).unwrap();
+        primary.add_transaction(transaction.clone()).unwrap();
+        primary.mine_block(&miner.address().0).unwrap();
+
+        for block in primary.get_chain().into_iter().skip(1) {
+            follower.append_block(block).unwrap();
+        }
+        assert_eq!(follower.get_last_block().hash, primary.get_last_block().hash);
+        let balance = |node: &Blockchain| node.account_state.get_account(miner.address()).balance;
+        assert_eq!(balance(&follower), balance(&primary));
+        assert_eq!(follower.get_address_history(&Address("bob".to_string())).len(), 1);
+
+        // Blocks that don't extend the tip are refused, and nothing of its own is written
+        let stale = primary.get_chain()[1].clone();
+        assert!(matches!(follower.append_block(stale), Err(BlockchainError::InvalidBlock(_))));
+        assert!(matches!(follower.add_transaction(transaction), Err(BlockchainError::ReadOnly)));
+        assert!(matches!(follower.mine_block(&miner.address().0), Err(BlockchainError::ReadOnly)));
+        assert_eq!(follower.get_chain().len(), 3);
+    }
+
+    #[test]
+    fn test_validation_hooks_reject_transactions_and_blocks() {
+        struct TransferLimit(f64);
+
+        impl ValidationHook for TransferLimit {
+            fn name(&self) -> &str {
+                "transfer-limit"
+            }
+
+            fn check_transaction(&self, transaction: &Transaction, _height: u64) -> Result<(), String> {
+                if !transaction.is_coinbase() && transaction.amount > self.0 {
+                    return Err(format!("{} is above the limit", transaction.amount));
+                }
+                Ok(())
+            }
+        }
+
+        let blockchain = Blockchain::new();
+        let miner = Wallet::new().unwrap();
+        blockchain.mine_block(&miner.address().0).unwrap();
+        let node = blockchain.scratch_copy().unwrap();
+        node.add_validation_hook(Arc::new(TransferLimit(5.0)));
+        let transfer = |amount: f64| {
+            let mut transaction = Transaction::new(miner.address().clone(), Address("bob".to_string()), amount, 0.1, 0);
+            transaction.sign(&miner).unwrap();
+            transaction
+        };
+
+        // The node's own rules come first
+        assert!(matches!(
+            node.add_transaction(transfer(10.0)),
+            Err(BlockchainError::HookError(HookError::TransactionRejected { hook, .. })) if hook == "transfer-limit"
+        ));
+        assert!(!matches!(node.add_transaction(transfer(1000.0)), Ok(_) | Err(BlockchainError::HookError(_))));
+        node.add_transaction(transfer(1.0)).unwrap();
+
+        // Blocks of other nodes are checked too
+        blockchain.add_transaction(transfer(10.0)).unwrap();
+        let block = blockchain.mine_block(&miner.address().0).unwrap();
+        assert!(matches!(node.append_block(block), Err(BlockchainError::HookError(_))));
+        assert_eq!(node.get_chain().len(), 2);
+
+        // Pending transactions a hook rejects by the time they are mined are dropped
+        node.add_validation_hook(Arc::new(TransferLimit(0.5)));
+        let block = node.mine_block(&miner.address().0).unwrap();
+        assert_eq!(block.transactions.len(), 1);
+        assert!(node.get_pending_transactions().is_empty());
+    }
 
-            // Check if the hash is correct
-            if current_block.hash != current_block.calculate_hash() {
-                return false;
-            }
+    #[test]
+    fn test_proof_search_is_the_same_on_any_number_of_threads() {
+        let coinbase = Transaction::new_coinbase(Address("miner".to_string()), 50.0);
+        let template = Block::new(1, vec![coinbase], 0, "0".to_string());
 
-            // Check if the previous hash is correct
-            if current_block.previous_hash != previous_block.hash {
-                return false;
-            }
+        let cancel = CancellationToken::new();
+        let sealed = search_proof(&template, 0, 2, 1, &cancel).unwrap();
+        assert!(sealed.hash.starts_with("00"));
+        assert_eq!(sealed.hash, sealed.calculate_hash());
+        for threads in [2, 3, 8] {
+            let block = search_proof(&template, 0, 2, threads, &cancel).unwrap();
+            assert_eq!((block.proof, block.hash), (sealed.proof, sealed.hash.clone()));
         }
 
-        true
+        // The search wraps around past the highest proof
+        let wrapped = search_proof(&template, u64::MAX - 1, 2, 4, &cancel).unwrap();
+        assert_eq!(wrapped.hash, wrapped.calculate_hash());
+        assert!(wrapped.hash.starts_with("00"));
+
+        // A cancelled search gives up
+        cancel.cancel();
+        assert!(search_proof(&template, 0, 64, 2, &cancel).is_none());
     }
 
-    /// Loads the blockchain from storage
-    ///
-    /// # Returns
-    ///
-    /// Result with () if successful
-    fn load_from_storage(&mut self) -> Result<(), BlockchainError> {
-        let storage = match &self.storage {
-            Some(storage) => storage,
-            None => return Err(BlockchainError::SystemError("No storage configured".to_string())),
+    #[test]
+    fn test_mining_is_cancelled_when_another_block_takes_the_height() {
+        // Two handles on the same chain, one sealing far slower than the other
+        let mut slow = Blockchain::new();
+        slow.set_difficulty(64);
+        slow.set_mining_threads(1);
+        let mut fast = slow.clone();
+        fast.set_difficulty(1);
+
+        let mining = {
+            let slow = slow.clone();
+            std::thread::spawn(move || slow.mine_block("slow"))
         };
+        while slow.mining.is_empty() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
 
-        // Get all blocks from storage
-        let blocks = storage.get_all_blocks()?;
+        let block = fast.mine_block("fast").unwrap();
+        let cancelled = mining.join().unwrap().unwrap_err();
+        assert!(matches!(&cancelled, BlockchainError::MiningCancelled { height: 1, tip } if *tip == block.hash));
+        assert_eq!(cancelled.code(), ErrorCode::StaleTip);
+        assert!(cancelled.is_retryable());
+        assert!(slow.mining.is_empty());
 
-        if blocks.is_empty() {
-            return Err(BlockchainError::StorageError(StorageError::NotFound("No blocks found in storage".to_string())));
-        }
+        // Nothing of the cancelled block was applied
+        assert_eq!(slow.get_chain().len(), 2);
+        assert_eq!(slow.account_state.get_account(&Address("slow".to_string())).balance, 0.0);
+    }
 
-        // Replace the chain with the loaded blocks
-        *self.chain.lock().unwrap() = blocks;
+    #[test]
+    fn test_miners_search_from_random_starts() {
+        let blockchain = Blockchain::new();
+        let miner = Wallet::new().unwrap();
 
-        // Load account state from storage
-        info!("Loading account state from storage");
-        match storage.get_all_accounts() {
-            Ok(accounts) => {
-                info!("Loaded {} accounts from storage", accounts.len());
-                for account in accounts {
-                    info!("Loaded account {} with balance {}", account.address.0, account.balance);
-                    self.account_state.update_account(account);
-                }
-            },
-            Err(err) => {
-                warn!("Failed to load accounts from storage: {}", err);
-                warn!("Account state may be incomplete");
+        let first = blockchain.mine_block(&miner.address().0).unwrap();
+        let second = blockchain.mine_block(&miner.address().0).unwrap();
+        let first_extra_nonce = first.transactions[0].extra_nonce.unwrap();
+        assert_ne!(Some(first_extra_nonce), second.transactions[0].extra_nonce);
+        assert!(first.transactions[0].has_valid_id());
+        assert!(blockchain.is_valid());
 
-                // Rebuild account state from transactions in the chain
-                info!("Rebuilding account state from blockchain transactions");
-                self.rebuild_account_state()?;
-            }
-        }
+        // Without randomization the coinbase carries no extra-nonce
+        let mut deterministic = Blockchain::new();
+        deterministic.set_randomized_proof_of_work(false);
+        let block = deterministic.mine_block(&miner.address().0).unwrap();
+        assert_eq!(block.transactions[0].extra_nonce, None);
+        assert!(!block.transactions[0].hash_json().as_object().unwrap().contains_key("extra_nonce"));
+    }
 
-        Ok(())
+    #[test]
+    fn test_coinbase_message_is_covered_by_block_hash() {
+        let blockchain = Blockchain::new();
+        let miner = Wallet::new().unwrap();
+
+        let block = blockchain
+            .mine_block_with_coinbase(&miner.address().0, Some(7), Some("hello from class 3B"))
+            .unwrap();
+        let coinbase = &block.transactions[0];
+        assert_eq!((coinbase.extra_nonce, coinbase.message.as_deref()), (Some(7), Some("hello from class 3B")));
+        assert!(coinbase.has_valid_id());
+
+        let mut tampered = block.clone();
+        tampered.transactions[0].message = Some("hello from class 3C".to_string());
+        assert_ne!(tampered.calculate_hash(), block.hash);
+
+        let too_long = "x".repeat(MAX_COINBASE_MESSAGE_BYTES + 1);
+        assert!(blockchain.mine_block_with_coinbase(&miner.address().0, None, Some(&too_long)).is_err());
     }
 
-    /// Rebuilds the account state from transactions in the chain
-    ///
-    /// # Returns
-    ///
-    /// Result with () if successful
-    fn rebuild_account_state(&self) -> Result<(), BlockchainError> {
-        // Get all blocks
-        let chain = self.chain.lock().unwrap();
+    #[tokio::test]
+    async fn test_wait_for_block_after() {
+        let blockchain = Blockchain::new();
+        let miner = Wallet::new().unwrap();
+        let genesis = blockchain.get_last_block().hash;
 
-        // Process all transactions in all blocks
-        for block in chain.iter() {
-            for transaction in &block.transactions {
-                if !transaction.is_coinbase() {
-                    // Transfer funds
-                    self.account_state.transfer(
-                        &transaction.sender,
-                        &transaction.recipient,
-                        transaction.amount,
-                        transaction.fee,
-                        transaction.nonce,
-                    )?;
-                } else {
-                    // Process mining reward
-                    self.account_state.process_mining_reward(&transaction.recipient, transaction.amount)?;
-                }
-            }
-        }
+        // Nothing is appended, so the wait times out
+        let next = blockchain.wait_for_block_after(&genesis, Duration::from_millis(10)).await.unwrap();
+        assert!(next.is_none());
 
-        info!("Account state rebuilt from {} blocks", chain.len());
-        Ok(())
+        let waiting = {
+            let blockchain = blockchain.clone();
+            let genesis = genesis.clone();
+            tokio::spawn(async move { blockchain.wait_for_block_after(&genesis, Duration::from_secs(30)).await })
+        };
+        let mined = blockchain.mine_block(&miner.address().0).unwrap();
+        assert_eq!(waiting.await.unwrap().unwrap().unwrap().hash, mined.hash);
+
+        // A block that is not the tip anymore returns right away
+        let next = blockchain.wait_for_block_after(&genesis, Duration::from_secs(30)).await.unwrap();
+        assert_eq!(next.unwrap().hash, mined.hash);
+
+        assert!(matches!(
+            blockchain.wait_for_block_after("unknown", Duration::from_millis(10)).await,
+            Err(BlockchainError::BlockNotFound(_))
+        ));
     }
 
-    /// Saves the blockchain to storage
-    ///
-    /// # Returns
-    ///
-    /// Result with () if successful
-    fn save_to_storage(&self) -> Result<(), BlockchainError> {
-        let storage = match &self.storage {
-            Some(storage) => storage,
-            None => return Err(BlockchainError::SystemError("No storage configured".to_string())),
+    #[test]
+    fn test_maintenance_pauses_writers() {
+        let blockchain = Blockchain::new();
+        let miner = Wallet::new().unwrap();
+
+        blockchain.set_maintenance(MaintenanceStatus::paused(true, true, "snapshot"));
+        assert!(matches!(
+            blockchain.mine_block(&miner.address().0),
+            Err(BlockchainError::Paused(reason)) if reason == "snapshot"
+        ));
+
+        let mut transaction = Transaction::new(
+            miner.address().clone(),
+            Address("recipient".to_string()),
+            1.0,
+            0.1,
+            0,
+        );
+        transaction.sign(&miner).unwrap();
+        assert!(matches!(
+            blockchain.add_transaction(transaction),
+            Err(BlockchainError::Paused(_))
+        ));
+
+        blockchain.set_maintenance(MaintenanceStatus::default());
+        assert!(blockchain.mine_block(&miner.address().0).is_ok());
+    }
+
+    #[test]
+    fn test_mempool_recovered_from_journal() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let sender = Wallet::new().unwrap();
+        let recipient = Address("recipient".to_string());
+
+        let sign = |nonce: u64| {
+            let mut transaction = Transaction::new(sender.address().clone(), recipient.clone(), 1.0, 0.1, nonce);
+            transaction.sign(&sender).unwrap();
+            transaction
         };
 
-        // Save all blocks to storage
-        for block in self.chain.lock().unwrap().iter() {
-            storage.save_block(block)?;
+        {
+            let blockchain = Blockchain::with_storage(data_dir.path()).unwrap();
+            let mut account = blockchain.account_state.get_account(sender.address());
+            account.deposit(100.0).unwrap();
+            blockchain.account_state.update_account(account.clone());
+            blockchain.get_storage().unwrap().save_account(&account).unwrap();
 
-            // Save all transactions in the block
-            for transaction in &block.transactions {
-                storage.save_transaction(transaction)?;
-            }
+            // One transaction is in the snapshot, the other only in the journal
+            blockchain.add_transaction(sign(0)).unwrap();
+            assert_eq!(blockchain.persist_mempool().unwrap(), 1);
+            blockchain.add_transaction(sign(1)).unwrap();
+            assert_eq!(blockchain.flush_mempool_journal().unwrap(), 1);
+
+            // Never flushed, lost in the crash
+            blockchain.add_transaction(sign(2)).unwrap();
         }
 
-        // Save account state
-        for account in self.account_state.get_all_accounts() {
-            storage.save_account(&account)?;
+        let blockchain = Blockchain::with_storage(data_dir.path()).unwrap();
+        let nonces: Vec<u64> = blockchain.get_pending_transactions().iter().map(|tx| tx.nonce).collect();
+        assert_eq!(nonces, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_transactions_of_interrupted_mining_recovered() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let sender = Wallet::new().unwrap();
+
+        {
+            let blockchain = Blockchain::with_storage(data_dir.path()).unwrap();
+            blockchain.fund_account(sender.address(), 100.0).unwrap();
+
+            let mut transaction = Transaction::new(sender.address().clone(), Address("recipient".to_string()), 1.0, 0.1, 0);
+            transaction.sign(&sender).unwrap();
+            blockchain.add_transaction(transaction).unwrap();
+            blockchain.persist_mempool().unwrap();
+
+            // A finished block leaves no template behind
+            blockchain.mine_block("miner").unwrap();
+            blockchain.flush_mempool_journal().unwrap();
+            assert!(blockchain.get_storage().unwrap().get_mining_template().unwrap().is_none());
         }
 
-        // Flush storage to disk
-        storage.flush()?;
+        // The process dies while sealing a block, after its transactions were journaled as removed
+        {
+            let blockchain = Blockchain::with_storage(data_dir.path()).unwrap();
+            let storage = blockchain.get_storage().unwrap();
+            let mut transaction = Transaction::new(sender.address().clone(), Address("recipient".to_string()), 1.0, 0.1, 1);
+            transaction.sign(&sender).unwrap();
+            let last_block = blockchain.get_last_block();
+            storage
+                .save_mining_template(&MiningTemplate {
+                    height: last_block.index + 1,
+                    previous_hash: last_block.hash,
+                    transactions: vec![transaction.clone(), Transaction::new_coinbase(Address("miner".to_string()), MINING_REWARD)],
+                    taken: vec![transaction.id.clone()],
+                })
+                .unwrap();
+            storage
+                .append_mempool_journal(&[MempoolJournalEntry::Removed(transaction.id.clone())])
+                .unwrap();
+        }
 
-        Ok(())
+        let blockchain = Blockchain::with_storage(data_dir.path()).unwrap();
+        let nonces: Vec<u64> = blockchain.get_pending_transactions().iter().map(|tx| tx.nonce).collect();
+        assert_eq!(nonces, vec![1]);
+        assert!(blockchain.get_storage().unwrap().get_mining_template().unwrap().is_none());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::blockchain::crypto::Wallet;
+    #[test]
+    fn test_faucet_deposits_are_replayed() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let sender = Wallet::new().unwrap();
+
+        {
+            let blockchain = Blockchain::with_storage(data_dir.path()).unwrap();
+            blockchain.mine_block("miner").unwrap();
+            blockchain.fund_account(sender.address(), 100.0).unwrap();
+
+            let mut transaction = Transaction::new(sender.address().clone(), Address("recipient".to_string()), 60.0, 0.1, 0);
+            transaction.sign(&sender).unwrap();
+            blockchain.add_transaction(transaction).unwrap();
+            blockchain.mine_block("miner").unwrap();
+        }
+
+        // The stored chain only replays with the deposit credited after block 1
+        let blockchain = Blockchain::with_storage(data_dir.path()).unwrap();
+        let balance_at = |height: u64| {
+            let state = blockchain.state_at(&BlockRef::Height(height)).unwrap();
+            state.accounts().get_account(sender.address()).balance
+        };
+        assert_eq!(balance_at(0), 0.0);
+        assert_eq!(balance_at(1), 100.0);
+        assert_eq!(balance_at(2), blockchain.get_account_state().get_account(sender.address()).balance);
+        assert!(balance_at(2) < 40.0);
+
+        // Followers can't fund, the deposit would not reach the chain they follow
+        let mut follower = Blockchain::new();
+        follower.set_follower(true);
+        assert!(matches!(follower.fund_account(sender.address(), 1.0), Err(BlockchainError::ReadOnly)));
+    }
 
     #[test]
-    fn test_new_blockchain() {
+    fn test_blocks_only_write_changed_accounts() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let blockchain = Blockchain::with_storage(data_dir.path()).unwrap();
+        let storage = blockchain.get_storage().unwrap();
+        let bystander = Address("bystander".to_string());
+        blockchain.account_state.process_mining_reward(&bystander, 5.0).unwrap();
+        blockchain.mine_block("miner").unwrap();
+        assert_eq!(storage.get_account(&bystander).unwrap().balance, 5.0);
+
+        // A record the next block doesn't touch is left as it is
+        let mut stored = storage.get_account(&bystander).unwrap();
+        stored.balance = 1.0;
+        storage.save_account(&stored).unwrap();
+        let funded = Address("funded".to_string());
+        blockchain.account_state.process_mining_reward(&funded, 2.0).unwrap();
+        blockchain.mine_block("miner").unwrap();
+
+        assert_eq!(storage.get_account(&bystander).unwrap().balance, 1.0);
+        assert_eq!(storage.get_account(&funded).unwrap().balance, 2.0);
+        assert_eq!(storage.get_account(&Address("miner".to_string())).unwrap().balance, 100.0);
+    }
+
+    #[test]
+    fn test_base_fee_steps_from_the_tip() {
         let blockchain = Blockchain::new();
-        let chain = blockchain.get_chain();
+        let replayed = |blocks: &[Block]| fees::base_fee_after(blocks, |height| blockchain.governance.parameters_at(height));
 
-        assert_eq!(chain.len(), 1);
-        assert_eq!(chain[0].index, 0);
+        for _ in 0..3 {
+            let tip = blockchain.mine_block("miner").unwrap();
+            assert_eq!(blockchain.get_base_fee(), replayed(&blockchain.get_chain()));
+            assert_eq!(blockchain.base_fee.lock().unwrap().as_ref().unwrap().0, tip.hash);
+        }
+
+        // Blocks behind the tip are replayed from the genesis block
+        let chain = blockchain.get_chain();
+        assert_eq!(blockchain.base_fee_after(&chain[..2]), replayed(&chain[..2]));
+        assert_eq!(blockchain.get_base_fee(), replayed(&chain));
     }
 
     #[test]
-    fn test_add_transaction() {
+    fn test_block_timestamps_never_go_back() {
         let blockchain = Blockchain::new();
-        let sender_wallet = Wallet::new().unwrap();
-        let recipient_wallet = Wallet::new().unwrap();
+        let genesis = blockchain.get_last_block();
+        let seal = |timestamp: DateTime<Utc>| {
+            let coinbase = Transaction::new_coinbase(Address("rival".to_string()), MINING_REWARD);
+            let mut block = Block::new(1, vec![coinbase], 0, genesis.hash.clone());
+            block.timestamp = timestamp;
+            search_proof(&block, 0, blockchain.rules.difficulty, 1, &CancellationToken::new()).unwrap()
+        };
 
-        // Create a transaction
-        let mut transaction = Transaction::new(
-            sender_wallet.address().clone(),
-            recipient_wallet.address().clone(),
-            10.0,
-            0.1,
-            0,
-        );
+        // Lookups by time would skip over a block from before its parent
+        let early = seal(genesis.timestamp - chrono::Duration::seconds(1));
+        assert!(matches!(blockchain.receive_block(early), Err(BlockchainError::InvalidBlock(_))));
 
-        // Sign the transaction
-        transaction.sign(&sender_wallet).unwrap();
+        let future = seal(Utc::now() + validation::MAX_FUTURE_BLOCK_TIME + chrono::Duration::minutes(1));
+        assert!(matches!(blockchain.receive_block(future), Err(BlockchainError::InvalidBlock(_))));
+        assert_eq!(blockchain.get_chain().len(), 1);
 
-        // Add funds to sender's account
-        let mut sender_account = blockchain.account_state.get_account(sender_wallet.address());
-        sender_account.deposit(100.0).unwrap();
-        blockchain.account_state.update_account(sender_account);
+        assert_eq!(blockchain.receive_block(seal(Utc::now())).unwrap(), BlockOutcome::Extended);
+    }
 
-        // Add the transaction
-        let block_index = blockchain.add_transaction(transaction).unwrap();
-        assert_eq!(block_index, 1);
+    #[test]
+    fn test_get_block_at() {
+        let blockchain = Blockchain::new();
+        let genesis = blockchain.get_last_block();
+        let mined = blockchain.mine_block("miner").unwrap();
 
-        let pending = blockchain.get_pending_transactions();
-        assert_eq!(pending.len(), 1);
+        assert!(blockchain.get_block_at(genesis.timestamp - chrono::Duration::seconds(1)).is_none());
+        assert_eq!(blockchain.get_block_at(genesis.timestamp).unwrap().index, 0);
+        assert_eq!(blockchain.get_block_at(mined.timestamp).unwrap().index, 1);
+        assert_eq!(blockchain.get_block_at(Utc::now()).unwrap().index, 1);
     }
 
     #[test]
-    fn test_mine_block() {
+    fn test_blocks_after_locator() {
         let blockchain = Blockchain::new();
-        let sender_wallet = Wallet::new().unwrap();
-        let recipient_wallet = Wallet::new().unwrap();
+        for _ in 0..3 {
+            blockchain.mine_block("miner").unwrap();
+        }
+        let chain = blockchain.get_chain();
 
-        // Create a transaction
-        let mut transaction = Transaction::new(
-            sender_wallet.address().clone(),
-            recipient_wallet.address().clone(),
-            10.0,
-            0.1,
-            0,
-        );
+        let locator = blockchain.get_block_locator();
+        assert_eq!(locator.len(), 4);
+        assert_eq!(locator[0], chain[3].hash);
+        assert_eq!(locator[3], chain[0].hash);
 
-        // Sign the transaction
-        transaction.sign(&sender_wallet).unwrap();
+        let indexes = |blocks: Vec<Block>| blocks.iter().map(|block| block.index).collect::<Vec<u64>>();
+        assert_eq!(indexes(blockchain.get_blocks_after_locator(&[chain[0].hash.clone()], 2)), vec![1, 2]);
 
-        // Add funds to sender's account
-        let mut sender_account = blockchain.account_state.get_account(sender_wallet.address());
-        sender_account.deposit(100.0).unwrap();
-        blockchain.account_state.update_account(sender_account);
+        // The most recent shared block counts, unknown hashes are skipped
+        let locator = vec!["unknown".to_string(), chain[2].hash.clone(), chain[0].hash.clone()];
+        assert_eq!(indexes(blockchain.get_blocks_after_locator(&locator, 10)), vec![3]);
+        assert!(blockchain.get_blocks_after_locator(&locator[..1], 10).is_empty());
+        assert!(blockchain.get_blocks_after_locator(&blockchain.get_block_locator(), 10).is_empty());
+    }
 
-        // Add the transaction
-        blockchain.add_transaction(transaction).unwrap();
+    /// A peer served straight from another node's chain, optionally altering the blocks it sends
+    struct LocalPeer {
+        blockchain: Blockchain,
+        tamper: bool,
+    }
 
-        // Mine a block
-        let miner_address = sender_wallet.address().0.clone();
-        let block = blockchain.mine_block(&miner_address).unwrap();
+    #[async_trait]
+    impl SyncPeer for LocalPeer {
+        async fn get_headers(&self, locator: Vec<String>, limit: usize) -> Result<Vec<BlockHeader>, BlockchainError> {
+            self.blockchain.get_headers_after_locator(&locator, limit)
+        }
 
-        assert_eq!(block.index, 1);
-        assert_eq!(block.transactions.len(), 2); // Original transaction + mining reward
+        async fn get_blocks(&self, hashes: Vec<String>) -> Result<Vec<Block>, BlockchainError> {
+            let mut blocks = self.blockchain.get_blocks_by_hash(&hashes);
+            if self.tamper {
+                for block in &mut blocks {
+                    block.proof += 1;
+                }
+            }
+            Ok(blocks)
+        }
+    }
 
-        // Check that the pending transactions are cleared
-        let pending = blockchain.get_pending_transactions();
-        assert_eq!(pending.len(), 0);
+    #[tokio::test]
+    async fn test_sync_from_peer() {
+        let ahead = Blockchain::new();
+        for _ in 0..3 {
+            ahead.mine_block("ahead").unwrap();
+        }
+        let peer = LocalPeer { blockchain: ahead.clone(), tamper: false };
 
-        // Check that the recipient received the funds
-        let recipient_account = blockchain.account_state.get_account(recipient_wallet.address());
-        assert_eq!(recipient_account.balance, 10.0);
+        // A node on a shorter branch of its own switches to the peer's
+        let node = Blockchain::new();
+        node.mine_block("node").unwrap();
+        let report = node.sync_from(&peer).await.unwrap();
+        assert_eq!((report.headers, report.blocks, report.height), (3, 3, 3));
+        assert_eq!(report.reorgs.len(), 1);
+        assert_eq!(node.get_block_locator(), ahead.get_block_locator());
 
-        // Check that the miner received the reward
-        let miner_account = blockchain.account_state.get_account(sender_wallet.address());
-        assert_eq!(miner_account.balance, 139.9); // 100 - 10 - 0.1 + 50 (mining reward)
+        // then takes the blocks after its tip, and nothing once it is up to date
+        ahead.mine_block("ahead").unwrap();
+        let report = node.sync_from(&peer).await.unwrap();
+        assert_eq!((report.blocks, report.height), (1, 4));
+        assert!(report.reorgs.is_empty());
+        assert_eq!(node.sync_from(&peer).await.unwrap().blocks, 0);
+
+        // A peer whose blocks don't hash to their headers changes nothing
+        ahead.mine_block("ahead").unwrap();
+        let tampered = LocalPeer { blockchain: ahead.clone(), tamper: true };
+        assert!(node.sync_from(&tampered).await.is_err());
+        assert_eq!(node.get_last_block().index, 4);
+
+        // and neither does a peer behind the node
+        let behind = LocalPeer { blockchain: Blockchain::new(), tamper: false };
+        assert_eq!(node.sync_from(&behind).await.unwrap(), SyncReport { height: 4, ..SyncReport::default() });
     }
 
     #[test]
-    fn test_blockchain_validity() {
+    fn test_blocks_by_hash() {
         let blockchain = Blockchain::new();
-        let sender_wallet = Wallet::new().unwrap();
-        let recipient_wallet = Wallet::new().unwrap();
+        blockchain.mine_block("miner").unwrap();
+        let chain = blockchain.get_chain();
 
-        // Create a transaction
-        let mut transaction = Transaction::new(
-            sender_wallet.address().clone(),
-            recipient_wallet.address().clone(),
-            10.0,
-            0.1,
-            0,
-        );
+        let hashes = vec![chain[1].hash.clone(), "unknown".to_string(), chain[0].hash.clone()];
+        let blocks = blockchain.get_blocks_by_hash(&hashes);
+        assert_eq!(blocks.iter().map(|block| block.index).collect::<Vec<u64>>(), vec![1, 0]);
 
-        // Sign the transaction
-        transaction.sign(&sender_wallet).unwrap();
+        let headers = blockchain.get_headers_after_locator(&[chain[0].hash.clone()], 10).unwrap();
+        assert_eq!(headers, vec![chain[1].header(AddressBloom::for_block(&chain[1]))]);
+    }
 
-        // Add funds to sender's account
-        let mut sender_account = blockchain.account_state.get_account(sender_wallet.address());
-        sender_account.deposit(100.0).unwrap();
-        blockchain.account_state.update_account(sender_account);
+    #[test]
+    fn test_reset_to_genesis() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let miner = Address("miner".to_string());
+        let balance = |blockchain: &Blockchain| blockchain.account_state.get_account(&miner).balance;
 
-        // Add the transaction and mine a block
-        blockchain.add_transaction(transaction).unwrap();
-        blockchain.mine_block(&sender_wallet.address().0).unwrap();
+        {
+            let blockchain = Blockchain::with_storage(data_dir.path()).unwrap();
+            blockchain.mine_block("miner").unwrap();
+            assert!(balance(&blockchain) > 0.0);
 
-        // The blockchain should be valid
-        assert!(blockchain.is_valid());
+            let mut events = blockchain.subscribe();
+            let genesis = blockchain.reset().unwrap();
+            let chain = blockchain.get_chain();
+            assert_eq!(chain.len(), 1);
+            assert_eq!(chain[0].hash, genesis.hash);
+            assert_eq!(balance(&blockchain), 0.0);
+            assert_eq!(
+                events.try_recv().unwrap(),
+                ChainEvent::Reset { genesis_hash: genesis.hash }
+            );
+        }
+
+        // The reset is persisted
+        let blockchain = Blockchain::with_storage(data_dir.path()).unwrap();
+        assert_eq!(blockchain.get_chain().len(), 1);
+        assert_eq!(blockchain.get_last_block().index, 0);
+        assert_eq!(balance(&blockchain), 0.0);
+    }
+
+    #[test]
+    fn test_data_directory_of_another_network_is_refused() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let classroom = GenesisConfig {
+            network: "classroom".to_string(),
+            ..GenesisConfig::default()
+        };
+
+        let genesis = {
+            let blockchain = Blockchain::open(data_dir.path(), StorageOptions::default(), classroom.clone()).unwrap();
+            assert_eq!(blockchain.get_storage().unwrap().get_network_magic().unwrap(), Some(classroom.magic()));
+            blockchain.get_last_block()
+        };
+
+        // Every node of the network builds the same genesis block
+        assert_eq!(genesis.hash, classroom.block().hash);
+        assert_eq!(Blockchain::with_genesis(classroom.clone()).get_last_block().hash, genesis.hash);
+
+        match Blockchain::with_storage(data_dir.path()) {
+            Err(BlockchainError::StorageError(StorageError::NetworkMismatch { expected, found })) => {
+                assert_eq!(expected, GenesisConfig::default().magic());
+                assert_eq!(found, classroom.magic());
+            }
+            other => panic!("expected a network mismatch, got {:?}", other.map(|_| ())),
+        }
+
+        let blockchain = Blockchain::open(data_dir.path(), StorageOptions::default(), classroom).unwrap();
+        assert_eq!(blockchain.get_last_block().hash, genesis.hash);
     }
 }
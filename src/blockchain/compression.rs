@@ -0,0 +1,209 @@
+use std::borrow::Cow;
+use std::io::{self, Read, Write};
+
+/// Magic number every zstd frame starts with
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// zstd compression level, the library default
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// Maximum size of a trained dictionary
+const DICTIONARY_SIZE: usize = 16 * 1024;
+
+/// Minimum number of samples needed to train a useful dictionary
+const MIN_TRAINING_SAMPLES: usize = 16;
+
+/// Compresses stored records with zstd, optionally using a trained dictionary
+///
+/// Records that are not zstd frames are passed through on decompression, so
+/// uncompressed data written before compression was enabled stays readable.
+/// Frames record whether they were written with the dictionary, so records
+/// compressed before a dictionary was trained stay readable too.
+#[derive(Debug, Clone, Default)]
+pub struct Compressor {
+    dictionary: Option<Vec<u8>>,
+}
+
+impl Compressor {
+    /// Creates a new compressor
+    ///
+    /// # Arguments
+    ///
+    /// * `dictionary` - Optional zstd dictionary to compress with
+    ///
+    /// # Returns
+    ///
+    /// A new Compressor instance
+    pub fn new(dictionary: Option<Vec<u8>>) -> Self {
+        Compressor { dictionary }
+    }
+
+    /// Gets the dictionary used for compression
+    pub fn dictionary(&self) -> Option<&[u8]> {
+        self.dictionary.as_deref()
+    }
+
+    /// Checks if a stored record is compressed
+    pub fn is_compressed(value: &[u8]) -> bool {
+        value.starts_with(&ZSTD_MAGIC)
+    }
+
+    /// Compresses a record
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The serialized record
+    ///
+    /// # Returns
+    ///
+    /// The zstd frame
+    pub fn compress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut encoder = match &self.dictionary {
+            Some(dictionary) => zstd::Encoder::with_dictionary(Vec::new(), COMPRESSION_LEVEL, dictionary)?,
+            None => zstd::Encoder::new(Vec::new(), COMPRESSION_LEVEL)?,
+        };
+
+        encoder.write_all(data)?;
+        encoder.finish()
+    }
+
+    /// Decompresses a record, passing uncompressed records through
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The stored record
+    ///
+    /// # Returns
+    ///
+    /// The serialized record
+    pub fn decompress<'a>(&self, value: &'a [u8]) -> io::Result<Cow<'a, [u8]>> {
+        if !Self::is_compressed(value) {
+            return Ok(Cow::Borrowed(value));
+        }
+
+        let uses_dictionary = zstd::zstd_safe::get_dict_id_from_frame(value).is_some();
+        let mut data = Vec::new();
+
+        match (&self.dictionary, uses_dictionary) {
+            (Some(dictionary), true) => {
+                zstd::Decoder::with_dictionary(value, dictionary)?.read_to_end(&mut data)?;
+            }
+            (None, true) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "record was compressed with a dictionary that is not available",
+                ));
+            }
+            (_, false) => {
+                zstd::Decoder::with_buffer(value)?.read_to_end(&mut data)?;
+            }
+        }
+
+        Ok(Cow::Owned(data))
+    }
+
+    /// Trains a dictionary on typical records
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - Serialized records to learn from
+    ///
+    /// # Returns
+    ///
+    /// The dictionary, or None if there are too few samples to train on
+    pub fn train(samples: &[Vec<u8>]) -> Option<Vec<u8>> {
+        if samples.len() < MIN_TRAINING_SAMPLES {
+            return None;
+        }
+
+        zstd::dict::from_samples(samples, DICTIONARY_SIZE).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(i: usize) -> Vec<u8> {
+        format!(
+            "{{\"version\":1,\"id\":\"tx-{}\",\"sender\":\"alice\",\"recipient\":\"bob\",\"amount\":{}.5,\"fee\":0.01,\"nonce\":{}}}",
+            i,
+            i * 7,
+            i
+        )
+        .into_bytes()
+    }
+
+    #[test]
+    fn test_round_trip_and_passthrough() {
+        let compressor = Compressor::default();
+        let data = sample(1).repeat(10);
+
+        let compressed = compressor.compress(&data).unwrap();
+        assert!(Compressor::is_compressed(&compressed));
+        assert!(compressed.len() < data.len());
+        assert_eq!(compressor.decompress(&compressed).unwrap().as_ref(), data.as_slice());
+
+        // Records written before compression was enabled are returned as they are
+        assert_eq!(compressor.decompress(&data).unwrap().as_ref(), data.as_slice());
+    }
+
+    #[test]
+    fn test_dictionary_compression() {
+        let samples: Vec<Vec<u8>> = (0..200).map(sample).collect();
+        let dictionary = Compressor::train(&samples).unwrap();
+        let plain = Compressor::default();
+        let trained = Compressor::new(Some(dictionary));
+
+        let data = sample(500);
+        let with_dictionary = trained.compress(&data).unwrap();
+        assert!(with_dictionary.len() < plain.compress(&data).unwrap().len());
+        assert_eq!(trained.decompress(&with_dictionary).unwrap().as_ref(), data.as_slice());
+
+        // Frames written before the dictionary existed stay readable
+        let without_dictionary = plain.compress(&data).unwrap();
+        assert_eq!(trained.decompress(&without_dictionary).unwrap().as_ref(), data.as_slice());
+
+        // Too few samples to learn from
+        assert!(Compressor::train(&samples[..2]).is_none());
+    }
+
+    #[test]
+    fn test_storage_compresses_existing_records() {
+        use crate::blockchain::block::Block;
+        use crate::blockchain::crypto::Address;
+        use crate::blockchain::storage::{BlockchainStorage, StorageOptions};
+        use crate::blockchain::transaction::Transaction;
+
+        let data_dir = tempfile::tempdir().unwrap();
+
+        {
+            // Written without compression
+            let storage = BlockchainStorage::new(data_dir.path()).unwrap();
+            for i in 0..32 {
+                let transaction = Transaction::new_coinbase(Address(format!("miner-{}", i)), 50.0);
+                storage.save_transaction(&transaction).unwrap();
+                storage.save_block(&Block::new(i, vec![transaction], i, "0".to_string())).unwrap();
+            }
+        }
+
+        let storage = BlockchainStorage::open(data_dir.path(), StorageOptions { compress: true, ..Default::default() }).unwrap();
+        let report = storage.compress_existing().unwrap();
+        // Every block, its raw bytes and its transaction
+        assert_eq!(report.records, 96);
+        assert!(report.trained_dictionary);
+        assert!(report.bytes_after < report.bytes_before);
+
+        // New records are compressed with the trained dictionary and everything reads back
+        let block = Block::new(32, Vec::new(), 0, "0".to_string());
+        storage.save_block(&block).unwrap();
+        assert_eq!(storage.get_block(&block.hash).unwrap().index, 32);
+        assert_eq!(storage.get_all_blocks().unwrap().len(), 33);
+        drop(storage);
+
+        // The dictionary is persisted with the data
+        let storage = BlockchainStorage::new(data_dir.path()).unwrap();
+        assert_eq!(storage.get_all_blocks().unwrap().len(), 33);
+        drop(storage);
+    }
+}
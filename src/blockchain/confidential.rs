@@ -0,0 +1,524 @@
+use bulletproofs::{BulletproofGens, PedersenGens, RangeProof};
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::Identity;
+use merlin::Transcript;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use thiserror::Error;
+
+use super::canonical;
+use super::crypto::{Address, CryptoError, Wallet};
+use super::signing::{self, SigningContext};
+use super::utxo::{OutPoint, TxInput};
+
+/// Bits of the range every hidden amount is proven to be in
+pub const AMOUNT_BITS: usize = 64;
+
+/// Label of the transcripts range proofs are made and checked with
+const TRANSCRIPT_LABEL: &[u8] = b"my_blockchain/confidential-output/v1";
+
+/// Errors that can occur while building or applying confidential transactions
+#[derive(Debug, Error)]
+pub enum ConfidentialError {
+    #[error("Output {0} does not exist or is already spent")]
+    MissingOutput(OutPoint),
+
+    #[error("Input {0} is not signed by the owner of the output it spends")]
+    InvalidSignature(usize),
+
+    #[error("Output {0} has an invalid range proof")]
+    InvalidRangeProof(usize),
+
+    #[error("The commitments of the inputs don't equal the outputs plus the fee")]
+    Unbalanced,
+
+    #[error("Insufficient funds: required {required}, available {available}")]
+    InsufficientFunds { required: u64, available: u64 },
+
+    #[error("Invalid block: {0}")]
+    InvalidBlock(String),
+
+    #[error("Invalid transaction: {0}")]
+    InvalidTransaction(String),
+
+    #[error("Crypto error: {0}")]
+    CryptoError(#[from] CryptoError),
+}
+
+/// What the owner of a confidential output must know to spend it
+///
+/// Only the sender and the recipient know the amount and the blinding
+/// factor, which the sender hands over outside the chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Opening {
+    /// The hidden amount, in base units
+    pub amount: u64,
+
+    /// The blinding factor of the commitment
+    pub blinding: Scalar,
+}
+
+impl Opening {
+    /// Checks if the opening matches a commitment
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - The output committed to
+    pub fn opens(&self, output: &ConfidentialOutput) -> bool {
+        output.commitment_point().ok() == Some(commit(self.amount, self.blinding))
+    }
+}
+
+/// An output whose amount is hidden behind a Pedersen commitment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfidentialOutput {
+    /// Address that may spend the output
+    pub recipient: Address,
+
+    /// Hex encoded commitment `amount * B + blinding * B_blinding`
+    pub commitment: String,
+
+    /// Hex encoded bulletproof that the amount is in 0..2^64, empty for a coinbase
+    pub range_proof: String,
+}
+
+impl ConfidentialOutput {
+    /// Creates an output with a range proof
+    ///
+    /// # Arguments
+    ///
+    /// * `recipient` - The owner
+    /// * `opening` - The amount and the blinding factor
+    ///
+    /// # Returns
+    ///
+    /// The output, or an error if the proof could not be made
+    pub fn new(recipient: Address, opening: Opening) -> Result<Self, ConfidentialError> {
+        let (proof, commitment) = RangeProof::prove_single(
+            &bulletproof_gens(),
+            &PedersenGens::default(),
+            &mut Transcript::new(TRANSCRIPT_LABEL),
+            opening.amount,
+            &opening.blinding,
+            AMOUNT_BITS,
+        )
+        .map_err(|err| ConfidentialError::InvalidTransaction(err.to_string()))?;
+
+        Ok(ConfidentialOutput {
+            recipient,
+            commitment: hex::encode(commitment.as_bytes()),
+            range_proof: hex::encode(proof.to_bytes()),
+        })
+    }
+
+    /// Checks the range proof of the output
+    pub fn verify_range(&self) -> bool {
+        let proof = match hex::decode(&self.range_proof).ok().and_then(|bytes| RangeProof::from_bytes(&bytes).ok()) {
+            Some(proof) => proof,
+            None => return false,
+        };
+        let commitment = match self.compressed_commitment() {
+            Some(commitment) => commitment,
+            None => return false,
+        };
+
+        proof
+            .verify_single(
+                &bulletproof_gens(),
+                &PedersenGens::default(),
+                &mut Transcript::new(TRANSCRIPT_LABEL),
+                &commitment,
+                AMOUNT_BITS,
+            )
+            .is_ok()
+    }
+
+    /// Decodes the commitment to a point
+    fn commitment_point(&self) -> Result<RistrettoPoint, ConfidentialError> {
+        self.compressed_commitment()
+            .and_then(|commitment| commitment.decompress())
+            .ok_or_else(|| ConfidentialError::InvalidTransaction(format!("invalid commitment {}", self.commitment)))
+    }
+
+    /// Decodes the hex commitment
+    fn compressed_commitment(&self) -> Option<CompressedRistretto> {
+        let bytes = hex::decode(&self.commitment).ok()?;
+        CompressedRistretto::from_slice(&bytes).ok()
+    }
+}
+
+/// A transaction of the UTXO ledger with hidden amounts
+///
+/// Nobody but the parties learns the amounts, yet anyone can check that no
+/// coins were created: Pedersen commitments add up, so the inputs'
+/// commitments must equal the outputs' plus the public fee, which holds
+/// only when the amounts and the blinding factors both balance. Because
+/// the sum could also balance with a "negative" output wrapping around the
+/// group order, every output carries a range proof that its amount is
+/// between 0 and 2^64.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfidentialTransaction {
+    /// SHA-256 hash of the outpoints, outputs and fee, signatures excluded
+    pub id: String,
+
+    /// The outputs spent, none for a coinbase
+    pub inputs: Vec<TxInput>,
+
+    /// The outputs created
+    pub outputs: Vec<ConfidentialOutput>,
+
+    /// The fee left to the miner, in the clear
+    pub fee: u64,
+
+    /// Amount of a coinbase, which is public, None for other transactions
+    pub coinbase_amount: Option<u64>,
+
+    /// Height of the block of a coinbase, which makes its ID unique
+    pub coinbase_height: Option<u64>,
+}
+
+impl ConfidentialTransaction {
+    /// Creates the coinbase of a block, committed with a zero blinding factor
+    ///
+    /// # Arguments
+    ///
+    /// * `recipient` - The miner
+    /// * `amount` - The reward plus the fees of the block
+    /// * `height` - Height of the block
+    ///
+    /// # Returns
+    ///
+    /// The coinbase and the opening of its output
+    pub fn coinbase(recipient: Address, amount: u64, height: u64) -> (Self, Opening) {
+        let opening = Opening {
+            amount,
+            blinding: Scalar::ZERO,
+        };
+        let output = ConfidentialOutput {
+            recipient,
+            commitment: hex::encode(commit(amount, Scalar::ZERO).compress().as_bytes()),
+            range_proof: String::new(),
+        };
+
+        (Self::with_id(Vec::new(), vec![output], 0, Some(amount), Some(height)), opening)
+    }
+
+    /// Builds a signed payment from outputs of a wallet
+    ///
+    /// The payment gets a random blinding factor and the change the one that
+    /// makes the blinding factors balance.
+    ///
+    /// # Arguments
+    ///
+    /// * `wallet` - The payer, the owner of the spent outputs
+    /// * `spends` - The outputs to spend with their openings
+    /// * `recipient` - The payee
+    /// * `amount` - The amount to pay
+    /// * `fee` - The fee left to the miner
+    ///
+    /// # Returns
+    ///
+    /// The transaction and the openings of the payment and the change, for the payee and the payer
+    pub fn transfer(
+        wallet: &Wallet,
+        spends: &[(OutPoint, Opening)],
+        recipient: Address,
+        amount: u64,
+        fee: u64,
+    ) -> Result<(Self, Opening, Opening), ConfidentialError> {
+        let available = spends.iter().try_fold(0u64, |sum, (_, opening)| sum.checked_add(opening.amount));
+        let required = amount.checked_add(fee);
+        let (available, required) = match (available, required) {
+            (Some(available), Some(required)) => (available, required),
+            _ => return Err(ConfidentialError::InvalidTransaction("amounts overflow".to_string())),
+        };
+        if available < required {
+            return Err(ConfidentialError::InsufficientFunds { required, available });
+        }
+
+        let input_blinding: Scalar = spends.iter().map(|(_, opening)| opening.blinding).sum();
+        let payment = Opening {
+            amount,
+            blinding: Scalar::random(&mut OsRng),
+        };
+        let change = Opening {
+            amount: available - required,
+            blinding: input_blinding - payment.blinding,
+        };
+
+        let outputs = vec![
+            ConfidentialOutput::new(recipient, payment)?,
+            ConfidentialOutput::new(wallet.address().clone(), change)?,
+        ];
+        let inputs = spends
+            .iter()
+            .map(|(outpoint, _)| TxInput {
+                outpoint: outpoint.clone(),
+                signature: None,
+            })
+            .collect();
+
+        let mut transaction = Self::with_id(inputs, outputs, fee, None, None);
+        let signature = signing::sign(wallet, SigningContext::ConfidentialTransaction, &transaction.signed_bytes())?;
+        for input in &mut transaction.inputs {
+            input.signature = Some(signature.clone());
+        }
+
+        Ok((transaction, payment, change))
+    }
+
+    /// Computes the ID from the content
+    pub fn compute_id(&self) -> String {
+        format!("{:x}", Sha256::digest(self.signed_bytes()))
+    }
+
+    /// Gets the canonical bytes the ID hashes and the inputs sign
+    fn signed_bytes(&self) -> Vec<u8> {
+        let inputs: Vec<_> = self
+            .inputs
+            .iter()
+            .map(|input| json!({ "transaction_id": input.outpoint.transaction_id, "index": input.outpoint.index }))
+            .collect();
+        let outputs: Vec<_> = self
+            .outputs
+            .iter()
+            .map(|output| {
+                json!({
+                    "recipient": output.recipient.0,
+                    "commitment": output.commitment,
+                    "range_proof": output.range_proof,
+                })
+            })
+            .collect();
+
+        canonical::to_vec(&json!({
+            "inputs": inputs,
+            "outputs": outputs,
+            "fee": self.fee,
+            "coinbase_amount": self.coinbase_amount,
+            "coinbase_height": self.coinbase_height,
+        }))
+    }
+
+    /// Creates a transaction and computes its ID
+    fn with_id(
+        inputs: Vec<TxInput>,
+        outputs: Vec<ConfidentialOutput>,
+        fee: u64,
+        coinbase_amount: Option<u64>,
+        coinbase_height: Option<u64>,
+    ) -> Self {
+        let mut transaction = ConfidentialTransaction {
+            id: String::new(),
+            inputs,
+            outputs,
+            fee,
+            coinbase_amount,
+            coinbase_height,
+        };
+        transaction.id = transaction.compute_id();
+        transaction
+    }
+}
+
+/// The unspent confidential outputs
+///
+/// Applies blocks like the `UtxoSet`, but never sees an amount other than
+/// the fees and the coinbase: it checks the signatures, the range proofs
+/// and that the commitments balance.
+#[derive(Debug, Clone, Default)]
+pub struct ConfidentialLedger {
+    outputs: HashMap<OutPoint, ConfidentialOutput>,
+}
+
+impl ConfidentialLedger {
+    /// Creates an empty ledger
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets an unspent output
+    ///
+    /// # Arguments
+    ///
+    /// * `outpoint` - The output
+    pub fn get(&self, outpoint: &OutPoint) -> Option<&ConfidentialOutput> {
+        self.outputs.get(outpoint)
+    }
+
+    /// Applies the transactions of a block, all or none of them
+    ///
+    /// # Arguments
+    ///
+    /// * `transactions` - The transactions, coinbase first
+    /// * `reward` - The block reward, in base units
+    ///
+    /// # Returns
+    ///
+    /// Result with the fees of the block
+    pub fn apply_block(
+        &mut self,
+        transactions: &[ConfidentialTransaction],
+        reward: u64,
+    ) -> Result<u64, ConfidentialError> {
+        let (coinbase, rest) = transactions
+            .split_first()
+            .ok_or_else(|| ConfidentialError::InvalidBlock("a block starts with its coinbase".to_string()))?;
+        if coinbase.coinbase_amount.is_none() || rest.iter().any(|transaction| transaction.coinbase_amount.is_some()) {
+            return Err(ConfidentialError::InvalidBlock("a block has exactly one coinbase, first".to_string()));
+        }
+
+        let mut next = self.clone();
+        let mut fees: u64 = 0;
+        for transaction in rest {
+            next.apply(transaction)?;
+            fees = fees
+                .checked_add(transaction.fee)
+                .ok_or_else(|| ConfidentialError::InvalidBlock("fees overflow".to_string()))?;
+        }
+        next.apply_coinbase(coinbase, reward.saturating_add(fees))?;
+
+        *self = next;
+        Ok(fees)
+    }
+
+    /// Checks a coinbase, whose amount is public, and adds its output
+    fn apply_coinbase(&mut self, coinbase: &ConfidentialTransaction, limit: u64) -> Result<(), ConfidentialError> {
+        let amount = coinbase.coinbase_amount.unwrap_or(0);
+        let output = match coinbase.outputs.as_slice() {
+            [output] => output,
+            _ => return Err(ConfidentialError::InvalidBlock("a coinbase has one output".to_string())),
+        };
+        if !coinbase.inputs.is_empty() || coinbase.fee != 0 {
+            return Err(ConfidentialError::InvalidBlock("a coinbase spends nothing and pays no fee".to_string()));
+        }
+        if output.commitment_point()? != commit(amount, Scalar::ZERO) {
+            return Err(ConfidentialError::InvalidBlock("the coinbase doesn't commit to its amount".to_string()));
+        }
+        if amount > limit {
+            return Err(ConfidentialError::InvalidBlock(format!(
+                "the coinbase pays {}, more than the reward and fees of {}",
+                amount, limit
+            )));
+        }
+
+        self.add_outputs(coinbase)
+    }
+
+    /// Spends the inputs of a transaction and adds its outputs
+    fn apply(&mut self, transaction: &ConfidentialTransaction) -> Result<(), ConfidentialError> {
+        if transaction.inputs.is_empty() {
+            return Err(ConfidentialError::InvalidTransaction("a transaction spends at least one output".to_string()));
+        }
+
+        let message = transaction.signed_bytes();
+        let mut balance = -commit(transaction.fee, Scalar::ZERO);
+        for (position, input) in transaction.inputs.iter().enumerate() {
+            let output = self
+                .outputs
+                .remove(&input.outpoint)
+                .ok_or_else(|| ConfidentialError::MissingOutput(input.outpoint.clone()))?;
+            let signature = input.signature.as_ref().ok_or(ConfidentialError::InvalidSignature(position))?;
+            let public_key = output.recipient.to_public_key()?;
+            if !signing::verify(&public_key, SigningContext::ConfidentialTransaction, &message, signature)? {
+                return Err(ConfidentialError::InvalidSignature(position));
+            }
+            balance += output.commitment_point()?;
+        }
+
+        for (position, output) in transaction.outputs.iter().enumerate() {
+            if !output.verify_range() {
+                return Err(ConfidentialError::InvalidRangeProof(position));
+            }
+            balance -= output.commitment_point()?;
+        }
+        if balance != RistrettoPoint::identity() {
+            return Err(ConfidentialError::Unbalanced);
+        }
+
+        self.add_outputs(transaction)
+    }
+
+    /// Adds the outputs of a transaction whose ID matches its content
+    fn add_outputs(&mut self, transaction: &ConfidentialTransaction) -> Result<(), ConfidentialError> {
+        if transaction.id != transaction.compute_id() {
+            return Err(ConfidentialError::InvalidTransaction(format!(
+                "{} does not match its content",
+                transaction.id
+            )));
+        }
+
+        for (index, output) in transaction.outputs.iter().enumerate() {
+            let outpoint = OutPoint {
+                transaction_id: transaction.id.clone(),
+                index: index as u32,
+            };
+            if self.outputs.insert(outpoint.clone(), output.clone()).is_some() {
+                return Err(ConfidentialError::InvalidTransaction(format!("output {} already exists", outpoint)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Commits to an amount with a blinding factor
+fn commit(amount: u64, blinding: Scalar) -> RistrettoPoint {
+    PedersenGens::default().commit(Scalar::from(amount), blinding)
+}
+
+/// The generators of single range proofs over `AMOUNT_BITS`
+fn bulletproof_gens() -> BulletproofGens {
+    BulletproofGens::new(AMOUNT_BITS, 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hidden_amounts_balance_without_being_revealed() {
+        let alice = Wallet::new().unwrap();
+        let bob = Wallet::new().unwrap();
+        let miner = Wallet::new().unwrap();
+        let mut ledger = ConfidentialLedger::new();
+
+        let (coinbase, funds) = ConfidentialTransaction::coinbase(alice.address().clone(), 50, 1);
+        ledger.apply_block(std::slice::from_ref(&coinbase), 50).unwrap();
+        let spent = OutPoint {
+            transaction_id: coinbase.id.clone(),
+            index: 0,
+        };
+
+        let bob_address = bob.address().clone();
+        let (payment, paid, change) =
+            ConfidentialTransaction::transfer(&alice, &[(spent.clone(), funds)], bob_address.clone(), 30, 1).unwrap();
+        let (reward, _) = ConfidentialTransaction::coinbase(miner.address().clone(), 51, 2);
+        assert_eq!(ledger.apply_block(&[reward, payment.clone()], 50).unwrap(), 1);
+
+        // Only the openings reveal the amounts
+        let output = |index| ledger.get(&OutPoint { transaction_id: payment.id.clone(), index }).unwrap();
+        assert!(paid.opens(output(0)) && paid.amount == 30);
+        assert!(change.opens(output(1)) && change.amount == 19);
+        assert!(!Opening { amount: 31, ..paid }.opens(output(0)));
+
+        // Claiming an input holds more than it does leaves the commitments unbalanced
+        let inflated = Opening { amount: 80, ..funds };
+        let (forged, _, _) =
+            ConfidentialTransaction::transfer(&alice, &[(spent, inflated)], bob_address, 70, 1).unwrap();
+        let mut replay = ConfidentialLedger::new();
+        replay.apply_block(&[coinbase], 50).unwrap();
+        let (reward, _) = ConfidentialTransaction::coinbase(miner.address().clone(), 50, 2);
+        assert!(matches!(replay.apply_block(&[reward, forged], 50), Err(ConfidentialError::Unbalanced)));
+
+        // A range proof only holds for its own commitment
+        let mut tampered = payment.outputs[0].clone();
+        assert!(tampered.verify_range());
+        tampered.range_proof = payment.outputs[1].range_proof.clone();
+        assert!(!tampered.verify_range());
+    }
+}
@@ -0,0 +1,409 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use log::warn;
+use thiserror::Error;
+
+use super::account::{Account, AccountState};
+use super::block::Block;
+use super::crypto::Address;
+use super::inheritance;
+use super::storage::{BlockchainStorage, StorageError};
+use super::transaction::Transaction;
+use super::validation;
+
+/// Largest difference between balances that still counts as equal
+const BALANCE_TOLERANCE: f64 = 1e-9;
+
+/// Errors that can occur while checking or repairing storage
+#[derive(Debug, Error)]
+pub enum ConsistencyError {
+    #[error("Storage error: {0}")]
+    StorageError(#[from] StorageError),
+
+    #[error("Storage can not be repaired: {0}")]
+    Unrepairable(String),
+}
+
+/// A problem found in the stored chain
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inconsistency {
+    /// Height the problem concerns
+    pub height: u64,
+
+    /// What is wrong
+    pub reason: String,
+}
+
+/// Outcome of a consistency check
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsistencyReport {
+    /// Number of blocks in storage, including ones that can't be read
+    pub stored_blocks: usize,
+
+    /// Height of the tip recorded in the metadata
+    pub recorded_height: Option<u64>,
+
+    /// Height up to which blocks and accounts agree, None if no height does
+    pub consistent_height: Option<u64>,
+
+    /// The problems found
+    pub issues: Vec<Inconsistency>,
+}
+
+impl ConsistencyReport {
+    /// Checks if no problem was found
+    pub fn is_consistent(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for ConsistencyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let height = |height: Option<u64>| height.map_or("none".to_string(), |height| height.to_string());
+
+        writeln!(f, "Storage consistency check")?;
+        writeln!(f, "  stored blocks:     {}", self.stored_blocks)?;
+        writeln!(f, "  recorded height:   {}", height(self.recorded_height))?;
+        write!(f, "  consistent height: {}", height(self.consistent_height))?;
+        for issue in &self.issues {
+            write!(f, "\n  height {}: {}", issue.height, issue.reason)?;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of a repair
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepairReport {
+    /// Height the chain was truncated to
+    pub height: u64,
+
+    /// Number of blocks removed from storage
+    pub removed_blocks: usize,
+}
+
+impl fmt::Display for RepairReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Storage repaired")?;
+        writeln!(f, "  height:         {}", self.height)?;
+        write!(f, "  removed blocks: {}", self.removed_blocks)
+    }
+}
+
+/// Checks that the stored chain can be loaded as it is
+///
+/// Follows the links from the genesis block, checks that the tip recorded in
+/// the metadata is the last linked block and replays the last blocks
+/// against the stored accounts. Accounts that lag behind the blocks, as
+/// after a crash between writing a block and its accounts, make the last
+/// height they do reflect the consistent one.
+///
+/// # Arguments
+///
+/// * `storage` - The storage to check
+/// * `replay_blocks` - How many of the last blocks to replay, 0 skips the accounts
+///
+/// # Returns
+///
+/// The report
+pub fn check_storage(storage: &BlockchainStorage, replay_blocks: u64) -> Result<ConsistencyReport, StorageError> {
+    inspect(storage, replay_blocks).map(|(report, _)| report)
+}
+
+/// Truncates the stored chain to its last consistent height
+///
+/// Removes every block above that height or off the chain, including ones
+/// that can't be read, and records the new tip. Accounts are kept, since
+/// they already reflect the consistent height.
+///
+/// # Arguments
+///
+/// * `storage` - The storage to repair
+/// * `replay_blocks` - How many of the last blocks to replay, 0 skips the accounts
+///
+/// # Returns
+///
+/// The repair report
+pub fn repair_storage(storage: &BlockchainStorage, replay_blocks: u64) -> Result<RepairReport, ConsistencyError> {
+    let (report, chain) = inspect(storage, replay_blocks)?;
+    let height = report.consistent_height.ok_or_else(|| {
+        ConsistencyError::Unrepairable("no height is consistent, restore a snapshot or reset the chain".to_string())
+    })?;
+
+    let kept = &chain[..=height as usize];
+    let keep: HashSet<&str> = kept.iter().map(|block| block.hash.as_str()).collect();
+
+    let mut removed_blocks = 0;
+    for hash in storage.get_block_hashes()? {
+        if !keep.contains(hash.as_str()) {
+            warn!("Removing block {} from storage", hash);
+            storage.remove_block(&hash)?;
+            removed_blocks += 1;
+        }
+    }
+
+    // Saving the tip again records it in the metadata
+    storage.save_block(kept.last().unwrap())?;
+    storage.flush()?;
+
+    Ok(RepairReport { height, removed_blocks })
+}
+
+/// Checks the storage, also returning the blocks linked from genesis
+fn inspect(storage: &BlockchainStorage, replay_blocks: u64) -> Result<(ConsistencyReport, Vec<Block>), StorageError> {
+    let stored_blocks = storage.get_block_hashes()?.len();
+    let blocks = storage.get_all_blocks()?;
+    let mut issues = Vec::new();
+
+    if blocks.len() < stored_blocks {
+        issues.push(Inconsistency {
+            height: 0,
+            reason: format!("{} stored blocks can not be read", stored_blocks - blocks.len()),
+        });
+    }
+
+    // Follow the links from the genesis block
+    let mut chain: Vec<Block> = Vec::new();
+    for block in blocks {
+        let linked = match chain.last() {
+            None => block.index == 0 && block.previous_hash == "0",
+            Some(parent) => block.index == parent.index + 1 && block.previous_hash == parent.hash,
+        };
+
+        if block.hash != block.calculate_hash() {
+            issues.push(Inconsistency {
+                height: block.index,
+                reason: format!("block {} does not match its hash", block.hash),
+            });
+        } else if !linked {
+            issues.push(Inconsistency {
+                height: block.index,
+                reason: format!("block {} does not link to a stored parent", block.hash),
+            });
+        } else {
+            chain.push(block);
+        }
+    }
+
+    let recorded_height = storage.get_block_height().ok();
+    let recorded_hash = storage.get_latest_block_hash().ok();
+
+    let tip = match chain.last() {
+        Some(tip) => tip,
+        None => {
+            // Nothing stored is a new node, anything else has lost its genesis block
+            if stored_blocks > 0 {
+                issues.push(Inconsistency {
+                    height: 0,
+                    reason: "no valid genesis block".to_string(),
+                });
+            }
+            let report = ConsistencyReport {
+                stored_blocks,
+                recorded_height,
+                consistent_height: None,
+                issues,
+            };
+            return Ok((report, chain));
+        }
+    };
+
+    if recorded_hash.as_deref() != Some(tip.hash.as_str()) || recorded_height != Some(tip.index) {
+        issues.push(Inconsistency {
+            height: tip.index,
+            reason: format!(
+                "metadata records tip {} at height {}, the last linked block is {}",
+                recorded_hash.as_deref().unwrap_or("none"),
+                recorded_height.map_or("none".to_string(), |height| height.to_string()),
+                tip.hash
+            ),
+        });
+    }
+
+    let consistent_height = if replay_blocks == 0 {
+        Some(tip.index)
+    } else {
+        let accounts = storage.get_all_accounts()?;
+        let reflected = reflected_height(&chain, &accounts, replay_blocks as usize);
+
+        match reflected {
+            Ok(height) => {
+                if height < tip.index {
+                    issues.push(Inconsistency {
+                        height: height + 1,
+                        reason: format!("accounts do not reflect the blocks from height {}", height + 1),
+                    });
+                }
+                Some(height)
+            }
+            Err(reason) => {
+                issues.push(Inconsistency {
+                    height: tip.index,
+                    reason: format!("accounts match none of the last {} blocks: {}", replay_blocks, reason),
+                });
+                None
+            }
+        }
+    };
+
+    let report = ConsistencyReport {
+        stored_blocks,
+        recorded_height,
+        consistent_height,
+        issues,
+    };
+    Ok((report, chain))
+}
+
+/// Finds the highest block the accounts reflect
+///
+/// Tries the tip first, then every lower block within the replay window.
+///
+/// # Returns
+///
+/// The height, or why the accounts don't reflect the tip
+fn reflected_height(chain: &[Block], accounts: &[Account], replay_blocks: usize) -> Result<u64, String> {
+    let lowest = chain.len().saturating_sub(replay_blocks).max(1);
+    let mut tip_reason = None;
+
+    for end in (lowest..=chain.len()).rev() {
+        let replayed = &chain[end.saturating_sub(replay_blocks)..end];
+        match replay(replayed, accounts) {
+            Ok(()) => return Ok(chain[end - 1].index),
+            Err(reason) => {
+                tip_reason.get_or_insert(reason);
+            }
+        }
+    }
+
+    Err(tip_reason.unwrap_or_default())
+}
+
+/// Replays blocks onto the state before them, which must give the accounts
+///
+/// The state before them is the accounts with the blocks unwound, which
+/// fails when the accounts can't have resulted from the blocks.
+fn replay(blocks: &[Block], accounts: &[Account]) -> Result<(), String> {
+    let state = AccountState::new();
+    state.replace_all(accounts.to_vec());
+
+    for block in blocks.iter().rev() {
+        for transaction in block.transactions.iter().rev() {
+            unwind(&state, transaction).map_err(|reason| format!("block {}: {}", block.index, reason))?;
+        }
+    }
+
+    for block in blocks {
+        validation::apply_block(&state, block).map_err(|err| format!("block {}: {}", block.index, err))?;
+    }
+
+    for account in accounts {
+        let replayed = state.get_account(&account.address);
+        if replayed.nonce != account.nonce || (replayed.balance - account.balance).abs() > BALANCE_TOLERANCE {
+            return Err(format!("account {} does not match its replay", account.address.0));
+        }
+    }
+
+    Ok(())
+}
+
+/// Takes a transaction back out of an account state
+fn unwind(state: &AccountState, transaction: &Transaction) -> Result<(), String> {
+    let take_back = |address: &Address, amount: f64| {
+        let mut recipient = state.get_account(address);
+        if recipient.balance + BALANCE_TOLERANCE < amount {
+            return Err(format!(
+                "account {} holds less than the {} it received in transaction {}",
+                recipient.address.0, amount, transaction.id
+            ));
+        }
+        recipient.balance -= amount;
+        state.update_account(recipient);
+        Ok(())
+    };
+
+    take_back(&transaction.recipient, transaction.amount)?;
+    if let Some(payout) = &transaction.treasury {
+        take_back(&Address(payout.address.clone()), payout.amount)?;
+    }
+
+    if !transaction.is_coinbase() {
+        let mut sender = state.get_account(&transaction.sender);
+        if sender.nonce != transaction.nonce + 1 {
+            return Err(format!(
+                "account {} has nonce {}, transaction {} calls for {}",
+                sender.address.0,
+                sender.nonce,
+                transaction.id,
+                transaction.nonce + 1
+            ));
+        }
+        sender.nonce = transaction.nonce;
+        sender.balance += transaction.total_amount();
+        state.update_account(sender);
+
+        // The sender got the fee back above, it goes to the fee payer instead
+        if let Some(fee_payer) = &transaction.fee_payer {
+            let mut sender = state.get_account(&transaction.sender);
+            let mut payer = state.get_account(&fee_payer.address);
+            sender.balance -= transaction.fee;
+            payer.balance += transaction.fee;
+            state.update_account(sender);
+            state.update_account(payer);
+        }
+
+        // The sender got the amount of a claim back above, it came from the owner
+        if let Some(owner) = inheritance::claimed_owner(transaction) {
+            let mut sender = state.get_account(&transaction.sender);
+            let mut owner = state.get_account(&owner);
+            sender.balance -= transaction.amount;
+            owner.balance += transaction.amount;
+            state.update_account(sender);
+            state.update_account(owner);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::{Address, Blockchain, Wallet};
+
+    #[test]
+    fn test_lagging_accounts_and_orphans_are_truncated() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let blockchain = Blockchain::with_storage(data_dir.path()).unwrap();
+        let storage = blockchain.get_storage().unwrap();
+        let miner = Wallet::new().unwrap();
+
+        blockchain.mine_block(&miner.address().0).unwrap();
+        let mut transaction = Transaction::new(miner.address().clone(), Address("bob".to_string()), 10.0, 1.0, 0);
+        transaction.sign(&miner).unwrap();
+        blockchain.add_transaction(transaction).unwrap();
+        assert!(check_storage(&storage, 10).unwrap().is_consistent());
+
+        // A crash after writing block 2 but before its accounts, then a stray block
+        let accounts = storage.get_all_accounts().unwrap();
+        blockchain.mine_block(&miner.address().0).unwrap();
+        assert!(check_storage(&storage, 10).unwrap().is_consistent());
+        storage.replace_accounts(&accounts).unwrap();
+        storage.save_block(&Block::new(7, Vec::new(), 0, "ff".to_string())).unwrap();
+
+        let report = check_storage(&storage, 10).unwrap();
+        assert_eq!((report.stored_blocks, report.recorded_height, report.consistent_height), (4, Some(7), Some(1)));
+        assert_eq!(report.issues.len(), 3);
+
+        let repaired = repair_storage(&storage, 10).unwrap();
+        assert_eq!((repaired.height, repaired.removed_blocks), (1, 2));
+        assert!(check_storage(&storage, 10).unwrap().is_consistent());
+
+        drop(storage);
+        drop(blockchain);
+        let reloaded = Blockchain::with_storage(data_dir.path()).unwrap();
+        assert_eq!(reloaded.get_last_block().index, 1);
+        assert_eq!(reloaded.get_account_state().get_account(miner.address()).nonce, 0);
+    }
+}
@@ -1,12 +1,22 @@
-use ed25519_dalek::{Signature, SigningKey, Signer, Verifier, VerifyingKey};
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use curve25519_dalek::scalar::Scalar;
+use ed25519_dalek::hazmat::{self, ExpandedSecretKey};
+use ed25519_dalek::{Signature, Signer as _, SigningKey, VerifyingKey};
 use rand::rngs::OsRng;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
 use thiserror::Error;
 use utoipa::ToSchema;
 
 use std::fmt;
 use std::str::FromStr;
 
+/// Tag of the hash turning a parent address and an index into the tweak of a child key
+const CHILD_TWEAK_TAG: &[u8] = b"my_blockchain/child-tweak/v1\0";
+
+/// Tag of the hash deriving the signature nonce prefix of a child key
+const CHILD_NONCE_PREFIX_TAG: &[u8] = b"my_blockchain/child-nonce-prefix/v1\0";
+
 /// Errors that can occur during cryptographic operations
 #[derive(Debug, Error)]
 pub enum CryptoError {
@@ -58,6 +68,21 @@ impl Address {
         })?)
         .map_err(|e| CryptoError::InvalidPublicKey(e.to_string()))
     }
+
+    /// Derives the address of a child key at the given index
+    ///
+    /// Derivation is not hardened: the child public key is the parent public key
+    /// plus `H(address, index) * G`, so child addresses can be derived from the
+    /// address alone, and only the holder of the parent key can sign for them,
+    /// see `Wallet::derive_child`.
+    pub fn derive_child(&self, index: u32) -> Result<Address, CryptoError> {
+        let parent = CompressedEdwardsY(self.to_public_key()?.to_bytes())
+            .decompress()
+            .ok_or_else(|| CryptoError::InvalidPublicKey("Public key is not a curve point".to_string()))?;
+        let child = EdwardsPoint::mul_base(&child_tweak(self, index)) + parent;
+
+        Ok(Address::from_public_key(&VerifyingKey::from(child)))
+    }
 }
 
 impl fmt::Display for Address {
@@ -165,6 +190,108 @@ impl Wallet {
     pub fn export_secret_key(&self) -> Vec<u8> {
         self.signing_key.to_bytes().to_vec()
     }
+
+    /// Derives the key of a child address at the given index
+    ///
+    /// The child secret scalar is the parent one plus `H(address, index)`, so the
+    /// key signs for `Address::derive_child` of the wallet's address. Together
+    /// with the parent address a child key gives the parent key away, it must be
+    /// kept as secret.
+    pub fn derive_child(&self, index: u32) -> Result<ChildKey, CryptoError> {
+        Ok(ChildKey::new(self.signing_key.to_scalar() + child_tweak(&self.address, index)))
+    }
+}
+
+/// Something holding the private key of an address
+pub trait Signer {
+    /// Gets the address the key signs for
+    fn address(&self) -> &Address;
+
+    /// Signs a message with the private key
+    fn sign(&self, message: &[u8]) -> Result<DigitalSignature, CryptoError>;
+}
+
+impl Signer for Wallet {
+    fn address(&self) -> &Address {
+        Wallet::address(self)
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<DigitalSignature, CryptoError> {
+        Wallet::sign(self, message)
+    }
+}
+
+/// The secret key of a child address, see `Wallet::derive_child`
+///
+/// It is not the hash of a seed like the key of a `Wallet`, so it signs with
+/// the expanded key directly.
+pub struct ChildKey {
+    expanded: ExpandedSecretKey,
+    public_key: VerifyingKey,
+    address: Address,
+}
+
+impl ChildKey {
+    /// Creates the key of a secret scalar
+    fn new(secret: Scalar) -> Self {
+        let mut hasher = Sha512::new();
+        hasher.update(CHILD_NONCE_PREFIX_TAG);
+        hasher.update(secret.as_bytes());
+        let mut hash_prefix = [0u8; 32];
+        hash_prefix.copy_from_slice(&hasher.finalize()[..32]);
+
+        let public_key = VerifyingKey::from(EdwardsPoint::mul_base(&secret));
+        ChildKey {
+            expanded: ExpandedSecretKey {
+                scalar: secret,
+                hash_prefix,
+            },
+            address: Address::from_public_key(&public_key),
+            public_key,
+        }
+    }
+
+    /// Gets the child address
+    pub fn address(&self) -> &Address {
+        &self.address
+    }
+
+    /// Gets the child key's public key
+    pub fn public_key(&self) -> &VerifyingKey {
+        &self.public_key
+    }
+
+    /// Signs a message with the child key
+    pub fn sign(&self, message: &[u8]) -> Result<DigitalSignature, CryptoError> {
+        let signature = hazmat::raw_sign::<Sha512>(&self.expanded, message, &self.public_key);
+        Ok(DigitalSignature::from_signature(&signature))
+    }
+}
+
+impl Signer for ChildKey {
+    fn address(&self) -> &Address {
+        ChildKey::address(self)
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<DigitalSignature, CryptoError> {
+        ChildKey::sign(self, message)
+    }
+}
+
+impl fmt::Debug for ChildKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChildKey").field("address", &self.address).finish_non_exhaustive()
+    }
+}
+
+/// Hashes a parent address and an index into the tweak of a child key
+fn child_tweak(parent: &Address, index: u32) -> Scalar {
+    let mut hasher = Sha512::new();
+    hasher.update(CHILD_TWEAK_TAG);
+    hasher.update(parent.0.as_bytes());
+    hasher.update(index.to_be_bytes());
+
+    Scalar::from_bytes_mod_order_wide(&hasher.finalize().into())
 }
 
 /// Verifies a signature against a message and public key
@@ -175,7 +302,8 @@ pub fn verify_signature(
 ) -> Result<bool, CryptoError> {
     let signature = signature.to_signature()?;
 
-    match public_key.verify(message, &signature) {
+    // Strict verification also rejects malleable signatures using small order points
+    match public_key.verify_strict(message, &signature) {
         Ok(_) => Ok(true),
         Err(_) => Ok(false),
     }
@@ -209,6 +337,29 @@ mod tests {
         assert!(!result);
     }
 
+    #[test]
+    fn test_derive_child() {
+        let wallet = Wallet::new().unwrap();
+
+        let first = wallet.derive_child(0).unwrap();
+        let second = wallet.derive_child(1).unwrap();
+
+        // Derivation is deterministic and every index yields a different address
+        assert_eq!(first.address(), wallet.derive_child(0).unwrap().address());
+        assert_ne!(first.address(), second.address());
+        assert_ne!(first.address(), wallet.address());
+
+        // The address alone derives the same child addresses
+        assert_eq!(&wallet.address().derive_child(0).unwrap(), first.address());
+        assert_eq!(&wallet.address().derive_child(1).unwrap(), second.address());
+
+        // The child key signs for its address
+        let message = b"Hello, child!";
+        let signature = first.sign(message).unwrap();
+        assert!(verify_signature(message, &signature, first.public_key()).unwrap());
+        assert!(!verify_signature(message, &signature, second.public_key()).unwrap());
+    }
+
     #[test]
     fn test_address_conversion() {
         let wallet = Wallet::new().unwrap();
@@ -0,0 +1,358 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::block::Block;
+use super::fees;
+use super::governance::ChainParameters;
+use super::maintenance::MaintenanceStatus;
+use super::storage::StorageHealth;
+use super::transaction::Transaction;
+
+/// Recent blocks the dashboard describes
+pub const DASHBOARD_BLOCKS: usize = 20;
+
+/// Health of the node in one payload, for monitoring UIs
+///
+/// Gathers what clients otherwise read from the chain, fee, supply, peer and
+/// storage endpoints, taken at the same moment.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Dashboard {
+    /// When the dashboard was put together
+    #[schema(value_type = String, example = "2023-01-01T12:00:00Z")]
+    pub generated_at: DateTime<Utc>,
+
+    /// The last block
+    pub tip: TipStatus,
+
+    /// The transactions waiting for a block
+    pub mempool: MempoolStatus,
+
+    /// Time between the recent blocks
+    pub block_intervals: BlockIntervals,
+
+    /// Coins block rewards issue
+    pub issuance: IssuanceRate,
+
+    /// Base fees and fees paid by the recent blocks
+    pub fees: FeeTrend,
+
+    /// Peers, branches and maintenance
+    pub network: NetworkStatus,
+
+    /// Storage backing the chain
+    pub storage: StorageHealth,
+}
+
+/// The last block of the chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TipStatus {
+    /// Height of the last block
+    pub height: u64,
+
+    /// Hash of the last block
+    pub hash: String,
+
+    /// When the last block was mined
+    #[schema(value_type = String, example = "2023-01-01T12:00:00Z")]
+    pub timestamp: DateTime<Utc>,
+
+    /// Seconds since the last block was mined, growing while the node mines nothing or falls behind
+    pub age_secs: i64,
+}
+
+impl TipStatus {
+    /// Describes the last block
+    ///
+    /// # Arguments
+    ///
+    /// * `tip` - The last block
+    /// * `now` - The current time
+    pub fn new(tip: &Block, now: DateTime<Utc>) -> Self {
+        TipStatus {
+            height: tip.index,
+            hash: tip.hash.clone(),
+            timestamp: tip.timestamp,
+            age_secs: (now - tip.timestamp).num_seconds(),
+        }
+    }
+}
+
+/// The transactions waiting for a block
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct MempoolStatus {
+    /// Transactions that can go into the next block
+    pub pending: usize,
+
+    /// Transactions waiting for an earlier nonce of their sender
+    pub queued: usize,
+
+    /// Amount the pending transactions send
+    pub total_amount: f64,
+
+    /// Fees the pending transactions pay
+    pub total_fees: f64,
+
+    /// Seconds since the oldest pending transaction was created, None with none pending
+    pub oldest_age_secs: Option<i64>,
+}
+
+impl MempoolStatus {
+    /// Describes the pending transactions
+    ///
+    /// # Arguments
+    ///
+    /// * `pending` - The pending transactions
+    /// * `queued` - Number of transactions waiting for an earlier nonce
+    /// * `now` - The current time
+    pub fn new(pending: &[Transaction], queued: usize, now: DateTime<Utc>) -> Self {
+        MempoolStatus {
+            pending: pending.len(),
+            queued,
+            total_amount: pending.iter().map(|transaction| transaction.amount).sum(),
+            total_fees: pending.iter().map(|transaction| transaction.fee).sum(),
+            oldest_age_secs: pending
+                .iter()
+                .map(|transaction| transaction.timestamp)
+                .min()
+                .map(|oldest| (now - oldest).num_seconds()),
+        }
+    }
+}
+
+/// Time between the recent blocks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct BlockIntervals {
+    /// Seconds between each recent block and its parent, oldest first
+    pub intervals_secs: Vec<i64>,
+
+    /// Average of the intervals, None before the second mined block
+    pub average_secs: Option<f64>,
+
+    /// Shortest interval
+    pub min_secs: Option<i64>,
+
+    /// Longest interval
+    pub max_secs: Option<i64>,
+}
+
+impl BlockIntervals {
+    /// Measures the time between the last `DASHBOARD_BLOCKS` blocks
+    ///
+    /// The genesis block is dated at the start of the network rather than
+    /// mined, so the time up to the first mined block is left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks of the chain, genesis first
+    pub fn new(blocks: &[Block]) -> Self {
+        let intervals_secs: Vec<i64> = recent_pairs(blocks)
+            .filter(|(parent, _)| parent.index > 0)
+            .map(|(parent, block)| (block.timestamp - parent.timestamp).num_seconds())
+            .collect();
+
+        BlockIntervals {
+            average_secs: (!intervals_secs.is_empty())
+                .then(|| intervals_secs.iter().sum::<i64>() as f64 / intervals_secs.len() as f64),
+            min_secs: intervals_secs.iter().min().copied(),
+            max_secs: intervals_secs.iter().max().copied(),
+            intervals_secs,
+        }
+    }
+}
+
+/// Coins block rewards issue, over the chain and lately
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct IssuanceRate {
+    /// Reward of the next block
+    pub current_reward: f64,
+
+    /// Coins issued by block rewards so far
+    pub total_issued: f64,
+
+    /// Coins issued by the recent blocks
+    pub recent_issued: f64,
+
+    /// Coins issued per hour at the pace of the recent blocks, None before the second mined block
+    pub per_hour: Option<f64>,
+}
+
+impl IssuanceRate {
+    /// Measures the issuance of a chain
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks of the chain, genesis first
+    /// * `reward_at` - The reward of a block at a height
+    pub fn new(blocks: &[Block], reward_at: impl Fn(u64) -> f64) -> Self {
+        let height = blocks.last().map(|block| block.index).unwrap_or(0);
+        let recent: Vec<(&Block, &Block)> = recent_pairs(blocks).filter(|(parent, _)| parent.index > 0).collect();
+        let recent_issued: f64 = recent.iter().map(|(_, block)| reward_at(block.index)).sum();
+        let elapsed = match (recent.first(), recent.last()) {
+            (Some((first, _)), Some((_, last))) => (last.timestamp - first.timestamp).num_seconds(),
+            _ => 0,
+        };
+
+        IssuanceRate {
+            current_reward: reward_at(height + 1),
+            total_issued: (1..=height).map(&reward_at).sum(),
+            recent_issued,
+            per_hour: (elapsed > 0).then(|| recent_issued * 3600.0 / elapsed as f64),
+        }
+    }
+}
+
+/// Base fee and fees of a block
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RecentBlockFees {
+    /// Height of the block
+    pub height: u64,
+
+    /// Lowest fee a transaction of the block had to pay
+    pub base_fee: f64,
+
+    /// Transactions of the block besides the coinbase
+    pub transactions: usize,
+
+    /// Fees the transactions of the block paid
+    pub total_fees: f64,
+}
+
+/// Base fees and fees paid by the recent blocks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FeeTrend {
+    /// The recent blocks, oldest first
+    pub blocks: Vec<RecentBlockFees>,
+
+    /// Base fee of the next block
+    pub next_base_fee: f64,
+}
+
+impl FeeTrend {
+    /// Follows the base fee through a chain
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks of the chain, genesis first
+    /// * `parameters_at` - The parameters governance has in force at a height
+    pub fn new(blocks: &[Block], parameters_at: impl Fn(u64) -> ChainParameters) -> Self {
+        let recent = blocks.len().saturating_sub(DASHBOARD_BLOCKS).max(1);
+        let mut base_fee = parameters_at(1).minimum_fee;
+        let mut trend = Vec::new();
+
+        for (position, block) in blocks.iter().enumerate().skip(1) {
+            if position >= recent {
+                let paid = block.transactions.iter().filter(|transaction| !transaction.is_coinbase());
+                trend.push(RecentBlockFees {
+                    height: block.index,
+                    base_fee,
+                    transactions: paid.clone().count(),
+                    total_fees: paid.map(|transaction| transaction.fee).sum(),
+                });
+            }
+            base_fee = fees::next_base_fee(
+                base_fee,
+                block,
+                parameters_at(block.index).max_block_transactions,
+                parameters_at(block.index + 1).minimum_fee,
+            );
+        }
+
+        FeeTrend {
+            blocks: trend,
+            next_base_fee: base_fee,
+        }
+    }
+}
+
+/// Peers, branches and maintenance of the node
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct NetworkStatus {
+    /// Name of the network the node belongs to
+    pub network: String,
+
+    /// Peers the node knows of
+    pub peers: usize,
+
+    /// Peers the node is connected to
+    pub connected_peers: usize,
+
+    /// Peers an operator banned
+    pub banned_peers: usize,
+
+    /// Whether the node only follows blocks mined elsewhere
+    pub follower: bool,
+
+    /// Blocks of competing branches the node keeps in case one overtakes the chain
+    pub side_blocks: usize,
+
+    /// Reorganizations since the chain was created
+    pub reorgs: usize,
+
+    /// Whether transactions or mining are paused
+    pub maintenance: MaintenanceStatus,
+}
+
+/// Pairs the last `DASHBOARD_BLOCKS` blocks with their parents
+fn recent_pairs(blocks: &[Block]) -> impl Iterator<Item = (&Block, &Block)> {
+    let start = blocks.len().saturating_sub(DASHBOARD_BLOCKS + 1);
+    blocks[start..].windows(2).map(|pair| (&pair[0], &pair[1]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::crypto::Address;
+    use chrono::Duration;
+
+    fn chain(intervals: &[i64]) -> Vec<Block> {
+        let start = Utc::now() - Duration::hours(1);
+        let mut blocks = vec![Block::new(0, vec![], 0, "0".to_string())];
+        blocks[0].timestamp = start - Duration::days(30);
+        let mut timestamp = start;
+        for (position, interval) in intervals.iter().enumerate() {
+            timestamp += Duration::seconds(*interval);
+            let coinbase = Transaction::new_coinbase(Address("miner".to_string()), 50.0);
+            let mut transfer = Transaction::new(Address("alice".to_string()), Address("bob".to_string()), 1.0, 0.5, 0);
+            transfer.id = format!("{}", position);
+            let mut block = Block::new(position as u64 + 1, vec![coinbase, transfer], 0, "0".to_string());
+            block.timestamp = timestamp;
+            blocks.push(block);
+        }
+        blocks
+    }
+
+    #[test]
+    fn test_intervals_and_issuance_leave_out_the_genesis_block() {
+        let blocks = chain(&[0, 60, 120, 30]);
+
+        let intervals = BlockIntervals::new(&blocks);
+        assert_eq!(intervals.intervals_secs, vec![60, 120, 30]);
+        assert_eq!(intervals.average_secs, Some(70.0));
+        assert_eq!((intervals.min_secs, intervals.max_secs), (Some(30), Some(120)));
+
+        // 3 rewards of 10 in 210 seconds
+        let issuance = IssuanceRate::new(&blocks, |_| 10.0);
+        assert_eq!(issuance.total_issued, 40.0);
+        assert_eq!(issuance.recent_issued, 30.0);
+        assert!((issuance.per_hour.unwrap() - 30.0 * 3600.0 / 210.0).abs() < 1e-9);
+
+        let trend = FeeTrend::new(&blocks, |_| ChainParameters::node());
+        assert_eq!(trend.blocks.len(), 4);
+        assert!(trend.blocks.iter().all(|block| block.transactions == 1 && block.total_fees == 0.5));
+
+        let empty = BlockIntervals::new(&blocks[..2]);
+        assert!(empty.intervals_secs.is_empty() && empty.average_secs.is_none());
+        assert!(IssuanceRate::new(&blocks[..2], |_| 10.0).per_hour.is_none());
+    }
+
+    #[test]
+    fn test_only_the_recent_blocks_are_described() {
+        let blocks = chain(&[10; 30]);
+
+        assert_eq!(BlockIntervals::new(&blocks).intervals_secs.len(), DASHBOARD_BLOCKS);
+        let trend = FeeTrend::new(&blocks, |_| ChainParameters::node());
+        assert_eq!(trend.blocks.len(), DASHBOARD_BLOCKS);
+        assert_eq!(trend.blocks[0].height, 11);
+    }
+}
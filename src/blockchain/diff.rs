@@ -0,0 +1,221 @@
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::block::Block;
+
+/// A block that is only on one of the compared chains
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DivergingBlock {
+    /// Height of the block
+    pub index: u64,
+
+    /// Hash of the block
+    pub hash: String,
+
+    /// Number of transactions in the block
+    pub transaction_count: usize,
+}
+
+/// How the diverging blocks of each chain change the balance of an address
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BalanceDifference {
+    /// The address
+    pub address: String,
+
+    /// Balance change from the diverging blocks of the local chain
+    pub local_change: f64,
+
+    /// Balance change from the diverging blocks of the other chain
+    pub other_change: f64,
+}
+
+/// Differences between two copies of the chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChainDiff {
+    /// Height of the local tip
+    pub local_height: u64,
+
+    /// Height of the other tip
+    pub other_height: u64,
+
+    /// Height of the last block both chains share, None if even the genesis blocks differ
+    pub fork_height: Option<u64>,
+
+    /// Blocks after the fork point on the local chain
+    pub local_blocks: Vec<DivergingBlock>,
+
+    /// Blocks after the fork point on the other chain
+    pub other_blocks: Vec<DivergingBlock>,
+
+    /// Addresses whose balance differs because of the diverging blocks
+    pub balance_differences: Vec<BalanceDifference>,
+}
+
+impl ChainDiff {
+    /// Checks if both chains are identical
+    pub fn is_identical(&self) -> bool {
+        self.local_blocks.is_empty() && self.other_blocks.is_empty()
+    }
+}
+
+impl fmt::Display for ChainDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Chain diff")?;
+        writeln!(f, "  local height: {}", self.local_height)?;
+        writeln!(f, "  other height: {}", self.other_height)?;
+
+        match self.fork_height {
+            Some(height) if self.is_identical() => {
+                return writeln!(f, "  chains are identical up to height {}", height);
+            }
+            Some(height) => writeln!(f, "  fork point:   {}", height)?,
+            None => writeln!(f, "  fork point:   none, the genesis blocks differ")?,
+        }
+
+        for (side, blocks) in [("local", &self.local_blocks), ("other", &self.other_blocks)] {
+            writeln!(f, "  {} only blocks: {}", side, blocks.len())?;
+            for block in blocks {
+                writeln!(f, "    #{:<8} {} ({} transactions)", block.index, block.hash, block.transaction_count)?;
+            }
+        }
+
+        writeln!(f, "  balance differences: {}", self.balance_differences.len())?;
+        for difference in &self.balance_differences {
+            writeln!(
+                f,
+                "    {:<48} local {:+} other {:+}",
+                difference.address, difference.local_change, difference.other_change
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Compares two copies of the chain
+///
+/// # Arguments
+///
+/// * `local` - The blocks of the local chain, genesis first
+/// * `other` - The blocks of the other chain, genesis first
+///
+/// # Returns
+///
+/// The fork point, the blocks after it on each side and the balance differences they cause
+pub fn diff_chains(local: &[Block], other: &[Block]) -> ChainDiff {
+    let shared = local
+        .iter()
+        .zip(other.iter())
+        .take_while(|(a, b)| a.hash == b.hash)
+        .count();
+
+    let local_changes = balance_changes(&local[shared..]);
+    let other_changes = balance_changes(&other[shared..]);
+
+    let mut addresses: Vec<&String> = local_changes.keys().chain(other_changes.keys()).collect();
+    addresses.sort();
+    addresses.dedup();
+
+    let balance_differences = addresses
+        .into_iter()
+        .map(|address| BalanceDifference {
+            address: address.clone(),
+            local_change: local_changes.get(address).copied().unwrap_or(0.0),
+            other_change: other_changes.get(address).copied().unwrap_or(0.0),
+        })
+        .filter(|difference| difference.local_change != difference.other_change)
+        .collect();
+
+    ChainDiff {
+        local_height: local.last().map_or(0, |block| block.index),
+        other_height: other.last().map_or(0, |block| block.index),
+        fork_height: shared.checked_sub(1).map(|i| local[i].index),
+        local_blocks: local[shared..].iter().map(diverging_block).collect(),
+        other_blocks: other[shared..].iter().map(diverging_block).collect(),
+        balance_differences,
+    }
+}
+
+/// Summarizes a block after the fork point
+fn diverging_block(block: &Block) -> DivergingBlock {
+    DivergingBlock {
+        index: block.index,
+        hash: block.hash.clone(),
+        transaction_count: block.transactions.len(),
+    }
+}
+
+/// Adds up the balance change of every address in some blocks
+///
+/// Senders pay the amount and the fee unless a fee payer pays it, recipients
+/// receive the amount and coinbase transactions credit their recipient and the treasury.
+fn balance_changes(blocks: &[Block]) -> BTreeMap<String, f64> {
+    let mut changes = BTreeMap::new();
+
+    for transaction in blocks.iter().flat_map(|block| block.transactions.iter()) {
+        if !transaction.is_coinbase() {
+            *changes.entry(transaction.sender.0.clone()).or_insert(0.0) -= transaction.amount;
+            *changes.entry(transaction.fee_paid_by().0.clone()).or_insert(0.0) -= transaction.fee;
+        }
+        *changes.entry(transaction.recipient.0.clone()).or_insert(0.0) += transaction.amount;
+        if let Some(payout) = &transaction.treasury {
+            *changes.entry(payout.address.clone()).or_insert(0.0) += payout.amount;
+        }
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::crypto::Address;
+    use crate::blockchain::transaction::Transaction;
+
+    #[test]
+    fn test_diff_finds_fork_and_balance_differences() {
+        let genesis = Block::new(0, Vec::new(), 1, "0".to_string());
+        let shared = Block::new(
+            1,
+            vec![Transaction::new_coinbase(Address("miner".to_string()), 50.0)],
+            0,
+            genesis.hash.clone(),
+        );
+        let local_tip = Block::new(
+            2,
+            vec![
+                Transaction::new(Address("miner".to_string()), Address("bob".to_string()), 10.0, 1.0, 0),
+                Transaction::new_coinbase(Address("miner".to_string()), 50.0),
+            ],
+            0,
+            shared.hash.clone(),
+        );
+        let other_tip = Block::new(
+            2,
+            vec![Transaction::new_coinbase(Address("miner".to_string()), 50.0)],
+            0,
+            shared.hash.clone(),
+        );
+
+        let local = vec![genesis.clone(), shared.clone(), local_tip.clone()];
+        let other = vec![genesis, shared, other_tip.clone()];
+
+        let diff = diff_chains(&local, &other);
+        assert_eq!((diff.local_height, diff.other_height, diff.fork_height), (2, 2, Some(1)));
+        assert_eq!(diff.local_blocks[0].hash, local_tip.hash);
+        assert_eq!(diff.other_blocks[0].hash, other_tip.hash);
+        assert_eq!(
+            diff.balance_differences,
+            vec![
+                BalanceDifference { address: "bob".to_string(), local_change: 10.0, other_change: 0.0 },
+                BalanceDifference { address: "miner".to_string(), local_change: 39.0, other_change: 50.0 },
+            ]
+        );
+
+        let same = diff_chains(&local, &local);
+        assert!(same.is_identical());
+        assert_eq!(same.fork_height, Some(2));
+    }
+}
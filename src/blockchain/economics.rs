@@ -0,0 +1,205 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::block::Block;
+use super::simulation::ConsensusRules;
+
+/// Blocks per period when the report is not told
+pub const DEFAULT_PERIOD_BLOCKS: u64 = 100;
+
+/// Periods projected when the report is not told
+pub const DEFAULT_PROJECTED_PERIODS: u64 = 10;
+
+/// Most periods the report projects
+pub const MAX_PROJECTED_PERIODS: u64 = 1_000;
+
+/// Coins issued by the blocks of a range of heights
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct EmissionPeriod {
+    /// First height of the period
+    pub first_height: u64,
+
+    /// Last height of the period
+    pub last_height: u64,
+
+    /// Coins the block rewards of the period issue
+    pub issued: f64,
+
+    /// Coins issued from the first block up to the end of the period
+    pub total_issued: f64,
+}
+
+/// The emission curve of the chain, past and projected
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct EconomicsReport {
+    /// Height of the last block
+    pub height: u64,
+
+    /// Blocks per period
+    pub period_blocks: u64,
+
+    /// Reward of the next block, as governance has it in force
+    pub current_reward: f64,
+
+    /// Blocks between reward halvings, None if the reward doesn't halve
+    pub halving_interval: Option<u64>,
+
+    /// Height of the next halving, None if there is none
+    pub next_halving: Option<u64>,
+
+    /// Coins issued by block rewards so far
+    pub issued: f64,
+
+    /// Most coins block rewards will ever issue, None if the emission is unbounded
+    pub supply_cap: Option<f64>,
+
+    /// Percentage of the cap issued so far, None without a cap
+    pub percent_of_cap_issued: Option<f64>,
+
+    /// Issuance of each period so far, the last one may be partial
+    pub history: Vec<EmissionPeriod>,
+
+    /// Issuance of the periods after the last block if the rules stay as they are
+    pub projection: Vec<EmissionPeriod>,
+}
+
+impl EconomicsReport {
+    /// Describes the emission of a chain
+    ///
+    /// Past issuance counts the reward in force at the height of each
+    /// coinbase. The projection and the cap follow the halving schedule of
+    /// the rules, scaled to the current reward, so a reward governance
+    /// changed is carried forward.
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks of the chain, genesis first
+    /// * `reward_at` - The reward of a block at a height
+    /// * `rules` - The rules rewards follow from now on
+    /// * `period_blocks` - Blocks per period, at least 1
+    /// * `projected_periods` - Number of periods to project
+    ///
+    /// # Returns
+    ///
+    /// The report
+    pub fn new(
+        blocks: &[Block],
+        reward_at: impl Fn(u64) -> f64,
+        rules: &ConsensusRules,
+        period_blocks: u64,
+        projected_periods: u64,
+    ) -> Self {
+        let height = blocks.last().map(|block| block.index).unwrap_or(0);
+        let current_reward = reward_at(height + 1);
+
+        // Heights are grouped into periods from height 1 on, the genesis block issues nothing
+        let period_of = |height: u64| height.saturating_sub(1) / period_blocks;
+        let mut history: Vec<EmissionPeriod> = Vec::new();
+        let mut issued = 0.0;
+        if height > 0 {
+            for period in 0..=period_of(height) {
+                history.push(EmissionPeriod {
+                    first_height: period * period_blocks + 1,
+                    last_height: ((period + 1) * period_blocks).min(height),
+                    issued: 0.0,
+                    total_issued: 0.0,
+                });
+            }
+        }
+        for block in blocks.iter().filter(|block| block.index > 0) {
+            let coinbases = block.transactions.iter().filter(|transaction| transaction.is_coinbase()).count();
+            history[period_of(block.index) as usize].issued += coinbases as f64 * reward_at(block.index);
+        }
+        for period in &mut history {
+            issued += period.issued;
+            period.total_issued = issued;
+        }
+
+        // The schedule of the rules, scaled so the next block pays the current reward
+        let scheduled = rules.reward_at(height + 1);
+        let scale = if scheduled > 0.0 { current_reward / scheduled } else { 0.0 };
+        let mut total_issued = issued;
+        let projection = (0..projected_periods)
+            .map(|period| {
+                let first_height = height + 1 + period * period_blocks;
+                let last_height = first_height + period_blocks - 1;
+                let issued: f64 = (first_height..=last_height).map(|height| scale * rules.reward_at(height)).sum();
+                total_issued += issued;
+                EmissionPeriod {
+                    first_height,
+                    last_height,
+                    issued,
+                    total_issued,
+                }
+            })
+            .collect();
+
+        let supply_cap = rules.emission_after(height).map(|remaining| issued + scale * remaining);
+        EconomicsReport {
+            height,
+            period_blocks,
+            current_reward,
+            halving_interval: rules.halving_interval,
+            next_halving: rules.next_halving(height),
+            issued,
+            supply_cap,
+            percent_of_cap_issued: supply_cap.filter(|cap| *cap > 0.0).map(|cap| issued / cap * 100.0),
+            history,
+            projection,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::crypto::Address;
+    use crate::blockchain::simulation::DEFAULT_TARGET_INTERVAL_SECS;
+    use crate::blockchain::transaction::Transaction;
+
+    fn chain(height: u64) -> Vec<Block> {
+        (0..=height)
+            .map(|index| {
+                let transactions = match index {
+                    0 => Vec::new(),
+                    _ => vec![Transaction::new_coinbase(Address("miner".to_string()), 1.0)],
+                };
+                Block::new(index, transactions, 0, "0".to_string())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_emission_follows_the_rules() {
+        // A fixed reward of 10, raised to 20 by governance from height 6 on
+        let rules = ConsensusRules {
+            initial_reward: 10.0,
+            ..ConsensusRules::node(DEFAULT_TARGET_INTERVAL_SECS)
+        };
+        let reward_at = |height: u64| if height >= 6 { 20.0 } else { 10.0 };
+        let report = EconomicsReport::new(&chain(7), reward_at, &rules, 3, 2);
+
+        let issued: Vec<(u64, u64, f64)> = report
+            .history
+            .iter()
+            .map(|period| (period.first_height, period.last_height, period.issued))
+            .collect();
+        assert_eq!(issued, vec![(1, 3, 30.0), (4, 6, 40.0), (7, 7, 20.0)]);
+        assert_eq!(report.issued, 90.0);
+        assert_eq!(report.projection[1].first_height, 11);
+        assert_eq!(report.projection[1].total_issued, 210.0);
+        assert_eq!(report.supply_cap, None);
+        assert_eq!(report.percent_of_cap_issued, None);
+
+        // Halving every 4 blocks caps the emission at twice the first era
+        let halving = ConsensusRules {
+            halving_interval: Some(4),
+            ..rules
+        };
+        let report = EconomicsReport::new(&chain(4), |height| halving.reward_at(height), &halving, 4, 1);
+        assert_eq!(report.next_halving, Some(5));
+        assert_eq!(report.projection[0].issued, 20.0);
+        assert!((report.supply_cap.unwrap() - 80.0).abs() < 1e-9);
+        assert!((report.percent_of_cap_issued.unwrap() - 50.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,184 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use std::fmt;
+
+/// Stable machine-readable code of an error
+///
+/// Messages may be reworded between versions, codes are not, so clients
+/// branch on the code and show the message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    /// A parameter or the body is malformed or out of range
+    InvalidRequest,
+
+    /// A private key is malformed or doesn't belong to the address it signs for
+    InvalidKey,
+
+    /// The admin token or the API key is missing or wrong
+    Unauthorized,
+
+    /// The caller or the address may not do this
+    NotPermitted,
+
+    /// The requested resource doesn't exist
+    NotFound,
+
+    /// The resource to create already exists
+    AlreadyExists,
+
+    /// The sender can't pay the amount and the fee
+    InsufficientFunds,
+
+    /// The transaction's nonce is not the sender's next one, or too far ahead of it
+    InvalidNonce,
+
+    /// The fee is below the node's minimum, or not above the fee of the transaction it replaces
+    FeeTooLow,
+
+    /// The transaction's signature doesn't verify
+    InvalidSignature,
+
+    /// The transaction is invalid for another reason
+    InvalidTransaction,
+
+    /// The block is invalid
+    InvalidBlock,
+
+    /// The chain is invalid
+    InvalidChain,
+
+    /// The block the client built on is no longer part of the chain, e.g. after a reorganization,
+    /// or another block took the height being mined
+    StaleTip,
+
+    /// The block or transaction version is not allowed at its height
+    UnsupportedVersion,
+
+    /// The name is malformed or the registration invalid
+    InvalidName,
+
+    /// The name is registered or being registered by someone else
+    NameTaken,
+
+    /// The anchored hash or the anchor is invalid
+    InvalidAnchor,
+
+    /// The sender is frozen
+    AccountFrozen,
+
+    /// The governance proposal or vote is invalid
+    InvalidProposal,
+
+    /// The lock's condition is malformed, or the spend doesn't meet it
+    InvalidScript,
+
+    /// The session key is not authorized, expired or over its limits, or its authorization is malformed
+    InvalidSession,
+
+    /// The account designated no such beneficiary, its owner is still active or the designation is malformed
+    InvalidInheritance,
+
+    /// A custom rule of the application embedding the node rejected the transaction or block
+    Rejected,
+
+    /// The node is under maintenance
+    Paused,
+
+    /// The node is a read-only follower, send writes to the node it follows
+    ReadOnly,
+
+    /// The node is busy, retry after the Retry-After header
+    Busy,
+
+    /// The API key used up its monthly quota, retry after the Retry-After header
+    QuotaExceeded,
+
+    /// The P2P network is not running on the node
+    NetworkDisabled,
+
+    /// The node failed, e.g. its storage
+    Internal,
+}
+
+impl ErrorCode {
+    /// Checks whether the same request may succeed later, unchanged
+    ///
+    /// Errors with a code that is not retryable may still be, see
+    /// `BlockchainError::is_retryable`.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            ErrorCode::StaleTip | ErrorCode::Paused | ErrorCode::Busy | ErrorCode::QuotaExceeded
+        )
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::String(name)) => write!(f, "{}", name),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+/// What an error is about, for the fields the error knows
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ErrorContext {
+    /// Height of the block
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u64>,
+
+    /// ID of the transaction
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction_id: Option<String>,
+
+    /// The address, e.g. the sender or the owner of an account
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+}
+
+impl ErrorContext {
+    /// Creates a context about an address
+    pub fn address(address: impl Into<String>) -> Self {
+        ErrorContext {
+            address: Some(address.into()),
+            ..Default::default()
+        }
+    }
+
+    /// Creates a context about a block
+    pub fn height(height: u64) -> Self {
+        ErrorContext {
+            height: Some(height),
+            ..Default::default()
+        }
+    }
+
+    /// Creates a context about a transaction, at a height if known
+    pub fn transaction(id: impl Into<String>, height: Option<u64>) -> Self {
+        ErrorContext {
+            transaction_id: Some(id.into()),
+            height,
+            ..Default::default()
+        }
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut fields = Vec::new();
+        if let Some(height) = self.height {
+            fields.push(format!("height={}", height));
+        }
+        if let Some(id) = &self.transaction_id {
+            fields.push(format!("transaction={}", id));
+        }
+        if let Some(address) = &self.address {
+            fields.push(format!("address={}", address));
+        }
+        write!(f, "{}", fields.join(" "))
+    }
+}
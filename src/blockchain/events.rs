@@ -0,0 +1,129 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+
+use super::crypto::Address;
+
+/// Number of events a slow subscriber can fall behind before it misses events
+const EVENT_BUS_CAPACITY: usize = 256;
+
+/// A chain reorganization
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ReorgEvent {
+    /// Timestamp when the reorganization happened
+    #[schema(value_type = String, example = "2023-01-01T12:00:00Z")]
+    pub timestamp: DateTime<Utc>,
+
+    /// Hash of the tip before the reorganization
+    pub old_tip: String,
+
+    /// Hash of the tip after the reorganization
+    pub new_tip: String,
+
+    /// Index of the last block both branches have in common
+    pub fork_height: u64,
+
+    /// Number of blocks that were removed from the chain
+    pub depth: u64,
+
+    /// IDs of the transactions that were in the removed blocks but are not in the new branch
+    pub affected_transactions: Vec<String>,
+}
+
+/// An event emitted by the blockchain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChainEvent {
+    /// A block was appended to the chain
+    BlockAdded {
+        /// Height of the block
+        index: u64,
+
+        /// Hash of the block
+        hash: String,
+    },
+
+    /// The chain switched to another branch
+    Reorg(ReorgEvent),
+
+    /// A transaction was admitted to the pending transactions
+    TransactionAdmitted {
+        /// ID of the transaction
+        id: String,
+
+        /// The sender's address
+        sender: Address,
+
+        /// The recipient's address
+        recipient: Address,
+
+        /// The amount sent
+        amount: f64,
+
+        /// The fee paid
+        fee: f64,
+    },
+
+    /// A pending transaction was evicted for waiting longer than the mempool keeps transactions
+    TransactionEvicted {
+        /// ID of the transaction
+        id: String,
+
+        /// The sender's address
+        sender: Address,
+
+        /// When the transaction entered the mempool
+        received_at: DateTime<Utc>,
+    },
+
+    /// The chain was reset to a new genesis block
+    Reset {
+        /// Hash of the new genesis block
+        genesis_hash: String,
+    },
+
+    /// The chain was restored from a snapshot
+    Restored {
+        /// Height of the restored tip
+        index: u64,
+
+        /// Hash of the restored tip
+        hash: String,
+    },
+}
+
+/// Broadcasts chain events to any number of in-process subscribers
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<ChainEvent>,
+}
+
+impl EventBus {
+    /// Creates a new event bus
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        EventBus { sender }
+    }
+
+    /// Publishes an event to all current subscribers
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event to publish
+    pub fn publish(&self, event: ChainEvent) {
+        // Having no subscribers is not an error
+        let _ = self.sender.send(event);
+    }
+
+    /// Subscribes to all events published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<ChainEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
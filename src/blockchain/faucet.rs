@@ -0,0 +1,105 @@
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::account::{AccountError, AccountState};
+use super::crypto::Address;
+
+/// A deposit the faucet made outside of any block
+///
+/// Blocks don't carry these funds, so every replay of the chain has to
+/// credit them itself, after the block that was the tip when they were
+/// made.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FaucetGrant {
+    /// The funded account
+    pub address: Address,
+
+    /// The amount deposited
+    pub amount: f64,
+
+    /// Height of the tip when the deposit was made
+    pub height: u64,
+
+    /// When the deposit was made
+    pub granted_at: DateTime<Utc>,
+}
+
+/// The deposits of the faucet, in the order they were made
+#[derive(Debug, Default)]
+pub struct FaucetGrants {
+    grants: Mutex<Vec<FaucetGrant>>,
+}
+
+impl FaucetGrants {
+    /// Creates an empty list of grants
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a deposit of the faucet
+    ///
+    /// # Arguments
+    ///
+    /// * `grant` - The deposit
+    pub fn record(&self, grant: FaucetGrant) {
+        self.grants.lock().unwrap().push(grant);
+    }
+
+    /// Replaces the grants, e.g. with the ones loaded from storage
+    ///
+    /// # Arguments
+    ///
+    /// * `grants` - The grants, oldest first
+    pub fn replace(&self, grants: Vec<FaucetGrant>) {
+        *self.grants.lock().unwrap() = grants;
+    }
+
+    /// Gets all grants, oldest first
+    pub fn all(&self) -> Vec<FaucetGrant> {
+        self.grants.lock().unwrap().clone()
+    }
+
+    /// Moves the grants made above a height down to it
+    ///
+    /// A reorganization onto a branch with fewer blocks leaves grants above
+    /// its tip, which count after the tip from then on.
+    ///
+    /// # Arguments
+    ///
+    /// * `height` - Height of the tip
+    ///
+    /// # Returns
+    ///
+    /// Whether any grant was moved
+    pub fn lower_to(&self, height: u64) -> bool {
+        let mut moved = false;
+        for grant in self.grants.lock().unwrap().iter_mut().filter(|grant| grant.height > height) {
+            grant.height = height;
+            moved = true;
+        }
+        moved
+    }
+}
+
+/// Credits the grants made while the block at a height was the tip
+///
+/// # Arguments
+///
+/// * `accounts` - The accounts after the block
+/// * `grants` - All grants, oldest first
+/// * `height` - Height of the block
+///
+/// # Returns
+///
+/// Ok(()) if every grant could be deposited
+pub fn credit(accounts: &AccountState, grants: &[FaucetGrant], height: u64) -> Result<(), AccountError> {
+    for grant in grants.iter().filter(|grant| grant.height == height) {
+        let mut account = accounts.get_account(&grant.address);
+        account.deposit(grant.amount)?;
+        accounts.update_account(account);
+    }
+
+    Ok(())
+}
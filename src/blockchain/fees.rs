@@ -0,0 +1,509 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use std::fmt;
+
+use super::account::Account;
+use super::block::Block;
+use super::governance::ChainParameters;
+use super::transaction::Transaction;
+use super::treasury::TREASURY_ADDRESS;
+
+/// Smallest amount the supply report shows
+const REPORT_PRECISION: f64 = 1e-8;
+
+/// Smallest step of the base fee
+const BASE_FEE_PRECISION: f64 = 1e-8;
+
+/// The base fee changes by at most an eighth from one block to the next
+const BASE_FEE_CHANGE_DENOMINATOR: f64 = 8.0;
+
+/// Recent blocks the fee estimate looks at for tips
+pub const ESTIMATE_BLOCKS: usize = 20;
+
+/// Errors that can occur with fee policies
+#[derive(Debug, Error)]
+pub enum FeeError {
+    #[error("Invalid fee policy: {0}")]
+    InvalidPolicy(String),
+}
+
+/// Where the fees and the reward of a block go
+///
+/// The policy is a consensus rule: the coinbase of every block must pay the
+/// miner and the treasury what it says, so every node of a network must use
+/// the same one.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "destination", rename_all = "snake_case")]
+pub enum FeePolicy {
+    /// The miner collects the fees on top of the reward
+    Miner,
+
+    /// The base fees are destroyed, reducing the supply, the tips go to the miner
+    #[default]
+    Burn,
+
+    /// Shares of the fees and the reward go to the treasury and the rest to the miner
+    Treasury {
+        /// Share of the fees the treasury gets, from 0 to 1
+        share: f64,
+
+        /// Share of the reward the treasury gets, from 0 to 1
+        reward_share: f64,
+    },
+}
+
+impl FeePolicy {
+    /// Builds a policy from the node configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The policy: miner, burn or treasury
+    /// * `share` - Share of the fees the treasury gets
+    /// * `reward_share` - Share of the reward the treasury gets
+    ///
+    /// # Returns
+    ///
+    /// The policy, or an error if it is unknown or its shares are out of range
+    pub fn from_config(name: &str, share: f64, reward_share: f64) -> Result<Self, FeeError> {
+        match name {
+            "miner" => Ok(FeePolicy::Miner),
+            "burn" => Ok(FeePolicy::Burn),
+            "treasury" => {
+                let in_range = |share: f64| (0.0..=1.0).contains(&share);
+                if !in_range(share) || !in_range(reward_share) || share + reward_share == 0.0 {
+                    return Err(FeeError::InvalidPolicy(format!(
+                        "The treasury shares must be from 0 to 1 and not both 0, got {} and {}",
+                        share, reward_share
+                    )));
+                }
+
+                Ok(FeePolicy::Treasury { share, reward_share })
+            }
+            _ => Err(FeeError::InvalidPolicy(format!(
+                "{} is not miner, burn or treasury",
+                name
+            ))),
+        }
+    }
+
+    /// Divides the reward and the fees of a block
+    ///
+    /// # Arguments
+    ///
+    /// * `reward` - The mining reward at the block's height
+    /// * `fees` - The fees of the block, see `block_fees`
+    pub fn split(&self, reward: f64, fees: f64) -> FeeSplit {
+        match self {
+            FeePolicy::Miner => FeeSplit {
+                miner: reward + fees,
+                ..FeeSplit::default()
+            },
+            FeePolicy::Burn => FeeSplit {
+                miner: reward,
+                burned: fees,
+                ..FeeSplit::default()
+            },
+            FeePolicy::Treasury { share, reward_share } => {
+                let treasury = fees * share + reward * reward_share;
+                FeeSplit {
+                    miner: reward + fees - treasury,
+                    treasury,
+                    burned: 0.0,
+                }
+            }
+        }
+    }
+
+    /// Gets what the coinbase of a block pays the miner
+    ///
+    /// The policy divides the reward and the base fees, the tips all go to the miner.
+    ///
+    /// # Arguments
+    ///
+    /// * `reward` - The mining reward at the block's height
+    /// * `fees` - The fees of the block, see `BlockFees::new`
+    pub fn miner_amount(&self, reward: f64, fees: &BlockFees) -> f64 {
+        self.split(reward, fees.base).miner + fees.tips
+    }
+
+    /// Gets what the coinbase of a block pays the treasury
+    ///
+    /// # Arguments
+    ///
+    /// * `reward` - The mining reward at the block's height
+    /// * `fees` - The fees of the block, see `block_fees`
+    ///
+    /// # Returns
+    ///
+    /// The payout, None if the treasury gets nothing
+    pub fn payout(&self, reward: f64, fees: f64) -> Option<TreasuryPayout> {
+        let amount = self.split(reward, fees).treasury;
+
+        (amount > 0.0).then(|| TreasuryPayout {
+            address: TREASURY_ADDRESS.to_string(),
+            amount,
+        })
+    }
+}
+
+impl fmt::Display for FeePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeePolicy::Miner => write!(f, "fees paid to the miner"),
+            FeePolicy::Burn => write!(f, "base fees burned, tips paid to the miner"),
+            FeePolicy::Treasury { share, reward_share } => write!(
+                f,
+                "{}% of base fees and {}% of rewards paid to the treasury, the rest and the tips to the miner",
+                share * 100.0,
+                reward_share * 100.0
+            ),
+        }
+    }
+}
+
+/// How the reward and the fees of a block are divided
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FeeSplit {
+    /// What the coinbase pays the miner
+    pub miner: f64,
+
+    /// What the coinbase pays the treasury
+    pub treasury: f64,
+
+    /// Fees nobody receives
+    pub burned: f64,
+}
+
+/// The share of the fees and the reward a coinbase pays to the treasury
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TreasuryPayout {
+    /// The treasury account
+    pub address: String,
+
+    /// The amount paid
+    pub amount: f64,
+}
+
+/// Sums the fees of the transactions of a block, in block order
+pub fn block_fees(block: &Block) -> f64 {
+    block
+        .transactions
+        .iter()
+        .filter(|transaction| !transaction.is_coinbase())
+        .map(|transaction| transaction.fee)
+        .sum()
+}
+
+/// The fees of a block, divided at its base fee
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BlockFees {
+    /// The fees up to the base fee, which go where the fee policy says
+    pub base: f64,
+
+    /// The fees above the base fee, which go to the miner
+    pub tips: f64,
+}
+
+impl BlockFees {
+    /// Divides the fees of the transactions of a block
+    ///
+    /// # Arguments
+    ///
+    /// * `transactions` - The transactions, the coinbase is left out
+    /// * `base_fee` - The base fee of the block
+    pub fn new<'a>(transactions: impl IntoIterator<Item = &'a Transaction>, base_fee: f64) -> Self {
+        transactions
+            .into_iter()
+            .filter(|transaction| !transaction.is_coinbase())
+            .fold(BlockFees::default(), |fees, transaction| {
+                let base = transaction.fee.min(base_fee);
+                BlockFees {
+                    base: fees.base + base,
+                    tips: fees.tips + transaction.fee - base,
+                }
+            })
+    }
+}
+
+/// Counts the transactions of a block besides the coinbase
+fn block_transactions(block: &Block) -> usize {
+    block.transactions.iter().filter(|transaction| !transaction.is_coinbase()).count()
+}
+
+/// Gets the base fee of a block from its parent
+///
+/// Like EIP-1559, a parent holding more transactions than half the most a
+/// block may hold raises the base fee, one holding fewer lowers it, by at
+/// most an eighth. The base fee never drops below the minimum fee.
+///
+/// # Arguments
+///
+/// * `base_fee` - The base fee of the parent
+/// * `parent` - The parent
+/// * `max_block_transactions` - Most transactions the parent could hold
+/// * `minimum_fee` - The minimum fee at the height of the block
+pub fn next_base_fee(base_fee: f64, parent: &Block, max_block_transactions: usize, minimum_fee: f64) -> f64 {
+    let target = (max_block_transactions / 2).max(1) as f64;
+    let used = block_transactions(parent) as f64;
+    let next = base_fee * (1.0 + (used - target) / target / BASE_FEE_CHANGE_DENOMINATOR);
+
+    round_fee(next).max(minimum_fee)
+}
+
+/// Rounds an amount to the smallest step of the base fee
+fn round_fee(amount: f64) -> f64 {
+    (amount / BASE_FEE_PRECISION).round() * BASE_FEE_PRECISION
+}
+
+/// Gets the base fee of the block after the last of some blocks
+///
+/// The first block after the genesis block starts at the minimum fee.
+///
+/// # Arguments
+///
+/// * `blocks` - The blocks of the chain, genesis first
+/// * `parameters_at` - The parameters governance has in force at a height
+pub fn base_fee_after(blocks: &[Block], parameters_at: impl Fn(u64) -> ChainParameters) -> f64 {
+    blocks.iter().skip(1).fold(parameters_at(1).minimum_fee, |base_fee, block| {
+        next_base_fee(
+            base_fee,
+            block,
+            parameters_at(block.index).max_block_transactions,
+            parameters_at(block.index + 1).minimum_fee,
+        )
+    })
+}
+
+/// What a transaction should pay to make the next block
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FeeEstimate {
+    /// Height of the next block
+    pub height: u64,
+
+    /// Lowest fee a transaction of the next block may pay, see `next_base_fee`
+    pub base_fee: f64,
+
+    /// Lowest the base fee can go
+    pub minimum_fee: f64,
+
+    /// Transactions a block holds without moving the base fee
+    pub target_transactions: usize,
+
+    /// Most transactions besides the coinbase a block may hold
+    pub max_block_transactions: usize,
+
+    /// Transactions waiting for a block
+    pub pending_transactions: usize,
+
+    /// Median tip of the transactions of the last blocks, 0 if they had none
+    pub suggested_tip: f64,
+
+    /// The base fee plus the suggested tip
+    pub suggested_fee: f64,
+}
+
+impl FeeEstimate {
+    /// Estimates the fee for the next block
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks of the chain, genesis first
+    /// * `parameters_at` - The parameters governance has in force at a height
+    /// * `pending_transactions` - Number of transactions waiting for a block
+    pub fn new(
+        blocks: &[Block],
+        parameters_at: impl Fn(u64) -> ChainParameters,
+        pending_transactions: usize,
+    ) -> Self {
+        let height = blocks.last().map(|block| block.index + 1).unwrap_or(0);
+        let parameters = parameters_at(height);
+
+        // The base fee of each recent block is needed to tell its tips apart
+        let mut base_fee = parameters_at(1).minimum_fee;
+        let mut tips = Vec::new();
+        let recent = blocks.len().saturating_sub(ESTIMATE_BLOCKS).max(1);
+        for (position, block) in blocks.iter().enumerate().skip(1) {
+            if position >= recent {
+                tips.extend(
+                    block
+                        .transactions
+                        .iter()
+                        .filter(|transaction| !transaction.is_coinbase() && !transaction.is_disbursement())
+                        .map(|transaction| transaction.fee - base_fee),
+                );
+            }
+            base_fee = next_base_fee(
+                base_fee,
+                block,
+                parameters_at(block.index).max_block_transactions,
+                parameters_at(block.index + 1).minimum_fee,
+            );
+        }
+        tips.sort_by(f64::total_cmp);
+        let suggested_tip = round_fee(tips.get(tips.len() / 2).copied().unwrap_or(0.0).max(0.0));
+
+        FeeEstimate {
+            height,
+            base_fee,
+            minimum_fee: parameters.minimum_fee,
+            target_transactions: (parameters.max_block_transactions / 2).max(1),
+            max_block_transactions: parameters.max_block_transactions,
+            pending_transactions,
+            suggested_tip,
+            suggested_fee: round_fee(base_fee + suggested_tip),
+        }
+    }
+}
+
+/// The coins on the chain and where the fees went
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SupplyReport {
+    /// Height of the last block
+    pub height: u64,
+
+    /// The policy new blocks follow
+    pub fee_policy: FeePolicy,
+
+    /// Coins created by block rewards
+    pub minted: f64,
+
+    /// Fees paid by all transactions
+    pub fees_paid: f64,
+
+    /// What coinbase transactions paid miners, rewards and fees
+    pub paid_to_miners: f64,
+
+    /// What coinbase transactions paid the treasury, rewards and fees
+    pub paid_to_treasury: f64,
+
+    /// Fees nobody received
+    pub fees_burned: f64,
+
+    /// Coins the chain accounts for, minted less burned
+    pub chain_supply: f64,
+
+    /// Sum of all account balances
+    pub total_balances: f64,
+
+    /// Balances the chain doesn't account for, such as test funds
+    pub unbacked: f64,
+}
+
+impl SupplyReport {
+    /// Audits the supply of a chain
+    ///
+    /// Payouts are taken from what each coinbase actually paid, so the
+    /// report stays right for blocks mined under an earlier policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks of the chain, genesis first
+    /// * `accounts` - The accounts after the last block
+    /// * `fee_policy` - The policy new blocks follow
+    /// * `reward_at` - The reward of a block at a height
+    ///
+    /// # Returns
+    ///
+    /// The report
+    pub fn new(blocks: &[Block], accounts: &[Account], fee_policy: FeePolicy, reward_at: impl Fn(u64) -> f64) -> Self {
+        let mut report = SupplyReport {
+            height: blocks.last().map(|block| block.index).unwrap_or(0),
+            fee_policy,
+            minted: 0.0,
+            fees_paid: 0.0,
+            paid_to_miners: 0.0,
+            paid_to_treasury: 0.0,
+            fees_burned: 0.0,
+            chain_supply: 0.0,
+            total_balances: accounts.iter().map(|account| account.balance).sum(),
+            unbacked: 0.0,
+        };
+
+        for block in blocks {
+            let fees = block_fees(block);
+            report.fees_paid += fees;
+
+            for coinbase in block.transactions.iter().filter(|transaction| transaction.is_coinbase()) {
+                report.minted += reward_at(block.index);
+                report.paid_to_miners += coinbase.amount;
+                report.paid_to_treasury += coinbase.treasury.as_ref().map(|payout| payout.amount).unwrap_or(0.0);
+            }
+        }
+
+        // Sums of floats drift, the report is rounded to the smallest amount it shows
+        let round = |amount: f64| (amount / REPORT_PRECISION).round() * REPORT_PRECISION;
+        report.paid_to_miners = round(report.paid_to_miners);
+        report.paid_to_treasury = round(report.paid_to_treasury);
+        report.fees_burned =
+            round(report.minted + report.fees_paid - report.paid_to_miners - report.paid_to_treasury);
+        report.chain_supply = round(report.minted - report.fees_burned);
+        report.unbacked = round(report.total_balances - report.chain_supply);
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::account::AccountState;
+    use crate::blockchain::crypto::Address;
+    use crate::blockchain::transaction::Transaction;
+    use crate::blockchain::validation;
+
+    #[test]
+    fn test_fees_follow_the_policy() {
+        assert_eq!(FeePolicy::from_config("burn", 1.0, 0.0).unwrap(), FeePolicy::Burn);
+        assert!(FeePolicy::from_config("treasury", 1.5, 0.0).is_err());
+        assert!(FeePolicy::from_config("treasury", 0.0, 0.0).is_err());
+        assert!(FeePolicy::from_config("charity", 1.0, 0.0).is_err());
+
+        let policy = FeePolicy::from_config("treasury", 0.25, 0.1).unwrap();
+        assert_eq!(policy.split(10.0, 2.0), FeeSplit { miner: 10.5, treasury: 1.5, burned: 0.0 });
+        assert_eq!(FeePolicy::from_config("treasury", 1.0, 0.0).unwrap().payout(50.0, 0.0), None);
+        assert_eq!(FeePolicy::Miner.split(10.0, 2.0).miner, 12.0);
+        assert_eq!(FeePolicy::Burn.split(10.0, 2.0), FeeSplit { miner: 10.0, treasury: 0.0, burned: 2.0 });
+        assert_eq!(FeePolicy::Burn.payout(10.0, 2.0), None);
+
+        // A block paying 1.0 in fees under the treasury policy
+        let accounts = AccountState::new();
+        accounts.process_mining_reward(&Address("alice".to_string()), 10.0).unwrap();
+        let transfer = Transaction::new(Address("alice".to_string()), Address("bob".to_string()), 2.0, 1.0, 0);
+        let fees = 1.0;
+        let coinbase = Transaction::new_coinbase(Address("miner".to_string()), policy.split(50.0, fees).miner)
+            .with_treasury(policy.payout(50.0, fees).unwrap());
+        let block = Block::new(1, vec![transfer, coinbase], 0, "0".to_string());
+        assert_eq!(block_fees(&block), fees);
+        validation::apply_block(&accounts, &block).unwrap();
+        assert_eq!(accounts.get_account(&Address(TREASURY_ADDRESS.to_string())).balance, 5.25);
+
+        let report = SupplyReport::new(&[block], &accounts.get_all_accounts(), policy, |_| 50.0);
+        assert_eq!(report.paid_to_miners, 45.75);
+        assert_eq!(report.paid_to_treasury, 5.25);
+        assert_eq!(report.fees_burned, 0.0);
+        assert_eq!(report.chain_supply, 50.0);
+        assert_eq!(report.unbacked, 10.0);
+    }
+
+    #[test]
+    fn test_base_fee_follows_block_fullness() {
+        let transfer = |fee: f64| Transaction::new(Address("alice".to_string()), Address("bob".to_string()), 1.0, fee, 0);
+        let block = |transactions: usize| {
+            let mut all: Vec<Transaction> = (0..transactions).map(|_| transfer(0.5)).collect();
+            all.push(Transaction::new_coinbase(Address("miner".to_string()), 50.0));
+            Block::new(1, all, 0, "0".to_string())
+        };
+
+        // Half full holds the base fee, full raises it an eighth, empty lowers it an eighth down to the minimum
+        assert_eq!(next_base_fee(0.16, &block(5), 10, 0.1), 0.16);
+        assert_eq!(next_base_fee(0.16, &block(10), 10, 0.1), 0.18);
+        assert_eq!(next_base_fee(0.16, &block(0), 10, 0.1), 0.14);
+        assert_eq!(next_base_fee(0.1, &block(0), 10, 0.1), 0.1);
+
+        // Fees up to the base fee go by the policy, the rest is the miner's tip
+        let fees = BlockFees::new(&[transfer(0.75), transfer(0.25)], 0.5);
+        assert_eq!(fees, BlockFees { base: 0.75, tips: 0.25 });
+        assert_eq!(FeePolicy::Burn.miner_amount(10.0, &BlockFees { base: 0.4, tips: 0.3 }), 10.3);
+    }
+}
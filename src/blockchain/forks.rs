@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use super::block::Block;
+use super::events::ReorgEvent;
+
+/// Most blocks of competing branches kept at once
+pub const MAX_FORK_BLOCKS: usize = 1024;
+
+/// How far below the tip a competing branch may start and still be kept
+pub const MAX_FORK_DEPTH: u64 = 100;
+
+/// What became of a block received from elsewhere, see `Blockchain::receive_block`
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockOutcome {
+    /// It followed the tip and was appended
+    Extended,
+
+    /// The node already has it
+    Known,
+
+    /// It belongs to a competing branch with less work than the chain, kept in case the branch grows
+    Stored,
+
+    /// Its branch had more work than the chain, which switched to it
+    Reorganized(ReorgEvent),
+
+    /// Its parent is unknown, the node is missing blocks
+    Orphan,
+}
+
+/// Blocks of branches competing with the chain
+///
+/// Blocks are kept by hash until their branch either wins the fork choice
+/// and becomes the chain, or falls more than `MAX_FORK_DEPTH` blocks behind
+/// the tip. Blocks the chain loses in a reorganization are kept the same way,
+/// so the node can switch back should their branch grow again.
+#[derive(Debug, Clone, Default)]
+pub struct ForkStore {
+    blocks: Arc<Mutex<HashMap<String, Block>>>,
+}
+
+impl ForkStore {
+    /// Creates an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps a block of a competing branch
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block
+    ///
+    /// # Returns
+    ///
+    /// Whether the block was kept, false if it is known or the store is full
+    pub fn insert(&self, block: Block) -> bool {
+        let mut blocks = self.blocks.lock().unwrap();
+        if blocks.len() >= MAX_FORK_BLOCKS || blocks.contains_key(&block.hash) {
+            return false;
+        }
+        blocks.insert(block.hash.clone(), block);
+        true
+    }
+
+    /// Gets whether a block is kept
+    pub fn contains(&self, hash: &str) -> bool {
+        self.blocks.lock().unwrap().contains_key(hash)
+    }
+
+    /// Gets a kept block by its hash
+    pub fn get(&self, hash: &str) -> Option<Block> {
+        self.blocks.lock().unwrap().get(hash).cloned()
+    }
+
+    /// Gets the branch of kept blocks ending at a block
+    ///
+    /// # Arguments
+    ///
+    /// * `tip` - Hash of the last block of the branch
+    /// * `on_chain` - Whether a hash is a block of the chain
+    ///
+    /// # Returns
+    ///
+    /// The blocks after the last block the branch shares with the chain,
+    /// oldest first, or None if the branch doesn't lead back to the chain
+    pub fn branch(&self, tip: &str, on_chain: impl Fn(&str) -> bool) -> Option<Vec<Block>> {
+        let blocks = self.blocks.lock().unwrap();
+        let mut branch = Vec::new();
+        let mut hash = tip;
+
+        while let Some(block) = blocks.get(hash) {
+            branch.push(block.clone());
+            if on_chain(&block.previous_hash) {
+                branch.reverse();
+                return Some(branch);
+            }
+            hash = &block.previous_hash;
+        }
+
+        None
+    }
+
+    /// Forgets blocks, e.g. once they are on the chain
+    pub fn remove(&self, hashes: &[String]) {
+        let mut blocks = self.blocks.lock().unwrap();
+        for hash in hashes {
+            blocks.remove(hash);
+        }
+    }
+
+    /// Forgets the blocks that fell too far behind the tip
+    ///
+    /// # Arguments
+    ///
+    /// * `height` - Height of the tip
+    pub fn prune(&self, height: u64) {
+        self.blocks
+            .lock()
+            .unwrap()
+            .retain(|_, block| block.index + MAX_FORK_DEPTH > height);
+    }
+
+    /// Gets the number of kept blocks
+    pub fn len(&self) -> usize {
+        self.blocks.lock().unwrap().len()
+    }
+
+    /// Gets whether no blocks are kept
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(index: u64, previous_hash: &str, hash: &str) -> Block {
+        Block {
+            hash: hash.to_string(),
+            ..Block::new(index, Vec::new(), 0, previous_hash.to_string())
+        }
+    }
+
+    #[test]
+    fn test_branch_leads_back_to_the_chain() {
+        let forks = ForkStore::new();
+        assert!(forks.insert(block(1, "genesis", "a1")));
+        assert!(forks.insert(block(2, "a1", "a2")));
+        assert!(!forks.insert(block(2, "a1", "a2")));
+        forks.insert(block(5, "unknown", "b5"));
+
+        let on_chain = |hash: &str| hash == "genesis";
+        let branch = forks.branch("a2", on_chain).unwrap();
+        assert_eq!(branch.iter().map(|block| block.hash.as_str()).collect::<Vec<_>>(), vec!["a1", "a2"]);
+        assert!(forks.branch("b5", on_chain).is_none());
+
+        forks.remove(&["a2".to_string()]);
+        forks.prune(MAX_FORK_DEPTH + 1);
+        assert!(!forks.contains("a1") && !forks.contains("a2"));
+        assert!(forks.contains("b5"));
+    }
+}
@@ -0,0 +1,195 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::block::Block;
+use super::canonical;
+
+/// Name of the network nodes join unless configured otherwise
+pub const DEFAULT_NETWORK: &str = "devnet";
+
+/// Time of the genesis block unless configured otherwise
+pub const DEFAULT_GENESIS_TIME: &str = "2024-01-01T00:00:00Z";
+
+/// Tag of the hash the network magic is taken from
+const NETWORK_MAGIC_TAG: &[u8] = b"my_blockchain/network-magic/v1\0";
+
+/// Errors that can occur while parsing network magic bytes
+#[derive(Debug, Error)]
+pub enum GenesisError {
+    #[error("Invalid network magic: {0}")]
+    InvalidMagic(String),
+
+    #[error("Invalid genesis file: {0}")]
+    InvalidFile(String),
+}
+
+/// What defines a network, the same on every node that joins it
+///
+/// The genesis block is built from it alone, so every node of a network
+/// builds the very same genesis block, down to its hash.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GenesisConfig {
+    /// Name of the network, e.g. devnet or classroom-2024
+    pub network: String,
+
+    /// Time of the genesis block
+    pub timestamp: DateTime<Utc>,
+}
+
+impl Default for GenesisConfig {
+    fn default() -> Self {
+        GenesisConfig {
+            network: DEFAULT_NETWORK.to_string(),
+            timestamp: DateTime::parse_from_rfc3339(DEFAULT_GENESIS_TIME)
+                .expect("the default genesis time is a valid RFC 3339 timestamp")
+                .with_timezone(&Utc),
+        }
+    }
+}
+
+impl GenesisConfig {
+    /// Reads a network from a JSON file
+    ///
+    /// The file holds the `network` name and the genesis `timestamp` in
+    /// RFC 3339, each defaulting to the default network's when missing.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file
+    ///
+    /// # Returns
+    ///
+    /// Result with the network
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, GenesisError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| GenesisError::InvalidFile(format!("{}: {}", path.display(), err)))?;
+        serde_json::from_str(&content).map_err(|err| GenesisError::InvalidFile(format!("{}: {}", path.display(), err)))
+    }
+
+    /// Builds the genesis block of the network
+    pub fn block(&self) -> Block {
+        let mut genesis = Block::new(0, Vec::new(), 1, "0".to_string());
+        genesis.timestamp = self.timestamp;
+        genesis.hash = genesis.calculate_hash();
+        genesis
+    }
+
+    /// Computes the magic bytes that identify the network
+    ///
+    /// They are the first bytes of a hash of the network name and the
+    /// genesis block, so two networks differ in their magic even when their
+    /// genesis blocks are alike.
+    pub fn magic(&self) -> NetworkMagic {
+        let content = serde_json::json!({
+            "network": self.network,
+            "genesis_hash": self.block().hash,
+        });
+        let mut hasher = Sha256::new();
+        hasher.update(NETWORK_MAGIC_TAG);
+        hasher.update(canonical::to_vec(&content));
+
+        let mut magic = [0u8; 4];
+        magic.copy_from_slice(&hasher.finalize()[..4]);
+        NetworkMagic(magic)
+    }
+
+    /// Gets what the node tells others about its network
+    pub fn info(&self) -> NetworkInfo {
+        NetworkInfo {
+            network: self.network.clone(),
+            genesis_hash: self.block().hash,
+            magic: self.magic().to_string(),
+        }
+    }
+}
+
+/// The bytes that identify a network
+///
+/// Stored with the chain and compared when peers meet, so data of one
+/// network never ends up in a node of another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NetworkMagic(pub [u8; 4]);
+
+impl fmt::Display for NetworkMagic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+impl FromStr for NetworkMagic {
+    type Err = GenesisError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = hex::decode(s).map_err(|err| GenesisError::InvalidMagic(err.to_string()))?;
+        let magic = bytes
+            .try_into()
+            .map_err(|_| GenesisError::InvalidMagic(format!("{} is not 4 bytes", s)))?;
+        Ok(NetworkMagic(magic))
+    }
+}
+
+/// The network a node belongs to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct NetworkInfo {
+    /// Name of the network
+    pub network: String,
+
+    /// Hash of the genesis block the network's config builds
+    pub genesis_hash: String,
+
+    /// Hex encoded network magic
+    pub magic: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_genesis_and_magic_are_deterministic() {
+        let config = GenesisConfig::default();
+        assert_eq!(config.block().hash, GenesisConfig::default().block().hash);
+        assert_eq!(config.magic(), GenesisConfig::default().magic());
+        assert_eq!(config.magic().to_string().parse::<NetworkMagic>().unwrap(), config.magic());
+
+        // Another name or another genesis time is another network
+        let renamed = GenesisConfig {
+            network: "classroom".to_string(),
+            ..config.clone()
+        };
+        assert_eq!(renamed.block().hash, config.block().hash);
+        assert_ne!(renamed.magic(), config.magic());
+
+        let later = GenesisConfig {
+            timestamp: config.timestamp + chrono::Duration::seconds(1),
+            ..config.clone()
+        };
+        assert_ne!(later.block().hash, config.block().hash);
+        assert_ne!(later.magic(), config.magic());
+    }
+
+    #[test]
+    fn test_genesis_is_read_from_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("genesis.json");
+        std::fs::write(&path, r#"{"network": "classroom", "timestamp": "2024-09-01T08:00:00Z"}"#).unwrap();
+        let config = GenesisConfig::from_file(&path).unwrap();
+        assert_eq!(config.network, "classroom");
+        assert_eq!(config.timestamp.to_rfc3339(), "2024-09-01T08:00:00+00:00");
+
+        // Missing fields are the default network's, malformed files are refused
+        std::fs::write(&path, r#"{"network": "classroom"}"#).unwrap();
+        assert_eq!(GenesisConfig::from_file(&path).unwrap().timestamp, GenesisConfig::default().timestamp);
+        std::fs::write(&path, "not json").unwrap();
+        assert!(matches!(GenesisConfig::from_file(&path), Err(GenesisError::InvalidFile(_))));
+    }
+}
@@ -0,0 +1,62 @@
+use std::fs;
+use std::path::PathBuf;
+
+use super::block::Block;
+use super::testnet::TestNet;
+
+/// Environment variable that rewrites the fixtures instead of checking them
+const UPDATE_VARIABLE: &str = "UPDATE_GOLDEN";
+
+/// Gets the path of a fixture
+fn path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(name)
+}
+
+/// Checks that text is exactly the same as a fixture
+///
+/// With `UPDATE_GOLDEN=1` the fixture is rewritten instead, which is only
+/// right when the format changed on purpose and old records still decode.
+///
+/// # Arguments
+///
+/// * `name` - File name of the fixture
+/// * `actual` - The text the code produces now
+pub fn assert_text(name: &str, actual: &str) {
+    if std::env::var_os(UPDATE_VARIABLE).is_some() {
+        fs::write(path(name), format!("{}\n", actual)).unwrap();
+    }
+
+    assert_eq!(actual, read_text(name), "{} changed, existing records may no longer decode", name);
+}
+
+/// Checks that bytes are exactly the same as a fixture stored as hex
+///
+/// # Arguments
+///
+/// * `name` - File name of the fixture
+/// * `actual` - The bytes the code produces now
+pub fn assert_bytes(name: &str, actual: &[u8]) {
+    assert_text(name, &hex::encode(actual));
+}
+
+/// Reads a text fixture
+pub fn read_text(name: &str) -> String {
+    let text = fs::read_to_string(path(name)).unwrap_or_else(|err| panic!("{} can not be read: {}", name, err));
+    text.trim_end().to_string()
+}
+
+/// Reads a fixture stored as hex
+pub fn read_bytes(name: &str) -> Vec<u8> {
+    hex::decode(read_text(name)).unwrap()
+}
+
+/// Gets the block the fixtures were made from, a mined block with a signed transfer
+pub fn sample_block() -> Block {
+    let testnet = TestNet::generate(1, 2, 3, 1).unwrap();
+
+    testnet
+        .blocks
+        .into_iter()
+        .find(|block| block.transactions.len() > 1)
+        .expect("the sample network holds a transfer")
+}
@@ -0,0 +1,785 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use super::account::AccountState;
+use super::block::Block;
+use super::chain::MINING_REWARD;
+use super::crypto::Address;
+use super::faucet::FaucetGrant;
+use super::state::ChainState;
+use super::transaction::Transaction;
+use super::treasury::{Disbursement, DisbursementStatus, DueInstallment, TREASURY_ADDRESS};
+use super::validation::{ValidationError, MAX_BLOCK_TRANSACTIONS, MINIMUM_FEE};
+
+/// Address proposals and votes are paid to, which has no key so the amount is burned
+pub const GOVERNANCE_ADDRESS: &str = "governance";
+
+/// Amount a proposal or vote pays to the governance address
+pub const GOVERNANCE_AMOUNT: f64 = 0.01;
+
+/// Fewest blocks between a proposal and its deadline
+pub const MIN_VOTING_BLOCKS: u64 = 10;
+
+/// Most blocks between a proposal and its deadline
+pub const MAX_VOTING_BLOCKS: u64 = 10_000;
+
+/// Errors that can occur with governance
+#[derive(Debug, Error)]
+pub enum GovernanceError {
+    #[error("Invalid proposal: {0}")]
+    InvalidProposal(String),
+
+    #[error("Invalid vote: {0}")]
+    InvalidVote(String),
+
+    #[error("Proposal {0} not found")]
+    ProposalNotFound(String),
+
+    #[error("Invalid disbursement: {0}")]
+    InvalidDisbursement(String),
+}
+
+/// A chain parameter stakeholders can vote on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Parameter {
+    /// Lowest fee a transaction may pay
+    MinimumFee,
+
+    /// Coins paid to the miner of a block
+    MiningReward,
+
+    /// Most transactions besides the coinbase a block may hold
+    MaxBlockTransactions,
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Parameter::MinimumFee => write!(f, "minimum_fee"),
+            Parameter::MiningReward => write!(f, "mining_reward"),
+            Parameter::MaxBlockTransactions => write!(f, "max_block_transactions"),
+        }
+    }
+}
+
+/// The parameters in force at a height
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ChainParameters {
+    /// Lowest fee a transaction may pay
+    pub minimum_fee: f64,
+
+    /// Coins paid to the miner of a block
+    pub mining_reward: f64,
+
+    /// Most transactions besides the coinbase a block may hold
+    pub max_block_transactions: usize,
+}
+
+impl ChainParameters {
+    /// Gets the parameters a chain starts with
+    pub fn node() -> Self {
+        ChainParameters {
+            minimum_fee: MINIMUM_FEE,
+            mining_reward: MINING_REWARD,
+            max_block_transactions: MAX_BLOCK_TRANSACTIONS,
+        }
+    }
+
+    /// Sets a parameter to a value checked with `validate_value`
+    fn set(&mut self, parameter: Parameter, value: f64) {
+        match parameter {
+            Parameter::MinimumFee => self.minimum_fee = value,
+            Parameter::MiningReward => self.mining_reward = value,
+            Parameter::MaxBlockTransactions => self.max_block_transactions = value as usize,
+        }
+    }
+}
+
+/// Checks that a parameter may take a value
+///
+/// # Arguments
+///
+/// * `parameter` - The parameter
+/// * `value` - The proposed value
+///
+/// # Returns
+///
+/// Ok(()) if the chain keeps working with the value
+pub fn validate_value(parameter: Parameter, value: f64) -> Result<(), GovernanceError> {
+    let valid = value.is_finite()
+        && match parameter {
+            Parameter::MinimumFee => value >= 0.0,
+            Parameter::MiningReward => value > 0.0,
+            Parameter::MaxBlockTransactions => value >= 1.0 && value.fract() == 0.0 && value <= 100_000.0,
+        };
+
+    if !valid {
+        return Err(GovernanceError::InvalidProposal(format!(
+            "{} is not a valid {}",
+            value, parameter
+        )));
+    }
+
+    Ok(())
+}
+
+/// What a governance transaction does
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GovernanceAction {
+    /// Proposes to change a parameter, voted on until the deadline
+    Propose {
+        parameter: Parameter,
+        value: f64,
+        deadline: u64,
+    },
+
+    /// Votes on a proposal, by the ID of the transaction that proposed it
+    Vote { proposal: String, approve: bool },
+
+    /// Proposes to pay a disbursement from the treasury, voted on until the deadline
+    ProposeDisbursement {
+        disbursement: Disbursement,
+        deadline: u64,
+    },
+
+    /// Pays an installment of an approved disbursement, added by the miner when it is due
+    Disburse { proposal: String, installment: u32 },
+}
+
+/// What a proposal changes if it passes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ProposedChange {
+    /// Sets a parameter from the block after the deadline on
+    Parameter { parameter: Parameter, value: f64 },
+
+    /// Pays a disbursement from the treasury, the first installment right after the deadline
+    Disbursement { disbursement: Disbursement },
+}
+
+/// Where a proposal stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ProposalStatus {
+    /// Votes are still counted
+    Open,
+
+    /// Approved, the change takes effect after the deadline
+    Passed,
+
+    /// Not approved by the deadline
+    Rejected,
+}
+
+/// A proposal and its votes
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Proposal {
+    /// ID of the transaction that made the proposal
+    pub id: String,
+
+    /// Address that made the proposal
+    #[schema(value_type = String)]
+    pub proposer: Address,
+
+    /// What the proposal changes
+    pub change: ProposedChange,
+
+    /// Height of the block that includes the proposal
+    pub proposed_at: u64,
+
+    /// Height of the last block votes are counted in, and where they are tallied
+    pub deadline: u64,
+
+    /// The latest vote of each address, true to approve
+    pub votes: BTreeMap<String, bool>,
+
+    /// Where the proposal stands
+    pub status: ProposalStatus,
+
+    /// Balances of the approving voters at the deadline
+    pub weight_for: f64,
+
+    /// Balances of the rejecting voters at the deadline
+    pub weight_against: f64,
+}
+
+/// A parameter change that passed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ParameterChange {
+    /// ID of the proposal that passed
+    pub proposal: String,
+
+    /// The changed parameter
+    pub parameter: Parameter,
+
+    /// The new value
+    pub value: f64,
+
+    /// First height the value is in force at
+    pub height: u64,
+}
+
+/// Checks that a governance transaction is well formed
+///
+/// Whether the proposal it votes on is open depends on the chain and is
+/// checked by `Governance::check_admission`.
+///
+/// # Arguments
+///
+/// * `transaction` - The transaction to check
+///
+/// # Returns
+///
+/// Ok(()) if the transaction carries no action, a valid one paid to the governance
+/// address or an unsigned disbursement without a fee paid by the treasury
+pub fn check_action(transaction: &Transaction) -> Result<(), GovernanceError> {
+    let action = match &transaction.governance {
+        Some(action) => action,
+        None => return Ok(()),
+    };
+
+    let invalid = |message: String| match action {
+        GovernanceAction::Propose { .. } | GovernanceAction::ProposeDisbursement { .. } => {
+            GovernanceError::InvalidProposal(message)
+        }
+        GovernanceAction::Vote { .. } => GovernanceError::InvalidVote(message),
+        GovernanceAction::Disburse { .. } => GovernanceError::InvalidDisbursement(message),
+    };
+    if transaction.is_coinbase() {
+        return Err(invalid("A coinbase transaction can't take part in governance".to_string()));
+    }
+    if let GovernanceAction::Disburse { proposal, .. } = action {
+        if transaction.sender.0 != TREASURY_ADDRESS
+            || transaction.signature.is_some()
+            || transaction.fee != 0.0
+            || proposal.is_empty()
+        {
+            return Err(invalid(format!(
+                "It must be paid by {} without a signature or a fee, for a proposal",
+                TREASURY_ADDRESS
+            )));
+        }
+        return Ok(());
+    }
+    if transaction.recipient.0 != GOVERNANCE_ADDRESS || transaction.amount < GOVERNANCE_AMOUNT {
+        return Err(invalid(format!(
+            "It must pay at least {} to {}",
+            GOVERNANCE_AMOUNT, GOVERNANCE_ADDRESS
+        )));
+    }
+
+    match action {
+        GovernanceAction::Propose { parameter, value, .. } => validate_value(*parameter, *value),
+        GovernanceAction::ProposeDisbursement { disbursement, .. } => disbursement.validate(),
+        GovernanceAction::Vote { proposal, .. } if proposal.is_empty() => {
+            Err(GovernanceError::InvalidVote("The proposal is empty".to_string()))
+        }
+        GovernanceAction::Vote { .. } | GovernanceAction::Disburse { .. } => Ok(()),
+    }
+}
+
+/// Installments of a disbursement decided so far
+#[derive(Debug, Default, Clone, Copy)]
+struct InstallmentTally {
+    paid: u32,
+    missed: u32,
+}
+
+#[derive(Debug, Default)]
+struct GovernanceState {
+    proposals: BTreeMap<String, Proposal>,
+    changes: Vec<ParameterChange>,
+    installments: BTreeMap<String, InstallmentTally>,
+}
+
+impl GovernanceState {
+    /// Gets the disbursements that passed, earliest deadline first
+    fn approved_disbursements(&self) -> Vec<(&Proposal, &Disbursement)> {
+        let mut approved: Vec<(&Proposal, &Disbursement)> = self
+            .proposals
+            .values()
+            .filter(|proposal| proposal.status == ProposalStatus::Passed)
+            .filter_map(|proposal| match &proposal.change {
+                ProposedChange::Disbursement { disbursement } => Some((proposal, disbursement)),
+                ProposedChange::Parameter { .. } => None,
+            })
+            .collect();
+        approved.sort_by(|(a, _), (b, _)| a.deadline.cmp(&b.deadline).then_with(|| a.id.cmp(&b.id)));
+        approved
+    }
+
+    /// Gets the installments scheduled at a height, whether the treasury can pay them or not
+    fn scheduled_installments(&self, height: u64) -> Vec<DueInstallment> {
+        self.approved_disbursements()
+            .into_iter()
+            .filter(|(proposal, disbursement)| {
+                height > proposal.deadline && (height - proposal.deadline - 1).is_multiple_of(disbursement.interval)
+            })
+            .map(|(proposal, disbursement)| (proposal, disbursement, (height - proposal.deadline - 1) / disbursement.interval))
+            .filter(|(_, disbursement, installment)| *installment < disbursement.installments as u64)
+            .map(|(proposal, disbursement, installment)| DueInstallment {
+                proposal: proposal.id.clone(),
+                installment: installment as u32,
+                recipient: disbursement.recipient.clone(),
+                amount: disbursement.amount,
+            })
+            .collect()
+    }
+}
+
+/// Proposals, votes and the changes they led to, updated as each block is committed
+///
+/// A proposal is open from its block up to and including its deadline.
+/// Each address may vote any number of times, its last vote counts. At the
+/// deadline every vote weighs the voter's balance after the deadline block,
+/// and the proposal passes if the approving balances outweigh the rejecting
+/// ones. The new value is in force from the block after the deadline on, for
+/// admission, mining and block validation alike. Votes on unknown or closed
+/// proposals are mined but change nothing, which admission to the mempool
+/// prevents.
+///
+/// A disbursement that passed is paid from the treasury in installments,
+/// the first in the block after the deadline and the next ones `interval`
+/// blocks apart. Each block must pay exactly the installments due at its
+/// height that the treasury can afford, earliest deadline first. An
+/// installment the treasury can't afford is missed and not paid later.
+#[derive(Debug)]
+pub struct Governance {
+    base: ChainParameters,
+    state: Mutex<GovernanceState>,
+}
+
+impl Default for Governance {
+    fn default() -> Self {
+        Governance::new(ChainParameters::node())
+    }
+}
+
+impl Governance {
+    /// Creates the governance of an empty chain
+    ///
+    /// # Arguments
+    ///
+    /// * `base` - The parameters before any change
+    pub fn new(base: ChainParameters) -> Self {
+        Governance {
+            base,
+            state: Mutex::new(GovernanceState::default()),
+        }
+    }
+
+    /// Creates the governance after a chain, from the same parameters
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks of a validated chain, genesis first
+    /// * `grants` - The deposits of the faucet, see `FaucetGrant`
+    pub fn replay(&self, blocks: &[Block], grants: &[FaucetGrant]) -> Result<Self, ValidationError> {
+        let governance = Governance::new(self.base.clone());
+        governance.rebuild(blocks, grants)?;
+        Ok(governance)
+    }
+
+    /// Gets the parameters in force at a height
+    ///
+    /// # Arguments
+    ///
+    /// * `height` - Height of the block
+    pub fn parameters_at(&self, height: u64) -> ChainParameters {
+        let mut parameters = self.base.clone();
+        for change in &self.state.lock().unwrap().changes {
+            if change.height <= height {
+                parameters.set(change.parameter, change.value);
+            }
+        }
+        parameters
+    }
+
+    /// Gets the parameter changes that passed, oldest first
+    pub fn changes(&self) -> Vec<ParameterChange> {
+        self.state.lock().unwrap().changes.clone()
+    }
+
+    /// Gets the proposals, newest deadline first
+    pub fn proposals(&self) -> Vec<Proposal> {
+        let mut proposals: Vec<Proposal> = self.state.lock().unwrap().proposals.values().cloned().collect();
+        proposals.sort_by(|a, b| b.deadline.cmp(&a.deadline).then_with(|| a.id.cmp(&b.id)));
+        proposals
+    }
+
+    /// Gets a proposal
+    pub fn proposal(&self, id: &str) -> Option<Proposal> {
+        self.state.lock().unwrap().proposals.get(id).cloned()
+    }
+
+    /// Gets the disbursements that passed and how they are being paid, earliest deadline first
+    pub fn disbursements(&self) -> Vec<DisbursementStatus> {
+        let state = self.state.lock().unwrap();
+
+        state
+            .approved_disbursements()
+            .into_iter()
+            .map(|(proposal, disbursement)| {
+                let tally = state.installments.get(&proposal.id).copied().unwrap_or_default();
+                let decided = tally.paid + tally.missed;
+                DisbursementStatus {
+                    proposal: proposal.id.clone(),
+                    disbursement: disbursement.clone(),
+                    first_due: disbursement.due_at(proposal.deadline, 0),
+                    paid: tally.paid,
+                    missed: tally.missed,
+                    disbursed: tally.paid as f64 * disbursement.amount,
+                    next_due: (decided < disbursement.installments)
+                        .then(|| disbursement.due_at(proposal.deadline, decided)),
+                }
+            })
+            .collect()
+    }
+
+    /// Gets the installments a block must pay
+    ///
+    /// # Arguments
+    ///
+    /// * `height` - Height of the block
+    /// * `treasury_balance` - The treasury's balance after the parent block
+    ///
+    /// # Returns
+    ///
+    /// The installments due at the height the treasury can afford, in the order they are paid
+    pub fn due_installments(&self, height: u64, treasury_balance: f64) -> Vec<DueInstallment> {
+        let mut available = treasury_balance;
+
+        self.state
+            .lock()
+            .unwrap()
+            .scheduled_installments(height)
+            .into_iter()
+            .filter(|installment| {
+                let affordable = installment.amount <= available;
+                if affordable {
+                    available -= installment.amount;
+                }
+                affordable
+            })
+            .collect()
+    }
+
+    /// Checks that a block pays the installments due and nothing else from the treasury
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block to check, before it is applied
+    /// * `treasury_balance` - The treasury's balance after the parent block
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if the block's disbursements match `due_installments`
+    pub fn check_disbursements(&self, block: &Block, treasury_balance: f64) -> Result<(), GovernanceError> {
+        let due = self.due_installments(block.index, treasury_balance);
+        let paid: Vec<&Transaction> = block
+            .transactions
+            .iter()
+            .filter(|transaction| matches!(transaction.governance, Some(GovernanceAction::Disburse { .. })))
+            .collect();
+
+        if paid.len() != due.len() || !due.iter().all(|installment| paid.iter().any(|tx| installment.is_paid_by(tx))) {
+            return Err(GovernanceError::InvalidDisbursement(format!(
+                "Block {} pays {} installments, {} are due",
+                block.index,
+                paid.len(),
+                due.len()
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Checks that a governance transaction may enter a block
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The transaction to check
+    /// * `height` - Height of the block the transaction is meant for
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if it proposes with a deadline in range or votes on an open proposal,
+    /// disbursements are only added by miners
+    pub fn check_admission(&self, transaction: &Transaction, height: u64) -> Result<(), GovernanceError> {
+        match &transaction.governance {
+            Some(GovernanceAction::Propose { deadline, .. } | GovernanceAction::ProposeDisbursement { deadline, .. })
+                if *deadline < height + MIN_VOTING_BLOCKS || *deadline > height + MAX_VOTING_BLOCKS =>
+            {
+                return Err(GovernanceError::InvalidProposal(format!(
+                    "The deadline must be {} to {} blocks after block {}",
+                    MIN_VOTING_BLOCKS, MAX_VOTING_BLOCKS, height
+                )));
+            }
+            Some(GovernanceAction::Vote { proposal, .. }) => {
+                let state = self.state.lock().unwrap();
+                let found = state
+                    .proposals
+                    .get(proposal)
+                    .ok_or_else(|| GovernanceError::ProposalNotFound(proposal.clone()))?;
+                if found.status != ProposalStatus::Open || found.deadline < height {
+                    return Err(GovernanceError::InvalidVote(format!(
+                        "Voting on proposal {} ended at block {}",
+                        proposal, found.deadline
+                    )));
+                }
+            }
+            Some(GovernanceAction::Disburse { .. }) => {
+                return Err(GovernanceError::InvalidDisbursement(
+                    "Installments are added by miners when they are due".to_string(),
+                ));
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Applies the proposals, votes and installments of a committed block and tallies the proposals due
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block that was added to the chain
+    /// * `accounts` - The account state after the block, to weigh votes with
+    pub fn record_block(&self, block: &Block, accounts: &AccountState) {
+        let mut state = self.state.lock().unwrap();
+
+        // Installments due at the block are paid by it or missed for good
+        for installment in state.scheduled_installments(block.index) {
+            let paid = block.transactions.iter().any(|transaction| installment.is_paid_by(transaction));
+            let tally = state.installments.entry(installment.proposal).or_default();
+            if paid {
+                tally.paid += 1;
+            } else {
+                tally.missed += 1;
+            }
+        }
+
+        for transaction in &block.transactions {
+            if check_action(transaction).is_err() {
+                continue;
+            }
+
+            let (change, deadline) = match &transaction.governance {
+                Some(GovernanceAction::Propose { parameter, value, deadline }) => (
+                    ProposedChange::Parameter {
+                        parameter: *parameter,
+                        value: *value,
+                    },
+                    *deadline,
+                ),
+                Some(GovernanceAction::ProposeDisbursement { disbursement, deadline }) => (
+                    ProposedChange::Disbursement {
+                        disbursement: disbursement.clone(),
+                    },
+                    *deadline,
+                ),
+                Some(GovernanceAction::Vote { proposal, approve }) => {
+                    if let Some(proposal) = state.proposals.get_mut(proposal) {
+                        if proposal.status == ProposalStatus::Open && block.index <= proposal.deadline {
+                            proposal.votes.insert(transaction.sender.0.clone(), *approve);
+                        }
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+
+            if deadline > block.index {
+                state.proposals.insert(
+                    transaction.id.clone(),
+                    Proposal {
+                        id: transaction.id.clone(),
+                        proposer: transaction.sender.clone(),
+                        change,
+                        proposed_at: block.index,
+                        deadline,
+                        votes: BTreeMap::new(),
+                        status: ProposalStatus::Open,
+                        weight_for: 0.0,
+                        weight_against: 0.0,
+                    },
+                );
+            }
+        }
+
+        let GovernanceState { proposals, changes, .. } = &mut *state;
+        for proposal in proposals.values_mut() {
+            if proposal.status != ProposalStatus::Open || proposal.deadline != block.index {
+                continue;
+            }
+
+            for (voter, approve) in &proposal.votes {
+                let balance = accounts.get_account(&Address(voter.clone())).balance;
+                if *approve {
+                    proposal.weight_for += balance;
+                } else {
+                    proposal.weight_against += balance;
+                }
+            }
+
+            if proposal.weight_for > proposal.weight_against {
+                proposal.status = ProposalStatus::Passed;
+                if let ProposedChange::Parameter { parameter, value } = proposal.change {
+                    changes.push(ParameterChange {
+                        proposal: proposal.id.clone(),
+                        parameter,
+                        value,
+                        height: block.index + 1,
+                    });
+                }
+            } else {
+                proposal.status = ProposalStatus::Rejected;
+            }
+        }
+    }
+
+    /// Replaces the proposals with the ones of a chain
+    ///
+    /// Replays the accounts along the way, since votes weigh the balances at
+    /// each deadline. The replay is done before anything is replaced, so a
+    /// chain that can't be replayed leaves the proposals as they were.
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks of a validated chain, genesis first
+    /// * `grants` - The deposits of the faucet, see `FaucetGrant`
+    pub fn rebuild(&self, blocks: &[Block], grants: &[FaucetGrant]) -> Result<(), ValidationError> {
+        let replayed = Governance::new(self.base.clone());
+
+        if let Some((genesis, rest)) = blocks.split_first() {
+            let mut state = ChainState::new(genesis.clone());
+            replayed.record_block(genesis, state.accounts());
+            state.credit(grants)?;
+            for block in rest {
+                state.apply_trusted(block.clone())?;
+                replayed.record_block(block, state.accounts());
+                state.credit(grants)?;
+            }
+        }
+
+        *self.state.lock().unwrap() = replayed.state.into_inner().unwrap();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn governance_transaction(sender: &str, nonce: u64, action: GovernanceAction) -> Transaction {
+        Transaction::new(Address(sender.to_string()), Address(GOVERNANCE_ADDRESS.to_string()), GOVERNANCE_AMOUNT, 0.1, nonce)
+            .with_governance(action)
+    }
+
+    fn vote(sender: &str, proposal: &str, approve: bool) -> Transaction {
+        let action = GovernanceAction::Vote {
+            proposal: proposal.to_string(),
+            approve,
+        };
+        governance_transaction(sender, 1, action)
+    }
+
+    #[test]
+    fn test_proposals_are_tallied_by_stake_at_the_deadline() {
+        assert!(validate_value(Parameter::MaxBlockTransactions, 2.5).is_err());
+        assert!(validate_value(Parameter::MiningReward, 0.0).is_err());
+
+        let accounts = AccountState::new();
+        accounts.process_mining_reward(&Address("whale".to_string()), 100.0).unwrap();
+        accounts.process_mining_reward(&Address("minnow".to_string()), 10.0).unwrap();
+
+        let governance = Governance::default();
+        let propose = |value: f64| {
+            let action = GovernanceAction::Propose {
+                parameter: Parameter::MiningReward,
+                value,
+                deadline: 20,
+            };
+            governance_transaction("minnow", 0, action)
+        };
+        assert!(governance.check_admission(&propose(25.0), 15).is_err());
+        governance.check_admission(&propose(25.0), 5).unwrap();
+
+        let first = propose(25.0);
+        let second = propose(75.0);
+        governance.record_block(&Block::new(5, vec![first.clone(), second.clone()], 0, "0".to_string()), &accounts);
+        assert!(matches!(
+            governance.check_admission(&vote("minnow", "unknown", true), 6),
+            Err(GovernanceError::ProposalNotFound(_))
+        ));
+
+        // The whale's stake decides both, its changed mind on the second counts
+        let votes = vec![
+            vote("minnow", &first.id, true),
+            vote("whale", &second.id, true),
+            vote("minnow", &second.id, false),
+        ];
+        governance.record_block(&Block::new(10, votes, 0, "0".to_string()), &accounts);
+        governance.record_block(&Block::new(20, vec![vote("whale", &second.id, false)], 0, "0".to_string()), &accounts);
+
+        assert_eq!(governance.proposal(&first.id).unwrap().status, ProposalStatus::Passed);
+        assert_eq!(governance.proposal(&second.id).unwrap().status, ProposalStatus::Rejected);
+        assert_eq!(governance.proposal(&second.id).unwrap().weight_against, 110.0);
+        assert!(governance.check_admission(&vote("whale", &first.id, true), 21).is_err());
+
+        // The change is in force from the block after the deadline
+        assert_eq!(governance.parameters_at(20).mining_reward, MINING_REWARD);
+        assert_eq!(governance.parameters_at(21).mining_reward, 25.0);
+    }
+
+    #[test]
+    fn test_approved_disbursements_are_paid_in_installments() {
+        let accounts = AccountState::new();
+        accounts.process_mining_reward(&Address("voter".to_string()), 100.0).unwrap();
+
+        let disbursement = Disbursement {
+            recipient: "grantee".to_string(),
+            amount: 5.0,
+            installments: 3,
+            interval: 10,
+        };
+        let action = GovernanceAction::ProposeDisbursement {
+            disbursement: disbursement.clone(),
+            deadline: 20,
+        };
+        let proposal = governance_transaction("voter", 0, action);
+
+        let governance = Governance::default();
+        governance.record_block(&Block::new(5, vec![proposal.clone()], 0, "0".to_string()), &accounts);
+        governance.record_block(&Block::new(10, vec![vote("voter", &proposal.id, true)], 0, "0".to_string()), &accounts);
+        governance.record_block(&Block::new(20, vec![], 0, "0".to_string()), &accounts);
+        assert_eq!(governance.due_installments(21, 12.0).len(), 1);
+        assert!(governance.due_installments(22, 12.0).is_empty());
+
+        // The block after the deadline must pay the first installment, and only once
+        let payment = governance.due_installments(21, 12.0)[0].transaction(0);
+        assert!(check_action(&payment).is_ok());
+        assert!(governance.check_admission(&payment, 21).is_err());
+        assert!(governance.check_disbursements(&Block::new(21, vec![], 0, "0".to_string()), 12.0).is_err());
+        let twice = Block::new(21, vec![payment.clone(), payment.clone()], 0, "0".to_string());
+        assert!(governance.check_disbursements(&twice, 12.0).is_err());
+        let paid = Block::new(21, vec![payment], 0, "0".to_string());
+        governance.check_disbursements(&paid, 12.0).unwrap();
+        governance.record_block(&paid, &accounts);
+
+        // The treasury can't afford the second one, which is missed for good
+        assert!(governance.due_installments(31, 2.0).is_empty());
+        governance.record_block(&Block::new(31, vec![], 0, "0".to_string()), &accounts);
+
+        let status = &governance.disbursements()[0];
+        assert_eq!((status.paid, status.missed, status.disbursed), (1, 1, 5.0));
+        assert_eq!(status.next_due, Some(41));
+    }
+}
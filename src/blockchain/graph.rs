@@ -0,0 +1,270 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use super::block::Block;
+
+/// File format of an exported transaction graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphFormat {
+    Graphml,
+    Dot,
+    Json,
+}
+
+impl GraphFormat {
+    /// Gets the MIME type of the format
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            GraphFormat::Graphml => "application/graphml+xml",
+            GraphFormat::Dot => "text/vnd.graphviz",
+            GraphFormat::Json => "application/json",
+        }
+    }
+
+    /// Gets the usual file extension of the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            GraphFormat::Graphml => "graphml",
+            GraphFormat::Dot => "dot",
+            GraphFormat::Json => "json",
+        }
+    }
+}
+
+/// An address in the transaction graph
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct GraphNode {
+    /// The address
+    pub id: String,
+
+    /// Total amount the address sent
+    pub sent: f64,
+
+    /// Total amount the address received
+    pub received: f64,
+}
+
+/// The aggregated transfers from one address to another
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct GraphEdge {
+    /// The sending address
+    pub source: String,
+
+    /// The receiving address
+    pub target: String,
+
+    /// Total amount transferred, the weight of the edge
+    pub amount: f64,
+
+    /// Number of transfers
+    pub transaction_count: u64,
+}
+
+/// The network of transfers between addresses over a range of blocks
+///
+/// Coinbase transactions are left out, since they create coins rather than
+/// move them between addresses.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TransactionGraph {
+    /// The addresses, sorted by address
+    pub nodes: Vec<GraphNode>,
+
+    /// The directed edges, sorted by source and target
+    pub edges: Vec<GraphEdge>,
+}
+
+impl TransactionGraph {
+    /// Builds the graph of the transfers in some blocks
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks to include
+    ///
+    /// # Returns
+    ///
+    /// The transaction graph
+    pub fn from_blocks<'a>(blocks: impl IntoIterator<Item = &'a Block>) -> Self {
+        let mut nodes: BTreeMap<&str, GraphNode> = BTreeMap::new();
+        let mut edges: BTreeMap<(&str, &str), GraphEdge> = BTreeMap::new();
+
+        for transaction in blocks.into_iter().flat_map(|block| block.transactions.iter()) {
+            if transaction.is_coinbase() {
+                continue;
+            }
+
+            let source = transaction.sender.0.as_str();
+            let target = transaction.recipient.0.as_str();
+
+            node(&mut nodes, source).sent += transaction.amount;
+            node(&mut nodes, target).received += transaction.amount;
+
+            let edge = edges.entry((source, target)).or_insert_with(|| GraphEdge {
+                source: source.to_string(),
+                target: target.to_string(),
+                amount: 0.0,
+                transaction_count: 0,
+            });
+            edge.amount += transaction.amount;
+            edge.transaction_count += 1;
+        }
+
+        TransactionGraph {
+            nodes: nodes.into_values().collect(),
+            edges: edges.into_values().collect(),
+        }
+    }
+
+    /// Renders the graph in a file format
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The file format
+    ///
+    /// # Returns
+    ///
+    /// The rendered graph
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Graphml => self.to_graphml(),
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::Json => serde_json::to_string_pretty(self).unwrap_or_default(),
+        }
+    }
+
+    /// Renders the graph as GraphML
+    fn to_graphml(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        out.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+        out.push_str("  <key id=\"sent\" for=\"node\" attr.name=\"sent\" attr.type=\"double\"/>\n");
+        out.push_str("  <key id=\"received\" for=\"node\" attr.name=\"received\" attr.type=\"double\"/>\n");
+        out.push_str("  <key id=\"amount\" for=\"edge\" attr.name=\"amount\" attr.type=\"double\"/>\n");
+        out.push_str("  <key id=\"transaction_count\" for=\"edge\" attr.name=\"transaction_count\" attr.type=\"long\"/>\n");
+        out.push_str("  <graph id=\"transactions\" edgedefault=\"directed\">\n");
+
+        for node in &self.nodes {
+            let _ = writeln!(out, "    <node id=\"{}\">", xml_escape(&node.id));
+            let _ = writeln!(out, "      <data key=\"sent\">{}</data>", node.sent);
+            let _ = writeln!(out, "      <data key=\"received\">{}</data>", node.received);
+            out.push_str("    </node>\n");
+        }
+
+        for edge in &self.edges {
+            let _ = writeln!(
+                out,
+                "    <edge source=\"{}\" target=\"{}\">",
+                xml_escape(&edge.source),
+                xml_escape(&edge.target)
+            );
+            let _ = writeln!(out, "      <data key=\"amount\">{}</data>", edge.amount);
+            let _ = writeln!(out, "      <data key=\"transaction_count\">{}</data>", edge.transaction_count);
+            out.push_str("    </edge>\n");
+        }
+
+        out.push_str("  </graph>\n");
+        out.push_str("</graphml>\n");
+        out
+    }
+
+    /// Renders the graph in the Graphviz DOT language
+    fn to_dot(&self) -> String {
+        let mut out = String::from("digraph transactions {\n");
+
+        for node in &self.nodes {
+            let _ = writeln!(
+                out,
+                "  {} [sent={}, received={}];",
+                dot_id(&node.id),
+                node.sent,
+                node.received
+            );
+        }
+
+        for edge in &self.edges {
+            let _ = writeln!(
+                out,
+                "  {} -> {} [weight={}, label=\"{}\", transaction_count={}];",
+                dot_id(&edge.source),
+                dot_id(&edge.target),
+                edge.amount,
+                edge.amount,
+                edge.transaction_count
+            );
+        }
+
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// Gets the node of an address, adding it if needed
+fn node<'a, 'b>(nodes: &'b mut BTreeMap<&'a str, GraphNode>, id: &'a str) -> &'b mut GraphNode {
+    nodes.entry(id).or_insert_with(|| GraphNode {
+        id: id.to_string(),
+        sent: 0.0,
+        received: 0.0,
+    })
+}
+
+/// Escapes a value for an XML attribute
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Quotes a value as a DOT identifier
+fn dot_id(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::crypto::Address;
+    use crate::blockchain::transaction::Transaction;
+
+    fn transfer(from: &str, to: &str, amount: f64) -> Transaction {
+        Transaction::new(Address(from.to_string()), Address(to.to_string()), amount, 0.1, 0)
+    }
+
+    #[test]
+    fn test_graph_aggregates_transfers() {
+        let block = Block::new(
+            1,
+            vec![
+                Transaction::new_coinbase(Address("alice".to_string()), 50.0),
+                transfer("alice", "bob", 10.0),
+                transfer("alice", "bob", 5.0),
+                transfer("bob", "carol", 2.0),
+            ],
+            0,
+            "0".to_string(),
+        );
+
+        let graph = TransactionGraph::from_blocks([&block]);
+        let ids: Vec<&str> = graph.nodes.iter().map(|node| node.id.as_str()).collect();
+        assert_eq!(ids, vec!["alice", "bob", "carol"]);
+        assert_eq!(graph.nodes[1].sent, 2.0);
+        assert_eq!(graph.nodes[1].received, 15.0);
+        assert_eq!(graph.edges.len(), 2);
+        assert_eq!(graph.edges[0].amount, 15.0);
+        assert_eq!(graph.edges[0].transaction_count, 2);
+
+        let dot = graph.render(GraphFormat::Dot);
+        assert!(dot.contains("\"alice\" -> \"bob\" [weight=15"));
+
+        let graphml = graph.render(GraphFormat::Graphml);
+        assert!(graphml.contains("<edge source=\"bob\" target=\"carol\">"));
+
+        let json: TransactionGraph = serde_json::from_str(&graph.render(GraphFormat::Json)).unwrap();
+        assert_eq!(json, graph);
+    }
+}
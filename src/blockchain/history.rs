@@ -0,0 +1,176 @@
+use dashmap::DashMap;
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::account::Account;
+use super::block::Block;
+use super::faucet::FaucetGrant;
+use super::state::ChainState;
+use super::validation::ValidationError;
+
+/// Heights at which the accounts are kept to rebuild historical states from
+pub const CHECKPOINT_INTERVAL: u64 = 100;
+
+/// A block named by its hash or its height on the current chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockRef {
+    /// The block with this hash
+    Hash(String),
+
+    /// The block at this height
+    Height(u64),
+}
+
+impl FromStr for BlockRef {
+    type Err = String;
+
+    /// Reads a height from decimal digits and a hash from anything else
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        if value.is_empty() {
+            return Err("Expected a block hash or height".to_string());
+        }
+
+        // Hashes are 64 hex digits, which could all be decimal ones
+        if value.len() < 64 && value.bytes().all(|byte| byte.is_ascii_digit()) {
+            value
+                .parse()
+                .map(BlockRef::Height)
+                .map_err(|_| format!("Height {} is out of range", value))
+        } else {
+            Ok(BlockRef::Hash(value.to_string()))
+        }
+    }
+}
+
+impl fmt::Display for BlockRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockRef::Hash(hash) => write!(f, "{}", hash),
+            BlockRef::Height(height) => write!(f, "height {}", height),
+        }
+    }
+}
+
+/// Accounts after every `CHECKPOINT_INTERVAL`th block, by block hash
+///
+/// A block hash fixes every block before it, so a checkpoint stays right
+/// after a reorganization and only stops being used once its block leaves
+/// the chain.
+#[derive(Debug, Default)]
+pub struct StateCheckpoints {
+    checkpoints: DashMap<String, Vec<Account>>,
+}
+
+impl StateCheckpoints {
+    /// Creates an empty set of checkpoints
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rebuilds the state after the last of the given blocks
+    ///
+    /// Starts from the closest checkpoint below the last block and records the
+    /// checkpoints passed on the way.
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks of a validated chain up to the wanted one, genesis first
+    /// * `grants` - The deposits of the faucet, see `FaucetGrant`
+    ///
+    /// # Returns
+    ///
+    /// The state after the last block
+    pub fn state_at(&self, blocks: &[Block], grants: &[FaucetGrant]) -> Result<ChainState, ValidationError> {
+        let start = blocks
+            .iter()
+            .enumerate()
+            .step_by(CHECKPOINT_INTERVAL as usize)
+            .rev()
+            .find_map(|(height, block)| {
+                self.checkpoints
+                    .get(&block.hash)
+                    .map(|accounts| (height, accounts.clone()))
+            });
+
+        let (mut state, next) = match start {
+            Some((height, accounts)) => (ChainState::from_parts(blocks[..=height].to_vec(), accounts), height + 1),
+            None => (ChainState::replay(&blocks[..blocks.len().min(1)], grants)?, 1),
+        };
+
+        for block in &blocks[next..] {
+            state.apply_trusted(block.clone())?;
+            state.credit(grants)?;
+            if block.index.is_multiple_of(CHECKPOINT_INTERVAL) {
+                self.checkpoints
+                    .insert(block.hash.clone(), state.accounts().get_all_accounts());
+            }
+        }
+
+        Ok(state)
+    }
+
+    /// Drops the checkpoints of blocks that are not in the chain any more
+    ///
+    /// # Arguments
+    ///
+    /// * `chain` - The current chain
+    pub fn retain(&self, chain: &[Block]) {
+        self.checkpoints.retain(|hash, _| {
+            chain
+                .iter()
+                .step_by(CHECKPOINT_INTERVAL as usize)
+                .any(|block| &block.hash == hash)
+        });
+    }
+
+    /// Gets the number of checkpoints kept
+    pub fn len(&self) -> usize {
+        self.checkpoints.len()
+    }
+
+    /// Checks if no checkpoint is kept
+    pub fn is_empty(&self) -> bool {
+        self.checkpoints.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::crypto::Address;
+    use crate::blockchain::transaction::Transaction;
+
+    #[test]
+    fn test_states_resume_from_checkpoints() {
+        assert_eq!("250".parse(), Ok(BlockRef::Height(250)));
+        assert_eq!("00ab".parse(), Ok(BlockRef::Hash("00ab".to_string())));
+        assert_eq!("0".repeat(64).parse(), Ok(BlockRef::Hash("0".repeat(64))));
+        assert!("".parse::<BlockRef>().is_err());
+
+        // Checkpoints don't check proofs, so plain linked blocks will do
+        let miner = Address("miner".to_string());
+        let mut blocks = vec![Block::new(0, Vec::new(), 0, "0".to_string())];
+        for index in 1..=250 {
+            let reward = Transaction::new_coinbase(miner.clone(), 1.0);
+            let previous_hash = blocks.last().unwrap().hash.clone();
+            blocks.push(Block::new(index, vec![reward], 0, previous_hash));
+        }
+
+        let checkpoints = StateCheckpoints::new();
+        let state = checkpoints.state_at(&blocks[..=250], &[]).unwrap();
+        assert_eq!(state.accounts().get_account(&miner).balance, 250.0);
+        assert_eq!(checkpoints.len(), 2);
+
+        // Earlier states start from the checkpoint below them
+        let state = checkpoints.state_at(&blocks[..=150], &[]).unwrap();
+        assert_eq!(state.tip().index, 150);
+        assert_eq!(state.blocks().len(), 151);
+        assert_eq!(state.accounts().get_account(&miner).balance, 150.0);
+
+        // A checkpoint of a block that left the chain is dropped
+        checkpoints.retain(&blocks[..=150]);
+        assert_eq!(checkpoints.len(), 1);
+    }
+}
@@ -0,0 +1,188 @@
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+use thiserror::Error;
+
+use super::block::Block;
+use super::transaction::Transaction;
+
+/// Errors of the validation hooks
+#[derive(Debug, Error)]
+pub enum HookError {
+    #[error("{hook} rejected transaction {transaction}: {reason}")]
+    TransactionRejected {
+        hook: String,
+        transaction: String,
+        reason: String,
+    },
+
+    #[error("{hook} rejected block {index}: {reason}")]
+    BlockRejected { hook: String, index: u64, reason: String },
+}
+
+/// A custom admission rule of an application embedding the node
+///
+/// Hooks run after every rule of the node passed, in the order they were
+/// registered, and the first rejection is the error of the call: the
+/// transaction is not admitted, or the block not mined, appended or switched
+/// to. The blocks of the node's own storage are not checked again when it
+/// starts. Hooks run while the chain is locked, so they must return quickly
+/// and must not call back into the blockchain.
+pub trait ValidationHook: Send + Sync {
+    /// Name of the hook, reported with its rejections
+    fn name(&self) -> &str;
+
+    /// Checks a transaction entering the mempool, a pending transaction going
+    /// into a mined block, or a transaction of a block from another node
+    ///
+    /// Coinbase and treasury transactions are checked in blocks too.
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The transaction
+    /// * `height` - Height of the block the transaction is meant for or part of
+    ///
+    /// # Returns
+    ///
+    /// Result with why the transaction is rejected, if it is
+    fn check_transaction(&self, _transaction: &Transaction, _height: u64) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Checks a block, after each of its transactions passed `check_transaction`
+    ///
+    /// A block the node mines is checked before its proof of work is
+    /// searched, so its proof and hash are not final yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block
+    ///
+    /// # Returns
+    ///
+    /// Result with why the block is rejected, if it is
+    fn check_block(&self, _block: &Block) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// The validation hooks registered with a blockchain
+#[derive(Clone, Default)]
+pub struct ValidationHooks {
+    hooks: Arc<RwLock<Vec<Arc<dyn ValidationHook>>>>,
+}
+
+impl fmt::Debug for ValidationHooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hooks = self.hooks.read().unwrap();
+        f.debug_struct("ValidationHooks")
+            .field("hooks", &hooks.iter().map(|hook| hook.name()).collect::<Vec<&str>>())
+            .finish()
+    }
+}
+
+impl ValidationHooks {
+    /// Creates an empty set of hooks
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a hook after the ones already registered
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - The hook to register
+    pub fn register(&self, hook: Arc<dyn ValidationHook>) {
+        self.hooks.write().unwrap().push(hook);
+    }
+
+    /// Checks a transaction with every hook, stopping at the first rejection
+    pub(crate) fn check_transaction(&self, transaction: &Transaction, height: u64) -> Result<(), HookError> {
+        for hook in self.hooks.read().unwrap().iter() {
+            hook.check_transaction(transaction, height)
+                .map_err(|reason| HookError::TransactionRejected {
+                    hook: hook.name().to_string(),
+                    transaction: transaction.id.clone(),
+                    reason,
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Checks the transactions of a block, then the block, with every hook, stopping at the first rejection
+    pub(crate) fn check_block(&self, block: &Block) -> Result<(), HookError> {
+        for transaction in &block.transactions {
+            self.check_transaction(transaction, block.index)?;
+        }
+        for hook in self.hooks.read().unwrap().iter() {
+            hook.check_block(block).map_err(|reason| HookError::BlockRejected {
+                hook: hook.name().to_string(),
+                index: block.index,
+                reason,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Address;
+
+    /// Rejects transfers above a limit
+    struct AmountLimit(f64);
+
+    impl ValidationHook for AmountLimit {
+        fn name(&self) -> &str {
+            "amount-limit"
+        }
+
+        fn check_transaction(&self, transaction: &Transaction, _height: u64) -> Result<(), String> {
+            if transaction.amount > self.0 {
+                return Err(format!("{} is above {}", transaction.amount, self.0));
+            }
+            Ok(())
+        }
+    }
+
+    /// Rejects blocks with more than one transaction
+    struct SingleTransaction;
+
+    impl ValidationHook for SingleTransaction {
+        fn name(&self) -> &str {
+            "single-transaction"
+        }
+
+        fn check_block(&self, block: &Block) -> Result<(), String> {
+            if block.transactions.len() > 1 {
+                return Err("more than one transaction".to_string());
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_hooks_run_in_order_and_report_the_first_rejection() {
+        let hooks = ValidationHooks::new();
+        hooks.register(Arc::new(SingleTransaction));
+        hooks.register(Arc::new(AmountLimit(10.0)));
+
+        let small = Transaction::new_coinbase(Address("miner".to_string()), 5.0);
+        let large = Transaction::new_coinbase(Address("miner".to_string()), 50.0);
+        assert!(hooks.check_transaction(&small, 1).is_ok());
+        assert!(matches!(
+            hooks.check_transaction(&large, 1),
+            Err(HookError::TransactionRejected { hook, .. }) if hook == "amount-limit"
+        ));
+
+        // Transactions are checked before the block
+        let block = Block::new(1, vec![small.clone(), large], 0, "0".to_string());
+        assert!(matches!(hooks.check_block(&block), Err(HookError::TransactionRejected { .. })));
+        let block = Block::new(1, vec![small.clone(), small], 0, "0".to_string());
+        assert!(matches!(
+            hooks.check_block(&block),
+            Err(HookError::BlockRejected { hook, index: 1, .. }) if hook == "single-transaction"
+        ));
+    }
+}
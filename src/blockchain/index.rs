@@ -0,0 +1,423 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+
+use super::block::Block;
+use super::crypto::Address;
+use super::transaction::Transaction;
+
+/// A confirmed transaction with the block that contains it
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct IndexedTransaction {
+    /// Index of the containing block
+    pub block_index: u64,
+
+    /// Time the containing block was created
+    #[schema(value_type = String, example = "2023-01-01T00:00:00Z")]
+    pub block_timestamp: DateTime<Utc>,
+
+    /// The transaction
+    pub transaction: Transaction,
+}
+
+/// Criteria a confirmed transaction must match, unset criteria match everything
+#[derive(Debug, Clone, Default)]
+pub struct TransactionFilter {
+    /// Only transactions sent by this address
+    pub from: Option<Address>,
+
+    /// Only transactions received by this address
+    pub to: Option<Address>,
+
+    /// Only transactions of at least this amount
+    pub min_amount: Option<f64>,
+
+    /// Only transactions confirmed at or after this moment
+    pub after: Option<DateTime<Utc>>,
+
+    /// Only transactions confirmed before this moment
+    pub before: Option<DateTime<Utc>>,
+
+    /// Only transactions confirmed at or below this height
+    pub at_height: Option<u64>,
+}
+
+impl TransactionFilter {
+    /// Checks if a confirmed transaction matches every criterion
+    fn matches(&self, entry: &IndexedTransaction) -> bool {
+        let transaction = &entry.transaction;
+
+        self.from.as_ref().is_none_or(|from| &transaction.sender == from)
+            && self.to.as_ref().is_none_or(|to| &transaction.recipient == to)
+            && self.min_amount.is_none_or(|min| transaction.amount >= min)
+            && self.after.is_none_or(|after| entry.block_timestamp >= after)
+            && self.before.is_none_or(|before| entry.block_timestamp < before)
+            && self.at_height.is_none_or(|height| entry.block_index <= height)
+    }
+}
+
+/// One page of query results
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TransactionPage {
+    /// The matching transactions of this page, newest first
+    pub transactions: Vec<IndexedTransaction>,
+
+    /// The page number, starting at 1
+    pub page: usize,
+
+    /// The maximum number of transactions per page
+    pub page_size: usize,
+
+    /// The number of matching transactions over all pages
+    pub total: usize,
+}
+
+/// Totals of the transactions between an address and one counterparty
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct CounterpartySummary {
+    /// The other address
+    pub address: Address,
+
+    /// Total amount sent to the counterparty
+    pub sent: f64,
+
+    /// Total amount received from the counterparty
+    pub received: f64,
+
+    /// Number of transactions between the two addresses
+    pub transaction_count: u64,
+
+    /// Confirmation time of the first transaction between the two addresses
+    #[schema(value_type = String, example = "2023-01-01T00:00:00Z")]
+    pub first_interaction: DateTime<Utc>,
+
+    /// Confirmation time of the last transaction between the two addresses
+    #[schema(value_type = String, example = "2023-01-01T00:00:00Z")]
+    pub last_interaction: DateTime<Utc>,
+}
+
+/// Confirmed transactions plus the secondary indexes over them
+///
+/// Every index maps to positions in `entries`, which are in chain order.
+#[derive(Debug, Default)]
+struct IndexState {
+    entries: Vec<IndexedTransaction>,
+    by_sender: HashMap<Address, Vec<usize>>,
+    by_recipient: HashMap<Address, Vec<usize>>,
+    by_time: BTreeSet<(DateTime<Utc>, usize)>,
+    by_amount: BTreeSet<(u64, usize)>,
+    by_data: HashMap<String, usize>,
+}
+
+/// Secondary indexes over the confirmed transactions, updated as each block is committed
+///
+/// A query walks the most selective index for its criteria and only checks
+/// the remaining criteria on those candidates, so it never scans the chain.
+#[derive(Debug, Default)]
+pub struct TransactionIndex {
+    state: Mutex<IndexState>,
+}
+
+impl TransactionIndex {
+    /// Creates an empty index
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the transactions of a committed block
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block that was added to the chain
+    pub fn record_block(&self, block: &Block) {
+        let mut state = self.state.lock().unwrap();
+
+        for transaction in &block.transactions {
+            let position = state.entries.len();
+
+            state.by_sender.entry(transaction.sender.clone()).or_default().push(position);
+            state.by_recipient.entry(transaction.recipient.clone()).or_default().push(position);
+            state.by_time.insert((block.timestamp, position));
+            state.by_amount.insert((amount_key(transaction.amount), position));
+            if let Some(data) = &transaction.data {
+                state.by_data.entry(data.clone()).or_insert(position);
+            }
+            state.entries.push(IndexedTransaction {
+                block_index: block.index,
+                block_timestamp: block.timestamp,
+                transaction: transaction.clone(),
+            });
+        }
+    }
+
+    /// Replaces the index with the transactions of a chain
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks of the chain
+    pub fn rebuild(&self, blocks: &[Block]) {
+        *self.state.lock().unwrap() = IndexState::default();
+
+        for block in blocks {
+            self.record_block(block);
+        }
+    }
+
+    /// Finds the confirmed transactions matching a filter
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - The criteria to match
+    /// * `page` - The page to return, starting at 1
+    /// * `page_size` - The maximum number of transactions per page
+    ///
+    /// # Returns
+    ///
+    /// The requested page of matching transactions, newest first
+    pub fn query(&self, filter: &TransactionFilter, page: usize, page_size: usize) -> TransactionPage {
+        let state = self.state.lock().unwrap();
+
+        let mut positions: Vec<usize> = state
+            .candidates(filter)
+            .into_iter()
+            .filter(|&position| filter.matches(&state.entries[position]))
+            .collect();
+        positions.sort_unstable_by(|a, b| b.cmp(a));
+
+        let total = positions.len();
+        let transactions = positions
+            .into_iter()
+            .skip(page.saturating_sub(1).saturating_mul(page_size))
+            .take(page_size)
+            .map(|position| state.entries[position].clone())
+            .collect();
+
+        TransactionPage {
+            transactions,
+            page,
+            page_size,
+            total,
+        }
+    }
+
+    /// Aggregates the confirmed transactions of an address per counterparty
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address to summarize
+    /// * `at_height` - Only count transactions confirmed at or below this height
+    ///
+    /// # Returns
+    ///
+    /// One summary per counterparty, largest total volume first
+    pub fn counterparties(&self, address: &Address, at_height: Option<u64>) -> Vec<CounterpartySummary> {
+        let state = self.state.lock().unwrap();
+
+        // Transfers to self are in both lists but must only be counted once
+        let mut positions: Vec<usize> = state
+            .by_sender
+            .get(address)
+            .into_iter()
+            .chain(state.by_recipient.get(address))
+            .flatten()
+            .copied()
+            .collect();
+        positions.sort_unstable();
+        positions.dedup();
+
+        let mut summaries: HashMap<&Address, CounterpartySummary> = HashMap::new();
+
+        for position in positions {
+            let entry = &state.entries[position];
+            if at_height.is_some_and(|height| entry.block_index > height) {
+                continue;
+            }
+            let transaction = &entry.transaction;
+            let counterparty = if &transaction.sender == address {
+                &transaction.recipient
+            } else {
+                &transaction.sender
+            };
+
+            let summary = summaries.entry(counterparty).or_insert_with(|| CounterpartySummary {
+                address: counterparty.clone(),
+                sent: 0.0,
+                received: 0.0,
+                transaction_count: 0,
+                first_interaction: entry.block_timestamp,
+                last_interaction: entry.block_timestamp,
+            });
+
+            if &transaction.sender == address {
+                summary.sent += transaction.amount;
+            }
+            if &transaction.recipient == address {
+                summary.received += transaction.amount;
+            }
+            summary.transaction_count += 1;
+            summary.last_interaction = entry.block_timestamp;
+        }
+
+        let mut summaries: Vec<CounterpartySummary> = summaries.into_values().collect();
+        summaries.sort_by(|a, b| {
+            (b.sent + b.received)
+                .total_cmp(&(a.sent + a.received))
+                .then_with(|| a.address.0.cmp(&b.address.0))
+        });
+
+        summaries
+    }
+
+    /// Finds the first confirmed transaction anchoring some data
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The anchored data
+    ///
+    /// # Returns
+    ///
+    /// The earliest transaction carrying the data, if any
+    pub fn find_data(&self, data: &str) -> Option<IndexedTransaction> {
+        let state = self.state.lock().unwrap();
+        state.by_data.get(data).map(|&position| state.entries[position].clone())
+    }
+}
+
+impl IndexState {
+    /// Gets the positions of the smallest index range covering the filter
+    fn candidates(&self, filter: &TransactionFilter) -> Vec<usize> {
+        let mut ranges: Vec<Vec<usize>> = Vec::new();
+
+        if let Some(from) = &filter.from {
+            ranges.push(self.by_sender.get(from).cloned().unwrap_or_default());
+        }
+
+        if let Some(to) = &filter.to {
+            ranges.push(self.by_recipient.get(to).cloned().unwrap_or_default());
+        }
+
+        if filter.after.is_some() || filter.before.is_some() {
+            let start = filter.after.unwrap_or(DateTime::<Utc>::MIN_UTC);
+            let range = match filter.before {
+                Some(before) if before <= start => Vec::new(),
+                Some(before) => self.by_time.range((start, 0)..(before, 0)).map(|&(_, p)| p).collect(),
+                None => self.by_time.range((start, 0)..).map(|&(_, p)| p).collect(),
+            };
+            ranges.push(range);
+        }
+
+        if let Some(min_amount) = filter.min_amount.filter(|min| *min > 0.0) {
+            let range = self.by_amount.range((amount_key(min_amount), 0)..).map(|&(_, p)| p).collect();
+            ranges.push(range);
+        }
+
+        ranges
+            .into_iter()
+            .min_by_key(Vec::len)
+            .unwrap_or_else(|| (0..self.entries.len()).collect())
+    }
+}
+
+/// Maps an amount to a key that sorts like the amount
+///
+/// Amounts are never negative, and the bit patterns of non-negative floats
+/// are ordered like their values.
+fn amount_key(amount: f64) -> u64 {
+    amount.max(0.0).to_bits()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn transfer(from: &str, to: &str, amount: f64) -> Transaction {
+        Transaction::new(Address(from.to_string()), Address(to.to_string()), amount, 0.1, 0)
+    }
+
+    #[test]
+    fn test_query_combines_filters() {
+        let index = TransactionIndex::new();
+        let mut old_block = Block::new(1, vec![transfer("alice", "bob", 150.0)], 0, "0".to_string());
+        old_block.timestamp = Utc::now() - Duration::days(30);
+        let new_block = Block::new(
+            2,
+            vec![
+                transfer("alice", "bob", 200.0),
+                transfer("alice", "carol", 300.0),
+                transfer("alice", "bob", 5.0),
+                transfer("dave", "bob", 120.0),
+            ],
+            0,
+            old_block.hash.clone(),
+        );
+        index.record_block(&old_block);
+        index.record_block(&new_block);
+
+        let last_week = TransactionFilter {
+            min_amount: Some(100.0),
+            after: Some(Utc::now() - Duration::weeks(1)),
+            ..Default::default()
+        };
+        let amounts: Vec<f64> = index
+            .query(&last_week, 1, 10)
+            .transactions
+            .iter()
+            .map(|entry| entry.transaction.amount)
+            .collect();
+        assert_eq!(amounts, vec![120.0, 300.0, 200.0]);
+
+        let alice_to_bob = TransactionFilter {
+            from: Some(Address("alice".to_string())),
+            to: Some(Address("bob".to_string())),
+            min_amount: Some(100.0),
+            ..Default::default()
+        };
+        let page = index.query(&alice_to_bob, 2, 1);
+        assert_eq!(page.total, 2);
+        assert_eq!(page.transactions.len(), 1);
+        assert_eq!(page.transactions[0].transaction.amount, 150.0);
+        assert_eq!(page.transactions[0].block_index, 1);
+
+        // Rebuilding drops the transactions of blocks that left the chain
+        index.rebuild(&[new_block]);
+        assert_eq!(index.query(&alice_to_bob, 1, 10).total, 1);
+        assert_eq!(index.query(&TransactionFilter::default(), 1, 10).total, 4);
+    }
+
+    #[test]
+    fn test_counterparties() {
+        let index = TransactionIndex::new();
+        let first = Block::new(
+            1,
+            vec![transfer("alice", "bob", 10.0), transfer("carol", "alice", 100.0)],
+            0,
+            "0".to_string(),
+        );
+        let mut second = Block::new(
+            2,
+            vec![transfer("bob", "alice", 4.0), transfer("alice", "alice", 1.0)],
+            0,
+            first.hash.clone(),
+        );
+        second.timestamp = first.timestamp + Duration::hours(1);
+        index.record_block(&first);
+        index.record_block(&second);
+
+        let summaries = index.counterparties(&Address("alice".to_string()), None);
+        let addresses: Vec<&str> = summaries.iter().map(|s| s.address.0.as_str()).collect();
+        assert_eq!(addresses, vec!["carol", "bob", "alice"]);
+
+        let bob = &summaries[1];
+        assert_eq!((bob.sent, bob.received, bob.transaction_count), (10.0, 4.0, 2));
+        assert_eq!(bob.first_interaction, first.timestamp);
+        assert_eq!(bob.last_interaction, second.timestamp);
+
+        // A transfer to self is one transaction in both directions
+        let alice = &summaries[2];
+        assert_eq!((alice.sent, alice.received, alice.transaction_count), (1.0, 1.0, 1));
+    }
+}
@@ -0,0 +1,438 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use super::account::{AccountError, AccountState};
+use super::block::Block;
+use super::crypto::Address;
+use super::transaction::Transaction;
+
+/// Address designations and cancellations are paid to, which has no key so the amount is burned
+pub const INHERITANCE_ADDRESS: &str = "inheritance";
+
+/// Amount a designation or cancellation pays to the inheritance address
+pub const INHERITANCE_CHANGE_AMOUNT: f64 = 0.01;
+
+/// Errors that can occur with inheritance switches
+#[derive(Debug, Error)]
+pub enum InheritanceError {
+    #[error("Invalid designation: {0}")]
+    InvalidDesignation(String),
+
+    #[error("Invalid claim: {0}")]
+    InvalidClaim(String),
+
+    #[error("{0} designated no beneficiary")]
+    NotDesignated(String),
+
+    #[error("{owner} was active at height {last_active}, its funds can be claimed from height {claimable_at}")]
+    OwnerActive { owner: String, last_active: u64, claimable_at: u64 },
+
+    #[error("A claim on {0} is already pending")]
+    Pending(String),
+}
+
+/// What an inheritance transaction does
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum InheritanceAction {
+    /// Designates who may claim the sender's funds once it sent nothing for `inactivity_blocks` blocks,
+    /// replacing an earlier designation
+    Designate { beneficiary: String, inactivity_blocks: u64 },
+
+    /// Cancels the designation of the sender
+    Cancel,
+
+    /// Claims the amount from an inactive owner whose beneficiary is the sender, sending it to the recipient
+    Claim { owner: String },
+}
+
+/// Checks that an inheritance transaction is well formed
+///
+/// Whether a claim's owner designated its sender and has been inactive
+/// long enough depends on the chain and is checked by `Inheritances::check_claim`.
+///
+/// # Arguments
+///
+/// * `transaction` - The transaction to check
+///
+/// # Returns
+///
+/// Ok(()) if the transaction carries no inheritance action, a designation of another
+/// address or a cancellation paid to the inheritance address, or a claim on another owner
+pub fn check_inheritance(transaction: &Transaction) -> Result<(), InheritanceError> {
+    let action = match &transaction.inheritance {
+        Some(action) => action,
+        None => return Ok(()),
+    };
+
+    match action {
+        InheritanceAction::Designate { .. } | InheritanceAction::Cancel => {
+            let invalid = |reason: String| Err(InheritanceError::InvalidDesignation(reason));
+            if transaction.is_coinbase() || transaction.is_disbursement() {
+                return invalid("Only a transaction signed by its sender can designate a beneficiary".to_string());
+            }
+            if transaction.recipient.0 != INHERITANCE_ADDRESS {
+                return invalid(format!("A designation or cancellation must be paid to {}", INHERITANCE_ADDRESS));
+            }
+            if let InheritanceAction::Designate {
+                beneficiary,
+                inactivity_blocks,
+            } = action
+            {
+                if beneficiary.is_empty() || beneficiary == &transaction.sender.0 || beneficiary == INHERITANCE_ADDRESS {
+                    return invalid(format!("{:?} can't be the beneficiary", beneficiary));
+                }
+                if *inactivity_blocks == 0 {
+                    return invalid("The inactivity period must be at least one block".to_string());
+                }
+            }
+
+            Ok(())
+        }
+        InheritanceAction::Claim { owner } => {
+            let invalid = |reason: String| Err(InheritanceError::InvalidClaim(reason));
+            if transaction.is_coinbase() || transaction.is_disbursement() {
+                return invalid("A coinbase transaction can't claim funds".to_string());
+            }
+            if owner.is_empty() || owner == &transaction.sender.0 {
+                return invalid(format!("{:?} can't be claimed from by its sender", owner));
+            }
+            if transaction.recipient.0 == INHERITANCE_ADDRESS {
+                return invalid(format!("A claim can't pay {}", INHERITANCE_ADDRESS));
+            }
+            if transaction.script.is_some() {
+                return invalid("A claim can't spend a lock as well".to_string());
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// Gets the owner a transaction claims funds from
+pub fn claimed_owner(transaction: &Transaction) -> Option<Address> {
+    match &transaction.inheritance {
+        Some(InheritanceAction::Claim { owner }) => Some(Address(owner.clone())),
+        _ => None,
+    }
+}
+
+/// Moves the amount a claim takes from the owner to its sender
+///
+/// Called right before the transaction's transfer, which then sends the
+/// amount on to its recipient, like the release of an unlock. Other
+/// transactions release nothing.
+///
+/// # Arguments
+///
+/// * `accounts` - The account state to update
+/// * `transaction` - The transaction being applied
+///
+/// # Returns
+///
+/// Result with () if successful
+pub fn release(accounts: &AccountState, transaction: &Transaction) -> Result<(), AccountError> {
+    let owner = match claimed_owner(transaction) {
+        Some(owner) => owner,
+        None => return Ok(()),
+    };
+
+    let mut owner = accounts.get_account(&owner);
+    let mut beneficiary = accounts.get_account(&transaction.sender);
+    owner.withdraw(transaction.amount)?;
+    beneficiary.deposit(transaction.amount)?;
+    accounts.update_account(owner);
+    accounts.update_account(beneficiary);
+
+    Ok(())
+}
+
+/// A beneficiary designated to claim an account's funds once the account goes quiet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct InheritanceSwitch {
+    /// The account whose funds may be claimed
+    #[schema(value_type = String)]
+    pub owner: Address,
+
+    /// The address that may claim them
+    #[schema(value_type = String)]
+    pub beneficiary: Address,
+
+    /// Blocks without a transaction of the owner before its funds may be claimed
+    pub inactivity_blocks: u64,
+
+    /// Height of the last block with a transaction of the owner
+    pub last_active: u64,
+
+    /// Height of the block that made the designation
+    pub designated_at: u64,
+}
+
+impl InheritanceSwitch {
+    /// Gets the first height a claim may be mined at, unless the owner sends a transaction before
+    pub fn claimable_at(&self) -> u64 {
+        self.last_active + self.inactivity_blocks + 1
+    }
+}
+
+/// The inheritance switches on the chain, updated as each block is committed
+///
+/// Every transaction the owner sends, signed by its key or a session key,
+/// refreshes its switch, so only an owner that went quiet can be claimed
+/// from. Designations and cancellations take effect after the block that
+/// includes them, so every claim of a block is checked against the switches
+/// at its parent. A claim leaves the switch in place: the beneficiary can
+/// claim again what the owner receives later, until the owner is active again.
+#[derive(Debug, Default)]
+pub struct Inheritances {
+    switches: Mutex<HashMap<Address, InheritanceSwitch>>,
+}
+
+impl Inheritances {
+    /// Creates the switches of an empty chain
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates the switches after a chain
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks of the chain
+    pub fn replay(blocks: &[Block]) -> Self {
+        let switches = Inheritances::new();
+        switches.rebuild(blocks);
+        switches
+    }
+
+    /// Applies the activity, designations and cancellations of a committed block
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block that was added to the chain
+    pub fn record_block(&self, block: &Block) {
+        let mut switches = self.switches.lock().unwrap();
+
+        for transaction in &block.transactions {
+            if transaction.is_coinbase() {
+                continue;
+            }
+            if let Some(switch) = switches.get_mut(&transaction.sender) {
+                switch.last_active = block.index;
+            }
+            if check_inheritance(transaction).is_err() {
+                continue;
+            }
+            match &transaction.inheritance {
+                Some(InheritanceAction::Designate {
+                    beneficiary,
+                    inactivity_blocks,
+                }) => {
+                    switches.insert(
+                        transaction.sender.clone(),
+                        InheritanceSwitch {
+                            owner: transaction.sender.clone(),
+                            beneficiary: Address(beneficiary.clone()),
+                            inactivity_blocks: *inactivity_blocks,
+                            last_active: block.index,
+                            designated_at: block.index,
+                        },
+                    );
+                }
+                Some(InheritanceAction::Cancel) => {
+                    switches.remove(&transaction.sender);
+                }
+                Some(InheritanceAction::Claim { .. }) | None => {}
+            }
+        }
+    }
+
+    /// Replaces the switches with the switches of a chain
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks of the chain
+    pub fn rebuild(&self, blocks: &[Block]) {
+        self.switches.lock().unwrap().clear();
+
+        for block in blocks {
+            self.record_block(block);
+        }
+    }
+
+    /// Gets the switch of an owner
+    pub fn get(&self, owner: &Address) -> Option<InheritanceSwitch> {
+        self.switches.lock().unwrap().get(owner).cloned()
+    }
+
+    /// Gets the switches naming an address as beneficiary, oldest first
+    pub fn for_beneficiary(&self, beneficiary: &Address) -> Vec<InheritanceSwitch> {
+        let mut switches: Vec<InheritanceSwitch> = self
+            .switches
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|switch| &switch.beneficiary == beneficiary)
+            .cloned()
+            .collect();
+        switches.sort_by(|a, b| a.designated_at.cmp(&b.designated_at).then_with(|| a.owner.0.cmp(&b.owner.0)));
+        switches
+    }
+
+    /// Checks that a transaction may claim the funds of its owner in a block
+    ///
+    /// Whether the owner holds the amount is checked when the claim is applied.
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The transaction to check
+    /// * `height` - Height of the block the transaction is in or meant for
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if the transaction claims nothing, or claims from an owner that
+    /// designated its sender and sent nothing for the inactivity period
+    pub fn check_claim(&self, transaction: &Transaction, height: u64) -> Result<(), InheritanceError> {
+        let owner = match claimed_owner(transaction) {
+            Some(owner) => owner,
+            None => return Ok(()),
+        };
+
+        let switch = self.get(&owner).ok_or_else(|| InheritanceError::NotDesignated(owner.0.clone()))?;
+        if switch.beneficiary != transaction.sender {
+            return Err(InheritanceError::InvalidClaim(format!(
+                "Only {} may claim the funds of {}",
+                switch.beneficiary, owner
+            )));
+        }
+        if height < switch.claimable_at() {
+            return Err(InheritanceError::OwnerActive {
+                owner: owner.0,
+                last_active: switch.last_active,
+                claimable_at: switch.claimable_at(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Checks every claim of a block
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block to check, following the last block recorded
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if every claim is allowed and no owner sends a transaction before a claim on it in the block
+    pub fn check_block(&self, block: &Block) -> Result<(), InheritanceError> {
+        let mut active = HashSet::new();
+
+        for transaction in &block.transactions {
+            if let Some(owner) = claimed_owner(transaction) {
+                if active.contains(&owner) {
+                    return Err(InheritanceError::OwnerActive {
+                        owner: owner.0.clone(),
+                        last_active: block.index,
+                        claimable_at: block.index + self.get(&owner).map(|switch| switch.inactivity_blocks).unwrap_or(0) + 1,
+                    });
+                }
+                self.check_claim(transaction, block.index)?;
+            }
+            if !transaction.is_coinbase() {
+                active.insert(transaction.sender.clone());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::crypto::Wallet;
+
+    fn designate(owner: &Wallet, beneficiary: &Address, inactivity_blocks: u64, nonce: u64) -> Transaction {
+        let mut transaction = Transaction::new(owner.address().clone(), Address(INHERITANCE_ADDRESS.to_string()), INHERITANCE_CHANGE_AMOUNT, 0.1, nonce)
+            .with_inheritance(InheritanceAction::Designate {
+                beneficiary: beneficiary.0.clone(),
+                inactivity_blocks,
+            });
+        transaction.sign(owner).unwrap();
+        transaction
+    }
+
+    fn claim(beneficiary: &Wallet, owner: &Address, amount: f64) -> Transaction {
+        let mut transaction = Transaction::new(beneficiary.address().clone(), Address("payee".to_string()), amount, 0.1, 0)
+            .with_inheritance(InheritanceAction::Claim { owner: owner.0.clone() });
+        transaction.sign(beneficiary).unwrap();
+        transaction
+    }
+
+    #[test]
+    fn test_inheritance_transactions_are_well_formed() {
+        let owner = Wallet::new().unwrap();
+        let heir = Wallet::new().unwrap();
+
+        check_inheritance(&designate(&owner, heir.address(), 10, 0)).unwrap();
+        check_inheritance(&claim(&heir, owner.address(), 5.0)).unwrap();
+
+        let invalid = [
+            designate(&owner, owner.address(), 10, 0),
+            designate(&owner, heir.address(), 0, 0),
+            claim(&heir, heir.address(), 5.0),
+        ];
+        for transaction in invalid {
+            assert!(check_inheritance(&transaction).is_err());
+        }
+    }
+
+    #[test]
+    fn test_claims_wait_for_the_owner_to_go_quiet() {
+        let owner = Wallet::new().unwrap();
+        let heir = Wallet::new().unwrap();
+        let switches = Inheritances::new();
+
+        assert!(matches!(
+            switches.check_claim(&claim(&heir, owner.address(), 5.0), 1),
+            Err(InheritanceError::NotDesignated(_))
+        ));
+
+        // Designated at height 1, so claimable from height 5 after 3 quiet blocks
+        switches.record_block(&Block::new(1, vec![designate(&owner, heir.address(), 3, 0)], 0, "0".to_string()));
+        assert_eq!(switches.get(owner.address()).unwrap().claimable_at(), 5);
+        assert!(matches!(
+            switches.check_claim(&claim(&heir, owner.address(), 5.0), 4),
+            Err(InheritanceError::OwnerActive { claimable_at: 5, .. })
+        ));
+        switches.check_claim(&claim(&heir, owner.address(), 5.0), 5).unwrap();
+
+        // Only the beneficiary may claim
+        let stranger = Wallet::new().unwrap();
+        assert!(matches!(
+            switches.check_claim(&claim(&stranger, owner.address(), 5.0), 5),
+            Err(InheritanceError::InvalidClaim(_))
+        ));
+
+        // Any transaction of the owner refreshes the switch, even in the block of the claim
+        let mut transfer = Transaction::new(owner.address().clone(), Address("bob".to_string()), 1.0, 0.1, 1);
+        transfer.sign(&owner).unwrap();
+        let block = Block::new(5, vec![transfer.clone(), claim(&heir, owner.address(), 5.0)], 0, "0".to_string());
+        assert!(matches!(switches.check_block(&block), Err(InheritanceError::OwnerActive { .. })));
+        switches.record_block(&Block::new(5, vec![transfer], 0, "0".to_string()));
+        assert_eq!(switches.get(owner.address()).unwrap().claimable_at(), 9);
+
+        // A cancellation removes the switch
+        let mut cancel = Transaction::new(owner.address().clone(), Address(INHERITANCE_ADDRESS.to_string()), INHERITANCE_CHANGE_AMOUNT, 0.1, 2)
+            .with_inheritance(InheritanceAction::Cancel);
+        cancel.sign(&owner).unwrap();
+        switches.record_block(&Block::new(6, vec![cancel], 0, "0".to_string()));
+        assert!(switches.get(owner.address()).is_none());
+        assert!(switches.for_beneficiary(heir.address()).is_empty());
+    }
+}
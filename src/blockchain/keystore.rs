@@ -0,0 +1,300 @@
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use std::sync::Arc;
+
+use super::backup::{self, BackupError, BackupSecret, EncryptedBackup};
+use super::crypto::{Address, CryptoError, Wallet};
+use super::storage::{BlockchainStorage, StorageError};
+
+/// Errors that can occur during keystore operations
+#[derive(Debug, Error)]
+pub enum KeystoreError {
+    #[error("Wallet not found: {0}")]
+    NotFound(String),
+
+    #[error("Wallet already exists: {0}")]
+    AlreadyExists(String),
+
+    #[error("Wallet {0} is watch-only and cannot sign")]
+    WatchOnly(String),
+
+    #[error("Invalid address: {0}")]
+    InvalidAddress(String),
+
+    #[error("Crypto error: {0}")]
+    CryptoError(#[from] CryptoError),
+
+    #[error("Backup error: {0}")]
+    BackupError(#[from] BackupError),
+
+    #[error("Storage error: {0}")]
+    StorageError(#[from] StorageError),
+}
+
+/// A wallet held by the node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeystoreEntry {
+    /// Name of the wallet
+    pub name: String,
+
+    /// Address of the wallet
+    pub address: Address,
+
+    /// Secret key of the wallet, None for watch-only wallets
+    pub secret_key: Option<Vec<u8>>,
+
+    /// Timestamp when the wallet was added
+    pub created_at: DateTime<Utc>,
+}
+
+impl KeystoreEntry {
+    /// Checks if the wallet is watch-only
+    pub fn is_watch_only(&self) -> bool {
+        self.secret_key.is_none()
+    }
+
+    /// Gets the public information of the wallet
+    pub fn info(&self) -> WalletInfo {
+        WalletInfo {
+            name: self.name.clone(),
+            address: self.address.clone(),
+            watch_only: self.is_watch_only(),
+            created_at: self.created_at,
+        }
+    }
+}
+
+/// Public information about a keystore wallet
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct WalletInfo {
+    /// Name of the wallet
+    pub name: String,
+
+    /// Address of the wallet
+    pub address: Address,
+
+    /// Whether the wallet is watch-only (no private key held by the node)
+    pub watch_only: bool,
+
+    /// Timestamp when the wallet was added
+    #[schema(value_type = String, example = "2023-01-01T12:00:00Z")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// Named wallets held by the node, either spending or watch-only
+#[derive(Debug, Clone)]
+pub struct Keystore {
+    entries: Arc<DashMap<String, KeystoreEntry>>,
+    storage: Option<Arc<BlockchainStorage>>,
+}
+
+impl Keystore {
+    /// Creates a new keystore
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - Optional storage to persist wallets in
+    ///
+    /// # Returns
+    ///
+    /// A new Keystore instance with the wallets loaded from storage
+    pub fn new(storage: Option<Arc<BlockchainStorage>>) -> Result<Self, KeystoreError> {
+        let entries = DashMap::new();
+
+        if let Some(storage) = &storage {
+            for entry in storage.get_all_keystore_entries()? {
+                entries.insert(entry.name.clone(), entry);
+            }
+        }
+
+        Ok(Keystore {
+            entries: Arc::new(entries),
+            storage,
+        })
+    }
+
+    /// Adds a spending wallet
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the wallet
+    /// * `wallet` - The wallet to hold
+    ///
+    /// # Returns
+    ///
+    /// The public information of the wallet
+    pub fn add_wallet(&self, name: &str, wallet: &Wallet) -> Result<WalletInfo, KeystoreError> {
+        self.insert(KeystoreEntry {
+            name: name.to_string(),
+            address: wallet.address().clone(),
+            secret_key: Some(wallet.export_secret_key()),
+            created_at: Utc::now(),
+        })
+    }
+
+    /// Adds a watch-only wallet
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the wallet
+    /// * `address` - The address to watch
+    ///
+    /// # Returns
+    ///
+    /// The public information of the wallet
+    pub fn add_watch_only(&self, name: &str, address: Address) -> Result<WalletInfo, KeystoreError> {
+        // Make sure the address is a valid public key
+        address
+            .to_public_key()
+            .map_err(|e| KeystoreError::InvalidAddress(e.to_string()))?;
+
+        self.insert(KeystoreEntry {
+            name: name.to_string(),
+            address,
+            secret_key: None,
+            created_at: Utc::now(),
+        })
+    }
+
+    /// Gets a wallet by name
+    pub fn get(&self, name: &str) -> Result<KeystoreEntry, KeystoreError> {
+        self.entries
+            .get(name)
+            .map(|entry| entry.clone())
+            .ok_or_else(|| KeystoreError::NotFound(name.to_string()))
+    }
+
+    /// Lists all wallets ordered by name
+    pub fn list(&self) -> Vec<WalletInfo> {
+        let mut wallets: Vec<WalletInfo> = self.entries.iter().map(|entry| entry.info()).collect();
+        wallets.sort_by(|a, b| a.name.cmp(&b.name));
+        wallets
+    }
+
+    /// Gets a signing wallet by name
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the wallet
+    ///
+    /// # Returns
+    ///
+    /// The wallet, or KeystoreError::WatchOnly if the node holds no private key for it
+    pub fn signer(&self, name: &str) -> Result<Wallet, KeystoreError> {
+        let entry = self.get(name)?;
+
+        match &entry.secret_key {
+            Some(secret_key) => Ok(Wallet::from_secret_key(secret_key)?),
+            None => Err(KeystoreError::WatchOnly(name.to_string())),
+        }
+    }
+
+    /// Exports all wallets as an encrypted backup
+    ///
+    /// # Arguments
+    ///
+    /// * `passphrase` - The passphrase that unlocks the backup
+    /// * `threshold` - Number of recovery codes that unlock the backup
+    /// * `share_count` - Number of recovery codes to generate
+    ///
+    /// # Returns
+    ///
+    /// The backup and its recovery codes
+    pub fn export_backup(
+        &self,
+        passphrase: &str,
+        threshold: u8,
+        share_count: u8,
+    ) -> Result<(EncryptedBackup, Vec<String>), KeystoreError> {
+        let mut entries: Vec<KeystoreEntry> = self.entries.iter().map(|entry| entry.clone()).collect();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(backup::create_backup(&entries, passphrase, threshold, share_count)?)
+    }
+
+    /// Restores the wallets of an encrypted backup
+    ///
+    /// Wallets the keystore already holds under the same name and address are
+    /// skipped. Nothing is restored if a name is taken by another address.
+    ///
+    /// # Arguments
+    ///
+    /// * `backup` - The backup
+    /// * `secret` - The passphrase or recovery codes
+    ///
+    /// # Returns
+    ///
+    /// The public information of the restored wallets
+    pub fn restore_backup(
+        &self,
+        backup: &EncryptedBackup,
+        secret: &BackupSecret,
+    ) -> Result<Vec<WalletInfo>, KeystoreError> {
+        let entries = backup::restore_backup(backup, secret)?;
+
+        let mut missing = Vec::new();
+        for entry in entries {
+            match self.entries.get(&entry.name) {
+                Some(existing) if existing.address == entry.address => {}
+                Some(_) => return Err(KeystoreError::AlreadyExists(entry.name)),
+                None => missing.push(entry),
+            }
+        }
+
+        missing.into_iter().map(|entry| self.insert(entry)).collect()
+    }
+
+    /// Stores a new wallet in memory and in storage if available
+    fn insert(&self, entry: KeystoreEntry) -> Result<WalletInfo, KeystoreError> {
+        if self.entries.contains_key(&entry.name) {
+            return Err(KeystoreError::AlreadyExists(entry.name));
+        }
+
+        if let Some(storage) = &self.storage {
+            storage.save_keystore_entry(&entry)?;
+        }
+
+        let info = entry.info();
+        self.entries.insert(entry.name.clone(), entry);
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spending_wallet_can_sign() {
+        let keystore = Keystore::new(None).unwrap();
+        let wallet = Wallet::new().unwrap();
+
+        let info = keystore.add_wallet("hot", &wallet).unwrap();
+        assert!(!info.watch_only);
+
+        let signer = keystore.signer("hot").unwrap();
+        assert_eq!(signer.address(), wallet.address());
+
+        // Names are unique
+        assert!(keystore.add_wallet("hot", &wallet).is_err());
+    }
+
+    #[test]
+    fn test_watch_only_wallet_cannot_sign() {
+        let keystore = Keystore::new(None).unwrap();
+        let cold = Wallet::new().unwrap();
+
+        let info = keystore.add_watch_only("cold", cold.address().clone()).unwrap();
+        assert!(info.watch_only);
+        assert_eq!(keystore.list().len(), 1);
+
+        assert!(matches!(keystore.signer("cold"), Err(KeystoreError::WatchOnly(_))));
+
+        // Watch-only addresses must be valid public keys
+        assert!(keystore.add_watch_only("bogus", Address("0".to_string())).is_err());
+    }
+}
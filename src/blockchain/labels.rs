@@ -0,0 +1,114 @@
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+use std::sync::Arc;
+
+use super::storage::{BlockchainStorage, StorageError};
+
+/// A private label attached to a transaction
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TransactionLabel {
+    /// The ID of the labeled transaction
+    pub transaction_id: String,
+
+    /// Free-form label
+    pub label: String,
+
+    /// Accounting category (e.g. "income", "fees")
+    pub category: Option<String>,
+}
+
+/// Stores transaction labels privately per API key
+///
+/// Labels are keyed by a hash of the API key so the keys themselves are never
+/// persisted, and one client can never see another client's labels.
+#[derive(Debug, Clone)]
+pub struct LabelStore {
+    labels: Arc<DashMap<(String, String), TransactionLabel>>,
+    storage: Option<Arc<BlockchainStorage>>,
+}
+
+impl LabelStore {
+    /// Creates a new label store
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - Optional storage to persist labels in
+    ///
+    /// # Returns
+    ///
+    /// A new LabelStore instance with the labels loaded from storage
+    pub fn new(storage: Option<Arc<BlockchainStorage>>) -> Result<Self, StorageError> {
+        let labels = DashMap::new();
+
+        if let Some(storage) = &storage {
+            for (owner, label) in storage.get_all_labels()? {
+                labels.insert((owner, label.transaction_id.clone()), label);
+            }
+        }
+
+        Ok(LabelStore {
+            labels: Arc::new(labels),
+            storage,
+        })
+    }
+
+    /// Sets the label of a transaction for an API key
+    ///
+    /// # Arguments
+    ///
+    /// * `api_key` - The API key owning the label
+    /// * `label` - The label to set
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if successful
+    pub fn set(&self, api_key: &str, label: TransactionLabel) -> Result<(), StorageError> {
+        let owner = owner_id(api_key);
+
+        if let Some(storage) = &self.storage {
+            storage.save_label(&owner, &label)?;
+        }
+
+        self.labels.insert((owner, label.transaction_id.clone()), label);
+        Ok(())
+    }
+
+    /// Gets the label of a transaction for an API key
+    pub fn get(&self, api_key: &str, transaction_id: &str) -> Option<TransactionLabel> {
+        self.labels
+            .get(&(owner_id(api_key), transaction_id.to_string()))
+            .map(|label| label.clone())
+    }
+}
+
+/// Derives the storage owner ID of an API key
+fn owner_id(api_key: &str) -> String {
+    format!("{:x}", Sha256::digest(api_key.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labels_are_private_per_api_key() {
+        let store = LabelStore::new(None).unwrap();
+
+        store
+            .set(
+                "team-a",
+                TransactionLabel {
+                    transaction_id: "tx1".to_string(),
+                    label: "rent".to_string(),
+                    category: Some("expenses".to_string()),
+                },
+            )
+            .unwrap();
+
+        assert_eq!(store.get("team-a", "tx1").unwrap().label, "rent");
+        assert!(store.get("team-b", "tx1").is_none());
+    }
+}
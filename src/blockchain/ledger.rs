@@ -0,0 +1,432 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use std::collections::BTreeMap;
+
+use super::account::Account;
+use super::block::Block;
+use super::faucet::FaucetGrant;
+use super::inheritance;
+use super::scripts::{ScriptAction, SCRIPT_ADDRESS};
+use super::transaction::Transaction;
+
+/// Account the block rewards are minted from, its balance is minus the coins minted
+pub const MINT_ACCOUNT: &str = "@mint";
+
+/// Account the fees and the reward of a block pass through on their way to its coinbase
+pub const COINBASE_ACCOUNT: &str = "@coinbase";
+
+/// Account the fees nobody receives go to
+pub const BURN_ACCOUNT: &str = "@burn";
+
+/// Account the funds credited outside the chain come from, such as test funds
+pub const FAUCET_ACCOUNT: &str = "@faucet";
+
+/// Smallest amount the ledger records
+const LEDGER_PRECISION: f64 = 1e-8;
+
+/// File format of an exported ledger
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LedgerFormat {
+    Csv,
+    Jsonl,
+}
+
+impl LedgerFormat {
+    /// Gets the MIME type of the format
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            LedgerFormat::Csv => "text/csv",
+            LedgerFormat::Jsonl => "application/x-ndjson",
+        }
+    }
+
+    /// Gets the usual file extension of the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            LedgerFormat::Csv => "csv",
+            LedgerFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// What moved the amount of a ledger entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    /// Funds credited outside the chain, e.g. by the faucet
+    Faucet,
+
+    /// The amount of a transaction, from its sender to its recipient
+    Transfer,
+
+    /// A locked amount or the funds of an inactive owner, released to the sender that spends them
+    Release,
+
+    /// The fee of a transaction, from its sender or fee payer to the coinbase
+    Fee,
+
+    /// The reward of a block, minted to the coinbase
+    Reward,
+
+    /// What the coinbase paid the miner or the treasury
+    Payout,
+
+    /// The fees and reward of a block the coinbase didn't pay out
+    Burn,
+
+    /// What an account holds beyond what the chain and the faucet account for, a single line
+    Unexplained,
+}
+
+impl EntryKind {
+    /// Gets the name of the kind, as in JSON
+    pub fn name(&self) -> &'static str {
+        match self {
+            EntryKind::Faucet => "faucet",
+            EntryKind::Transfer => "transfer",
+            EntryKind::Release => "release",
+            EntryKind::Fee => "fee",
+            EntryKind::Reward => "reward",
+            EntryKind::Payout => "payout",
+            EntryKind::Burn => "burn",
+            EntryKind::Unexplained => "unexplained",
+        }
+    }
+}
+
+/// One side of a ledger entry
+///
+/// Every entry has a debit line, the account the amount left, and a credit
+/// line, the account it entered, for the same amount. Unexplained amounts
+/// are the exception, with only the line of the account.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct LedgerLine {
+    /// Number of the entry, counting from 0
+    pub entry: u64,
+
+    /// What moved the amount
+    pub kind: EntryKind,
+
+    /// Height of the block, None for funds credited outside the chain
+    pub block_index: Option<u64>,
+
+    /// Hash of the block, None for funds credited outside the chain
+    pub block_hash: Option<String>,
+
+    /// ID of the transaction, None for rewards and burns
+    pub transaction_id: Option<String>,
+
+    /// The account, an address or one of the `@` accounts of the ledger
+    pub account: String,
+
+    /// Amount leaving the account
+    pub debit: f64,
+
+    /// Amount entering the account
+    pub credit: f64,
+}
+
+/// Every balance change of a chain as double-entry lines
+///
+/// Blocks are booked the way they are applied to the accounts. The fees of
+/// a block and its reward, minted from `@mint`, are collected in
+/// `@coinbase`, which pays the miner and the treasury what the coinbase
+/// transaction says, and what is left goes to `@burn`. Deposits of the
+/// faucet are booked from `@faucet` after the block they were made at.
+///
+/// Summing credits less debits per account gives the balances of the
+/// accounts and 0 for `@coinbase`. The balances of all accounts add up to 0
+/// unless an account holds what neither the blocks nor the faucet explain,
+/// which is booked as an `Unexplained` line of its own.
+#[derive(Debug, Clone, Default)]
+pub struct Ledger {
+    /// The lines, two per entry, oldest first
+    pub lines: Vec<LedgerLine>,
+
+    /// Credits less debits per account
+    balances: BTreeMap<String, f64>,
+}
+
+impl Ledger {
+    /// Books the balance changes of a chain
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks of the chain, genesis first
+    /// * `grants` - The deposits of the faucet, see `FaucetGrant`
+    /// * `accounts` - The accounts after the last block
+    /// * `reward_at` - The reward of a block at a height
+    ///
+    /// # Returns
+    ///
+    /// The ledger
+    pub fn new(blocks: &[Block], grants: &[FaucetGrant], accounts: &[Account], reward_at: impl Fn(u64) -> f64) -> Self {
+        let mut ledger = Ledger::default();
+        for block in blocks {
+            ledger.book_block(block, reward_at(block.index));
+            for grant in grants.iter().filter(|grant| grant.height == block.index) {
+                ledger.post(EntryKind::Faucet, None, None, FAUCET_ACCOUNT, &grant.address.0, grant.amount);
+            }
+        }
+
+        for account in accounts {
+            let difference = account.balance - ledger.balance(&account.address.0);
+            ledger.post_unexplained(&account.address.0, difference);
+        }
+
+        ledger
+    }
+
+    /// Gets the lines of amounts nothing explains, see `EntryKind::Unexplained`
+    pub fn unexplained(&self) -> Vec<&LedgerLine> {
+        self.lines.iter().filter(|line| line.kind == EntryKind::Unexplained).collect()
+    }
+
+    /// Gets the credits less the debits of an account
+    pub fn balance(&self, account: &str) -> f64 {
+        self.balances.get(account).copied().unwrap_or(0.0)
+    }
+
+    /// Gets the credits less the debits of every account in the ledger
+    pub fn balances(&self) -> &BTreeMap<String, f64> {
+        &self.balances
+    }
+
+    /// Gets the number of entries
+    pub fn entries(&self) -> u64 {
+        self.lines.last().map(|line| line.entry + 1).unwrap_or(0)
+    }
+
+    /// Renders the ledger in a format
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - The file format
+    ///
+    /// # Returns
+    ///
+    /// The rendered ledger
+    pub fn render(&self, format: LedgerFormat) -> String {
+        match format {
+            LedgerFormat::Csv => self.to_csv(),
+            LedgerFormat::Jsonl => self.to_jsonl(),
+        }
+    }
+
+    /// Renders the ledger as CSV with a header row
+    fn to_csv(&self) -> String {
+        let mut out = String::from("entry,kind,block_index,block_hash,transaction_id,account,debit,credit\n");
+
+        for line in &self.lines {
+            let fields = [
+                line.entry.to_string(),
+                line.kind.name().to_string(),
+                line.block_index.map(|index| index.to_string()).unwrap_or_default(),
+                line.block_hash.clone().unwrap_or_default(),
+                line.transaction_id.clone().unwrap_or_default(),
+                line.account.clone(),
+                line.debit.to_string(),
+                line.credit.to_string(),
+            ];
+            let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+            out.push_str(&row.join(","));
+            out.push('\n');
+        }
+
+        out
+    }
+
+    /// Renders the ledger as one JSON object per line
+    fn to_jsonl(&self) -> String {
+        let mut out = String::new();
+
+        for line in &self.lines {
+            out.push_str(&serde_json::to_string(line).unwrap_or_default());
+            out.push('\n');
+        }
+
+        out
+    }
+
+    /// Books the transactions of a block, in the order they are applied
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block
+    /// * `reward` - The mining reward at the block's height
+    fn book_block(&mut self, block: &Block, reward: f64) {
+        for transaction in &block.transactions {
+            if transaction.is_coinbase() {
+                self.post(EntryKind::Reward, Some(block), None, MINT_ACCOUNT, COINBASE_ACCOUNT, reward);
+                let miner = &transaction.recipient.0;
+                self.post(EntryKind::Payout, Some(block), Some(transaction), COINBASE_ACCOUNT, miner, transaction.amount);
+                if let Some(payout) = &transaction.treasury {
+                    self.post(EntryKind::Payout, Some(block), Some(transaction), COINBASE_ACCOUNT, &payout.address, payout.amount);
+                }
+                continue;
+            }
+
+            let sender = &transaction.sender.0;
+            if matches!(transaction.script, Some(ScriptAction::Unlock { .. })) {
+                self.post(EntryKind::Release, Some(block), Some(transaction), SCRIPT_ADDRESS, sender, transaction.amount);
+            }
+            if let Some(owner) = inheritance::claimed_owner(transaction) {
+                self.post(EntryKind::Release, Some(block), Some(transaction), &owner.0, sender, transaction.amount);
+            }
+            self.post(EntryKind::Transfer, Some(block), Some(transaction), sender, &transaction.recipient.0, transaction.amount);
+            let payer = transaction.fee_payer.as_ref().map(|fee_payer| &fee_payer.address.0).unwrap_or(sender);
+            self.post(EntryKind::Fee, Some(block), Some(transaction), payer, COINBASE_ACCOUNT, transaction.fee);
+        }
+
+        let unpaid = self.balance(COINBASE_ACCOUNT);
+        self.post(EntryKind::Burn, Some(block), None, COINBASE_ACCOUNT, BURN_ACCOUNT, unpaid);
+    }
+
+    /// Books an amount moving from one account to another, unless it is too small to record
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - What moved the amount
+    /// * `block` - The block that moved it, None outside the chain
+    /// * `transaction` - The transaction that moved it, if any
+    /// * `from` - The account debited
+    /// * `to` - The account credited
+    /// * `amount` - The amount, not negative
+    fn post(&mut self, kind: EntryKind, block: Option<&Block>, transaction: Option<&Transaction>, from: &str, to: &str, amount: f64) {
+        if amount < LEDGER_PRECISION {
+            return;
+        }
+
+        let entry = self.entries();
+        for (account, debit, credit) in [(from, amount, 0.0), (to, 0.0, amount)] {
+            self.lines.push(LedgerLine {
+                entry,
+                kind,
+                block_index: block.map(|block| block.index),
+                block_hash: block.map(|block| block.hash.clone()),
+                transaction_id: transaction.map(|transaction| transaction.id.clone()),
+                account: account.to_string(),
+                debit,
+                credit,
+            });
+            *self.balances.entry(account.to_string()).or_default() += credit - debit;
+        }
+    }
+
+    /// Books what an account holds beyond its entries, unless it is too small to record
+    ///
+    /// # Arguments
+    ///
+    /// * `account` - The account
+    /// * `difference` - Its balance less the credits less debits booked so far
+    fn post_unexplained(&mut self, account: &str, difference: f64) {
+        if difference.abs() < LEDGER_PRECISION {
+            return;
+        }
+
+        self.lines.push(LedgerLine {
+            entry: self.entries(),
+            kind: EntryKind::Unexplained,
+            block_index: None,
+            block_hash: None,
+            transaction_id: None,
+            account: account.to_string(),
+            debit: (-difference).max(0.0),
+            credit: difference.max(0.0),
+        });
+        *self.balances.entry(account.to_string()).or_default() += difference;
+    }
+}
+
+/// Escapes a value for a CSV field
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::account::AccountState;
+    use crate::blockchain::crypto::Address;
+    use crate::blockchain::fees::TreasuryPayout;
+    use crate::blockchain::transaction::FeePayer;
+    use crate::blockchain::validation;
+
+    fn address(name: &str) -> Address {
+        Address(name.to_string())
+    }
+
+    #[test]
+    fn test_ledger_balances_every_account() {
+        let mut coinbase = Transaction::new_coinbase(address("miner"), 48.5);
+        coinbase.treasury = Some(TreasuryPayout {
+            address: "treasury".to_string(),
+            amount: 2.0,
+        });
+        let mut sponsored = Transaction::new(address("alice"), address("carol"), 1.0, 0.5, 1);
+        sponsored.fee_payer = Some(FeePayer {
+            address: address("sponsor"),
+            signature: None,
+        });
+        let genesis = Block::new(0, Vec::new(), 0, "0".to_string());
+        let block = Block::new(
+            1,
+            vec![Transaction::new(address("alice"), address("bob"), 10.0, 1.0, 0), sponsored, coinbase],
+            0,
+            genesis.hash.clone(),
+        );
+
+        // The faucet funded Alice, the sponsor got its funds some other way
+        let grant = FaucetGrant {
+            address: address("alice"),
+            amount: 20.0,
+            height: 0,
+            granted_at: chrono::Utc::now(),
+        };
+        let accounts = AccountState::new();
+        accounts.process_mining_reward(&address("alice"), 20.0).unwrap();
+        accounts.process_mining_reward(&address("sponsor"), 1.0).unwrap();
+        validation::apply_block(&accounts, &block).unwrap();
+
+        let ledger = Ledger::new(&[genesis, block.clone()], &[grant], &accounts.get_all_accounts(), |_| 50.0);
+        for account in accounts.get_all_accounts() {
+            assert!((ledger.balance(&account.address.0) - account.balance).abs() < 1e-9, "{}", account.address);
+        }
+        assert_eq!(ledger.balance(FAUCET_ACCOUNT), -20.0);
+        assert_eq!(ledger.balance(MINT_ACCOUNT), -50.0);
+        assert_eq!(ledger.balance(COINBASE_ACCOUNT), 0.0);
+        assert_eq!(ledger.balance(BURN_ACCOUNT), 1.0);
+
+        // What nothing explains is reported, so the value no longer adds up
+        let unexplained = ledger.unexplained();
+        assert_eq!(unexplained.len(), 1);
+        assert_eq!((unexplained[0].account.as_str(), unexplained[0].credit), ("sponsor", 1.0));
+        assert!((ledger.balances().values().sum::<f64>() - 1.0).abs() < 1e-9);
+
+        // Every other entry is one debit and one credit of the same amount
+        let paired: Vec<&LedgerLine> = ledger.lines.iter().filter(|line| line.kind != EntryKind::Unexplained).collect();
+        assert_eq!(paired.len() as u64, (ledger.entries() - 1) * 2);
+        for pair in paired.chunks(2) {
+            assert_eq!(pair[0].entry, pair[1].entry);
+            assert_eq!(pair[0].debit, pair[1].credit);
+        }
+        assert_eq!(ledger.lines[0].kind, EntryKind::Faucet);
+        assert_eq!(ledger.lines[0].block_index, None);
+        let fee = ledger.lines.iter().find(|line| line.kind == EntryKind::Fee && line.debit == 0.5).unwrap();
+        assert_eq!(fee.account, "sponsor");
+
+        let csv = ledger.render(LedgerFormat::Csv);
+        assert_eq!(csv.lines().count(), ledger.lines.len() + 1);
+        assert!(csv.contains(&format!(",burn,1,{},,@burn,0,1\n", block.hash)));
+        assert!(csv.ends_with(",unexplained,,,,sponsor,0,1\n"));
+        let jsonl = ledger.render(LedgerFormat::Jsonl);
+        let parsed: Vec<LedgerLine> = jsonl.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(parsed, ledger.lines);
+    }
+}
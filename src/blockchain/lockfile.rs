@@ -0,0 +1,97 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use super::storage::StorageError;
+
+/// Name of the lock file in the data directory
+pub const LOCK_FILE: &str = "node.lock";
+
+/// An exclusive advisory lock on a data directory, held until dropped
+///
+/// sled locks its database too, but only after retrying for a while, and a
+/// node that can't open its storage carries on in memory. Taking this lock
+/// first makes a second process on the same directory fail right away with
+/// an error naming the process holding it. The operating system releases
+/// the lock when the process exits, so a crash never leaves it behind.
+#[derive(Debug)]
+pub struct DataDirLock {
+    /// The locked file, unlocked when closed
+    _file: File,
+
+    /// Path of the lock file
+    path: PathBuf,
+}
+
+impl DataDirLock {
+    /// Locks a data directory, creating it if needed
+    ///
+    /// # Arguments
+    ///
+    /// * `data_dir` - The data directory
+    ///
+    /// # Returns
+    ///
+    /// Result with the lock, or StorageError::DirectoryLocked if another
+    /// process holds it
+    pub fn acquire<P: AsRef<Path>>(data_dir: P) -> Result<Self, StorageError> {
+        std::fs::create_dir_all(data_dir.as_ref())?;
+        let path = data_dir.as_ref().join(LOCK_FILE);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                let mut holder = String::new();
+                let holder = match file.read_to_string(&mut holder) {
+                    Ok(_) if !holder.trim().is_empty() => format!("pid {}", holder.trim()),
+                    _ => "unknown process".to_string(),
+                };
+                return Err(StorageError::DirectoryLocked {
+                    path: data_dir.as_ref().display().to_string(),
+                    holder,
+                });
+            }
+            Err(TryLockError::Error(err)) => return Err(err.into()),
+        }
+
+        // Tell whoever finds the directory locked which process holds it
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        writeln!(file, "{}", std::process::id())?;
+
+        Ok(DataDirLock { _file: file, path })
+    }
+
+    /// Gets the path of the lock file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_second_lock_fails_until_released() {
+        let data_dir = tempfile::tempdir().unwrap();
+
+        let lock = DataDirLock::acquire(data_dir.path()).unwrap();
+        assert!(lock.path().exists());
+        match DataDirLock::acquire(data_dir.path()) {
+            Err(StorageError::DirectoryLocked { holder, .. }) => {
+                assert_eq!(holder, format!("pid {}", std::process::id()));
+            }
+            other => panic!("expected the directory to be locked, got {:?}", other),
+        }
+
+        drop(lock);
+        DataDirLock::acquire(data_dir.path()).unwrap();
+    }
+}
@@ -0,0 +1,71 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Which writers are paused while the node is under maintenance
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct MaintenanceStatus {
+    /// Whether new transactions are rejected
+    pub transactions_paused: bool,
+
+    /// Whether block production is paused
+    pub mining_paused: bool,
+
+    /// Why the node is under maintenance
+    pub reason: Option<String>,
+
+    /// Timestamp when maintenance started
+    #[schema(value_type = Option<String>, example = "2023-01-01T12:00:00Z")]
+    pub since: Option<DateTime<Utc>>,
+}
+
+impl MaintenanceStatus {
+    /// Creates the status for a pause
+    ///
+    /// # Arguments
+    ///
+    /// * `transactions` - Whether to pause transaction admission
+    /// * `mining` - Whether to pause block production
+    /// * `reason` - Why the node is paused
+    ///
+    /// # Returns
+    ///
+    /// The new maintenance status
+    pub fn paused(transactions: bool, mining: bool, reason: &str) -> Self {
+        MaintenanceStatus {
+            transactions_paused: transactions,
+            mining_paused: mining,
+            reason: Some(reason.to_string()),
+            since: Some(Utc::now()),
+        }
+    }
+
+    /// Checks if anything is paused
+    pub fn is_active(&self) -> bool {
+        self.transactions_paused || self.mining_paused
+    }
+
+    /// Gets the reason to report to rejected writers
+    pub fn reason(&self) -> String {
+        self.reason
+            .clone()
+            .unwrap_or_else(|| "Node is under maintenance".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_paused_status() {
+        assert!(!MaintenanceStatus::default().is_active());
+
+        let status = MaintenanceStatus::paused(true, false, "snapshot");
+        assert!(status.is_active());
+        assert!(status.transactions_paused);
+        assert!(!status.mining_paused);
+        assert_eq!(status.reason(), "snapshot");
+        assert!(status.since.is_some());
+    }
+}
@@ -0,0 +1,324 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+
+use super::observers::ChainObserver;
+use super::transaction::Transaction;
+
+/// Most transactions of one sender that may wait for an earlier nonce
+pub const MAX_QUEUED_PER_SENDER: usize = 64;
+
+/// Most transaction IDs the node remembers having seen
+pub const MAX_SEEN_TRANSACTIONS: usize = 50_000;
+
+/// How a submitted transaction entered the mempool
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Admission {
+    /// It was added to the pending transactions
+    Accepted,
+
+    /// It took the place of the pending transaction with this ID, which had the same nonce and a lower fee
+    Replaced(String),
+
+    /// It waits for the transactions with the nonces before it, held in memory only
+    Queued,
+
+    /// The node already has it, nothing changed
+    Known,
+}
+
+/// IDs of the transactions the node recently took into its mempool
+///
+/// Peers relay every transaction they admit, so the same transaction comes
+/// back from several of them. Remembering the IDs lets the node drop those
+/// copies without checking them again or relaying them once more. The
+/// oldest IDs are forgotten past `MAX_SEEN_TRANSACTIONS`.
+#[derive(Debug, Default)]
+pub struct SeenTransactions {
+    seen: Mutex<(HashSet<String>, VecDeque<String>)>,
+}
+
+impl SeenTransactions {
+    /// Creates an empty cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remembers a transaction
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction_id` - ID of the transaction
+    pub fn insert(&self, transaction_id: &str) {
+        let mut seen = self.seen.lock().unwrap();
+        let (ids, order) = &mut *seen;
+        if !ids.insert(transaction_id.to_string()) {
+            return;
+        }
+        order.push_back(transaction_id.to_string());
+        if order.len() > MAX_SEEN_TRANSACTIONS {
+            if let Some(oldest) = order.pop_front() {
+                ids.remove(&oldest);
+            }
+        }
+    }
+
+    /// Gets whether a transaction was seen
+    pub fn contains(&self, transaction_id: &str) -> bool {
+        self.seen.lock().unwrap().0.contains(transaction_id)
+    }
+
+    /// Gets the number of remembered transactions
+    pub fn len(&self) -> usize {
+        self.seen.lock().unwrap().1.len()
+    }
+
+    /// Gets whether no transactions are remembered
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A pending transaction and when it entered the mempool
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PendingTransaction {
+    /// The transaction
+    #[serde(flatten)]
+    pub transaction: Transaction,
+
+    /// When the node took it into its mempool
+    #[schema(value_type = String, example = "2023-01-01T12:00:00Z")]
+    pub received_at: DateTime<Utc>,
+}
+
+/// When the pending transactions entered the mempool
+///
+/// The times are held in memory only: transactions recovered from the
+/// mempool journal on start, or returned to the mempool by a reorganization,
+/// count as received when they are first looked at. Times of transactions
+/// that left the mempool are dropped the next time the pending ones are
+/// looked at.
+#[derive(Debug, Default)]
+pub struct Arrivals {
+    received: Mutex<HashMap<String, DateTime<Utc>>>,
+}
+
+impl Arrivals {
+    /// Creates an empty set of arrival times
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pairs the pending transactions with when they were received
+    ///
+    /// # Arguments
+    ///
+    /// * `pending` - The pending transactions
+    /// * `now` - The current time, for transactions without a time yet
+    ///
+    /// # Returns
+    ///
+    /// The pending transactions with their arrival times, in the same order
+    pub fn entries(&self, pending: &[Transaction], now: DateTime<Utc>) -> Vec<PendingTransaction> {
+        let mut received = self.received.lock().unwrap();
+        let ids: HashSet<&str> = pending.iter().map(|transaction| transaction.id.as_str()).collect();
+        received.retain(|id, _| ids.contains(id.as_str()));
+
+        pending
+            .iter()
+            .map(|transaction| PendingTransaction {
+                transaction: transaction.clone(),
+                received_at: *received.entry(transaction.id.clone()).or_insert(now),
+            })
+            .collect()
+    }
+
+    /// Picks the pending transactions received longer ago than a maximum age
+    ///
+    /// # Arguments
+    ///
+    /// * `pending` - The pending transactions
+    /// * `max_age` - How long a transaction may wait in the mempool
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    ///
+    /// The expired transactions with their arrival times, in admission order
+    pub fn expired(&self, pending: &[Transaction], max_age: Duration, now: DateTime<Utc>) -> Vec<PendingTransaction> {
+        self.entries(pending, now)
+            .into_iter()
+            .filter(|entry| now - entry.received_at > max_age)
+            .collect()
+    }
+
+    /// Gets when a transaction was received
+    pub fn get(&self, transaction_id: &str) -> Option<DateTime<Utc>> {
+        self.received.lock().unwrap().get(transaction_id).copied()
+    }
+}
+
+impl ChainObserver for Arrivals {
+    fn on_tx_admitted(&self, transaction: &Transaction) {
+        self.received
+            .lock()
+            .unwrap()
+            .entry(transaction.id.clone())
+            .or_insert_with(Utc::now);
+    }
+}
+
+/// A change to the pending transactions since the last mempool snapshot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum MempoolJournalEntry {
+    /// A transaction was admitted
+    Admitted(Box<Transaction>),
+
+    /// A transaction left the mempool, e.g. because it was mined
+    Removed(String),
+
+    /// A transaction took the place of the pending transaction with this ID
+    Replaced(String, Box<Transaction>),
+}
+
+/// A block being mined, persisted until it is on the chain
+///
+/// Should the process die while sealing, or before the mempool journal
+/// records that the block took its transactions, the transactions taken go
+/// back to the mempool on the next start instead of being lost.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MiningTemplate {
+    /// Height of the block
+    pub height: u64,
+
+    /// Hash of the block it follows
+    pub previous_hash: String,
+
+    /// Transactions of the block, the coinbase last
+    pub transactions: Vec<Transaction>,
+
+    /// IDs of the transactions taken out of the mempool for it
+    pub taken: Vec<String>,
+}
+
+impl MiningTemplate {
+    /// Gets the transactions taken out of the mempool, in admission order
+    pub fn taken_transactions(&self) -> Vec<Transaction> {
+        self.transactions
+            .iter()
+            .filter(|transaction| self.taken.contains(&transaction.id))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Recovers the pending transactions from a snapshot and the journal written after it
+///
+/// # Arguments
+///
+/// * `snapshot` - The pending transactions of the last snapshot
+/// * `journal` - The journal entries written since the snapshot, oldest first
+///
+/// # Returns
+///
+/// The pending transactions in admission order
+pub fn replay(snapshot: Vec<Transaction>, journal: Vec<MempoolJournalEntry>) -> Vec<Transaction> {
+    let mut pending = snapshot;
+
+    for entry in journal {
+        match entry {
+            MempoolJournalEntry::Admitted(transaction) => {
+                if !pending.iter().any(|tx| tx.id == transaction.id) {
+                    pending.push(*transaction);
+                }
+            }
+            MempoolJournalEntry::Removed(id) => pending.retain(|tx| tx.id != id),
+            // In place, so the sender's nonces stay in order
+            MempoolJournalEntry::Replaced(id, transaction) => {
+                if let Some(replaced) = pending.iter_mut().find(|tx| tx.id == id) {
+                    *replaced = *transaction;
+                }
+            }
+        }
+    }
+
+    pending
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::crypto::Address;
+
+    fn transaction(nonce: u64) -> Transaction {
+        Transaction::new(
+            Address("sender".to_string()),
+            Address("recipient".to_string()),
+            1.0,
+            0.1,
+            nonce,
+        )
+    }
+
+    #[test]
+    fn test_replay_applies_journal_to_snapshot() {
+        let first = transaction(0);
+        let second = transaction(1);
+        let third = transaction(2);
+
+        let pending = replay(
+            vec![first.clone(), second.clone()],
+            vec![
+                MempoolJournalEntry::Removed(first.id.clone()),
+                MempoolJournalEntry::Admitted(Box::new(third.clone())),
+                // Entries already in the snapshot are not duplicated
+                MempoolJournalEntry::Admitted(Box::new(second.clone())),
+            ],
+        );
+        let bumped = Transaction::new(second.sender.clone(), second.recipient.clone(), 1.0, 0.5, 1);
+        let pending = replay(pending, vec![MempoolJournalEntry::Replaced(second.id.clone(), Box::new(bumped.clone()))]);
+
+        let ids: Vec<String> = pending.into_iter().map(|tx| tx.id).collect();
+        assert_eq!(ids, vec![bumped.id, third.id]);
+    }
+
+    #[test]
+    fn test_seen_transactions_forget_the_oldest() {
+        let seen = SeenTransactions::new();
+        seen.insert("first");
+        seen.insert("first");
+        assert_eq!(seen.len(), 1);
+
+        for index in 0..MAX_SEEN_TRANSACTIONS {
+            seen.insert(&index.to_string());
+        }
+        assert!(!seen.contains("first"));
+        assert!(seen.contains("0") && seen.contains(&(MAX_SEEN_TRANSACTIONS - 1).to_string()));
+        assert_eq!(seen.len(), MAX_SEEN_TRANSACTIONS);
+    }
+
+    #[test]
+    fn test_old_transactions_expire() {
+        let arrivals = Arrivals::new();
+        let old = transaction(0);
+        let new = transaction(1);
+        arrivals.on_tx_admitted(&old);
+        let start = arrivals.get(&old.id).unwrap();
+
+        // A transaction without a time, e.g. recovered on start, is received now
+        let later = start + Duration::hours(2);
+        let pending = vec![old.clone(), new.clone()];
+        let entries = arrivals.entries(&pending, later);
+        assert_eq!((entries[0].received_at, entries[1].received_at), (start, later));
+
+        let expired = arrivals.expired(&pending, Duration::hours(1), later + Duration::minutes(1));
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].transaction.id, old.id);
+
+        // Times of transactions that left the mempool are dropped
+        arrivals.entries(std::slice::from_ref(&new), later);
+        assert!(arrivals.get(&old.id).is_none());
+    }
+}
@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+use super::block::Block;
+use super::canonical;
+
+/// Side of the running hash a sibling is combined on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// A sibling hash on the path from a leaf to the root
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ProofStep {
+    /// Hex encoded hash of the sibling
+    pub hash: String,
+
+    /// Whether the sibling goes left or right of the running hash
+    pub side: Side,
+}
+
+/// Proof that a leaf is part of a merkle tree
+///
+/// Leaves are hashed as `SHA-256(0x00 || data)` and inner nodes as
+/// `SHA-256(0x01 || left || right)`, so a leaf can never pass for a node.
+/// A node without a sibling moves up a level unchanged.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct MerkleProof {
+    /// Position of the leaf
+    pub index: usize,
+
+    /// Hex encoded hash of the leaf
+    pub leaf: String,
+
+    /// Siblings from the leaf up to the root
+    pub path: Vec<ProofStep>,
+
+    /// Hex encoded root of the tree
+    pub root: String,
+}
+
+impl MerkleProof {
+    /// Checks that the path leads from the leaf to the root
+    pub fn verify(&self) -> bool {
+        let decode = |hash: &str| -> Option<[u8; 32]> { hex::decode(hash).ok()?.try_into().ok() };
+
+        let mut running = match decode(&self.leaf) {
+            Some(leaf) => leaf,
+            None => return false,
+        };
+        for step in &self.path {
+            let sibling = match decode(&step.hash) {
+                Some(sibling) => sibling,
+                None => return false,
+            };
+            running = match step.side {
+                Side::Left => node_hash(&sibling, &running),
+                Side::Right => node_hash(&running, &sibling),
+            };
+        }
+
+        hex::encode(running) == self.root
+    }
+}
+
+/// Hashes the data of a leaf
+pub fn leaf_hash(data: &[u8]) -> [u8; 32] {
+    Sha256::new().chain_update([0x00]).chain_update(data).finalize().into()
+}
+
+/// Hashes two child nodes
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    Sha256::new()
+        .chain_update([0x01])
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+/// Combines each pair of nodes of a level
+fn next_level(level: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node_hash(left, right),
+            [single] => *single,
+            _ => unreachable!("chunks of two"),
+        })
+        .collect()
+}
+
+/// Computes the root of the tree over leaf hashes
+///
+/// # Arguments
+///
+/// * `leaves` - The leaf hashes, at least one
+///
+/// # Returns
+///
+/// The root, None without leaves
+pub fn root(leaves: &[[u8; 32]]) -> Option<[u8; 32]> {
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level.first().copied()
+}
+
+/// Builds the proof that one leaf is part of the tree
+///
+/// # Arguments
+///
+/// * `leaves` - The leaf hashes
+/// * `index` - Position of the leaf to prove
+///
+/// # Returns
+///
+/// The proof, None if there is no leaf at the position
+pub fn prove(leaves: &[[u8; 32]], index: usize) -> Option<MerkleProof> {
+    let leaf = *leaves.get(index)?;
+
+    let mut path = Vec::new();
+    let mut level = leaves.to_vec();
+    let mut position = index;
+    while level.len() > 1 {
+        let sibling = position ^ 1;
+        if let Some(hash) = level.get(sibling) {
+            path.push(ProofStep {
+                hash: hex::encode(hash),
+                side: if sibling < position { Side::Left } else { Side::Right },
+            });
+        }
+        level = next_level(&level);
+        position /= 2;
+    }
+
+    Some(MerkleProof {
+        index,
+        leaf: hex::encode(leaf),
+        path,
+        root: hex::encode(level[0]),
+    })
+}
+
+/// Gets the leaf hashes of the transactions of a block
+///
+/// Each leaf is the canonical JSON the block hash covers for the transaction,
+/// so a leaf can be checked against the raw bytes of the block.
+pub fn transaction_leaves(block: &Block) -> Vec<[u8; 32]> {
+    block
+        .transactions
+        .iter()
+        .map(|transaction| leaf_hash(&canonical::to_vec(&transaction.hash_json())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proofs_lead_to_the_root() {
+        for count in 1..=9 {
+            let leaves: Vec<[u8; 32]> = (0..count).map(|leaf: u8| leaf_hash(&[leaf])).collect();
+            let expected = hex::encode(root(&leaves).unwrap());
+
+            for index in 0..leaves.len() {
+                let proof = prove(&leaves, index).unwrap();
+                assert_eq!(proof.root, expected);
+                assert!(proof.verify(), "leaf {} of {}", index, count);
+
+                // Any other leaf or a flipped side breaks the proof
+                let mut forged = proof.clone();
+                forged.leaf = hex::encode(leaf_hash(&[99]));
+                assert!(!forged.verify());
+                if let Some(step) = forged.path.first_mut() {
+                    let mut flipped = proof.clone();
+                    flipped.path[0].side = match step.side {
+                        Side::Left => Side::Right,
+                        Side::Right => Side::Left,
+                    };
+                    assert!(!flipped.verify());
+                }
+            }
+            assert!(prove(&leaves, leaves.len()).is_none());
+        }
+
+        // A single leaf is its own root, an empty tree has none
+        assert_eq!(root(&[leaf_hash(b"x")]), Some(leaf_hash(b"x")));
+        assert_eq!(root(&[]), None);
+    }
+}
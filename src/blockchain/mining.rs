@@ -0,0 +1,141 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Tells a proof of work search to give up
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a token that is not cancelled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks the search holding the token to stop
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Checks whether the search should stop
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// The proof of work searches in progress, by the block they build on
+///
+/// Once another block takes the tip, a search building on the old tip can
+/// only end in a block at a height that is already taken, so it is
+/// cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct MiningSearches {
+    searches: Arc<Mutex<Vec<(String, CancellationToken)>>>,
+}
+
+impl MiningSearches {
+    /// Creates an empty set of searches
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a search for a block on top of another one
+    ///
+    /// # Arguments
+    ///
+    /// * `previous_hash` - Hash of the block the search builds on
+    ///
+    /// # Returns
+    ///
+    /// The search, which leaves the set when dropped
+    pub fn start(&self, previous_hash: &str) -> MiningSearch {
+        let token = CancellationToken::new();
+        self.searches
+            .lock()
+            .unwrap()
+            .push((previous_hash.to_string(), token.clone()));
+
+        MiningSearch {
+            searches: self.clone(),
+            token,
+        }
+    }
+
+    /// Cancels the searches not building on the tip
+    ///
+    /// # Arguments
+    ///
+    /// * `tip` - Hash of the new tip
+    ///
+    /// # Returns
+    ///
+    /// The number of searches cancelled
+    pub fn tip_changed(&self, tip: &str) -> usize {
+        let searches = self.searches.lock().unwrap();
+        let mut cancelled = 0;
+        for (previous_hash, token) in searches.iter() {
+            if previous_hash != tip && !token.is_cancelled() {
+                token.cancel();
+                cancelled += 1;
+            }
+        }
+        cancelled
+    }
+
+    /// Gets the number of searches in progress
+    pub fn len(&self) -> usize {
+        self.searches.lock().unwrap().len()
+    }
+
+    /// Checks whether no search is in progress
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A proof of work search in progress, see `MiningSearches::start`
+#[derive(Debug)]
+pub struct MiningSearch {
+    searches: MiningSearches,
+    token: CancellationToken,
+}
+
+impl MiningSearch {
+    /// Gets the token the search checks
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+}
+
+impl Drop for MiningSearch {
+    fn drop(&mut self) {
+        self.searches
+            .searches
+            .lock()
+            .unwrap()
+            .retain(|(_, token)| !Arc::ptr_eq(&token.0, &self.token.0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_searches_on_an_old_tip_are_cancelled() {
+        let searches = MiningSearches::new();
+        let stale = searches.start("a");
+        let current = searches.start("b");
+        assert_eq!(searches.len(), 2);
+
+        assert_eq!(searches.tip_changed("b"), 1);
+        assert!(stale.token().is_cancelled());
+        assert!(!current.token().is_cancelled());
+
+        // A finished search leaves the set
+        drop(stale);
+        assert_eq!(searches.len(), 1);
+        assert_eq!(searches.tip_changed("c"), 1);
+        drop(current);
+        assert!(searches.is_empty());
+    }
+}
@@ -2,18 +2,140 @@
 //
 // This module contains the core blockchain implementation including:
 // - Block structure
-// - Blockchain structure
+// - Bloom filters of the addresses each block touches
+// - Blockchain structure, the node around the chain
+// - Error codes, retryability and context shared by the errors of the node
+// - Node builder assembling a node from its parts
+// - Genesis blocks and magic bytes of networks
+// - Peers found by the network service
+// - Chain state of blocks and accounts without IO
+// - Validation rules as free functions over the chain state
 // - Transaction structure
+// - Canonical JSON for hashing and signing
+// - Block and transaction version rules and upgrades of stored records
 // - Cryptography utilities
+// - Domain-separated signing contexts
 // - Account state
+// - Faucet deposits made outside the chain
+// - UTXO ledger, an educational alternative to the account model
+// - Confidential UTXO amounts with Pedersen commitments (experimental)
+// - Stealth addresses paid through one-time addresses (experimental)
 // - Proof of work algorithm
+// - Payment tracking for merchants
+// - Withdrawal queue with approval workflow
+// - Recurring payments paid from session keys on schedule
+// - Audit log
+// - Keystore of node-held and watch-only wallets
+// - Encrypted keystore backups with recovery codes
+// - Rotating node identity and webhook signing keys
+// - Private transaction labels
+// - API usage per API key with monthly quotas
+// - Secondary indexes for transaction queries
+// - On-chain name registrations
+// - Document anchoring with merkle inclusion proofs
+// - Permissioned mode with allowed senders and miners
+// - Governance proposals and votes on chain parameters
+// - Fee policies and supply audits
+// - Double-entry ledger of every balance change
+// - Protocol treasury and its scheduled disbursements
+// - Script locks, amounts spendable under a small condition language
+// - Session keys signing transfers of an account within limits
+// - Inheritance switches letting a beneficiary claim the funds of an inactive account
+// - Transaction graph export
+// - Chain activity reports
+// - Node health dashboard for monitoring UIs
+// - Chain events such as reorganizations
+// - Competing branches kept for the fork choice
+// - Historical chain states rebuilt from checkpoints
+// - Observers following the head of the chain
+// - Validation hooks adding custom admission rules
+// - Proof of work searches, cancelled when another block takes the tip
+// - Maintenance mode
+// - Mempool crash-recovery journal and arrival times of pending transactions
+// - Rebroadcasts of stuck local transactions
+// - Storage migration
+// - Startup consistency check and repair of stored chains
+// - Scheduled storage snapshots with retention
+// - Chain comparison between nodes
+// - Full replay validation of stored chains
+// - Compression of stored blocks and transactions
+// - Storage writes on a dedicated thread
+// - Advisory lock of the data directory against a second node
+// - Difficulty and emission simulation
+// - Double-spend attack simulation
+// - Emission schedule reports
+// - Deterministic test networks generated from a seed
+// - Golden wire format fixtures, for tests
 
 pub mod block;
+pub mod bloom;
 pub mod chain;
+pub mod errors;
+pub mod builder;
+pub mod genesis;
+pub mod peers;
+pub mod state;
+pub mod validation;
 pub mod crypto;
+pub mod signing;
 pub mod transaction;
+pub mod canonical;
+pub mod versioning;
 pub mod account;
+pub mod faucet;
+pub mod utxo;
+#[cfg(feature = "confidential")]
+pub mod confidential;
+#[cfg(feature = "stealth")]
+pub mod stealth;
 pub mod storage;
+pub mod storage_writer;
+pub mod lockfile;
+pub mod payments;
+pub mod withdrawals;
+pub mod schedules;
+pub mod audit;
+pub mod keystore;
+pub mod backup;
+pub mod node_keys;
+pub mod labels;
+pub mod usage;
+pub mod index;
+pub mod names;
+pub mod anchors;
+pub mod merkle;
+pub mod permissions;
+pub mod governance;
+pub mod fees;
+pub mod ledger;
+pub mod economics;
+pub mod dashboard;
+pub mod treasury;
+pub mod scripts;
+pub mod sessions;
+pub mod inheritance;
+pub mod graph;
+pub mod reports;
+pub mod events;
+pub mod forks;
+pub mod history;
+pub mod observers;
+pub mod hooks;
+pub mod mining;
+pub mod maintenance;
+pub mod mempool;
+pub mod rebroadcast;
+pub mod migration;
+pub mod consistency;
+pub mod snapshots;
+pub mod diff;
+pub mod verify;
+pub mod compression;
+pub mod attack;
+pub mod simulation;
+pub mod testnet;
+#[cfg(test)]
+mod golden;
 
 // Re-export main components for easier access
 pub use block::Block;
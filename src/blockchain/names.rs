@@ -0,0 +1,311 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::anchors::ANCHOR_ADDRESS;
+use super::block::Block;
+use super::crypto::Address;
+use super::inheritance::INHERITANCE_ADDRESS;
+use super::permissions::PERMISSIONS_ADDRESS;
+use super::scripts::SCRIPT_ADDRESS;
+use super::sessions::SESSIONS_ADDRESS;
+use super::treasury::TREASURY_ADDRESS;
+use super::transaction::Transaction;
+
+/// Address registrations are paid to, which has no key so the fees are burned
+pub const NAME_REGISTRY_ADDRESS: &str = "names";
+
+/// Amount a registration or renewal pays to the registry
+pub const NAME_FEE: f64 = 1.0;
+
+/// Number of blocks a registration or renewal lasts
+pub const NAME_REGISTRATION_BLOCKS: u64 = 10_000;
+
+/// Shortest allowed name
+pub const MIN_NAME_LENGTH: usize = 3;
+
+/// Longest allowed name
+pub const MAX_NAME_LENGTH: usize = 32;
+
+/// Errors that can occur with names
+#[derive(Debug, Error)]
+pub enum NameError {
+    #[error("Invalid name: {0}")]
+    InvalidName(String),
+
+    #[error("Invalid registration: {0}")]
+    InvalidRegistration(String),
+
+    #[error("Name {name} is registered to {owner} until block {expires_at}")]
+    Taken { name: String, owner: String, expires_at: u64 },
+
+    #[error("Name {0} is already being registered by a pending transaction")]
+    Pending(String),
+
+    #[error("Name {0} is not registered")]
+    NotRegistered(String),
+}
+
+/// A name and the address it points to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct NameRecord {
+    /// The name
+    pub name: String,
+
+    /// The address that registered the name and that it resolves to
+    #[schema(value_type = String)]
+    pub owner: Address,
+
+    /// Height of the block the current registration started in
+    pub registered_at: u64,
+
+    /// Height of the first block the name is free again in
+    pub expires_at: u64,
+}
+
+impl NameRecord {
+    /// Checks if the name is still registered at a height
+    pub fn is_active(&self, height: u64) -> bool {
+        height < self.expires_at
+    }
+}
+
+/// Checks that a name is well formed
+///
+/// Names are 3 to 32 lowercase ASCII letters, digits and inner hyphens, so
+/// they can never be mistaken for an address. The keyless addresses of the
+/// registry, anchors, permissions, the treasury and script locks are reserved.
+///
+/// # Arguments
+///
+/// * `name` - The name to check
+///
+/// # Returns
+///
+/// Ok(()) if the name can be registered
+pub fn validate_name(name: &str) -> Result<(), NameError> {
+    if name.len() < MIN_NAME_LENGTH || name.len() > MAX_NAME_LENGTH {
+        return Err(NameError::InvalidName(format!(
+            "{} must be between {} and {} characters",
+            name, MIN_NAME_LENGTH, MAX_NAME_LENGTH
+        )));
+    }
+
+    let allowed = |byte: u8| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'-';
+    if !name.bytes().all(allowed) || name.starts_with('-') || name.ends_with('-') {
+        return Err(NameError::InvalidName(format!(
+            "{} may only contain lowercase letters, digits and inner hyphens",
+            name
+        )));
+    }
+
+    if [
+        NAME_REGISTRY_ADDRESS,
+        ANCHOR_ADDRESS,
+        PERMISSIONS_ADDRESS,
+        TREASURY_ADDRESS,
+        SCRIPT_ADDRESS,
+        SESSIONS_ADDRESS,
+        INHERITANCE_ADDRESS,
+    ]
+    .contains(&name)
+    {
+        return Err(NameError::InvalidName(format!("{} is reserved", name)));
+    }
+
+    Ok(())
+}
+
+/// Checks that a transaction carrying a name is a well formed registration
+///
+/// # Arguments
+///
+/// * `transaction` - The transaction to check
+///
+/// # Returns
+///
+/// Ok(()) if the transaction carries no name or registers it properly
+pub fn check_registration(transaction: &Transaction) -> Result<(), NameError> {
+    let name = match &transaction.name {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+
+    validate_name(name)?;
+
+    if transaction.is_coinbase() {
+        return Err(NameError::InvalidRegistration("A coinbase transaction can't register a name".to_string()));
+    }
+    if transaction.recipient.0 != NAME_REGISTRY_ADDRESS {
+        return Err(NameError::InvalidRegistration(format!(
+            "A registration must be paid to {}",
+            NAME_REGISTRY_ADDRESS
+        )));
+    }
+    if transaction.amount < NAME_FEE {
+        return Err(NameError::InvalidRegistration(format!(
+            "A registration must pay at least {}",
+            NAME_FEE
+        )));
+    }
+
+    Ok(())
+}
+
+/// The names registered on the chain, updated as each block is committed
+///
+/// The registry is a function of the chain: a registration takes effect in
+/// the block that includes it if the name is free or expired, and a
+/// registration by the current owner renews the name for another
+/// `NAME_REGISTRATION_BLOCKS`. Any other registration still pays the fee but
+/// changes nothing, which admission to the mempool prevents.
+#[derive(Debug, Default)]
+pub struct NameRegistry {
+    names: Mutex<HashMap<String, NameRecord>>,
+}
+
+impl NameRegistry {
+    /// Creates an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies the registrations of a committed block
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block that was added to the chain
+    pub fn record_block(&self, block: &Block) {
+        let mut names = self.names.lock().unwrap();
+
+        for transaction in &block.transactions {
+            let name = match &transaction.name {
+                Some(name) if check_registration(transaction).is_ok() => name,
+                _ => continue,
+            };
+
+            match names.get_mut(name) {
+                Some(record) if record.is_active(block.index) => {
+                    if record.owner == transaction.sender {
+                        record.expires_at += NAME_REGISTRATION_BLOCKS;
+                    }
+                }
+                _ => {
+                    names.insert(
+                        name.clone(),
+                        NameRecord {
+                            name: name.clone(),
+                            owner: transaction.sender.clone(),
+                            registered_at: block.index,
+                            expires_at: block.index + NAME_REGISTRATION_BLOCKS,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    /// Replaces the registry with the registrations of a chain
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks of the chain
+    pub fn rebuild(&self, blocks: &[Block]) {
+        self.names.lock().unwrap().clear();
+
+        for block in blocks {
+            self.record_block(block);
+        }
+    }
+
+    /// Looks up a name
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name to look up
+    /// * `height` - The height to look it up at
+    ///
+    /// # Returns
+    ///
+    /// The registration, if the name is registered at that height
+    pub fn lookup(&self, name: &str, height: u64) -> Option<NameRecord> {
+        self.names
+            .lock()
+            .unwrap()
+            .get(name)
+            .filter(|record| record.is_active(height))
+            .cloned()
+    }
+
+    /// Checks that an address may register a name in a block
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name to register
+    /// * `owner` - The address registering it
+    /// * `height` - The height of the block the registration would be in
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if the name is free there or already belongs to the address
+    pub fn check_available(&self, name: &str, owner: &Address, height: u64) -> Result<(), NameError> {
+        match self.lookup(name, height) {
+            Some(record) if &record.owner != owner => Err(NameError::Taken {
+                name: record.name,
+                owner: record.owner.0,
+                expires_at: record.expires_at,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registration(sender: &str, name: &str, amount: f64) -> Transaction {
+        Transaction::new(Address(sender.to_string()), Address(NAME_REGISTRY_ADDRESS.to_string()), amount, 0.1, 0)
+            .with_name(name)
+    }
+
+    fn block(index: u64, transactions: Vec<Transaction>) -> Block {
+        Block::new(index, transactions, 0, "0".to_string())
+    }
+
+    #[test]
+    fn test_registrations_renewals_and_expiry() {
+        assert!(validate_name("alice-42").is_ok());
+        for name in ["al", "Alice", "-alice", "alice-", "al ice", NAME_REGISTRY_ADDRESS, ANCHOR_ADDRESS, PERMISSIONS_ADDRESS, TREASURY_ADDRESS, &"a".repeat(33)] {
+            assert!(matches!(validate_name(name), Err(NameError::InvalidName(_))), "{}", name);
+        }
+        assert!(check_registration(&registration("alice", "alice", NAME_FEE / 2.0)).is_err());
+
+        let registry = NameRegistry::new();
+        registry.record_block(&block(1, vec![registration("alice", "alice", NAME_FEE)]));
+
+        // Someone else can't take a registered name, the owner renews it
+        let expires_at = 1 + NAME_REGISTRATION_BLOCKS;
+        assert!(matches!(
+            registry.check_available("alice", &Address("mallory".to_string()), 5),
+            Err(NameError::Taken { expires_at: at, .. }) if at == expires_at
+        ));
+        registry.record_block(&block(5, vec![registration("mallory", "alice", NAME_FEE)]));
+        assert_eq!(registry.lookup("alice", 5).unwrap().owner.0, "alice");
+        registry.record_block(&block(6, vec![registration("alice", "alice", NAME_FEE)]));
+        assert_eq!(registry.lookup("alice", 6).unwrap().expires_at, expires_at + NAME_REGISTRATION_BLOCKS);
+
+        // Once expired the name is free for anyone
+        let free_at = expires_at + NAME_REGISTRATION_BLOCKS;
+        assert!(registry.lookup("alice", free_at).is_none());
+        registry.check_available("alice", &Address("mallory".to_string()), free_at).unwrap();
+        registry.record_block(&block(free_at, vec![registration("mallory", "alice", NAME_FEE)]));
+        assert_eq!(registry.lookup("alice", free_at).unwrap().owner.0, "mallory");
+
+        registry.rebuild(&[]);
+        assert!(registry.lookup("alice", free_at).is_none());
+    }
+}
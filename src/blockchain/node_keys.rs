@@ -0,0 +1,392 @@
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use super::audit::AuditLog;
+use super::crypto::{Address, CryptoError, DigitalSignature, Wallet};
+use super::signing::{self, SigningContext};
+use super::storage::{BlockchainStorage, StorageError};
+
+/// Longest overlap window a rotation may ask for (one year)
+pub const MAX_KEY_OVERLAP_SECS: u64 = 365 * 24 * 60 * 60;
+
+/// Errors that can occur during node key operations
+#[derive(Debug, Error)]
+pub enum NodeKeyError {
+    #[error("Unknown key purpose: {0}")]
+    UnknownPurpose(String),
+
+    #[error("Crypto error: {0}")]
+    CryptoError(#[from] CryptoError),
+
+    #[error("Storage error: {0}")]
+    StorageError(#[from] StorageError),
+}
+
+/// What a node key is used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyPurpose {
+    /// Identifies the node to its peers
+    Identity,
+
+    /// Signs webhook deliveries
+    Webhook,
+}
+
+impl KeyPurpose {
+    /// All key purposes
+    pub const ALL: [KeyPurpose; 2] = [KeyPurpose::Identity, KeyPurpose::Webhook];
+
+    /// Gets the name of the purpose
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyPurpose::Identity => "identity",
+            KeyPurpose::Webhook => "webhook",
+        }
+    }
+
+    /// Gets the context payloads are signed in with keys of this purpose
+    fn context(&self) -> SigningContext {
+        match self {
+            KeyPurpose::Identity => SigningContext::PeerHandshake,
+            KeyPurpose::Webhook => SigningContext::Webhook,
+        }
+    }
+}
+
+impl fmt::Display for KeyPurpose {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for KeyPurpose {
+    type Err = NodeKeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        KeyPurpose::ALL
+            .into_iter()
+            .find(|purpose| purpose.name() == s)
+            .ok_or_else(|| NodeKeyError::UnknownPurpose(s.to_string()))
+    }
+}
+
+/// A key held by the node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeKey {
+    /// Public key of the key pair
+    pub public_key: Address,
+
+    /// Secret key of the key pair
+    pub secret_key: Vec<u8>,
+
+    /// Timestamp when the key was generated
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp until which a rotated out key still validates, None for the current key
+    pub valid_until: Option<DateTime<Utc>>,
+}
+
+impl NodeKey {
+    /// Generates a new key
+    fn generate() -> Result<Self, NodeKeyError> {
+        let wallet = Wallet::new()?;
+
+        Ok(NodeKey {
+            public_key: wallet.address().clone(),
+            secret_key: wallet.export_secret_key(),
+            created_at: Utc::now(),
+            valid_until: None,
+        })
+    }
+
+    /// Checks if the key still validates signatures at a point in time
+    fn is_valid_at(&self, time: DateTime<Utc>) -> bool {
+        self.valid_until.is_none_or(|until| time < until)
+    }
+
+    /// Gets the public information of the key
+    fn info(&self) -> NodeKeyInfo {
+        NodeKeyInfo {
+            public_key: self.public_key.clone(),
+            created_at: self.created_at,
+            valid_until: self.valid_until,
+        }
+    }
+}
+
+/// The current key of a purpose and the keys it replaced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyRing {
+    /// What the keys are used for
+    pub purpose: KeyPurpose,
+
+    /// The key new signatures are made with
+    pub current: NodeKey,
+
+    /// Rotated out keys, oldest first
+    pub previous: Vec<NodeKey>,
+}
+
+impl KeyRing {
+    /// Gets the public information of the key ring
+    ///
+    /// Rotated out keys past their overlap window are left out.
+    fn info(&self, now: DateTime<Utc>) -> KeyRingInfo {
+        KeyRingInfo {
+            purpose: self.purpose,
+            current: self.current.info(),
+            previous: self
+                .previous
+                .iter()
+                .filter(|key| key.is_valid_at(now))
+                .map(NodeKey::info)
+                .collect(),
+        }
+    }
+}
+
+/// Public information about a node key
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct NodeKeyInfo {
+    /// Public key signatures are verified with
+    pub public_key: Address,
+
+    /// Timestamp when the key was generated
+    #[schema(value_type = String, example = "2023-01-01T12:00:00Z")]
+    pub created_at: DateTime<Utc>,
+
+    /// Timestamp until which a rotated out key still validates
+    #[schema(value_type = Option<String>, example = "2023-01-02T12:00:00Z")]
+    pub valid_until: Option<DateTime<Utc>>,
+}
+
+/// Public information about the keys of a purpose
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct KeyRingInfo {
+    /// What the keys are used for
+    pub purpose: KeyPurpose,
+
+    /// The key new signatures are made with
+    pub current: NodeKeyInfo,
+
+    /// Rotated out keys that still validate
+    pub previous: Vec<NodeKeyInfo>,
+}
+
+/// Keys the node signs with on its own behalf
+///
+/// Rotating a key keeps the old one valid for an overlap window, so peers and
+/// webhook receivers can switch to the new public key without rejecting
+/// signatures made just before the rotation.
+#[derive(Debug, Clone)]
+pub struct NodeKeys {
+    rings: Arc<DashMap<KeyPurpose, KeyRing>>,
+    storage: Option<Arc<BlockchainStorage>>,
+    audit: AuditLog,
+    default_overlap: Duration,
+}
+
+impl NodeKeys {
+    /// Creates the node keys
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - Optional storage to persist keys in
+    /// * `audit` - The audit log rotations are recorded in
+    /// * `default_overlap` - How long rotated out keys stay valid unless a rotation says otherwise
+    ///
+    /// # Returns
+    ///
+    /// A new NodeKeys instance with the keys loaded from storage, generating missing ones
+    pub fn new(
+        storage: Option<Arc<BlockchainStorage>>,
+        audit: AuditLog,
+        default_overlap: Duration,
+    ) -> Result<Self, NodeKeyError> {
+        let rings = DashMap::new();
+
+        if let Some(storage) = &storage {
+            for ring in storage.get_all_key_rings()? {
+                rings.insert(ring.purpose, ring);
+            }
+        }
+
+        for purpose in KeyPurpose::ALL {
+            if rings.contains_key(&purpose) {
+                continue;
+            }
+
+            let ring = KeyRing {
+                purpose,
+                current: NodeKey::generate()?,
+                previous: Vec::new(),
+            };
+            if let Some(storage) = &storage {
+                storage.save_key_ring(&ring)?;
+            }
+            audit.record("system", "key.created", purpose.name(), &format!("public key {}", ring.current.public_key));
+            rings.insert(purpose, ring);
+        }
+
+        Ok(NodeKeys {
+            rings: Arc::new(rings),
+            storage,
+            audit,
+            default_overlap,
+        })
+    }
+
+    /// Replaces the key of a purpose with a new one
+    ///
+    /// # Arguments
+    ///
+    /// * `purpose` - The key to rotate
+    /// * `operator` - Who rotates the key
+    /// * `overlap` - How long the old key stays valid, the default overlap if None
+    ///
+    /// # Returns
+    ///
+    /// The public information of the rotated key ring
+    pub fn rotate(
+        &self,
+        purpose: KeyPurpose,
+        operator: &str,
+        overlap: Option<Duration>,
+    ) -> Result<KeyRingInfo, NodeKeyError> {
+        let now = Utc::now();
+        let overlap = overlap.unwrap_or(self.default_overlap);
+        let mut ring = self.rings.get_mut(&purpose).expect("a key ring exists for every purpose");
+
+        let mut rotated = ring.clone();
+        let mut old = std::mem::replace(&mut rotated.current, NodeKey::generate()?);
+        old.valid_until = Some(now + overlap);
+        rotated.previous.retain(|key| key.is_valid_at(now));
+        rotated.previous.push(old.clone());
+
+        if let Some(storage) = &self.storage {
+            storage.save_key_ring(&rotated)?;
+        }
+
+        self.audit.record(
+            operator,
+            "key.rotated",
+            purpose.name(),
+            &format!(
+                "new public key {}, previous key {} valid until {}",
+                rotated.current.public_key,
+                old.public_key,
+                now + overlap
+            ),
+        );
+
+        *ring = rotated;
+        Ok(ring.info(now))
+    }
+
+    /// Signs a payload with the current key of a purpose
+    ///
+    /// # Arguments
+    ///
+    /// * `purpose` - The key to sign with
+    /// * `payload` - The bytes to sign
+    ///
+    /// # Returns
+    ///
+    /// The public key to verify with and the signature
+    pub fn sign(&self, purpose: KeyPurpose, payload: &[u8]) -> Result<(Address, DigitalSignature), NodeKeyError> {
+        let current = self.rings.get(&purpose).expect("a key ring exists for every purpose").current.clone();
+        let wallet = Wallet::from_secret_key(&current.secret_key)?;
+
+        Ok((current.public_key, signing::sign(&wallet, purpose.context(), payload)?))
+    }
+
+    /// Verifies a signature made by the node
+    ///
+    /// Signatures by the current key and by rotated out keys still inside
+    /// their overlap window are valid.
+    ///
+    /// # Arguments
+    ///
+    /// * `purpose` - The key the signature must be made with
+    /// * `payload` - The signed bytes
+    /// * `signature` - The signature
+    ///
+    /// # Returns
+    ///
+    /// Whether the signature is valid
+    pub fn verify(
+        &self,
+        purpose: KeyPurpose,
+        payload: &[u8],
+        signature: &DigitalSignature,
+    ) -> Result<bool, NodeKeyError> {
+        let now = Utc::now();
+        let ring = self.rings.get(&purpose).expect("a key ring exists for every purpose");
+
+        for key in std::iter::once(&ring.current).chain(ring.previous.iter()) {
+            if !key.is_valid_at(now) {
+                continue;
+            }
+            if signing::verify(&key.public_key.to_public_key()?, purpose.context(), payload, signature)? {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Gets the public information of the keys of a purpose
+    pub fn info(&self, purpose: KeyPurpose) -> KeyRingInfo {
+        self.rings
+            .get(&purpose)
+            .expect("a key ring exists for every purpose")
+            .info(Utc::now())
+    }
+
+    /// Gets the public information of all keys
+    pub fn list(&self) -> Vec<KeyRingInfo> {
+        KeyPurpose::ALL.into_iter().map(|purpose| self.info(purpose)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotated_key_validates_during_overlap() {
+        let audit = AuditLog::new(None).unwrap();
+        let keys = NodeKeys::new(None, audit.clone(), Duration::hours(1)).unwrap();
+        let payload = b"delivery 1";
+
+        let (old_key, old_signature) = keys.sign(KeyPurpose::Webhook, payload).unwrap();
+        let info = keys.rotate(KeyPurpose::Webhook, "alice", None).unwrap();
+        assert_ne!(info.current.public_key, old_key);
+        assert_eq!(info.previous[0].public_key, old_key);
+
+        // Both the old and the new key validate during the overlap
+        let (_, new_signature) = keys.sign(KeyPurpose::Webhook, payload).unwrap();
+        assert!(keys.verify(KeyPurpose::Webhook, payload, &old_signature).unwrap());
+        assert!(keys.verify(KeyPurpose::Webhook, payload, &new_signature).unwrap());
+
+        // Webhook signatures are not identity signatures
+        assert!(!keys.verify(KeyPurpose::Identity, payload, &new_signature).unwrap());
+
+        // Without an overlap the old key stops validating right away
+        let info = keys.rotate(KeyPurpose::Webhook, "alice", Some(Duration::zero())).unwrap();
+        assert_eq!(info.previous.len(), 1);
+        assert!(keys.verify(KeyPurpose::Webhook, payload, &old_signature).unwrap());
+        assert!(!keys.verify(KeyPurpose::Webhook, payload, &new_signature).unwrap());
+
+        let rotations = audit.entries_for("webhook");
+        assert_eq!(rotations.iter().filter(|entry| entry.action == "key.rotated").count(), 2);
+    }
+}
@@ -0,0 +1,452 @@
+use log::info;
+
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+use super::account::AccountState;
+use super::block::Block;
+use super::chain::BlockchainError;
+use super::events::{ChainEvent, EventBus, ReorgEvent};
+use super::faucet::FaucetGrants;
+use super::governance::Governance;
+use super::history::StateCheckpoints;
+use super::index::TransactionIndex;
+use super::names::NameRegistry;
+use super::permissions::Permissions;
+use super::scripts::ScriptLocks;
+use super::sessions::SessionKeys;
+use super::inheritance::Inheritances;
+use super::mempool::PendingTransaction;
+use super::reports::ActivityReports;
+use super::storage_writer::StorageWriter;
+use super::transaction::Transaction;
+
+/// A component that follows the head of the chain
+///
+/// Observers run on the thread that changed the chain, in the order they
+/// were registered, while the chain is locked for the change. They must
+/// return quickly and must not call back into the blockchain. Anything slow,
+/// like network calls, belongs on a task fed by `Blockchain::subscribe`.
+pub trait ChainObserver: Send + Sync {
+    /// Called after a block was appended to the chain
+    ///
+    /// An error is returned by the call that appended the block, and the
+    /// observers registered after this one are not called.
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The appended block
+    fn on_block_applied(&self, _block: &Block) -> Result<(), BlockchainError> {
+        Ok(())
+    }
+
+    /// Called after the chain switched to another branch
+    ///
+    /// An error is returned by the reorganization, and the observers
+    /// registered after this one are not called.
+    ///
+    /// # Arguments
+    ///
+    /// * `reorg` - The reorganization
+    /// * `orphaned` - The blocks that were removed from the chain
+    /// * `chain` - The whole chain after the switch
+    fn on_reorg(&self, _reorg: &ReorgEvent, _orphaned: &[Block], _chain: &[Block]) -> Result<(), BlockchainError> {
+        Ok(())
+    }
+
+    /// Called after a transaction was admitted to the pending transactions
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The admitted transaction
+    fn on_tx_admitted(&self, _transaction: &Transaction) {}
+
+    /// Called when a pending transaction is to be announced to the peers again
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The pending transaction
+    fn on_tx_rebroadcast(&self, _transaction: &Transaction) {}
+
+    /// Called after a pending transaction was evicted for waiting too long
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The evicted transaction and when it was received
+    fn on_tx_evicted(&self, _entry: &PendingTransaction) {}
+}
+
+/// The observers registered with a blockchain
+#[derive(Clone, Default)]
+pub struct Observers {
+    observers: Arc<RwLock<Vec<Arc<dyn ChainObserver>>>>,
+}
+
+impl fmt::Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Observers")
+            .field("count", &self.observers.read().unwrap().len())
+            .finish()
+    }
+}
+
+impl Observers {
+    /// Creates an empty set of observers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers an observer after the ones already registered
+    ///
+    /// # Arguments
+    ///
+    /// * `observer` - The observer to register
+    pub fn register(&self, observer: Arc<dyn ChainObserver>) {
+        self.observers.write().unwrap().push(observer);
+    }
+
+    /// Tells every observer about an appended block, stopping at the first error
+    pub(crate) fn block_applied(&self, block: &Block) -> Result<(), BlockchainError> {
+        for observer in self.observers.read().unwrap().iter() {
+            observer.on_block_applied(block)?;
+        }
+        Ok(())
+    }
+
+    /// Tells every observer about a reorganization, stopping at the first error
+    pub(crate) fn reorg(&self, reorg: &ReorgEvent, orphaned: &[Block], chain: &[Block]) -> Result<(), BlockchainError> {
+        for observer in self.observers.read().unwrap().iter() {
+            observer.on_reorg(reorg, orphaned, chain)?;
+        }
+        Ok(())
+    }
+
+    /// Tells every observer about an admitted transaction
+    pub(crate) fn tx_admitted(&self, transaction: &Transaction) {
+        for observer in self.observers.read().unwrap().iter() {
+            observer.on_tx_admitted(transaction);
+        }
+    }
+
+    /// Tells every observer about a transaction to announce again
+    pub(crate) fn tx_rebroadcast(&self, transaction: &Transaction) {
+        for observer in self.observers.read().unwrap().iter() {
+            observer.on_tx_rebroadcast(transaction);
+        }
+    }
+
+    /// Tells every observer about an evicted transaction
+    pub(crate) fn tx_evicted(&self, entry: &PendingTransaction) {
+        for observer in self.observers.read().unwrap().iter() {
+            observer.on_tx_evicted(entry);
+        }
+    }
+}
+
+impl ChainObserver for ActivityReports {
+    fn on_block_applied(&self, block: &Block) -> Result<(), BlockchainError> {
+        self.record_block(block);
+        Ok(())
+    }
+
+    fn on_reorg(&self, _reorg: &ReorgEvent, _orphaned: &[Block], chain: &[Block]) -> Result<(), BlockchainError> {
+        self.rebuild(chain);
+        Ok(())
+    }
+}
+
+impl ChainObserver for TransactionIndex {
+    fn on_block_applied(&self, block: &Block) -> Result<(), BlockchainError> {
+        self.record_block(block);
+        Ok(())
+    }
+
+    fn on_reorg(&self, _reorg: &ReorgEvent, _orphaned: &[Block], chain: &[Block]) -> Result<(), BlockchainError> {
+        self.rebuild(chain);
+        Ok(())
+    }
+}
+
+impl ChainObserver for NameRegistry {
+    fn on_block_applied(&self, block: &Block) -> Result<(), BlockchainError> {
+        self.record_block(block);
+        Ok(())
+    }
+
+    fn on_reorg(&self, _reorg: &ReorgEvent, _orphaned: &[Block], chain: &[Block]) -> Result<(), BlockchainError> {
+        self.rebuild(chain);
+        Ok(())
+    }
+}
+
+impl ChainObserver for Permissions {
+    fn on_block_applied(&self, block: &Block) -> Result<(), BlockchainError> {
+        self.record_block(block);
+        Ok(())
+    }
+
+    fn on_reorg(&self, _reorg: &ReorgEvent, _orphaned: &[Block], chain: &[Block]) -> Result<(), BlockchainError> {
+        self.rebuild(chain);
+        Ok(())
+    }
+}
+
+impl ChainObserver for ScriptLocks {
+    fn on_block_applied(&self, block: &Block) -> Result<(), BlockchainError> {
+        self.record_block(block);
+        Ok(())
+    }
+
+    fn on_reorg(&self, _reorg: &ReorgEvent, _orphaned: &[Block], chain: &[Block]) -> Result<(), BlockchainError> {
+        self.rebuild(chain);
+        Ok(())
+    }
+}
+
+impl ChainObserver for SessionKeys {
+    fn on_block_applied(&self, block: &Block) -> Result<(), BlockchainError> {
+        self.record_block(block);
+        Ok(())
+    }
+
+    fn on_reorg(&self, _reorg: &ReorgEvent, _orphaned: &[Block], chain: &[Block]) -> Result<(), BlockchainError> {
+        self.rebuild(chain);
+        Ok(())
+    }
+}
+
+impl ChainObserver for Inheritances {
+    fn on_block_applied(&self, block: &Block) -> Result<(), BlockchainError> {
+        self.record_block(block);
+        Ok(())
+    }
+
+    fn on_reorg(&self, _reorg: &ReorgEvent, _orphaned: &[Block], chain: &[Block]) -> Result<(), BlockchainError> {
+        self.rebuild(chain);
+        Ok(())
+    }
+}
+
+impl ChainObserver for StateCheckpoints {
+    fn on_reorg(&self, _reorg: &ReorgEvent, _orphaned: &[Block], chain: &[Block]) -> Result<(), BlockchainError> {
+        self.retain(chain);
+        Ok(())
+    }
+}
+
+impl ChainObserver for EventBus {
+    fn on_block_applied(&self, block: &Block) -> Result<(), BlockchainError> {
+        self.publish(ChainEvent::BlockAdded {
+            index: block.index,
+            hash: block.hash.clone(),
+        });
+        Ok(())
+    }
+
+    fn on_reorg(&self, reorg: &ReorgEvent, _orphaned: &[Block], _chain: &[Block]) -> Result<(), BlockchainError> {
+        self.publish(ChainEvent::Reorg(reorg.clone()));
+        Ok(())
+    }
+
+    fn on_tx_admitted(&self, transaction: &Transaction) {
+        self.publish(ChainEvent::TransactionAdmitted {
+            id: transaction.id.clone(),
+            sender: transaction.sender.clone(),
+            recipient: transaction.recipient.clone(),
+            amount: transaction.amount,
+            fee: transaction.fee,
+        });
+    }
+
+    fn on_tx_evicted(&self, entry: &PendingTransaction) {
+        self.publish(ChainEvent::TransactionEvicted {
+            id: entry.transaction.id.clone(),
+            sender: entry.transaction.sender.clone(),
+            received_at: entry.received_at,
+        });
+    }
+}
+
+/// Counts the proposals and votes of the chain, weighing votes with the live accounts
+pub struct GovernanceObserver {
+    governance: Arc<Governance>,
+    account_state: Arc<AccountState>,
+    faucet: Arc<FaucetGrants>,
+}
+
+impl GovernanceObserver {
+    /// Creates a new governance observer
+    ///
+    /// # Arguments
+    ///
+    /// * `governance` - The governance to update
+    /// * `account_state` - The account state, already updated when a block is applied
+    /// * `faucet` - The deposits of the faucet, credited when the chain is replayed
+    ///
+    /// # Returns
+    ///
+    /// A new GovernanceObserver instance
+    pub fn new(governance: Arc<Governance>, account_state: Arc<AccountState>, faucet: Arc<FaucetGrants>) -> Self {
+        GovernanceObserver {
+            governance,
+            account_state,
+            faucet,
+        }
+    }
+}
+
+impl ChainObserver for GovernanceObserver {
+    fn on_block_applied(&self, block: &Block) -> Result<(), BlockchainError> {
+        self.governance.record_block(block, &self.account_state);
+        Ok(())
+    }
+
+    fn on_reorg(&self, _reorg: &ReorgEvent, _orphaned: &[Block], chain: &[Block]) -> Result<(), BlockchainError> {
+        Ok(self.governance.rebuild(chain, &self.faucet.all())?)
+    }
+}
+
+/// Persists the blocks of the chain along with the accounts they changed
+///
+/// Only accounts changed since the last block are written, by the block or
+/// outside the chain like test funding. The writes are handed to the storage writer with a copy of the accounts,
+/// so the chain is only locked while they are copied. The event bus is told
+/// by the writer once a change is written, so subscribers only hear about
+/// persisted blocks.
+pub struct StorageObserver {
+    writer: StorageWriter,
+    account_state: Arc<AccountState>,
+    events: EventBus,
+}
+
+impl StorageObserver {
+    /// Creates a new storage observer
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - The writer of the storage
+    /// * `account_state` - The account state to persist with every change
+    /// * `events` - The event bus to tell about the written changes
+    ///
+    /// # Returns
+    ///
+    /// A new StorageObserver instance
+    pub fn new(writer: StorageWriter, account_state: Arc<AccountState>, events: EventBus) -> Self {
+        StorageObserver { writer, account_state, events }
+    }
+}
+
+impl ChainObserver for StorageObserver {
+    fn on_block_applied(&self, block: &Block) -> Result<(), BlockchainError> {
+        let block = block.clone();
+        let accounts = self.account_state.take_changed_accounts();
+        let events = self.events.clone();
+
+        self.writer.write(move |storage| {
+            storage.save_block(&block)?;
+            for account in &accounts {
+                storage.save_account(account)?;
+            }
+            storage.commit_block()?;
+
+            info!("Saved block {} to storage", block.index);
+            let _ = events.on_block_applied(&block);
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+
+    fn on_reorg(&self, reorg: &ReorgEvent, orphaned: &[Block], chain: &[Block]) -> Result<(), BlockchainError> {
+        let reorg = reorg.clone();
+        let orphaned = orphaned.to_vec();
+        let branch = chain[reorg.fork_height as usize + 1..].to_vec();
+        // Accounts the orphaned blocks created are gone, so all of them are rewritten
+        self.account_state.take_changed_accounts();
+        let accounts = self.account_state.get_all_accounts();
+        let events = self.events.clone();
+
+        self.writer.write(move |storage| {
+            for block in &orphaned {
+                storage.remove_block(&block.hash)?;
+            }
+            for block in &branch {
+                storage.save_block(block)?;
+            }
+            storage.replace_accounts(&accounts)?;
+            storage.append_reorg(&reorg)?;
+            storage.commit_block()?;
+
+            let _ = events.on_reorg(&reorg, &orphaned, &branch);
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+
+    fn on_tx_admitted(&self, transaction: &Transaction) {
+        // The mempool journal is written apart from the blocks, nothing to wait for
+        self.events.on_tx_admitted(transaction);
+    }
+
+    fn on_tx_evicted(&self, entry: &PendingTransaction) {
+        self.events.on_tx_evicted(entry);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::{Blockchain, Wallet};
+    use std::sync::Mutex;
+
+    /// Records what it is told, in order
+    #[derive(Default)]
+    struct Recorder {
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl ChainObserver for Recorder {
+        fn on_block_applied(&self, block: &Block) -> Result<(), BlockchainError> {
+            self.calls.lock().unwrap().push(format!("block {}", block.index));
+            Ok(())
+        }
+
+        fn on_tx_admitted(&self, transaction: &Transaction) {
+            self.calls.lock().unwrap().push(format!("tx {}", transaction.nonce));
+        }
+    }
+
+    /// Fails every block
+    struct Failing;
+
+    impl ChainObserver for Failing {
+        fn on_block_applied(&self, _block: &Block) -> Result<(), BlockchainError> {
+            Err(BlockchainError::SystemError("disk full".to_string()))
+        }
+    }
+
+    #[test]
+    fn test_observers_follow_the_chain_in_order() {
+        let blockchain = Blockchain::new();
+        let recorder = Arc::new(Recorder::default());
+        blockchain.add_observer(recorder.clone());
+
+        let miner = Wallet::new().unwrap();
+        blockchain.mine_block(&miner.address().0).unwrap();
+        let mut transaction = Transaction::new(miner.address().clone(), miner.address().clone(), 1.0, 0.1, 0);
+        transaction.sign(&miner).unwrap();
+        blockchain.add_transaction(transaction).unwrap();
+        blockchain.mine_block(&miner.address().0).unwrap();
+
+        assert_eq!(*recorder.calls.lock().unwrap(), vec!["block 1", "tx 0", "block 2"]);
+
+        // The built-in observers ran before, a failing one stops the rest
+        let mut events = blockchain.subscribe();
+        blockchain.add_observer(Arc::new(Failing));
+        blockchain.add_observer(recorder.clone());
+        assert!(matches!(blockchain.mine_block(&miner.address().0), Err(BlockchainError::SystemError(_))));
+        assert_eq!(recorder.calls.lock().unwrap().len(), 4);
+        assert!(matches!(events.try_recv(), Ok(ChainEvent::BlockAdded { index: 3, .. })));
+    }
+}
@@ -0,0 +1,605 @@
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use std::sync::{Arc, Mutex};
+
+use super::chain::Blockchain;
+use super::crypto::{Address, ChildKey, CryptoError};
+use super::keystore::{Keystore, KeystoreError};
+use super::storage::{BlockchainStorage, StorageError};
+use super::transaction::Transaction;
+
+/// Number of confirmations after which a payment is considered final by default
+pub const DEFAULT_REQUIRED_CONFIRMATIONS: u64 = 6;
+
+/// Errors that can occur during payment operations
+#[derive(Debug, Error)]
+pub enum PaymentError {
+    #[error("Payment not found: {0}")]
+    NotFound(String),
+
+    #[error("Invalid amount: {0}")]
+    InvalidAmount(String),
+
+    #[error("Invalid confirmations: {0}")]
+    InvalidConfirmations(String),
+
+    #[error("No keystore wallet for merchant {0}")]
+    MerchantNotFound(String),
+
+    #[error("Failed to sweep invoice: {0}")]
+    SweepFailed(String),
+
+    #[error("Crypto error: {0}")]
+    CryptoError(#[from] CryptoError),
+
+    #[error("Keystore error: {0}")]
+    KeystoreError(#[from] KeystoreError),
+
+    #[error("Storage error: {0}")]
+    StorageError(#[from] StorageError),
+}
+
+/// The state of an expected payment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentStatus {
+    /// No matching transaction has been seen yet
+    Unpaid,
+
+    /// A matching transaction is waiting in the mempool
+    Seen,
+
+    /// A matching transaction is in the chain but not yet final
+    Confirmed,
+
+    /// A matching transaction has reached the required number of confirmations
+    Final,
+}
+
+/// Represents a payment a merchant expects to receive
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Payment {
+    /// Unique identifier for the payment
+    pub id: String,
+
+    /// Address the payment must be sent to
+    pub recipient: Address,
+
+    /// Minimum amount that settles the payment
+    pub amount: f64,
+
+    /// Merchant reference (order number, invoice id, ...)
+    pub reference: String,
+
+    /// Number of confirmations after which the payment is final
+    pub required_confirmations: u64,
+
+    /// Timestamp when the payment was registered
+    #[schema(value_type = String, example = "2023-01-01T12:00:00Z")]
+    pub created_at: DateTime<Utc>,
+
+    /// ID of the transaction that settled the payment, once detected
+    pub transaction_id: Option<String>,
+
+    /// Invoice details when the recipient is a single-use derived address
+    pub invoice: Option<Invoice>,
+}
+
+/// A single-use invoice address derived from a merchant wallet
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Invoice {
+    /// The merchant's main address the invoice address was derived from
+    pub merchant: Address,
+
+    /// The derivation index of the invoice address
+    pub derivation_index: u32,
+
+    /// The address the received funds are swept to once the payment is final
+    pub sweep_address: Address,
+
+    /// ID of the sweep transaction, once submitted
+    pub sweep_transaction_id: Option<String>,
+}
+
+/// Current status of a payment as observed on the chain
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PaymentReport {
+    /// The payment
+    pub payment: Payment,
+
+    /// The payment status
+    pub status: PaymentStatus,
+
+    /// The number of confirmations of the settling transaction
+    pub confirmations: u64,
+}
+
+/// Tracks expected payments and matches them against the chain
+#[derive(Debug, Clone)]
+pub struct PaymentRegistry {
+    payments: Arc<DashMap<String, Payment>>,
+    invoice_lock: Arc<Mutex<()>>,
+    sweep_lock: Arc<Mutex<()>>,
+    storage: Option<Arc<BlockchainStorage>>,
+    default_confirmations: u64,
+}
+
+impl PaymentRegistry {
+    /// Creates a new payment registry
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - Optional storage to persist payments in
+    ///
+    /// # Returns
+    ///
+    /// A new PaymentRegistry instance with the payments loaded from storage
+    pub fn new(storage: Option<Arc<BlockchainStorage>>) -> Result<Self, PaymentError> {
+        let payments = DashMap::new();
+
+        if let Some(storage) = &storage {
+            for payment in storage.get_all_payments()? {
+                payments.insert(payment.id.clone(), payment);
+            }
+        }
+
+        Ok(PaymentRegistry {
+            payments: Arc::new(payments),
+            invoice_lock: Arc::new(Mutex::new(())),
+            sweep_lock: Arc::new(Mutex::new(())),
+            storage,
+            default_confirmations: DEFAULT_REQUIRED_CONFIRMATIONS,
+        })
+    }
+
+    /// Sets the number of confirmations used when a payment does not specify one
+    pub fn with_default_confirmations(mut self, confirmations: u64) -> Self {
+        self.default_confirmations = confirmations.max(1);
+        self
+    }
+
+    /// Registers a new expected payment
+    ///
+    /// # Arguments
+    ///
+    /// * `recipient` - The address the payment must be sent to
+    /// * `amount` - The expected amount
+    /// * `reference` - The merchant reference
+    /// * `confirmations` - The number of confirmations required, or None for the default
+    ///
+    /// # Returns
+    ///
+    /// The registered payment
+    pub fn register(
+        &self,
+        recipient: Address,
+        amount: f64,
+        reference: String,
+        confirmations: Option<u64>,
+    ) -> Result<Payment, PaymentError> {
+        self.insert_new(recipient, amount, reference, confirmations, None)
+    }
+
+    /// Registers a payment to a fresh single-use address derived from a merchant address
+    ///
+    /// Once the payment is final the received funds are swept to the sweep
+    /// address, which defaults to the merchant's main address. The invoice
+    /// address is derived from the merchant address alone, so a watch-only
+    /// merchant can hand out invoices. Only the derivation index is kept; the
+    /// key of the invoice address is derived from the merchant wallet in the
+    /// keystore when it is swept.
+    ///
+    /// # Arguments
+    ///
+    /// * `merchant` - The merchant address to derive the invoice address from
+    /// * `amount` - The expected amount
+    /// * `reference` - The merchant reference
+    /// * `confirmations` - The number of confirmations required, or None for the default
+    /// * `sweep_address` - Where to sweep the funds, or None for the merchant address
+    ///
+    /// # Returns
+    ///
+    /// The registered payment
+    pub fn create_invoice(
+        &self,
+        merchant: &Address,
+        amount: f64,
+        reference: String,
+        confirmations: Option<u64>,
+        sweep_address: Option<Address>,
+    ) -> Result<Payment, PaymentError> {
+        // Serialize invoice creation so two invoices never get the same index
+        let _guard = self.invoice_lock.lock().unwrap();
+
+        let derivation_index = self
+            .payments
+            .iter()
+            .filter(|payment| {
+                payment
+                    .invoice
+                    .as_ref()
+                    .is_some_and(|invoice| &invoice.merchant == merchant)
+            })
+            .count() as u32;
+
+        let address = merchant.derive_child(derivation_index)?;
+
+        let invoice = Invoice {
+            merchant: merchant.clone(),
+            derivation_index,
+            sweep_address: sweep_address.unwrap_or_else(|| merchant.clone()),
+            sweep_transaction_id: None,
+        };
+
+        self.insert_new(address, amount, reference, confirmations, Some(invoice))
+    }
+
+    /// Validates and stores a new payment
+    fn insert_new(
+        &self,
+        recipient: Address,
+        amount: f64,
+        reference: String,
+        confirmations: Option<u64>,
+        invoice: Option<Invoice>,
+    ) -> Result<Payment, PaymentError> {
+        if amount <= 0.0 {
+            return Err(PaymentError::InvalidAmount(format!(
+                "Amount must be positive: {}",
+                amount
+            )));
+        }
+
+        let required_confirmations = confirmations.unwrap_or(self.default_confirmations);
+        if required_confirmations == 0 {
+            return Err(PaymentError::InvalidConfirmations(
+                "At least one confirmation is required".to_string(),
+            ));
+        }
+
+        let payment = Payment {
+            id: Uuid::new_v4().to_string(),
+            recipient,
+            amount,
+            reference,
+            required_confirmations,
+            created_at: Utc::now(),
+            transaction_id: None,
+            invoice,
+        };
+
+        self.save(&payment)?;
+
+        Ok(payment)
+    }
+
+    /// Gets a payment by its ID
+    pub fn get(&self, id: &str) -> Option<Payment> {
+        self.payments.get(id).map(|payment| payment.clone())
+    }
+
+    /// Checks the status of a payment against the chain and the mempool
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the payment
+    /// * `blockchain` - The blockchain to check against
+    ///
+    /// # Returns
+    ///
+    /// The current status of the payment
+    pub fn check(&self, id: &str, blockchain: &Blockchain) -> Result<PaymentReport, PaymentError> {
+        let mut payment = self
+            .get(id)
+            .ok_or_else(|| PaymentError::NotFound(id.to_string()))?;
+
+        // Look for a settling transaction in the chain first, then in the mempool
+        if payment.transaction_id.is_none() {
+            if let Some(transaction_id) = self.find_settling_transaction(&payment, blockchain) {
+                payment.transaction_id = Some(transaction_id);
+                self.save(&payment)?;
+            }
+        }
+
+        let (status, confirmations) = match &payment.transaction_id {
+            None => (PaymentStatus::Unpaid, 0),
+            Some(transaction_id) => match blockchain.get_transaction_confirmations(transaction_id) {
+                None => (PaymentStatus::Seen, 0),
+                Some(n) if n >= payment.required_confirmations => (PaymentStatus::Final, n),
+                Some(n) => (PaymentStatus::Confirmed, n),
+            },
+        };
+
+        Ok(PaymentReport {
+            payment,
+            status,
+            confirmations,
+        })
+    }
+
+    /// Sweeps the funds of the final invoices to their sweep addresses
+    ///
+    /// Runs as blocks arrive rather than when a payment is looked at. An
+    /// invoice records its sweep transaction before it is submitted, so it
+    /// is swept at most once, even across restarts.
+    ///
+    /// # Arguments
+    ///
+    /// * `blockchain` - The blockchain to check the invoices against and submit the sweeps to
+    /// * `keystore` - The keystore holding the merchant wallets
+    ///
+    /// # Returns
+    ///
+    /// The IDs of the sweep transactions submitted
+    pub fn sweep_final(&self, blockchain: &Blockchain, keystore: &Keystore) -> Result<Vec<String>, PaymentError> {
+        // Only one run at a time so an invoice is never swept twice
+        let _guard = self.sweep_lock.lock().unwrap();
+
+        let mut pending: Vec<Payment> = self
+            .payments
+            .iter()
+            .filter(|payment| {
+                payment
+                    .invoice
+                    .as_ref()
+                    .is_some_and(|invoice| invoice.sweep_transaction_id.is_none())
+            })
+            .map(|payment| payment.clone())
+            .collect();
+        pending.sort_by_key(|payment| payment.created_at);
+
+        let mut swept = Vec::new();
+        for payment in pending {
+            if self.check(&payment.id, blockchain)?.status != PaymentStatus::Final {
+                continue;
+            }
+
+            match self.sweep(&payment.id, blockchain, keystore) {
+                Ok(transaction_id) => swept.push(transaction_id),
+                Err(PaymentError::StorageError(err)) => return Err(err.into()),
+                Err(err) => warn!("Failed to sweep invoice payment {}: {}", payment.id, err),
+            }
+        }
+
+        Ok(swept)
+    }
+
+    /// Sweeps the balance of an invoice address to its sweep address
+    fn sweep(&self, id: &str, blockchain: &Blockchain, keystore: &Keystore) -> Result<String, PaymentError> {
+        let mut payment = self
+            .get(id)
+            .ok_or_else(|| PaymentError::NotFound(id.to_string()))?;
+        let invoice = match payment.invoice.as_mut() {
+            Some(invoice) if invoice.sweep_transaction_id.is_none() => invoice,
+            _ => return Err(PaymentError::SweepFailed(format!("Payment {} has nothing to sweep", id))),
+        };
+
+        let key = self.invoice_key(keystore, &invoice.merchant, invoice.derivation_index)?;
+        if key.address() != &payment.recipient {
+            return Err(PaymentError::SweepFailed(format!(
+                "Derived address {} does not match the invoice address {}",
+                key.address(),
+                payment.recipient
+            )));
+        }
+
+        let balance = blockchain.get_account_state().get_account(&payment.recipient).balance;
+        let fee = blockchain.get_base_fee();
+
+        if balance <= fee {
+            return Err(PaymentError::SweepFailed(format!(
+                "Balance {} does not cover the fee {}",
+                balance, fee
+            )));
+        }
+
+        let mut transaction = Transaction::new(
+            payment.recipient.clone(),
+            invoice.sweep_address.clone(),
+            balance - fee,
+            fee,
+            blockchain.get_next_nonce(&payment.recipient),
+        );
+        transaction
+            .sign(&key)
+            .map_err(|e| PaymentError::SweepFailed(e.to_string()))?;
+
+        // Record the sweep first so a crash after submitting can't sweep again
+        let transaction_id = transaction.id.clone();
+        invoice.sweep_transaction_id = Some(transaction_id.clone());
+        self.save(&payment)?;
+
+        if let Err(err) = blockchain.add_transaction(transaction) {
+            if let Some(invoice) = payment.invoice.as_mut() {
+                invoice.sweep_transaction_id = None;
+            }
+            self.save(&payment)?;
+            return Err(PaymentError::SweepFailed(err.to_string()));
+        }
+
+        Ok(transaction_id)
+    }
+
+    /// Derives the key of an invoice address from the merchant wallet in the keystore
+    fn invoice_key(&self, keystore: &Keystore, merchant: &Address, index: u32) -> Result<ChildKey, PaymentError> {
+        let name = keystore
+            .list()
+            .into_iter()
+            .find(|info| &info.address == merchant)
+            .map(|info| info.name)
+            .ok_or_else(|| PaymentError::MerchantNotFound(merchant.0.clone()))?;
+
+        Ok(keystore.signer(&name)?.derive_child(index)?)
+    }
+
+    /// Finds a transaction settling the payment that isn't claimed by another payment
+    fn find_settling_transaction(&self, payment: &Payment, blockchain: &Blockchain) -> Option<String> {
+        let is_claimed = |transaction_id: &str| {
+            self.payments
+                .iter()
+                .any(|other| other.transaction_id.as_deref() == Some(transaction_id))
+        };
+
+        let chain = blockchain.get_chain();
+        let confirmed = chain
+            .iter()
+            .rev()
+            .take_while(|block| block.timestamp >= payment.created_at)
+            .flat_map(|block| block.transactions.iter());
+        let pending = blockchain.get_pending_transactions();
+
+        confirmed
+            .chain(pending.iter())
+            .find(|tx| {
+                tx.recipient == payment.recipient
+                    && tx.amount >= payment.amount
+                    && tx.timestamp >= payment.created_at
+                    && !is_claimed(&tx.id)
+            })
+            .map(|tx| tx.id.clone())
+    }
+
+    /// Stores a payment in memory and in storage if available
+    fn save(&self, payment: &Payment) -> Result<(), PaymentError> {
+        if let Some(storage) = &self.storage {
+            storage.save_payment(payment)?;
+        }
+
+        self.payments.insert(payment.id.clone(), payment.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::crypto::Wallet;
+
+    #[test]
+    fn test_register_payment() {
+        let registry = PaymentRegistry::new(None).unwrap();
+        let recipient = Address("merchant".to_string());
+
+        let payment = registry
+            .register(recipient.clone(), 25.0, "order-1".to_string(), None)
+            .unwrap();
+
+        assert_eq!(payment.recipient, recipient);
+        assert_eq!(payment.required_confirmations, DEFAULT_REQUIRED_CONFIRMATIONS);
+        assert!(registry.get(&payment.id).is_some());
+
+        // Non-positive amounts are rejected
+        assert!(registry.register(recipient, 0.0, "order-2".to_string(), None).is_err());
+    }
+
+    #[test]
+    fn test_payment_status_progression() {
+        let blockchain = Blockchain::new();
+        let registry = PaymentRegistry::new(None).unwrap();
+        let customer = Wallet::new().unwrap();
+        let merchant = Wallet::new().unwrap();
+
+        let payment = registry
+            .register(merchant.address().clone(), 10.0, "order-1".to_string(), Some(2))
+            .unwrap();
+        let report = registry.check(&payment.id, &blockchain).unwrap();
+        assert_eq!(report.status, PaymentStatus::Unpaid);
+
+        // Fund the customer and pay the merchant
+        let mut account = blockchain.get_account_state().get_account(customer.address());
+        account.deposit(100.0).unwrap();
+        blockchain.get_account_state().update_account(account);
+
+        let mut transaction = Transaction::new(
+            customer.address().clone(),
+            merchant.address().clone(),
+            10.0,
+            0.1,
+            0,
+        );
+        transaction.sign(&customer).unwrap();
+        blockchain.add_transaction(transaction).unwrap();
+
+        let report = registry.check(&payment.id, &blockchain).unwrap();
+        assert_eq!(report.status, PaymentStatus::Seen);
+
+        blockchain.mine_block(&customer.address().0).unwrap();
+        let report = registry.check(&payment.id, &blockchain).unwrap();
+        assert_eq!(report.status, PaymentStatus::Confirmed);
+        assert_eq!(report.confirmations, 1);
+
+        blockchain.mine_block(&customer.address().0).unwrap();
+        let report = registry.check(&payment.id, &blockchain).unwrap();
+        assert_eq!(report.status, PaymentStatus::Final);
+    }
+
+    #[test]
+    fn test_invoice_is_swept_to_merchant() {
+        let blockchain = Blockchain::new();
+        let registry = PaymentRegistry::new(None).unwrap();
+        let keystore = Keystore::new(None).unwrap();
+        let customer = Wallet::new().unwrap();
+        let merchant = Wallet::new().unwrap();
+
+        let first = registry
+            .create_invoice(merchant.address(), 10.0, "invoice-1".to_string(), Some(1), None)
+            .unwrap();
+        let second = registry
+            .create_invoice(merchant.address(), 10.0, "invoice-2".to_string(), Some(1), None)
+            .unwrap();
+
+        // Every invoice gets its own address
+        assert_ne!(first.recipient, second.recipient);
+        assert_ne!(&first.recipient, merchant.address());
+
+        // Pay the first invoice and confirm it
+        let mut account = blockchain.get_account_state().get_account(customer.address());
+        account.deposit(100.0).unwrap();
+        blockchain.get_account_state().update_account(account);
+
+        let mut transaction = Transaction::new(
+            customer.address().clone(),
+            first.recipient.clone(),
+            10.0,
+            0.1,
+            0,
+        );
+        transaction.sign(&customer).unwrap();
+        blockchain.add_transaction(transaction).unwrap();
+        blockchain.mine_block(&customer.address().0).unwrap();
+
+        // Checking a final invoice doesn't sweep it
+        let report = registry.check(&first.id, &blockchain).unwrap();
+        assert_eq!(report.status, PaymentStatus::Final);
+        assert!(report.payment.invoice.unwrap().sweep_transaction_id.is_none());
+
+        // Without the merchant's private key the invoice key can't be derived
+        assert!(registry.sweep_final(&blockchain, &keystore).unwrap().is_empty());
+        let watching = Keystore::new(None).unwrap();
+        watching.add_watch_only("shop", merchant.address().clone()).unwrap();
+        assert!(registry.sweep_final(&blockchain, &watching).unwrap().is_empty());
+
+        keystore.add_wallet("shop", &merchant).unwrap();
+        let swept = registry.sweep_final(&blockchain, &keystore).unwrap();
+        assert_eq!(swept.len(), 1);
+
+        // A swept invoice is never swept again
+        assert!(registry.sweep_final(&blockchain, &keystore).unwrap().is_empty());
+
+        let sweep_id = registry.get(&first.id).unwrap().invoice.unwrap().sweep_transaction_id.unwrap();
+        assert_eq!(sweep_id, swept[0]);
+        let sweep = blockchain
+            .get_pending_transactions()
+            .into_iter()
+            .find(|tx| tx.id == sweep_id)
+            .unwrap();
+        assert_eq!(&sweep.recipient, merchant.address());
+        assert_eq!(sweep.total_amount(), 10.0);
+    }
+}
@@ -0,0 +1,315 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::sync::mpsc;
+use utoipa::ToSchema;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Errors that can occur while managing peers
+#[derive(Debug, Error, PartialEq)]
+pub enum PeerError {
+    #[error("Peer not found: {0}")]
+    NotFound(String),
+
+    #[error("The P2P network is not running")]
+    NetworkDisabled,
+}
+
+/// What an operator asks the network service to do with a peer
+#[derive(Debug, Clone, PartialEq)]
+pub enum PeerCommand {
+    /// Connect to the peer at a multiaddress
+    Dial(String),
+
+    /// Close the connections to a peer, and refuse new ones if it is banned
+    Disconnect {
+        /// ID of the peer
+        peer_id: String,
+
+        /// Whether the peer is banned
+        ban: bool,
+    },
+}
+
+/// How the node learned about a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PeerSource {
+    /// One of the bootstrap peers of the node's configuration
+    Bootstrap,
+
+    /// Announced on the local network over mDNS
+    Mdns,
+
+    /// Connected to the node on its own
+    Inbound,
+
+    /// Added by an operator through the API
+    Manual,
+}
+
+/// A peer the node knows of
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PeerInfo {
+    /// ID of the peer on the P2P network
+    pub peer_id: String,
+
+    /// Multiaddresses the peer was found or connected at
+    pub addresses: Vec<String>,
+
+    /// How the node learned about the peer
+    pub source: PeerSource,
+
+    /// Whether the node is connected to the peer
+    pub connected: bool,
+
+    /// When the node learned about the peer
+    #[schema(value_type = String, example = "2023-01-01T12:00:00Z")]
+    pub discovered_at: DateTime<Utc>,
+
+    /// When the node last connected to the peer, None if it never did
+    #[schema(value_type = Option<String>, example = "2023-01-01T12:00:00Z")]
+    pub last_connected_at: Option<DateTime<Utc>>,
+
+    /// Whether an operator banned the peer, the node refuses its connections until it restarts
+    pub banned: bool,
+}
+
+/// The peers the node knows of, shared by the network service and the API
+///
+/// Operators' commands reach the network service through the peer set too,
+/// once the service took the receiving end of them.
+#[derive(Debug, Clone, Default)]
+pub struct PeerSet {
+    peers: Arc<RwLock<BTreeMap<String, PeerInfo>>>,
+
+    /// IDs of the banned peers, including those the node never saw
+    banned: Arc<RwLock<BTreeSet<String>>>,
+
+    /// Hands operators' commands to the network service, None without one
+    commands: Arc<Mutex<Option<mpsc::UnboundedSender<PeerCommand>>>>,
+}
+
+impl PeerSet {
+    /// Creates an empty peer set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an address a peer was found at
+    ///
+    /// A peer that is already known keeps the source it was first found by.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - ID of the peer
+    /// * `address` - The address it was found at
+    /// * `source` - How it was found
+    pub fn discovered(&self, peer_id: &str, address: &str, source: PeerSource) {
+        let banned = self.is_banned(peer_id);
+        let mut peers = self.peers.write().unwrap();
+        let peer = peers.entry(peer_id.to_string()).or_insert_with(|| PeerInfo {
+            peer_id: peer_id.to_string(),
+            addresses: Vec::new(),
+            source,
+            connected: false,
+            discovered_at: Utc::now(),
+            last_connected_at: None,
+            banned,
+        });
+        if !peer.addresses.iter().any(|known| known == address) {
+            peer.addresses.push(address.to_string());
+        }
+    }
+
+    /// Records a connection to a peer
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - ID of the peer
+    /// * `address` - The address of the connection
+    /// * `source` - How the peer was found, if it is new
+    pub fn connected(&self, peer_id: &str, address: &str, source: PeerSource) {
+        self.discovered(peer_id, address, source);
+        if let Some(peer) = self.peers.write().unwrap().get_mut(peer_id) {
+            peer.connected = true;
+            peer.last_connected_at = Some(Utc::now());
+        }
+    }
+
+    /// Records that the last connection to a peer closed
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - ID of the peer
+    pub fn disconnected(&self, peer_id: &str) {
+        if let Some(peer) = self.peers.write().unwrap().get_mut(peer_id) {
+            peer.connected = false;
+        }
+    }
+
+    /// Forgets an address of a peer that is no longer announced there
+    ///
+    /// A peer without addresses is forgotten, unless the node is connected to it.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - ID of the peer
+    /// * `address` - The address that expired
+    pub fn expired(&self, peer_id: &str, address: &str) {
+        let mut peers = self.peers.write().unwrap();
+        let forget = match peers.get_mut(peer_id) {
+            Some(peer) => {
+                peer.addresses.retain(|known| known != address);
+                peer.addresses.is_empty() && !peer.connected
+            }
+            None => false,
+        };
+        if forget {
+            peers.remove(peer_id);
+        }
+    }
+
+    /// Gets whether the node is connected to a peer
+    pub fn is_connected(&self, peer_id: &str) -> bool {
+        self.peers.read().unwrap().get(peer_id).is_some_and(|peer| peer.connected)
+    }
+
+    /// Gets whether a peer is banned
+    pub fn is_banned(&self, peer_id: &str) -> bool {
+        self.banned.read().unwrap().contains(peer_id)
+    }
+
+    /// Gets the IDs of the banned peers
+    pub fn banned(&self) -> Vec<String> {
+        self.banned.read().unwrap().iter().cloned().collect()
+    }
+
+    /// Gets every known peer, ordered by peer ID
+    pub fn list(&self) -> Vec<PeerInfo> {
+        self.peers.read().unwrap().values().cloned().collect()
+    }
+
+    /// Takes the operators' commands, for the network service
+    ///
+    /// Commands sent before go nowhere; a later call leaves the earlier receiver without commands.
+    ///
+    /// # Returns
+    ///
+    /// The receiving end of the commands
+    pub fn take_commands(&self) -> mpsc::UnboundedReceiver<PeerCommand> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        *self.commands.lock().unwrap() = Some(sender);
+        receiver
+    }
+
+    /// Asks the network service to connect to a peer
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Multiaddress of the peer
+    ///
+    /// # Returns
+    ///
+    /// Result indicating whether the service got the command
+    pub fn dial(&self, address: &str) -> Result<(), PeerError> {
+        self.send(PeerCommand::Dial(address.to_string()))
+    }
+
+    /// Asks the network service to disconnect from a peer, and to refuse it from now on if banned
+    ///
+    /// # Arguments
+    ///
+    /// * `peer_id` - ID of the peer
+    /// * `ban` - Whether to ban the peer, which needn't be known yet
+    ///
+    /// # Returns
+    ///
+    /// Result indicating whether the service got the command, PeerError::NotFound
+    /// if the peer is neither known nor to be banned
+    pub fn disconnect(&self, peer_id: &str, ban: bool) -> Result<(), PeerError> {
+        if !ban && !self.peers.read().unwrap().contains_key(peer_id) {
+            return Err(PeerError::NotFound(peer_id.to_string()));
+        }
+        self.send(PeerCommand::Disconnect {
+            peer_id: peer_id.to_string(),
+            ban,
+        })?;
+
+        if ban {
+            self.banned.write().unwrap().insert(peer_id.to_string());
+            if let Some(peer) = self.peers.write().unwrap().get_mut(peer_id) {
+                peer.banned = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Hands a command to the network service
+    fn send(&self, command: PeerCommand) -> Result<(), PeerError> {
+        match self.commands.lock().unwrap().as_ref() {
+            Some(sender) => sender.send(command).map_err(|_| PeerError::NetworkDisabled),
+            None => Err(PeerError::NetworkDisabled),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_lifecycle() {
+        let peers = PeerSet::new();
+        peers.discovered("a", "/ip4/10.0.0.1/tcp/9000", PeerSource::Mdns);
+        peers.discovered("a", "/ip4/10.0.0.1/tcp/9000", PeerSource::Mdns);
+        peers.connected("a", "/ip4/10.0.0.1/tcp/9000", PeerSource::Inbound);
+        peers.connected("b", "/ip4/10.0.0.2/tcp/51234", PeerSource::Inbound);
+
+        let listed = peers.list();
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[0].source, PeerSource::Mdns);
+        assert_eq!(listed[0].addresses.len(), 1);
+        assert!(listed[0].connected && listed[0].last_connected_at.is_some());
+
+        // A connected peer stays known when its announcement expires
+        peers.expired("a", "/ip4/10.0.0.1/tcp/9000");
+        assert!(peers.is_connected("a"));
+
+        peers.disconnected("a");
+        peers.discovered("a", "/ip4/10.0.0.1/tcp/9000", PeerSource::Mdns);
+        peers.expired("a", "/ip4/10.0.0.1/tcp/9000");
+        assert_eq!(peers.list().len(), 1);
+        assert!(!peers.is_connected("a"));
+    }
+
+    #[test]
+    fn test_commands_reach_the_network_service() {
+        let peers = PeerSet::new();
+        assert_eq!(peers.dial("/ip4/10.0.0.1/tcp/9000"), Err(PeerError::NetworkDisabled));
+
+        let mut commands = peers.take_commands();
+        peers.connected("a", "/ip4/10.0.0.1/tcp/9000", PeerSource::Inbound);
+        peers.dial("/ip4/10.0.0.2/tcp/9000").unwrap();
+        assert_eq!(peers.disconnect("unknown", false), Err(PeerError::NotFound("unknown".to_string())));
+        peers.disconnect("a", true).unwrap();
+
+        assert_eq!(commands.try_recv().unwrap(), PeerCommand::Dial("/ip4/10.0.0.2/tcp/9000".to_string()));
+        assert_eq!(
+            commands.try_recv().unwrap(),
+            PeerCommand::Disconnect {
+                peer_id: "a".to_string(),
+                ban: true
+            }
+        );
+        assert!(peers.is_banned("a") && peers.list()[0].banned);
+        assert_eq!(peers.banned(), vec!["a".to_string()]);
+
+        // The service stopped
+        drop(commands);
+        assert_eq!(peers.disconnect("b", true), Err(PeerError::NetworkDisabled));
+        assert!(!peers.is_banned("b"));
+    }
+}
@@ -0,0 +1,522 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use std::collections::HashSet;
+use std::sync::{Mutex, RwLock};
+
+use super::block::Block;
+use super::crypto::Address;
+use super::transaction::Transaction;
+
+/// Address permission changes are paid to, which has no key so the amount is burned
+pub const PERMISSIONS_ADDRESS: &str = "permissions";
+
+/// Amount a permission change or freeze order pays to the permissions address
+pub const PERMISSION_CHANGE_AMOUNT: f64 = 0.01;
+
+/// Longest reason a freeze order may give, in bytes
+pub const MAX_FREEZE_REASON_BYTES: usize = 200;
+
+/// Errors that can occur with permissions
+#[derive(Debug, Error)]
+pub enum PermissionError {
+    #[error("{address} is not permitted to {action}")]
+    NotPermitted { address: String, action: String },
+
+    #[error("Invalid permission change: {0}")]
+    InvalidChange(String),
+
+    #[error("{0} is frozen and can't send transactions")]
+    Frozen(String),
+}
+
+/// A change of the addresses allowed on a permissioned chain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct PermissionChange {
+    /// The address the change is about
+    pub address: String,
+
+    /// Whether the address is allowed from now on or revoked
+    pub allowed: bool,
+}
+
+/// An order of a governor to freeze or unfreeze an account
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct FreezeOrder {
+    /// The address to freeze or unfreeze
+    pub address: String,
+
+    /// Whether the address is frozen from now on or unfrozen
+    pub frozen: bool,
+
+    /// Why the governor gave the order
+    pub reason: String,
+}
+
+/// A freeze order that took effect, as recorded on the chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct FreezeRecord {
+    /// The address frozen or unfrozen
+    pub address: String,
+
+    /// Whether the address was frozen or unfrozen
+    pub frozen: bool,
+
+    /// Why the governor gave the order
+    pub reason: String,
+
+    /// The governor who gave the order
+    pub governor: String,
+
+    /// ID of the transaction carrying the order
+    pub transaction_id: String,
+
+    /// Height of the block that includes the transaction
+    pub block_index: u64,
+}
+
+/// Who may send transactions and mine, set in the node configuration
+///
+/// A policy without governors and allowed addresses leaves the chain open to
+/// everyone. Every node of a permissioned network must use the same policy,
+/// since blocks are validated against it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PermissionPolicy {
+    /// Addresses that approve and revoke others with permission changes
+    pub governors: HashSet<Address>,
+
+    /// Addresses allowed without a permission change
+    pub allowed: HashSet<Address>,
+}
+
+impl PermissionPolicy {
+    /// Checks if the policy leaves the chain open to everyone
+    pub fn is_open(&self) -> bool {
+        self.governors.is_empty() && self.allowed.is_empty()
+    }
+}
+
+/// Addresses allowed on the chain, for the API
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PermissionStatus {
+    /// Whether only allowed addresses may send transactions and mine
+    pub permissioned: bool,
+
+    /// Addresses that approve and revoke others
+    pub governors: Vec<String>,
+
+    /// Addresses allowed by the node configuration
+    pub allowed: Vec<String>,
+
+    /// Addresses approved by permission changes on the chain
+    pub approved: Vec<String>,
+
+    /// Addresses frozen by freeze orders on the chain
+    pub frozen: Vec<String>,
+}
+
+/// Checks that a transaction carrying a permission change or freeze order is well formed
+///
+/// Whether its sender is a governor depends on the policy and is checked by
+/// `Permissions::check_transaction`.
+///
+/// # Arguments
+///
+/// * `transaction` - The transaction to check
+///
+/// # Returns
+///
+/// Ok(()) if the transaction changes nothing, or one thing and is sent to the permissions address
+pub fn check_change(transaction: &Transaction) -> Result<(), PermissionError> {
+    let address = match (&transaction.permission, &transaction.freeze) {
+        (None, None) => return Ok(()),
+        (Some(_), Some(_)) => {
+            return Err(PermissionError::InvalidChange(
+                "A transaction can't change a permission and give a freeze order at once".to_string(),
+            ))
+        }
+        (Some(change), None) => &change.address,
+        (None, Some(order)) if order.reason.len() > MAX_FREEZE_REASON_BYTES => {
+            return Err(PermissionError::InvalidChange(format!(
+                "The reason is longer than {} bytes",
+                MAX_FREEZE_REASON_BYTES
+            )))
+        }
+        (None, Some(order)) => &order.address,
+    };
+
+    if address.is_empty() {
+        return Err(PermissionError::InvalidChange("The address is empty".to_string()));
+    }
+    if transaction.is_coinbase() {
+        return Err(PermissionError::InvalidChange(
+            "A coinbase transaction can't change permissions or freeze accounts".to_string(),
+        ));
+    }
+    if transaction.recipient.0 != PERMISSIONS_ADDRESS {
+        return Err(PermissionError::InvalidChange(format!(
+            "A permission change or freeze order must be sent to {}",
+            PERMISSIONS_ADDRESS
+        )));
+    }
+
+    Ok(())
+}
+
+/// The addresses allowed on the chain, updated as each block is committed
+///
+/// The configured policy is fixed, and the governors approve or revoke
+/// further addresses with permission changes on the chain. Changes take
+/// effect after the block that includes them, so every transaction of a
+/// block is checked against the permissions at its parent. Revoking an
+/// address of the policy has no effect.
+///
+/// Governors also freeze accounts with freeze orders, which stops them from
+/// sending transactions until they are unfrozen. Frozen accounts still
+/// receive transfers. Governors can't be frozen.
+#[derive(Debug, Default)]
+pub struct Permissions {
+    policy: RwLock<PermissionPolicy>,
+    approved: Mutex<HashSet<Address>>,
+    frozen: Mutex<HashSet<Address>>,
+    freezes: Mutex<Vec<FreezeRecord>>,
+}
+
+impl Permissions {
+    /// Creates the permissions of an empty chain
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The configured policy
+    pub fn new(policy: PermissionPolicy) -> Self {
+        Permissions {
+            policy: RwLock::new(policy),
+            approved: Mutex::new(HashSet::new()),
+            frozen: Mutex::new(HashSet::new()),
+            freezes: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Creates the permissions after a chain, under the same policy
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks of the chain
+    pub fn replay(&self, blocks: &[Block]) -> Self {
+        let permissions = Permissions::new(self.policy());
+        permissions.rebuild(blocks);
+        permissions
+    }
+
+    /// Gets the configured policy
+    pub fn policy(&self) -> PermissionPolicy {
+        self.policy.read().unwrap().clone()
+    }
+
+    /// Replaces the policy and the changes made under it
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The new policy
+    /// * `blocks` - The blocks of the chain
+    pub fn set_policy(&self, policy: PermissionPolicy, blocks: &[Block]) {
+        *self.policy.write().unwrap() = policy;
+        self.rebuild(blocks);
+    }
+
+    /// Checks if an address may send transactions and mine
+    pub fn is_allowed(&self, address: &Address) -> bool {
+        let policy = self.policy.read().unwrap();
+
+        policy.is_open()
+            || policy.governors.contains(address)
+            || policy.allowed.contains(address)
+            || self.approved.lock().unwrap().contains(address)
+    }
+
+    /// Checks if an address is frozen
+    pub fn is_frozen(&self, address: &Address) -> bool {
+        self.frozen.lock().unwrap().contains(address)
+    }
+
+    /// Checks that the sender of a transaction is allowed
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The transaction to check
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if the sender may send it, isn't frozen, and is a governor if it
+    /// changes permissions or gives a freeze order. Coinbases and treasury
+    /// disbursements are always allowed, governance decided them.
+    pub fn check_transaction(&self, transaction: &Transaction) -> Result<(), PermissionError> {
+        if transaction.is_coinbase() || transaction.is_disbursement() {
+            return Ok(());
+        }
+
+        let governors = self.policy.read().unwrap().governors.clone();
+        if transaction.permission.is_some() && !governors.contains(&transaction.sender) {
+            return Err(PermissionError::NotPermitted {
+                address: transaction.sender.0.clone(),
+                action: "change permissions".to_string(),
+            });
+        }
+        if let Some(order) = &transaction.freeze {
+            if !governors.contains(&transaction.sender) {
+                return Err(PermissionError::NotPermitted {
+                    address: transaction.sender.0.clone(),
+                    action: "freeze accounts".to_string(),
+                });
+            }
+            if order.frozen && governors.contains(&Address(order.address.clone())) {
+                return Err(PermissionError::InvalidChange(format!("Governor {} can't be frozen", order.address)));
+            }
+        }
+        if !self.is_allowed(&transaction.sender) {
+            return Err(PermissionError::NotPermitted {
+                address: transaction.sender.0.clone(),
+                action: "send transactions".to_string(),
+            });
+        }
+        if self.is_frozen(&transaction.sender) {
+            return Err(PermissionError::Frozen(transaction.sender.0.clone()));
+        }
+
+        Ok(())
+    }
+
+    /// Checks that an address may mine
+    ///
+    /// # Arguments
+    ///
+    /// * `miner` - The address receiving the reward
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if the address is allowed
+    pub fn check_miner(&self, miner: &Address) -> Result<(), PermissionError> {
+        if !self.is_allowed(miner) {
+            return Err(PermissionError::NotPermitted {
+                address: miner.0.clone(),
+                action: "mine".to_string(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Checks that every sender and the miner of a block are allowed
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block to check, following the last block recorded
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if the block may follow the recorded chain
+    pub fn check_block(&self, block: &Block) -> Result<(), PermissionError> {
+        for transaction in &block.transactions {
+            if transaction.is_coinbase() {
+                self.check_miner(&transaction.recipient)?;
+            } else {
+                self.check_transaction(transaction)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies the permission changes and freeze orders of a committed block
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block that was added to the chain
+    pub fn record_block(&self, block: &Block) {
+        let governors = self.policy.read().unwrap().governors.clone();
+        let mut approved = self.approved.lock().unwrap();
+        let mut frozen = self.frozen.lock().unwrap();
+        let mut freezes = self.freezes.lock().unwrap();
+
+        for transaction in &block.transactions {
+            if !governors.contains(&transaction.sender) || check_change(transaction).is_err() {
+                continue;
+            }
+
+            if let Some(change) = &transaction.permission {
+                let address = Address(change.address.clone());
+                if change.allowed {
+                    approved.insert(address);
+                } else {
+                    approved.remove(&address);
+                }
+            }
+
+            if let Some(order) = &transaction.freeze {
+                let address = Address(order.address.clone());
+                if order.frozen && governors.contains(&address) {
+                    continue;
+                }
+
+                freezes.push(FreezeRecord {
+                    address: order.address.clone(),
+                    frozen: order.frozen,
+                    reason: order.reason.clone(),
+                    governor: transaction.sender.0.clone(),
+                    transaction_id: transaction.id.clone(),
+                    block_index: block.index,
+                });
+                if order.frozen {
+                    frozen.insert(address);
+                } else {
+                    frozen.remove(&address);
+                }
+            }
+        }
+    }
+
+    /// Replaces the changes with the ones of a chain
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks of the chain
+    pub fn rebuild(&self, blocks: &[Block]) {
+        self.approved.lock().unwrap().clear();
+        self.frozen.lock().unwrap().clear();
+        self.freezes.lock().unwrap().clear();
+
+        for block in blocks {
+            self.record_block(block);
+        }
+    }
+
+    /// Gets the allowed addresses
+    pub fn status(&self) -> PermissionStatus {
+        let policy = self.policy();
+        let sorted = |addresses: &mut dyn Iterator<Item = &Address>| {
+            let mut addresses: Vec<String> = addresses.map(|address| address.0.clone()).collect();
+            addresses.sort();
+            addresses
+        };
+
+        PermissionStatus {
+            permissioned: !policy.is_open(),
+            governors: sorted(&mut policy.governors.iter()),
+            allowed: sorted(&mut policy.allowed.iter()),
+            approved: sorted(&mut self.approved.lock().unwrap().iter()),
+            frozen: sorted(&mut self.frozen.lock().unwrap().iter()),
+        }
+    }
+
+    /// Gets the freeze orders that took effect, oldest first
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - Only the orders about this address, all of them if None
+    pub fn freezes(&self, address: Option<&str>) -> Vec<FreezeRecord> {
+        self.freezes
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|record| address.is_none_or(|address| record.address == address))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(governor: &str, address: &str, allowed: bool) -> Transaction {
+        Transaction::new(Address(governor.to_string()), Address(PERMISSIONS_ADDRESS.to_string()), PERMISSION_CHANGE_AMOUNT, 0.1, 0)
+            .with_permission(PermissionChange {
+                address: address.to_string(),
+                allowed,
+            })
+    }
+
+    fn transfer(sender: &str) -> Transaction {
+        Transaction::new(Address(sender.to_string()), Address("bob".to_string()), 1.0, 0.1, 0)
+    }
+
+    #[test]
+    fn test_governors_approve_and_revoke_senders() {
+        let open = Permissions::default();
+        open.check_transaction(&transfer("anyone")).unwrap();
+        assert!(open.check_transaction(&change("anyone", "alice", true)).is_err());
+
+        let policy = PermissionPolicy {
+            governors: HashSet::from([Address("gov".to_string())]),
+            allowed: HashSet::from([Address("miner".to_string())]),
+        };
+        let permissions = Permissions::new(policy);
+        permissions.check_miner(&Address("miner".to_string())).unwrap();
+        assert!(matches!(
+            permissions.check_transaction(&transfer("alice")),
+            Err(PermissionError::NotPermitted { .. })
+        ));
+
+        // Only a governor's change is accepted, and applies after its block
+        let mut coinbase = Transaction::new_coinbase(Address("miner".to_string()), 50.0);
+        let block = Block::new(1, vec![coinbase.clone(), change("gov", "alice", true), transfer("alice")], 0, "0".to_string());
+        assert!(permissions.check_block(&block).is_err());
+        let block = Block::new(1, vec![coinbase.clone(), change("gov", "alice", true), change("miner", "eve", true)], 0, "0".to_string());
+        assert!(permissions.check_block(&block).is_err());
+        permissions.record_block(&block);
+        permissions.check_transaction(&transfer("alice")).unwrap();
+        assert!(!permissions.is_allowed(&Address("eve".to_string())));
+
+        // A revoked address may neither send nor mine
+        permissions.record_block(&Block::new(2, vec![change("gov", "alice", false)], 0, "0".to_string()));
+        coinbase.recipient = Address("alice".to_string());
+        assert!(permissions.check_block(&Block::new(3, vec![coinbase], 0, "0".to_string())).is_err());
+        assert_eq!(permissions.status().approved, Vec::<String>::new());
+
+        assert!(check_change(&change("gov", "", true)).is_err());
+        let mut misdirected = change("gov", "alice", true);
+        misdirected.recipient = Address("bob".to_string());
+        assert!(matches!(check_change(&misdirected), Err(PermissionError::InvalidChange(_))));
+    }
+
+    #[test]
+    fn test_governors_freeze_and_unfreeze_senders() {
+        let freeze = |governor: &str, address: &str, frozen: bool| {
+            let mut order = change(governor, address, true);
+            order.permission = None;
+            order.with_freeze(FreezeOrder {
+                address: address.to_string(),
+                frozen,
+                reason: "court order".to_string(),
+            })
+        };
+        let permissions = Permissions::new(PermissionPolicy {
+            governors: HashSet::from([Address("gov".to_string())]),
+            allowed: HashSet::from([Address("alice".to_string()), Address("bob".to_string())]),
+        });
+        assert!(matches!(
+            permissions.check_transaction(&freeze("alice", "bob", true)),
+            Err(PermissionError::NotPermitted { .. })
+        ));
+        assert!(permissions.check_transaction(&freeze("gov", "gov", true)).is_err());
+
+        // A frozen account can't send, the order and its reason are kept
+        permissions.record_block(&Block::new(1, vec![freeze("gov", "alice", true), freeze("bob", "bob", true)], 0, "0".to_string()));
+        assert!(matches!(permissions.check_transaction(&transfer("alice")), Err(PermissionError::Frozen(_))));
+        permissions.check_transaction(&transfer("bob")).unwrap();
+        assert_eq!(permissions.status().frozen, vec!["alice".to_string()]);
+        assert_eq!(permissions.freezes(Some("alice"))[0].reason, "court order");
+
+        permissions.record_block(&Block::new(2, vec![freeze("gov", "alice", false)], 0, "0".to_string()));
+        permissions.check_transaction(&transfer("alice")).unwrap();
+        assert_eq!(permissions.freezes(None).len(), 2);
+
+        let mut both = freeze("gov", "alice", true);
+        both.permission = Some(PermissionChange {
+            address: "alice".to_string(),
+            allowed: true,
+        });
+        assert!(check_change(&both).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use super::observers::ChainObserver;
+use super::transaction::Transaction;
+
+/// When the node announces its own stuck transactions to its peers again
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RebroadcastPolicy {
+    /// How long a local transaction stays pending after it was last announced before it is announced again
+    pub interval: Duration,
+
+    /// How often a transaction is announced again at most, 0 for no limit
+    pub max_rebroadcasts: u32,
+}
+
+impl Default for RebroadcastPolicy {
+    fn default() -> Self {
+        RebroadcastPolicy {
+            interval: Duration::minutes(5),
+            max_rebroadcasts: 12,
+        }
+    }
+}
+
+/// When a pending transaction was last announced to the peers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct BroadcastRecord {
+    /// ID of the transaction
+    pub transaction_id: String,
+
+    /// Whether it was submitted to this node rather than relayed by a peer
+    pub local: bool,
+
+    /// When it entered the mempool or was last announced again
+    #[schema(value_type = String, example = "2023-01-01T12:00:00Z")]
+    pub broadcast_at: DateTime<Utc>,
+
+    /// How often it was announced again
+    pub rebroadcasts: u32,
+}
+
+impl BroadcastRecord {
+    /// Creates the record of a transaction announced now
+    fn new(transaction_id: &str, local: bool, now: DateTime<Utc>) -> Self {
+        BroadcastRecord {
+            transaction_id: transaction_id.to_string(),
+            local,
+            broadcast_at: now,
+            rebroadcasts: 0,
+        }
+    }
+}
+
+/// When the pending transactions were last announced
+///
+/// Transactions are local unless a peer relayed them: the peer that
+/// announced a transaction is the one to announce it again. Records of
+/// transactions that left the mempool are dropped the next time the due
+/// rebroadcasts are picked.
+#[derive(Debug, Default)]
+pub struct Broadcasts {
+    records: Mutex<HashMap<String, BroadcastRecord>>,
+}
+
+impl Broadcasts {
+    /// Creates an empty set of records
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks a transaction a peer relayed, before it is submitted
+    ///
+    /// A transaction the node already knows keeps its record.
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction_id` - ID of the transaction
+    pub fn mark_relayed(&self, transaction_id: &str) {
+        self.records
+            .lock()
+            .unwrap()
+            .entry(transaction_id.to_string())
+            .or_insert_with(|| BroadcastRecord::new(transaction_id, false, Utc::now()));
+    }
+
+    /// Records that a transaction was announced again
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction_id` - ID of the transaction
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    ///
+    /// The updated record
+    pub fn rebroadcast(&self, transaction_id: &str, now: DateTime<Utc>) -> BroadcastRecord {
+        let mut records = self.records.lock().unwrap();
+        let record = records
+            .entry(transaction_id.to_string())
+            .or_insert_with(|| BroadcastRecord::new(transaction_id, true, now));
+        record.broadcast_at = now;
+        record.rebroadcasts += 1;
+        record.clone()
+    }
+
+    /// Picks the local pending transactions due to be announced again and records their rebroadcast
+    ///
+    /// Pending transactions without a record, e.g. recovered from the
+    /// mempool journal on start, count as local and announced now.
+    ///
+    /// # Arguments
+    ///
+    /// * `pending` - The pending transactions
+    /// * `policy` - When transactions are announced again
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    ///
+    /// The updated records of the transactions to announce again
+    pub fn due(&self, pending: &[Transaction], policy: &RebroadcastPolicy, now: DateTime<Utc>) -> Vec<BroadcastRecord> {
+        let mut records = self.records.lock().unwrap();
+        let ids: HashSet<&str> = pending.iter().map(|transaction| transaction.id.as_str()).collect();
+        records.retain(|id, _| ids.contains(id.as_str()));
+
+        let mut due = Vec::new();
+        for transaction in pending {
+            let record = records
+                .entry(transaction.id.clone())
+                .or_insert_with(|| BroadcastRecord::new(&transaction.id, true, now));
+            let exhausted = policy.max_rebroadcasts > 0 && record.rebroadcasts >= policy.max_rebroadcasts;
+            if record.local && !exhausted && now - record.broadcast_at >= policy.interval {
+                record.broadcast_at = now;
+                record.rebroadcasts += 1;
+                due.push(record.clone());
+            }
+        }
+
+        due
+    }
+
+    /// Gets the record of a transaction
+    pub fn get(&self, transaction_id: &str) -> Option<BroadcastRecord> {
+        self.records.lock().unwrap().get(transaction_id).cloned()
+    }
+}
+
+impl ChainObserver for Broadcasts {
+    fn on_tx_admitted(&self, transaction: &Transaction) {
+        self.records
+            .lock()
+            .unwrap()
+            .entry(transaction.id.clone())
+            .or_insert_with(|| BroadcastRecord::new(&transaction.id, true, Utc::now()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::crypto::Address;
+
+    fn transaction(nonce: u64) -> Transaction {
+        Transaction::new(
+            Address("sender".to_string()),
+            Address("recipient".to_string()),
+            1.0,
+            0.1,
+            nonce,
+        )
+    }
+
+    #[test]
+    fn test_only_local_transactions_are_due() {
+        let broadcasts = Broadcasts::new();
+        let policy = RebroadcastPolicy {
+            interval: Duration::minutes(5),
+            max_rebroadcasts: 2,
+        };
+        let local = transaction(0);
+        let relayed = transaction(1);
+        broadcasts.on_tx_admitted(&local);
+        broadcasts.mark_relayed(&relayed.id);
+        broadcasts.on_tx_admitted(&relayed);
+        let pending = vec![local.clone(), relayed.clone()];
+
+        let start = broadcasts.get(&local.id).unwrap().broadcast_at;
+        assert!(broadcasts.due(&pending, &policy, start + Duration::minutes(4)).is_empty());
+
+        let due = broadcasts.due(&pending, &policy, start + Duration::minutes(5));
+        assert_eq!(due.len(), 1);
+        assert_eq!((due[0].transaction_id.as_str(), due[0].rebroadcasts), (local.id.as_str(), 1));
+
+        // A manual rebroadcast restarts the interval and counts against the limit
+        broadcasts.rebroadcast(&local.id, start + Duration::minutes(7));
+        assert!(broadcasts.due(&pending, &policy, start + Duration::minutes(11)).is_empty());
+        assert!(broadcasts.due(&pending, &policy, start + Duration::minutes(30)).is_empty());
+        assert_eq!(broadcasts.get(&local.id).unwrap().rebroadcasts, 2);
+    }
+
+    #[test]
+    fn test_records_of_mined_transactions_are_dropped() {
+        let broadcasts = Broadcasts::new();
+        let mined = transaction(0);
+        broadcasts.on_tx_admitted(&mined);
+
+        // A transaction recovered on start has no record yet
+        let recovered = transaction(1);
+        let now = Utc::now();
+        assert!(broadcasts.due(std::slice::from_ref(&recovered), &RebroadcastPolicy::default(), now).is_empty());
+
+        assert!(broadcasts.get(&mined.id).is_none());
+        assert_eq!(broadcasts.get(&recovered.id).unwrap().broadcast_at, now);
+    }
+}
@@ -0,0 +1,246 @@
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Mutex;
+
+use super::block::Block;
+
+/// Length of the periods of an activity report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    Hour,
+    Day,
+    Week,
+}
+
+impl Granularity {
+    /// Gets the length of one period
+    fn duration(&self) -> Duration {
+        match self {
+            Granularity::Hour => Duration::hours(1),
+            Granularity::Day => Duration::days(1),
+            Granularity::Week => Duration::weeks(1),
+        }
+    }
+}
+
+/// Aggregated chain activity over one period
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ActivityPeriod {
+    /// Start of the period
+    #[schema(value_type = String, example = "2023-01-01T00:00:00Z")]
+    pub period_start: DateTime<Utc>,
+
+    /// Number of non-coinbase transactions
+    pub transaction_count: u64,
+
+    /// Total amount transferred by non-coinbase transactions
+    pub volume: f64,
+
+    /// Total fees paid
+    pub fees: f64,
+
+    /// Number of addresses seen for the first time
+    pub new_addresses: u64,
+
+    /// Number of blocks mined
+    pub blocks_mined: u64,
+}
+
+impl ActivityPeriod {
+    /// Adds the activity of another period to this one
+    fn merge(&mut self, other: &ActivityPeriod) {
+        self.transaction_count += other.transaction_count;
+        self.volume += other.volume;
+        self.fees += other.fees;
+        self.new_addresses += other.new_addresses;
+        self.blocks_mined += other.blocks_mined;
+    }
+}
+
+/// Hourly buckets plus the addresses seen so far
+#[derive(Debug, Default)]
+struct ActivityState {
+    hours: BTreeMap<DateTime<Utc>, ActivityPeriod>,
+    seen_addresses: HashSet<String>,
+}
+
+/// Chain activity aggregates, updated as each block is committed
+///
+/// Activity is kept in hourly buckets so reports of any granularity only
+/// need to merge buckets, never rescan the chain.
+#[derive(Debug, Default)]
+pub struct ActivityReports {
+    state: Mutex<ActivityState>,
+}
+
+impl ActivityReports {
+    /// Creates an empty set of activity reports
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the activity of a committed block
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block that was added to the chain
+    pub fn record_block(&self, block: &Block) {
+        let mut state = self.state.lock().unwrap();
+        let ActivityState { hours, seen_addresses } = &mut *state;
+
+        let hour = truncate(block.timestamp, Granularity::Hour);
+        let bucket = hours.entry(hour).or_insert_with(|| ActivityPeriod {
+            period_start: hour,
+            ..Default::default()
+        });
+
+        // The genesis block is created, not mined
+        if block.index > 0 {
+            bucket.blocks_mined += 1;
+        }
+
+        for transaction in &block.transactions {
+            if !transaction.is_coinbase() {
+                bucket.transaction_count += 1;
+                bucket.volume += transaction.amount;
+                bucket.fees += transaction.fee;
+
+                if seen_addresses.insert(transaction.sender.0.clone()) {
+                    bucket.new_addresses += 1;
+                }
+            }
+
+            if seen_addresses.insert(transaction.recipient.0.clone()) {
+                bucket.new_addresses += 1;
+            }
+        }
+    }
+
+    /// Replaces all aggregates with the activity of a chain
+    ///
+    /// Only needed when blocks leave the chain, since new addresses can not
+    /// be subtracted incrementally.
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks of the chain
+    pub fn rebuild(&self, blocks: &[Block]) {
+        *self.state.lock().unwrap() = ActivityState::default();
+
+        for block in blocks {
+            self.record_block(block);
+        }
+    }
+
+    /// Gets the activity per period
+    ///
+    /// # Arguments
+    ///
+    /// * `granularity` - The length of each period
+    /// * `limit` - Only return the most recent periods if set
+    ///
+    /// # Returns
+    ///
+    /// The periods with activity, oldest first
+    pub fn report(&self, granularity: Granularity, limit: Option<usize>) -> Vec<ActivityPeriod> {
+        let state = self.state.lock().unwrap();
+        let mut periods: BTreeMap<DateTime<Utc>, ActivityPeriod> = BTreeMap::new();
+
+        for (hour, bucket) in &state.hours {
+            let start = truncate(*hour, granularity);
+            periods
+                .entry(start)
+                .or_insert_with(|| ActivityPeriod {
+                    period_start: start,
+                    ..Default::default()
+                })
+                .merge(bucket);
+        }
+
+        let periods: Vec<ActivityPeriod> = periods.into_values().collect();
+        match limit {
+            Some(limit) if limit < periods.len() => periods[periods.len() - limit..].to_vec(),
+            _ => periods,
+        }
+    }
+}
+
+/// Truncates a timestamp to the start of its period
+fn truncate(timestamp: DateTime<Utc>, granularity: Granularity) -> DateTime<Utc> {
+    // Weeks are truncated from the Unix epoch, which was a Thursday
+    timestamp
+        .duration_trunc(granularity.duration())
+        .unwrap_or(timestamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::crypto::Address;
+    use crate::blockchain::transaction::Transaction;
+    use chrono::TimeZone;
+
+    fn block_at(index: u64, timestamp: DateTime<Utc>, transactions: Vec<Transaction>) -> Block {
+        let mut block = Block::new(index, transactions, 0, "0".to_string());
+        block.timestamp = timestamp;
+        block
+    }
+
+    #[test]
+    fn test_record_block_aggregates_per_day() {
+        let reports = ActivityReports::new();
+        let morning = Utc.with_ymd_and_hms(2024, 3, 1, 9, 30, 0).unwrap();
+        let evening = Utc.with_ymd_and_hms(2024, 3, 1, 21, 0, 0).unwrap();
+        let next_day = Utc.with_ymd_and_hms(2024, 3, 2, 1, 0, 0).unwrap();
+
+        let alice = Address("alice".to_string());
+        let bob = Address("bob".to_string());
+
+        reports.record_block(&block_at(0, morning, Vec::new()));
+        reports.record_block(&block_at(1, morning, vec![Transaction::new_coinbase(alice.clone(), 50.0)]));
+        reports.record_block(&block_at(
+            2,
+            evening,
+            vec![Transaction::new(alice.clone(), bob.clone(), 10.0, 0.5, 0)],
+        ));
+        reports.record_block(&block_at(
+            3,
+            next_day,
+            vec![Transaction::new(bob, alice, 4.0, 0.25, 0)],
+        ));
+
+        let days = reports.report(Granularity::Day, None);
+        assert_eq!(days.len(), 2);
+
+        assert_eq!(days[0].period_start, Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap());
+        assert_eq!(days[0].blocks_mined, 2);
+        assert_eq!(days[0].transaction_count, 1);
+        assert_eq!(days[0].volume, 10.0);
+        assert_eq!(days[0].fees, 0.5);
+        assert_eq!(days[0].new_addresses, 2);
+
+        assert_eq!(days[1].transaction_count, 1);
+        assert_eq!(days[1].new_addresses, 0);
+
+        // Hourly buckets are kept separately
+        assert_eq!(reports.report(Granularity::Hour, None).len(), 3);
+    }
+
+    #[test]
+    fn test_report_limit_keeps_latest_periods() {
+        let reports = ActivityReports::new();
+
+        for day in 1..=5 {
+            let timestamp = Utc.with_ymd_and_hms(2024, 3, day, 12, 0, 0).unwrap();
+            reports.record_block(&block_at(day as u64, timestamp, Vec::new()));
+        }
+
+        let days = reports.report(Granularity::Day, Some(2));
+        assert_eq!(days.len(), 2);
+        assert_eq!(days[1].period_start, Utc.with_ymd_and_hms(2024, 3, 5, 0, 0, 0).unwrap());
+    }
+}
@@ -0,0 +1,570 @@
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use std::sync::{Arc, Mutex};
+
+use super::canonical;
+use super::chain::Blockchain;
+use super::crypto::{Address, CryptoError, DigitalSignature, Wallet};
+use super::signing::{self, SigningContext};
+use super::storage::{BlockchainStorage, StorageError};
+use super::transaction::Transaction;
+
+/// Most payments a schedule may make, 0 asks for payments until it is cancelled
+pub const MAX_SCHEDULED_PAYMENTS: u32 = 10_000;
+
+/// Errors that can occur during schedule operations
+#[derive(Debug, Error)]
+pub enum ScheduleError {
+    #[error("Schedule not found: {0}")]
+    NotFound(String),
+
+    #[error("Schedule already registered: {0}")]
+    AlreadyExists(String),
+
+    #[error("Invalid schedule: {0}")]
+    InvalidSchedule(String),
+
+    #[error("The signature is not the owner's")]
+    InvalidSignature,
+
+    #[error("Schedule {id} is {status:?}, expected {expected:?}")]
+    InvalidState {
+        id: String,
+        status: ScheduleStatus,
+        expected: ScheduleStatus,
+    },
+
+    #[error("Crypto error: {0}")]
+    CryptoError(#[from] CryptoError),
+
+    #[error("Storage error: {0}")]
+    StorageError(#[from] StorageError),
+}
+
+/// The state of a schedule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleStatus {
+    /// Paying on schedule
+    Active,
+
+    /// Made all its payments
+    Completed,
+
+    /// Cancelled by its owner
+    Cancelled,
+}
+
+/// What the owner of a schedule authorizes with one signature
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ScheduleTerms {
+    /// Address that authorizes the payments and gets the rest back on cancellation
+    #[schema(value_type = String)]
+    pub owner: Address,
+
+    /// Address that is paid
+    #[schema(value_type = String)]
+    pub recipient: Address,
+
+    /// Amount of each payment
+    pub amount: f64,
+
+    /// Blocks between two payments
+    pub interval: u64,
+
+    /// Number of payments, 0 for payments until the schedule is cancelled
+    pub payments: u32,
+
+    /// Height from which the first payment is due
+    pub start_height: u64,
+}
+
+impl ScheduleTerms {
+    /// Gets the bytes the owner signs to authorize the schedule
+    ///
+    /// The canonical JSON of the terms, signed in the schedule context.
+    pub fn payload(&self) -> Vec<u8> {
+        canonical::to_vec(&serde_json::json!({
+            "owner": self.owner.0,
+            "recipient": self.recipient.0,
+            "amount": self.amount,
+            "interval": self.interval,
+            "payments": self.payments,
+            "start_height": self.start_height,
+        }))
+    }
+
+    /// Gets the ID of the schedule, the SHA-256 hash of the signed payload
+    ///
+    /// Registering the same signed terms again gets the same ID, so an
+    /// authorization can't be replayed into a second schedule.
+    pub fn id(&self) -> String {
+        hex::encode(Sha256::digest(self.payload()))
+    }
+
+    /// Checks the terms are within limits
+    fn validate(&self) -> Result<(), ScheduleError> {
+        if !self.amount.is_finite() || self.amount <= 0.0 {
+            return Err(ScheduleError::InvalidSchedule(format!(
+                "Amount must be positive: {}",
+                self.amount
+            )));
+        }
+        if self.interval == 0 {
+            return Err(ScheduleError::InvalidSchedule("Interval must be at least one block".to_string()));
+        }
+        if self.payments > MAX_SCHEDULED_PAYMENTS {
+            return Err(ScheduleError::InvalidSchedule(format!(
+                "At most {} payments are allowed",
+                MAX_SCHEDULED_PAYMENTS
+            )));
+        }
+        if self.owner == self.recipient {
+            return Err(ScheduleError::InvalidSchedule("The owner can't pay itself".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+/// Gets the bytes the owner of a schedule signs to cancel it
+///
+/// # Arguments
+///
+/// * `id` - The ID of the schedule
+pub fn cancel_payload(id: &str) -> Vec<u8> {
+    canonical::to_vec(&serde_json::json!({ "cancel": id }))
+}
+
+/// Signs the terms of a schedule, or the cancellation of one, as its owner
+///
+/// # Arguments
+///
+/// * `wallet` - The owner's wallet
+/// * `payload` - The terms' payload or the cancel payload
+///
+/// # Returns
+///
+/// The signature
+pub fn sign(wallet: &Wallet, payload: &[u8]) -> Result<DigitalSignature, CryptoError> {
+    signing::sign(wallet, SigningContext::Schedule, payload)
+}
+
+/// Represents recurring payments the node makes for an owner
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Schedule {
+    /// Unique identifier for the schedule, see `ScheduleTerms::id`
+    pub id: String,
+
+    /// The authorized terms
+    pub terms: ScheduleTerms,
+
+    /// The owner's signature of the terms
+    #[schema(value_type = String)]
+    pub signature: DigitalSignature,
+
+    /// Address of the session key the node pays from, funded by the owner
+    #[schema(value_type = String)]
+    pub session_address: Address,
+
+    /// The schedule status
+    pub status: ScheduleStatus,
+
+    /// Height from which the next payment is due
+    pub next_height: u64,
+
+    /// IDs of the payments submitted so far, oldest first
+    pub transactions: Vec<String>,
+
+    /// ID of the transaction returning the session key's balance on cancellation
+    pub refund_transaction_id: Option<String>,
+
+    /// Why the last due payment could not be submitted, it is retried with the next block
+    pub last_error: Option<String>,
+
+    /// Timestamp when the schedule was registered
+    #[schema(value_type = String, example = "2023-01-01T12:00:00Z")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// A schedule with the secret key of its session key, as it is stored
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleRecord {
+    /// The schedule
+    pub schedule: Schedule,
+
+    /// Secret key of the session key
+    pub session_key: Vec<u8>,
+}
+
+/// Recurring payments the node submits on schedule
+///
+/// The owner signs the terms once. The node then pays from a session key it
+/// generates for the schedule, which the owner funds with an ordinary
+/// transfer, so the owner's own key never leaves their hands and the node can
+/// never spend more than was put on the session key. Cancelling returns what
+/// is left to the owner.
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    schedules: Arc<DashMap<String, ScheduleRecord>>,
+    storage: Option<Arc<BlockchainStorage>>,
+    submit_lock: Arc<Mutex<()>>,
+}
+
+impl Scheduler {
+    /// Creates a new scheduler
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - Optional storage to persist schedules in
+    ///
+    /// # Returns
+    ///
+    /// A new Scheduler instance with the schedules loaded from storage
+    pub fn new(storage: Option<Arc<BlockchainStorage>>) -> Result<Self, ScheduleError> {
+        let schedules = DashMap::new();
+
+        if let Some(storage) = &storage {
+            for record in storage.get_all_schedules()? {
+                schedules.insert(record.schedule.id.clone(), record);
+            }
+        }
+
+        Ok(Scheduler {
+            schedules: Arc::new(schedules),
+            storage,
+            submit_lock: Arc::new(Mutex::new(())),
+        })
+    }
+
+    /// Registers a schedule authorized by its owner
+    ///
+    /// # Arguments
+    ///
+    /// * `terms` - The terms of the schedule
+    /// * `signature` - The owner's signature of the terms' payload
+    ///
+    /// # Returns
+    ///
+    /// The registered schedule, whose session address the owner has to fund
+    pub fn register(&self, terms: ScheduleTerms, signature: DigitalSignature) -> Result<Schedule, ScheduleError> {
+        terms.validate()?;
+        verify_owner(&terms.owner, &terms.payload(), &signature)?;
+
+        let id = terms.id();
+        if self.schedules.contains_key(&id) {
+            return Err(ScheduleError::AlreadyExists(id));
+        }
+
+        let session = Wallet::new()?;
+        let schedule = Schedule {
+            id,
+            next_height: terms.start_height,
+            terms,
+            signature,
+            session_address: session.address().clone(),
+            status: ScheduleStatus::Active,
+            transactions: Vec::new(),
+            refund_transaction_id: None,
+            last_error: None,
+            created_at: Utc::now(),
+        };
+
+        self.save(&ScheduleRecord {
+            schedule: schedule.clone(),
+            session_key: session.export_secret_key(),
+        })?;
+
+        Ok(schedule)
+    }
+
+    /// Cancels an active schedule and returns the session key's balance to the owner
+    ///
+    /// Nothing is returned when the balance doesn't cover the base fee.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the schedule
+    /// * `signature` - The owner's signature of `cancel_payload(id)`
+    /// * `blockchain` - The blockchain to submit the refund to
+    ///
+    /// # Returns
+    ///
+    /// The cancelled schedule
+    pub fn cancel(&self, id: &str, signature: &DigitalSignature, blockchain: &Blockchain) -> Result<Schedule, ScheduleError> {
+        let _guard = self.submit_lock.lock().unwrap();
+
+        let mut record = self
+            .schedules
+            .get(id)
+            .map(|record| record.clone())
+            .ok_or_else(|| ScheduleError::NotFound(id.to_string()))?;
+        verify_owner(&record.schedule.terms.owner, &cancel_payload(id), signature)?;
+
+        if record.schedule.status != ScheduleStatus::Active {
+            return Err(ScheduleError::InvalidState {
+                id: id.to_string(),
+                status: record.schedule.status,
+                expected: ScheduleStatus::Active,
+            });
+        }
+
+        let session = Wallet::from_secret_key(&record.session_key)?;
+        let fee = blockchain.get_base_fee();
+        let refund = spendable(blockchain, session.address()) - fee;
+        if refund > 0.0 {
+            let owner = record.schedule.terms.owner.clone();
+            record.schedule.refund_transaction_id = submit(blockchain, &session, owner, refund, fee).ok();
+        }
+
+        record.schedule.status = ScheduleStatus::Cancelled;
+        self.save(&record)?;
+        Ok(record.schedule)
+    }
+
+    /// Submits the payments that are due at the height of the tip
+    ///
+    /// A payment the session key can't cover stays due and is tried again
+    /// the next time, so funding the session key late catches up with one
+    /// payment rather than all the missed ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `blockchain` - The blockchain to submit the payments to
+    ///
+    /// # Returns
+    ///
+    /// The schedules that had a payment due, with their new state
+    pub fn submit_due(&self, blockchain: &Blockchain) -> Result<Vec<Schedule>, ScheduleError> {
+        // Only one run at a time so nonces of the session keys don't collide
+        let _guard = self.submit_lock.lock().unwrap();
+
+        // Leave the payments due until transactions are accepted again
+        if blockchain.get_maintenance_status().transactions_paused {
+            return Ok(Vec::new());
+        }
+
+        let height = blockchain.get_last_block().index;
+        let mut due: Vec<ScheduleRecord> = self
+            .schedules
+            .iter()
+            .filter(|record| record.schedule.status == ScheduleStatus::Active && record.schedule.next_height <= height)
+            .map(|record| record.clone())
+            .collect();
+        due.sort_by_key(|record| record.schedule.created_at);
+
+        let mut processed = Vec::with_capacity(due.len());
+        for mut record in due {
+            let session = Wallet::from_secret_key(&record.session_key)?;
+            let schedule = &mut record.schedule;
+            let recipient = schedule.terms.recipient.clone();
+
+            match submit(blockchain, &session, recipient, schedule.terms.amount, blockchain.get_base_fee()) {
+                Ok(transaction_id) => {
+                    schedule.transactions.push(transaction_id);
+                    schedule.next_height = height + schedule.terms.interval;
+                    schedule.last_error = None;
+                    if schedule.terms.payments != 0 && schedule.transactions.len() as u64 >= schedule.terms.payments as u64 {
+                        schedule.status = ScheduleStatus::Completed;
+                    }
+                }
+                Err(err) => schedule.last_error = Some(err),
+            }
+
+            self.save(&record)?;
+            processed.push(record.schedule);
+        }
+
+        Ok(processed)
+    }
+
+    /// Gets a schedule by its ID
+    pub fn get(&self, id: &str) -> Option<Schedule> {
+        self.schedules.get(id).map(|record| record.schedule.clone())
+    }
+
+    /// Lists schedules, optionally only those of an owner
+    pub fn list(&self, owner: Option<&Address>) -> Vec<Schedule> {
+        let mut schedules: Vec<Schedule> = self
+            .schedules
+            .iter()
+            .filter(|record| owner.is_none_or(|owner| &record.schedule.terms.owner == owner))
+            .map(|record| record.schedule.clone())
+            .collect();
+
+        schedules.sort_by_key(|schedule| schedule.created_at);
+        schedules
+    }
+
+    /// Stores a schedule in memory and in storage if available
+    fn save(&self, record: &ScheduleRecord) -> Result<(), ScheduleError> {
+        if let Some(storage) = &self.storage {
+            storage.save_schedule(record)?;
+        }
+
+        self.schedules.insert(record.schedule.id.clone(), record.clone());
+        Ok(())
+    }
+}
+
+/// Checks that the owner signed a payload in the schedule context
+fn verify_owner(owner: &Address, payload: &[u8], signature: &DigitalSignature) -> Result<(), ScheduleError> {
+    let public_key = owner
+        .to_public_key()
+        .map_err(|err| ScheduleError::InvalidSchedule(format!("Invalid owner: {}", err)))?;
+
+    match signing::verify(&public_key, SigningContext::Schedule, payload, signature) {
+        Ok(true) => Ok(()),
+        _ => Err(ScheduleError::InvalidSignature),
+    }
+}
+
+/// Gets the balance of an address less what its pending transactions spend
+fn spendable(blockchain: &Blockchain, address: &Address) -> f64 {
+    let pending: f64 = blockchain
+        .get_pending_transactions()
+        .iter()
+        .filter(|transaction| &transaction.sender == address)
+        .map(|transaction| transaction.amount + transaction.fee)
+        .sum();
+
+    blockchain.get_account_state().get_account(address).balance - pending
+}
+
+/// Signs and submits a payment from a session key
+///
+/// # Returns
+///
+/// The ID of the transaction, or why it was rejected
+fn submit(blockchain: &Blockchain, session: &Wallet, recipient: Address, amount: f64, fee: f64) -> Result<String, String> {
+    let mut transaction = Transaction::new(
+        session.address().clone(),
+        recipient,
+        amount,
+        fee,
+        blockchain.get_next_nonce(session.address()),
+    );
+    transaction.sign(session).map_err(|e| e.to_string())?;
+
+    let transaction_id = transaction.id.clone();
+    blockchain
+        .add_transaction(transaction)
+        .map(|_| transaction_id)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terms(owner: &Wallet, payments: u32) -> ScheduleTerms {
+        ScheduleTerms {
+            owner: owner.address().clone(),
+            recipient: Address("recipient".to_string()),
+            amount: 5.0,
+            interval: 2,
+            payments,
+            start_height: 1,
+        }
+    }
+
+    fn fund(blockchain: &Blockchain, address: &Address, amount: f64) {
+        let mut account = blockchain.get_account_state().get_account(address);
+        account.deposit(amount).unwrap();
+        blockchain.get_account_state().update_account(account);
+    }
+
+    #[test]
+    fn test_register_checks_the_authorization() {
+        let scheduler = Scheduler::new(None).unwrap();
+        let owner = Wallet::new().unwrap();
+        let terms = terms(&owner, 3);
+
+        // Only the owner can authorize, and only for these terms
+        let stranger = sign(&Wallet::new().unwrap(), &terms.payload()).unwrap();
+        assert!(matches!(scheduler.register(terms.clone(), stranger), Err(ScheduleError::InvalidSignature)));
+        let raw = owner.sign(&terms.payload()).unwrap();
+        assert!(matches!(scheduler.register(terms.clone(), raw), Err(ScheduleError::InvalidSignature)));
+
+        let signature = sign(&owner, &terms.payload()).unwrap();
+        let schedule = scheduler.register(terms.clone(), signature.clone()).unwrap();
+        assert_eq!(schedule.id, terms.id());
+        assert_eq!(schedule.status, ScheduleStatus::Active);
+
+        // The same authorization can't be registered twice
+        assert!(matches!(scheduler.register(terms, signature), Err(ScheduleError::AlreadyExists(_))));
+        assert_eq!(scheduler.list(Some(owner.address())).len(), 1);
+    }
+
+    #[test]
+    fn test_payments_follow_the_schedule() {
+        let blockchain = Blockchain::new();
+        let scheduler = Scheduler::new(None).unwrap();
+        let owner = Wallet::new().unwrap();
+        let miner = Wallet::new().unwrap();
+        let terms = terms(&owner, 2);
+        let schedule = scheduler.register(terms.clone(), sign(&owner, &terms.payload()).unwrap()).unwrap();
+
+        // Not due before the start height
+        assert!(scheduler.submit_due(&blockchain).unwrap().is_empty());
+        blockchain.mine_block(&miner.address().0).unwrap();
+
+        // Due, but the session key isn't funded yet
+        let unfunded = scheduler.submit_due(&blockchain).unwrap();
+        assert!(unfunded[0].last_error.is_some());
+        assert!(unfunded[0].transactions.is_empty());
+
+        fund(&blockchain, &schedule.session_address, 20.0);
+        let paid = scheduler.submit_due(&blockchain).unwrap();
+        assert_eq!(paid[0].transactions.len(), 1);
+        assert_eq!(paid[0].next_height, 3);
+
+        // Nothing more until the interval has passed
+        blockchain.mine_block(&miner.address().0).unwrap();
+        assert!(scheduler.submit_due(&blockchain).unwrap().is_empty());
+        blockchain.mine_block(&miner.address().0).unwrap();
+
+        let last = scheduler.submit_due(&blockchain).unwrap();
+        assert_eq!(last[0].status, ScheduleStatus::Completed);
+        blockchain.mine_block(&miner.address().0).unwrap();
+        assert_eq!(blockchain.get_account_state().get_account(&terms.recipient).balance, 10.0);
+    }
+
+    #[test]
+    fn test_cancel_refunds_the_owner() {
+        let blockchain = Blockchain::new();
+        let scheduler = Scheduler::new(None).unwrap();
+        let owner = Wallet::new().unwrap();
+        let terms = terms(&owner, 0);
+        let schedule = scheduler.register(terms.clone(), sign(&owner, &terms.payload()).unwrap()).unwrap();
+        fund(&blockchain, &schedule.session_address, 20.0);
+
+        // Only the owner can cancel
+        let stranger = sign(&Wallet::new().unwrap(), &cancel_payload(&schedule.id)).unwrap();
+        assert!(matches!(
+            scheduler.cancel(&schedule.id, &stranger, &blockchain),
+            Err(ScheduleError::InvalidSignature)
+        ));
+
+        let signature = sign(&owner, &cancel_payload(&schedule.id)).unwrap();
+        let cancelled = scheduler.cancel(&schedule.id, &signature, &blockchain).unwrap();
+        assert_eq!(cancelled.status, ScheduleStatus::Cancelled);
+        let refund = blockchain
+            .get_pending_transactions()
+            .into_iter()
+            .find(|transaction| Some(&transaction.id) == cancelled.refund_transaction_id.as_ref())
+            .unwrap();
+        assert_eq!(refund.recipient, terms.owner);
+        assert!((refund.amount + refund.fee - 20.0).abs() < 1e-9);
+
+        // A cancelled schedule pays no more
+        blockchain.mine_block(&Wallet::new().unwrap().address().0).unwrap();
+        assert!(scheduler.submit_due(&blockchain).unwrap().is_empty());
+        assert!(scheduler.cancel(&schedule.id, &signature, &blockchain).is_err());
+    }
+}
@@ -0,0 +1,677 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use super::account::{AccountError, AccountState};
+use super::block::Block;
+use super::crypto::{Address, CryptoError, DigitalSignature, Wallet};
+use super::signing::{self, SigningContext};
+use super::transaction::Transaction;
+
+/// Address locked amounts are paid to, which has no key so only an unlock releases them
+pub const SCRIPT_ADDRESS: &str = "scripts";
+
+/// Deepest nesting of `all` and `any` in a condition
+pub const MAX_CONDITION_DEPTH: usize = 4;
+
+/// Most terms a condition may have, nested ones included
+pub const MAX_CONDITION_TERMS: usize = 16;
+
+/// Most keys the signature terms of a condition may list together
+pub const MAX_CONDITION_KEYS: usize = 16;
+
+/// Longest preimage a witness may reveal, in bytes
+pub const MAX_PREIMAGE_BYTES: usize = 256;
+
+/// Errors that can occur with script conditions
+#[derive(Debug, Error)]
+pub enum ScriptError {
+    #[error("Invalid condition: {0}")]
+    InvalidCondition(String),
+
+    #[error("Invalid lock: {0}")]
+    InvalidLock(String),
+
+    #[error("Invalid unlock: {0}")]
+    InvalidUnlock(String),
+
+    #[error("Lock {0} is not open")]
+    LockNotFound(String),
+
+    #[error("Lock {0} is already being spent by a pending transaction")]
+    Pending(String),
+
+    #[error("Condition of lock {lock} is not met: {reason}")]
+    Unsatisfied { lock: String, reason: String },
+}
+
+/// A spending condition, a small language without loops or calls
+///
+/// Conditions are evaluated once, when the recipient of a lock spends it,
+/// against the witness of the spending transaction and the height of its
+/// block. Their size is bounded, so evaluating one costs at most a few
+/// signature checks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    /// At least `required` of the keys signed the lock ID, see `sign_witness`
+    Signatures { required: u32, keys: Vec<String> },
+
+    /// The spend is in a block at this height or later
+    NotBefore { height: u64 },
+
+    /// The witness reveals a preimage whose SHA-256 hash is this hash, 64 hex digits
+    Hashlock { hash: String },
+
+    /// Every condition is met
+    All { conditions: Vec<Condition> },
+
+    /// At least one condition is met
+    Any { conditions: Vec<Condition> },
+}
+
+impl Condition {
+    /// Checks that the condition is well formed and within the size limits
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if the condition can be attached to a lock
+    pub fn validate(&self) -> Result<(), ScriptError> {
+        let mut terms = 0;
+        let mut keys = 0;
+        self.validate_term(1, &mut terms, &mut keys)?;
+
+        if terms > MAX_CONDITION_TERMS {
+            return Err(ScriptError::InvalidCondition(format!(
+                "It has {} terms, at most {} are allowed",
+                terms, MAX_CONDITION_TERMS
+            )));
+        }
+        if keys > MAX_CONDITION_KEYS {
+            return Err(ScriptError::InvalidCondition(format!(
+                "It lists {} keys, at most {} are allowed",
+                keys, MAX_CONDITION_KEYS
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Checks a term and the terms nested in it, counting them and their keys
+    fn validate_term(&self, depth: usize, terms: &mut usize, keys: &mut usize) -> Result<(), ScriptError> {
+        if depth > MAX_CONDITION_DEPTH {
+            return Err(ScriptError::InvalidCondition(format!(
+                "It nests deeper than {} levels",
+                MAX_CONDITION_DEPTH
+            )));
+        }
+        *terms += 1;
+
+        match self {
+            Condition::Signatures { required, keys: listed } => {
+                if *required == 0 || *required as usize > listed.len() {
+                    return Err(ScriptError::InvalidCondition(format!(
+                        "It requires {} of {} signatures",
+                        required,
+                        listed.len()
+                    )));
+                }
+                let mut unique = HashSet::new();
+                for key in listed {
+                    Address(key.clone())
+                        .to_public_key()
+                        .map_err(|err| ScriptError::InvalidCondition(format!("Key {} is invalid: {}", key, err)))?;
+                    if !unique.insert(key) {
+                        return Err(ScriptError::InvalidCondition(format!("Key {} is listed twice", key)));
+                    }
+                }
+                *keys += listed.len();
+            }
+            Condition::NotBefore { .. } => {}
+            Condition::Hashlock { hash } => {
+                if hash.len() != 64 || !hash.bytes().all(|byte| byte.is_ascii_digit() || (b'a'..=b'f').contains(&byte)) {
+                    return Err(ScriptError::InvalidCondition(format!(
+                        "Hash {} must be 64 lowercase hex digits",
+                        hash
+                    )));
+                }
+            }
+            Condition::All { conditions } | Condition::Any { conditions } => {
+                if conditions.is_empty() {
+                    return Err(ScriptError::InvalidCondition("It combines no conditions".to_string()));
+                }
+                for condition in conditions {
+                    condition.validate_term(depth + 1, terms, keys)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Evaluates the condition
+    ///
+    /// # Arguments
+    ///
+    /// * `lock` - ID of the lock the signatures must be for
+    /// * `witness` - What the spending transaction reveals
+    /// * `height` - Height of the block the spend is in
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if the condition is met, otherwise why not
+    pub fn evaluate(&self, lock: &str, witness: &Witness, height: u64) -> Result<(), String> {
+        match self {
+            Condition::Signatures { required, keys } => {
+                let signed = keys.iter().filter(|key| witness.is_signed_by(key, lock)).count();
+                if signed < *required as usize {
+                    return Err(format!("{} of {} required signatures", signed, required));
+                }
+                Ok(())
+            }
+            Condition::NotBefore { height: not_before } => {
+                if height < *not_before {
+                    return Err(format!("it can't be spent before block {}", not_before));
+                }
+                Ok(())
+            }
+            Condition::Hashlock { hash } => match &witness.preimage {
+                Some(preimage) if format!("{:x}", Sha256::digest(preimage.as_bytes())) == *hash => Ok(()),
+                Some(_) => Err("the preimage doesn't match the hash".to_string()),
+                None => Err("no preimage was revealed".to_string()),
+            },
+            Condition::All { conditions } => conditions
+                .iter()
+                .try_for_each(|condition| condition.evaluate(lock, witness, height)),
+            Condition::Any { conditions } => {
+                let mut reasons = Vec::new();
+                for condition in conditions {
+                    match condition.evaluate(lock, witness, height) {
+                        Ok(()) => return Ok(()),
+                        Err(reason) => reasons.push(reason),
+                    }
+                }
+                Err(format!("none of: {}", reasons.join("; ")))
+            }
+        }
+    }
+}
+
+/// A signature of a lock ID by a key a condition lists
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct WitnessSignature {
+    /// Address of the key that signed
+    pub address: String,
+
+    /// The signature
+    #[schema(value_type = String)]
+    pub signature: DigitalSignature,
+}
+
+/// What a spending transaction reveals to meet the condition of a lock
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct Witness {
+    /// Signatures of the lock ID, see `sign_witness`
+    #[serde(default)]
+    pub signatures: Vec<WitnessSignature>,
+
+    /// Preimage of a hashlock
+    #[serde(default)]
+    pub preimage: Option<String>,
+}
+
+impl Witness {
+    /// Checks that the witness is within the size limits
+    fn validate(&self) -> Result<(), ScriptError> {
+        if self.signatures.len() > MAX_CONDITION_KEYS {
+            return Err(ScriptError::InvalidUnlock(format!(
+                "It carries {} signatures, at most {} are allowed",
+                self.signatures.len(),
+                MAX_CONDITION_KEYS
+            )));
+        }
+        if self.preimage.as_ref().is_some_and(|preimage| preimage.len() > MAX_PREIMAGE_BYTES) {
+            return Err(ScriptError::InvalidUnlock(format!(
+                "The preimage is longer than {} bytes",
+                MAX_PREIMAGE_BYTES
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Checks if the witness holds a valid signature of a lock ID by a key
+    fn is_signed_by(&self, key: &str, lock: &str) -> bool {
+        let public_key = match Address(key.to_string()).to_public_key() {
+            Ok(public_key) => public_key,
+            Err(_) => return false,
+        };
+
+        self.signatures
+            .iter()
+            .filter(|signature| signature.address == key)
+            .any(|signature| {
+                signing::verify(&public_key, SigningContext::ScriptWitness, lock.as_bytes(), &signature.signature)
+                    .unwrap_or(false)
+            })
+    }
+}
+
+/// Signs a lock ID for the witness of a spend
+///
+/// # Arguments
+///
+/// * `wallet` - The wallet of a key the condition lists
+/// * `lock` - ID of the lock
+///
+/// # Returns
+///
+/// The signature to add to the witness
+pub fn sign_witness(wallet: &Wallet, lock: &str) -> Result<WitnessSignature, CryptoError> {
+    Ok(WitnessSignature {
+        address: wallet.address().0.clone(),
+        signature: signing::sign(wallet, SigningContext::ScriptWitness, lock.as_bytes())?,
+    })
+}
+
+/// What a script transaction does
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptAction {
+    /// Locks the amount, paid to `SCRIPT_ADDRESS`, for a recipient who may only spend it under the condition
+    Lock { recipient: String, condition: Condition },
+
+    /// Spends a lock, by the ID of the transaction that made it, sending its amount to the transaction's recipient
+    Unlock { lock: String, witness: Witness },
+}
+
+/// Checks that a script transaction is well formed
+///
+/// Whether the lock it spends is open and its condition met depends on the
+/// chain and is checked by `ScriptLocks::check_spend`.
+///
+/// # Arguments
+///
+/// * `transaction` - The transaction to check
+///
+/// # Returns
+///
+/// Ok(()) if the transaction carries no script action, a lock with a valid condition
+/// paid to the script address, or an unlock paying out of it
+pub fn check_script(transaction: &Transaction) -> Result<(), ScriptError> {
+    let action = match &transaction.script {
+        Some(action) => action,
+        None => return Ok(()),
+    };
+
+    match action {
+        ScriptAction::Lock { recipient, condition } => {
+            if transaction.is_coinbase() {
+                return Err(ScriptError::InvalidLock("A coinbase transaction can't lock its amount".to_string()));
+            }
+            if transaction.recipient.0 != SCRIPT_ADDRESS {
+                return Err(ScriptError::InvalidLock(format!("A lock must be paid to {}", SCRIPT_ADDRESS)));
+            }
+            if recipient.is_empty() || recipient == SCRIPT_ADDRESS {
+                return Err(ScriptError::InvalidLock(format!("{:?} can't spend a lock", recipient)));
+            }
+            condition.validate()
+        }
+        ScriptAction::Unlock { lock, witness } => {
+            if transaction.is_coinbase() {
+                return Err(ScriptError::InvalidUnlock("A coinbase transaction can't spend a lock".to_string()));
+            }
+            if lock.is_empty() {
+                return Err(ScriptError::InvalidUnlock("The lock is empty".to_string()));
+            }
+            if transaction.recipient.0 == SCRIPT_ADDRESS {
+                return Err(ScriptError::InvalidUnlock(format!("An unlock can't pay {}", SCRIPT_ADDRESS)));
+            }
+            witness.validate()
+        }
+    }
+}
+
+/// Moves the amount an unlock releases from the script address to its sender
+///
+/// Called right before the transaction's transfer, which then sends the
+/// amount on to its recipient. Other transactions release nothing.
+///
+/// # Arguments
+///
+/// * `accounts` - The account state to update
+/// * `transaction` - The transaction being applied
+///
+/// # Returns
+///
+/// Result with () if successful
+pub fn release(accounts: &AccountState, transaction: &Transaction) -> Result<(), AccountError> {
+    if !matches!(transaction.script, Some(ScriptAction::Unlock { .. })) {
+        return Ok(());
+    }
+
+    let mut locked = accounts.get_account(&Address(SCRIPT_ADDRESS.to_string()));
+    let mut spender = accounts.get_account(&transaction.sender);
+    locked.withdraw(transaction.amount)?;
+    spender.deposit(transaction.amount)?;
+    accounts.update_account(locked);
+    accounts.update_account(spender);
+
+    Ok(())
+}
+
+/// An amount locked under a condition
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ScriptLock {
+    /// ID of the transaction that made the lock
+    pub id: String,
+
+    /// The address that locked the amount
+    #[schema(value_type = String)]
+    pub owner: Address,
+
+    /// The address that may spend it once the condition is met
+    #[schema(value_type = String)]
+    pub recipient: Address,
+
+    /// The locked amount, which a spend must send on in full
+    pub amount: f64,
+
+    /// The condition
+    pub condition: Condition,
+
+    /// Height of the block that made the lock
+    pub locked_at: u64,
+}
+
+/// The open locks on the chain, updated as each block is committed
+///
+/// A lock opens after the block that includes it and closes with the block
+/// that includes its spend, so every spend of a block is checked against the
+/// locks at its parent and a lock can't be spent twice.
+#[derive(Debug, Default)]
+pub struct ScriptLocks {
+    locks: Mutex<HashMap<String, ScriptLock>>,
+}
+
+impl ScriptLocks {
+    /// Creates the locks of an empty chain
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates the locks after a chain
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks of the chain
+    pub fn replay(blocks: &[Block]) -> Self {
+        let locks = ScriptLocks::new();
+        locks.rebuild(blocks);
+        locks
+    }
+
+    /// Applies the locks and spends of a committed block
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block that was added to the chain
+    pub fn record_block(&self, block: &Block) {
+        let mut locks = self.locks.lock().unwrap();
+
+        for transaction in &block.transactions {
+            if check_script(transaction).is_err() {
+                continue;
+            }
+            match &transaction.script {
+                Some(ScriptAction::Lock { recipient, condition }) => {
+                    locks.insert(
+                        transaction.id.clone(),
+                        ScriptLock {
+                            id: transaction.id.clone(),
+                            owner: transaction.sender.clone(),
+                            recipient: Address(recipient.clone()),
+                            amount: transaction.amount,
+                            condition: condition.clone(),
+                            locked_at: block.index,
+                        },
+                    );
+                }
+                Some(ScriptAction::Unlock { lock, .. }) => {
+                    locks.remove(lock);
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Replaces the locks with the locks of a chain
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks of the chain
+    pub fn rebuild(&self, blocks: &[Block]) {
+        self.locks.lock().unwrap().clear();
+
+        for block in blocks {
+            self.record_block(block);
+        }
+    }
+
+    /// Gets an open lock
+    pub fn get(&self, id: &str) -> Option<ScriptLock> {
+        self.locks.lock().unwrap().get(id).cloned()
+    }
+
+    /// Gets the open locks an address may spend, oldest first
+    pub fn for_recipient(&self, recipient: &Address) -> Vec<ScriptLock> {
+        let mut locks: Vec<ScriptLock> = self
+            .locks
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|lock| &lock.recipient == recipient)
+            .cloned()
+            .collect();
+        locks.sort_by(|a, b| a.locked_at.cmp(&b.locked_at).then_with(|| a.id.cmp(&b.id)));
+        locks
+    }
+
+    /// Checks that a transaction may spend the lock it unlocks in a block
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The transaction to check
+    /// * `height` - Height of the block the transaction is in or meant for
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if the transaction unlocks nothing, or an open lock of its sender
+    /// whose amount it sends on in full and whose condition its witness meets
+    pub fn check_spend(&self, transaction: &Transaction, height: u64) -> Result<(), ScriptError> {
+        let (id, witness) = match &transaction.script {
+            Some(ScriptAction::Unlock { lock, witness }) => (lock, witness),
+            _ => return Ok(()),
+        };
+
+        let lock = self.get(id).ok_or_else(|| ScriptError::LockNotFound(id.clone()))?;
+        if lock.recipient != transaction.sender {
+            return Err(ScriptError::InvalidUnlock(format!(
+                "Lock {} may only be spent by {}",
+                id, lock.recipient
+            )));
+        }
+        if transaction.amount != lock.amount {
+            return Err(ScriptError::InvalidUnlock(format!(
+                "Lock {} holds {}, the spend sends {}",
+                id, lock.amount, transaction.amount
+            )));
+        }
+
+        lock.condition
+            .evaluate(id, witness, height)
+            .map_err(|reason| ScriptError::Unsatisfied { lock: id.clone(), reason })
+    }
+
+    /// Checks every spend of a block
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block to check, following the last block recorded
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if every spend is allowed and no lock is spent twice
+    pub fn check_block(&self, block: &Block) -> Result<(), ScriptError> {
+        let mut spent = HashSet::new();
+
+        for transaction in &block.transactions {
+            if let Some(ScriptAction::Unlock { lock, .. }) = &transaction.script {
+                if !spent.insert(lock) {
+                    return Err(ScriptError::InvalidUnlock(format!(
+                        "Lock {} is spent twice in block {}",
+                        lock, block.index
+                    )));
+                }
+                self.check_spend(transaction, block.index)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(preimage: &str) -> String {
+        format!("{:x}", Sha256::digest(preimage.as_bytes()))
+    }
+
+    #[test]
+    fn test_conditions_are_bounded() {
+        let key = Wallet::new().unwrap().address().0.clone();
+        let signatures = Condition::Signatures {
+            required: 1,
+            keys: vec![key.clone()],
+        };
+        signatures.validate().unwrap();
+
+        let invalid = [
+            Condition::Signatures { required: 2, keys: vec![key.clone()] },
+            Condition::Signatures { required: 1, keys: vec![key.clone(), key.clone()] },
+            Condition::Signatures { required: 1, keys: vec!["not-a-key".to_string()] },
+            Condition::Hashlock { hash: "ABC".to_string() },
+            Condition::Any { conditions: vec![] },
+        ];
+        for condition in invalid {
+            assert!(condition.validate().is_err(), "{:?}", condition);
+        }
+
+        let mut deep = signatures.clone();
+        for _ in 0..MAX_CONDITION_DEPTH {
+            deep = Condition::All { conditions: vec![deep] };
+        }
+        assert!(deep.validate().is_err());
+        let wide = Condition::Any {
+            conditions: vec![Condition::NotBefore { height: 1 }; MAX_CONDITION_TERMS],
+        };
+        assert!(wide.validate().is_err());
+    }
+
+    #[test]
+    fn test_conditions_evaluate_against_the_witness() {
+        let alice = Wallet::new().unwrap();
+        let bob = Wallet::new().unwrap();
+        let carol = Wallet::new().unwrap();
+        let two_of_three = Condition::Signatures {
+            required: 2,
+            keys: [&alice, &bob, &carol].iter().map(|wallet| wallet.address().0.clone()).collect(),
+        };
+
+        let one = Witness {
+            signatures: vec![sign_witness(&alice, "lock").unwrap()],
+            preimage: None,
+        };
+        assert!(two_of_three.evaluate("lock", &one, 1).is_err());
+
+        // The same signer twice counts once, a signature of another lock not at all
+        let mut two = one.clone();
+        two.signatures.push(sign_witness(&alice, "lock").unwrap());
+        two.signatures.push(sign_witness(&bob, "other").unwrap());
+        assert!(two_of_three.evaluate("lock", &two, 1).is_err());
+        two.signatures.push(sign_witness(&carol, "lock").unwrap());
+        two_of_three.evaluate("lock", &two, 1).unwrap();
+
+        // Either both signatures after block 100, or the secret at any time
+        let escrow = Condition::Any {
+            conditions: vec![
+                Condition::All {
+                    conditions: vec![two_of_three, Condition::NotBefore { height: 100 }],
+                },
+                Condition::Hashlock { hash: hash("secret") },
+            ],
+        };
+        escrow.validate().unwrap();
+        assert!(escrow.evaluate("lock", &two, 99).is_err());
+        escrow.evaluate("lock", &two, 100).unwrap();
+        let revealed = Witness {
+            signatures: vec![],
+            preimage: Some("secret".to_string()),
+        };
+        escrow.evaluate("lock", &revealed, 1).unwrap();
+        let wrong = Witness {
+            signatures: vec![],
+            preimage: Some("guess".to_string()),
+        };
+        assert!(escrow.evaluate("lock", &wrong, 1).is_err());
+    }
+
+    #[test]
+    fn test_locks_open_and_close_with_blocks() {
+        let owner = Wallet::new().unwrap();
+        let recipient = Wallet::new().unwrap();
+        let scripts = Address(SCRIPT_ADDRESS.to_string());
+
+        let mut lock = Transaction::new(owner.address().clone(), scripts, 5.0, 0.1, 0).with_script(ScriptAction::Lock {
+            recipient: recipient.address().0.clone(),
+            condition: Condition::NotBefore { height: 3 },
+        });
+        lock.sign(&owner).unwrap();
+        check_script(&lock).unwrap();
+
+        let locks = ScriptLocks::new();
+        locks.record_block(&Block::new(1, vec![lock.clone()], 0, "0".to_string()));
+        assert_eq!(locks.for_recipient(recipient.address()).len(), 1);
+
+        let spend = |sender: &Wallet, amount: f64| {
+            let unlock = ScriptAction::Unlock {
+                lock: lock.id.clone(),
+                witness: Witness::default(),
+            };
+            let mut transaction =
+                Transaction::new(sender.address().clone(), owner.address().clone(), amount, 0.1, 0).with_script(unlock);
+            transaction.sign(sender).unwrap();
+            transaction
+        };
+
+        // Only the recipient, with the full amount, once the condition is met
+        assert!(matches!(locks.check_spend(&spend(&owner, 5.0), 3), Err(ScriptError::InvalidUnlock(_))));
+        assert!(matches!(locks.check_spend(&spend(&recipient, 4.0), 3), Err(ScriptError::InvalidUnlock(_))));
+        assert!(matches!(locks.check_spend(&spend(&recipient, 5.0), 2), Err(ScriptError::Unsatisfied { .. })));
+        locks.check_spend(&spend(&recipient, 5.0), 3).unwrap();
+
+        let twice = Block::new(3, vec![spend(&recipient, 5.0), spend(&recipient, 5.0)], 0, "0".to_string());
+        assert!(locks.check_block(&twice).is_err());
+
+        let spent = Block::new(3, vec![spend(&recipient, 5.0)], 0, "0".to_string());
+        locks.check_block(&spent).unwrap();
+        locks.record_block(&spent);
+        assert!(matches!(locks.check_spend(&spend(&recipient, 5.0), 4), Err(ScriptError::LockNotFound(_))));
+    }
+}
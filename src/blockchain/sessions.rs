@@ -0,0 +1,489 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use super::block::Block;
+use super::crypto::Address;
+use super::transaction::{Transaction, SIGNING_CONTEXT_VERSION};
+
+/// Address authorizations and revocations of session keys are paid to, which has no key so the amount is burned
+pub const SESSIONS_ADDRESS: &str = "sessions";
+
+/// Amount an authorization or revocation pays to the sessions address
+pub const SESSION_CHANGE_AMOUNT: f64 = 0.01;
+
+/// Errors that can occur with session keys
+#[derive(Debug, Error)]
+pub enum SessionError {
+    #[error("Invalid authorization: {0}")]
+    InvalidAuthorization(String),
+
+    #[error("Invalid session spend: {0}")]
+    InvalidSpend(String),
+
+    #[error("{key} is not an authorized session key of {owner}")]
+    NotAuthorized { owner: String, key: String },
+
+    #[error("Session key {key} of {owner} expired at height {expires_at}")]
+    Expired { owner: String, key: String, expires_at: u64 },
+
+    #[error("Session key {key} exceeds its limit: {reason}")]
+    LimitExceeded { key: String, reason: String },
+}
+
+/// What a session transaction does
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionAction {
+    /// Authorizes a key to sign transfers of the sender within limits, replacing an earlier authorization of it
+    Authorize {
+        /// Public key of the session key
+        key: String,
+
+        /// Most the key may spend in all, amounts and fees together
+        max_total: f64,
+
+        /// Most one transaction signed by the key may spend
+        max_per_transaction: f64,
+
+        /// Last height a transaction signed by the key may be mined at
+        expires_at: u64,
+    },
+
+    /// Revokes a session key of the sender
+    Revoke { key: String },
+
+    /// Marks the transaction as signed by a session key of the sender instead of the sender
+    Spend { key: String },
+}
+
+/// Checks that a session transaction is well formed
+///
+/// Whether the key of a spend is authorized and within its limits depends
+/// on the chain and is checked by `SessionKeys::check_spend`.
+///
+/// # Arguments
+///
+/// * `transaction` - The transaction to check
+///
+/// # Returns
+///
+/// Ok(()) if the transaction carries no session action, an authorization or
+/// revocation of another key sent to the sessions address, or
+/// a plain transfer marked as signed by another key
+pub fn check_session(transaction: &Transaction) -> Result<(), SessionError> {
+    let action = match &transaction.session {
+        Some(action) => action,
+        None => return Ok(()),
+    };
+
+    match action {
+        SessionAction::Authorize { key, .. } | SessionAction::Revoke { key } => {
+            let invalid = |reason: String| Err(SessionError::InvalidAuthorization(reason));
+            if transaction.is_coinbase() || transaction.is_disbursement() {
+                return invalid("Only a transaction signed by its sender can manage session keys".to_string());
+            }
+            if transaction.recipient.0 != SESSIONS_ADDRESS {
+                return invalid(format!("An authorization or revocation must be sent to {}", SESSIONS_ADDRESS));
+            }
+            check_key(key, transaction).map_err(SessionError::InvalidAuthorization)?;
+
+            if let SessionAction::Authorize {
+                max_total,
+                max_per_transaction,
+                ..
+            } = action
+            {
+                if !max_total.is_finite() || *max_total <= 0.0 {
+                    return invalid(format!("The total limit {} must be positive", max_total));
+                }
+                if !max_per_transaction.is_finite() || *max_per_transaction <= 0.0 || max_per_transaction > max_total {
+                    return invalid(format!(
+                        "The limit per transaction {} must be positive and at most the total limit {}",
+                        max_per_transaction, max_total
+                    ));
+                }
+            }
+
+            Ok(())
+        }
+        SessionAction::Spend { key } => {
+            let invalid = |reason: &str| Err(SessionError::InvalidSpend(reason.to_string()));
+            if transaction.is_coinbase() || transaction.is_disbursement() {
+                return invalid("Only a transaction of an account can be signed by a session key");
+            }
+            if transaction.recipient.0 == SESSIONS_ADDRESS {
+                return invalid("A session key can't manage session keys");
+            }
+            // Session keys only move coins, the account's other powers stay with its own key
+            if transaction.name.is_some()
+                || transaction.permission.is_some()
+                || transaction.governance.is_some()
+                || transaction.freeze.is_some()
+                || transaction.script.is_some()
+                || transaction.inheritance.is_some()
+            {
+                return invalid("A session key may only sign transfers");
+            }
+            check_key(key, transaction).map_err(SessionError::InvalidSpend)
+        }
+    }
+}
+
+/// Checks the key a session transaction names
+fn check_key(key: &str, transaction: &Transaction) -> Result<(), String> {
+    // Older versions don't sign the session action
+    if transaction.version < SIGNING_CONTEXT_VERSION {
+        return Err(format!("A transaction of version {} can't use session keys", transaction.version));
+    }
+    if key == transaction.sender.0 {
+        return Err("An account can't be its own session key".to_string());
+    }
+    Address(key.to_string())
+        .to_public_key()
+        .map(|_| ())
+        .map_err(|_| format!("{:?} is not a public key", key))
+}
+
+/// Gets what a transaction signed by a session key counts against its limits
+///
+/// The amount, and the fee unless a fee payer pays it.
+pub fn session_spend(transaction: &Transaction) -> f64 {
+    if transaction.fee_paid_by() == &transaction.sender {
+        transaction.amount + transaction.fee
+    } else {
+        transaction.amount
+    }
+}
+
+/// A key an account authorized to sign its transfers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SessionKey {
+    /// The account the key signs for
+    #[schema(value_type = String)]
+    pub owner: Address,
+
+    /// Public key of the session key
+    pub key: String,
+
+    /// Most the key may spend in all
+    pub max_total: f64,
+
+    /// Most one transaction signed by the key may spend
+    pub max_per_transaction: f64,
+
+    /// Last height a transaction signed by the key may be mined at
+    pub expires_at: u64,
+
+    /// What transactions signed by the key spent so far
+    pub spent: f64,
+
+    /// Height of the block that authorized the key
+    pub authorized_at: u64,
+}
+
+impl SessionKey {
+    /// Gets what the key may still spend
+    pub fn remaining(&self) -> f64 {
+        (self.max_total - self.spent).max(0.0)
+    }
+
+    /// Checks that a transaction signed by the key fits its limits
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The transaction signed by the key
+    /// * `height` - Height of the block the transaction is in or meant for
+    /// * `spent` - What the key spent on top of `spent`, e.g. in pending transactions
+    fn check(&self, transaction: &Transaction, height: u64, spent: f64) -> Result<(), SessionError> {
+        if height > self.expires_at {
+            return Err(SessionError::Expired {
+                owner: self.owner.0.clone(),
+                key: self.key.clone(),
+                expires_at: self.expires_at,
+            });
+        }
+
+        let amount = session_spend(transaction);
+        if amount > self.max_per_transaction {
+            return Err(SessionError::LimitExceeded {
+                key: self.key.clone(),
+                reason: format!("spends {}, at most {} per transaction", amount, self.max_per_transaction),
+            });
+        }
+        if self.spent + spent + amount > self.max_total {
+            return Err(SessionError::LimitExceeded {
+                key: self.key.clone(),
+                reason: format!(
+                    "spends {}, only {} of {} is left",
+                    amount,
+                    (self.remaining() - spent).max(0.0),
+                    self.max_total
+                ),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// The session keys on the chain, updated as each block is committed
+///
+/// An authorization takes effect after the block that includes it, so
+/// every transaction signed by a session key is checked against the keys at
+/// the parent of its block. Spends count against the key once mined, and a
+/// new authorization of the same key starts over with its own limits.
+#[derive(Debug, Default)]
+pub struct SessionKeys {
+    keys: Mutex<HashMap<(Address, String), SessionKey>>,
+}
+
+impl SessionKeys {
+    /// Creates the session keys of an empty chain
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates the session keys after a chain
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks of the chain
+    pub fn replay(blocks: &[Block]) -> Self {
+        let keys = SessionKeys::new();
+        keys.rebuild(blocks);
+        keys
+    }
+
+    /// Applies the authorizations, revocations and spends of a committed block
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block that was added to the chain
+    pub fn record_block(&self, block: &Block) {
+        let mut keys = self.keys.lock().unwrap();
+
+        for transaction in &block.transactions {
+            if check_session(transaction).is_err() {
+                continue;
+            }
+            match &transaction.session {
+                Some(SessionAction::Authorize {
+                    key,
+                    max_total,
+                    max_per_transaction,
+                    expires_at,
+                }) => {
+                    keys.insert(
+                        (transaction.sender.clone(), key.clone()),
+                        SessionKey {
+                            owner: transaction.sender.clone(),
+                            key: key.clone(),
+                            max_total: *max_total,
+                            max_per_transaction: *max_per_transaction,
+                            expires_at: *expires_at,
+                            spent: 0.0,
+                            authorized_at: block.index,
+                        },
+                    );
+                }
+                Some(SessionAction::Revoke { key }) => {
+                    keys.remove(&(transaction.sender.clone(), key.clone()));
+                }
+                Some(SessionAction::Spend { key }) => {
+                    if let Some(session) = keys.get_mut(&(transaction.sender.clone(), key.clone())) {
+                        session.spent += session_spend(transaction);
+                    }
+                }
+                None => {}
+            }
+        }
+    }
+
+    /// Replaces the session keys with the session keys of a chain
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks of the chain
+    pub fn rebuild(&self, blocks: &[Block]) {
+        self.keys.lock().unwrap().clear();
+
+        for block in blocks {
+            self.record_block(block);
+        }
+    }
+
+    /// Gets an authorized session key
+    ///
+    /// # Arguments
+    ///
+    /// * `owner` - The account the key signs for
+    /// * `key` - Public key of the session key
+    pub fn get(&self, owner: &Address, key: &str) -> Option<SessionKey> {
+        self.keys.lock().unwrap().get(&(owner.clone(), key.to_string())).cloned()
+    }
+
+    /// Gets the session keys of an account, expired ones included, oldest first
+    pub fn for_owner(&self, owner: &Address) -> Vec<SessionKey> {
+        let mut keys: Vec<SessionKey> = self
+            .keys
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|session| &session.owner == owner)
+            .cloned()
+            .collect();
+        keys.sort_by(|a, b| a.authorized_at.cmp(&b.authorized_at).then_with(|| a.key.cmp(&b.key)));
+        keys
+    }
+
+    /// Checks that a transaction signed by a session key may spend in a block
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The transaction to check
+    /// * `height` - Height of the block the transaction is in or meant for
+    /// * `spent` - What other transactions of the key spend before it, in the same block or pending
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if the transaction is not signed by a session key, or by one its
+    /// sender authorized that has not expired and whose limits it fits
+    pub fn check_spend(&self, transaction: &Transaction, height: u64, spent: f64) -> Result<(), SessionError> {
+        let key = match &transaction.session {
+            Some(SessionAction::Spend { key }) => key,
+            _ => return Ok(()),
+        };
+
+        let session = self.get(&transaction.sender, key).ok_or_else(|| SessionError::NotAuthorized {
+            owner: transaction.sender.0.clone(),
+            key: key.clone(),
+        })?;
+        session.check(transaction, height, spent)
+    }
+
+    /// Checks every transaction signed by a session key in a block
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block to check, following the last block recorded
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if every spend is authorized and the spends of each key together fit its limits
+    pub fn check_block(&self, block: &Block) -> Result<(), SessionError> {
+        let mut spent: HashMap<(&Address, &String), f64> = HashMap::new();
+
+        for transaction in &block.transactions {
+            if let Some(SessionAction::Spend { key }) = &transaction.session {
+                let before = spent.entry((&transaction.sender, key)).or_insert(0.0);
+                self.check_spend(transaction, block.index, *before)?;
+                *before += session_spend(transaction);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::crypto::Wallet;
+
+    fn authorize(owner: &Wallet, key: &Wallet, max_total: f64, max_per_transaction: f64, expires_at: u64) -> Transaction {
+        let mut transaction = Transaction::new(owner.address().clone(), Address(SESSIONS_ADDRESS.to_string()), SESSION_CHANGE_AMOUNT, 0.1, 0)
+            .with_session(SessionAction::Authorize {
+                key: key.address().0.clone(),
+                max_total,
+                max_per_transaction,
+                expires_at,
+            });
+        transaction.sign(owner).unwrap();
+        transaction
+    }
+
+    fn spend(owner: &Wallet, key: &Wallet, amount: f64, nonce: u64) -> Transaction {
+        let mut transaction = Transaction::new(owner.address().clone(), Address("bob".to_string()), amount, 0.1, nonce)
+            .with_session(SessionAction::Spend { key: key.address().0.clone() });
+        transaction.sign(key).unwrap();
+        transaction
+    }
+
+    #[test]
+    fn test_session_transactions_are_well_formed() {
+        let owner = Wallet::new().unwrap();
+        let key = Wallet::new().unwrap();
+
+        let authorization = authorize(&owner, &key, 10.0, 2.0, 5);
+        check_session(&authorization).unwrap();
+        check_session(&spend(&owner, &key, 1.0, 1)).unwrap();
+
+        // Limits must make sense and the key can't be the owner's
+        let invalid = [
+            authorize(&owner, &key, 1.0, 2.0, 5),
+            authorize(&owner, &key, 0.0, 0.0, 5),
+            authorize(&owner, &owner, 10.0, 2.0, 5),
+        ];
+        for transaction in invalid {
+            assert!(matches!(check_session(&transaction), Err(SessionError::InvalidAuthorization(_))));
+        }
+
+        // Session keys only sign transfers
+        let mut naming = Transaction::new(owner.address().clone(), Address("names".to_string()), 1.0, 0.1, 1)
+            .with_name("alice")
+            .with_session(SessionAction::Spend { key: key.address().0.clone() });
+        naming.sign(&key).unwrap();
+        assert!(matches!(check_session(&naming), Err(SessionError::InvalidSpend(_))));
+
+        // Only the session key signs a spend, the owner's signature doesn't verify
+        let mut by_owner = Transaction::new(owner.address().clone(), Address("bob".to_string()), 1.0, 0.1, 1)
+            .with_session(SessionAction::Spend { key: key.address().0.clone() });
+        assert!(by_owner.sign(&owner).is_err());
+        assert!(spend(&owner, &key, 1.0, 1).verify_signature().unwrap());
+    }
+
+    #[test]
+    fn test_spends_are_checked_against_the_limits() {
+        let owner = Wallet::new().unwrap();
+        let key = Wallet::new().unwrap();
+        let keys = SessionKeys::new();
+
+        // Spends before the authorization is mined are refused
+        assert!(matches!(
+            keys.check_spend(&spend(&owner, &key, 1.0, 1), 1, 0.0),
+            Err(SessionError::NotAuthorized { .. })
+        ));
+
+        keys.record_block(&Block::new(1, vec![authorize(&owner, &key, 3.0, 2.0, 4)], 0, "0".to_string()));
+        keys.check_spend(&spend(&owner, &key, 1.5, 1), 2, 0.0).unwrap();
+        assert!(matches!(
+            keys.check_spend(&spend(&owner, &key, 2.5, 1), 2, 0.0),
+            Err(SessionError::LimitExceeded { .. })
+        ));
+        assert!(matches!(
+            keys.check_spend(&spend(&owner, &key, 1.0, 1), 5, 0.0),
+            Err(SessionError::Expired { expires_at: 4, .. })
+        ));
+
+        // Spends of one block count together, and mined spends against the rest of the chain
+        let block = Block::new(2, vec![spend(&owner, &key, 1.45, 1), spend(&owner, &key, 1.45, 2)], 0, "0".to_string());
+        assert!(matches!(keys.check_block(&block), Err(SessionError::LimitExceeded { .. })));
+        let block = Block::new(2, vec![spend(&owner, &key, 1.4, 1)], 0, "0".to_string());
+        keys.check_block(&block).unwrap();
+        keys.record_block(&block);
+        let session = keys.get(owner.address(), &key.address().0).unwrap();
+        assert!((session.remaining() - 1.5).abs() < 1e-9);
+        assert!(keys.check_spend(&spend(&owner, &key, 1.5, 2), 3, 0.0).is_err());
+
+        // A revoked key signs nothing
+        let mut revoke = Transaction::new(owner.address().clone(), Address(SESSIONS_ADDRESS.to_string()), SESSION_CHANGE_AMOUNT, 0.1, 2)
+            .with_session(SessionAction::Revoke { key: key.address().0.clone() });
+        revoke.sign(&owner).unwrap();
+        keys.record_block(&Block::new(3, vec![revoke], 0, "0".to_string()));
+        assert!(keys.for_owner(owner.address()).is_empty());
+    }
+}
@@ -0,0 +1,135 @@
+use ed25519_dalek::VerifyingKey;
+
+use super::crypto::{verify_signature, CryptoError, DigitalSignature, Signer};
+
+/// What a signature is for
+///
+/// Every payload is prefixed with the tag of its context before it is signed,
+/// so a signature produced for one purpose never verifies for another, even
+/// if the payload bytes happen to be the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigningContext {
+    /// Transactions
+    Transaction,
+
+    /// Transactions of the educational UTXO ledger
+    UtxoTransaction,
+
+    /// Transactions of the UTXO ledger with hidden amounts
+    ConfidentialTransaction,
+
+    /// Free-form messages signed by a wallet
+    Message,
+
+    /// Handshakes between peers
+    PeerHandshake,
+
+    /// Webhook deliveries
+    Webhook,
+
+    /// Witnesses meeting the condition of a script lock
+    ScriptWitness,
+
+    /// Fee payers agreeing to pay the fee of a transaction
+    FeePayer,
+
+    /// Owners authorizing or cancelling scheduled payments
+    Schedule,
+
+    /// Accounts requesting withdrawals from the hot wallet
+    Withdrawal,
+}
+
+impl SigningContext {
+    /// Gets the tag that prefixes payloads signed in this context
+    ///
+    /// Tags end in a NUL byte, so no tag is a prefix of another.
+    pub fn tag(&self) -> &'static [u8] {
+        match self {
+            SigningContext::Transaction => b"my_blockchain/transaction/v1\0",
+            SigningContext::UtxoTransaction => b"my_blockchain/utxo-transaction/v1\0",
+            SigningContext::ConfidentialTransaction => b"my_blockchain/confidential-transaction/v1\0",
+            SigningContext::Message => b"my_blockchain/message/v1\0",
+            SigningContext::PeerHandshake => b"my_blockchain/peer-handshake/v1\0",
+            SigningContext::Webhook => b"my_blockchain/webhook/v1\0",
+            SigningContext::ScriptWitness => b"my_blockchain/script-witness/v1\0",
+            SigningContext::FeePayer => b"my_blockchain/fee-payer/v1\0",
+            SigningContext::Schedule => b"my_blockchain/schedule/v1\0",
+            SigningContext::Withdrawal => b"my_blockchain/withdrawal/v1\0",
+        }
+    }
+
+    /// Gets the bytes actually signed for a payload
+    pub(crate) fn message(&self, payload: &[u8]) -> Vec<u8> {
+        [self.tag(), payload].concat()
+    }
+}
+
+/// Signs a payload in a context
+///
+/// # Arguments
+///
+/// * `wallet` - The wallet or child key to sign with
+/// * `context` - What the signature is for
+/// * `payload` - The bytes to sign
+///
+/// # Returns
+///
+/// The signature
+pub fn sign(wallet: &impl Signer, context: SigningContext, payload: &[u8]) -> Result<DigitalSignature, CryptoError> {
+    wallet.sign(&context.message(payload))
+}
+
+/// Verifies a signature made in a context
+///
+/// # Arguments
+///
+/// * `public_key` - The key of the signer
+/// * `context` - What the signature must be for
+/// * `payload` - The signed bytes
+/// * `signature` - The signature
+///
+/// # Returns
+///
+/// Whether the signature is valid for the payload in this context
+pub fn verify(
+    public_key: &VerifyingKey,
+    context: SigningContext,
+    payload: &[u8],
+    signature: &DigitalSignature,
+) -> Result<bool, CryptoError> {
+    verify_signature(&context.message(payload), signature, public_key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::crypto::Wallet;
+
+    #[test]
+    fn test_signatures_do_not_cross_contexts() {
+        let wallet = Wallet::new().unwrap();
+        let payload = b"pay 10 coins";
+
+        let signature = sign(&wallet, SigningContext::Transaction, payload).unwrap();
+        assert!(verify(wallet.public_key(), SigningContext::Transaction, payload, &signature).unwrap());
+
+        for context in [
+            SigningContext::UtxoTransaction,
+            SigningContext::ConfidentialTransaction,
+            SigningContext::Message,
+            SigningContext::PeerHandshake,
+            SigningContext::Webhook,
+            SigningContext::ScriptWitness,
+            SigningContext::FeePayer,
+            SigningContext::Schedule,
+            SigningContext::Withdrawal,
+        ] {
+            assert!(!verify(wallet.public_key(), context, payload, &signature).unwrap());
+        }
+
+        // A raw signature over the same bytes is not valid in any context
+        let raw = wallet.sign(payload).unwrap();
+        assert!(!verify(wallet.public_key(), SigningContext::Transaction, payload, &raw).unwrap());
+    }
+}
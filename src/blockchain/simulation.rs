@@ -0,0 +1,252 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::chain::{DIFFICULTY, MINING_REWARD};
+
+/// Seconds a block should take when nothing else is said
+pub const DEFAULT_TARGET_INTERVAL_SECS: f64 = 60.0;
+
+/// Halvings after which blocks pay no reward, as in Bitcoin
+pub const MAX_HALVINGS: u64 = 64;
+
+/// Number of hashes a difficulty calls for on average
+///
+/// A hash meets difficulty `d` when its first `d` hex digits are zero, which
+/// happens once every 16^d hashes.
+pub fn expected_hashes(difficulty: u8) -> f64 {
+    16f64.powi(difficulty as i32)
+}
+
+/// Rules that set the difficulty and the block reward over time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ConsensusRules {
+    /// Difficulty of the first block
+    pub initial_difficulty: u8,
+
+    /// Reward of the first block
+    pub initial_reward: f64,
+
+    /// Seconds a block should take, what retargeting aims for
+    pub target_interval_secs: f64,
+
+    /// Blocks between difficulty adjustments, None for a fixed difficulty
+    pub retarget_interval: Option<u64>,
+
+    /// Blocks between reward halvings, None for a fixed reward
+    pub halving_interval: Option<u64>,
+}
+
+impl ConsensusRules {
+    /// Gets the rules this node mines with
+    ///
+    /// The node keeps its difficulty and reward fixed.
+    ///
+    /// # Arguments
+    ///
+    /// * `target_interval_secs` - Seconds a block should take
+    pub fn node(target_interval_secs: f64) -> Self {
+        ConsensusRules {
+            initial_difficulty: DIFFICULTY,
+            initial_reward: MINING_REWARD,
+            target_interval_secs,
+            retarget_interval: None,
+            halving_interval: None,
+        }
+    }
+
+    /// Gets the difficulty after a retarget
+    ///
+    /// Moves the difficulty by the number of hex digits that brings the block
+    /// interval closest to the target, at most one digit per retarget so a
+    /// single bad period can not swing it wildly.
+    fn retarget(&self, difficulty: u8, hashrate: f64) -> u8 {
+        let interval = expected_hashes(difficulty) / hashrate;
+        let steps = (self.target_interval_secs / interval).log(16.0).round().clamp(-1.0, 1.0) as i16;
+
+        (difficulty as i16 + steps).clamp(1, 64) as u8
+    }
+
+    /// Gets the reward of a block
+    pub fn reward_at(&self, height: u64) -> f64 {
+        match self.halving_interval {
+            Some(interval) => {
+                let halvings = height.saturating_sub(1) / interval;
+                if halvings >= MAX_HALVINGS {
+                    0.0
+                } else {
+                    self.initial_reward / 2f64.powi(halvings as i32)
+                }
+            }
+            None => self.initial_reward,
+        }
+    }
+
+    /// Gets the height of the next halving
+    ///
+    /// # Arguments
+    ///
+    /// * `height` - Height of the last block
+    ///
+    /// # Returns
+    ///
+    /// The first height with a halved reward after `height`, None if the reward never halves again
+    pub fn next_halving(&self, height: u64) -> Option<u64> {
+        let interval = self.halving_interval?;
+        let halvings = height.div_ceil(interval).max(1);
+
+        (halvings <= MAX_HALVINGS).then(|| halvings * interval + 1)
+    }
+
+    /// Gets the coins all blocks after a height will emit
+    ///
+    /// # Arguments
+    ///
+    /// * `height` - Height of the last block
+    ///
+    /// # Returns
+    ///
+    /// The emission still to come, None if it is unbounded because the reward never halves
+    pub fn emission_after(&self, height: u64) -> Option<f64> {
+        let interval = self.halving_interval?;
+
+        // Era k pays the reward of its first block from height k * interval + 1 to (k + 1) * interval
+        let remaining = (0..MAX_HALVINGS)
+            .map(|era| {
+                let first = era * interval + 1;
+                let last = (era + 1) * interval;
+                let blocks = last.saturating_sub(height.max(first - 1));
+                blocks as f64 * self.reward_at(first)
+            })
+            .sum();
+
+        Some(remaining)
+    }
+}
+
+/// The simulated state of the chain at a height
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SimulationStep {
+    /// Height of the block
+    pub height: u64,
+
+    /// Seconds since the first simulated block started
+    pub elapsed_secs: f64,
+
+    /// Difficulty of the block
+    pub difficulty: u8,
+
+    /// Expected seconds to mine a block at this difficulty
+    pub block_interval_secs: f64,
+
+    /// Reward of the block
+    pub reward: f64,
+
+    /// Coins emitted up to and including the block
+    pub total_emission: f64,
+}
+
+/// Predicted difficulty and emission of a chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SimulationReport {
+    /// Assumed hashes per second of the whole network
+    pub hashrate: f64,
+
+    /// The rules that were simulated
+    pub rules: ConsensusRules,
+
+    /// Samples of the trajectory, including every change of difficulty or reward
+    pub steps: Vec<SimulationStep>,
+}
+
+/// Predicts how difficulty and emission evolve
+///
+/// Every block takes exactly the expected time at its difficulty, so the
+/// prediction is the average trajectory rather than one random outcome.
+///
+/// # Arguments
+///
+/// * `rules` - The rules to simulate
+/// * `hashrate` - Assumed hashes per second of the whole network
+/// * `blocks` - Number of blocks to simulate
+/// * `samples` - Roughly how many evenly spaced steps to report besides the changes
+///
+/// # Returns
+///
+/// The report
+pub fn simulate(rules: &ConsensusRules, hashrate: f64, blocks: u64, samples: u64) -> SimulationReport {
+    let sample_every = (blocks / samples.max(1)).max(1);
+    let mut difficulty = rules.initial_difficulty;
+    let mut elapsed_secs = 0.0;
+    let mut total_emission = 0.0;
+    let mut steps: Vec<SimulationStep> = Vec::new();
+
+    for height in 1..=blocks {
+        if let Some(interval) = rules.retarget_interval {
+            if height > 1 && (height - 1) % interval == 0 {
+                difficulty = rules.retarget(difficulty, hashrate);
+            }
+        }
+
+        let block_interval_secs = expected_hashes(difficulty) / hashrate;
+        let reward = rules.reward_at(height);
+        elapsed_secs += block_interval_secs;
+        total_emission += reward;
+
+        let changed = steps
+            .last()
+            .is_none_or(|last| last.difficulty != difficulty || last.reward != reward);
+        if changed || height % sample_every == 0 || height == blocks {
+            steps.push(SimulationStep {
+                height,
+                elapsed_secs,
+                difficulty,
+                block_interval_secs,
+                reward,
+                total_emission,
+            });
+        }
+    }
+
+    SimulationReport {
+        hashrate,
+        rules: rules.clone(),
+        steps,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retargeting_converges_and_halving_caps_emission() {
+        // 16^4 hashes at 1000 H/s take about 65s, so a 10s target needs difficulty 3
+        let rules = ConsensusRules {
+            retarget_interval: Some(10),
+            halving_interval: Some(100),
+            ..ConsensusRules::node(10.0)
+        };
+        let report = simulate(&rules, 1000.0, 1000, 10);
+
+        let last = report.steps.last().unwrap();
+        assert_eq!(last.height, 1000);
+        assert_eq!(last.difficulty, 3);
+        assert!(report.steps.iter().any(|step| step.height == 11 && step.difficulty == 3));
+
+        // Ten halvings emit a little less than twice the first era
+        let first_era = 100.0 * MINING_REWARD;
+        assert!(last.total_emission < 2.0 * first_era && last.total_emission > 1.99 * first_era);
+
+        // The halvings run out, so the emission is capped
+        assert_eq!(rules.next_halving(150), Some(201));
+        assert_eq!(rules.reward_at(MAX_HALVINGS * 100 + 1), 0.0);
+        let cap = rules.emission_after(0).unwrap();
+        assert!((cap - 2.0 * first_era).abs() < 1e-6 && cap > last.total_emission);
+        assert!((rules.emission_after(1000).unwrap() - (cap - last.total_emission)).abs() < 1e-9);
+
+        // The node's own rules keep everything fixed
+        let report = simulate(&ConsensusRules::node(10.0), 1000.0, 1000, 10);
+        assert!(report.steps.iter().all(|step| step.difficulty == DIFFICULTY && step.reward == MINING_REWARD));
+        assert_eq!(report.steps.last().unwrap().total_emission, 1000.0 * MINING_REWARD);
+    }
+}
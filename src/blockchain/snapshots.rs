@@ -0,0 +1,245 @@
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use super::block::Block;
+use super::chain::{Blockchain, BlockchainError};
+use super::storage::{BlockchainStorage, StorageError};
+
+/// File in a snapshot directory describing the snapshot
+const INFO_FILE: &str = "info.json";
+
+/// Directory in a snapshot directory holding the copied database
+const DATA_DIR: &str = "data";
+
+/// Errors that can occur while taking or restoring snapshots
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("Snapshot not found: {0}")]
+    NotFound(String),
+
+    #[error("Blockchain error: {0}")]
+    BlockchainError(#[from] BlockchainError),
+
+    #[error("Storage error: {0}")]
+    StorageError(#[from] StorageError),
+
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Invalid snapshot info: {0}")]
+    InvalidInfo(String),
+}
+
+/// What caused a snapshot to be taken
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotTrigger {
+    /// Requested by an operator
+    Manual,
+
+    /// Taken after every N blocks
+    Blocks,
+
+    /// Taken every M minutes
+    Schedule,
+}
+
+/// A snapshot of the node's storage
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct SnapshotInfo {
+    /// Identifier of the snapshot, also the name of its directory
+    pub id: String,
+
+    /// Height of the last block in the snapshot
+    pub height: u64,
+
+    /// Hash of the last block in the snapshot
+    pub tip_hash: String,
+
+    /// When the snapshot was taken
+    pub created_at: DateTime<Utc>,
+
+    /// What caused the snapshot
+    pub trigger: SnapshotTrigger,
+}
+
+/// Keeps copies of the node's storage and restores the chain from them
+///
+/// Every snapshot is a full copy of the database next to a description, so
+/// it can also serve as the data directory of a node started by hand. Only
+/// the newest snapshots are kept.
+#[derive(Debug, Clone)]
+pub struct SnapshotStore {
+    dir: PathBuf,
+    keep: usize,
+    // Snapshots are taken and restored one at a time
+    lock: Arc<Mutex<()>>,
+}
+
+impl SnapshotStore {
+    /// Creates a new snapshot store
+    ///
+    /// # Arguments
+    ///
+    /// * `dir` - The directory holding the snapshots
+    /// * `keep` - How many snapshots to keep, older ones are removed
+    ///
+    /// # Returns
+    ///
+    /// A new SnapshotStore instance
+    pub fn new<P: AsRef<Path>>(dir: P, keep: usize) -> Result<Self, SnapshotError> {
+        fs::create_dir_all(dir.as_ref())?;
+
+        Ok(SnapshotStore {
+            dir: dir.as_ref().to_path_buf(),
+            keep: keep.max(1),
+            lock: Arc::new(Mutex::new(())),
+        })
+    }
+
+    /// Takes a snapshot and removes the ones beyond the retention
+    ///
+    /// # Arguments
+    ///
+    /// * `blockchain` - The blockchain whose storage to copy
+    /// * `trigger` - What caused the snapshot
+    ///
+    /// # Returns
+    ///
+    /// The new snapshot
+    pub fn take(&self, blockchain: &Blockchain, trigger: SnapshotTrigger) -> Result<SnapshotInfo, SnapshotError> {
+        let _lock = self.lock.lock().unwrap();
+
+        let created_at = Utc::now();
+        let id = created_at.format("%Y%m%dT%H%M%S%6fZ").to_string();
+        let path = self.dir.join(&id);
+
+        let taken = Self::write(&path, blockchain).and_then(|tip| {
+            let info = SnapshotInfo {
+                id,
+                height: tip.index,
+                tip_hash: tip.hash,
+                created_at,
+                trigger,
+            };
+            let json = serde_json::to_vec_pretty(&info).map_err(|e| SnapshotError::InvalidInfo(e.to_string()))?;
+            fs::write(path.join(INFO_FILE), json)?;
+            Ok(info)
+        });
+
+        let info = match taken {
+            Ok(info) => info,
+            Err(err) => {
+                // A partial copy must not be mistaken for a snapshot
+                let _ = fs::remove_dir_all(&path);
+                return Err(err);
+            }
+        };
+
+        info!("Took snapshot {} at height {}", info.id, info.height);
+
+        for old in self.read_all()?.into_iter().skip(self.keep) {
+            match fs::remove_dir_all(self.dir.join(&old.id)) {
+                Ok(()) => info!("Removed snapshot {}", old.id),
+                Err(err) => warn!("Failed to remove snapshot {}: {}", old.id, err),
+            }
+        }
+
+        Ok(info)
+    }
+
+    /// Gets all snapshots, newest first
+    pub fn list(&self) -> Result<Vec<SnapshotInfo>, SnapshotError> {
+        let _lock = self.lock.lock().unwrap();
+        self.read_all()
+    }
+
+    /// Replaces the chain with the chain data of a snapshot
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the snapshot
+    /// * `blockchain` - The blockchain to restore
+    ///
+    /// # Returns
+    ///
+    /// The restored snapshot
+    pub fn restore(&self, id: &str, blockchain: &Blockchain) -> Result<SnapshotInfo, SnapshotError> {
+        let _lock = self.lock.lock().unwrap();
+
+        // Only listed snapshots, so the ID can't point outside the directory
+        let info = self
+            .read_all()?
+            .into_iter()
+            .find(|info| info.id == id)
+            .ok_or_else(|| SnapshotError::NotFound(id.to_string()))?;
+
+        let storage = BlockchainStorage::new(self.dir.join(&info.id).join(DATA_DIR))?;
+        blockchain.restore_storage(&storage)?;
+
+        info!("Restored snapshot {} at height {}", info.id, info.height);
+        Ok(info)
+    }
+
+    /// Copies the storage of a blockchain into a new snapshot directory
+    fn write(path: &Path, blockchain: &Blockchain) -> Result<Block, SnapshotError> {
+        fs::create_dir(path)?;
+        let storage = BlockchainStorage::new(path.join(DATA_DIR))?;
+        Ok(blockchain.snapshot_storage(&storage)?)
+    }
+
+    /// Reads the descriptions of all snapshots, newest first
+    fn read_all(&self) -> Result<Vec<SnapshotInfo>, SnapshotError> {
+        let mut snapshots = Vec::new();
+
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path().join(INFO_FILE);
+            if !path.is_file() {
+                continue;
+            }
+
+            match serde_json::from_slice::<SnapshotInfo>(&fs::read(&path)?) {
+                Ok(info) => snapshots.push(info),
+                Err(err) => warn!("Ignoring snapshot with unreadable {}: {}", path.display(), err),
+            }
+        }
+
+        snapshots.sort_by_key(|info| std::cmp::Reverse(info.created_at));
+        Ok(snapshots)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshots_are_pruned_and_restored() {
+        let root = tempfile::tempdir().unwrap();
+        let blockchain = Blockchain::with_storage(root.path().join("node")).unwrap();
+        let snapshots = SnapshotStore::new(root.path().join("snapshots"), 2).unwrap();
+
+        let genesis = snapshots.take(&blockchain, SnapshotTrigger::Manual).unwrap();
+        assert_eq!(genesis.height, 0);
+        blockchain.mine_block("miner").unwrap();
+        let mined = snapshots.take(&blockchain, SnapshotTrigger::Blocks).unwrap();
+        assert_eq!(mined.height, 1);
+
+        // Going back to genesis drops the mined block
+        snapshots.restore(&genesis.id, &blockchain).unwrap();
+        assert_eq!(blockchain.get_last_block().hash, genesis.tip_hash);
+        assert!(matches!(snapshots.restore("../node", &blockchain), Err(SnapshotError::NotFound(_))));
+
+        // Only the two newest are kept
+        let latest = snapshots.take(&blockchain, SnapshotTrigger::Schedule).unwrap();
+        let ids: Vec<String> = snapshots.list().unwrap().into_iter().map(|info| info.id).collect();
+        assert_eq!(ids, vec![latest.id, mined.id]);
+    }
+}
@@ -0,0 +1,203 @@
+use super::account::{Account, AccountState};
+use super::block::Block;
+use super::faucet::{self, FaucetGrant};
+use super::validation::{self, ValidationError, ValidationRules};
+
+/// Blocks and the account state they lead to
+///
+/// Holds no storage, mempool or network, so consensus decisions can be made
+/// and tested on their own. The `Blockchain` keeps the live chain behind its
+/// locks and storage, and uses a ChainState wherever it builds a new state,
+/// such as a competing branch.
+#[derive(Debug)]
+pub struct ChainState {
+    blocks: Vec<Block>,
+    accounts: AccountState,
+}
+
+impl ChainState {
+    /// Creates a state holding only a genesis block
+    ///
+    /// # Arguments
+    ///
+    /// * `genesis` - The genesis block
+    ///
+    /// # Returns
+    ///
+    /// A new ChainState instance
+    pub fn new(genesis: Block) -> Self {
+        ChainState {
+            blocks: vec![genesis],
+            accounts: AccountState::new(),
+        }
+    }
+
+    /// Resumes from blocks and the accounts they were known to lead to
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks, genesis first
+    /// * `accounts` - The accounts after the last block
+    ///
+    /// # Returns
+    ///
+    /// A new ChainState instance
+    pub fn from_parts(blocks: Vec<Block>, accounts: Vec<Account>) -> Self {
+        assert!(!blocks.is_empty(), "a chain state always holds a genesis block");
+
+        let state = AccountState::new();
+        state.replace_all(accounts);
+        ChainState { blocks, accounts: state }
+    }
+
+    /// Rebuilds the state of trusted blocks by applying their transactions
+    ///
+    /// The blocks are not validated, only applied, so they must come from a
+    /// chain that was validated before. The faucet grants are credited after
+    /// the block they were made at.
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks, genesis first
+    /// * `grants` - The deposits of the faucet, see `FaucetGrant`
+    ///
+    /// # Returns
+    ///
+    /// The state after the last block
+    pub fn replay(blocks: &[Block], grants: &[FaucetGrant]) -> Result<Self, ValidationError> {
+        let (genesis, rest) = blocks
+            .split_first()
+            .ok_or_else(|| ValidationError::InvalidChain("No blocks found".to_string()))?;
+
+        let mut state = ChainState::new(genesis.clone());
+        state.credit(grants)?;
+        for block in rest {
+            state.apply_trusted(block.clone())?;
+            state.credit(grants)?;
+        }
+
+        Ok(state)
+    }
+
+    /// Gets the blocks, genesis first
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    /// Gets the last block
+    pub fn tip(&self) -> &Block {
+        self.blocks.last().expect("a chain state always holds a genesis block")
+    }
+
+    /// Gets the account state after the last block
+    pub fn accounts(&self) -> &AccountState {
+        &self.accounts
+    }
+
+    /// Appends a block after checking it against the rules
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block to append
+    /// * `rules` - The rules the block must follow
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if the block was valid and appended, otherwise the state is unchanged
+    pub fn connect(&mut self, block: Block, rules: &ValidationRules) -> Result<(), ValidationError> {
+        validation::check_block(self.tip(), &block, rules)?;
+
+        // Apply to a copy, so a transaction failing halfway leaves nothing behind
+        let accounts = AccountState::new();
+        accounts.replace_all(self.accounts.get_all_accounts());
+        validation::apply_block(&accounts, &block).map_err(|err| {
+            ValidationError::InvalidChain(format!("Block {} can not be applied: {}", block.index, err))
+        })?;
+
+        self.accounts = accounts;
+        self.blocks.push(block);
+        Ok(())
+    }
+
+    /// Credits the faucet grants made while the last block was the tip
+    ///
+    /// # Arguments
+    ///
+    /// * `grants` - The deposits of the faucet, see `FaucetGrant`
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if every grant could be deposited
+    pub fn credit(&mut self, grants: &[FaucetGrant]) -> Result<(), ValidationError> {
+        Ok(faucet::credit(&self.accounts, grants, self.tip().index)?)
+    }
+
+    /// Splits the state into its blocks and accounts
+    pub fn into_parts(self) -> (Vec<Block>, Vec<Account>) {
+        (self.blocks, self.accounts.get_all_accounts())
+    }
+
+    /// Appends a block of a chain that was validated before, without checking it
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block to append
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if the transactions of the block could be applied
+    pub fn apply_trusted(&mut self, block: Block) -> Result<(), ValidationError> {
+        validation::apply_block(&self.accounts, &block).map_err(|err| {
+            ValidationError::InvalidChain(format!("Block {} can not be applied: {}", block.index, err))
+        })?;
+
+        self.blocks.push(block);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::crypto::{Address, Wallet};
+    use crate::blockchain::transaction::Transaction;
+
+    /// Searches a proof for a block at the rules' difficulty
+    fn mine(parent: &Block, transactions: Vec<Transaction>, rules: &ValidationRules) -> Block {
+        let target = "0".repeat(rules.difficulty as usize);
+        (0..)
+            .map(|proof| Block::new(parent.index + 1, transactions.clone(), proof, parent.hash.clone()))
+            .find(|block| block.hash.starts_with(&target))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_connect_validates_and_applies_blocks() {
+        let rules = ValidationRules {
+            difficulty: 1,
+            ..ValidationRules::node()
+        };
+        let miner = Wallet::new().unwrap();
+        let mut state = ChainState::new(Block::new(0, Vec::new(), 1, "0".to_string()));
+
+        let reward = |amount: f64| Transaction::new_coinbase(miner.address().clone(), amount);
+        let first = mine(state.tip(), vec![reward(50.0)], &rules);
+        state.connect(first, &rules).unwrap();
+
+        // A block not linking to the tip is rejected
+        let stray = mine(&Block::new(0, Vec::new(), 2, "0".to_string()), vec![reward(50.0)], &rules);
+        assert!(matches!(state.connect(stray, &rules), Err(ValidationError::InvalidBlock(_))));
+
+        // Spending more than the miner has, even with the new reward, leaves the state unchanged
+        let mut overspend = Transaction::new(miner.address().clone(), Address("bob".to_string()), 120.0, rules.minimum_fee, 0);
+        overspend.sign(&miner).unwrap();
+        let second = mine(state.tip(), vec![overspend, reward(50.0)], &rules);
+        assert!(matches!(state.connect(second, &rules), Err(ValidationError::InvalidChain(_))));
+        assert_eq!(state.accounts().get_account(miner.address()).balance, 50.0);
+
+        // Replaying the blocks gives the same state
+        let replayed = ChainState::replay(state.blocks(), &[]).unwrap();
+        assert_eq!(replayed.tip().hash, state.tip().hash);
+        assert_eq!(replayed.accounts().get_account(miner.address()).balance, 50.0);
+    }
+}
@@ -0,0 +1,444 @@
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use curve25519_dalek::scalar::Scalar;
+use ed25519_dalek::hazmat::{self, ExpandedSecretKey};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
+use thiserror::Error;
+
+use std::fmt;
+use std::str::FromStr;
+
+use super::block::Block;
+use super::crypto::{Address, CryptoError, DigitalSignature, Wallet};
+use super::signing::SigningContext;
+use super::transaction::{Transaction, TransactionError, SIGNING_CONTEXT_VERSION};
+
+/// Tag of the hash turning a shared secret into the tweak of a one-time key
+const TWEAK_TAG: &[u8] = b"my_blockchain/stealth-tweak/v1\0";
+
+/// Tag of the hash deriving the scan key of a wallet
+const SCAN_KEY_TAG: &[u8] = b"my_blockchain/stealth-scan-key/v1\0";
+
+/// Tag of the hash deriving the signature nonce prefix of a one-time key
+const NONCE_PREFIX_TAG: &[u8] = b"my_blockchain/stealth-nonce-prefix/v1\0";
+
+/// Errors that can occur while paying or spending stealth payments
+#[derive(Debug, Error)]
+pub enum StealthError {
+    #[error("Invalid stealth address: {0}")]
+    InvalidAddress(String),
+
+    #[error("Invalid scan key: {0}")]
+    InvalidScanKey(String),
+
+    #[error("Payment {0} was not made to this stealth address")]
+    NotOurs(String),
+
+    #[error("Transaction error: {0}")]
+    TransactionError(#[from] TransactionError),
+
+    #[error("Crypto error: {0}")]
+    CryptoError(#[from] CryptoError),
+}
+
+/// The address a recipient publishes to be paid privately
+///
+/// Holds two public keys, the scan key `A = a * G` and the spend key
+/// `B = b * G`, base58 encoded together. Nobody pays it directly: the payer
+/// derives a fresh one-time address `P = H(x * A) * G + B` from the secret
+/// key `x` of the paying account, and the transaction is an ordinary
+/// transfer to `P`. The recipient finds it by computing the same shared
+/// secret as `a * X` from the sender's public key `X`, and spends it with
+/// the one-time secret key `H(a * X) + b`. Observers see unrelated addresses
+/// and can't tell that two payments went to the same recipient, but the
+/// sender and the amounts stay public.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct StealthAddress(pub String);
+
+impl StealthAddress {
+    /// Creates the unsigned transaction of a payment to a fresh one-time address
+    ///
+    /// The sender and the nonce both go into the one-time address, so every
+    /// payment lands on a different one.
+    ///
+    /// # Arguments
+    ///
+    /// * `sender` - The paying wallet, which signs the transaction afterwards
+    /// * `amount` - The amount to pay
+    /// * `fee` - The transaction fee
+    /// * `nonce` - The sender's next nonce
+    ///
+    /// # Returns
+    ///
+    /// The transaction to sign with `Transaction::sign`
+    pub fn pay(&self, sender: &Wallet, amount: f64, fee: f64, nonce: u64) -> Result<Transaction, StealthError> {
+        let (scan_key, spend_key) = self.keys()?;
+        let secret = secret_scalar(sender)?;
+        let tweak = tweak(&(scan_key * secret), sender.address(), nonce);
+        let recipient = one_time_address(&tweak, &spend_key);
+
+        Ok(Transaction::new(sender.address().clone(), recipient, amount, fee, nonce))
+    }
+
+    /// Decodes the scan and spend keys
+    fn keys(&self) -> Result<(EdwardsPoint, EdwardsPoint), StealthError> {
+        let bytes = bs58::decode(&self.0)
+            .into_vec()
+            .map_err(|err| StealthError::InvalidAddress(err.to_string()))?;
+        if bytes.len() != 64 {
+            return Err(StealthError::InvalidAddress(format!("{} bytes instead of 64", bytes.len())));
+        }
+
+        let scan_key = decompress(&bytes[..32]).ok_or_else(|| StealthError::InvalidAddress("invalid scan key".to_string()))?;
+        let spend_key = decompress(&bytes[32..]).ok_or_else(|| StealthError::InvalidAddress("invalid spend key".to_string()))?;
+        Ok((scan_key, spend_key))
+    }
+}
+
+impl fmt::Display for StealthAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for StealthAddress {
+    type Err = StealthError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let address = StealthAddress(s.to_string());
+        address.keys()?;
+        Ok(address)
+    }
+}
+
+/// A payment found by scanning the chain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StealthPayment {
+    /// ID of the paying transaction
+    pub transaction_id: String,
+
+    /// Height of the block that includes it
+    pub block_index: u64,
+
+    /// The one-time address paid
+    pub address: Address,
+
+    /// The amount paid
+    pub amount: f64,
+
+    /// Hex encoded tweak the one-time secret key is the spend key plus
+    pub tweak: String,
+}
+
+/// The key that finds payments to a stealth address, without spending them
+///
+/// Holds the secret scan key and the public spend key, so a wallet service
+/// can watch the chain for a recipient that keeps its spend key offline.
+#[derive(Debug, Clone)]
+pub struct ScanKey {
+    scan_secret: Scalar,
+    spend_key: EdwardsPoint,
+}
+
+impl ScanKey {
+    /// Checks if a transaction pays the stealth address
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The transaction, which need not be mined
+    ///
+    /// # Returns
+    ///
+    /// The tweak of the one-time key, None if the transaction pays someone else
+    pub fn detect(&self, transaction: &Transaction) -> Option<Scalar> {
+        if transaction.is_coinbase() {
+            return None;
+        }
+
+        let sender = transaction.sender.to_public_key().ok()?;
+        let sender = decompress(sender.as_bytes())?;
+        let tweak = tweak(&(sender * self.scan_secret), &transaction.sender, transaction.nonce);
+
+        (one_time_address(&tweak, &self.spend_key) == transaction.recipient).then_some(tweak)
+    }
+
+    /// Finds the payments to the stealth address in blocks
+    ///
+    /// Every transaction costs a scalar multiplication, and bloom filters
+    /// can't narrow the blocks down since the addresses are not known
+    /// beforehand.
+    ///
+    /// # Arguments
+    ///
+    /// * `blocks` - The blocks to scan
+    ///
+    /// # Returns
+    ///
+    /// The payments, in chain order
+    pub fn scan(&self, blocks: &[Block]) -> Vec<StealthPayment> {
+        blocks
+            .iter()
+            .flat_map(|block| {
+                block.transactions.iter().filter_map(move |transaction| {
+                    self.detect(transaction).map(|tweak| StealthPayment {
+                        transaction_id: transaction.id.clone(),
+                        block_index: block.index,
+                        address: transaction.recipient.clone(),
+                        amount: transaction.amount,
+                        tweak: hex::encode(tweak.as_bytes()),
+                    })
+                })
+            })
+            .collect()
+    }
+
+    /// Exports the key as hex, the secret scan key followed by the public spend key
+    pub fn export(&self) -> String {
+        hex::encode([self.scan_secret.to_bytes(), self.spend_key.compress().to_bytes()].concat())
+    }
+
+    /// Imports a key exported by `export`
+    ///
+    /// # Arguments
+    ///
+    /// * `hex_key` - The exported key
+    pub fn import(hex_key: &str) -> Result<Self, StealthError> {
+        let bytes = hex::decode(hex_key).map_err(|err| StealthError::InvalidScanKey(err.to_string()))?;
+        if bytes.len() != 64 {
+            return Err(StealthError::InvalidScanKey(format!("{} bytes instead of 64", bytes.len())));
+        }
+
+        let scan_secret = scalar(&bytes[..32]).ok_or_else(|| StealthError::InvalidScanKey("invalid scan key".to_string()))?;
+        let spend_key = decompress(&bytes[32..]).ok_or_else(|| StealthError::InvalidScanKey("invalid spend key".to_string()))?;
+        Ok(ScanKey { scan_secret, spend_key })
+    }
+}
+
+/// The keys of a stealth address
+///
+/// The spend key is an ordinary wallet, the scan key is derived from it.
+#[derive(Debug, Clone)]
+pub struct StealthWallet {
+    spend: Wallet,
+    scan_secret: Scalar,
+}
+
+impl StealthWallet {
+    /// Creates a stealth wallet with a random spend key
+    pub fn new() -> Result<Self, StealthError> {
+        Ok(Self::from_wallet(Wallet::new()?))
+    }
+
+    /// Creates the stealth wallet of an existing wallet
+    ///
+    /// # Arguments
+    ///
+    /// * `spend` - The wallet whose key spends the payments
+    pub fn from_wallet(spend: Wallet) -> Self {
+        let mut hasher = Sha512::new();
+        hasher.update(SCAN_KEY_TAG);
+        hasher.update(spend.export_secret_key());
+        let scan_secret = Scalar::from_bytes_mod_order_wide(&hasher.finalize().into());
+
+        StealthWallet { spend, scan_secret }
+    }
+
+    /// Gets the stealth address to publish
+    pub fn address(&self) -> StealthAddress {
+        let scan_key = EdwardsPoint::mul_base(&self.scan_secret).compress();
+        let spend_key = self.spend.public_key().as_bytes();
+        StealthAddress(bs58::encode([scan_key.to_bytes(), *spend_key].concat()).into_string())
+    }
+
+    /// Gets the key that finds payments without being able to spend them
+    pub fn scan_key(&self) -> Result<ScanKey, StealthError> {
+        let spend_key = decompress(self.spend.public_key().as_bytes())
+            .ok_or_else(|| CryptoError::InvalidPublicKey("spend key is not a curve point".to_string()))?;
+
+        Ok(ScanKey {
+            scan_secret: self.scan_secret,
+            spend_key,
+        })
+    }
+
+    /// Gets the key that spends a payment
+    ///
+    /// # Arguments
+    ///
+    /// * `payment` - The payment, found by scanning
+    ///
+    /// # Returns
+    ///
+    /// The one-time key, or an error if the payment is not to this wallet
+    pub fn one_time_key(&self, payment: &StealthPayment) -> Result<OneTimeKey, StealthError> {
+        let not_ours = || StealthError::NotOurs(payment.transaction_id.clone());
+        let tweak = hex::decode(&payment.tweak).ok().and_then(|bytes| scalar(&bytes)).ok_or_else(not_ours)?;
+
+        let key = OneTimeKey::new(tweak + secret_scalar(&self.spend)?);
+        if key.address != payment.address {
+            return Err(not_ours());
+        }
+
+        Ok(key)
+    }
+}
+
+/// The secret key of a one-time address
+///
+/// It is not the hash of a seed like the key of a `Wallet`, so it signs with
+/// the expanded key directly.
+pub struct OneTimeKey {
+    expanded: ExpandedSecretKey,
+    public_key: VerifyingKey,
+    address: Address,
+}
+
+impl OneTimeKey {
+    /// Creates the key of a secret scalar
+    fn new(secret: Scalar) -> Self {
+        let mut hasher = Sha512::new();
+        hasher.update(NONCE_PREFIX_TAG);
+        hasher.update(secret.as_bytes());
+        let mut hash_prefix = [0u8; 32];
+        hash_prefix.copy_from_slice(&hasher.finalize()[..32]);
+
+        let public_key = VerifyingKey::from(EdwardsPoint::mul_base(&secret));
+        OneTimeKey {
+            expanded: ExpandedSecretKey {
+                scalar: secret,
+                hash_prefix,
+            },
+            address: Address::from_public_key(&public_key),
+            public_key,
+        }
+    }
+
+    /// Gets the one-time address
+    pub fn address(&self) -> &Address {
+        &self.address
+    }
+
+    /// Signs a transaction sent from the one-time address
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The transaction, of a version signed in the transaction signing context
+    pub fn sign(&self, transaction: &mut Transaction) -> Result<(), StealthError> {
+        if transaction.signature.is_some() {
+            return Err(TransactionError::AlreadySigned.into());
+        }
+        if transaction.sender != self.address {
+            return Err(TransactionError::InvalidSenderAddress(
+                "One-time address does not match sender address".to_string(),
+            )
+            .into());
+        }
+        if transaction.version < SIGNING_CONTEXT_VERSION {
+            return Err(TransactionError::SystemError(format!(
+                "version {} is not signed in a signing context",
+                transaction.version
+            ))
+            .into());
+        }
+
+        transaction.id = transaction.compute_id();
+        let message = SigningContext::Transaction.message(&transaction.signed_bytes()?);
+        let signature = hazmat::raw_sign::<Sha512>(&self.expanded, &message, &self.public_key);
+        transaction.signature = Some(DigitalSignature::from_signature(&signature));
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for OneTimeKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OneTimeKey").field("address", &self.address).finish_non_exhaustive()
+    }
+}
+
+/// Gets the secret scalar of a wallet, the one its public key is a multiple of
+fn secret_scalar(wallet: &Wallet) -> Result<Scalar, CryptoError> {
+    let secret: [u8; 32] = wallet
+        .export_secret_key()
+        .try_into()
+        .map_err(|_| CryptoError::InvalidPrivateKey("Invalid private key length".to_string()))?;
+
+    Ok(SigningKey::from_bytes(&secret).to_scalar())
+}
+
+/// Hashes a shared secret into the tweak of a one-time key
+fn tweak(shared: &EdwardsPoint, sender: &Address, nonce: u64) -> Scalar {
+    let mut hasher = Sha512::new();
+    hasher.update(TWEAK_TAG);
+    hasher.update(shared.mul_by_cofactor().compress().as_bytes());
+    hasher.update(sender.0.as_bytes());
+    hasher.update(nonce.to_be_bytes());
+
+    Scalar::from_bytes_mod_order_wide(&hasher.finalize().into())
+}
+
+/// Gets the one-time address `tweak * G + spend_key`
+fn one_time_address(tweak: &Scalar, spend_key: &EdwardsPoint) -> Address {
+    Address::from_public_key(&VerifyingKey::from(EdwardsPoint::mul_base(tweak) + spend_key))
+}
+
+/// Decodes a compressed curve point
+fn decompress(bytes: &[u8]) -> Option<EdwardsPoint> {
+    CompressedEdwardsY::from_slice(bytes).ok()?.decompress()
+}
+
+/// Decodes a canonical scalar
+fn scalar(bytes: &[u8]) -> Option<Scalar> {
+    Option::from(Scalar::from_canonical_bytes(bytes.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+
+    #[test]
+    fn test_stealth_payment_is_found_and_spent() {
+        let blockchain = Blockchain::new();
+        let alice = Wallet::new().unwrap();
+        let bob = StealthWallet::new().unwrap();
+        let carol = Wallet::new().unwrap();
+        let fee = blockchain.get_base_fee();
+        blockchain.mine_block(&alice.address().0).unwrap();
+
+        // Two payments to the same stealth address land on unrelated addresses
+        let address: StealthAddress = bob.address().0.parse().unwrap();
+        let mut first = address.pay(&alice, 10.0, fee, 0).unwrap();
+        let mut second = address.pay(&alice, 5.0, fee, 1).unwrap();
+        assert_ne!(first.recipient, second.recipient);
+        first.sign(&alice).unwrap();
+        second.sign(&alice).unwrap();
+        blockchain.add_transaction(first.clone()).unwrap();
+        blockchain.add_transaction(second).unwrap();
+        blockchain.mine_block(&alice.address().0).unwrap();
+
+        // A watch-only scan key finds both, someone else's finds none
+        let scan_key = ScanKey::import(&bob.scan_key().unwrap().export()).unwrap();
+        let payments = scan_key.scan(&blockchain.get_chain());
+        assert_eq!(payments.iter().map(|payment| payment.amount).collect::<Vec<_>>(), vec![10.0, 5.0]);
+        assert_eq!(payments[0].address, first.recipient);
+        let stranger = StealthWallet::new().unwrap().scan_key().unwrap();
+        assert!(stranger.scan(&blockchain.get_chain()).is_empty());
+
+        // Only the one-time key of the payment spends it
+        let key = bob.one_time_key(&payments[0]).unwrap();
+        let mut spend = Transaction::new(key.address().clone(), carol.address().clone(), 4.0, fee, 0);
+        key.sign(&mut spend).unwrap();
+        assert!(spend.verify_signature().unwrap());
+        blockchain.add_transaction(spend).unwrap();
+        blockchain.mine_block(&alice.address().0).unwrap();
+        assert_eq!(blockchain.get_account_state().get_account(carol.address()).balance, 4.0);
+
+        let forged = StealthPayment {
+            tweak: payments[0].tweak.clone(),
+            ..payments[1].clone()
+        };
+        assert!(matches!(bob.one_time_key(&forged), Err(StealthError::NotOurs(_))));
+    }
+}
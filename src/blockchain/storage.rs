@@ -1,14 +1,41 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
 
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use sled::{Db, Tree};
 use thiserror::Error;
 use log::warn;
-use bincode;
+use bincode::{self, Options};
+use utoipa::ToSchema;
 
-use super::block::Block;
+use super::block::{Block, RawBlock};
+use super::bloom::AddressBloom;
+use super::compression::Compressor;
 use super::crypto::Address;
+use super::genesis::NetworkMagic;
 use super::transaction::Transaction;
 use super::account::Account;
+use super::payments::Payment;
+use super::audit::AuditEntry;
+use super::withdrawals::Withdrawal;
+use super::schedules::ScheduleRecord;
+use super::keystore::KeystoreEntry;
+use super::node_keys::KeyRing;
+use super::labels::TransactionLabel;
+use super::usage::DailyUsage;
+use super::errors::ErrorCode;
+use super::events::ReorgEvent;
+use super::faucet::FaucetGrant;
+use super::mempool::{MempoolJournalEntry, MiningTemplate};
+use super::versioning;
 
 /// Errors that can occur during storage operations
 #[derive(Debug, Error)]
@@ -24,6 +51,33 @@ pub enum StorageError {
 
     #[error("Item not found: {0}")]
     NotFound(String),
+
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Invalid backup: {0}")]
+    InvalidBackup(String),
+
+    #[error("Data directory belongs to network {found}, this node is configured for {expected}")]
+    NetworkMismatch { expected: NetworkMagic, found: NetworkMagic },
+
+    #[error("Data directory {path} is in use by another node ({holder})")]
+    DirectoryLocked { path: String, holder: String },
+}
+
+impl StorageError {
+    /// Gets the stable code of the error, always a failure of the node
+    pub fn code(&self) -> ErrorCode {
+        ErrorCode::Internal
+    }
+
+    /// Checks whether the same operation may succeed later, e.g. once the disk or the other node is done
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            StorageError::DatabaseError(_) | StorageError::IoError(_) | StorageError::DirectoryLocked { .. }
+        )
+    }
 }
 
 /// Storage for blockchain data
@@ -34,6 +88,12 @@ pub struct BlockchainStorage {
     /// Tree for blocks
     blocks: Tree,
 
+    /// Tree for the bytes blocks were hashed from
+    raw_blocks: Tree,
+
+    /// Tree for the bloom filters of the addresses blocks touch
+    address_blooms: Tree,
+
     /// Tree for transactions
     transactions: Tree,
 
@@ -42,6 +102,315 @@ pub struct BlockchainStorage {
 
     /// Tree for metadata
     metadata: Tree,
+
+    /// Tree for expected payments
+    payments: Tree,
+
+    /// Tree for withdrawal requests
+    withdrawals: Tree,
+
+    /// Tree for scheduled payments and their session keys
+    schedules: Tree,
+
+    /// Tree for the audit log
+    audit_log: Tree,
+
+    /// Tree for the wallets held by the node
+    keystore: Tree,
+
+    /// Tree for the keys the node signs with on its own behalf
+    node_keys: Tree,
+
+    /// Tree for private transaction labels
+    labels: Tree,
+
+    /// Tree for the daily API usage of each API key
+    usage: Tree,
+
+    /// Tree for past chain reorganizations
+    reorgs: Tree,
+
+    /// Tree for the deposits of the faucet
+    faucet_grants: Tree,
+
+    /// Tree for the last snapshot of the pending transactions
+    mempool: Tree,
+
+    /// Tree for mempool changes since the last snapshot
+    mempool_journal: Tree,
+
+    /// Options the storage was opened with
+    options: StorageOptions,
+
+    /// Compressor for blocks and transactions
+    compressor: RwLock<Compressor>,
+
+    /// Raw block reads served from the raw blocks tree since the storage was opened
+    raw_block_hits: AtomicU64,
+
+    /// Raw block reads that had to rebuild the bytes from the block
+    raw_block_misses: AtomicU64,
+
+    /// When the storage was last flushed to disk since it was opened
+    last_flush: Mutex<Option<DateTime<Utc>>>,
+}
+
+/// Metadata key of the trained zstd dictionary
+const DICTIONARY_KEY: &str = "zstd_dictionary";
+
+/// Metadata key of the time the existing records were last compressed
+const LAST_COMPACTION_KEY: &str = "last_compaction";
+
+/// Metadata key of the magic of the network the chain belongs to
+const NETWORK_MAGIC_KEY: &str = "network_magic";
+
+/// Metadata key of the block being mined
+const MINING_TEMPLATE_KEY: &str = "mining_template";
+
+/// Prefix of blocks stored as a header plus transaction IDs
+const BLOCK_HEADER_PREFIX: &[u8] = b"BHv1";
+
+/// How often to try to lock a database that is still held by this process
+const LOCK_ATTEMPTS: u32 = 40;
+
+/// Milliseconds between sled's background flushes, unless the flush policy sets them
+const BACKGROUND_FLUSH_MS: u64 = 500;
+
+/// Time between attempts to lock the database
+const LOCK_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// Name of the backup format written by `export_to`
+const BACKUP_FORMAT: &str = "my_blockchain-storage-backup";
+
+/// Version of the backup format, raised when a reader can no longer import older backups
+const BACKUP_VERSION: u32 = 1;
+
+/// A block as stored, referencing its transactions in the transactions tree
+///
+/// Storing full blocks kept every transaction twice, once in the block and
+/// once in the transactions tree.
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredBlock {
+    version: u32,
+    index: u64,
+    timestamp: DateTime<Utc>,
+    transaction_ids: Vec<String>,
+    proof: u64,
+    previous_hash: String,
+    hash: String,
+}
+
+/// Options for opening the storage
+#[derive(Debug, Clone, Default)]
+pub struct StorageOptions {
+    /// Compress blocks and transactions written from now on
+    pub compress: bool,
+
+    /// When writes are flushed to disk
+    pub flush_policy: FlushPolicy,
+}
+
+/// When writes are flushed to disk, trading durability for write speed
+///
+/// Whatever is not flushed yet is lost if the machine crashes, although
+/// the node itself crashing doesn't lose it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// Every write waits for the disk, so nothing acknowledged is ever lost, at the cost of slow writes
+    EveryWrite,
+
+    /// Applying a block waits for the disk, other writes are flushed in the background
+    #[default]
+    OnBlockCommit,
+
+    /// Everything is flushed in the background every this many milliseconds, the fastest,
+    /// but a crash loses up to that much, blocks included
+    Interval(u64),
+}
+
+impl FlushPolicy {
+    /// Gets the interval of sled's background flushes, None to disable them
+    fn background_interval(&self) -> Option<u64> {
+        match self {
+            FlushPolicy::EveryWrite => None,
+            FlushPolicy::OnBlockCommit => Some(BACKGROUND_FLUSH_MS),
+            FlushPolicy::Interval(ms) => Some(*ms),
+        }
+    }
+}
+
+impl std::fmt::Display for FlushPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FlushPolicy::EveryWrite => write!(f, "every-write"),
+            FlushPolicy::OnBlockCommit => write!(f, "on-block-commit"),
+            FlushPolicy::Interval(ms) => write!(f, "interval:{}", ms),
+        }
+    }
+}
+
+impl std::str::FromStr for FlushPolicy {
+    type Err = String;
+
+    /// Reads every-write, on-block-commit or interval:<milliseconds>
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "every-write" => Ok(FlushPolicy::EveryWrite),
+            "on-block-commit" => Ok(FlushPolicy::OnBlockCommit),
+            other => match other.strip_prefix("interval:").map(str::parse::<u64>) {
+                Some(Ok(ms)) if ms > 0 => Ok(FlushPolicy::Interval(ms)),
+                _ => Err(format!("{} is not every-write, on-block-commit or interval:<milliseconds>", other)),
+            },
+        }
+    }
+}
+
+/// Outcome of compressing the existing records
+#[derive(Debug, Clone, Default)]
+pub struct CompressionReport {
+    /// Number of blocks and transactions rewritten
+    pub records: usize,
+
+    /// Size of the records before compression
+    pub bytes_before: usize,
+
+    /// Size of the records after compression
+    pub bytes_after: usize,
+
+    /// Whether a dictionary was trained during this pass
+    pub trained_dictionary: bool,
+}
+
+/// First line of a backup, describing what it holds
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupHeader {
+    format: String,
+    version: u32,
+    created_at: DateTime<Utc>,
+    blocks: usize,
+    tip: Option<String>,
+    last_id: u64,
+}
+
+/// A line of a backup
+///
+/// A backup is one JSON object per line: the header, then every key/value
+/// pair of every tree in hex, then the number of pairs, so a truncated
+/// backup is detected. Nothing in it is specific to sled.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BackupLine {
+    Header(BackupHeader),
+    Entry { tree: String, key: String, value: String },
+    End { entries: usize },
+}
+
+/// Outcome of exporting or importing a backup
+#[derive(Debug, Clone)]
+pub struct BackupReport {
+    /// Name and number of entries of every tree in the backup
+    pub trees: Vec<(String, usize)>,
+
+    /// Number of blocks, verified against their hashes on import
+    pub blocks: usize,
+
+    /// Hash of the last block, None for an empty chain
+    pub tip: Option<String>,
+}
+
+impl std::fmt::Display for BackupReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "  trees:")?;
+        for (name, entries) in &self.trees {
+            writeln!(f, "    {:<24} {:>8} entries", name, entries)?;
+        }
+        writeln!(f, "  blocks: {}", self.blocks)?;
+        write!(f, "  tip:    {}", self.tip.as_deref().unwrap_or("none"))
+    }
+}
+
+/// Size of one tree of the database
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TreeStats {
+    /// Name of the tree
+    pub name: String,
+
+    /// Number of keys
+    pub keys: u64,
+
+    /// Bytes of the keys and values as stored, before sled's own overhead
+    pub bytes: u64,
+}
+
+/// Hits and misses of a cache since the storage was opened
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct CacheStats {
+    /// Reads served from the cache
+    pub hits: u64,
+
+    /// Reads that had to rebuild the entry
+    pub misses: u64,
+
+    /// Share of the reads that were hits, None before the first read
+    pub hit_rate: Option<f64>,
+}
+
+/// Disk usage of the database, for operators watching its growth
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct StorageStats {
+    /// Bytes of the database files on disk
+    pub size_on_disk: u64,
+
+    /// Every tree, largest first
+    pub trees: Vec<TreeStats>,
+
+    /// The raw bytes of blocks, kept once rebuilt for GET /blocks/{hash}/raw
+    pub raw_block_cache: CacheStats,
+
+    /// Whether new blocks and transactions are compressed
+    pub compressed: bool,
+
+    /// When the storage was last flushed to disk, None if not since the node started
+    pub last_flush: Option<DateTime<Utc>>,
+
+    /// When compress-storage last rewrote the existing records
+    pub last_compaction: Option<DateTime<Utc>>,
+}
+
+/// Health of the storage backing a chain, cheap enough to check on every poll
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct StorageHealth {
+    /// Whether the chain is stored on disk, an in-memory chain is lost on restart
+    pub persistent: bool,
+
+    /// Bytes of the database files on disk
+    pub size_on_disk: Option<u64>,
+
+    /// Whether new blocks and transactions are compressed
+    pub compressed: bool,
+
+    /// When the storage was last flushed to disk, None if not since the node started
+    pub last_flush: Option<DateTime<Utc>>,
+
+    /// When compress-storage last rewrote the existing records
+    pub last_compaction: Option<DateTime<Utc>>,
+
+    /// Why the storage could not be read, None if it could
+    pub error: Option<String>,
+}
+
+impl StorageHealth {
+    /// The health of a chain kept in memory
+    pub fn in_memory() -> Self {
+        StorageHealth {
+            persistent: false,
+            size_on_disk: None,
+            compressed: false,
+            last_flush: None,
+            last_compaction: None,
+            error: None,
+        }
+    }
 }
 
 impl std::fmt::Debug for BlockchainStorage {
@@ -51,6 +420,32 @@ impl std::fmt::Debug for BlockchainStorage {
     }
 }
 
+/// Opens the database, waiting briefly if its lock is still held
+///
+/// sled's background threads keep the lock for a moment after a database is
+/// closed, so reopening it in the same process can fail spuriously. A
+/// database held by another process still fails after the last attempt.
+fn open_db(path: &Path, flush_every_ms: Option<u64>) -> Result<Db, sled::Error> {
+    let mut attempt = 1;
+
+    loop {
+        match sled::Config::new().path(path).flush_every_ms(flush_every_ms).open() {
+            Err(sled::Error::Io(err)) if err.kind() == std::io::ErrorKind::Other && attempt < LOCK_ATTEMPTS => {
+                let locked = err
+                    .get_ref()
+                    .is_some_and(|inner| inner.to_string().contains("could not acquire lock"));
+                if !locked {
+                    return Err(sled::Error::Io(err));
+                }
+
+                attempt += 1;
+                thread::sleep(LOCK_RETRY_DELAY);
+            }
+            result => return result,
+        }
+    }
+}
+
 impl BlockchainStorage {
     /// Creates a new blockchain storage
     ///
@@ -62,23 +457,75 @@ impl BlockchainStorage {
     ///
     /// A new BlockchainStorage instance
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, StorageError> {
-        let db = sled::open(path)?;
+        Self::open(path, StorageOptions::default())
+    }
+
+    /// Opens a blockchain storage with options
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the database directory
+    /// * `options` - How to write records
+    ///
+    /// # Returns
+    ///
+    /// A new BlockchainStorage instance
+    pub fn open<P: AsRef<Path>>(path: P, options: StorageOptions) -> Result<Self, StorageError> {
+        let db = open_db(path.as_ref(), options.flush_policy.background_interval())?;
 
         let blocks = db.open_tree("blocks")?;
+        let raw_blocks = db.open_tree("raw_blocks")?;
+        let address_blooms = db.open_tree("address_blooms")?;
         let transactions = db.open_tree("transactions")?;
         let accounts = db.open_tree("accounts")?;
         let metadata = db.open_tree("metadata")?;
+        let payments = db.open_tree("payments")?;
+        // Invoice keys are derived from the keystore again, drop the ones older versions kept
+        db.drop_tree("invoice_keys")?;
+        let withdrawals = db.open_tree("withdrawals")?;
+        let schedules = db.open_tree("schedules")?;
+        let audit_log = db.open_tree("audit_log")?;
+        let keystore = db.open_tree("keystore")?;
+        let node_keys = db.open_tree("node_keys")?;
+        let labels = db.open_tree("labels")?;
+        let usage = db.open_tree("usage")?;
+        let reorgs = db.open_tree("reorgs")?;
+        let faucet_grants = db.open_tree("faucet_grants")?;
+        let mempool = db.open_tree("mempool")?;
+        let mempool_journal = db.open_tree("mempool_journal")?;
+
+        // Records compressed with the dictionary need it to be read back
+        let dictionary = metadata.get(DICTIONARY_KEY)?.map(|value| value.to_vec());
 
         Ok(Self {
             db,
             blocks,
+            raw_blocks,
+            address_blooms,
             transactions,
             accounts,
             metadata,
+            payments,
+            withdrawals,
+            schedules,
+            audit_log,
+            keystore,
+            node_keys,
+            labels,
+            usage,
+            reorgs,
+            faucet_grants,
+            mempool,
+            mempool_journal,
+            options,
+            compressor: RwLock::new(Compressor::new(dictionary)),
+            raw_block_hits: AtomicU64::new(0),
+            raw_block_misses: AtomicU64::new(0),
+            last_flush: Mutex::new(None),
         })
     }
 
-    /// Saves a block to the database
+    /// Saves a block and its transactions to the database
     ///
     /// # Arguments
     ///
@@ -88,11 +535,8 @@ impl BlockchainStorage {
     ///
     /// Ok(()) if successful
     pub fn save_block(&self, block: &Block) -> Result<(), StorageError> {
+        self.write_block(block)?;
         let key = block.hash.as_bytes();
-        let value = bincode::serialize(block)
-            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
-
-        self.blocks.insert(key, value)?;
 
         // Update latest block hash
         self.metadata.insert("latest_block_hash", key)?;
@@ -102,9 +546,102 @@ impl BlockchainStorage {
             .map_err(|e| StorageError::SerializationError(e.to_string()))?;
         self.metadata.insert("block_height", height_bytes)?;
 
+        self.written()
+    }
+
+    /// Writes a block as a header, with its transactions in the transactions tree
+    fn write_block(&self, block: &Block) -> Result<(), StorageError> {
+        // Transactions first, so a header never references missing transactions
+        for transaction in &block.transactions {
+            self.save_transaction(transaction)?;
+        }
+
+        let header = StoredBlock {
+            version: block.version,
+            index: block.index,
+            timestamp: block.timestamp,
+            transaction_ids: block.transactions.iter().map(|tx| tx.id.clone()).collect(),
+            proof: block.proof,
+            previous_hash: block.previous_hash.clone(),
+            hash: block.hash.clone(),
+        };
+
+        let mut value = BLOCK_HEADER_PREFIX.to_vec();
+        bincode::serialize_into(&mut value, &header)
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+        let value = self.encode_record(value)?;
+
+        self.blocks.insert(block.hash.as_bytes(), value)?;
+        self.address_blooms.insert(block.hash.as_bytes(), AddressBloom::for_block(block).as_bytes())?;
+        self.write_raw_block(&block.raw())
+    }
+
+    /// Writes the bytes a block was hashed from
+    fn write_raw_block(&self, raw: &RawBlock) -> Result<(), StorageError> {
+        let value = bincode::serialize(raw)
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+        let value = self.encode_record(value)?;
+
+        self.raw_blocks.insert(raw.hash.as_bytes(), value)?;
         Ok(())
     }
 
+    /// Reads a stored block, rebuilding it from its header and transactions
+    ///
+    /// Blocks stored in full by older versions are rewritten as headers the
+    /// first time they are read, upgrading their transactions on the way.
+    fn read_block(&self, value: &[u8]) -> Result<Block, StorageError> {
+        let data = self.decode_record(value)?;
+
+        let header = match data.strip_prefix(BLOCK_HEADER_PREFIX) {
+            Some(header) => header,
+            None => {
+                let block = bincode::DefaultOptions::new()
+                    .with_fixint_encoding()
+                    .reject_trailing_bytes()
+                    .deserialize::<Block>(&data)
+                    .or_else(|_| versioning::decode_full_block(&data))
+                    .map_err(|e| StorageError::DeserializationError(e.to_string()))?;
+                self.write_block(&block)?;
+                return Ok(block);
+            }
+        };
+
+        let header: StoredBlock = bincode::deserialize(header)
+            .map_err(|e| StorageError::DeserializationError(e.to_string()))?;
+        let transactions = header
+            .transaction_ids
+            .iter()
+            .map(|id| self.get_transaction(id))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Block {
+            version: header.version,
+            index: header.index,
+            timestamp: header.timestamp,
+            transactions,
+            proof: header.proof,
+            previous_hash: header.previous_hash,
+            hash: header.hash,
+        })
+    }
+
+    /// Removes a block from the database
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The hash of the block
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if successful
+    pub fn remove_block(&self, hash: &str) -> Result<(), StorageError> {
+        self.blocks.remove(hash.as_bytes())?;
+        self.raw_blocks.remove(hash.as_bytes())?;
+        self.address_blooms.remove(hash.as_bytes())?;
+        self.written()
+    }
+
     /// Gets a block by its hash
     ///
     /// # Arguments
@@ -118,8 +655,7 @@ impl BlockchainStorage {
         let key = hash.as_bytes();
 
         if let Some(value) = self.blocks.get(key)? {
-            let block: Block = bincode::deserialize(&value)
-                .map_err(|e| StorageError::DeserializationError(e.to_string()))?;
+            let block = self.read_block(&value)?;
 
             Ok(block)
         } else {
@@ -127,6 +663,66 @@ impl BlockchainStorage {
         }
     }
 
+    /// Gets the bytes a block and its transactions were hashed from
+    ///
+    /// Blocks stored before the bytes were kept get them computed from the
+    /// stored fields on first request.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The hash of the block
+    ///
+    /// # Returns
+    ///
+    /// The raw bytes of the block if found
+    pub fn get_raw_block(&self, hash: &str) -> Result<RawBlock, StorageError> {
+        if let Some(value) = self.raw_blocks.get(hash.as_bytes())? {
+            self.raw_block_hits.fetch_add(1, Ordering::Relaxed);
+            let data = self.decode_record(&value)?;
+            return bincode::deserialize(&data).map_err(|e| StorageError::DeserializationError(e.to_string()));
+        }
+
+        self.raw_block_misses.fetch_add(1, Ordering::Relaxed);
+        let raw = self.get_block(hash)?.raw();
+        self.write_raw_block(&raw)?;
+        Ok(raw)
+    }
+
+    /// Gets the bloom filter of the addresses a block touches
+    ///
+    /// Blocks stored before the filters were kept get theirs computed from
+    /// the block on first request.
+    ///
+    /// # Arguments
+    ///
+    /// * `hash` - The hash of the block
+    ///
+    /// # Returns
+    ///
+    /// The filter if the block is found
+    pub fn get_address_bloom(&self, hash: &str) -> Result<AddressBloom, StorageError> {
+        if let Some(value) = self.address_blooms.get(hash.as_bytes())? {
+            return AddressBloom::from_bytes(&value).map_err(StorageError::DeserializationError);
+        }
+
+        let bloom = AddressBloom::for_block(&self.get_block(hash)?);
+        self.address_blooms.insert(hash.as_bytes(), bloom.as_bytes())?;
+        Ok(bloom)
+    }
+
+    /// Gets the hashes of all stored blocks, including ones that can't be read
+    ///
+    /// # Returns
+    ///
+    /// The hashes in key order
+    pub fn get_block_hashes(&self) -> Result<Vec<String>, StorageError> {
+        self.blocks
+            .iter()
+            .keys()
+            .map(|key| Ok(String::from_utf8_lossy(&key?).to_string()))
+            .collect()
+    }
+
     /// Gets all blocks in the chain
     ///
     /// # Returns
@@ -139,7 +735,7 @@ impl BlockchainStorage {
         for result in self.blocks.iter() {
             match result {
                 Ok((key, value)) => {
-                    match bincode::deserialize::<Block>(&value) {
+                    match self.read_block(&value) {
                         Ok(block) => {
                             blocks.push(block);
                         },
@@ -188,6 +784,7 @@ impl BlockchainStorage {
         let key = transaction.id.as_bytes();
         let value = bincode::serialize(transaction)
             .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+        let value = self.encode_record(value)?;
 
         self.transactions.insert(key, value)?;
         Ok(())
@@ -206,7 +803,7 @@ impl BlockchainStorage {
         let key = id.as_bytes();
 
         if let Some(value) = self.transactions.get(key)? {
-            let transaction: Transaction = bincode::deserialize(&value)
+            let transaction = versioning::decode_transaction(&self.decode_record(&value)?)
                 .map_err(|e| StorageError::DeserializationError(e.to_string()))?;
 
             Ok(transaction)
@@ -230,7 +827,7 @@ impl BlockchainStorage {
             .map_err(|e| StorageError::SerializationError(e.to_string()))?;
 
         self.accounts.insert(key, value)?;
-        Ok(())
+        self.written()
     }
 
     /// Gets an account by its address
@@ -285,54 +882,1273 @@ impl BlockchainStorage {
         }
     }
 
-    /// Flushes all pending writes to disk
-    pub fn flush(&self) -> Result<(), StorageError> {
-        self.db.flush()?;
-        Ok(())
+    /// Gets the magic of the network the stored chain belongs to
+    ///
+    /// # Returns
+    ///
+    /// The magic, None for a new data directory or one written before networks were recorded
+    pub fn get_network_magic(&self) -> Result<Option<NetworkMagic>, StorageError> {
+        match self.metadata.get(NETWORK_MAGIC_KEY)? {
+            Some(value) => {
+                let magic = value
+                    .as_ref()
+                    .try_into()
+                    .map_err(|_| StorageError::DeserializationError("network magic is not 4 bytes".to_string()))?;
+                Ok(Some(NetworkMagic(magic)))
+            }
+            None => Ok(None),
+        }
     }
 
-    /// Gets all accounts from storage
+    /// Records the magic of the network the stored chain belongs to
+    ///
+    /// # Arguments
+    ///
+    /// * `magic` - The network magic
+    pub fn set_network_magic(&self, magic: NetworkMagic) -> Result<(), StorageError> {
+        self.metadata.insert(NETWORK_MAGIC_KEY, magic.0.as_slice())?;
+        self.flush()
+    }
+
+    /// Copies every tree of this database into another database
+    ///
+    /// Raw key/value pairs are copied, so the target holds exactly the same
+    /// data. The target's ID generator is advanced past this database's so
+    /// entries appended after the copy still sort after the copied ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - The storage to copy into
     ///
     /// # Returns
     ///
-    /// A vector of all accounts
-    pub fn get_all_accounts(&self) -> Result<Vec<Account>, StorageError> {
-        let mut accounts = Vec::new();
-        let mut deserialization_errors = Vec::new();
+    /// The name and number of entries of every copied tree
+    pub fn copy_into(&self, target: &BlockchainStorage) -> Result<Vec<(String, usize)>, StorageError> {
+        let mut copied = Vec::new();
 
-        for result in self.accounts.iter() {
-            match result {
-                Ok((key, value)) => {
-                    match bincode::deserialize::<Account>(&value) {
-                        Ok(account) => {
-                            accounts.push(account);
-                        },
-                        Err(e) => {
-                            // Log the error but continue processing other accounts
-                            let key_str = String::from_utf8_lossy(key.as_ref()).to_string();
-                            deserialization_errors.push(format!("Failed to deserialize account {}: {}", key_str, e));
-                        }
-                    }
-                },
-                Err(e) => {
-                    return Err(StorageError::DatabaseError(e));
-                }
+        for name in self.db.tree_names() {
+            let source = self.db.open_tree(&name)?;
+            let destination = target.db.open_tree(&name)?;
+            let mut entries = 0;
+
+            for result in source.iter() {
+                let (key, value) = result?;
+                destination.insert(key, value)?;
+                entries += 1;
             }
+
+            copied.push((String::from_utf8_lossy(&name).to_string(), entries));
         }
 
-        // If we have deserialization errors but also some valid accounts, log the errors but continue
-        if !deserialization_errors.is_empty() {
-            if accounts.is_empty() {
-                // If we have no valid accounts, return an error
-                return Err(StorageError::DeserializationError(
-                    format!("Failed to deserialize any accounts: {}", deserialization_errors.join(", "))
-                ));
-            } else {
-                // Log the errors but continue with the valid accounts
-                warn!("Some accounts could not be deserialized: {}", deserialization_errors.join(", "));
+        copied.sort();
+
+        let last_id = self.db.generate_id()?;
+        while target.db.generate_id()? < last_id {}
+
+        target.flush()?;
+        Ok(copied)
+    }
+
+    /// Writes a backup of every tree to a file
+    ///
+    /// Wraps sled's export. The records are written as they are stored,
+    /// compressed or not, with the compression dictionary, so the backup
+    /// restores exactly this database. The node must be stopped for the
+    /// backup to be consistent.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The file to create
+    ///
+    /// # Returns
+    ///
+    /// The backup report
+    pub fn export_to<P: AsRef<Path>>(&self, path: P) -> Result<BackupReport, StorageError> {
+        let blocks = self.get_all_blocks()?;
+        let header = BackupHeader {
+            format: BACKUP_FORMAT.to_string(),
+            version: BACKUP_VERSION,
+            created_at: Utc::now(),
+            blocks: blocks.len(),
+            tip: blocks.last().map(|block| block.hash.clone()),
+            last_id: self.db.generate_id()?,
+        };
+
+        let mut file = BufWriter::new(File::create(path)?);
+        let mut write_line = |line: &BackupLine| -> Result<(), StorageError> {
+            serde_json::to_writer(&mut file, line).map_err(|e| StorageError::SerializationError(e.to_string()))?;
+            file.write_all(b"\n")?;
+            Ok(())
+        };
+
+        write_line(&BackupLine::Header(header))?;
+        let mut trees = Vec::new();
+        let mut total = 0;
+        for (_, name, entries) in self.db.export() {
+            let tree = String::from_utf8_lossy(&name).to_string();
+            let mut count = 0;
+            for mut pair in entries {
+                let value = pair.pop().unwrap_or_default();
+                let key = pair.pop().unwrap_or_default();
+                write_line(&BackupLine::Entry {
+                    tree: tree.clone(),
+                    key: hex::encode(key),
+                    value: hex::encode(value),
+                })?;
+                count += 1;
             }
+            total += count;
+            trees.push((tree, count));
         }
+        write_line(&BackupLine::End { entries: total })?;
+        file.flush()?;
 
-        Ok(accounts)
+        trees.sort();
+        Ok(BackupReport {
+            trees,
+            blocks: blocks.len(),
+            tip: blocks.last().map(|block| block.hash.clone()),
+        })
+    }
+
+    /// Restores a backup written by `export_to` into this empty database
+    ///
+    /// Wraps sled's import. Every block is checked against its hash and its
+    /// parent, and the chain must end at the tip the backup was taken at.
+    /// If the check fails the imported data is removed again.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The backup file
+    ///
+    /// # Returns
+    ///
+    /// The backup report
+    pub fn import_from<P: AsRef<Path>>(&self, path: P) -> Result<BackupReport, StorageError> {
+        for name in self.db.tree_names() {
+            if !self.db.open_tree(&name)?.is_empty() {
+                return Err(StorageError::InvalidBackup("the target database is not empty".to_string()));
+            }
+        }
+
+        let invalid = |message: String| StorageError::InvalidBackup(message);
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let mut read_line = || -> Result<Option<BackupLine>, StorageError> {
+            match lines.next() {
+                Some(line) => serde_json::from_str(&line?).map(Some).map_err(|e| invalid(e.to_string())),
+                None => Ok(None),
+            }
+        };
+
+        let header = match read_line()? {
+            Some(BackupLine::Header(header)) if header.format == BACKUP_FORMAT => header,
+            _ => return Err(invalid("the file is not a storage backup".to_string())),
+        };
+        if header.version != BACKUP_VERSION {
+            return Err(invalid(format!("version {} is not supported", header.version)));
+        }
+
+        let mut trees: BTreeMap<String, Vec<Vec<Vec<u8>>>> = BTreeMap::new();
+        let mut total = 0;
+        loop {
+            match read_line()? {
+                Some(BackupLine::Entry { tree, key, value }) => {
+                    let key = hex::decode(key).map_err(|e| invalid(e.to_string()))?;
+                    let value = hex::decode(value).map_err(|e| invalid(e.to_string()))?;
+                    trees.entry(tree).or_default().push(vec![key, value]);
+                    total += 1;
+                }
+                Some(BackupLine::End { entries }) if entries == total => break,
+                Some(BackupLine::End { entries }) => {
+                    return Err(invalid(format!("expected {} entries, found {}", entries, total)));
+                }
+                Some(BackupLine::Header(_)) => return Err(invalid("a second header was found".to_string())),
+                None => return Err(invalid("the backup is truncated".to_string())),
+            }
+        }
+
+        let counts: Vec<(String, usize)> = trees.iter().map(|(name, entries)| (name.clone(), entries.len())).collect();
+        self.db.import(
+            trees
+                .into_iter()
+                .map(|(name, entries)| (b"tree".to_vec(), name.into_bytes(), entries.into_iter()))
+                .collect(),
+        );
+        while self.db.generate_id()? < header.last_id {}
+
+        // Records compressed with the dictionary need it to be read back
+        let dictionary = self.metadata.get(DICTIONARY_KEY)?.map(|value| value.to_vec());
+        *self.compressor.write().unwrap() = Compressor::new(dictionary);
+
+        if let Err(err) = self.verify_backup(&header) {
+            for name in self.db.tree_names() {
+                self.db.open_tree(&name)?.clear()?;
+            }
+            return Err(err);
+        }
+
+        self.flush()?;
+        Ok(BackupReport {
+            trees: counts,
+            blocks: header.blocks,
+            tip: header.tip,
+        })
+    }
+
+    /// Checks that the imported blocks hash to their keys and link up to the backup's tip
+    fn verify_backup(&self, header: &BackupHeader) -> Result<(), StorageError> {
+        let invalid = |message: String| StorageError::InvalidBackup(message);
+        let blocks = self.get_all_blocks().map_err(|e| invalid(e.to_string()))?;
+        if blocks.len() != header.blocks {
+            return Err(invalid(format!("expected {} blocks, found {}", header.blocks, blocks.len())));
+        }
+
+        for (position, block) in blocks.iter().enumerate() {
+            if block.hash != block.calculate_hash() {
+                return Err(invalid(format!("block {} does not match its hash {}", block.index, block.hash)));
+            }
+            if position > 0 && block.previous_hash != blocks[position - 1].hash {
+                return Err(invalid(format!("block {} does not link to block {}", block.index, position - 1)));
+            }
+        }
+        if blocks.last().map(|block| &block.hash) != header.tip.as_ref() {
+            return Err(invalid("the chain does not end at the backup's tip".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Compresses a serialized block or transaction if compression is enabled
+    fn encode_record(&self, data: Vec<u8>) -> Result<Vec<u8>, StorageError> {
+        if !self.options.compress {
+            return Ok(data);
+        }
+
+        self.compressor
+            .read()
+            .unwrap()
+            .compress(&data)
+            .map_err(|e| StorageError::SerializationError(e.to_string()))
+    }
+
+    /// Decompresses a stored block or transaction, whether compressed or not
+    fn decode_record<'a>(&self, value: &'a [u8]) -> Result<Cow<'a, [u8]>, StorageError> {
+        self.compressor
+            .read()
+            .unwrap()
+            .decompress(value)
+            .map_err(|e| StorageError::DeserializationError(e.to_string()))
+    }
+
+    /// Compresses all existing blocks, their raw bytes and transactions
+    ///
+    /// Trains a dictionary on the existing records first if none exists yet.
+    /// An existing dictionary is never replaced, since records compressed
+    /// with it could no longer be read. Safe to interrupt and run again.
+    ///
+    /// # Returns
+    ///
+    /// The compression report
+    pub fn compress_existing(&self) -> Result<CompressionReport, StorageError> {
+        let mut records = Vec::new();
+
+        for tree in [&self.blocks, &self.raw_blocks, &self.transactions] {
+            for result in tree.iter() {
+                let (key, value) = result?;
+                let data = self.decode_record(&value)?.into_owned();
+                records.push((tree, key, data, value.len()));
+            }
+        }
+
+        let mut report = CompressionReport::default();
+
+        if self.compressor.read().unwrap().dictionary().is_none() {
+            let samples: Vec<Vec<u8>> = records.iter().map(|(_, _, data, _)| data.clone()).collect();
+
+            if let Some(dictionary) = Compressor::train(&samples) {
+                self.metadata.insert(DICTIONARY_KEY, dictionary.as_slice())?;
+                *self.compressor.write().unwrap() = Compressor::new(Some(dictionary));
+                report.trained_dictionary = true;
+            }
+        }
+
+        let compressor = self.compressor.read().unwrap();
+        for (tree, key, data, stored_len) in records {
+            let compressed = compressor
+                .compress(&data)
+                .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+
+            report.records += 1;
+            report.bytes_before += stored_len;
+            report.bytes_after += compressed.len();
+            tree.insert(key, compressed)?;
+        }
+
+        self.metadata.insert(LAST_COMPACTION_KEY, Utc::now().to_rfc3339().as_bytes())?;
+        self.flush()?;
+        Ok(report)
+    }
+
+    /// Flushes all pending writes to disk
+    pub fn flush(&self) -> Result<(), StorageError> {
+        self.db.flush()?;
+        *self.last_flush.lock().unwrap() = Some(Utc::now());
+        Ok(())
+    }
+
+    /// Ends the writes of an applied block, flushing them unless the policy leaves it to the background
+    pub fn commit_block(&self) -> Result<(), StorageError> {
+        match self.options.flush_policy {
+            FlushPolicy::EveryWrite | FlushPolicy::OnBlockCommit => self.flush(),
+            FlushPolicy::Interval(_) => Ok(()),
+        }
+    }
+
+    /// Flushes a write that just finished if the policy flushes every write
+    fn written(&self) -> Result<(), StorageError> {
+        match self.options.flush_policy {
+            FlushPolicy::EveryWrite => self.flush(),
+            FlushPolicy::OnBlockCommit | FlushPolicy::Interval(_) => Ok(()),
+        }
+    }
+
+    /// Measures the database, reading every tree
+    ///
+    /// # Returns
+    ///
+    /// The size of the database and of each tree, the raw block cache and the last maintenance
+    pub fn stats(&self) -> Result<StorageStats, StorageError> {
+        let mut trees = Vec::new();
+        for name in self.db.tree_names() {
+            let tree = self.db.open_tree(&name)?;
+            let mut stats = TreeStats {
+                name: String::from_utf8_lossy(&name).to_string(),
+                keys: 0,
+                bytes: 0,
+            };
+            for result in tree.iter() {
+                let (key, value) = result?;
+                stats.keys += 1;
+                stats.bytes += (key.len() + value.len()) as u64;
+            }
+            trees.push(stats);
+        }
+        trees.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+
+        let hits = self.raw_block_hits.load(Ordering::Relaxed);
+        let misses = self.raw_block_misses.load(Ordering::Relaxed);
+        let last_compaction = self.last_compaction()?;
+
+        Ok(StorageStats {
+            size_on_disk: self.db.size_on_disk()?,
+            trees,
+            raw_block_cache: CacheStats {
+                hits,
+                misses,
+                hit_rate: (hits + misses > 0).then(|| hits as f64 / (hits + misses) as f64),
+            },
+            compressed: self.options.compress,
+            last_flush: *self.last_flush.lock().unwrap(),
+            last_compaction,
+        })
+    }
+
+    /// Checks the health of the database without reading its trees
+    ///
+    /// # Returns
+    ///
+    /// The size of the database and its last maintenance, or what kept them from being read
+    pub fn health(&self) -> StorageHealth {
+        let mut health = StorageHealth {
+            persistent: true,
+            size_on_disk: None,
+            compressed: self.options.compress,
+            last_flush: *self.last_flush.lock().unwrap(),
+            last_compaction: None,
+            error: None,
+        };
+
+        match self.db.size_on_disk().map_err(StorageError::from).and_then(|size| Ok((size, self.last_compaction()?))) {
+            Ok((size, last_compaction)) => {
+                health.size_on_disk = Some(size);
+                health.last_compaction = last_compaction;
+            }
+            Err(err) => health.error = Some(err.to_string()),
+        }
+
+        health
+    }
+
+    /// Reads when compress-storage last rewrote the existing records
+    fn last_compaction(&self) -> Result<Option<DateTime<Utc>>, StorageError> {
+        Ok(self
+            .metadata
+            .get(LAST_COMPACTION_KEY)?
+            .and_then(|value| DateTime::parse_from_rfc3339(&String::from_utf8_lossy(&value)).ok())
+            .map(|time| time.with_timezone(&Utc)))
+    }
+
+    /// Replaces all stored accounts
+    ///
+    /// # Arguments
+    ///
+    /// * `accounts` - The accounts to keep
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if successful
+    pub fn replace_accounts(&self, accounts: &[Account]) -> Result<(), StorageError> {
+        self.accounts.clear()?;
+
+        for account in accounts {
+            self.save_account(account)?;
+        }
+
+        self.written()
+    }
+
+    /// Removes all chain data, leaving the node's own records in place
+    ///
+    /// Blocks, transactions, accounts, reorganizations, faucet deposits, the
+    /// mempool and the block being mined are cleared. The compression dictionary, keystore, keys, audit log and
+    /// other records of the node are kept.
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if successful
+    pub fn clear_chain_data(&self) -> Result<(), StorageError> {
+        self.blocks.clear()?;
+        self.raw_blocks.clear()?;
+        self.address_blooms.clear()?;
+        self.transactions.clear()?;
+        self.accounts.clear()?;
+        self.reorgs.clear()?;
+        self.faucet_grants.clear()?;
+        self.mempool.clear()?;
+        self.mempool_journal.clear()?;
+        self.metadata.remove(MINING_TEMPLATE_KEY)?;
+        self.metadata.remove("latest_block_hash")?;
+        self.metadata.remove("block_height")?;
+
+        self.written()
+    }
+
+    /// Gets all accounts from storage
+    ///
+    /// # Returns
+    ///
+    /// A vector of all accounts
+    pub fn get_all_accounts(&self) -> Result<Vec<Account>, StorageError> {
+        let mut accounts = Vec::new();
+        let mut deserialization_errors = Vec::new();
+
+        for result in self.accounts.iter() {
+            match result {
+                Ok((key, value)) => {
+                    match bincode::deserialize::<Account>(&value) {
+                        Ok(account) => {
+                            accounts.push(account);
+                        },
+                        Err(e) => {
+                            // Log the error but continue processing other accounts
+                            let key_str = String::from_utf8_lossy(key.as_ref()).to_string();
+                            deserialization_errors.push(format!("Failed to deserialize account {}: {}", key_str, e));
+                        }
+                    }
+                },
+                Err(e) => {
+                    return Err(StorageError::DatabaseError(e));
+                }
+            }
+        }
+
+        // If we have deserialization errors but also some valid accounts, log the errors but continue
+        if !deserialization_errors.is_empty() {
+            if accounts.is_empty() {
+                // If we have no valid accounts, return an error
+                return Err(StorageError::DeserializationError(
+                    format!("Failed to deserialize any accounts: {}", deserialization_errors.join(", "))
+                ));
+            } else {
+                // Log the errors but continue with the valid accounts
+                warn!("Some accounts could not be deserialized: {}", deserialization_errors.join(", "));
+            }
+        }
+
+        Ok(accounts)
+    }
+
+    /// Saves an expected payment to the database
+    ///
+    /// # Arguments
+    ///
+    /// * `payment` - The payment to save
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if successful
+    pub fn save_payment(&self, payment: &Payment) -> Result<(), StorageError> {
+        let key = payment.id.as_bytes();
+        let value = bincode::serialize(payment)
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+
+        self.payments.insert(key, value)?;
+        self.written()
+    }
+
+    /// Gets all expected payments from storage
+    ///
+    /// # Returns
+    ///
+    /// A vector of all payments
+    pub fn get_all_payments(&self) -> Result<Vec<Payment>, StorageError> {
+        let mut payments = Vec::new();
+
+        for result in self.payments.iter() {
+            let (key, value) = result?;
+
+            match bincode::deserialize::<Payment>(&value) {
+                Ok(payment) => payments.push(payment),
+                Err(e) => {
+                    let key_str = String::from_utf8_lossy(key.as_ref()).to_string();
+                    warn!("Failed to deserialize payment {}: {}", key_str, e);
+                }
+            }
+        }
+
+        Ok(payments)
+    }
+
+    /// Saves a withdrawal request to the database
+    ///
+    /// # Arguments
+    ///
+    /// * `withdrawal` - The withdrawal to save
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if successful
+    pub fn save_withdrawal(&self, withdrawal: &Withdrawal) -> Result<(), StorageError> {
+        let key = withdrawal.id.as_bytes();
+        let value = bincode::serialize(withdrawal)
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+
+        self.withdrawals.insert(key, value)?;
+        self.written()
+    }
+
+    /// Replaces a withdrawal request only if storage still holds the given version
+    ///
+    /// # Arguments
+    ///
+    /// * `current` - The withdrawal as it was read
+    /// * `new` - The withdrawal to store in its place
+    ///
+    /// # Returns
+    ///
+    /// Whether the withdrawal was replaced, false if it changed in the meantime
+    pub fn swap_withdrawal(&self, current: &Withdrawal, new: &Withdrawal) -> Result<bool, StorageError> {
+        let serialize = |withdrawal: &Withdrawal| {
+            bincode::serialize(withdrawal).map_err(|e| StorageError::SerializationError(e.to_string()))
+        };
+
+        let swapped = self
+            .withdrawals
+            .compare_and_swap(new.id.as_bytes(), Some(serialize(current)?), Some(serialize(new)?))?;
+        if swapped.is_err() {
+            return Ok(false);
+        }
+
+        self.written()?;
+        Ok(true)
+    }
+
+    /// Gets all withdrawal requests from storage
+    ///
+    /// # Returns
+    ///
+    /// A vector of all withdrawals
+    pub fn get_all_withdrawals(&self) -> Result<Vec<Withdrawal>, StorageError> {
+        let mut withdrawals = Vec::new();
+
+        for result in self.withdrawals.iter() {
+            let (key, value) = result?;
+
+            match bincode::deserialize::<Withdrawal>(&value) {
+                Ok(withdrawal) => withdrawals.push(withdrawal),
+                Err(e) => {
+                    let key_str = String::from_utf8_lossy(key.as_ref()).to_string();
+                    warn!("Failed to deserialize withdrawal {}: {}", key_str, e);
+                }
+            }
+        }
+
+        Ok(withdrawals)
+    }
+
+    /// Saves a schedule and its session key to the database
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - The schedule to save
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if successful
+    pub fn save_schedule(&self, record: &ScheduleRecord) -> Result<(), StorageError> {
+        let key = record.schedule.id.as_bytes();
+        let value = bincode::serialize(record)
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+
+        self.schedules.insert(key, value)?;
+        self.written()
+    }
+
+    /// Gets all schedules from storage
+    ///
+    /// # Returns
+    ///
+    /// A vector of all schedules with their session keys
+    pub fn get_all_schedules(&self) -> Result<Vec<ScheduleRecord>, StorageError> {
+        let mut records = Vec::new();
+
+        for result in self.schedules.iter() {
+            let (key, value) = result?;
+
+            match bincode::deserialize::<ScheduleRecord>(&value) {
+                Ok(record) => records.push(record),
+                Err(e) => {
+                    let key_str = String::from_utf8_lossy(key.as_ref()).to_string();
+                    warn!("Failed to deserialize schedule {}: {}", key_str, e);
+                }
+            }
+        }
+
+        Ok(records)
+    }
+
+    /// Appends an entry to the audit log
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The entry to append
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if successful
+    pub fn append_audit_entry(&self, entry: &AuditEntry) -> Result<(), StorageError> {
+        // Monotonic IDs keep the entries in insertion order
+        let key = self.db.generate_id()?.to_be_bytes();
+        let value = bincode::serialize(entry)
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+
+        self.audit_log.insert(key, value)?;
+        self.written()
+    }
+
+    /// Gets all audit log entries in insertion order
+    ///
+    /// # Returns
+    ///
+    /// A vector of all audit entries
+    pub fn get_audit_entries(&self) -> Result<Vec<AuditEntry>, StorageError> {
+        let mut entries = Vec::new();
+
+        for result in self.audit_log.iter() {
+            let (_, value) = result?;
+            let entry = bincode::deserialize::<AuditEntry>(&value)
+                .map_err(|e| StorageError::DeserializationError(e.to_string()))?;
+            entries.push(entry);
+        }
+
+        Ok(entries)
+    }
+
+    /// Saves a keystore wallet to the database
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The wallet to save
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if successful
+    pub fn save_keystore_entry(&self, entry: &KeystoreEntry) -> Result<(), StorageError> {
+        let key = entry.name.as_bytes();
+        let value = bincode::serialize(entry)
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+
+        self.keystore.insert(key, value)?;
+        self.written()
+    }
+
+    /// Gets all keystore wallets from storage
+    ///
+    /// # Returns
+    ///
+    /// A vector of all keystore wallets
+    pub fn get_all_keystore_entries(&self) -> Result<Vec<KeystoreEntry>, StorageError> {
+        let mut entries = Vec::new();
+
+        for result in self.keystore.iter() {
+            let (key, value) = result?;
+
+            match bincode::deserialize::<KeystoreEntry>(&value) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => {
+                    let key_str = String::from_utf8_lossy(key.as_ref()).to_string();
+                    warn!("Failed to deserialize keystore wallet {}: {}", key_str, e);
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Saves the key ring of a node key purpose to the database
+    ///
+    /// # Arguments
+    ///
+    /// * `ring` - The key ring to save
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if successful
+    pub fn save_key_ring(&self, ring: &KeyRing) -> Result<(), StorageError> {
+        let key = ring.purpose.name().as_bytes();
+        let value = bincode::serialize(ring)
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+
+        self.node_keys.insert(key, value)?;
+        self.written()
+    }
+
+    /// Gets all node key rings from storage
+    ///
+    /// # Returns
+    ///
+    /// A vector of all node key rings
+    pub fn get_all_key_rings(&self) -> Result<Vec<KeyRing>, StorageError> {
+        let mut rings = Vec::new();
+
+        for result in self.node_keys.iter() {
+            let (key, value) = result?;
+
+            match bincode::deserialize::<KeyRing>(&value) {
+                Ok(ring) => rings.push(ring),
+                Err(e) => {
+                    let key_str = String::from_utf8_lossy(key.as_ref()).to_string();
+                    warn!("Failed to deserialize node key ring {}: {}", key_str, e);
+                }
+            }
+        }
+
+        Ok(rings)
+    }
+
+    /// Saves a transaction label to the database
+    ///
+    /// # Arguments
+    ///
+    /// * `owner` - The owner of the label
+    /// * `label` - The label to save
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if successful
+    pub fn save_label(&self, owner: &str, label: &TransactionLabel) -> Result<(), StorageError> {
+        let key = format!("{}:{}", owner, label.transaction_id);
+        let value = bincode::serialize(&(owner, label))
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+
+        self.labels.insert(key.as_bytes(), value)?;
+        self.written()
+    }
+
+    /// Gets all transaction labels with their owners from storage
+    ///
+    /// # Returns
+    ///
+    /// A vector of all (owner, label) pairs
+    pub fn get_all_labels(&self) -> Result<Vec<(String, TransactionLabel)>, StorageError> {
+        let mut labels = Vec::new();
+
+        for result in self.labels.iter() {
+            let (key, value) = result?;
+
+            match bincode::deserialize::<(String, TransactionLabel)>(&value) {
+                Ok(label) => labels.push(label),
+                Err(e) => {
+                    let key_str = String::from_utf8_lossy(key.as_ref()).to_string();
+                    warn!("Failed to deserialize label {}: {}", key_str, e);
+                }
+            }
+        }
+
+        Ok(labels)
+    }
+
+    /// Saves the usage of an API key on a day to the database
+    ///
+    /// # Arguments
+    ///
+    /// * `usage` - The usage to save, replacing the earlier count of the day
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if successful
+    pub fn save_usage(&self, usage: &DailyUsage) -> Result<(), StorageError> {
+        let key = format!("{}:{}", usage.key_id, usage.day);
+        let value = bincode::serialize(usage).map_err(|e| StorageError::SerializationError(e.to_string()))?;
+
+        self.usage.insert(key.as_bytes(), value)?;
+        self.written()
+    }
+
+    /// Gets the daily usage of every API key from storage
+    ///
+    /// # Returns
+    ///
+    /// A vector of the usage of each key on each day
+    pub fn get_all_usage(&self) -> Result<Vec<DailyUsage>, StorageError> {
+        let mut usage = Vec::new();
+
+        for result in self.usage.iter() {
+            let (key, value) = result?;
+
+            match bincode::deserialize::<DailyUsage>(&value) {
+                Ok(day) => usage.push(day),
+                Err(e) => {
+                    let key_str = String::from_utf8_lossy(key.as_ref()).to_string();
+                    warn!("Failed to deserialize usage {}: {}", key_str, e);
+                }
+            }
+        }
+
+        Ok(usage)
+    }
+
+    /// Appends a chain reorganization to the database
+    ///
+    /// # Arguments
+    ///
+    /// * `reorg` - The reorganization to append
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if successful
+    pub fn append_reorg(&self, reorg: &ReorgEvent) -> Result<(), StorageError> {
+        let key = self.db.generate_id()?.to_be_bytes();
+        let value = bincode::serialize(reorg)
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+
+        self.reorgs.insert(key, value)?;
+        self.written()
+    }
+
+    /// Gets all chain reorganizations in the order they happened
+    ///
+    /// # Returns
+    ///
+    /// A vector of all reorganizations
+    pub fn get_all_reorgs(&self) -> Result<Vec<ReorgEvent>, StorageError> {
+        let mut reorgs = Vec::new();
+
+        for result in self.reorgs.iter() {
+            let (_, value) = result?;
+            let reorg = bincode::deserialize::<ReorgEvent>(&value)
+                .map_err(|e| StorageError::DeserializationError(e.to_string()))?;
+            reorgs.push(reorg);
+        }
+
+        Ok(reorgs)
+    }
+
+    /// Appends a deposit of the faucet to the database
+    ///
+    /// # Arguments
+    ///
+    /// * `grant` - The deposit to append
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if successful
+    pub fn append_faucet_grant(&self, grant: &FaucetGrant) -> Result<(), StorageError> {
+        let key = self.db.generate_id()?.to_be_bytes();
+        let value = bincode::serialize(grant)
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+
+        self.faucet_grants.insert(key, value)?;
+        self.written()
+    }
+
+    /// Replaces the deposits of the faucet
+    ///
+    /// # Arguments
+    ///
+    /// * `grants` - The deposits, oldest first
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if successful
+    pub fn replace_faucet_grants(&self, grants: &[FaucetGrant]) -> Result<(), StorageError> {
+        self.faucet_grants.clear()?;
+        for grant in grants {
+            self.append_faucet_grant(grant)?;
+        }
+
+        self.written()
+    }
+
+    /// Gets all deposits of the faucet in the order they were made
+    ///
+    /// # Returns
+    ///
+    /// A vector of all deposits
+    pub fn get_faucet_grants(&self) -> Result<Vec<FaucetGrant>, StorageError> {
+        let mut grants = Vec::new();
+
+        for result in self.faucet_grants.iter() {
+            let (_, value) = result?;
+            let grant = bincode::deserialize::<FaucetGrant>(&value)
+                .map_err(|e| StorageError::DeserializationError(e.to_string()))?;
+            grants.push(grant);
+        }
+
+        Ok(grants)
+    }
+
+    /// Replaces the mempool snapshot and clears the journal
+    ///
+    /// Replaying a journal on top of a newer snapshot is harmless, so a crash
+    /// between writing the snapshot and clearing the journal loses nothing.
+    ///
+    /// # Arguments
+    ///
+    /// * `pending` - The pending transactions in admission order
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if successful
+    pub fn save_mempool_snapshot(&self, pending: &[Transaction]) -> Result<(), StorageError> {
+        self.mempool.clear()?;
+
+        for (position, transaction) in pending.iter().enumerate() {
+            let value = bincode::serialize(transaction)
+                .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+            self.mempool.insert((position as u64).to_be_bytes(), value)?;
+        }
+
+        self.mempool_journal.clear()?;
+        self.written()
+    }
+
+    /// Gets the pending transactions of the last mempool snapshot
+    ///
+    /// # Returns
+    ///
+    /// The pending transactions in admission order
+    pub fn get_mempool_snapshot(&self) -> Result<Vec<Transaction>, StorageError> {
+        let mut pending = Vec::new();
+
+        for result in self.mempool.iter() {
+            let (key, value) = result?;
+
+            match versioning::decode_transaction(&value) {
+                Ok(transaction) => pending.push(transaction),
+                Err(e) => warn!("Failed to deserialize mempool transaction {:?}: {}", key, e),
+            }
+        }
+
+        Ok(pending)
+    }
+
+    /// Appends entries to the mempool journal
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - The entries to append, oldest first
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if successful
+    pub fn append_mempool_journal(&self, entries: &[MempoolJournalEntry]) -> Result<(), StorageError> {
+        for entry in entries {
+            let key = self.db.generate_id()?.to_be_bytes();
+            let value = bincode::serialize(entry)
+                .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+            self.mempool_journal.insert(key, value)?;
+        }
+
+        self.written()
+    }
+
+    /// Gets the mempool journal written since the last snapshot
+    ///
+    /// # Returns
+    ///
+    /// The journal entries, oldest first
+    pub fn get_mempool_journal(&self) -> Result<Vec<MempoolJournalEntry>, StorageError> {
+        let mut entries = Vec::new();
+
+        for result in self.mempool_journal.iter() {
+            let (key, value) = result?;
+
+            match bincode::deserialize::<MempoolJournalEntry>(&value) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => warn!("Failed to deserialize mempool journal entry {:?}: {}", key, e),
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Records the block being mined and the transactions taken out of the mempool for it
+    ///
+    /// Flushed whatever the flush policy, so the transactions are never only
+    /// in memory while the proof of work runs.
+    ///
+    /// # Arguments
+    ///
+    /// * `template` - The block being mined
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if successful
+    pub fn save_mining_template(&self, template: &MiningTemplate) -> Result<(), StorageError> {
+        let value = bincode::serialize(template)
+            .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+        self.metadata.insert(MINING_TEMPLATE_KEY, value)?;
+        self.flush()
+    }
+
+    /// Gets the block that was being mined, if the node stopped before it was on the chain
+    ///
+    /// # Returns
+    ///
+    /// The block being mined, None if there is none
+    pub fn get_mining_template(&self) -> Result<Option<MiningTemplate>, StorageError> {
+        match self.metadata.get(MINING_TEMPLATE_KEY)? {
+            Some(value) => bincode::deserialize(&value)
+                .map(Some)
+                .map_err(|e| StorageError::DeserializationError(e.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    /// Forgets the block being mined, once it is on the chain or its transactions are back in the mempool
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if successful
+    pub fn clear_mining_template(&self) -> Result<(), StorageError> {
+        self.metadata.remove(MINING_TEMPLATE_KEY)?;
+        self.written()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocks_stored_as_headers_with_lazy_migration() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let storage = BlockchainStorage::new(data_dir.path()).unwrap();
+
+        let transaction = Transaction::new_coinbase(Address("miner".to_string()), 50.0);
+        let block = Block::new(1, vec![transaction.clone()], 7, "0".to_string());
+        storage.save_block(&block).unwrap();
+
+        // The transaction is stored once, the block only references it
+        let stored = storage.blocks.get(block.hash.as_bytes()).unwrap().unwrap();
+        assert!(stored.starts_with(BLOCK_HEADER_PREFIX));
+        assert!(stored.len() < bincode::serialize(&block).unwrap().len());
+
+        let loaded = storage.get_block(&block.hash).unwrap();
+        assert_eq!(loaded.calculate_hash(), block.hash);
+        assert_eq!(loaded.transactions[0].id, transaction.id);
+
+        // A block stored in full by an older version is rewritten on first read
+        let legacy = Block::new(2, vec![Transaction::new_coinbase(Address("old".to_string()), 50.0)], 9, block.hash.clone());
+        storage
+            .blocks
+            .insert(legacy.hash.as_bytes(), bincode::serialize(&legacy).unwrap())
+            .unwrap();
+
+        let blocks = storage.get_all_blocks().unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[1].calculate_hash(), legacy.hash);
+
+        let migrated = storage.blocks.get(legacy.hash.as_bytes()).unwrap().unwrap();
+        assert!(migrated.starts_with(BLOCK_HEADER_PREFIX));
+        assert!(storage.get_transaction(&legacy.transactions[0].id).is_ok());
+    }
+
+    #[test]
+    fn test_raw_blocks_hash_to_the_block() {
+        use sha2::{Digest, Sha256};
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let storage = BlockchainStorage::new(data_dir.path()).unwrap();
+
+        let transaction = Transaction::new_coinbase(Address("miner".to_string()), 50.0);
+        let block = Block::new(1, vec![transaction.clone()], 7, "0".to_string());
+        storage.save_block(&block).unwrap();
+
+        let raw = storage.get_raw_block(&block.hash).unwrap();
+        assert_eq!(format!("{:x}", Sha256::digest(&raw.bytes)), block.hash);
+        let id_bytes = raw.transactions[0].bytes.as_ref().unwrap();
+        assert_eq!(format!("{:x}", Sha256::digest(id_bytes)), transaction.id);
+
+        // Blocks stored before the bytes were kept get them on first request
+        storage.raw_blocks.remove(block.hash.as_bytes()).unwrap();
+        assert_eq!(storage.get_raw_block(&block.hash).unwrap(), raw);
+        assert!(storage.raw_blocks.contains_key(block.hash.as_bytes()).unwrap());
+
+        storage.remove_block(&block.hash).unwrap();
+        assert!(matches!(storage.get_raw_block(&block.hash), Err(StorageError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_address_blooms_are_kept_with_blocks() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let storage = BlockchainStorage::new(data_dir.path()).unwrap();
+        let miner = Address("miner".to_string());
+        let block = Block::new(1, vec![Transaction::new_coinbase(miner.clone(), 50.0)], 7, "0".to_string());
+        storage.save_block(&block).unwrap();
+
+        let bloom = storage.get_address_bloom(&block.hash).unwrap();
+        assert!(bloom.may_contain(&miner));
+        assert_eq!(bloom, AddressBloom::for_block(&block));
+
+        // Blocks stored before the filters were kept get them on first request
+        storage.address_blooms.remove(block.hash.as_bytes()).unwrap();
+        assert_eq!(storage.get_address_bloom(&block.hash).unwrap(), bloom);
+        assert!(storage.address_blooms.contains_key(block.hash.as_bytes()).unwrap());
+
+        storage.remove_block(&block.hash).unwrap();
+        assert!(matches!(storage.get_address_bloom(&block.hash), Err(StorageError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_stats_count_trees_and_cache_hits() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let storage = BlockchainStorage::new(data_dir.path()).unwrap();
+        let block = Block::new(1, vec![Transaction::new_coinbase(Address("miner".to_string()), 50.0)], 7, "0".to_string());
+        storage.save_block(&block).unwrap();
+
+        storage.get_raw_block(&block.hash).unwrap();
+        storage.raw_blocks.remove(block.hash.as_bytes()).unwrap();
+        storage.get_raw_block(&block.hash).unwrap();
+        storage.flush().unwrap();
+
+        let stats = storage.stats().unwrap();
+        let blocks = stats.trees.iter().find(|tree| tree.name == "blocks").unwrap();
+        assert_eq!(blocks.keys, 1);
+        assert!(blocks.bytes > 0);
+        assert_eq!(stats.raw_block_cache.hits, 1);
+        assert_eq!(stats.raw_block_cache.hit_rate, Some(0.5));
+        assert!(stats.last_flush.is_some());
+        assert!(stats.last_compaction.is_none());
+
+        let health = storage.health();
+        assert_eq!(health.size_on_disk, Some(stats.size_on_disk));
+        assert_eq!(health.last_flush, stats.last_flush);
+        assert!(health.error.is_none());
+
+        storage.compress_existing().unwrap();
+        assert!(storage.stats().unwrap().last_compaction.is_some());
+        assert!(storage.health().last_compaction.is_some());
+    }
+
+    #[test]
+    fn test_flush_policy_decides_when_writes_are_flushed() {
+        assert_eq!("interval:250".parse(), Ok(FlushPolicy::Interval(250)));
+        assert_eq!("every-write".parse::<FlushPolicy>().unwrap().to_string(), "every-write");
+        assert!("interval:0".parse::<FlushPolicy>().is_err());
+        assert!("sometimes".parse::<FlushPolicy>().is_err());
+
+        let open = |flush_policy: FlushPolicy| {
+            let data_dir = tempfile::tempdir().unwrap();
+            let storage = BlockchainStorage::open(data_dir.path(), StorageOptions { flush_policy, ..Default::default() });
+            (storage.unwrap(), data_dir)
+        };
+        let account = Account::new(Address("alice".to_string()));
+
+        let (storage, _data_dir) = open(FlushPolicy::Interval(60_000));
+        storage.save_account(&account).unwrap();
+        storage.commit_block().unwrap();
+        assert!(storage.stats().unwrap().last_flush.is_none());
+
+        let (storage, _data_dir) = open(FlushPolicy::OnBlockCommit);
+        storage.save_account(&account).unwrap();
+        assert!(storage.stats().unwrap().last_flush.is_none());
+        storage.commit_block().unwrap();
+        assert!(storage.stats().unwrap().last_flush.is_some());
+
+        let (storage, _data_dir) = open(FlushPolicy::EveryWrite);
+        storage.save_account(&account).unwrap();
+        assert!(storage.stats().unwrap().last_flush.is_some());
+    }
+
+    #[test]
+    fn test_backup_restores_and_verifies_blocks() {
+        let root = tempfile::tempdir().unwrap();
+        let temp = |name: &str| root.path().join(name);
+        let (data_dir, restored_dir, forged_dir, backup) =
+            (temp("backup-source"), temp("backup-restored"), temp("backup-forged"), temp("storage.backup"));
+
+        let storage = BlockchainStorage::open(&data_dir, StorageOptions { compress: true, ..Default::default() }).unwrap();
+        let genesis = Block::new(0, Vec::new(), 1, "0".to_string());
+        let block = Block::new(1, vec![Transaction::new_coinbase(Address("miner".to_string()), 50.0)], 7, genesis.hash.clone());
+        storage.save_block(&genesis).unwrap();
+        storage.save_block(&block).unwrap();
+        storage.save_account(&Account::new(Address("miner".to_string()))).unwrap();
+        assert_eq!(storage.export_to(&backup).unwrap().blocks, 2);
+
+        let restored = BlockchainStorage::new(&restored_dir).unwrap();
+        let report = restored.import_from(&backup).unwrap();
+        assert_eq!(report.tip, Some(block.hash.clone()));
+        assert!(report.trees.contains(&("accounts".to_string(), 1)));
+        assert_eq!(restored.get_all_blocks().unwrap()[1].calculate_hash(), block.hash);
+        assert!(matches!(restored.import_from(&backup), Err(StorageError::InvalidBackup(_))));
+
+        // A block that doesn't match its hash is refused and nothing is left behind
+        let mut forged = block.clone();
+        forged.proof = 8;
+        storage.blocks.insert(forged.hash.as_bytes(), bincode::serialize(&forged).unwrap()).unwrap();
+        storage.export_to(&backup).unwrap();
+        let target = BlockchainStorage::new(&forged_dir).unwrap();
+        assert!(matches!(target.import_from(&backup), Err(StorageError::InvalidBackup(_))));
+        assert!(target.get_all_blocks().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_stored_records_are_stable() {
+        use crate::blockchain::golden;
+
+        let data_dir = tempfile::tempdir().unwrap();
+        let storage = BlockchainStorage::new(data_dir.path()).unwrap();
+
+        let block = golden::sample_block();
+        storage.save_block(&block).unwrap();
+        let record = |tree: &Tree, key: &str| tree.get(key.as_bytes()).unwrap().unwrap().to_vec();
+        golden::assert_bytes("storage.block.hex", &record(&storage.blocks, &block.hash));
+        golden::assert_bytes("storage.raw_block.hex", &record(&storage.raw_blocks, &block.hash));
+        for (position, transaction) in block.transactions.iter().enumerate() {
+            let name = format!("storage.transaction.{}.hex", position);
+            golden::assert_bytes(&name, &record(&storage.transactions, &transaction.id));
+        }
+
+        // A database written by this version reads back the same block
+        storage.clear_chain_data().unwrap();
+        storage.blocks.insert(block.hash.as_bytes(), golden::read_bytes("storage.block.hex")).unwrap();
+        for (position, transaction) in block.transactions.iter().enumerate() {
+            let name = format!("storage.transaction.{}.hex", position);
+            storage.transactions.insert(transaction.id.as_bytes(), golden::read_bytes(&name)).unwrap();
+        }
+        let loaded = storage.get_block(&block.hash).unwrap();
+        assert_eq!(loaded.calculate_hash(), block.hash);
     }
 }
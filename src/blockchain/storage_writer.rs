@@ -0,0 +1,141 @@
+use log::warn;
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::Arc;
+use std::thread;
+
+use super::storage::{BlockchainStorage, StorageError};
+
+/// Writes waiting for the writer thread before handing over another one blocks
+pub const WRITE_QUEUE_CAPACITY: usize = 64;
+
+/// A write to run on the writer thread
+type Write = Box<dyn FnOnce(&BlockchainStorage) -> Result<(), StorageError> + Send>;
+
+/// Work for the writer thread
+enum Job {
+    /// Runs a write
+    Write(Write),
+
+    /// Answers once the writes handed over before are done, with the first of their errors
+    Sync(mpsc::Sender<Result<(), StorageError>>),
+}
+
+/// Writes to the storage on a dedicated thread, in the order they are handed over
+///
+/// The chain hands its block and account writes over while it is locked and
+/// waits for them after unlocking, so a slow disk holds up the request that
+/// changed the chain but not the requests waiting for the lock. The queue is
+/// bounded: once it is full, handing over a write blocks until the thread
+/// catches up.
+#[derive(Clone)]
+pub struct StorageWriter {
+    sender: SyncSender<Job>,
+}
+
+impl fmt::Debug for StorageWriter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StorageWriter").finish()
+    }
+}
+
+impl StorageWriter {
+    /// Starts the writer thread, which stops once every handle is dropped
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - The storage to write to
+    /// * `capacity` - How many writes may wait for the thread
+    ///
+    /// # Returns
+    ///
+    /// Result with the writer, or an error if the thread could not be started
+    pub fn start(storage: Arc<BlockchainStorage>, capacity: usize) -> Result<Self, StorageError> {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        thread::Builder::new()
+            .name("storage-writer".to_string())
+            .spawn(move || Self::run(&storage, receiver))
+            .map_err(StorageError::IoError)?;
+
+        Ok(StorageWriter { sender })
+    }
+
+    /// Hands a write over to the writer thread, waiting while the queue is full
+    ///
+    /// # Arguments
+    ///
+    /// * `write` - The write, its error is returned by the next `sync`
+    pub fn write<F>(&self, write: F) -> Result<(), StorageError>
+    where
+        F: FnOnce(&BlockchainStorage) -> Result<(), StorageError> + Send + 'static,
+    {
+        self.sender
+            .send(Job::Write(Box::new(write)))
+            .map_err(|_| Self::stopped())
+    }
+
+    /// Waits until the writes handed over so far are done
+    ///
+    /// # Returns
+    ///
+    /// Result with the first error of these writes, if any
+    pub fn sync(&self) -> Result<(), StorageError> {
+        let (sender, receiver) = mpsc::channel();
+        self.sender.send(Job::Sync(sender)).map_err(|_| Self::stopped())?;
+        receiver.recv().map_err(|_| Self::stopped())?
+    }
+
+    /// Runs the writes until every handle is dropped
+    fn run(storage: &BlockchainStorage, receiver: Receiver<Job>) {
+        let mut failed = None;
+
+        for job in receiver {
+            match job {
+                Job::Write(write) => {
+                    if let Err(err) = write(storage) {
+                        warn!("Storage write failed: {}", err);
+                        failed.get_or_insert(err);
+                    }
+                }
+                Job::Sync(reply) => {
+                    let _ = reply.send(failed.take().map_or(Ok(()), Err));
+                }
+            }
+        }
+    }
+
+    /// The error of a writer whose thread is gone
+    fn stopped() -> StorageError {
+        StorageError::IoError(std::io::Error::other("The storage writer stopped"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::account::Account;
+    use crate::blockchain::crypto::Address;
+
+    #[test]
+    fn test_writes_run_in_order_and_report_errors_on_sync() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let storage = Arc::new(BlockchainStorage::new(data_dir.path()).unwrap());
+        let writer = StorageWriter::start(storage.clone(), 1).unwrap();
+
+        for balance in 1..=3 {
+            writer
+                .write(move |storage| {
+                    let mut account = Account::new(Address("alice".to_string()));
+                    account.balance = balance as f64;
+                    storage.save_account(&account)
+                })
+                .unwrap();
+        }
+        writer.write(|_| Err(StorageError::NotFound("gone".to_string()))).unwrap();
+        assert!(matches!(writer.sync(), Err(StorageError::NotFound(_))));
+        assert_eq!(storage.get_account(&Address("alice".to_string())).unwrap().balance, 3.0);
+
+        // An error is reported once
+        writer.sync().unwrap();
+    }
+}
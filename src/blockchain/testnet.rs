@@ -0,0 +1,250 @@
+use chrono::{DateTime, Duration, Utc};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use thiserror::Error;
+
+use super::account::{Account, AccountError, AccountState};
+use super::block::Block;
+use super::crypto::{CryptoError, Wallet};
+use super::fees::{self, BlockFees};
+use super::state::ChainState;
+use super::transaction::{Transaction, TransactionError};
+use super::validation::{ValidationError, ValidationRules};
+
+/// Difficulty of generated chains unless the rules say otherwise, low enough to generate quickly
+pub const TESTNET_DIFFICULTY: u8 = 1;
+
+/// Time of the genesis block of every generated chain
+pub const TESTNET_GENESIS_TIME: &str = "2024-01-01T00:00:00Z";
+
+/// Seconds between two generated blocks
+pub const TESTNET_BLOCK_INTERVAL_SECS: i64 = 60;
+
+/// Errors that can occur while generating a test network
+#[derive(Debug, Error)]
+pub enum TestNetError {
+    #[error("Invalid test network: {0}")]
+    InvalidParameters(String),
+
+    #[error("Crypto error: {0}")]
+    CryptoError(#[from] CryptoError),
+
+    #[error("Transaction error: {0}")]
+    TransactionError(#[from] TransactionError),
+
+    #[error("Account error: {0}")]
+    AccountError(#[from] AccountError),
+
+    #[error("Validation error: {0}")]
+    ValidationError(#[from] ValidationError),
+}
+
+/// A valid chain generated from a seed, with the wallets that built it
+///
+/// The same seed and sizes always give the same keys, transactions and
+/// blocks, down to their timestamps and hashes, so benchmarks, fuzzing
+/// corpora and documentation examples can rely on them.
+///
+/// ```
+/// use my_blockchain::blockchain::testnet::TestNet;
+/// use my_blockchain::blockchain::verify::verify_chain;
+///
+/// let testnet = TestNet::generate(7, 3, 5, 2).unwrap();
+/// assert_eq!(testnet.blocks.len(), 6);
+///
+/// let rules = &testnet.rules;
+/// verify_chain(&testnet.blocks, rules.difficulty, rules.mining_reward, &rules.fee_policy).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct TestNet {
+    /// The wallets that mine and send, mining the blocks in turn
+    pub wallets: Vec<Wallet>,
+
+    /// The blocks of the chain, genesis first
+    pub blocks: Vec<Block>,
+
+    /// The accounts after the last block
+    pub accounts: Vec<Account>,
+
+    /// The rules every block was checked against
+    pub rules: ValidationRules,
+}
+
+impl TestNet {
+    /// Generates a test network with the node's rules at `TESTNET_DIFFICULTY`
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - Seed of the keys and transactions
+    /// * `accounts` - Number of wallets, at least 1
+    /// * `blocks` - Number of blocks after the genesis block
+    /// * `tx_per_block` - Most transfers each block holds besides its coinbase
+    ///
+    /// # Returns
+    ///
+    /// Result with the test network
+    pub fn generate(seed: u64, accounts: usize, blocks: u64, tx_per_block: usize) -> Result<Self, TestNetError> {
+        let rules = ValidationRules {
+            difficulty: TESTNET_DIFFICULTY,
+            ..ValidationRules::node()
+        };
+
+        Self::generate_with_rules(seed, accounts, blocks, tx_per_block, rules)
+    }
+
+    /// Generates a test network following some rules
+    ///
+    /// The wallets mine the blocks in turn. Each block holds up to
+    /// `tx_per_block` transfers between random wallets, sent by wallets
+    /// that can pay them before the block, so the first blocks may hold
+    /// fewer while coins are still scarce. Every block is connected to the
+    /// chain through full validation.
+    ///
+    /// # Arguments
+    ///
+    /// * `seed` - Seed of the keys and transactions
+    /// * `accounts` - Number of wallets, at least 1
+    /// * `blocks` - Number of blocks after the genesis block
+    /// * `tx_per_block` - Most transfers each block holds besides its coinbase
+    /// * `rules` - The rules blocks follow
+    ///
+    /// # Returns
+    ///
+    /// Result with the test network
+    pub fn generate_with_rules(
+        seed: u64,
+        accounts: usize,
+        blocks: u64,
+        tx_per_block: usize,
+        rules: ValidationRules,
+    ) -> Result<Self, TestNetError> {
+        if accounts == 0 {
+            return Err(TestNetError::InvalidParameters("A test network needs at least 1 account".to_string()));
+        }
+        if tx_per_block > rules.max_block_transactions {
+            return Err(TestNetError::InvalidParameters(format!(
+                "A block holds at most {} transactions besides its coinbase",
+                rules.max_block_transactions
+            )));
+        }
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let wallets = (0..accounts)
+            .map(|_| Wallet::from_secret_key(&rng.gen::<[u8; 32]>()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let genesis_time = DateTime::parse_from_rfc3339(TESTNET_GENESIS_TIME)
+            .expect("the genesis time is a valid RFC 3339 timestamp")
+            .with_timezone(&Utc);
+        let mut genesis = Block::new(0, Vec::new(), 1, "0".to_string());
+        genesis.timestamp = genesis_time;
+        genesis.hash = genesis.calculate_hash();
+        let mut state = ChainState::new(genesis);
+        let mut base_fee = rules.minimum_fee;
+
+        for index in 1..=blocks {
+            let time = genesis_time + Duration::seconds(index as i64 * TESTNET_BLOCK_INTERVAL_SECS);
+
+            // Transfers are applied to a copy as they are made, so none overdraws its sender
+            let pending = AccountState::new();
+            pending.replace_all(state.accounts().get_all_accounts());
+            let mut transactions = Vec::new();
+            for _ in 0..tx_per_block {
+                if accounts < 2 {
+                    break;
+                }
+                let sender = &wallets[rng.gen_range(0..accounts)];
+                let recipient = loop {
+                    let recipient = &wallets[rng.gen_range(0..accounts)];
+                    if recipient.address() != sender.address() {
+                        break recipient;
+                    }
+                };
+
+                // Up to half of what the sender can spend, in whole cents
+                let account = pending.get_account(sender.address());
+                let spendable = account.balance - base_fee;
+                let amount = (spendable / 2.0 * rng.gen::<f64>() * 100.0).floor() / 100.0;
+                if amount <= 0.0 {
+                    continue;
+                }
+
+                let mut transaction = Transaction::new(
+                    sender.address().clone(),
+                    recipient.address().clone(),
+                    amount,
+                    base_fee,
+                    account.nonce,
+                );
+                transaction.timestamp = time;
+                transaction.sign(sender)?;
+                pending.transfer(sender.address(), recipient.address(), amount, transaction.fee, transaction.nonce)?;
+                transactions.push(transaction);
+            }
+
+            let miner = &wallets[(index - 1) as usize % accounts];
+            let block_fees = BlockFees::new(&transactions, base_fee);
+            let mut coinbase =
+                Transaction::new_coinbase(miner.address().clone(), rules.fee_policy.miner_amount(rules.mining_reward, &block_fees));
+            if let Some(payout) = rules.fee_policy.payout(rules.mining_reward, block_fees.base) {
+                coinbase = coinbase.with_treasury(payout);
+            }
+            coinbase.timestamp = time;
+            coinbase.id = coinbase.compute_id();
+            transactions.push(coinbase);
+
+            let block = mine(index, transactions, state.tip().hash.clone(), time, rules.difficulty);
+            let next_base_fee = fees::next_base_fee(base_fee, &block, rules.max_block_transactions, rules.minimum_fee);
+            state.connect(block, &rules.with_base_fee(base_fee))?;
+            base_fee = next_base_fee;
+        }
+
+        let (blocks, accounts) = state.into_parts();
+        Ok(TestNet {
+            wallets,
+            blocks,
+            accounts,
+            rules,
+        })
+    }
+}
+
+/// Finds the proof of a block with a fixed timestamp, counting up from 0
+fn mine(index: u64, transactions: Vec<Transaction>, previous_hash: String, time: DateTime<Utc>, difficulty: u8) -> Block {
+    let target = "0".repeat(difficulty as usize);
+    let mut block = Block::new(index, transactions, 0, previous_hash);
+    block.timestamp = time;
+
+    loop {
+        block.hash = block.calculate_hash();
+        if block.hash.starts_with(&target) {
+            return block;
+        }
+        block.proof += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::verify::verify_chain;
+
+    #[test]
+    fn test_generated_networks_are_valid_and_reproducible() {
+        let testnet = TestNet::generate(42, 4, 8, 3).unwrap();
+        assert_eq!(testnet.blocks.len(), 9);
+        assert_eq!(testnet.blocks[8].timestamp.to_rfc3339(), "2024-01-01T00:08:00+00:00");
+
+        let rules = &testnet.rules;
+        let report = verify_chain(&testnet.blocks, rules.difficulty, rules.mining_reward, &rules.fee_policy).unwrap();
+        assert!(report.transactions_verified > 8);
+
+        let hashes = |testnet: &TestNet| testnet.blocks.iter().map(|block| block.hash.clone()).collect::<Vec<_>>();
+        let again = TestNet::generate(42, 4, 8, 3).unwrap();
+        assert_eq!(hashes(&again), hashes(&testnet));
+        assert_eq!(again.wallets[0].address(), testnet.wallets[0].address());
+        assert_ne!(hashes(&TestNet::generate(43, 4, 8, 3).unwrap()), hashes(&testnet));
+
+        assert!(TestNet::generate(42, 0, 8, 3).is_err());
+    }
+}
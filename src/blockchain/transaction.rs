@@ -1,10 +1,19 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 use utoipa::ToSchema;
-use uuid::Uuid;
 
+use super::canonical;
 use super::crypto::{Address, DigitalSignature, verify_signature, CryptoError};
+use super::fees::TreasuryPayout;
+use super::governance::GovernanceAction;
+use super::inheritance::InheritanceAction;
+use super::permissions::{FreezeOrder, PermissionChange};
+use super::scripts::ScriptAction;
+use super::sessions::SessionAction;
+use super::signing::{self, SigningContext};
+use super::treasury::TREASURY_ADDRESS;
 
 /// Errors that can occur during transaction operations
 #[derive(Debug, Error)]
@@ -24,12 +33,30 @@ pub enum TransactionError {
     #[error("Invalid amount: {0}")]
     InvalidAmount(String),
 
+    #[error("Transaction fee too low: {fee} (minimum: {minimum})")]
+    FeeTooLow { fee: f64, minimum: f64 },
+
+    #[error("Replacement fee {fee} must be above the fee {replaced_fee} of the transaction it replaces")]
+    ReplacementUnderpriced { fee: f64, replaced_fee: f64 },
+
+    #[error("Too many transactions of {0} wait for earlier nonces")]
+    TooManyQueued(String),
+
+    #[error("Transaction {0} is made by the miner of a block and can't be submitted")]
+    MinerOnly(String),
+
     #[error("Transaction already signed")]
     AlreadySigned,
 
     #[error("Transaction not signed")]
     NotSigned,
 
+    #[error("Invalid fee payer: {0}")]
+    InvalidFeePayer(String),
+
+    #[error("Invalid fee payer signature")]
+    InvalidFeePayerSignature,
+
     #[error("Crypto error: {0}")]
     CryptoError(#[from] CryptoError),
 
@@ -37,7 +64,59 @@ pub enum TransactionError {
     SystemError(String),
 }
 
+/// First version whose ID is the hash of its signed fields
+pub(crate) const CONTENT_ID_VERSION: u32 = 2;
+
+/// First version signed in the transaction signing context
+pub(crate) const SIGNING_CONTEXT_VERSION: u32 = 3;
+
+/// Version of new transactions
+const TRANSACTION_VERSION: u32 = 3;
+
+/// Longest message a coinbase transaction may carry, in bytes
+pub const MAX_COINBASE_MESSAGE_BYTES: usize = 100;
+
+/// Optional fields, which are left out of IDs and block hashes when unset
+const OPTIONAL_FIELDS: [&str; 12] = [
+    "extra_nonce",
+    "message",
+    "name",
+    "data",
+    "permission",
+    "governance",
+    "freeze",
+    "treasury",
+    "script",
+    "fee_payer",
+    "session",
+    "inheritance",
+];
+
 /// Represents a transaction in the blockchain
+///
+/// Every field except the signature is covered by the signature, so changing
+/// any of them invalidates the transaction. Since version 2 the ID is the
+/// SHA-256 hash of the signed fields rather than a random UUID: it identifies
+/// the economic content of the transaction, and neither a relayer nor the
+/// sender can attach a different ID to the same signed content. The
+/// signature is left out of the ID, so the ID is known before signing. Blocks
+/// hash their transactions including the signature, so the exact signature
+/// that was mined is committed to as well.
+///
+/// Since version 3 the signed fields are prefixed with the transaction
+/// signing context, so a transaction signature can never be passed off as a
+/// signature for another purpose or the other way around.
+///
+/// Version 1 transactions carry a random ID that is part of the signed
+/// fields. Older versions stay valid so existing chains keep verifying.
+///
+/// A fee payer, when set, pays the fee instead of the sender. The sender
+/// signs the payer's address along with the other fields, and the payer
+/// signs the same fields in its own signing context, so neither can be
+/// made to pay for a transaction it didn't agree to.
+///
+/// A session spend is signed by a session key its sender authorized on the
+/// chain rather than by the sender, see `sessions::SessionKeys`.
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Transaction {
     /// Version of the transaction structure
@@ -62,16 +141,77 @@ pub struct Transaction {
     /// Nonce to prevent replay attacks
     pub nonce: u64,
 
-    /// Digital signature of the transaction
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Digital signature of the transaction (None for coinbase transactions)
     pub signature: Option<DigitalSignature>,
 
     /// Timestamp when the transaction was created
     #[schema(value_type = String, example = "2023-01-01T12:00:00Z")]
     pub timestamp: DateTime<Utc>,
+
+    /// Random value chosen by the miner of a coinbase transaction
+    ///
+    /// Makes the coinbase, and so the block template, of every miner unique,
+    /// so miners never search the same proofs.
+    #[serde(default)]
+    pub extra_nonce: Option<u64>,
+
+    /// Short message embedded by the miner of a coinbase transaction
+    #[serde(default)]
+    pub message: Option<String>,
+
+    /// Name the sender registers or renews, paying the amount to the name registry
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// Hash of a document the transaction anchors on the chain
+    #[serde(default)]
+    pub data: Option<String>,
+
+    /// Address a governor of a permissioned chain approves or revokes
+    #[serde(default)]
+    pub permission: Option<PermissionChange>,
+
+    /// Proposal or vote on a chain parameter
+    #[serde(default)]
+    pub governance: Option<GovernanceAction>,
+
+    /// Account a governor of a permissioned chain freezes or unfreezes
+    #[serde(default)]
+    pub freeze: Option<FreezeOrder>,
+
+    /// Share of the block's fees a coinbase pays to the treasury
+    #[serde(default)]
+    pub treasury: Option<TreasuryPayout>,
+
+    /// Lock of the amount under a spending condition, or spend of such a lock
+    #[serde(default)]
+    pub script: Option<ScriptAction>,
+
+    /// Third party paying the fee instead of the sender
+    #[serde(default)]
+    pub fee_payer: Option<FeePayer>,
+
+    /// Authorization or revocation of a session key, or the session key that signed the transaction
+    #[serde(default)]
+    pub session: Option<SessionAction>,
+
+    /// Designation or cancellation of a beneficiary of the sender, or a claim on an inactive owner's funds
+    #[serde(default)]
+    pub inheritance: Option<InheritanceAction>,
+}
+
+/// A third party paying the fee of a transaction, e.g. for a new user holding no coins yet
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FeePayer {
+    /// The address paying the fee
+    #[schema(value_type = String)]
+    pub address: Address,
+
+    /// Signature of the payer over the signed fields of the transaction, see `Transaction::sign_fee_payer`
+    pub signature: Option<DigitalSignature>,
 }
 
-/// Default version for transactions
+/// Default version for transactions stored without one
 fn default_version() -> u32 {
     1
 }
@@ -91,9 +231,9 @@ impl Transaction {
     ///
     /// A new Transaction instance
     pub fn new(sender: Address, recipient: Address, amount: f64, fee: f64, nonce: u64) -> Self {
-        Transaction {
-            version: default_version(),
-            id: Uuid::new_v4().to_string(),
+        let mut transaction = Transaction {
+            version: TRANSACTION_VERSION,
+            id: String::new(),
             sender,
             recipient,
             amount,
@@ -101,7 +241,22 @@ impl Transaction {
             nonce,
             signature: None,
             timestamp: Utc::now(),
-        }
+            extra_nonce: None,
+            message: None,
+            name: None,
+            data: None,
+            permission: None,
+            governance: None,
+            freeze: None,
+            treasury: None,
+            script: None,
+            fee_payer: None,
+            session: None,
+            inheritance: None,
+        };
+
+        transaction.id = transaction.compute_id();
+        transaction
     }
 
     /// Creates a new coinbase transaction (mining reward)
@@ -117,9 +272,9 @@ impl Transaction {
     pub fn new_coinbase(recipient: Address, amount: f64) -> Self {
         let system_address = Address("0".to_string());
 
-        Transaction {
-            version: default_version(),
-            id: Uuid::new_v4().to_string(),
+        let mut transaction = Transaction {
+            version: TRANSACTION_VERSION,
+            id: String::new(),
             sender: system_address,
             recipient,
             amount,
@@ -127,36 +282,242 @@ impl Transaction {
             nonce: 0,
             signature: None,
             timestamp: Utc::now(),
-        }
+            extra_nonce: None,
+            message: None,
+            name: None,
+            data: None,
+            permission: None,
+            governance: None,
+            freeze: None,
+            treasury: None,
+            script: None,
+            fee_payer: None,
+            session: None,
+            inheritance: None,
+        };
+
+        transaction.id = transaction.compute_id();
+        transaction
+    }
+
+    /// Sets the extra-nonce of a coinbase transaction
+    ///
+    /// # Arguments
+    ///
+    /// * `extra_nonce` - The value chosen by the miner
+    ///
+    /// # Returns
+    ///
+    /// The transaction with the extra-nonce and the ID that goes with it
+    pub fn with_extra_nonce(mut self, extra_nonce: u64) -> Self {
+        self.extra_nonce = Some(extra_nonce);
+        self.id = self.compute_id();
+        self
+    }
+
+    /// Sets the message of a coinbase transaction
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The message, at most `MAX_COINBASE_MESSAGE_BYTES` long
+    ///
+    /// # Returns
+    ///
+    /// The transaction with the message and the ID that goes with it
+    pub fn with_message(mut self, message: &str) -> Self {
+        self.message = Some(message.to_string());
+        self.id = self.compute_id();
+        self
+    }
+
+    /// Makes the transaction register or renew a name for its sender
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name, see `names::validate_name`
+    ///
+    /// # Returns
+    ///
+    /// The transaction with the name and the ID that goes with it
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self.id = self.compute_id();
+        self
+    }
+
+    /// Makes the transaction anchor the hash of a document
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The hash, see `anchors::validate_hash`
+    ///
+    /// # Returns
+    ///
+    /// The transaction with the hash and the ID that goes with it
+    pub fn with_data(mut self, data: &str) -> Self {
+        self.data = Some(data.to_string());
+        self.id = self.compute_id();
+        self
+    }
+
+    /// Makes the transaction approve or revoke an address on a permissioned chain
+    ///
+    /// # Arguments
+    ///
+    /// * `change` - The change, sent by a governor to `permissions::PERMISSIONS_ADDRESS`
+    ///
+    /// # Returns
+    ///
+    /// The transaction with the change and the ID that goes with it
+    pub fn with_permission(mut self, change: PermissionChange) -> Self {
+        self.permission = Some(change);
+        self.id = self.compute_id();
+        self
+    }
+
+    /// Makes the transaction propose a parameter change or vote on one
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The proposal or vote, paid to `governance::GOVERNANCE_ADDRESS`
+    ///
+    /// # Returns
+    ///
+    /// The transaction with the action and the ID that goes with it
+    pub fn with_governance(mut self, action: GovernanceAction) -> Self {
+        self.governance = Some(action);
+        self.id = self.compute_id();
+        self
+    }
+
+    /// Makes the transaction freeze or unfreeze an account on a permissioned chain
+    ///
+    /// # Arguments
+    ///
+    /// * `order` - The order, sent by a governor to `permissions::PERMISSIONS_ADDRESS`
+    ///
+    /// # Returns
+    ///
+    /// The transaction with the order and the ID that goes with it
+    pub fn with_freeze(mut self, order: FreezeOrder) -> Self {
+        self.freeze = Some(order);
+        self.id = self.compute_id();
+        self
+    }
+
+    /// Makes a coinbase transaction pay the treasury its share of the fees
+    ///
+    /// # Arguments
+    ///
+    /// * `payout` - The payout, see `FeePolicy::payout`
+    ///
+    /// # Returns
+    ///
+    /// The transaction with the payout and the ID that goes with it
+    pub fn with_treasury(mut self, payout: TreasuryPayout) -> Self {
+        self.treasury = Some(payout);
+        self.id = self.compute_id();
+        self
+    }
+
+    /// Makes the transaction lock its amount under a condition or spend such a lock
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The lock, paid to `scripts::SCRIPT_ADDRESS`, or the spend
+    ///
+    /// # Returns
+    ///
+    /// The transaction with the action and the ID that goes with it
+    pub fn with_script(mut self, action: ScriptAction) -> Self {
+        self.script = Some(action);
+        self.id = self.compute_id();
+        self
+    }
+
+    /// Makes a third party pay the fee of the transaction instead of its sender
+    ///
+    /// The payer still has to sign, see `sign_fee_payer`.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address paying the fee
+    ///
+    /// # Returns
+    ///
+    /// The transaction with the fee payer and the ID that goes with it
+    pub fn with_fee_payer(mut self, address: Address) -> Self {
+        self.fee_payer = Some(FeePayer { address, signature: None });
+        self.id = self.compute_id();
+        self
+    }
+
+    /// Authorizes or revokes a session key of the sender, or marks the transaction as signed by one
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The authorization or revocation, sent to `sessions::SESSIONS_ADDRESS`, or the spend
+    ///
+    /// # Returns
+    ///
+    /// The transaction with the action and the ID that goes with it
+    pub fn with_session(mut self, action: SessionAction) -> Self {
+        self.session = Some(action);
+        self.id = self.compute_id();
+        self
+    }
+
+    /// Designates or cancels a beneficiary of the sender, or claims the funds of an inactive owner
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The designation or cancellation, paid to `inheritance::INHERITANCE_ADDRESS`, or the claim
+    ///
+    /// # Returns
+    ///
+    /// The transaction with the action and the ID that goes with it
+    pub fn with_inheritance(mut self, action: InheritanceAction) -> Self {
+        self.inheritance = Some(action);
+        self.id = self.compute_id();
+        self
     }
 
     /// Signs the transaction with a wallet
     ///
     /// # Arguments
     ///
-    /// * `wallet` - The wallet to sign with
+    /// * `wallet` - The wallet or child key to sign with, the session key's for a session spend
     ///
     /// # Returns
     ///
     /// Result indicating success or failure
-    pub fn sign(&mut self, wallet: &super::crypto::Wallet) -> Result<(), TransactionError> {
+    pub fn sign(&mut self, wallet: &impl super::crypto::Signer) -> Result<(), TransactionError> {
         // Check if the transaction is already signed
         if self.signature.is_some() {
             return Err(TransactionError::AlreadySigned);
         }
 
-        // Check if the wallet address matches the sender address
-        if wallet.address() != &self.sender {
+        // Check if the wallet address matches the sender address, or the session key signing for it
+        if wallet.address() != &self.signer() {
             return Err(TransactionError::InvalidSenderAddress(
                 "Wallet address does not match sender address".to_string(),
             ));
         }
 
+        // Fields may have been changed since the transaction was created
+        if self.version >= CONTENT_ID_VERSION {
+            self.id = self.compute_id();
+        }
+
         // Create a message from the transaction data
-        let message = self.to_bytes()?;
+        let message = self.signed_bytes()?;
 
         // Sign the message
-        let signature = wallet.sign(&message)?;
+        let signature = if self.version >= SIGNING_CONTEXT_VERSION {
+            signing::sign(wallet, SigningContext::Transaction, &message)?
+        } else {
+            wallet.sign(&message)?
+        };
 
         // Set the signature
         self.signature = Some(signature);
@@ -166,6 +527,9 @@ impl Transaction {
 
     /// Verifies the transaction's signature
     ///
+    /// A transaction whose ID does not match its signed fields is rejected
+    /// as well, since the signature would not vouch for the ID it is known by.
+    ///
     /// # Returns
     ///
     /// Result indicating if the signature is valid
@@ -176,49 +540,209 @@ impl Transaction {
             None => return Err(TransactionError::NotSigned),
         };
 
-        // Get the sender's public key
-        let public_key = self.sender.to_public_key()?;
+        if !self.has_valid_id() {
+            return Ok(false);
+        }
+
+        // Get the public key of the sender, or of the session key signing for it
+        let public_key = self.signer().to_public_key()?;
 
         // Create a message from the transaction data
-        let message = self.to_bytes_without_signature()?;
+        let message = self.signed_bytes()?;
 
         // Verify the signature
-        verify_signature(&message, signature, &public_key)
-            .map_err(TransactionError::from)
+        if self.version >= SIGNING_CONTEXT_VERSION {
+            signing::verify(&public_key, SigningContext::Transaction, &message, signature)
+        } else {
+            verify_signature(&message, signature, &public_key)
+        }
+        .map_err(TransactionError::from)
     }
 
-    /// Converts the transaction to bytes for signing
-    fn to_bytes(&self) -> Result<Vec<u8>, TransactionError> {
-        let data = serde_json::json!({
-            "version": self.version,
-            "id": self.id,
-            "sender": self.sender.0,
-            "recipient": self.recipient.0,
-            "amount": self.amount,
-            "fee": self.fee,
-            "nonce": self.nonce,
-            "timestamp": self.timestamp,
-        });
+    /// Signs the transaction as its fee payer
+    ///
+    /// The payer signs the same fields as the sender, in the fee payer
+    /// signing context, so the two signatures can be made in any order.
+    ///
+    /// # Arguments
+    ///
+    /// * `wallet` - The wallet of the fee payer
+    ///
+    /// # Returns
+    ///
+    /// Result indicating success or failure
+    pub fn sign_fee_payer(&mut self, wallet: &super::crypto::Wallet) -> Result<(), TransactionError> {
+        let fee_payer = match &self.fee_payer {
+            Some(fee_payer) => fee_payer,
+            None => return Err(TransactionError::InvalidFeePayer("The transaction has no fee payer".to_string())),
+        };
+        if fee_payer.signature.is_some() {
+            return Err(TransactionError::AlreadySigned);
+        }
+        if wallet.address() != &fee_payer.address {
+            return Err(TransactionError::InvalidFeePayer(
+                "Wallet address does not match fee payer address".to_string(),
+            ));
+        }
+
+        let signature = signing::sign(wallet, SigningContext::FeePayer, &self.signed_bytes()?)?;
+        if let Some(fee_payer) = self.fee_payer.as_mut() {
+            fee_payer.signature = Some(signature);
+        }
+
+        Ok(())
+    }
+
+    /// Verifies the signature of the fee payer
+    ///
+    /// # Returns
+    ///
+    /// Result indicating if the signature is valid, true if there is no fee payer
+    pub fn verify_fee_payer_signature(&self) -> Result<bool, TransactionError> {
+        let fee_payer = match &self.fee_payer {
+            Some(fee_payer) => fee_payer,
+            None => return Ok(true),
+        };
+        let signature = match &fee_payer.signature {
+            Some(signature) => signature,
+            None => return Err(TransactionError::NotSigned),
+        };
+
+        if !self.has_valid_id() {
+            return Ok(false);
+        }
+
+        let public_key = fee_payer.address.to_public_key()?;
+        signing::verify(&public_key, SigningContext::FeePayer, &self.signed_bytes()?, signature).map_err(TransactionError::from)
+    }
 
-        serde_json::to_vec(&data)
-            .map_err(|e| TransactionError::SystemError(e.to_string()))
+    /// Computes the ID of the transaction from its signed fields
+    ///
+    /// # Returns
+    ///
+    /// The hex encoded SHA-256 hash of the signed fields
+    pub fn compute_id(&self) -> String {
+        format!("{:x}", Sha256::digest(canonical::to_vec(&self.content_json())))
+    }
+
+    /// Gets the bytes the ID of the transaction is the hash of
+    ///
+    /// # Returns
+    ///
+    /// The signed fields as canonical JSON, or None for version 1 whose ID is random
+    pub fn id_bytes(&self) -> Option<Vec<u8>> {
+        (self.version >= CONTENT_ID_VERSION).then(|| canonical::to_vec(&self.content_json()))
+    }
+
+    /// Checks if the ID is the one the signed fields call for
+    ///
+    /// Version 1 IDs are random and only bound to the transaction by the signature.
+    pub fn has_valid_id(&self) -> bool {
+        self.version < CONTENT_ID_VERSION || self.id == self.compute_id()
     }
 
-    /// Converts the transaction to bytes without the signature
-    fn to_bytes_without_signature(&self) -> Result<Vec<u8>, TransactionError> {
-        let data = serde_json::json!({
+    /// Converts the signed fields of the transaction to bytes
+    pub(crate) fn signed_bytes(&self) -> Result<Vec<u8>, TransactionError> {
+        let data = if self.version < CONTENT_ID_VERSION {
+            // Version 1 signs its random ID along with the other fields
+            serde_json::json!({
+                "version": self.version,
+                "id": self.id,
+                "sender": self.sender.0,
+                "recipient": self.recipient.0,
+                "amount": self.amount,
+                "fee": self.fee,
+                "nonce": self.nonce,
+                "timestamp": canonical::timestamp(&self.timestamp),
+            })
+        } else {
+            self.content_json()
+        };
+
+        Ok(canonical::to_vec(&data))
+    }
+
+    /// Gets the fields the ID is computed from, every field but the ID and signature
+    fn content_json(&self) -> serde_json::Value {
+        let mut content = serde_json::json!({
             "version": self.version,
-            "id": self.id,
             "sender": self.sender.0,
             "recipient": self.recipient.0,
             "amount": self.amount,
             "fee": self.fee,
             "nonce": self.nonce,
-            "timestamp": self.timestamp,
+            "timestamp": canonical::timestamp(&self.timestamp),
         });
 
-        serde_json::to_vec(&data)
-            .map_err(|e| TransactionError::SystemError(e.to_string()))
+        // Optional fields only count when set, so older IDs stay the same
+        if let Some(extra_nonce) = self.extra_nonce {
+            content["extra_nonce"] = extra_nonce.into();
+        }
+        if let Some(message) = &self.message {
+            content["message"] = message.clone().into();
+        }
+        if let Some(name) = &self.name {
+            content["name"] = name.clone().into();
+        }
+        if let Some(data) = &self.data {
+            content["data"] = data.clone().into();
+        }
+        if let Some(permission) = &self.permission {
+            content["permission"] = serde_json::json!({
+                "address": permission.address,
+                "allowed": permission.allowed,
+            });
+        }
+        if let Some(governance) = &self.governance {
+            content["governance"] = serde_json::to_value(governance).expect("governance actions serialize to JSON");
+        }
+        if let Some(freeze) = &self.freeze {
+            content["freeze"] = serde_json::json!({
+                "address": freeze.address,
+                "frozen": freeze.frozen,
+                "reason": freeze.reason,
+            });
+        }
+        if let Some(treasury) = &self.treasury {
+            content["treasury"] = serde_json::json!({
+                "address": treasury.address,
+                "amount": treasury.amount,
+            });
+        }
+        if let Some(script) = &self.script {
+            content["script"] = serde_json::to_value(script).expect("script actions serialize to JSON");
+        }
+        // The payer's signature is left out like the sender's
+        if let Some(fee_payer) = &self.fee_payer {
+            content["fee_payer"] = fee_payer.address.0.clone().into();
+        }
+        if let Some(session) = &self.session {
+            content["session"] = serde_json::to_value(session).expect("session actions serialize to JSON");
+        }
+        if let Some(inheritance) = &self.inheritance {
+            content["inheritance"] = serde_json::to_value(inheritance).expect("inheritance actions serialize to JSON");
+        }
+
+        content
+    }
+
+    /// Gets the JSON the hash of a block covers for this transaction
+    ///
+    /// Every field is covered, but unset optional fields are left out so
+    /// blocks mined before they existed keep their hash.
+    pub fn hash_json(&self) -> serde_json::Value {
+        let mut value = serde_json::to_value(self).expect("transactions serialize to JSON");
+        value["timestamp"] = canonical::timestamp(&self.timestamp);
+
+        if let Some(fields) = value.as_object_mut() {
+            for field in OPTIONAL_FIELDS {
+                if fields.get(field).is_some_and(|value| value.is_null()) {
+                    fields.remove(field);
+                }
+            }
+        }
+
+        value
     }
 
     /// Checks if the transaction is a coinbase transaction
@@ -226,10 +750,32 @@ impl Transaction {
         self.sender.0 == "0" && self.fee == 0.0 && self.nonce == 0
     }
 
+    /// Checks if the transaction pays an installment of a disbursement
+    ///
+    /// Disbursements come from the treasury, which has no key, so they are
+    /// not signed. Whether the installment is due is checked by
+    /// `Governance::check_disbursements`.
+    pub fn is_disbursement(&self) -> bool {
+        self.sender.0 == TREASURY_ADDRESS && matches!(self.governance, Some(GovernanceAction::Disburse { .. }))
+    }
+
     /// Gets the total amount required for the transaction (amount + fee)
     pub fn total_amount(&self) -> f64 {
         self.amount + self.fee
     }
+
+    /// Gets the address whose key signs the transaction, the session key of a session spend, the sender otherwise
+    pub fn signer(&self) -> Address {
+        match &self.session {
+            Some(SessionAction::Spend { key }) => Address(key.clone()),
+            _ => self.sender.clone(),
+        }
+    }
+
+    /// Gets the address paying the fee, the fee payer if there is one, the sender otherwise
+    pub fn fee_paid_by(&self) -> &Address {
+        self.fee_payer.as_ref().map(|fee_payer| &fee_payer.address).unwrap_or(&self.sender)
+    }
 }
 
 #[cfg(test)]
@@ -288,6 +834,52 @@ mod tests {
         assert!(transaction.verify_signature().unwrap());
     }
 
+    #[test]
+    fn test_signed_fields_and_id_are_immalleable() {
+        let sender_wallet = Wallet::new().unwrap();
+        let recipient = Address("recipient".to_string());
+
+        let mut transaction = Transaction::new(sender_wallet.address().clone(), recipient, 10.0, 0.1, 0);
+        let id = transaction.id.clone();
+        transaction.sign(&sender_wallet).unwrap();
+
+        // The ID commits to the content and does not depend on the signature
+        assert_eq!(transaction.id, id);
+        assert_eq!(transaction.id, transaction.compute_id());
+        assert!(transaction.verify_signature().unwrap());
+
+        // A relayer can not change any field, including the ID and timestamp
+        let mut relayed = transaction.clone();
+        relayed.id = "0".repeat(64);
+        assert!(!relayed.verify_signature().unwrap());
+
+        let mut relayed = transaction.clone();
+        relayed.timestamp += chrono::Duration::seconds(1);
+        assert!(!relayed.verify_signature().unwrap());
+
+        // Even with a matching ID, changed content breaks the signature
+        let mut relayed = transaction.clone();
+        relayed.fee = 0.2;
+        relayed.id = relayed.compute_id();
+        assert!(!relayed.verify_signature().unwrap());
+
+        // Legacy transactions with a random, signed ID still verify
+        let mut legacy = Transaction::new(sender_wallet.address().clone(), Address("recipient".to_string()), 1.0, 0.1, 1);
+        legacy.version = 1;
+        legacy.id = uuid::Uuid::new_v4().to_string();
+        legacy.sign(&sender_wallet).unwrap();
+        assert!(legacy.verify_signature().unwrap());
+
+        legacy.id = uuid::Uuid::new_v4().to_string();
+        assert!(!legacy.verify_signature().unwrap());
+
+        // Version 2 transactions were signed without a signing context
+        let mut unprefixed = Transaction::new(sender_wallet.address().clone(), Address("recipient".to_string()), 1.0, 0.1, 2);
+        unprefixed.version = 2;
+        unprefixed.sign(&sender_wallet).unwrap();
+        assert!(unprefixed.verify_signature().unwrap());
+    }
+
     #[test]
     fn test_coinbase_transaction() {
         let miner_wallet = Wallet::new().unwrap();
@@ -301,5 +893,25 @@ mod tests {
         assert_eq!(transaction.fee, 0.0);
         assert_eq!(transaction.nonce, 0);
         assert!(transaction.is_coinbase());
+        assert!(transaction.has_valid_id());
+    }
+
+    #[test]
+    fn test_transaction_wire_format_is_stable() {
+        use crate::blockchain::golden;
+
+        let block = golden::sample_block();
+        let transaction = block.transactions.iter().find(|transaction| !transaction.is_coinbase()).unwrap();
+        golden::assert_text("transaction.json", &serde_json::to_string_pretty(transaction).unwrap());
+        golden::assert_text("transaction.signed.json", &String::from_utf8(transaction.signed_bytes().unwrap()).unwrap());
+        golden::assert_bytes("transaction.bin.hex", &bincode::serialize(transaction).unwrap());
+
+        // Signatures made over the old payload still verify after decoding
+        let decoded: Transaction = serde_json::from_str(&golden::read_text("transaction.json")).unwrap();
+        assert!(decoded.has_valid_id());
+        assert!(decoded.verify_signature().unwrap());
+        let stored = crate::blockchain::versioning::decode_transaction(&golden::read_bytes("transaction.bin.hex")).unwrap();
+        assert_eq!(stored.id, transaction.id);
+        assert!(stored.verify_signature().unwrap());
     }
 }
@@ -0,0 +1,202 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::crypto::Address;
+use super::fees::FeePolicy;
+use super::governance::{GovernanceAction, GovernanceError};
+use super::transaction::Transaction;
+
+/// Address of the protocol treasury, which has no key so only disbursements spend from it
+pub const TREASURY_ADDRESS: &str = "treasury";
+
+/// Most installments a disbursement may be paid in
+pub const MAX_INSTALLMENTS: u32 = 1_000;
+
+/// A payment from the treasury in one or more installments, paid once governance approves it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Disbursement {
+    /// Address that receives the installments
+    pub recipient: String,
+
+    /// Amount of each installment
+    pub amount: f64,
+
+    /// Number of installments
+    pub installments: u32,
+
+    /// Blocks between two installments
+    pub interval: u64,
+}
+
+impl Disbursement {
+    /// Checks that the disbursement can be paid
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if it pays a positive amount to another address, in 1 to
+    /// `MAX_INSTALLMENTS` installments at least a block apart
+    pub fn validate(&self) -> Result<(), GovernanceError> {
+        let invalid = |message: String| Err(GovernanceError::InvalidProposal(message));
+
+        if self.recipient.is_empty() || self.recipient == TREASURY_ADDRESS {
+            return invalid("A disbursement must pay an address other than the treasury".to_string());
+        }
+        if !(self.amount.is_finite() && self.amount > 0.0) {
+            return invalid(format!("{} is not a valid installment amount", self.amount));
+        }
+        if self.installments == 0 || self.installments > MAX_INSTALLMENTS {
+            return invalid(format!("A disbursement is paid in 1 to {} installments", MAX_INSTALLMENTS));
+        }
+        if self.interval == 0 {
+            return invalid("Installments must be at least a block apart".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Gets the height an installment is due at
+    ///
+    /// # Arguments
+    ///
+    /// * `deadline` - Deadline of the proposal, the first installment is due right after it
+    /// * `installment` - The installment, counted from 0
+    pub fn due_at(&self, deadline: u64, installment: u32) -> u64 {
+        deadline + 1 + installment as u64 * self.interval
+    }
+}
+
+/// An installment a block must pay
+#[derive(Debug, Clone, PartialEq)]
+pub struct DueInstallment {
+    /// ID of the proposal that approved the disbursement
+    pub proposal: String,
+
+    /// The installment, counted from 0
+    pub installment: u32,
+
+    /// Address that receives it
+    pub recipient: String,
+
+    /// Amount paid
+    pub amount: f64,
+}
+
+impl DueInstallment {
+    /// Builds the unsigned transaction that pays the installment
+    ///
+    /// # Arguments
+    ///
+    /// * `nonce` - The treasury's nonce for the transaction
+    pub fn transaction(&self, nonce: u64) -> Transaction {
+        let action = GovernanceAction::Disburse {
+            proposal: self.proposal.clone(),
+            installment: self.installment,
+        };
+
+        Transaction::new(
+            Address(TREASURY_ADDRESS.to_string()),
+            Address(self.recipient.clone()),
+            self.amount,
+            0.0,
+            nonce,
+        )
+        .with_governance(action)
+    }
+
+    /// Checks if a transaction pays this installment
+    pub fn is_paid_by(&self, transaction: &Transaction) -> bool {
+        let pays_this = matches!(
+            &transaction.governance,
+            Some(GovernanceAction::Disburse { proposal, installment })
+                if *proposal == self.proposal && *installment == self.installment
+        );
+
+        pays_this
+            && transaction.is_disbursement()
+            && transaction.recipient.0 == self.recipient
+            && transaction.amount == self.amount
+            && transaction.fee == 0.0
+    }
+}
+
+/// How an approved disbursement is being paid
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DisbursementStatus {
+    /// ID of the proposal that approved it
+    pub proposal: String,
+
+    /// What is paid
+    pub disbursement: Disbursement,
+
+    /// Height the first installment was due at
+    pub first_due: u64,
+
+    /// Installments paid
+    pub paid: u32,
+
+    /// Installments the treasury could not afford when they were due, which are not paid later
+    pub missed: u32,
+
+    /// Total paid so far
+    pub disbursed: f64,
+
+    /// Height the next installment is due at, None once all are due
+    pub next_due: Option<u64>,
+}
+
+/// The treasury and the disbursements governance approved
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TreasuryStatus {
+    /// The treasury account
+    pub address: String,
+
+    /// Balance after the last block
+    pub balance: f64,
+
+    /// The policy deciding what the treasury gets from each block
+    pub fee_policy: FeePolicy,
+
+    /// Total paid out by disbursements
+    pub disbursed: f64,
+
+    /// Approved disbursements, oldest proposal first
+    pub disbursements: Vec<DisbursementStatus>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disbursements_are_validated_and_scheduled() {
+        let disbursement = Disbursement {
+            recipient: "grantee".to_string(),
+            amount: 5.0,
+            installments: 3,
+            interval: 10,
+        };
+        disbursement.validate().unwrap();
+        assert_eq!(disbursement.due_at(20, 0), 21);
+        assert_eq!(disbursement.due_at(20, 2), 41);
+
+        for invalid in [
+            Disbursement { recipient: TREASURY_ADDRESS.to_string(), ..disbursement.clone() },
+            Disbursement { amount: 0.0, ..disbursement.clone() },
+            Disbursement { installments: MAX_INSTALLMENTS + 1, ..disbursement.clone() },
+            Disbursement { interval: 0, ..disbursement.clone() },
+        ] {
+            assert!(invalid.validate().is_err());
+        }
+
+        let due = DueInstallment {
+            proposal: "proposal".to_string(),
+            installment: 1,
+            recipient: "grantee".to_string(),
+            amount: 5.0,
+        };
+        let transaction = due.transaction(4);
+        assert!(transaction.is_disbursement());
+        assert!(due.is_paid_by(&transaction));
+        assert!(!DueInstallment { installment: 2, ..due.clone() }.is_paid_by(&transaction));
+    }
+}
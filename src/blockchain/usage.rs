@@ -0,0 +1,337 @@
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use super::storage::{BlockchainStorage, StorageError};
+
+/// What one API key used on one day (UTC)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct DailyUsage {
+    /// ID of the API key, see `key_id`
+    pub key_id: String,
+
+    /// The day
+    #[schema(value_type = String, example = "2023-01-01")]
+    pub day: NaiveDate,
+
+    /// Requests served
+    pub requests: u64,
+
+    /// Blocks returned
+    pub blocks_served: u64,
+
+    /// Transactions submitted
+    pub transactions_submitted: u64,
+}
+
+impl DailyUsage {
+    /// Creates the empty usage of a key on a day
+    fn new(key_id: &str, day: NaiveDate) -> Self {
+        DailyUsage {
+            key_id: key_id.to_string(),
+            day,
+            requests: 0,
+            blocks_served: 0,
+            transactions_submitted: 0,
+        }
+    }
+}
+
+/// What one API key used over a range of days
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct KeyUsage {
+    /// ID of the API key, see `key_id`
+    pub key_id: String,
+
+    /// Requests the key may make per calendar month, None for no limit
+    pub monthly_quota: Option<u64>,
+
+    /// Requests made in the current calendar month
+    pub month_requests: u64,
+
+    /// Requests served over the range
+    pub requests: u64,
+
+    /// Blocks returned over the range
+    pub blocks_served: u64,
+
+    /// Transactions submitted over the range
+    pub transactions_submitted: u64,
+
+    /// Usage per day of the range with any, oldest first
+    pub days: Vec<DailyUsage>,
+}
+
+/// A request turned away because its key used up its monthly quota
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuotaExceeded {
+    /// Requests the key may make per calendar month
+    pub quota: u64,
+
+    /// When the quota starts over
+    pub resets_at: DateTime<Utc>,
+}
+
+/// How many requests API keys may make per calendar month
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QuotaPolicy {
+    /// Quota of the keys without one of their own, None for no limit
+    pub default: Option<u64>,
+
+    /// Quotas of single keys by key ID, 0 for no limit
+    pub per_key: HashMap<String, u64>,
+}
+
+impl QuotaPolicy {
+    /// Creates a quota policy from configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `default` - Quota of every key, 0 for no limit
+    /// * `per_key` - Quotas of single keys as key=quota, malformed entries are ignored
+    pub fn from_config(default: u64, per_key: &[String]) -> Self {
+        QuotaPolicy {
+            default: (default > 0).then_some(default),
+            per_key: per_key
+                .iter()
+                .filter_map(|entry| entry.split_once('='))
+                .filter_map(|(key, quota)| Some((key_id(key.trim()), quota.trim().parse().ok()?)))
+                .collect(),
+        }
+    }
+
+    /// Gets the monthly quota of a key, None for no limit
+    pub fn quota(&self, key_id: &str) -> Option<u64> {
+        match self.per_key.get(key_id) {
+            Some(0) => None,
+            Some(quota) => Some(*quota),
+            None => self.default,
+        }
+    }
+}
+
+/// Meters the API usage of every API key in daily rollups
+///
+/// Keys are known by their ID, a hash, so the keys themselves are never
+/// persisted or reported. Requests without a key are not metered.
+#[derive(Debug, Clone)]
+pub struct UsageMeter {
+    days: Arc<Mutex<BTreeMap<(String, NaiveDate), DailyUsage>>>,
+    quotas: QuotaPolicy,
+    storage: Option<Arc<BlockchainStorage>>,
+}
+
+impl UsageMeter {
+    /// Creates a new usage meter
+    ///
+    /// # Arguments
+    ///
+    /// * `storage` - Optional storage to persist the usage in
+    /// * `quotas` - How many requests keys may make per month
+    ///
+    /// # Returns
+    ///
+    /// A new UsageMeter instance with the usage loaded from storage
+    pub fn new(storage: Option<Arc<BlockchainStorage>>, quotas: QuotaPolicy) -> Result<Self, StorageError> {
+        let mut days = BTreeMap::new();
+
+        if let Some(storage) = &storage {
+            for usage in storage.get_all_usage()? {
+                days.insert((usage.key_id.clone(), usage.day), usage);
+            }
+        }
+
+        Ok(UsageMeter {
+            days: Arc::new(Mutex::new(days)),
+            quotas,
+            storage,
+        })
+    }
+
+    /// Counts a request of a key, unless the key used up its monthly quota
+    ///
+    /// # Arguments
+    ///
+    /// * `api_key` - The API key of the request
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if the request may be served, QuotaExceeded otherwise
+    pub fn admit(&self, api_key: &str, now: DateTime<Utc>) -> Result<(), QuotaExceeded> {
+        let key_id = key_id(api_key);
+        let mut days = self.days.lock().unwrap();
+
+        if let Some(quota) = self.quotas.quota(&key_id) {
+            if month_requests(&days, &key_id, now.date_naive()) >= quota {
+                return Err(QuotaExceeded {
+                    quota,
+                    resets_at: next_month(now.date_naive()),
+                });
+            }
+        }
+
+        self.update(&mut days, &key_id, now, |usage| usage.requests += 1);
+        Ok(())
+    }
+
+    /// Counts the resources a request of a key used
+    ///
+    /// # Arguments
+    ///
+    /// * `api_key` - The API key of the request
+    /// * `blocks` - Blocks the request returned
+    /// * `transactions` - Transactions the request submitted
+    /// * `now` - The current time
+    pub fn record(&self, api_key: &str, blocks: u64, transactions: u64, now: DateTime<Utc>) {
+        if blocks == 0 && transactions == 0 {
+            return;
+        }
+        let mut days = self.days.lock().unwrap();
+        self.update(&mut days, &key_id(api_key), now, |usage| {
+            usage.blocks_served += blocks;
+            usage.transactions_submitted += transactions;
+        });
+    }
+
+    /// Reports the usage of the keys over a range of days
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - The first day
+    /// * `to` - The last day
+    /// * `key_id` - Only report this key, if given
+    /// * `today` - The current day, for the monthly totals
+    ///
+    /// # Returns
+    ///
+    /// The usage of every key with any over the range, ordered by key ID
+    pub fn report(&self, from: NaiveDate, to: NaiveDate, key_id: Option<&str>, today: NaiveDate) -> Vec<KeyUsage> {
+        let days = self.days.lock().unwrap();
+        let mut report: BTreeMap<&str, KeyUsage> = BTreeMap::new();
+
+        for ((key, day), usage) in days.iter() {
+            if *day < from || *day > to || key_id.is_some_and(|key_id| key_id != key) {
+                continue;
+            }
+            let entry = report.entry(key.as_str()).or_insert_with(|| KeyUsage {
+                key_id: key.clone(),
+                monthly_quota: self.quotas.quota(key),
+                month_requests: month_requests(&days, key, today),
+                requests: 0,
+                blocks_served: 0,
+                transactions_submitted: 0,
+                days: Vec::new(),
+            });
+            entry.requests += usage.requests;
+            entry.blocks_served += usage.blocks_served;
+            entry.transactions_submitted += usage.transactions_submitted;
+            entry.days.push(usage.clone());
+        }
+
+        report.into_values().collect()
+    }
+
+    /// Applies a change to the usage of a key today and persists it
+    fn update(
+        &self,
+        days: &mut BTreeMap<(String, NaiveDate), DailyUsage>,
+        key_id: &str,
+        now: DateTime<Utc>,
+        change: impl FnOnce(&mut DailyUsage),
+    ) {
+        let day = now.date_naive();
+        let usage = days
+            .entry((key_id.to_string(), day))
+            .or_insert_with(|| DailyUsage::new(key_id, day));
+        change(usage);
+
+        if let Some(storage) = &self.storage {
+            // Losing a count is better than failing the request
+            if let Err(err) = storage.save_usage(usage) {
+                warn!("Failed to persist the usage of key {}: {}", key_id, err);
+            }
+        }
+    }
+}
+
+/// Derives the ID an API key is metered and reported by
+///
+/// The ID is the first 16 hex digits of the key's SHA-256 hash, e.g.
+/// `printf %s "$KEY" | sha256sum | cut -c1-16`.
+pub fn key_id(api_key: &str) -> String {
+    format!("{:x}", Sha256::digest(api_key.as_bytes()))[..16].to_string()
+}
+
+/// Counts the requests of a key in the calendar month of a day
+fn month_requests(days: &BTreeMap<(String, NaiveDate), DailyUsage>, key_id: &str, today: NaiveDate) -> u64 {
+    let first = today.with_day(1).expect("every month has a first day");
+    days.range((key_id.to_string(), first)..=(key_id.to_string(), today))
+        .map(|(_, usage)| usage.requests)
+        .sum()
+}
+
+/// Gets the start of the calendar month after a day
+fn next_month(day: NaiveDate) -> DateTime<Utc> {
+    let (year, month) = match day.month() {
+        12 => (day.year() + 1, 1),
+        month => (day.year(), month + 1),
+    };
+    NaiveDate::from_ymd_opt(year, month, 1)
+        .expect("the first of a month is a valid date")
+        .and_hms_opt(0, 0, 0)
+        .expect("midnight is a valid time")
+        .and_utc()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(day: &str) -> DateTime<Utc> {
+        format!("{}T12:00:00Z", day).parse().unwrap()
+    }
+
+    #[test]
+    fn test_monthly_quota_resets_with_the_month() {
+        let quotas = QuotaPolicy::from_config(2, &["unlimited=0".to_string(), "malformed".to_string()]);
+        let meter = UsageMeter::new(None, quotas).unwrap();
+
+        meter.admit("team-a", at("2024-01-30")).unwrap();
+        meter.admit("team-a", at("2024-01-31")).unwrap();
+        let exceeded = meter.admit("team-a", at("2024-01-31")).unwrap_err();
+        assert_eq!(exceeded.quota, 2);
+        assert_eq!(exceeded.resets_at, "2024-02-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap());
+
+        meter.admit("team-a", at("2024-02-01")).unwrap();
+        for _ in 0..5 {
+            meter.admit("unlimited", at("2024-01-31")).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_report_rolls_up_days_per_key() {
+        let meter = UsageMeter::new(None, QuotaPolicy::default()).unwrap();
+        meter.admit("team-a", at("2024-03-01")).unwrap();
+        meter.record("team-a", 10, 0, at("2024-03-01"));
+        meter.admit("team-a", at("2024-03-02")).unwrap();
+        meter.record("team-a", 0, 3, at("2024-03-02"));
+        meter.admit("team-b", at("2024-03-02")).unwrap();
+
+        let from = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let to = NaiveDate::from_ymd_opt(2024, 3, 2).unwrap();
+        let report = meter.report(from, to, Some(&key_id("team-a")), to);
+        assert_eq!(report.len(), 1);
+        let usage = &report[0];
+        assert_eq!((usage.requests, usage.blocks_served, usage.transactions_submitted), (2, 10, 3));
+        assert_eq!((usage.month_requests, usage.monthly_quota), (2, None));
+        assert_eq!(usage.days.len(), 2);
+
+        assert_eq!(meter.report(to, to, None, to).len(), 2);
+    }
+}
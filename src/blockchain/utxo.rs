@@ -0,0 +1,450 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use thiserror::Error;
+
+use super::canonical;
+use super::crypto::{Address, CryptoError, DigitalSignature, Wallet};
+use super::signing::{self, SigningContext};
+
+/// Errors that can occur while building or applying UTXO transactions
+#[derive(Debug, Error)]
+pub enum UtxoError {
+    #[error("Output {0} does not exist or is already spent")]
+    MissingOutput(OutPoint),
+
+    #[error("Input {0} is not signed by the owner of the output it spends")]
+    InvalidSignature(usize),
+
+    #[error("Outputs of {outputs} exceed the inputs of {inputs}")]
+    Overspend { inputs: f64, outputs: f64 },
+
+    #[error("Insufficient funds: required {required}, available {available}")]
+    InsufficientFunds { required: f64, available: f64 },
+
+    #[error("Invalid block: {0}")]
+    InvalidBlock(String),
+
+    #[error("Invalid transaction: {0}")]
+    InvalidTransaction(String),
+
+    #[error("Crypto error: {0}")]
+    CryptoError(#[from] CryptoError),
+}
+
+/// A reference to one output of a transaction
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct OutPoint {
+    /// ID of the transaction that created the output
+    pub transaction_id: String,
+
+    /// Position of the output in that transaction
+    pub index: u32,
+}
+
+impl fmt::Display for OutPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.transaction_id, self.index)
+    }
+}
+
+/// Coins locked to an address until an input spends them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TxOutput {
+    /// Address that may spend the output
+    pub recipient: Address,
+
+    /// Amount of the output
+    pub amount: f64,
+}
+
+/// Spends an unspent output, signed by its recipient
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TxInput {
+    /// The output spent
+    pub outpoint: OutPoint,
+
+    /// Signature of the output's recipient over the transaction, None until signed
+    pub signature: Option<DigitalSignature>,
+}
+
+/// A transaction of the UTXO ledger
+///
+/// Where an account transaction moves an amount between two balances, a
+/// UTXO transaction consumes whole outputs and creates new ones: the inputs
+/// must be spent entirely, so paying less than an output holds means
+/// sending the rest back to oneself as change. What the inputs hold beyond
+/// the outputs is the fee. No nonces are needed, since an output can only
+/// be spent once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UtxoTransaction {
+    /// SHA-256 hash of the inputs' outpoints and the outputs, signatures excluded
+    pub id: String,
+
+    /// The outputs spent, none for a coinbase
+    pub inputs: Vec<TxInput>,
+
+    /// The outputs created
+    pub outputs: Vec<TxOutput>,
+
+    /// Height of the block of a coinbase, which makes its ID unique
+    pub coinbase_height: Option<u64>,
+}
+
+impl UtxoTransaction {
+    /// Creates an unsigned transaction
+    ///
+    /// # Arguments
+    ///
+    /// * `outpoints` - The outputs to spend
+    /// * `outputs` - The outputs to create
+    ///
+    /// # Returns
+    ///
+    /// A new UtxoTransaction instance
+    pub fn new(outpoints: Vec<OutPoint>, outputs: Vec<TxOutput>) -> Self {
+        let inputs = outpoints
+            .into_iter()
+            .map(|outpoint| TxInput { outpoint, signature: None })
+            .collect();
+        Self::with_id(inputs, outputs, None)
+    }
+
+    /// Creates the coinbase of a block, which pays the reward and the fees
+    ///
+    /// # Arguments
+    ///
+    /// * `recipient` - The miner
+    /// * `amount` - The reward plus the fees of the block
+    /// * `height` - Height of the block
+    ///
+    /// # Returns
+    ///
+    /// A new UtxoTransaction instance
+    pub fn coinbase(recipient: Address, amount: f64, height: u64) -> Self {
+        Self::with_id(Vec::new(), vec![TxOutput { recipient, amount }], Some(height))
+    }
+
+    /// Builds a signed payment from the unspent outputs of a wallet
+    ///
+    /// Outputs are taken oldest first by outpoint until they cover the
+    /// amount and the fee, and what they hold beyond that comes back to the
+    /// wallet as a change output.
+    ///
+    /// # Arguments
+    ///
+    /// * `wallet` - The payer
+    /// * `utxos` - The unspent outputs
+    /// * `recipient` - The payee
+    /// * `amount` - The amount to pay
+    /// * `fee` - The fee left to the miner
+    ///
+    /// # Returns
+    ///
+    /// The signed transaction, or UtxoError::InsufficientFunds
+    pub fn transfer(
+        wallet: &Wallet,
+        utxos: &UtxoSet,
+        recipient: Address,
+        amount: f64,
+        fee: f64,
+    ) -> Result<Self, UtxoError> {
+        if !(amount > 0.0 && amount.is_finite() && fee >= 0.0 && fee.is_finite()) {
+            return Err(UtxoError::InvalidTransaction("amount must be positive and fee not negative".to_string()));
+        }
+
+        let required = amount + fee;
+        let mut outpoints = Vec::new();
+        let mut available = 0.0;
+        for (outpoint, output) in utxos.unspent(wallet.address()) {
+            if available >= required {
+                break;
+            }
+            available += output.amount;
+            outpoints.push(outpoint);
+        }
+        if available < required {
+            return Err(UtxoError::InsufficientFunds { required, available });
+        }
+
+        let mut outputs = vec![TxOutput { recipient, amount }];
+        let change = available - required;
+        if change > 0.0 {
+            outputs.push(TxOutput {
+                recipient: wallet.address().clone(),
+                amount: change,
+            });
+        }
+
+        let mut transaction = Self::new(outpoints, outputs);
+        transaction.sign(wallet)?;
+        Ok(transaction)
+    }
+
+    /// Checks if the transaction is a coinbase
+    pub fn is_coinbase(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// Signs every input, all of them must spend outputs of the wallet
+    ///
+    /// # Arguments
+    ///
+    /// * `wallet` - The owner of the spent outputs
+    pub fn sign(&mut self, wallet: &Wallet) -> Result<(), UtxoError> {
+        let signature = signing::sign(wallet, SigningContext::UtxoTransaction, &self.signed_bytes())?;
+        for input in &mut self.inputs {
+            input.signature = Some(signature.clone());
+        }
+        Ok(())
+    }
+
+    /// Gets the sum of the outputs
+    pub fn output_total(&self) -> f64 {
+        self.outputs.iter().map(|output| output.amount).sum()
+    }
+
+    /// Computes the ID from the content
+    pub fn compute_id(&self) -> String {
+        format!("{:x}", Sha256::digest(self.signed_bytes()))
+    }
+
+    /// Gets the canonical bytes the ID hashes and the inputs sign
+    fn signed_bytes(&self) -> Vec<u8> {
+        let inputs: Vec<_> = self
+            .inputs
+            .iter()
+            .map(|input| json!({ "transaction_id": input.outpoint.transaction_id, "index": input.outpoint.index }))
+            .collect();
+        let outputs: Vec<_> = self
+            .outputs
+            .iter()
+            .map(|output| json!({ "recipient": output.recipient.0, "amount": output.amount }))
+            .collect();
+
+        canonical::to_vec(&json!({
+            "inputs": inputs,
+            "outputs": outputs,
+            "coinbase_height": self.coinbase_height,
+        }))
+    }
+
+    /// Creates a transaction and computes its ID
+    fn with_id(inputs: Vec<TxInput>, outputs: Vec<TxOutput>, coinbase_height: Option<u64>) -> Self {
+        let mut transaction = UtxoTransaction {
+            id: String::new(),
+            inputs,
+            outputs,
+            coinbase_height,
+        };
+        transaction.id = transaction.compute_id();
+        transaction
+    }
+}
+
+/// The unspent outputs, which are the whole state of a UTXO ledger
+///
+/// An account ledger keeps one balance and nonce per address and checks
+/// each transaction against them; here a balance is only the sum of the
+/// outputs an address can spend, and a transaction is valid when every
+/// output it spends is still in the set and signed for by its recipient.
+/// The node's chain stays account-based, this ledger exists to compare the
+/// two models on the same keys and signatures.
+#[derive(Debug, Clone, Default)]
+pub struct UtxoSet {
+    outputs: HashMap<OutPoint, TxOutput>,
+}
+
+impl UtxoSet {
+    /// Creates an empty set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets an unspent output
+    ///
+    /// # Arguments
+    ///
+    /// * `outpoint` - The output
+    pub fn get(&self, outpoint: &OutPoint) -> Option<&TxOutput> {
+        self.outputs.get(outpoint)
+    }
+
+    /// Gets the number of unspent outputs
+    pub fn len(&self) -> usize {
+        self.outputs.len()
+    }
+
+    /// Checks if no output is unspent
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+
+    /// Gets the unspent outputs of an address
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address
+    ///
+    /// # Returns
+    ///
+    /// The outputs, ordered by outpoint
+    pub fn unspent(&self, address: &Address) -> Vec<(OutPoint, TxOutput)> {
+        let mut unspent: Vec<_> = self
+            .outputs
+            .iter()
+            .filter(|(_, output)| &output.recipient == address)
+            .map(|(outpoint, output)| (outpoint.clone(), output.clone()))
+            .collect();
+        unspent.sort_by(|a, b| a.0.cmp(&b.0));
+        unspent
+    }
+
+    /// Gets the balance of an address, the sum of its unspent outputs
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address
+    pub fn balance(&self, address: &Address) -> f64 {
+        self.unspent(address).iter().map(|(_, output)| output.amount).sum()
+    }
+
+    /// Applies the transactions of a block, all or none of them
+    ///
+    /// The first transaction must be the only coinbase and pay at most the
+    /// reward plus the fees. A transaction may spend outputs created earlier
+    /// in the same block.
+    ///
+    /// # Arguments
+    ///
+    /// * `transactions` - The transactions, coinbase first
+    /// * `reward` - The block reward
+    ///
+    /// # Returns
+    ///
+    /// Result with the fees of the block
+    pub fn apply_block(&mut self, transactions: &[UtxoTransaction], reward: f64) -> Result<f64, UtxoError> {
+        let (coinbase, rest) = transactions
+            .split_first()
+            .ok_or_else(|| UtxoError::InvalidBlock("a block starts with its coinbase".to_string()))?;
+        if !coinbase.is_coinbase() || rest.iter().any(UtxoTransaction::is_coinbase) {
+            return Err(UtxoError::InvalidBlock("a block has exactly one coinbase, first".to_string()));
+        }
+
+        let mut next = self.clone();
+        let mut fees = 0.0;
+        for transaction in rest {
+            fees += next.apply(transaction)?;
+        }
+        if coinbase.output_total() > reward + fees {
+            return Err(UtxoError::InvalidBlock(format!(
+                "the coinbase pays {}, more than the reward and fees of {}",
+                coinbase.output_total(),
+                reward + fees
+            )));
+        }
+        next.add_outputs(coinbase)?;
+
+        *self = next;
+        Ok(fees)
+    }
+
+    /// Spends the inputs of a transaction and adds its outputs
+    ///
+    /// # Returns
+    ///
+    /// Result with the fee, what the inputs hold beyond the outputs
+    fn apply(&mut self, transaction: &UtxoTransaction) -> Result<f64, UtxoError> {
+        let message = transaction.signed_bytes();
+        let mut inputs = 0.0;
+        for (position, input) in transaction.inputs.iter().enumerate() {
+            let output = self
+                .outputs
+                .remove(&input.outpoint)
+                .ok_or_else(|| UtxoError::MissingOutput(input.outpoint.clone()))?;
+            let signature = input.signature.as_ref().ok_or(UtxoError::InvalidSignature(position))?;
+            let public_key = output.recipient.to_public_key()?;
+            if !signing::verify(&public_key, SigningContext::UtxoTransaction, &message, signature)? {
+                return Err(UtxoError::InvalidSignature(position));
+            }
+            inputs += output.amount;
+        }
+
+        let outputs = transaction.output_total();
+        if outputs > inputs {
+            return Err(UtxoError::Overspend { inputs, outputs });
+        }
+        self.add_outputs(transaction)?;
+
+        Ok(inputs - outputs)
+    }
+
+    /// Adds the outputs of a transaction whose ID matches its content
+    fn add_outputs(&mut self, transaction: &UtxoTransaction) -> Result<(), UtxoError> {
+        if transaction.id != transaction.compute_id() {
+            return Err(UtxoError::InvalidTransaction(format!("{} does not match its content", transaction.id)));
+        }
+        if transaction.outputs.iter().any(|output| !(output.amount > 0.0 && output.amount.is_finite())) {
+            return Err(UtxoError::InvalidTransaction("outputs must hold a positive amount".to_string()));
+        }
+
+        for (index, output) in transaction.outputs.iter().enumerate() {
+            let outpoint = OutPoint {
+                transaction_id: transaction.id.clone(),
+                index: index as u32,
+            };
+            if self.outputs.insert(outpoint.clone(), output.clone()).is_some() {
+                return Err(UtxoError::InvalidTransaction(format!("output {} already exists", outpoint)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payments_spend_outputs_and_return_change() {
+        let alice = Wallet::new().unwrap();
+        let bob = Wallet::new().unwrap();
+        let miner = Wallet::new().unwrap();
+        let mut utxos = UtxoSet::new();
+
+        utxos
+            .apply_block(&[UtxoTransaction::coinbase(alice.address().clone(), 50.0, 1)], 50.0)
+            .unwrap();
+        let payment = UtxoTransaction::transfer(&alice, &utxos, bob.address().clone(), 30.0, 1.0).unwrap();
+        assert_eq!(payment.outputs.len(), 2);
+
+        let block = [UtxoTransaction::coinbase(miner.address().clone(), 51.0, 2), payment.clone()];
+        assert_eq!(utxos.apply_block(&block, 50.0).unwrap(), 1.0);
+        assert_eq!(utxos.balance(alice.address()), 19.0);
+        assert_eq!(utxos.balance(bob.address()), 30.0);
+        assert_eq!(utxos.balance(miner.address()), 51.0);
+
+        // The spent output is gone, so replaying the payment fails and changes nothing
+        let replay = [UtxoTransaction::coinbase(miner.address().clone(), 50.0, 3), payment];
+        assert!(matches!(utxos.apply_block(&replay, 50.0), Err(UtxoError::MissingOutput(_))));
+        assert_eq!(utxos.len(), 3);
+
+        // Bob can't sign away Alice's change
+        let (change, _) = utxos.unspent(alice.address()).remove(0);
+        let stolen = TxOutput {
+            recipient: bob.address().clone(),
+            amount: 19.0,
+        };
+        let mut theft = UtxoTransaction::new(vec![change], vec![stolen]);
+        theft.sign(&bob).unwrap();
+        let block = [UtxoTransaction::coinbase(miner.address().clone(), 50.0, 3), theft];
+        assert!(matches!(utxos.apply_block(&block, 50.0), Err(UtxoError::InvalidSignature(0))));
+
+        // The coinbase can't pay more than the reward and the fees
+        let greedy = [UtxoTransaction::coinbase(miner.address().clone(), 60.0, 3)];
+        assert!(matches!(utxos.apply_block(&greedy, 50.0), Err(UtxoError::InvalidBlock(_))));
+    }
+}
@@ -0,0 +1,925 @@
+use chrono::{Duration, Utc};
+use thiserror::Error;
+
+use super::account::{AccountError, AccountState};
+use super::anchors::{self, AnchorError};
+use super::block::{Block, BlockHeader};
+use super::chain::{DIFFICULTY, MINING_REWARD};
+use super::crypto::Address;
+use super::fees::{BlockFees, FeePolicy};
+use super::governance::{self, ChainParameters, GovernanceError};
+use super::inheritance::{self, InheritanceError, Inheritances};
+use super::names::{self, NameError, NameRegistry};
+use super::permissions::{self, PermissionError};
+use super::scripts::{self, ScriptAction, ScriptError, ScriptLocks};
+use super::sessions::{self, SessionAction, SessionError, SessionKeys};
+use super::transaction::{Transaction, TransactionError, SIGNING_CONTEXT_VERSION};
+use super::versioning::{VersionError, VersionSchedule};
+
+/// Lowest fee a transaction may pay until governance changes it
+pub const MINIMUM_FEE: f64 = 0.01;
+
+/// Most transactions besides the coinbase a block may hold until governance changes it
+pub const MAX_BLOCK_TRANSACTIONS: usize = 1_000;
+
+/// How far ahead of the local clock a block's timestamp may be
+pub const MAX_FUTURE_BLOCK_TIME: Duration = Duration::minutes(10);
+
+/// Errors that can occur while validating blocks and transactions
+#[derive(Debug, Error)]
+pub enum ValidationError {
+    #[error("Transaction error: {0}")]
+    TransactionError(#[from] TransactionError),
+
+    #[error("Account error: {0}")]
+    AccountError(#[from] AccountError),
+
+    #[error("Version error: {0}")]
+    VersionError(#[from] VersionError),
+
+    #[error("Name error: {0}")]
+    NameError(#[from] NameError),
+
+    #[error("Anchor error: {0}")]
+    AnchorError(#[from] AnchorError),
+
+    #[error("Permission error: {0}")]
+    PermissionError(#[from] PermissionError),
+
+    #[error("Governance error: {0}")]
+    GovernanceError(#[from] GovernanceError),
+
+    #[error("Script error: {0}")]
+    ScriptError(#[from] ScriptError),
+
+    #[error("Session error: {0}")]
+    SessionError(#[from] SessionError),
+
+    #[error("Inheritance error: {0}")]
+    InheritanceError(#[from] InheritanceError),
+
+    #[error("Invalid block: {0}")]
+    InvalidBlock(String),
+
+    #[error("Invalid chain: {0}")]
+    InvalidChain(String),
+}
+
+/// Rules blocks and transactions must follow to enter the chain
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationRules {
+    /// Number of leading zeros required in the hash of a block
+    pub difficulty: u8,
+
+    /// Lowest fee a transaction may pay
+    pub minimum_fee: f64,
+
+    /// Lowest fee a transaction of the block may pay, never below the minimum fee
+    pub base_fee: f64,
+
+    /// Coins a coinbase transaction must pay
+    pub mining_reward: f64,
+
+    /// Most transactions besides the coinbase a block may hold
+    pub max_block_transactions: usize,
+
+    /// Where the fees of a block go
+    pub fee_policy: FeePolicy,
+
+    /// Versions of blocks and transactions allowed at each height
+    pub versions: VersionSchedule,
+}
+
+impl ValidationRules {
+    /// Gets the rules this node validates with before any parameter change
+    pub fn node() -> Self {
+        ValidationRules {
+            difficulty: DIFFICULTY,
+            minimum_fee: MINIMUM_FEE,
+            base_fee: MINIMUM_FEE,
+            mining_reward: MINING_REWARD,
+            max_block_transactions: MAX_BLOCK_TRANSACTIONS,
+            fee_policy: FeePolicy::default(),
+            versions: VersionSchedule::node(),
+        }
+    }
+
+    /// Gets the rules with the parameters in force at some height
+    ///
+    /// The base fee starts at the minimum fee, see `with_base_fee`.
+    ///
+    /// # Arguments
+    ///
+    /// * `parameters` - The parameters, see `Governance::parameters_at`
+    pub fn with_parameters(&self, parameters: &ChainParameters) -> Self {
+        ValidationRules {
+            minimum_fee: parameters.minimum_fee,
+            base_fee: parameters.minimum_fee,
+            mining_reward: parameters.mining_reward,
+            max_block_transactions: parameters.max_block_transactions,
+            ..self.clone()
+        }
+    }
+
+    /// Gets the rules with the base fee of a block
+    ///
+    /// # Arguments
+    ///
+    /// * `base_fee` - The base fee, see `fees::next_base_fee`
+    pub fn with_base_fee(&self, base_fee: f64) -> Self {
+        ValidationRules {
+            base_fee: base_fee.max(self.minimum_fee),
+            ..self.clone()
+        }
+    }
+
+    /// Gets the work of a block at the difficulty, the number of hashes expected to find its proof
+    ///
+    /// Each leading zero of the hex hash takes 16 times the hashes, so the
+    /// fork choice compares branches by the sum of the work of their blocks
+    /// rather than by their length.
+    pub fn block_work(&self) -> u128 {
+        16u128.checked_pow(self.difficulty as u32).unwrap_or(u128::MAX)
+    }
+}
+
+/// Checks what can be checked about a transaction without any state
+///
+/// # Arguments
+///
+/// * `transaction` - The transaction to check
+/// * `height` - Height of the block the transaction is meant for
+/// * `rules` - The rules to check against
+///
+/// # Returns
+///
+/// Ok(()) if the version is allowed, a name is registered, data anchored, permissions
+/// changed, governance actions taken, amounts locked or unlocked, session keys used and
+/// beneficiaries designated or claims made properly, the signatures of the sender or its session key and the fee payer are valid
+/// and the fee covers the base fee
+pub fn check_transaction(transaction: &Transaction, height: u64, rules: &ValidationRules) -> Result<(), ValidationError> {
+    // Unknown versions can't be checked any further
+    rules.versions.check_transaction(transaction, height)?;
+    names::check_registration(transaction)?;
+    anchors::check_anchor(transaction)?;
+    permissions::check_change(transaction)?;
+    governance::check_action(transaction)?;
+    scripts::check_script(transaction)?;
+    sessions::check_session(transaction)?;
+    inheritance::check_inheritance(transaction)?;
+    check_fee_payer(transaction)?;
+
+    if transaction.is_coinbase() {
+        return Ok(());
+    }
+
+    if !transaction.verify_signature()? {
+        return Err(ValidationError::TransactionError(TransactionError::InvalidSignature));
+    }
+
+    if transaction.fee < rules.base_fee {
+        return Err(ValidationError::TransactionError(TransactionError::FeeTooLow {
+            fee: transaction.fee,
+            minimum: rules.base_fee,
+        }));
+    }
+
+    Ok(())
+}
+
+/// Checks the fee payer of a transaction
+///
+/// # Arguments
+///
+/// * `transaction` - The transaction to check
+///
+/// # Returns
+///
+/// Ok(()) if the transaction has no fee payer, or a signed transaction of the
+/// current version has one other than its sender, who signed it
+pub fn check_fee_payer(transaction: &Transaction) -> Result<(), TransactionError> {
+    let fee_payer = match &transaction.fee_payer {
+        Some(fee_payer) => fee_payer,
+        None => return Ok(()),
+    };
+
+    if transaction.is_coinbase() || transaction.is_disbursement() {
+        return Err(TransactionError::InvalidFeePayer(
+            "Only a transaction signed by its sender can have a fee payer".to_string(),
+        ));
+    }
+    // Older versions don't sign the fee payer's address
+    if transaction.version < SIGNING_CONTEXT_VERSION {
+        return Err(TransactionError::InvalidFeePayer(format!(
+            "A transaction of version {} can't have a fee payer",
+            transaction.version
+        )));
+    }
+    if fee_payer.address == transaction.sender {
+        return Err(TransactionError::InvalidFeePayer("The sender can't be its own fee payer".to_string()));
+    }
+    if !transaction.verify_fee_payer_signature()? {
+        return Err(TransactionError::InvalidFeePayerSignature);
+    }
+
+    Ok(())
+}
+
+/// Checks that a transaction may be submitted to the mempool
+///
+/// Coinbases and treasury disbursements carry no signature. Only the miner
+/// of a block makes them, for that block, and `check_block` checks them.
+///
+/// # Arguments
+///
+/// * `transaction` - The transaction to check
+///
+/// # Returns
+///
+/// Ok(()) if the transaction is neither a coinbase nor a disbursement
+pub fn check_submittable(transaction: &Transaction) -> Result<(), TransactionError> {
+    if transaction.is_coinbase() || transaction.is_disbursement() {
+        return Err(TransactionError::MinerOnly(transaction.id.clone()));
+    }
+
+    Ok(())
+}
+
+/// Checks that a transaction can follow the pending transactions
+///
+/// # Arguments
+///
+/// * `accounts` - The confirmed account state
+/// * `pending` - The transactions already pending
+/// * `transaction` - The transaction to check
+///
+/// # Returns
+///
+/// Ok(()) if the transaction may be submitted, the sender and the fee payer can afford it
+/// and its nonce is next in line
+pub fn check_admission(
+    accounts: &AccountState,
+    pending: &[Transaction],
+    transaction: &Transaction,
+) -> Result<(), ValidationError> {
+    check_submittable(transaction)?;
+
+    // Funds already committed to pending transactions are not available
+    check_payers_afford(accounts, pending.iter(), transaction)?;
+
+    let sender_account = accounts.get_account(&transaction.sender);
+
+    // Check if the nonce follows the sender's pending transactions
+    let expected_nonce = next_nonce(pending, &transaction.sender, sender_account.nonce);
+    if transaction.nonce != expected_nonce {
+        return Err(ValidationError::AccountError(AccountError::InvalidNonce {
+            expected: expected_nonce,
+            got: transaction.nonce,
+        }));
+    }
+
+    Ok(())
+}
+
+/// Checks that a transaction can take the place of a pending one with the same nonce
+///
+/// # Arguments
+///
+/// * `accounts` - The confirmed account state
+/// * `pending` - The transactions already pending, the replaced one included
+/// * `replaced` - The pending transaction of the same sender and nonce
+/// * `transaction` - The replacement
+///
+/// # Returns
+///
+/// Ok(()) if the replacement pays a higher fee and the sender can still afford all its pending transactions
+pub fn check_replacement(
+    accounts: &AccountState,
+    pending: &[Transaction],
+    replaced: &Transaction,
+    transaction: &Transaction,
+) -> Result<(), ValidationError> {
+    if transaction.fee <= replaced.fee {
+        return Err(ValidationError::TransactionError(TransactionError::ReplacementUnderpriced {
+            fee: transaction.fee,
+            replaced_fee: replaced.fee,
+        }));
+    }
+
+    check_payers_afford(accounts, pending.iter().filter(|tx| tx.id != replaced.id), transaction)
+}
+
+/// Checks that the sender, the fee payer and the owner claimed from by a transaction can pay their part on top of pending transactions
+///
+/// # Arguments
+///
+/// * `accounts` - The confirmed account state
+/// * `pending` - The pending transactions the transaction would follow
+/// * `transaction` - The transaction to check
+fn check_payers_afford<'a>(
+    accounts: &AccountState,
+    pending: impl Iterator<Item = &'a Transaction> + Clone,
+    transaction: &Transaction,
+) -> Result<(), ValidationError> {
+    let claimed_owner = inheritance::claimed_owner(transaction);
+    for payer in [Some(&transaction.sender), Some(transaction.fee_paid_by()), claimed_owner.as_ref()]
+        .into_iter()
+        .flatten()
+    {
+        let pending_spend: f64 = pending.clone().map(|tx| spend(tx, payer)).sum();
+        let available = accounts.get_account(payer).balance - pending_spend;
+
+        if available < spend(transaction, payer) {
+            return Err(ValidationError::AccountError(AccountError::InsufficientFunds {
+                required: spend(transaction, payer),
+                available,
+            }));
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that the name a transaction registers is available to its sender
+///
+/// # Arguments
+///
+/// * `registry` - The names registered on the chain
+/// * `pending` - The transactions already pending
+/// * `transaction` - The transaction to check
+/// * `height` - Height of the block the transaction is meant for
+///
+/// # Returns
+///
+/// Ok(()) if the transaction registers no name, or one that is free or already the sender's
+/// and that no other pending transaction registers
+pub fn check_name_admission(
+    registry: &NameRegistry,
+    pending: &[Transaction],
+    transaction: &Transaction,
+    height: u64,
+) -> Result<(), ValidationError> {
+    let name = match &transaction.name {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+
+    registry.check_available(name, &transaction.sender, height)?;
+
+    if pending
+        .iter()
+        .any(|tx| tx.name.as_ref() == Some(name) && tx.sender != transaction.sender)
+    {
+        return Err(ValidationError::NameError(NameError::Pending(name.clone())));
+    }
+
+    Ok(())
+}
+
+/// Checks that the lock a transaction spends may be spent by it
+///
+/// # Arguments
+///
+/// * `locks` - The open locks of the chain
+/// * `pending` - The transactions already pending
+/// * `transaction` - The transaction to check
+/// * `height` - Height of the block the transaction is meant for
+///
+/// # Returns
+///
+/// Ok(()) if the transaction spends no lock, or one it meets the condition of
+/// and that no other pending transaction spends
+pub fn check_lock_admission(
+    locks: &ScriptLocks,
+    pending: &[Transaction],
+    transaction: &Transaction,
+    height: u64,
+) -> Result<(), ValidationError> {
+    let lock = match &transaction.script {
+        Some(ScriptAction::Unlock { lock, .. }) => lock,
+        _ => return Ok(()),
+    };
+
+    locks.check_spend(transaction, height)?;
+
+    if pending
+        .iter()
+        .any(|tx| matches!(&tx.script, Some(ScriptAction::Unlock { lock: other, .. }) if other == lock))
+    {
+        return Err(ValidationError::ScriptError(ScriptError::Pending(lock.clone())));
+    }
+
+    Ok(())
+}
+
+/// Checks that the session key signing a transaction may spend what it does
+///
+/// # Arguments
+///
+/// * `keys` - The session keys of the chain
+/// * `pending` - The transactions already pending
+/// * `transaction` - The transaction to check
+/// * `height` - Height of the block the transaction is meant for
+///
+/// # Returns
+///
+/// Ok(()) if the transaction is not signed by a session key, or by one its sender
+/// authorized that has not expired and whose limits the pending spends of the key leave room for
+pub fn check_session_admission(
+    keys: &SessionKeys,
+    pending: &[Transaction],
+    transaction: &Transaction,
+    height: u64,
+) -> Result<(), ValidationError> {
+    if !matches!(transaction.session, Some(SessionAction::Spend { .. })) {
+        return Ok(());
+    }
+
+    // Pending spends of the same key count against its limits already
+    let pending_spend: f64 = pending
+        .iter()
+        .filter(|tx| tx.sender == transaction.sender && tx.session.as_ref() == transaction.session.as_ref())
+        .map(sessions::session_spend)
+        .sum();
+    keys.check_spend(transaction, height, pending_spend)?;
+
+    Ok(())
+}
+
+/// Checks that a transaction may claim the funds of an inactive owner
+///
+/// # Arguments
+///
+/// * `switches` - The inheritance switches of the chain
+/// * `pending` - The transactions already pending
+/// * `transaction` - The transaction to check
+/// * `height` - Height of the block the transaction is meant for
+///
+/// # Returns
+///
+/// Ok(()) if the transaction claims nothing, or claims from an owner that designated its
+/// sender, has been inactive long enough, has no pending transaction and no other pending claim
+pub fn check_claim_admission(
+    switches: &Inheritances,
+    pending: &[Transaction],
+    transaction: &Transaction,
+    height: u64,
+) -> Result<(), ValidationError> {
+    let owner = match inheritance::claimed_owner(transaction) {
+        Some(owner) => owner,
+        None => return Ok(()),
+    };
+
+    switches.check_claim(transaction, height)?;
+
+    if pending.iter().any(|tx| inheritance::claimed_owner(tx).as_ref() == Some(&owner)) {
+        return Err(ValidationError::InheritanceError(InheritanceError::Pending(owner.0)));
+    }
+    // A pending transaction of the owner would refresh the switch once mined
+    if pending.iter().any(|tx| tx.sender == owner) {
+        let switch = switches.get(&owner).expect("checked claims have a switch");
+        return Err(ValidationError::InheritanceError(InheritanceError::OwnerActive {
+            owner: owner.0,
+            last_active: height,
+            claimable_at: height + switch.inactivity_blocks + 1,
+        }));
+    }
+
+    Ok(())
+}
+
+/// Gets what a transaction takes from the balance of an address
+///
+/// The sender pays the amount, except for an unlock whose amount comes out
+/// of the lock and a claim whose amount comes out of the owner, and the fee
+/// unless a fee payer pays it.
+fn spend(transaction: &Transaction, address: &Address) -> f64 {
+    let mut total = 0.0;
+    match inheritance::claimed_owner(transaction) {
+        Some(owner) => {
+            if &owner == address {
+                total += transaction.amount;
+            }
+        }
+        None => {
+            if &transaction.sender == address && !matches!(transaction.script, Some(ScriptAction::Unlock { .. })) {
+                total += transaction.amount;
+            }
+        }
+    }
+    if transaction.fee_paid_by() == address {
+        total += transaction.fee;
+    }
+    total
+}
+
+/// Walks the pending transactions of a sender starting at its confirmed nonce
+///
+/// # Arguments
+///
+/// * `pending` - The pending transactions
+/// * `address` - The sender
+/// * `confirmed_nonce` - The nonce of the sender's confirmed account
+///
+/// # Returns
+///
+/// The nonce the next transaction from the sender must use
+pub fn next_nonce(pending: &[Transaction], address: &Address, confirmed_nonce: u64) -> u64 {
+    let mut next_nonce = confirmed_nonce;
+
+    while pending
+        .iter()
+        .any(|tx| !tx.is_coinbase() && &tx.sender == address && tx.nonce == next_nonce)
+    {
+        next_nonce += 1;
+    }
+
+    next_nonce
+}
+
+/// Checks that a block can follow its parent
+///
+/// # Arguments
+///
+/// * `parent` - The block it must follow
+/// * `block` - The block to check
+/// * `rules` - The rules to check against
+///
+/// # Returns
+///
+/// Ok(()) if the version is allowed, the block links to its parent, its hash meets
+/// the difficulty, it is not timestamped before its parent or too far in the future,
+/// it ends with its only coinbase, the coinbase pays the reward and the base fees as the fee policy says,
+/// the tips to the miner and alone pays the treasury, the block holds no more transactions than
+/// allowed and every transaction has a valid ID and signature and covers the base fee, unless
+/// it is a treasury disbursement, and registers names, anchors data, changes permissions,
+/// takes governance actions, locks or unlocks amounts, uses session keys and designates
+/// beneficiaries or claims properly
+pub fn check_block(parent: &Block, block: &Block, rules: &ValidationRules) -> Result<(), ValidationError> {
+    rules.versions.check_block(block)?;
+
+    if block.index != parent.index + 1 || block.previous_hash != parent.hash {
+        return Err(ValidationError::InvalidBlock(format!(
+            "Block {} does not link to block {}",
+            block.index, parent.index
+        )));
+    }
+
+    check_proof_of_work(block, rules)?;
+
+    // Time never goes back along the chain, lookups by time depend on it
+    if block.timestamp < parent.timestamp {
+        return Err(ValidationError::InvalidBlock(format!(
+            "Block {} is timestamped before block {}",
+            block.index, parent.index
+        )));
+    }
+    if block.timestamp > Utc::now() + MAX_FUTURE_BLOCK_TIME {
+        return Err(ValidationError::InvalidBlock(format!(
+            "Block {} is timestamped too far in the future",
+            block.index
+        )));
+    }
+
+    // One coinbase pays the miner, always the last transaction
+    let coinbases = block.transactions.iter().filter(|tx| tx.is_coinbase()).count();
+    if coinbases != 1 || !block.transactions.last().is_some_and(|tx| tx.is_coinbase()) {
+        return Err(ValidationError::InvalidBlock(format!(
+            "Block {} holds {} coinbases, it must end with exactly one",
+            block.index, coinbases
+        )));
+    }
+
+    let fees = BlockFees::new(&block.transactions, rules.base_fee);
+    let expected_amount = rules.fee_policy.miner_amount(rules.mining_reward, &fees);
+    let expected_payout = rules.fee_policy.payout(rules.mining_reward, fees.base);
+
+    for transaction in &block.transactions {
+        if !transaction.has_valid_id() {
+            return Err(ValidationError::InvalidBlock(format!(
+                "Transaction {} in block {} does not match its ID",
+                transaction.id, block.index
+            )));
+        }
+        // Disbursements come from the keyless treasury, governance checks them against the chain
+        if !transaction.is_coinbase() && !transaction.is_disbursement() && !transaction.verify_signature()? {
+            return Err(ValidationError::TransactionError(TransactionError::InvalidSignature));
+        }
+        check_fee_payer(transaction)?;
+        if !transaction.is_coinbase() && !transaction.is_disbursement() && transaction.fee < rules.base_fee {
+            return Err(ValidationError::InvalidBlock(format!(
+                "Transaction {} in block {} pays {}, below the base fee {}",
+                transaction.id, block.index, transaction.fee, rules.base_fee
+            )));
+        }
+        names::check_registration(transaction)?;
+        anchors::check_anchor(transaction)?;
+        permissions::check_change(transaction)?;
+        governance::check_action(transaction)?;
+        scripts::check_script(transaction)?;
+        sessions::check_session(transaction)?;
+        inheritance::check_inheritance(transaction)?;
+
+        if transaction.is_coinbase() && transaction.amount != expected_amount {
+            return Err(ValidationError::InvalidBlock(format!(
+                "Coinbase of block {} pays {}, expected {}",
+                block.index, transaction.amount, expected_amount
+            )));
+        }
+        if transaction.is_coinbase() && transaction.treasury != expected_payout {
+            return Err(ValidationError::InvalidBlock(format!(
+                "Coinbase of block {} pays the treasury {:?}, expected {:?}",
+                block.index, transaction.treasury, expected_payout
+            )));
+        }
+        if !transaction.is_coinbase() && transaction.treasury.is_some() {
+            return Err(ValidationError::InvalidBlock(format!(
+                "Transaction {} in block {} pays the treasury but is no coinbase",
+                transaction.id, block.index
+            )));
+        }
+    }
+
+    let transaction_count = block.transactions.iter().filter(|tx| !tx.is_coinbase()).count();
+    if transaction_count > rules.max_block_transactions {
+        return Err(ValidationError::InvalidBlock(format!(
+            "Block {} holds {} transactions, at most {} are allowed",
+            block.index, transaction_count, rules.max_block_transactions
+        )));
+    }
+
+    Ok(())
+}
+
+/// Checks that a block hashes to its hash and the hash meets the difficulty
+///
+/// Cheap next to the rest of `check_block` and independent of the chain,
+/// so blocks from peers are checked before the node keeps them anywhere.
+///
+/// # Arguments
+///
+/// * `block` - The block to check
+/// * `rules` - The rules at the block's height
+///
+/// # Returns
+///
+/// Ok(()) if the proof of work is valid
+pub fn check_proof_of_work(block: &Block, rules: &ValidationRules) -> Result<(), ValidationError> {
+    let target = "0".repeat(rules.difficulty as usize);
+    if block.hash != block.calculate_hash() || !block.hash.starts_with(&target) {
+        return Err(ValidationError::InvalidBlock(format!(
+            "Block {} has an invalid proof of work",
+            block.index
+        )));
+    }
+
+    Ok(())
+}
+
+/// Checks that a block header can follow the header of its parent
+///
+/// The hash of a block covers its transactions, so a header alone can't be
+/// rehashed. This only catches headers with a version the height doesn't
+/// allow, that don't link to their parent or whose hash misses the
+/// difficulty, before their blocks are fetched. `check_block` checks each
+/// block in full once it arrives.
+///
+/// # Arguments
+///
+/// * `parent` - The header it must follow
+/// * `header` - The header to check
+/// * `rules` - The rules to check against
+///
+/// # Returns
+///
+/// Ok(()) if the version is allowed, the header links to its parent and its hash meets the difficulty
+pub fn check_header(parent: &BlockHeader, header: &BlockHeader, rules: &ValidationRules) -> Result<(), ValidationError> {
+    rules.versions.check_header(header)?;
+
+    if header.index != parent.index + 1 || header.previous_hash != parent.hash {
+        return Err(ValidationError::InvalidBlock(format!(
+            "Header of block {} does not link to block {}",
+            header.index, parent.index
+        )));
+    }
+
+    let target = "0".repeat(rules.difficulty as usize);
+    if !header.hash.starts_with(&target) {
+        return Err(ValidationError::InvalidBlock(format!(
+            "Header of block {} has an invalid proof of work",
+            header.index
+        )));
+    }
+
+    Ok(())
+}
+
+/// Applies the transactions of a block to an account state
+///
+/// # Arguments
+///
+/// * `accounts` - The account state to update
+/// * `block` - The block to apply
+///
+/// # Returns
+///
+/// Result with () if successful
+pub fn apply_block(accounts: &AccountState, block: &Block) -> Result<(), AccountError> {
+    for transaction in &block.transactions {
+        if transaction.is_coinbase() {
+            accounts.process_mining_reward(&transaction.recipient, transaction.amount)?;
+            if let Some(payout) = &transaction.treasury {
+                accounts.process_mining_reward(&Address(payout.address.clone()), payout.amount)?;
+            }
+        } else {
+            apply_transfer(accounts, transaction)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Applies a transaction other than a coinbase to an account state
+///
+/// Releases the amount of an unlock or a claim first, then transfers the amount and
+/// takes the fee from the sender or the fee payer. A sponsored transfer is
+/// checked in full before the fee moves, so one that fails changes nothing
+/// but the release.
+///
+/// # Arguments
+///
+/// * `accounts` - The account state to update
+/// * `transaction` - The transaction to apply
+///
+/// # Returns
+///
+/// Result with () if successful
+pub fn apply_transfer(accounts: &AccountState, transaction: &Transaction) -> Result<(), AccountError> {
+    scripts::release(accounts, transaction)?;
+    inheritance::release(accounts, transaction)?;
+
+    let fee_payer = match &transaction.fee_payer {
+        Some(fee_payer) => &fee_payer.address,
+        None => {
+            return accounts.transfer(
+                &transaction.sender,
+                &transaction.recipient,
+                transaction.amount,
+                transaction.fee,
+                transaction.nonce,
+            )
+        }
+    };
+
+    let mut sender = accounts.get_account(&transaction.sender);
+    let mut payer = accounts.get_account(fee_payer);
+    if !sender.is_valid_nonce(transaction.nonce) {
+        return Err(AccountError::InvalidNonce {
+            expected: sender.nonce,
+            got: transaction.nonce,
+        });
+    }
+    for (account, required) in [(&sender, transaction.amount), (&payer, transaction.fee)] {
+        if !account.has_sufficient_funds(required) {
+            return Err(AccountError::InsufficientFunds {
+                required,
+                available: account.balance,
+            });
+        }
+    }
+
+    // The fee passes through the sender, whose transfer then pays it as usual
+    payer.withdraw(transaction.fee)?;
+    sender.deposit(transaction.fee)?;
+    accounts.update_account(payer);
+    accounts.update_account(sender);
+
+    accounts.transfer(
+        &transaction.sender,
+        &transaction.recipient,
+        transaction.amount,
+        transaction.fee,
+        transaction.nonce,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::crypto::Wallet;
+
+    #[test]
+    fn test_transactions_are_checked_before_admission() {
+        let rules = ValidationRules::node();
+        let sender = Wallet::new().unwrap();
+        let accounts = AccountState::new();
+        accounts.process_mining_reward(sender.address(), 10.0).unwrap();
+
+        let transfer = |amount: f64, fee: f64, nonce: u64| {
+            let mut transaction = Transaction::new(sender.address().clone(), Address("bob".to_string()), amount, fee, nonce);
+            transaction.sign(&sender).unwrap();
+            transaction
+        };
+
+        let first = transfer(4.0, 0.5, 0);
+        check_transaction(&first, 1, &rules).unwrap();
+        check_admission(&accounts, &[], &first).unwrap();
+
+        // A cheap fee and a tampered amount fail without any state
+        let cheap = transfer(1.0, MINIMUM_FEE / 2.0, 1);
+        assert!(matches!(check_transaction(&cheap, 1, &rules), Err(ValidationError::TransactionError(TransactionError::FeeTooLow { .. }))));
+        let mut tampered = first.clone();
+        tampered.amount = 9.0;
+        assert!(matches!(check_transaction(&tampered, 1, &rules), Err(ValidationError::TransactionError(_))));
+
+        // Funds and nonces count the pending transactions
+        let pending = vec![first];
+        assert_eq!(next_nonce(&pending, sender.address(), 0), 1);
+        assert!(matches!(
+            check_admission(&accounts, &pending, &transfer(6.0, 0.5, 1)),
+            Err(ValidationError::AccountError(AccountError::InsufficientFunds { .. }))
+        ));
+        assert!(matches!(
+            check_admission(&accounts, &pending, &transfer(1.0, 0.5, 0)),
+            Err(ValidationError::AccountError(AccountError::InvalidNonce { expected: 1, got: 0 }))
+        ));
+
+        // A replacement must pay more and keep every pending transaction affordable
+        check_replacement(&accounts, &pending, &pending[0], &transfer(4.0, 0.6, 0)).unwrap();
+        assert!(matches!(
+            check_replacement(&accounts, &pending, &pending[0], &transfer(5.0, 0.5, 0)),
+            Err(ValidationError::TransactionError(TransactionError::ReplacementUnderpriced { .. }))
+        ));
+        assert!(matches!(
+            check_replacement(&accounts, &pending, &pending[0], &transfer(9.5, 1.0, 0)),
+            Err(ValidationError::AccountError(AccountError::InsufficientFunds { .. }))
+        ));
+
+        // A name may only be registered once among the pending transactions
+        let registry_address = Address(names::NAME_REGISTRY_ADDRESS.to_string());
+        let registration = Transaction::new(sender.address().clone(), registry_address.clone(), names::NAME_FEE, 0.5, 1).with_name("alice");
+        let registry = NameRegistry::new();
+        check_name_admission(&registry, &pending, &registration, 1).unwrap();
+        let other = Wallet::new().unwrap();
+        let competing = Transaction::new(other.address().clone(), registry_address, names::NAME_FEE, 0.5, 0).with_name("alice");
+        assert!(matches!(
+            check_name_admission(&registry, &[registration], &competing, 1),
+            Err(ValidationError::NameError(NameError::Pending(_)))
+        ));
+    }
+
+    #[test]
+    fn test_fee_payer_pays_the_fee() {
+        let rules = ValidationRules::node();
+        let sender = Wallet::new().unwrap();
+        let sponsor = Wallet::new().unwrap();
+        let accounts = AccountState::new();
+        accounts.process_mining_reward(sender.address(), 4.0).unwrap();
+        accounts.process_mining_reward(sponsor.address(), 1.0).unwrap();
+
+        let sponsored = |amount: f64, fee: f64, nonce: u64| {
+            let mut transaction = Transaction::new(sender.address().clone(), Address("bob".to_string()), amount, fee, nonce)
+                .with_fee_payer(sponsor.address().clone());
+            transaction.sign(&sender).unwrap();
+            transaction.sign_fee_payer(&sponsor).unwrap();
+            transaction
+        };
+
+        // The sender only needs the amount, the sponsor the fee
+        let first = sponsored(4.0, 0.5, 0);
+        check_transaction(&first, 1, &rules).unwrap();
+        check_admission(&accounts, &[], &first).unwrap();
+        assert!(matches!(
+            check_admission(&accounts, std::slice::from_ref(&first), &sponsored(0.0, 0.75, 1)),
+            Err(ValidationError::AccountError(AccountError::InsufficientFunds { required, .. })) if required == 0.75
+        ));
+
+        // Both must sign, and the sender can't sponsor itself
+        let mut unsigned = Transaction::new(sender.address().clone(), Address("bob".to_string()), 1.0, 0.5, 0)
+            .with_fee_payer(sponsor.address().clone());
+        unsigned.sign(&sender).unwrap();
+        assert!(check_transaction(&unsigned, 1, &rules).is_err());
+        let mut forged = unsigned.clone();
+        forged.sign_fee_payer(&sponsor).unwrap();
+        forged.fee_payer.as_mut().unwrap().address = Wallet::new().unwrap().address().clone();
+        assert!(check_transaction(&forged, 1, &rules).is_err());
+        let mut own = Transaction::new(sender.address().clone(), Address("bob".to_string()), 1.0, 0.5, 0)
+            .with_fee_payer(sender.address().clone());
+        own.sign(&sender).unwrap();
+        own.sign_fee_payer(&sender).unwrap();
+        assert!(matches!(
+            check_transaction(&own, 1, &rules),
+            Err(ValidationError::TransactionError(TransactionError::InvalidFeePayer(_)))
+        ));
+
+        apply_transfer(&accounts, &first).unwrap();
+        assert_eq!(accounts.get_account(sender.address()).balance, 0.0);
+        assert_eq!(accounts.get_account(sponsor.address()).balance, 0.5);
+        assert_eq!(accounts.get_account(&Address("bob".to_string())).balance, 4.0);
+
+        // A sponsor short of the fee changes nothing
+        assert!(apply_transfer(&accounts, &sponsored(0.0, 0.75, 1)).is_err());
+        assert_eq!(accounts.get_account(sender.address()).nonce, 1);
+        assert_eq!(accounts.get_account(sponsor.address()).balance, 0.5);
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use super::account::AccountState;
+use super::block::Block;
+use super::crypto::Address;
+use super::fees::{self, BlockFees, FeePolicy};
+use super::governance::{ChainParameters, Governance};
+use super::scripts::ScriptLocks;
+use super::sessions::SessionKeys;
+use super::inheritance::Inheritances;
+use super::transaction::MAX_COINBASE_MESSAGE_BYTES;
+use super::treasury::TREASURY_ADDRESS;
+use super::validation;
+use super::versioning::VersionSchedule;
+
+/// The first rule a chain breaks
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    /// Height of the offending block
+    pub height: u64,
+
+    /// Hash of the offending block
+    pub block_hash: String,
+
+    /// ID of the offending transaction, if the rule concerns one
+    pub transaction_id: Option<String>,
+
+    /// The broken rule
+    pub reason: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Chain verification failed")?;
+        writeln!(f, "  height:      {}", self.height)?;
+        writeln!(f, "  block:       {}", self.block_hash)?;
+        if let Some(transaction_id) = &self.transaction_id {
+            writeln!(f, "  transaction: {}", transaction_id)?;
+        }
+        write!(f, "  violation:   {}", self.reason)
+    }
+}
+
+/// Outcome of a successful verification
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyReport {
+    /// Number of blocks verified, including the genesis block
+    pub blocks_verified: usize,
+
+    /// Number of transactions verified, including coinbase transactions
+    pub transactions_verified: usize,
+
+    /// Number of accounts in the replayed state
+    pub accounts: usize,
+}
+
+impl fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Chain verified")?;
+        writeln!(f, "  blocks:       {}", self.blocks_verified)?;
+        writeln!(f, "  transactions: {}", self.transactions_verified)?;
+        write!(f, "  accounts:     {}", self.accounts)
+    }
+}
+
+/// Replays a chain through full validation with a fresh account state
+///
+/// Checks the genesis block, every block's link, hash, version, timestamp and proof of
+/// work, the single coinbase of each mined block and the reward governance has
+/// in force at its height, the treasury installments due, every transaction ID,
+/// signature, fee payer and fee against the base fee of its block, every spend of a script lock against its condition,
+/// every transaction signed by a session key against the key's authorization and limits, every claim
+/// against its owner's designation and inactivity, and applies
+/// every transaction, so balances and nonces must work out from nothing but
+/// the chain itself.
+///
+/// # Arguments
+///
+/// * `blocks` - The blocks of the chain, genesis first
+/// * `difficulty` - Number of leading zeros required in the hash of mined blocks
+/// * `mining_reward` - The reward a coinbase transaction must pay until governance changes it
+/// * `fee_policy` - Where the fees of each block must go
+///
+/// # Returns
+///
+/// The report if the chain is valid, otherwise the first violation in chain order
+pub fn verify_chain(
+    blocks: &[Block],
+    difficulty: u8,
+    mining_reward: f64,
+    fee_policy: &FeePolicy,
+) -> Result<VerifyReport, Violation> {
+    if blocks.is_empty() {
+        return Err(Violation {
+            height: 0,
+            block_hash: String::new(),
+            transaction_id: None,
+            reason: "the chain has no blocks".to_string(),
+        });
+    }
+
+    let target = "0".repeat(difficulty as usize);
+    let versions = VersionSchedule::node();
+    let account_state = AccountState::new();
+    let governance = Governance::new(ChainParameters {
+        mining_reward,
+        ..ChainParameters::node()
+    });
+    let locks = ScriptLocks::new();
+    let session_keys = SessionKeys::new();
+    let switches = Inheritances::new();
+    let mut seen_transactions = HashSet::new();
+    let mut transactions_verified = 0;
+    let mut base_fee = governance.parameters_at(1).minimum_fee;
+
+    for (position, block) in blocks.iter().enumerate() {
+        let violation = |transaction_id: Option<&str>, reason: String| Violation {
+            height: block.index,
+            block_hash: block.hash.clone(),
+            transaction_id: transaction_id.map(str::to_string),
+            reason,
+        };
+
+        if block.hash != block.calculate_hash() {
+            return Err(violation(None, format!("hash does not match contents, expected {}", block.calculate_hash())));
+        }
+        if let Err(err) = versions.check_block(block) {
+            return Err(violation(None, err.to_string()));
+        }
+
+        if position == 0 {
+            if block.index != 0 || block.previous_hash != "0" || !block.transactions.is_empty() {
+                return Err(violation(None, "not a valid genesis block".to_string()));
+            }
+            continue;
+        }
+
+        let previous = &blocks[position - 1];
+        if block.index != previous.index + 1 {
+            return Err(violation(None, format!("index follows block {}", previous.index)));
+        }
+        if block.previous_hash != previous.hash {
+            return Err(violation(None, format!("does not link to block {} ({})", previous.index, previous.hash)));
+        }
+        if block.timestamp < previous.timestamp {
+            return Err(violation(None, format!("timestamp {} is before its parent's", block.timestamp.to_rfc3339())));
+        }
+        if !block.hash.starts_with(&target) {
+            return Err(violation(None, format!("hash does not meet difficulty {}", difficulty)));
+        }
+
+        let parameters = governance.parameters_at(block.index);
+        let fees = BlockFees::new(&block.transactions, base_fee);
+        let expected_amount = fee_policy.miner_amount(parameters.mining_reward, &fees);
+        let expected_payout = fee_policy.payout(parameters.mining_reward, fees.base);
+        let coinbase_count = block.transactions.iter().filter(|tx| tx.is_coinbase()).count();
+        if coinbase_count != 1 {
+            return Err(violation(None, format!("has {} coinbase transactions, expected 1", coinbase_count)));
+        }
+        let treasury = account_state.get_account(&Address(TREASURY_ADDRESS.to_string()));
+        if let Err(err) = governance.check_disbursements(block, treasury.balance) {
+            return Err(violation(None, err.to_string()));
+        }
+        if let Err(err) = locks.check_block(block) {
+            return Err(violation(None, err.to_string()));
+        }
+        if let Err(err) = session_keys.check_block(block) {
+            return Err(violation(None, err.to_string()));
+        }
+        if let Err(err) = switches.check_block(block) {
+            return Err(violation(None, err.to_string()));
+        }
+
+        for transaction in &block.transactions {
+            let id = Some(transaction.id.as_str());
+
+            if !seen_transactions.insert(transaction.id.as_str()) {
+                return Err(violation(id, "transaction is included more than once".to_string()));
+            }
+
+            if !transaction.has_valid_id() {
+                return Err(violation(id, format!("ID does not match its content, expected {}", transaction.compute_id())));
+            }
+
+            let applied = if transaction.is_coinbase() {
+                if transaction.amount != expected_amount {
+                    return Err(violation(
+                        id,
+                        format!("coinbase pays {}, expected {}", transaction.amount, expected_amount),
+                    ));
+                }
+                if transaction.treasury != expected_payout {
+                    return Err(violation(
+                        id,
+                        format!("coinbase pays the treasury {:?}, expected {:?}", transaction.treasury, expected_payout),
+                    ));
+                }
+                if transaction.message.as_ref().is_some_and(|message| message.len() > MAX_COINBASE_MESSAGE_BYTES) {
+                    return Err(violation(
+                        id,
+                        format!("coinbase message is longer than {} bytes", MAX_COINBASE_MESSAGE_BYTES),
+                    ));
+                }
+                account_state
+                    .process_mining_reward(&transaction.recipient, transaction.amount)
+                    .and_then(|()| match &transaction.treasury {
+                        Some(payout) => account_state.process_mining_reward(&Address(payout.address.clone()), payout.amount),
+                        None => Ok(()),
+                    })
+            } else {
+                if transaction.treasury.is_some() {
+                    return Err(violation(id, "only a coinbase may pay the treasury".to_string()));
+                }
+                match transaction.verify_signature() {
+                    _ if transaction.is_disbursement() => {}
+                    Ok(true) => {}
+                    Ok(false) => return Err(violation(id, "invalid signature".to_string())),
+                    Err(err) => return Err(violation(id, format!("signature can not be checked: {}", err))),
+                }
+                if !transaction.is_disbursement() && transaction.fee < base_fee {
+                    return Err(violation(id, format!("fee {} is below the base fee {}", transaction.fee, base_fee)));
+                }
+                if let Err(err) = validation::check_fee_payer(transaction) {
+                    return Err(violation(id, err.to_string()));
+                }
+                validation::apply_transfer(&account_state, transaction)
+            };
+
+            if let Err(err) = applied {
+                return Err(violation(id, format!("can not be applied: {}", err)));
+            }
+            transactions_verified += 1;
+        }
+        governance.record_block(block, &account_state);
+        locks.record_block(block);
+        session_keys.record_block(block);
+        switches.record_block(block);
+        base_fee = fees::next_base_fee(
+            base_fee,
+            block,
+            parameters.max_block_transactions,
+            governance.parameters_at(block.index + 1).minimum_fee,
+        );
+    }
+
+    Ok(VerifyReport {
+        blocks_verified: blocks.len(),
+        transactions_verified,
+        accounts: account_state.get_all_accounts().len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::transaction::Transaction;
+    use crate::blockchain::chain::{DIFFICULTY, MINING_REWARD};
+    use crate::blockchain::{Address, Blockchain, Wallet};
+
+    #[test]
+    fn test_verify_reports_first_violation() {
+        let blockchain = Blockchain::new();
+        let miner = Wallet::new().unwrap();
+        blockchain.mine_block(&miner.address().0).unwrap();
+
+        let recipient = Address("recipient".to_string());
+        let mut transaction = Transaction::new(miner.address().clone(), recipient, 10.0, 1.0, 0);
+        transaction.sign(&miner).unwrap();
+        blockchain.add_transaction(transaction).unwrap();
+        blockchain.mine_block(&miner.address().0).unwrap();
+
+        let chain = blockchain.get_chain();
+        let report = verify_chain(&chain, DIFFICULTY, MINING_REWARD, &FeePolicy::Burn).unwrap();
+        assert_eq!((report.blocks_verified, report.transactions_verified, report.accounts), (3, 3, 2));
+
+        // Tampering with a signed transaction breaks the block hash first
+        let mut tampered = chain.clone();
+        tampered[2].transactions[0].amount = 40.0;
+        let violation = verify_chain(&tampered, DIFFICULTY, MINING_REWARD, &FeePolicy::Burn).unwrap_err();
+        assert_eq!(violation.height, 2);
+        assert!(violation.reason.starts_with("hash does not match"));
+
+        // Even with a recalculated hash the ID no longer matches the content
+        tampered[2].hash = tampered[2].calculate_hash();
+        let violation = verify_chain(&tampered, 0, MINING_REWARD, &FeePolicy::Burn).unwrap_err();
+        assert_eq!(violation.transaction_id.as_deref(), Some(tampered[2].transactions[0].id.as_str()));
+        assert!(violation.reason.starts_with("ID does not match"));
+
+        // And with a recalculated ID the signature does not
+        tampered[2].transactions[0].id = tampered[2].transactions[0].compute_id();
+        tampered[2].hash = tampered[2].calculate_hash();
+        let violation = verify_chain(&tampered, 0, MINING_REWARD, &FeePolicy::Burn).unwrap_err();
+        assert_eq!(violation.reason, "invalid signature");
+
+        // Leaving out a block breaks the links
+        let violation = verify_chain(&[chain[0].clone(), chain[2].clone()], DIFFICULTY, MINING_REWARD, &FeePolicy::Burn).unwrap_err();
+        assert_eq!(violation.reason, "index follows block 0");
+    }
+}
@@ -0,0 +1,1034 @@
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+
+use std::fmt;
+
+use super::block::{Block, BlockHeader};
+use super::crypto::{Address, DigitalSignature};
+use super::transaction::{Transaction, CONTENT_ID_VERSION, SIGNING_CONTEXT_VERSION};
+
+/// Errors that occur when a block or transaction has a version its height does not allow
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum VersionError {
+    #[error("Block {height} has version {version}, allowed are {allowed}")]
+    Block {
+        height: u64,
+        version: u32,
+        allowed: VersionRange,
+    },
+
+    #[error("Transaction {id} has version {version}, allowed at height {height} are {allowed}")]
+    Transaction {
+        id: String,
+        height: u64,
+        version: u32,
+        allowed: VersionRange,
+    },
+}
+
+/// The structures that carry a version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Versioned {
+    Block,
+    Transaction,
+}
+
+/// The versions allowed at a height, both ends included
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionRange {
+    pub min: u32,
+    pub max: u32,
+}
+
+impl VersionRange {
+    /// Checks if a version is in the range
+    pub fn contains(&self, version: u32) -> bool {
+        (self.min..=self.max).contains(&version)
+    }
+}
+
+impl fmt::Display for VersionRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} to {}", self.min, self.max)
+    }
+}
+
+/// A feature that introduces a new version from a height on
+#[derive(Debug, Clone, PartialEq)]
+pub struct Activation {
+    /// Name of the feature
+    pub feature: String,
+
+    /// The structure whose version the feature bumps
+    pub structure: Versioned,
+
+    /// The version introducing the feature
+    pub version: u32,
+
+    /// First height the version is allowed at
+    pub height: u64,
+}
+
+impl Activation {
+    fn new(feature: &str, structure: Versioned, version: u32, height: u64) -> Self {
+        Activation {
+            feature: feature.to_string(),
+            structure,
+            version,
+            height,
+        }
+    }
+}
+
+/// The versions allowed at each height
+///
+/// A version becomes allowed once its feature activates. Versions are never
+/// retired, since existing chains hold blocks and transactions of every
+/// version, and versions nothing has activated are unknown and rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VersionSchedule {
+    activations: Vec<Activation>,
+}
+
+impl VersionSchedule {
+    /// Creates a version schedule
+    ///
+    /// # Arguments
+    ///
+    /// * `activations` - The features and the heights they activate at
+    pub fn new(activations: Vec<Activation>) -> Self {
+        VersionSchedule { activations }
+    }
+
+    /// Gets the schedule this node enforces
+    ///
+    /// Every version was in use before versions were enforced, so all of
+    /// them are active from the genesis block on.
+    pub fn node() -> Self {
+        VersionSchedule::new(vec![
+            Activation::new("versioned blocks", Versioned::Block, 1, 0),
+            Activation::new("signed transactions", Versioned::Transaction, 1, 0),
+            Activation::new("content-derived transaction IDs", Versioned::Transaction, CONTENT_ID_VERSION, 0),
+            Activation::new("transaction signing context", Versioned::Transaction, SIGNING_CONTEXT_VERSION, 0),
+        ])
+    }
+
+    /// Gets the versions of a structure allowed at a height
+    ///
+    /// # Arguments
+    ///
+    /// * `structure` - The versioned structure
+    /// * `height` - The height of the block
+    ///
+    /// # Returns
+    ///
+    /// The allowed versions, None if no version is active yet
+    pub fn allowed(&self, structure: Versioned, height: u64) -> Option<VersionRange> {
+        let active = self
+            .activations
+            .iter()
+            .filter(|activation| activation.structure == structure && activation.height <= height)
+            .map(|activation| activation.version);
+
+        let min = active.clone().min()?;
+        let max = active.max()?;
+        Some(VersionRange { min, max })
+    }
+
+    /// Checks the versions of a block and its transactions
+    ///
+    /// # Arguments
+    ///
+    /// * `block` - The block to check
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if every version is allowed at the height of the block
+    pub fn check_block(&self, block: &Block) -> Result<(), VersionError> {
+        self.check_block_version(block.index, block.version)?;
+
+        for transaction in &block.transactions {
+            self.check_transaction(transaction, block.index)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks the version of a block header, before its transactions are known
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - The header to check
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if the block version is allowed at the height of the block
+    pub fn check_header(&self, header: &BlockHeader) -> Result<(), VersionError> {
+        self.check_block_version(header.index, header.version)
+    }
+
+    /// Checks the version of a block at a height
+    fn check_block_version(&self, height: u64, version: u32) -> Result<(), VersionError> {
+        let allowed = self.allowed(Versioned::Block, height).unwrap_or(VersionRange { min: 1, max: 0 });
+        if !allowed.contains(version) {
+            return Err(VersionError::Block {
+                height,
+                version,
+                allowed,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Checks the version of a transaction
+    ///
+    /// # Arguments
+    ///
+    /// * `transaction` - The transaction to check
+    /// * `height` - The height of the block that includes it
+    ///
+    /// # Returns
+    ///
+    /// Ok(()) if the version is allowed at the height
+    pub fn check_transaction(&self, transaction: &Transaction, height: u64) -> Result<(), VersionError> {
+        let allowed = self.allowed(Versioned::Transaction, height).unwrap_or(VersionRange { min: 1, max: 0 });
+        if !allowed.contains(transaction.version) {
+            return Err(VersionError::Transaction {
+                id: transaction.id.clone(),
+                height,
+                version: transaction.version,
+                allowed,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Decodes a stored transaction written by any version of the node
+///
+/// Stored transactions grew over time, new fields were always appended:
+///
+/// * At first the signature was left out entirely when there was none, so
+///   unsigned coinbase transactions could not be read back
+/// * Then the signature was always written
+/// * Then the coinbase extra-nonce was appended
+/// * Then the coinbase message was appended
+/// * Then the registered name was appended
+/// * Then the anchored document hash was appended
+/// * Then the permission change was appended
+/// * Then the governance action was appended
+/// * Then the freeze order was appended
+/// * Then the treasury payout was appended
+/// * Then the script lock or unlock was appended
+/// * Then the fee payer was appended
+/// * Then the session key action was appended
+/// * Then the inheritance action was appended
+///
+/// Fields a record does not have are left unset.
+///
+/// # Arguments
+///
+/// * `bytes` - The bincode record
+///
+/// # Returns
+///
+/// The transaction
+pub fn decode_transaction(bytes: &[u8]) -> bincode::Result<Transaction> {
+    let mut reader = bytes;
+    let mut transaction = read_transaction(&mut reader)?;
+
+    // Appended fields, missing from older records
+    if !reader.is_empty() {
+        transaction.extra_nonce = bincode::deserialize_from(&mut reader)?;
+    }
+    if !reader.is_empty() {
+        transaction.message = bincode::deserialize_from(&mut reader)?;
+    }
+    if !reader.is_empty() {
+        transaction.name = bincode::deserialize_from(&mut reader)?;
+    }
+    if !reader.is_empty() {
+        transaction.data = bincode::deserialize_from(&mut reader)?;
+    }
+    if !reader.is_empty() {
+        transaction.permission = bincode::deserialize_from(&mut reader)?;
+    }
+    if !reader.is_empty() {
+        transaction.governance = bincode::deserialize_from(&mut reader)?;
+    }
+    if !reader.is_empty() {
+        transaction.freeze = bincode::deserialize_from(&mut reader)?;
+    }
+    if !reader.is_empty() {
+        transaction.treasury = bincode::deserialize_from(&mut reader)?;
+    }
+    if !reader.is_empty() {
+        transaction.script = bincode::deserialize_from(&mut reader)?;
+    }
+    if !reader.is_empty() {
+        transaction.fee_payer = bincode::deserialize_from(&mut reader)?;
+    }
+    if !reader.is_empty() {
+        transaction.session = bincode::deserialize_from(&mut reader)?;
+    }
+    if !reader.is_empty() {
+        transaction.inheritance = bincode::deserialize_from(&mut reader)?;
+    }
+
+    if !reader.is_empty() {
+        return Err(Box::new(bincode::ErrorKind::Custom(format!(
+            "{} unexpected bytes after the transaction",
+            reader.len()
+        ))));
+    }
+
+    Ok(transaction)
+}
+
+/// Decodes a block stored in full, before blocks were stored as headers
+///
+/// Its transactions are in one of the first two layouts described at
+/// [`decode_transaction`], the appended fields came later.
+///
+/// # Arguments
+///
+/// * `bytes` - The bincode record
+///
+/// # Returns
+///
+/// The block
+pub fn decode_full_block(bytes: &[u8]) -> bincode::Result<Block> {
+    let mut reader = bytes;
+
+    let version: u32 = bincode::deserialize_from(&mut reader)?;
+    let index: u64 = bincode::deserialize_from(&mut reader)?;
+    let timestamp: DateTime<Utc> = bincode::deserialize_from(&mut reader)?;
+    let count: u64 = bincode::deserialize_from(&mut reader)?;
+    let transactions = (0..count)
+        .map(|_| read_transaction(&mut reader))
+        .collect::<bincode::Result<Vec<_>>>()?;
+    let proof: u64 = bincode::deserialize_from(&mut reader)?;
+    let previous_hash: String = bincode::deserialize_from(&mut reader)?;
+    let hash: String = bincode::deserialize_from(&mut reader)?;
+
+    if !reader.is_empty() {
+        return Err(Box::new(bincode::ErrorKind::Custom(format!(
+            "{} unexpected bytes after the block",
+            reader.len()
+        ))));
+    }
+
+    Ok(Block {
+        version,
+        index,
+        timestamp,
+        transactions,
+        proof,
+        previous_hash,
+        hash,
+    })
+}
+
+/// Reads the fields every stored transaction has
+fn read_transaction(reader: &mut &[u8]) -> bincode::Result<Transaction> {
+    let version: u32 = bincode::deserialize_from(&mut *reader)?;
+    let id: String = bincode::deserialize_from(&mut *reader)?;
+    let sender: Address = bincode::deserialize_from(&mut *reader)?;
+    let recipient: Address = bincode::deserialize_from(&mut *reader)?;
+    let amount: f64 = bincode::deserialize_from(&mut *reader)?;
+    let fee: f64 = bincode::deserialize_from(&mut *reader)?;
+    let nonce: u64 = bincode::deserialize_from(&mut *reader)?;
+
+    // An option tag is 0 or 1, while the timestamp starts with its length,
+    // which is never that short, so a left out signature is told apart
+    let signature: Option<DigitalSignature> = match reader.first() {
+        Some(0) | Some(1) => bincode::deserialize_from(&mut *reader)?,
+        _ => None,
+    };
+    let timestamp: DateTime<Utc> = bincode::deserialize_from(&mut *reader)?;
+
+    Ok(Transaction {
+        version,
+        id,
+        sender,
+        recipient,
+        amount,
+        fee,
+        nonce,
+        signature,
+        timestamp,
+        extra_nonce: None,
+        message: None,
+        name: None,
+        data: None,
+        permission: None,
+        governance: None,
+        freeze: None,
+        treasury: None,
+        script: None,
+        fee_payer: None,
+        session: None,
+        inheritance: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::fees::TreasuryPayout;
+    use crate::blockchain::governance::GovernanceAction;
+    use crate::blockchain::permissions::{FreezeOrder, PermissionChange};
+    use crate::blockchain::scripts::{Condition, ScriptAction};
+    use crate::blockchain::inheritance::InheritanceAction;
+    use crate::blockchain::sessions::SessionAction;
+    use crate::blockchain::transaction::FeePayer;
+    use crate::blockchain::Wallet;
+    use serde::Serialize;
+
+    /// A transaction as first stored, the signature left out when unset
+    #[derive(Serialize)]
+    struct FirstRecord {
+        version: u32,
+        id: String,
+        sender: Address,
+        recipient: Address,
+        amount: f64,
+        fee: f64,
+        nonce: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        signature: Option<DigitalSignature>,
+        timestamp: DateTime<Utc>,
+    }
+
+    /// A transaction as stored once the signature was always written
+    #[derive(Serialize)]
+    struct SignedRecord {
+        version: u32,
+        id: String,
+        sender: Address,
+        recipient: Address,
+        amount: f64,
+        fee: f64,
+        nonce: u64,
+        signature: Option<DigitalSignature>,
+        timestamp: DateTime<Utc>,
+    }
+
+    /// A transaction as stored once the extra-nonce was appended
+    #[derive(Serialize)]
+    struct ExtraNonceRecord {
+        version: u32,
+        id: String,
+        sender: Address,
+        recipient: Address,
+        amount: f64,
+        fee: f64,
+        nonce: u64,
+        signature: Option<DigitalSignature>,
+        timestamp: DateTime<Utc>,
+        extra_nonce: Option<u64>,
+    }
+
+    /// A transaction as stored once the message was appended
+    #[derive(Serialize)]
+    struct MessageRecord {
+        version: u32,
+        id: String,
+        sender: Address,
+        recipient: Address,
+        amount: f64,
+        fee: f64,
+        nonce: u64,
+        signature: Option<DigitalSignature>,
+        timestamp: DateTime<Utc>,
+        extra_nonce: Option<u64>,
+        message: Option<String>,
+    }
+
+    /// A transaction as stored once the name was appended
+    #[derive(Serialize)]
+    struct NameRecord {
+        version: u32,
+        id: String,
+        sender: Address,
+        recipient: Address,
+        amount: f64,
+        fee: f64,
+        nonce: u64,
+        signature: Option<DigitalSignature>,
+        timestamp: DateTime<Utc>,
+        extra_nonce: Option<u64>,
+        message: Option<String>,
+        name: Option<String>,
+    }
+
+    /// A transaction as stored once the anchored hash was appended
+    #[derive(Serialize)]
+    struct DataRecord {
+        version: u32,
+        id: String,
+        sender: Address,
+        recipient: Address,
+        amount: f64,
+        fee: f64,
+        nonce: u64,
+        signature: Option<DigitalSignature>,
+        timestamp: DateTime<Utc>,
+        extra_nonce: Option<u64>,
+        message: Option<String>,
+        name: Option<String>,
+        data: Option<String>,
+    }
+
+    /// A transaction as stored once the permission change was appended
+    #[derive(Serialize)]
+    struct PermissionRecord {
+        version: u32,
+        id: String,
+        sender: Address,
+        recipient: Address,
+        amount: f64,
+        fee: f64,
+        nonce: u64,
+        signature: Option<DigitalSignature>,
+        timestamp: DateTime<Utc>,
+        extra_nonce: Option<u64>,
+        message: Option<String>,
+        name: Option<String>,
+        data: Option<String>,
+        permission: Option<PermissionChange>,
+    }
+
+    /// A transaction as stored once the governance action was appended
+    #[derive(Serialize)]
+    struct GovernanceRecord {
+        version: u32,
+        id: String,
+        sender: Address,
+        recipient: Address,
+        amount: f64,
+        fee: f64,
+        nonce: u64,
+        signature: Option<DigitalSignature>,
+        timestamp: DateTime<Utc>,
+        extra_nonce: Option<u64>,
+        message: Option<String>,
+        name: Option<String>,
+        data: Option<String>,
+        permission: Option<PermissionChange>,
+        governance: Option<GovernanceAction>,
+    }
+
+    /// A transaction as stored once the freeze order was appended
+    #[derive(Serialize)]
+    struct FreezeRecord {
+        version: u32,
+        id: String,
+        sender: Address,
+        recipient: Address,
+        amount: f64,
+        fee: f64,
+        nonce: u64,
+        signature: Option<DigitalSignature>,
+        timestamp: DateTime<Utc>,
+        extra_nonce: Option<u64>,
+        message: Option<String>,
+        name: Option<String>,
+        data: Option<String>,
+        permission: Option<PermissionChange>,
+        governance: Option<GovernanceAction>,
+        freeze: Option<FreezeOrder>,
+    }
+
+    /// A transaction as stored once the treasury payout was appended
+    #[derive(Serialize)]
+    struct TreasuryRecord {
+        version: u32,
+        id: String,
+        sender: Address,
+        recipient: Address,
+        amount: f64,
+        fee: f64,
+        nonce: u64,
+        signature: Option<DigitalSignature>,
+        timestamp: DateTime<Utc>,
+        extra_nonce: Option<u64>,
+        message: Option<String>,
+        name: Option<String>,
+        data: Option<String>,
+        permission: Option<PermissionChange>,
+        governance: Option<GovernanceAction>,
+        freeze: Option<FreezeOrder>,
+        treasury: Option<TreasuryPayout>,
+    }
+
+    /// A transaction as stored once the script action was appended
+    #[derive(Serialize)]
+    struct ScriptRecord {
+        version: u32,
+        id: String,
+        sender: Address,
+        recipient: Address,
+        amount: f64,
+        fee: f64,
+        nonce: u64,
+        signature: Option<DigitalSignature>,
+        timestamp: DateTime<Utc>,
+        extra_nonce: Option<u64>,
+        message: Option<String>,
+        name: Option<String>,
+        data: Option<String>,
+        permission: Option<PermissionChange>,
+        governance: Option<GovernanceAction>,
+        freeze: Option<FreezeOrder>,
+        treasury: Option<TreasuryPayout>,
+        script: Option<ScriptAction>,
+    }
+
+    /// A transaction as stored once the fee payer was appended
+    #[derive(Serialize)]
+    struct FeePayerRecord {
+        version: u32,
+        id: String,
+        sender: Address,
+        recipient: Address,
+        amount: f64,
+        fee: f64,
+        nonce: u64,
+        signature: Option<DigitalSignature>,
+        timestamp: DateTime<Utc>,
+        extra_nonce: Option<u64>,
+        message: Option<String>,
+        name: Option<String>,
+        data: Option<String>,
+        permission: Option<PermissionChange>,
+        governance: Option<GovernanceAction>,
+        freeze: Option<FreezeOrder>,
+        treasury: Option<TreasuryPayout>,
+        script: Option<ScriptAction>,
+        fee_payer: Option<FeePayer>,
+    }
+
+    /// A transaction as stored once the session key action was appended
+    #[derive(Serialize)]
+    struct SessionRecord {
+        version: u32,
+        id: String,
+        sender: Address,
+        recipient: Address,
+        amount: f64,
+        fee: f64,
+        nonce: u64,
+        signature: Option<DigitalSignature>,
+        timestamp: DateTime<Utc>,
+        extra_nonce: Option<u64>,
+        message: Option<String>,
+        name: Option<String>,
+        data: Option<String>,
+        permission: Option<PermissionChange>,
+        governance: Option<GovernanceAction>,
+        freeze: Option<FreezeOrder>,
+        treasury: Option<TreasuryPayout>,
+        script: Option<ScriptAction>,
+        fee_payer: Option<FeePayer>,
+        session: Option<SessionAction>,
+    }
+
+    /// A block as stored in full
+    #[derive(Serialize)]
+    struct FullBlockRecord<T> {
+        version: u32,
+        index: u64,
+        timestamp: DateTime<Utc>,
+        transactions: Vec<T>,
+        proof: u64,
+        previous_hash: String,
+        hash: String,
+    }
+
+    fn first_record(tx: &Transaction) -> FirstRecord {
+        FirstRecord {
+            version: tx.version,
+            id: tx.id.clone(),
+            sender: tx.sender.clone(),
+            recipient: tx.recipient.clone(),
+            amount: tx.amount,
+            fee: tx.fee,
+            nonce: tx.nonce,
+            signature: tx.signature.clone(),
+            timestamp: tx.timestamp,
+        }
+    }
+
+    fn signed_record(tx: &Transaction) -> SignedRecord {
+        SignedRecord {
+            version: tx.version,
+            id: tx.id.clone(),
+            sender: tx.sender.clone(),
+            recipient: tx.recipient.clone(),
+            amount: tx.amount,
+            fee: tx.fee,
+            nonce: tx.nonce,
+            signature: tx.signature.clone(),
+            timestamp: tx.timestamp,
+        }
+    }
+
+    fn json(transaction: &Transaction) -> serde_json::Value {
+        serde_json::to_value(transaction).unwrap()
+    }
+
+    #[test]
+    fn test_every_stored_layout_decodes() {
+        let wallet = Wallet::new().unwrap();
+        let mut signed = Transaction::new(wallet.address().clone(), Address("recipient".to_string()), 1.0, 0.1, 0);
+        signed.sign(&wallet).unwrap();
+        let coinbase = Transaction::new_coinbase(Address("miner".to_string()), 50.0);
+
+        // Every layout, signed and unsigned
+        for transaction in [&signed, &coinbase] {
+            let first = bincode::serialize(&first_record(transaction)).unwrap();
+            assert_eq!(json(&decode_transaction(&first).unwrap()), json(transaction));
+
+            let always_signed = bincode::serialize(&signed_record(transaction)).unwrap();
+            assert_eq!(json(&decode_transaction(&always_signed).unwrap()), json(transaction));
+        }
+
+        let with_extra_nonce = coinbase.clone().with_extra_nonce(7);
+        let record = ExtraNonceRecord {
+            version: with_extra_nonce.version,
+            id: with_extra_nonce.id.clone(),
+            sender: with_extra_nonce.sender.clone(),
+            recipient: with_extra_nonce.recipient.clone(),
+            amount: with_extra_nonce.amount,
+            fee: with_extra_nonce.fee,
+            nonce: with_extra_nonce.nonce,
+            signature: None,
+            timestamp: with_extra_nonce.timestamp,
+            extra_nonce: Some(7),
+        };
+        let decoded = decode_transaction(&bincode::serialize(&record).unwrap()).unwrap();
+        assert_eq!(json(&decoded), json(&with_extra_nonce));
+
+        let with_message = coinbase.clone().with_extra_nonce(7).with_message("hello");
+        let record = MessageRecord {
+            version: with_message.version,
+            id: with_message.id.clone(),
+            sender: with_message.sender.clone(),
+            recipient: with_message.recipient.clone(),
+            amount: with_message.amount,
+            fee: with_message.fee,
+            nonce: with_message.nonce,
+            signature: None,
+            timestamp: with_message.timestamp,
+            extra_nonce: Some(7),
+            message: Some("hello".to_string()),
+        };
+        let decoded = decode_transaction(&bincode::serialize(&record).unwrap()).unwrap();
+        assert_eq!(json(&decoded), json(&with_message));
+
+        let mut with_name = Transaction::new(wallet.address().clone(), Address("names".to_string()), 1.0, 0.1, 1).with_name("alice");
+        with_name.sign(&wallet).unwrap();
+        let record = NameRecord {
+            version: with_name.version,
+            id: with_name.id.clone(),
+            sender: with_name.sender.clone(),
+            recipient: with_name.recipient.clone(),
+            amount: with_name.amount,
+            fee: with_name.fee,
+            nonce: with_name.nonce,
+            signature: with_name.signature.clone(),
+            timestamp: with_name.timestamp,
+            extra_nonce: None,
+            message: None,
+            name: Some("alice".to_string()),
+        };
+        let decoded = decode_transaction(&bincode::serialize(&record).unwrap()).unwrap();
+        assert_eq!(json(&decoded), json(&with_name));
+
+        let mut with_data = Transaction::new(wallet.address().clone(), Address("anchors".to_string()), 0.01, 0.1, 2).with_data(&"ab".repeat(32));
+        with_data.sign(&wallet).unwrap();
+        let record = DataRecord {
+            version: with_data.version,
+            id: with_data.id.clone(),
+            sender: with_data.sender.clone(),
+            recipient: with_data.recipient.clone(),
+            amount: with_data.amount,
+            fee: with_data.fee,
+            nonce: with_data.nonce,
+            signature: with_data.signature.clone(),
+            timestamp: with_data.timestamp,
+            extra_nonce: None,
+            message: None,
+            name: None,
+            data: Some("ab".repeat(32)),
+        };
+        let decoded = decode_transaction(&bincode::serialize(&record).unwrap()).unwrap();
+        assert_eq!(json(&decoded), json(&with_data));
+
+        let change = PermissionChange {
+            address: "alice".to_string(),
+            allowed: true,
+        };
+        let mut with_permission = Transaction::new(wallet.address().clone(), Address("permissions".to_string()), 0.01, 0.1, 3).with_permission(change.clone());
+        with_permission.sign(&wallet).unwrap();
+        let record = PermissionRecord {
+            version: with_permission.version,
+            id: with_permission.id.clone(),
+            sender: with_permission.sender.clone(),
+            recipient: with_permission.recipient.clone(),
+            amount: with_permission.amount,
+            fee: with_permission.fee,
+            nonce: with_permission.nonce,
+            signature: with_permission.signature.clone(),
+            timestamp: with_permission.timestamp,
+            extra_nonce: None,
+            message: None,
+            name: None,
+            data: None,
+            permission: Some(change),
+        };
+        let decoded = decode_transaction(&bincode::serialize(&record).unwrap()).unwrap();
+        assert_eq!(json(&decoded), json(&with_permission));
+
+        let vote = GovernanceAction::Vote {
+            proposal: "proposal".to_string(),
+            approve: true,
+        };
+        let mut with_governance = Transaction::new(wallet.address().clone(), Address("governance".to_string()), 0.01, 0.1, 4).with_governance(vote.clone());
+        with_governance.sign(&wallet).unwrap();
+        let record = GovernanceRecord {
+            version: with_governance.version,
+            id: with_governance.id.clone(),
+            sender: with_governance.sender.clone(),
+            recipient: with_governance.recipient.clone(),
+            amount: with_governance.amount,
+            fee: with_governance.fee,
+            nonce: with_governance.nonce,
+            signature: with_governance.signature.clone(),
+            timestamp: with_governance.timestamp,
+            extra_nonce: None,
+            message: None,
+            name: None,
+            data: None,
+            permission: None,
+            governance: Some(vote),
+        };
+        let decoded = decode_transaction(&bincode::serialize(&record).unwrap()).unwrap();
+        assert_eq!(json(&decoded), json(&with_governance));
+
+        let order = FreezeOrder {
+            address: "alice".to_string(),
+            frozen: true,
+            reason: "stolen keys".to_string(),
+        };
+        let mut with_freeze = Transaction::new(wallet.address().clone(), Address("permissions".to_string()), 0.01, 0.1, 5).with_freeze(order.clone());
+        with_freeze.sign(&wallet).unwrap();
+        let record = FreezeRecord {
+            version: with_freeze.version,
+            id: with_freeze.id.clone(),
+            sender: with_freeze.sender.clone(),
+            recipient: with_freeze.recipient.clone(),
+            amount: with_freeze.amount,
+            fee: with_freeze.fee,
+            nonce: with_freeze.nonce,
+            signature: with_freeze.signature.clone(),
+            timestamp: with_freeze.timestamp,
+            extra_nonce: None,
+            message: None,
+            name: None,
+            data: None,
+            permission: None,
+            governance: None,
+            freeze: Some(order),
+        };
+        let decoded = decode_transaction(&bincode::serialize(&record).unwrap()).unwrap();
+        assert_eq!(json(&decoded), json(&with_freeze));
+
+        let payout = TreasuryPayout {
+            address: "treasury".to_string(),
+            amount: 0.5,
+        };
+        let with_treasury = Transaction::new_coinbase(wallet.address().clone(), 50.5).with_treasury(payout.clone());
+        let record = TreasuryRecord {
+            version: with_treasury.version,
+            id: with_treasury.id.clone(),
+            sender: with_treasury.sender.clone(),
+            recipient: with_treasury.recipient.clone(),
+            amount: with_treasury.amount,
+            fee: with_treasury.fee,
+            nonce: with_treasury.nonce,
+            signature: None,
+            timestamp: with_treasury.timestamp,
+            extra_nonce: None,
+            message: None,
+            name: None,
+            data: None,
+            permission: None,
+            governance: None,
+            freeze: None,
+            treasury: Some(payout),
+        };
+        let decoded = decode_transaction(&bincode::serialize(&record).unwrap()).unwrap();
+        assert_eq!(json(&decoded), json(&with_treasury));
+
+        let lock = ScriptAction::Lock {
+            recipient: "bob".to_string(),
+            condition: Condition::All {
+                conditions: vec![
+                    Condition::NotBefore { height: 10 },
+                    Condition::Hashlock { hash: "ab".repeat(32) },
+                ],
+            },
+        };
+        let mut with_script = Transaction::new(wallet.address().clone(), Address("scripts".to_string()), 3.0, 0.1, 6).with_script(lock.clone());
+        with_script.sign(&wallet).unwrap();
+        let record = ScriptRecord {
+            version: with_script.version,
+            id: with_script.id.clone(),
+            sender: with_script.sender.clone(),
+            recipient: with_script.recipient.clone(),
+            amount: with_script.amount,
+            fee: with_script.fee,
+            nonce: with_script.nonce,
+            signature: with_script.signature.clone(),
+            timestamp: with_script.timestamp,
+            extra_nonce: None,
+            message: None,
+            name: None,
+            data: None,
+            permission: None,
+            governance: None,
+            freeze: None,
+            treasury: None,
+            script: Some(lock),
+        };
+        let decoded = decode_transaction(&bincode::serialize(&record).unwrap()).unwrap();
+        assert_eq!(json(&decoded), json(&with_script));
+
+        let sponsor = Wallet::new().unwrap();
+        let mut sponsored =
+            Transaction::new(wallet.address().clone(), Address("bob".to_string()), 3.0, 0.1, 7).with_fee_payer(sponsor.address().clone());
+        sponsored.sign(&wallet).unwrap();
+        sponsored.sign_fee_payer(&sponsor).unwrap();
+        let record = FeePayerRecord {
+            version: sponsored.version,
+            id: sponsored.id.clone(),
+            sender: sponsored.sender.clone(),
+            recipient: sponsored.recipient.clone(),
+            amount: sponsored.amount,
+            fee: sponsored.fee,
+            nonce: sponsored.nonce,
+            signature: sponsored.signature.clone(),
+            timestamp: sponsored.timestamp,
+            extra_nonce: None,
+            message: None,
+            name: None,
+            data: None,
+            permission: None,
+            governance: None,
+            freeze: None,
+            treasury: None,
+            script: None,
+            fee_payer: sponsored.fee_payer.clone(),
+        };
+        let decoded = decode_transaction(&bincode::serialize(&record).unwrap()).unwrap();
+        assert_eq!(json(&decoded), json(&sponsored));
+        assert!(decoded.verify_fee_payer_signature().unwrap());
+
+        let session = Wallet::new().unwrap();
+        let mut session_spend = Transaction::new(wallet.address().clone(), Address("bob".to_string()), 3.0, 0.1, 8)
+            .with_session(SessionAction::Spend { key: session.address().0.clone() });
+        session_spend.sign(&session).unwrap();
+        let record = SessionRecord {
+            version: session_spend.version,
+            id: session_spend.id.clone(),
+            sender: session_spend.sender.clone(),
+            recipient: session_spend.recipient.clone(),
+            amount: session_spend.amount,
+            fee: session_spend.fee,
+            nonce: session_spend.nonce,
+            signature: session_spend.signature.clone(),
+            timestamp: session_spend.timestamp,
+            extra_nonce: None,
+            message: None,
+            name: None,
+            data: None,
+            permission: None,
+            governance: None,
+            freeze: None,
+            treasury: None,
+            script: None,
+            fee_payer: None,
+            session: session_spend.session.clone(),
+        };
+        let decoded = decode_transaction(&bincode::serialize(&record).unwrap()).unwrap();
+        assert_eq!(json(&decoded), json(&session_spend));
+        assert!(decoded.verify_signature().unwrap());
+
+        let mut current = Transaction::new(session.address().clone(), Address("bob".to_string()), 3.0, 0.1, 0)
+            .with_inheritance(InheritanceAction::Claim { owner: wallet.address().0.clone() });
+        current.sign(&session).unwrap();
+        let decoded = decode_transaction(&bincode::serialize(&current).unwrap()).unwrap();
+        assert_eq!(json(&decoded), json(&current));
+        assert!(decoded.verify_signature().unwrap());
+
+        // A full block mixes signed transactions with a coinbase whose signature was left out
+        let block = Block::new(1, vec![signed.clone(), coinbase.clone()], 7, "0".to_string());
+        let record = FullBlockRecord {
+            version: block.version,
+            index: block.index,
+            timestamp: block.timestamp,
+            transactions: vec![first_record(&signed), first_record(&coinbase)],
+            proof: block.proof,
+            previous_hash: block.previous_hash.clone(),
+            hash: block.hash.clone(),
+        };
+        let decoded = decode_full_block(&bincode::serialize(&record).unwrap()).unwrap();
+        assert_eq!(decoded.hash, decoded.calculate_hash());
+        assert_eq!(decoded.transactions.len(), 2);
+        assert!(decoded.transactions[0].verify_signature().unwrap());
+
+        // Transactions sent before versions and coinbase fields existed
+        let old_json = serde_json::json!({
+            "id": signed.id,
+            "sender": signed.sender,
+            "recipient": signed.recipient,
+            "amount": 1.0,
+            "fee": 0.1,
+            "nonce": 0,
+            "signature": null,
+            "timestamp": signed.timestamp,
+        });
+        let decoded: Transaction = serde_json::from_value(old_json).unwrap();
+        assert_eq!(decoded.version, 1);
+        assert_eq!(decoded.extra_nonce, None);
+    }
+
+    #[test]
+    fn test_versions_are_enforced_per_height() {
+        let coinbase = Transaction::new_coinbase(Address("miner".to_string()), 50.0);
+        let node = VersionSchedule::node();
+        assert_eq!(node.allowed(Versioned::Transaction, 0), Some(VersionRange { min: 1, max: 3 }));
+        assert!(node.check_transaction(&coinbase, 1).is_ok());
+
+        // Unknown future versions are rejected
+        let mut future = coinbase.clone();
+        future.version = SIGNING_CONTEXT_VERSION + 1;
+        assert!(node.check_transaction(&future, 1).is_err());
+        let mut block = Block::new(1, vec![coinbase.clone()], 7, "0".to_string());
+        block.version = 2;
+        assert!(matches!(node.check_block(&block), Err(VersionError::Block { version: 2, .. })));
+
+        // A version is only allowed once its feature is active
+        let schedule = VersionSchedule::new(vec![
+            Activation::new("signed transactions", Versioned::Transaction, 1, 0),
+            Activation::new("transaction signing context", Versioned::Transaction, 3, 10),
+        ]);
+        assert!(schedule.check_transaction(&coinbase, 9).is_err());
+        assert!(schedule.check_transaction(&coinbase, 10).is_ok());
+        assert_eq!(schedule.allowed(Versioned::Block, 10), None);
+    }
+}
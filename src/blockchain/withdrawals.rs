@@ -0,0 +1,775 @@
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use std::sync::{Arc, Mutex};
+
+use super::audit::AuditLog;
+use super::canonical;
+use super::chain::Blockchain;
+use super::crypto::{Address, CryptoError, DigitalSignature, Wallet};
+use super::signing::{self, SigningContext};
+use super::storage::{BlockchainStorage, StorageError};
+use super::transaction::Transaction;
+
+/// Actor recorded in the audit log for actions taken by the node itself
+const SYSTEM_ACTOR: &str = "system";
+
+/// Errors that can occur during withdrawal operations
+#[derive(Debug, Error)]
+pub enum WithdrawalError {
+    #[error("Withdrawal not found: {0}")]
+    NotFound(String),
+
+    #[error("Withdrawal already requested: {0}")]
+    AlreadyExists(String),
+
+    #[error("Invalid amount: {0}")]
+    InvalidAmount(String),
+
+    #[error("Invalid account: {0}")]
+    InvalidAccount(String),
+
+    #[error("The signature is not the account's")]
+    InvalidSignature,
+
+    #[error("Account {account} has {available} available, {amount} requested")]
+    InsufficientBalance {
+        account: String,
+        available: f64,
+        amount: f64,
+    },
+
+    #[error("Withdrawal {id} is {status:?}, expected {expected:?}")]
+    InvalidState {
+        id: String,
+        status: WithdrawalStatus,
+        expected: WithdrawalStatus,
+    },
+
+    #[error("Withdrawal {0} was changed concurrently")]
+    Conflict(String),
+
+    #[error("Node is under maintenance: {0}")]
+    Paused(String),
+
+    #[error("Storage error: {0}")]
+    StorageError(#[from] StorageError),
+}
+
+/// The state of a withdrawal request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WithdrawalStatus {
+    /// Waiting for an operator decision
+    Pending,
+
+    /// Approved and waiting for the next batch submission
+    Approved,
+
+    /// Rejected by an operator
+    Rejected,
+
+    /// Submitted to the mempool as a transaction
+    Submitted,
+
+    /// The transaction could not be submitted
+    Failed,
+}
+
+/// What the holder of an account authorizes with their signature
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct WithdrawalTerms {
+    /// Address whose deposits the withdrawal is paid from
+    #[schema(value_type = String)]
+    pub account: Address,
+
+    /// Address the funds are paid to
+    #[schema(value_type = String)]
+    pub recipient: Address,
+
+    /// Amount to pay out
+    pub amount: f64,
+
+    /// Any number telling otherwise identical withdrawals apart
+    pub nonce: u64,
+}
+
+impl WithdrawalTerms {
+    /// Gets the bytes the account signs to request the withdrawal
+    ///
+    /// The canonical JSON of the terms, signed in the withdrawal context.
+    pub fn payload(&self) -> Vec<u8> {
+        canonical::to_vec(&serde_json::json!({
+            "account": self.account.0,
+            "recipient": self.recipient.0,
+            "amount": self.amount,
+            "nonce": self.nonce,
+        }))
+    }
+
+    /// Gets the ID of the withdrawal, the SHA-256 hash of the signed payload
+    ///
+    /// Requesting the same signed terms again gets the same ID, so a
+    /// signature can't be replayed into a second withdrawal.
+    pub fn id(&self) -> String {
+        hex::encode(Sha256::digest(self.payload()))
+    }
+}
+
+/// Signs the terms of a withdrawal as the holder of the account
+///
+/// # Arguments
+///
+/// * `wallet` - The account's wallet
+/// * `terms` - The terms of the withdrawal
+///
+/// # Returns
+///
+/// The signature
+pub fn sign(wallet: &Wallet, terms: &WithdrawalTerms) -> Result<DigitalSignature, CryptoError> {
+    signing::sign(wallet, SigningContext::Withdrawal, &terms.payload())
+}
+
+/// What an account can withdraw
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct WithdrawalBalance {
+    /// The account
+    #[schema(value_type = String)]
+    pub account: Address,
+
+    /// Sum of the account's confirmed transfers to the hot wallet
+    pub deposited: f64,
+
+    /// Sum of the account's withdrawals that are pending, approved or submitted
+    pub reserved: f64,
+
+    /// What is left to withdraw
+    pub available: f64,
+}
+
+/// Represents a request to pay funds out of the hot wallet
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Withdrawal {
+    /// Unique identifier for the withdrawal, see `WithdrawalTerms::id`
+    pub id: String,
+
+    /// Address whose balance the withdrawal is debited from
+    #[schema(value_type = String)]
+    pub account: Address,
+
+    /// Address the funds are paid to
+    pub recipient: Address,
+
+    /// Amount to pay out
+    pub amount: f64,
+
+    /// The withdrawal status
+    pub status: WithdrawalStatus,
+
+    /// Timestamp when the withdrawal was requested
+    #[schema(value_type = String, example = "2023-01-01T12:00:00Z")]
+    pub created_at: DateTime<Utc>,
+
+    /// Operator who approved or rejected the withdrawal
+    pub decided_by: Option<String>,
+
+    /// ID of the payout transaction, once submitted
+    pub transaction_id: Option<String>,
+
+    /// Reason for a rejection or a failed submission
+    pub error: Option<String>,
+}
+
+/// Queue of withdrawals paid out from a hot wallet after approval
+///
+/// Accounts fund their withdrawals with ordinary transfers to the hot
+/// wallet. A withdrawal is signed by the account and debits what it
+/// deposited; rejected and failed withdrawals give it back.
+#[derive(Debug, Clone)]
+pub struct WithdrawalQueue {
+    withdrawals: Arc<DashMap<String, Withdrawal>>,
+    hot_wallet: Wallet,
+    auto_approve_limit: f64,
+    audit: AuditLog,
+    storage: Option<Arc<BlockchainStorage>>,
+    request_lock: Arc<Mutex<()>>,
+    submit_lock: Arc<Mutex<()>>,
+}
+
+impl WithdrawalQueue {
+    /// Creates a new withdrawal queue
+    ///
+    /// # Arguments
+    ///
+    /// * `hot_wallet` - The wallet withdrawals are paid from
+    /// * `auto_approve_limit` - Withdrawals up to this amount skip operator approval
+    /// * `audit` - The audit log to record actions in
+    /// * `storage` - Optional storage to persist withdrawals in
+    ///
+    /// # Returns
+    ///
+    /// A new WithdrawalQueue instance with the withdrawals loaded from storage
+    pub fn new(
+        hot_wallet: Wallet,
+        auto_approve_limit: f64,
+        audit: AuditLog,
+        storage: Option<Arc<BlockchainStorage>>,
+    ) -> Result<Self, WithdrawalError> {
+        let withdrawals = DashMap::new();
+
+        if let Some(storage) = &storage {
+            for withdrawal in storage.get_all_withdrawals()? {
+                withdrawals.insert(withdrawal.id.clone(), withdrawal);
+            }
+        }
+
+        Ok(WithdrawalQueue {
+            withdrawals: Arc::new(withdrawals),
+            hot_wallet,
+            auto_approve_limit,
+            audit,
+            storage,
+            request_lock: Arc::new(Mutex::new(())),
+            submit_lock: Arc::new(Mutex::new(())),
+        })
+    }
+
+    /// Gets the address of the hot wallet
+    pub fn hot_wallet_address(&self) -> &Address {
+        self.hot_wallet.address()
+    }
+
+    /// Gets what an account can withdraw
+    ///
+    /// # Arguments
+    ///
+    /// * `account` - The account
+    /// * `blockchain` - The blockchain holding the account's deposits
+    ///
+    /// # Returns
+    ///
+    /// The account's deposits, reserved withdrawals and what is left
+    pub fn balance(&self, account: &Address, blockchain: &Blockchain) -> WithdrawalBalance {
+        let hot_wallet = self.hot_wallet.address();
+        let deposited: f64 = blockchain
+            .get_address_history(hot_wallet)
+            .iter()
+            .filter(|(_, tx)| &tx.sender == account && &tx.recipient == hot_wallet)
+            .map(|(_, tx)| tx.amount)
+            .sum();
+
+        let reserved: f64 = self
+            .withdrawals
+            .iter()
+            .filter(|withdrawal| {
+                &withdrawal.account == account
+                    && !matches!(withdrawal.status, WithdrawalStatus::Rejected | WithdrawalStatus::Failed)
+            })
+            .map(|withdrawal| withdrawal.amount)
+            .sum();
+
+        WithdrawalBalance {
+            account: account.clone(),
+            deposited,
+            reserved,
+            available: deposited - reserved,
+        }
+    }
+
+    /// Queues a new withdrawal signed by its account
+    ///
+    /// Withdrawals up to the auto-approve limit are approved immediately.
+    ///
+    /// # Arguments
+    ///
+    /// * `terms` - The terms of the withdrawal
+    /// * `signature` - The account's signature of the terms' payload
+    /// * `blockchain` - The blockchain holding the account's deposits
+    ///
+    /// # Returns
+    ///
+    /// The queued withdrawal
+    pub fn request(
+        &self,
+        terms: WithdrawalTerms,
+        signature: &DigitalSignature,
+        blockchain: &Blockchain,
+    ) -> Result<Withdrawal, WithdrawalError> {
+        if !terms.amount.is_finite() || terms.amount <= 0.0 {
+            return Err(WithdrawalError::InvalidAmount(format!(
+                "Amount must be positive: {}",
+                terms.amount
+            )));
+        }
+        if &terms.account == self.hot_wallet.address() {
+            return Err(WithdrawalError::InvalidAccount(
+                "The hot wallet can't withdraw from itself".to_string(),
+            ));
+        }
+        verify_account(&terms.account, &terms.payload(), signature)?;
+
+        // One request at a time so two withdrawals can't spend the same deposit
+        let _guard = self.request_lock.lock().unwrap();
+
+        let id = terms.id();
+        if self.withdrawals.contains_key(&id) {
+            return Err(WithdrawalError::AlreadyExists(id));
+        }
+
+        let balance = self.balance(&terms.account, blockchain);
+        if terms.amount > balance.available {
+            return Err(WithdrawalError::InsufficientBalance {
+                account: terms.account.0,
+                available: balance.available,
+                amount: terms.amount,
+            });
+        }
+
+        let auto_approved = terms.amount <= self.auto_approve_limit;
+        let withdrawal = Withdrawal {
+            id,
+            account: terms.account,
+            recipient: terms.recipient,
+            amount: terms.amount,
+            status: if auto_approved {
+                WithdrawalStatus::Approved
+            } else {
+                WithdrawalStatus::Pending
+            },
+            created_at: Utc::now(),
+            decided_by: auto_approved.then(|| SYSTEM_ACTOR.to_string()),
+            transaction_id: None,
+            error: None,
+        };
+
+        self.save(&withdrawal)?;
+
+        let detail = format!(
+            "{} from {} to {}",
+            withdrawal.amount, withdrawal.account, withdrawal.recipient
+        );
+        self.audit.record(SYSTEM_ACTOR, "withdrawal.requested", &withdrawal.id, &detail);
+        if auto_approved {
+            self.audit.record(
+                SYSTEM_ACTOR,
+                "withdrawal.approved",
+                &withdrawal.id,
+                &format!("auto-approved under limit {}", self.auto_approve_limit),
+            );
+        }
+
+        Ok(withdrawal)
+    }
+
+    /// Approves a pending withdrawal
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the withdrawal
+    /// * `operator` - The operator approving it
+    ///
+    /// # Returns
+    ///
+    /// The approved withdrawal
+    pub fn approve(&self, id: &str, operator: &str) -> Result<Withdrawal, WithdrawalError> {
+        let withdrawal = self.decide(id, operator, WithdrawalStatus::Approved, None)?;
+        self.audit.record(operator, "withdrawal.approved", id, "");
+        Ok(withdrawal)
+    }
+
+    /// Rejects a pending withdrawal
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the withdrawal
+    /// * `operator` - The operator rejecting it
+    /// * `reason` - Why it was rejected
+    ///
+    /// # Returns
+    ///
+    /// The rejected withdrawal
+    pub fn reject(&self, id: &str, operator: &str, reason: &str) -> Result<Withdrawal, WithdrawalError> {
+        let withdrawal = self.decide(id, operator, WithdrawalStatus::Rejected, Some(reason.to_string()))?;
+        self.audit.record(operator, "withdrawal.rejected", id, reason);
+        Ok(withdrawal)
+    }
+
+    /// Moves a pending withdrawal to its decided state
+    fn decide(
+        &self,
+        id: &str,
+        operator: &str,
+        status: WithdrawalStatus,
+        error: Option<String>,
+    ) -> Result<Withdrawal, WithdrawalError> {
+        self.transition(id, WithdrawalStatus::Pending, |withdrawal| {
+            withdrawal.status = status;
+            withdrawal.decided_by = Some(operator.to_string());
+            withdrawal.error = error;
+        })
+    }
+
+    /// Updates a withdrawal only if it still has the expected status
+    ///
+    /// The entry stays locked from the check to the write, and storage only
+    /// takes the new record if it still holds the one that was checked, so
+    /// two concurrent decisions can't both succeed.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The ID of the withdrawal
+    /// * `expected` - The status the withdrawal must have
+    /// * `update` - Applies the transition
+    ///
+    /// # Returns
+    ///
+    /// The updated withdrawal
+    fn transition(
+        &self,
+        id: &str,
+        expected: WithdrawalStatus,
+        update: impl FnOnce(&mut Withdrawal),
+    ) -> Result<Withdrawal, WithdrawalError> {
+        let mut entry = self
+            .withdrawals
+            .get_mut(id)
+            .ok_or_else(|| WithdrawalError::NotFound(id.to_string()))?;
+
+        if entry.status != expected {
+            return Err(WithdrawalError::InvalidState {
+                id: id.to_string(),
+                status: entry.status,
+                expected,
+            });
+        }
+
+        let mut updated = entry.clone();
+        update(&mut updated);
+
+        if let Some(storage) = &self.storage {
+            if !storage.swap_withdrawal(&entry, &updated)? {
+                return Err(WithdrawalError::Conflict(id.to_string()));
+            }
+        }
+
+        *entry = updated.clone();
+        Ok(updated)
+    }
+
+    /// Submits all approved withdrawals as transactions from the hot wallet
+    ///
+    /// # Arguments
+    ///
+    /// * `blockchain` - The blockchain to submit the transactions to
+    ///
+    /// # Returns
+    ///
+    /// The withdrawals processed in this batch with their new status
+    pub fn submit_approved(&self, blockchain: &Blockchain) -> Result<Vec<Withdrawal>, WithdrawalError> {
+        // Only one batch at a time so nonces of the hot wallet don't collide
+        let _guard = self.submit_lock.lock().unwrap();
+
+        // Leave the batch approved instead of failing every withdrawal
+        let maintenance = blockchain.get_maintenance_status();
+        if maintenance.transactions_paused {
+            return Err(WithdrawalError::Paused(maintenance.reason()));
+        }
+
+        let mut batch = self.list(Some(WithdrawalStatus::Approved));
+        batch.sort_by_key(|withdrawal| withdrawal.created_at);
+
+        let mut processed = Vec::with_capacity(batch.len());
+        for withdrawal in batch {
+            match self.submit(&withdrawal, blockchain) {
+                Ok(withdrawal) => processed.push(withdrawal),
+                Err(err) => warn!("Failed to submit withdrawal {}: {}", withdrawal.id, err),
+            }
+        }
+
+        Ok(processed)
+    }
+
+    // Records the payout as submitted before handing it to the mempool, so a
+    // crash in between leaves a transaction ID to reconcile instead of paying twice
+    fn submit(&self, withdrawal: &Withdrawal, blockchain: &Blockchain) -> Result<Withdrawal, WithdrawalError> {
+        let mut transaction = Transaction::new(
+            self.hot_wallet.address().clone(),
+            withdrawal.recipient.clone(),
+            withdrawal.amount,
+            blockchain.get_base_fee(),
+            blockchain.get_next_nonce(self.hot_wallet.address()),
+        );
+
+        if let Err(err) = transaction.sign(&self.hot_wallet) {
+            return self.fail(&withdrawal.id, WithdrawalStatus::Approved, err.to_string());
+        }
+
+        let transaction_id = transaction.id.clone();
+        let submitted = self.transition(&withdrawal.id, WithdrawalStatus::Approved, |withdrawal| {
+            withdrawal.status = WithdrawalStatus::Submitted;
+            withdrawal.transaction_id = Some(transaction_id.clone());
+        })?;
+
+        match blockchain.add_transaction(transaction) {
+            Ok(_) => {
+                self.audit.record(SYSTEM_ACTOR, "withdrawal.submitted", &withdrawal.id, &transaction_id);
+                Ok(submitted)
+            }
+            Err(err) => self.fail(&withdrawal.id, WithdrawalStatus::Submitted, err.to_string()),
+        }
+    }
+
+    // Marks a withdrawal whose payout never reached the mempool as failed
+    fn fail(&self, id: &str, expected: WithdrawalStatus, error: String) -> Result<Withdrawal, WithdrawalError> {
+        let failed = self.transition(id, expected, |withdrawal| {
+            withdrawal.status = WithdrawalStatus::Failed;
+            withdrawal.transaction_id = None;
+            withdrawal.error = Some(error.clone());
+        })?;
+
+        self.audit.record(SYSTEM_ACTOR, "withdrawal.failed", id, &error);
+        Ok(failed)
+    }
+
+    /// Gets a withdrawal by its ID
+    pub fn get(&self, id: &str) -> Option<Withdrawal> {
+        self.withdrawals.get(id).map(|withdrawal| withdrawal.clone())
+    }
+
+    /// Lists withdrawals, optionally only those with the given status
+    pub fn list(&self, status: Option<WithdrawalStatus>) -> Vec<Withdrawal> {
+        let mut withdrawals: Vec<Withdrawal> = self
+            .withdrawals
+            .iter()
+            .filter(|withdrawal| status.is_none_or(|status| withdrawal.status == status))
+            .map(|withdrawal| withdrawal.clone())
+            .collect();
+
+        withdrawals.sort_by_key(|withdrawal| withdrawal.created_at);
+        withdrawals
+    }
+
+    /// Stores a withdrawal in memory and in storage if available
+    fn save(&self, withdrawal: &Withdrawal) -> Result<(), WithdrawalError> {
+        if let Some(storage) = &self.storage {
+            storage.save_withdrawal(withdrawal)?;
+        }
+
+        self.withdrawals.insert(withdrawal.id.clone(), withdrawal.clone());
+        Ok(())
+    }
+}
+
+/// Checks that the account signed a payload in the withdrawal context
+fn verify_account(account: &Address, payload: &[u8], signature: &DigitalSignature) -> Result<(), WithdrawalError> {
+    let public_key = account
+        .to_public_key()
+        .map_err(|err| WithdrawalError::InvalidAccount(err.to_string()))?;
+
+    match signing::verify(&public_key, SigningContext::Withdrawal, payload, signature) {
+        Ok(true) => Ok(()),
+        _ => Err(WithdrawalError::InvalidSignature),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_queue(auto_approve_limit: f64) -> (WithdrawalQueue, AuditLog) {
+        let audit = AuditLog::new(None).unwrap();
+        let queue = WithdrawalQueue::new(Wallet::new().unwrap(), auto_approve_limit, audit.clone(), None).unwrap();
+        (queue, audit)
+    }
+
+    /// Deposits an amount from a new account to the hot wallet and mines it
+    fn deposit(blockchain: &Blockchain, queue: &WithdrawalQueue, amount: f64) -> Wallet {
+        let account = Wallet::new().unwrap();
+        blockchain.fund_account(account.address(), amount + 1.0).unwrap();
+
+        let mut transaction = Transaction::new(
+            account.address().clone(),
+            queue.hot_wallet_address().clone(),
+            amount,
+            0.1,
+            0,
+        );
+        transaction.sign(&account).unwrap();
+        blockchain.add_transaction(transaction).unwrap();
+        blockchain.mine_block(&account.address().0).unwrap();
+
+        account
+    }
+
+    /// Requests a withdrawal signed by the account
+    fn request(
+        queue: &WithdrawalQueue,
+        blockchain: &Blockchain,
+        account: &Wallet,
+        amount: f64,
+        nonce: u64,
+    ) -> Result<Withdrawal, WithdrawalError> {
+        let terms = WithdrawalTerms {
+            account: account.address().clone(),
+            recipient: Address("recipient".to_string()),
+            amount,
+            nonce,
+        };
+        let signature = sign(account, &terms).unwrap();
+        queue.request(terms, &signature, blockchain)
+    }
+
+    #[test]
+    fn test_auto_approve_under_limit() {
+        let blockchain = Blockchain::new();
+        let (queue, _) = new_queue(10.0);
+        let account = deposit(&blockchain, &queue, 100.0);
+
+        let small = request(&queue, &blockchain, &account, 5.0, 0).unwrap();
+        let large = request(&queue, &blockchain, &account, 50.0, 1).unwrap();
+
+        assert_eq!(small.status, WithdrawalStatus::Approved);
+        assert_eq!(large.status, WithdrawalStatus::Pending);
+    }
+
+    #[test]
+    fn test_withdrawals_debit_the_account() {
+        let blockchain = Blockchain::new();
+        let (queue, _) = new_queue(1000.0);
+        let account = deposit(&blockchain, &queue, 30.0);
+
+        // Only the account can sign its withdrawals
+        let terms = WithdrawalTerms {
+            account: account.address().clone(),
+            recipient: Address("recipient".to_string()),
+            amount: 10.0,
+            nonce: 0,
+        };
+        let forged = sign(&Wallet::new().unwrap(), &terms).unwrap();
+        assert!(matches!(
+            queue.request(terms.clone(), &forged, &blockchain),
+            Err(WithdrawalError::InvalidSignature)
+        ));
+
+        // A signature is good for one withdrawal
+        let signature = sign(&account, &terms).unwrap();
+        queue.request(terms.clone(), &signature, &blockchain).unwrap();
+        assert!(matches!(
+            queue.request(terms, &signature, &blockchain),
+            Err(WithdrawalError::AlreadyExists(_))
+        ));
+
+        // Withdrawals can't spend more than was deposited
+        request(&queue, &blockchain, &account, 20.0, 1).unwrap();
+        assert!(matches!(
+            request(&queue, &blockchain, &account, 0.5, 2),
+            Err(WithdrawalError::InsufficientBalance { .. })
+        ));
+
+        let balance = queue.balance(account.address(), &blockchain);
+        assert_eq!(balance.deposited, 30.0);
+        assert_eq!(balance.available, 0.0);
+
+        // Accounts without deposits have nothing to withdraw
+        let other = Wallet::new().unwrap();
+        assert!(request(&queue, &blockchain, &other, 1.0, 0).is_err());
+    }
+
+    #[test]
+    fn test_approval_workflow() {
+        let blockchain = Blockchain::new();
+        let (queue, audit) = new_queue(0.0);
+        let account = deposit(&blockchain, &queue, 10.0);
+
+        let first = request(&queue, &blockchain, &account, 5.0, 0).unwrap();
+        let second = request(&queue, &blockchain, &account, 5.0, 1).unwrap();
+
+        let approved = queue.approve(&first.id, "alice").unwrap();
+        assert_eq!(approved.status, WithdrawalStatus::Approved);
+        assert_eq!(approved.decided_by.as_deref(), Some("alice"));
+
+        let rejected = queue.reject(&second.id, "bob", "suspicious").unwrap();
+        assert_eq!(rejected.status, WithdrawalStatus::Rejected);
+
+        // Decisions are final
+        assert!(queue.approve(&second.id, "alice").is_err());
+
+        // A rejection gives the amount back
+        assert_eq!(queue.balance(account.address(), &blockchain).available, 5.0);
+
+        // Every step is audited
+        assert_eq!(audit.entries_for(&first.id).len(), 2);
+    }
+
+    #[test]
+    fn test_decisions_compare_and_swap_in_storage() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let storage = Arc::new(BlockchainStorage::new(data_dir.path()).unwrap());
+        let blockchain = Blockchain::new();
+        let audit = AuditLog::new(None).unwrap();
+        let queue = WithdrawalQueue::new(Wallet::new().unwrap(), 0.0, audit, Some(storage.clone())).unwrap();
+        let account = deposit(&blockchain, &queue, 10.0);
+
+        let withdrawal = request(&queue, &blockchain, &account, 5.0, 0).unwrap();
+
+        // Another writer decided the withdrawal in storage first
+        let mut decided = withdrawal.clone();
+        decided.status = WithdrawalStatus::Rejected;
+        assert!(storage.swap_withdrawal(&withdrawal, &decided).unwrap());
+
+        assert!(matches!(
+            queue.approve(&withdrawal.id, "alice"),
+            Err(WithdrawalError::Conflict(_))
+        ));
+        assert!(!storage.swap_withdrawal(&withdrawal, &decided).unwrap());
+    }
+
+    #[test]
+    fn test_submit_approved_batch() {
+        let blockchain = Blockchain::new();
+        let (queue, audit) = new_queue(1000.0);
+        let account = deposit(&blockchain, &queue, 40.0);
+
+        request(&queue, &blockchain, &account, 10.0, 0).unwrap();
+        request(&queue, &blockchain, &account, 20.0, 1).unwrap();
+        request(&queue, &blockchain, &account, 10.0, 2).unwrap();
+
+        // Pay the fees of the first two from the hot wallet, leaving too little for the third
+        let mut hot_wallet = blockchain.get_account_state().get_account(queue.hot_wallet_address());
+        hot_wallet.withdraw(hot_wallet.balance - 30.2).unwrap();
+        blockchain.get_account_state().update_account(hot_wallet);
+
+        let batch = queue.submit_approved(&blockchain).unwrap();
+        let submitted = batch
+            .iter()
+            .filter(|withdrawal| withdrawal.status == WithdrawalStatus::Submitted)
+            .count();
+
+        // The hot wallet can only cover the first two
+        assert_eq!(submitted, 2);
+        assert_eq!(blockchain.get_pending_transactions().len(), 2);
+        assert_eq!(queue.list(Some(WithdrawalStatus::Failed)).len(), 1);
+
+        // Submitted withdrawals point at their pending payout, the failed one is rolled back
+        let pending: Vec<String> = blockchain.get_pending_transactions().into_iter().map(|tx| tx.id).collect();
+        for withdrawal in &batch {
+            let last_action = audit.entries_for(&withdrawal.id).last().unwrap().action.clone();
+            if withdrawal.status == WithdrawalStatus::Submitted {
+                assert!(pending.contains(withdrawal.transaction_id.as_ref().unwrap()));
+                assert_eq!(last_action, "withdrawal.submitted");
+            } else {
+                assert!(withdrawal.transaction_id.is_none());
+                assert!(withdrawal.error.is_some());
+                assert_eq!(last_action, "withdrawal.failed");
+            }
+        }
+
+        // The failed withdrawal gives its amount back
+        assert_eq!(queue.balance(account.address(), &blockchain).available, 10.0);
+    }
+}
@@ -0,0 +1,413 @@
+use futures::stream::{self, Stream};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::api::errors::{ErrorCode, ErrorResponse};
+use crate::api::handlers::{
+    BalanceResponse, BatchSubmitRequest, BatchSubmitResponse, BlockTemplateResponse, ChainResponse, FundWalletRequest,
+    FundWalletResponse, MineRequest, MineResponse, SubmitBlockRequest, TransactionRequest, TransactionResponse,
+    WalletResponse,
+};
+use crate::blockchain::block::BlockHeader;
+use crate::blockchain::mempool::PendingTransaction;
+#[cfg(feature = "stealth")]
+use crate::blockchain::stealth::{ScanKey, StealthPayment};
+use crate::blockchain::{Block, Transaction};
+
+/// How long the node holds a request for the next block before answering that none came
+const SUBSCRIBE_POLL_TIMEOUT: &str = "30s";
+
+/// Errors that can occur while talking to a node
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("HTTP error: {0}")]
+    HttpError(#[from] reqwest::Error),
+
+    /// The node refused the request, `code` is None when the body is not a JSON error
+    #[error("Node answered {status}: {message}")]
+    ApiError {
+        status: u16,
+        code: Option<ErrorCode>,
+        message: String,
+    },
+}
+
+/// A typed client of a node's REST API
+///
+/// The requests and responses are the types the node's handlers use, so a
+/// client built from the same version of this crate always matches the node.
+///
+/// ```no_run
+/// # async fn example() -> Result<(), my_blockchain::client::ClientError> {
+/// use my_blockchain::client::Client;
+///
+/// let client = Client::new("http://localhost:8080");
+/// let wallet = client.create_wallet().await?;
+/// let balance = client.get_balance(&wallet.address).await?;
+/// println!("{} holds {}", balance.address, balance.balance);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Client {
+    /// The HTTP client, which pools connections
+    http: reqwest::Client,
+
+    /// Base URL of the node, without a trailing slash
+    base_url: String,
+}
+
+impl Client {
+    /// Creates a client of a node
+    ///
+    /// # Arguments
+    ///
+    /// * `base_url` - Base URL of the node, e.g. http://localhost:8080
+    pub fn new(base_url: &str) -> Self {
+        Self::with_http_client(base_url, reqwest::Client::new())
+    }
+
+    /// Creates a client of a node using a configured HTTP client, e.g. with timeouts or a proxy
+    ///
+    /// # Arguments
+    ///
+    /// * `base_url` - Base URL of the node
+    /// * `http` - The HTTP client to send requests with
+    pub fn with_http_client(base_url: &str, http: reqwest::Client) -> Self {
+        Client {
+            http,
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Creates a wallet, see POST /api/v1/wallet/new
+    ///
+    /// # Returns
+    ///
+    /// Result with the address and the hex encoded private key, which the node doesn't keep
+    pub async fn create_wallet(&self) -> Result<WalletResponse, ClientError> {
+        self.post("/api/v1/wallet/new", &()).await
+    }
+
+    /// Adds test funds to a wallet, see POST /api/v1/wallet/fund
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The address and the amount
+    ///
+    /// # Returns
+    ///
+    /// Result with the new balance
+    pub async fn fund_wallet(&self, request: &FundWalletRequest) -> Result<FundWalletResponse, ClientError> {
+        self.post("/api/v1/wallet/fund", request).await
+    }
+
+    /// Sends coins, see POST /api/v1/transactions/new
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The transaction, signed by the node with the given private key
+    ///
+    /// # Returns
+    ///
+    /// Result with the index of the block the transaction is expected in
+    pub async fn send(&self, request: &TransactionRequest) -> Result<TransactionResponse, ClientError> {
+        self.post("/api/v1/transactions/new", request).await
+    }
+
+    /// Submits signed transactions, see POST /api/v1/transactions/batch
+    ///
+    /// # Arguments
+    ///
+    /// * `transactions` - The transactions, signed by their senders
+    ///
+    /// # Returns
+    ///
+    /// Result with whether each transaction was accepted, replaced another, queued or rejected
+    pub async fn submit_batch(&self, transactions: Vec<Transaction>) -> Result<BatchSubmitResponse, ClientError> {
+        self.post("/api/v1/transactions/batch", &BatchSubmitRequest { transactions }).await
+    }
+
+    /// Gets the balance and nonce of an address, see GET /api/v1/wallet/balance/{address}
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address
+    pub async fn get_balance(&self, address: &str) -> Result<BalanceResponse, ClientError> {
+        self.get(&format!("/api/v1/wallet/balance/{}", address), &[]).await
+    }
+
+    /// Gets the whole chain, see GET /api/v1/chain
+    pub async fn get_chain(&self) -> Result<ChainResponse, ClientError> {
+        self.get("/api/v1/chain", &[]).await
+    }
+
+    /// Gets block headers with the bloom filters of their addresses, see GET /api/v1/blocks/headers
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - Height of the first block
+    /// * `count` - How many blocks to read, at most 1000
+    /// * `address` - Only blocks whose filter may contain this address, when set
+    pub async fn get_block_headers(
+        &self,
+        from: u64,
+        count: usize,
+        address: Option<&str>,
+    ) -> Result<Vec<BlockHeader>, ClientError> {
+        let mut query = vec![("from", from.to_string()), ("count", count.to_string())];
+        if let Some(address) = address {
+            query.push(("address", address.to_string()));
+        }
+
+        self.get("/api/v1/blocks/headers", &query).await
+    }
+
+    /// Finds the payments to a stealth address, see `ScanKey::scan`
+    ///
+    /// The node never learns which address is scanned for: the whole chain
+    /// is downloaded and scanned here.
+    ///
+    /// # Arguments
+    ///
+    /// * `scan_key` - The scan key of the stealth address
+    /// * `from` - Height of the first block to scan
+    #[cfg(feature = "stealth")]
+    pub async fn scan_stealth_payments(&self, scan_key: &ScanKey, from: u64) -> Result<Vec<StealthPayment>, ClientError> {
+        let chain = self.get_chain().await?.chain;
+        let start = chain.len().min(from as usize);
+        Ok(scan_key.scan(&chain[start..]))
+    }
+
+    /// Gets the pending transactions, see GET /api/v1/transactions/pending
+    pub async fn get_pending_transactions(&self) -> Result<Vec<PendingTransaction>, ClientError> {
+        self.get("/api/v1/transactions/pending", &[]).await
+    }
+
+    /// Mines a block with the pending transactions, see POST /api/v1/mine
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The miner's address and optional coinbase contents
+    pub async fn mine(&self, request: &MineRequest) -> Result<MineResponse, ClientError> {
+        self.post("/api/v1/mine", request).await
+    }
+
+    /// Gets the next block for this process to seal, see GET /api/v1/mining/template
+    ///
+    /// # Arguments
+    ///
+    /// * `miner_address` - The address the coinbase pays
+    pub async fn get_block_template(&self, miner_address: &str) -> Result<BlockTemplateResponse, ClientError> {
+        self.get("/api/v1/mining/template", &[("miner_address", miner_address.to_string())])
+            .await
+    }
+
+    /// Submits the proof found for a template, see POST /api/v1/mining/submit
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The template's ID, the proof and the hash of the sealed block
+    pub async fn submit_block(&self, request: &SubmitBlockRequest) -> Result<MineResponse, ClientError> {
+        self.post("/api/v1/mining/submit", request).await
+    }
+
+    /// Follows the blocks appended to the node's chain
+    ///
+    /// Long-polls GET /api/v1/blocks/next, so blocks arrive as soon as they
+    /// are appended, one after the other. The stream ends after the first
+    /// error, such as a reorganization removing the last block it returned,
+    /// which shows as an ApiError with the STALE_TIP code; the caller can then
+    /// resubscribe from a block it still shares with the node.
+    ///
+    /// # Arguments
+    ///
+    /// * `after_hash` - Hash of the last block the caller has. When None the stream starts at
+    ///   the tip, and a block appended while no request is waiting may be missed until the next
+    ///
+    /// # Returns
+    ///
+    /// The stream of blocks
+    pub fn subscribe_blocks(&self, after_hash: Option<String>) -> impl Stream<Item = Result<Block, ClientError>> {
+        let client = self.clone();
+
+        stream::unfold(Some(after_hash), move |state| {
+            let client = client.clone();
+            async move {
+                let mut after_hash = state?;
+                loop {
+                    let mut query = vec![("timeout", SUBSCRIBE_POLL_TIMEOUT.to_string())];
+                    if let Some(hash) = &after_hash {
+                        query.push(("after_hash", hash.clone()));
+                    }
+
+                    match client.get_optional::<Block>("/api/v1/blocks/next", &query).await {
+                        Ok(Some(block)) => {
+                            after_hash = Some(block.hash.clone());
+                            return Some((Ok(block), Some(after_hash)));
+                        }
+                        // No block before the timeout, wait again
+                        Ok(None) => continue,
+                        Err(err) => return Some((Err(err), None)),
+                    }
+                }
+            }
+        })
+    }
+
+    /// Sends a GET request and decodes the JSON response
+    async fn get<T: DeserializeOwned>(&self, path: &str, query: &[(&str, String)]) -> Result<T, ClientError> {
+        let response = self.http.get(self.url(path)).query(query).send().await?;
+        Self::decode(response).await
+    }
+
+    /// Sends a GET request, a 204 answer giving None
+    async fn get_optional<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<Option<T>, ClientError> {
+        let response = self.http.get(self.url(path)).query(query).send().await?;
+        if response.status() == StatusCode::NO_CONTENT {
+            return Ok(None);
+        }
+
+        Self::decode(response).await.map(Some)
+    }
+
+    /// Sends a POST request with a JSON body and decodes the JSON response
+    async fn post<B: Serialize + ?Sized, T: DeserializeOwned>(&self, path: &str, body: &B) -> Result<T, ClientError> {
+        let response = self.http.post(self.url(path)).json(body).send().await?;
+        Self::decode(response).await
+    }
+
+    /// Decodes a successful response, or turns the node's error into an ApiError
+    async fn decode<T: DeserializeOwned>(response: reqwest::Response) -> Result<T, ClientError> {
+        let status = response.status().as_u16();
+        if response.status().is_success() {
+            return Ok(response.json().await?);
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        Err(match serde_json::from_str::<ErrorResponse>(&body) {
+            Ok(error) => ClientError::ApiError {
+                status,
+                code: Some(error.code),
+                message: error.error,
+            },
+            Err(_) => ClientError::ApiError {
+                status,
+                code: None,
+                message: body,
+            },
+        })
+    }
+
+    /// Gets the URL of an endpoint
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::Blockchain;
+    use actix_web::{web, App, HttpServer};
+    use futures::StreamExt;
+
+    #[actix_web::test]
+    async fn test_client_follows_a_payment_to_a_block() {
+        let blockchain = web::Data::new(Blockchain::new());
+        let limiter = web::Data::new(crate::api::limits::MiningLimiter::new(1));
+        let jobs = web::Data::new(crate::api::mining::MiningJobs::new());
+        let templates = web::Data::new(crate::api::mining::BlockTemplates::new());
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(blockchain.clone())
+                .app_data(limiter.clone())
+                .app_data(jobs.clone())
+                .app_data(templates.clone())
+                .configure(crate::api::configure_routes)
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let port = server.addrs()[0].port();
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let client = Client::new(&format!("http://127.0.0.1:{}/", port));
+        let alice = client.create_wallet().await.unwrap();
+        let bob = client.create_wallet().await.unwrap();
+        let funding = FundWalletRequest {
+            address: alice.address.clone(),
+            amount: 20.0,
+        };
+        assert_eq!(client.fund_wallet(&funding).await.unwrap().new_balance, 20.0);
+
+        let genesis = client.get_chain().await.unwrap().chain[0].hash.clone();
+        let mut blocks = Box::pin(client.subscribe_blocks(Some(genesis)));
+        let transaction = TransactionRequest {
+            sender: alice.address.clone(),
+            recipient: bob.address.clone(),
+            amount: 5.0,
+            fee: 0.5,
+            private_key: alice.private_key.clone(),
+            fee_payer_private_key: None,
+            session: false,
+        };
+        assert_eq!(client.send(&transaction).await.unwrap().block_index, 1);
+        let mine = MineRequest {
+            miner_address: bob.address.clone(),
+            extra_nonce: None,
+            message: None,
+        };
+        client.mine(&mine).await.unwrap();
+
+        let block = blocks.next().await.unwrap().unwrap();
+        assert_eq!(block.index, 1);
+        let headers = client.get_block_headers(0, 10, Some(&alice.address)).await.unwrap();
+        assert_eq!(headers.last().unwrap().hash, block.hash);
+        assert_eq!(client.get_balance(&alice.address).await.unwrap().balance, 14.5);
+        // The miner keeps the tip above the burned base fee
+        let balance = client.get_balance(&bob.address).await.unwrap().balance;
+        assert!((balance - 55.49).abs() < 1e-9);
+
+        // Errors carry the node's code and message
+        let overdraft = TransactionRequest {
+            amount: 100.0,
+            ..transaction
+        };
+        match client.send(&overdraft).await {
+            Err(ClientError::ApiError { status, code, message }) => {
+                assert_eq!(status, 400);
+                assert_eq!(code, Some(ErrorCode::InsufficientFunds));
+                assert!(message.contains("Insufficient funds"));
+            }
+            other => panic!("expected an API error, got {:?}", other.map(|response| response.block_index)),
+        }
+
+        // An external miner gets the next block and the node checks what it submits
+        let template = client.get_block_template(&bob.address).await.unwrap();
+        assert_eq!(template.block.index, 2);
+        assert_eq!(template.block.previous_hash, block.hash);
+        let unsolved = SubmitBlockRequest {
+            template_id: template.template_id,
+            proof: 0,
+            hash: "0".repeat(64),
+        };
+        match client.submit_block(&unsolved).await {
+            Err(ClientError::ApiError { status, code, .. }) => {
+                assert_eq!(status, 400);
+                assert_eq!(code, Some(ErrorCode::InvalidBlock));
+            }
+            other => panic!("expected an API error, got {:?}", other.map(|response| response.block.hash)),
+        }
+
+        handle.stop(true).await;
+    }
+}
@@ -0,0 +1,333 @@
+// Node configuration
+//
+// Settings are read from environment variables so the node can be configured
+// without recompiling. Every setting has a sensible default.
+
+use chrono::{DateTime, Utc};
+use std::env;
+
+use crate::blockchain::fees::FeePolicy;
+use crate::blockchain::genesis::GenesisConfig;
+use crate::blockchain::node_keys::MAX_KEY_OVERLAP_SECS;
+use crate::blockchain::rebroadcast::RebroadcastPolicy;
+use crate::blockchain::storage::FlushPolicy;
+use crate::blockchain::usage::QuotaPolicy;
+
+/// Configuration of a node
+#[derive(Debug, Clone)]
+pub struct NodeConfig {
+    /// Directory where the blockchain data is stored
+    pub data_dir: String,
+
+    /// Host the HTTP server binds to
+    pub host: String,
+
+    /// Port the HTTP server binds to
+    pub port: u16,
+
+    /// Token required by administrative endpoints (disabled when unset)
+    pub admin_token: Option<String>,
+
+    /// Private key (hex encoded) of the hot wallet paying out withdrawals, the
+    /// `withdrawals` keystore wallet is used when unset
+    pub withdrawal_wallet_key: Option<String>,
+
+    /// Withdrawals up to this amount are approved without an operator
+    pub withdrawal_auto_approve_limit: f64,
+
+    /// Seconds between full snapshots of the pending transactions
+    pub mempool_snapshot_interval_secs: u64,
+
+    /// Milliseconds between flushes of the mempool journal
+    pub mempool_journal_interval_ms: u64,
+
+    /// Whether blocks and transactions are compressed on disk
+    pub storage_compression: bool,
+
+    /// When storage writes are flushed to disk
+    pub flush_policy: FlushPolicy,
+
+    /// Seconds a rotated out node key keeps validating signatures
+    pub key_rotation_overlap_secs: u64,
+
+    /// Mining requests that may wait while a block is being mined
+    pub mining_queue_limit: usize,
+
+    /// Whether mining starts from a random proof with a random coinbase extra-nonce
+    pub randomize_proof_of_work: bool,
+
+    /// Threads searching for a proof of work (0 uses one per CPU core)
+    pub mining_threads: usize,
+
+    /// Directory where storage snapshots are kept
+    pub snapshot_dir: String,
+
+    /// Blocks between automatic snapshots (0 disables them)
+    pub snapshot_every_blocks: u64,
+
+    /// Minutes between automatic snapshots (0 disables them)
+    pub snapshot_every_minutes: u64,
+
+    /// Number of snapshots to keep
+    pub snapshot_keep: usize,
+
+    /// Blocks replayed against the stored accounts at startup (0 skips the accounts)
+    pub consistency_check_blocks: u64,
+
+    /// Addresses that approve and revoke others on a permissioned chain
+    pub governors: Vec<String>,
+
+    /// Addresses allowed to send transactions and mine (empty with no governors: everyone)
+    pub allowed_addresses: Vec<String>,
+
+    /// Where the fees of new blocks go, the same on every node of a network
+    pub fee_policy: FeePolicy,
+
+    /// Whether the node only follows blocks mined elsewhere, refusing transactions and mining
+    pub follower: bool,
+
+    /// Name of the network the node joins
+    pub network: String,
+
+    /// Time of the genesis block of the network
+    pub genesis_time: DateTime<Utc>,
+
+    /// Multiaddress the node listens on for peers, e.g. /ip4/0.0.0.0/tcp/9000
+    pub p2p_listen: Option<String>,
+
+    /// Multiaddresses of the bootstrap peers the node connects to
+    pub peers: Vec<String>,
+
+    /// Whether to find peers on the local network over mDNS
+    pub mdns: bool,
+
+    /// Seconds a local transaction stays pending before it is announced to the peers again (0 disables it)
+    pub rebroadcast_interval_secs: u64,
+
+    /// How often a transaction is announced again at most (0 for no limit)
+    pub rebroadcast_limit: u32,
+
+    /// Seconds a transaction may wait in the mempool before it is evicted (0 keeps them until mined)
+    pub mempool_max_age_secs: u64,
+
+    /// Requests an API key may make per calendar month (0 for no limit)
+    pub api_quota: u64,
+
+    /// Monthly quotas of single API keys as key=quota, overriding `api_quota`
+    pub api_key_quotas: Vec<String>,
+
+    /// Address the gRPC server listens on, e.g. 127.0.0.1:50051, with the grpc feature
+    pub grpc_listen: Option<String>,
+
+    /// Address the node mines to in the background, without `/mine` requests (unset disables it)
+    pub auto_mine_address: Option<String>,
+
+    /// Milliseconds between blocks mined in the background, empty ones included (0 mines when transactions are pending)
+    pub auto_mine_interval_ms: u64,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        NodeConfig {
+            data_dir: "data/blockchain".to_string(),
+            host: "127.0.0.1".to_string(),
+            port: 8080,
+            admin_token: None,
+            withdrawal_wallet_key: None,
+            withdrawal_auto_approve_limit: 0.0,
+            mempool_snapshot_interval_secs: 60,
+            mempool_journal_interval_ms: 1000,
+            storage_compression: false,
+            flush_policy: FlushPolicy::default(),
+            key_rotation_overlap_secs: 86400,
+            mining_queue_limit: 4,
+            randomize_proof_of_work: true,
+            mining_threads: 0,
+            snapshot_dir: "data/snapshots".to_string(),
+            snapshot_every_blocks: 0,
+            snapshot_every_minutes: 0,
+            snapshot_keep: 5,
+            consistency_check_blocks: 10,
+            governors: Vec::new(),
+            allowed_addresses: Vec::new(),
+            fee_policy: FeePolicy::default(),
+            follower: false,
+            network: GenesisConfig::default().network,
+            genesis_time: GenesisConfig::default().timestamp,
+            p2p_listen: None,
+            peers: Vec::new(),
+            mdns: false,
+            rebroadcast_interval_secs: 300,
+            rebroadcast_limit: 12,
+            mempool_max_age_secs: 86_400,
+            api_quota: 0,
+            api_key_quotas: Vec::new(),
+            grpc_listen: None,
+            auto_mine_address: None,
+            auto_mine_interval_ms: 0,
+        }
+    }
+}
+
+impl NodeConfig {
+    /// Creates a configuration from environment variables
+    ///
+    /// # Environment variables
+    ///
+    /// * `BLOCKCHAIN_DATA_DIR` - The data directory
+    /// * `BLOCKCHAIN_HOST` - The HTTP host
+    /// * `BLOCKCHAIN_PORT` - The HTTP port
+    /// * `BLOCKCHAIN_ADMIN_TOKEN` - The admin API token
+    /// * `BLOCKCHAIN_WITHDRAWAL_WALLET_KEY` - The withdrawal hot wallet private key
+    /// * `BLOCKCHAIN_WITHDRAWAL_AUTO_APPROVE_LIMIT` - The withdrawal auto-approve limit
+    /// * `BLOCKCHAIN_MEMPOOL_SNAPSHOT_INTERVAL_SECS` - The mempool snapshot interval
+    /// * `BLOCKCHAIN_MEMPOOL_JOURNAL_INTERVAL_MS` - The mempool journal flush interval
+    /// * `BLOCKCHAIN_STORAGE_COMPRESSION` - Whether to compress stored blocks (true/false)
+    /// * `BLOCKCHAIN_FLUSH_POLICY` - When writes are flushed: every-write, on-block-commit or interval:<ms>
+    /// * `BLOCKCHAIN_KEY_ROTATION_OVERLAP_SECS` - How long rotated out node keys stay valid
+    /// * `BLOCKCHAIN_MINING_QUEUE_LIMIT` - How many mining requests may wait for the running one
+    /// * `BLOCKCHAIN_RANDOMIZE_POW` - Whether to randomize the proof search (true/false)
+    /// * `BLOCKCHAIN_MINING_THREADS` - Threads searching for a proof of work, 0 for one per CPU core
+    /// * `BLOCKCHAIN_SNAPSHOT_DIR` - The snapshot directory
+    /// * `BLOCKCHAIN_SNAPSHOT_EVERY_BLOCKS` - Blocks between automatic snapshots
+    /// * `BLOCKCHAIN_SNAPSHOT_EVERY_MINUTES` - Minutes between automatic snapshots
+    /// * `BLOCKCHAIN_SNAPSHOT_KEEP` - How many snapshots to keep
+    /// * `BLOCKCHAIN_CONSISTENCY_CHECK_BLOCKS` - Blocks replayed against the stored accounts at startup
+    /// * `BLOCKCHAIN_GOVERNORS` - Comma separated governor addresses of a permissioned chain
+    /// * `BLOCKCHAIN_ALLOWED_ADDRESSES` - Comma separated addresses allowed on a permissioned chain
+    /// * `BLOCKCHAIN_FEE_POLICY` - Where fees go: miner, burn or treasury
+    /// * `BLOCKCHAIN_TREASURY_SHARE` - Share of the fees the treasury gets, the rest goes to the miner
+    /// * `BLOCKCHAIN_TREASURY_REWARD_SHARE` - Share of the mining reward the treasury gets
+    /// * `BLOCKCHAIN_FOLLOWER` - Whether the node is a read-only follower (true/false)
+    /// * `BLOCKCHAIN_NETWORK` - Name of the network
+    /// * `BLOCKCHAIN_GENESIS_TIME` - RFC 3339 time of the network's genesis block
+    /// * `BLOCKCHAIN_P2P_LISTEN` - Multiaddress to listen on for peers
+    /// * `BLOCKCHAIN_PEERS` - Comma separated multiaddresses of bootstrap peers
+    /// * `BLOCKCHAIN_MDNS` - Whether to find peers on the local network (true/false)
+    /// * `BLOCKCHAIN_REBROADCAST_INTERVAL_SECS` - Seconds before a stuck local transaction is announced again
+    /// * `BLOCKCHAIN_REBROADCAST_LIMIT` - How often a transaction is announced again at most
+    /// * `BLOCKCHAIN_API_QUOTA` - Requests an API key may make per calendar month
+    /// * `BLOCKCHAIN_API_KEY_QUOTAS` - Comma separated key=quota monthly quotas of single API keys
+    /// * `BLOCKCHAIN_GRPC_LISTEN` - Address to serve gRPC on, with the grpc feature
+    /// * `BLOCKCHAIN_AUTO_MINE_ADDRESS` - Address to mine to in the background
+    /// * `BLOCKCHAIN_AUTO_MINE_INTERVAL_MS` - Milliseconds between blocks mined in the background, 0 when transactions are pending
+    pub fn from_env() -> Self {
+        let defaults = NodeConfig::default();
+
+        NodeConfig {
+            data_dir: env::var("BLOCKCHAIN_DATA_DIR").unwrap_or(defaults.data_dir),
+            host: env::var("BLOCKCHAIN_HOST").unwrap_or(defaults.host),
+            port: parse_var("BLOCKCHAIN_PORT").unwrap_or(defaults.port),
+            admin_token: env::var("BLOCKCHAIN_ADMIN_TOKEN").ok().filter(|token| !token.is_empty()),
+            withdrawal_wallet_key: env::var("BLOCKCHAIN_WITHDRAWAL_WALLET_KEY").ok(),
+            withdrawal_auto_approve_limit: parse_var("BLOCKCHAIN_WITHDRAWAL_AUTO_APPROVE_LIMIT")
+                .unwrap_or(defaults.withdrawal_auto_approve_limit),
+            mempool_snapshot_interval_secs: parse_var("BLOCKCHAIN_MEMPOOL_SNAPSHOT_INTERVAL_SECS")
+                .filter(|secs| *secs > 0)
+                .unwrap_or(defaults.mempool_snapshot_interval_secs),
+            mempool_journal_interval_ms: parse_var("BLOCKCHAIN_MEMPOOL_JOURNAL_INTERVAL_MS")
+                .filter(|ms| *ms > 0)
+                .unwrap_or(defaults.mempool_journal_interval_ms),
+            storage_compression: parse_var("BLOCKCHAIN_STORAGE_COMPRESSION")
+                .unwrap_or(defaults.storage_compression),
+            flush_policy: parse_var("BLOCKCHAIN_FLUSH_POLICY").unwrap_or(defaults.flush_policy),
+            key_rotation_overlap_secs: parse_var("BLOCKCHAIN_KEY_ROTATION_OVERLAP_SECS")
+                .filter(|secs| *secs <= MAX_KEY_OVERLAP_SECS)
+                .unwrap_or(defaults.key_rotation_overlap_secs),
+            mining_queue_limit: parse_var("BLOCKCHAIN_MINING_QUEUE_LIMIT")
+                .unwrap_or(defaults.mining_queue_limit),
+            randomize_proof_of_work: parse_var("BLOCKCHAIN_RANDOMIZE_POW")
+                .unwrap_or(defaults.randomize_proof_of_work),
+            mining_threads: parse_var("BLOCKCHAIN_MINING_THREADS").unwrap_or(defaults.mining_threads),
+            snapshot_dir: env::var("BLOCKCHAIN_SNAPSHOT_DIR").unwrap_or(defaults.snapshot_dir),
+            snapshot_every_blocks: parse_var("BLOCKCHAIN_SNAPSHOT_EVERY_BLOCKS")
+                .unwrap_or(defaults.snapshot_every_blocks),
+            snapshot_every_minutes: parse_var("BLOCKCHAIN_SNAPSHOT_EVERY_MINUTES")
+                .unwrap_or(defaults.snapshot_every_minutes),
+            snapshot_keep: parse_var("BLOCKCHAIN_SNAPSHOT_KEEP")
+                .filter(|keep| *keep > 0)
+                .unwrap_or(defaults.snapshot_keep),
+            consistency_check_blocks: parse_var("BLOCKCHAIN_CONSISTENCY_CHECK_BLOCKS")
+                .unwrap_or(defaults.consistency_check_blocks),
+            governors: list_var("BLOCKCHAIN_GOVERNORS"),
+            allowed_addresses: list_var("BLOCKCHAIN_ALLOWED_ADDRESSES"),
+            fee_policy: env::var("BLOCKCHAIN_FEE_POLICY")
+                .ok()
+                .and_then(|name| {
+                    let share = parse_var("BLOCKCHAIN_TREASURY_SHARE").unwrap_or(1.0);
+                    let reward_share = parse_var("BLOCKCHAIN_TREASURY_REWARD_SHARE").unwrap_or(0.0);
+                    FeePolicy::from_config(&name, share, reward_share).ok()
+                })
+                .unwrap_or(defaults.fee_policy),
+            follower: parse_var("BLOCKCHAIN_FOLLOWER").unwrap_or(defaults.follower),
+            network: env::var("BLOCKCHAIN_NETWORK")
+                .ok()
+                .filter(|network| !network.is_empty())
+                .unwrap_or(defaults.network),
+            genesis_time: env::var("BLOCKCHAIN_GENESIS_TIME")
+                .ok()
+                .and_then(|time| DateTime::parse_from_rfc3339(&time).ok())
+                .map(|time| time.with_timezone(&Utc))
+                .unwrap_or(defaults.genesis_time),
+            p2p_listen: env::var("BLOCKCHAIN_P2P_LISTEN").ok().filter(|address| !address.is_empty()),
+            peers: list_var("BLOCKCHAIN_PEERS"),
+            mdns: parse_var("BLOCKCHAIN_MDNS").unwrap_or(defaults.mdns),
+            rebroadcast_interval_secs: parse_var("BLOCKCHAIN_REBROADCAST_INTERVAL_SECS")
+                .unwrap_or(defaults.rebroadcast_interval_secs),
+            rebroadcast_limit: parse_var("BLOCKCHAIN_REBROADCAST_LIMIT").unwrap_or(defaults.rebroadcast_limit),
+            mempool_max_age_secs: parse_var("BLOCKCHAIN_MEMPOOL_MAX_AGE_SECS").unwrap_or(defaults.mempool_max_age_secs),
+            api_quota: parse_var("BLOCKCHAIN_API_QUOTA").unwrap_or(defaults.api_quota),
+            api_key_quotas: list_var("BLOCKCHAIN_API_KEY_QUOTAS"),
+            grpc_listen: env::var("BLOCKCHAIN_GRPC_LISTEN").ok().filter(|address| !address.is_empty()),
+            auto_mine_address: env::var("BLOCKCHAIN_AUTO_MINE_ADDRESS").ok().filter(|address| !address.is_empty()),
+            auto_mine_interval_ms: parse_var("BLOCKCHAIN_AUTO_MINE_INTERVAL_MS").unwrap_or(defaults.auto_mine_interval_ms),
+        }
+    }
+
+    /// Whether the node connects to peers over the P2P network
+    pub fn p2p_enabled(&self) -> bool {
+        self.p2p_listen.is_some() || !self.peers.is_empty() || self.mdns
+    }
+
+    /// Gets when stuck local transactions are announced to the peers again, None if never
+    pub fn rebroadcast_policy(&self) -> Option<RebroadcastPolicy> {
+        (self.rebroadcast_interval_secs > 0).then(|| RebroadcastPolicy {
+            interval: chrono::Duration::seconds(self.rebroadcast_interval_secs as i64),
+            max_rebroadcasts: self.rebroadcast_limit,
+        })
+    }
+
+    /// Gets how long a transaction may wait in the mempool, None if it waits until mined
+    pub fn mempool_max_age(&self) -> Option<chrono::Duration> {
+        (self.mempool_max_age_secs > 0).then(|| chrono::Duration::seconds(self.mempool_max_age_secs as i64))
+    }
+
+    /// Gets how many requests API keys may make per month
+    pub fn quota_policy(&self) -> QuotaPolicy {
+        QuotaPolicy::from_config(self.api_quota, &self.api_key_quotas)
+    }
+
+    /// Gets the network the node joins, which every node of it must agree on
+    pub fn genesis(&self) -> GenesisConfig {
+        GenesisConfig {
+            network: self.network.clone(),
+            timestamp: self.genesis_time,
+        }
+    }
+}
+
+/// Parses an environment variable, ignoring it when missing or malformed
+fn parse_var<T: std::str::FromStr>(name: &str) -> Option<T> {
+    env::var(name).ok().and_then(|value| value.parse().ok())
+}
+
+/// Splits a comma separated environment variable, empty when missing
+fn list_var(name: &str) -> Vec<String> {
+    env::var(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
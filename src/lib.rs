@@ -0,0 +1,13 @@
+// Library crate
+//
+// Exposes the blockchain core, the REST API and the peer-to-peer network so
+// that they can be used by the node binary as well as by other crates
+// embedding a node, and with the client feature a typed HTTP client for
+// crates talking to a node
+
+pub mod api;
+pub mod blockchain;
+#[cfg(feature = "client")]
+pub mod client;
+pub mod config;
+pub mod network;
@@ -1,46 +1,141 @@
 use actix_cors::Cors;
 use actix_web::{middleware, web, App, HttpServer};
+use clap::{Parser, Subcommand};
 use log::{info, warn};
-use utoipa::OpenApi;
-use utoipa_swagger_ui::SwaggerUi;
 use std::path::Path;
+use std::time::Duration;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+use utoipa_swagger_ui::SwaggerUi;
 
-mod api;
-mod blockchain;
+use my_blockchain::config::NodeConfig;
+use my_blockchain::{api, blockchain, network};
 
 // Initialize the blockchain with a genesis block and some initial accounts
-fn initialize_blockchain() -> blockchain::Blockchain {
-    // Try to initialize blockchain with storage
-    let data_dir = "data/blockchain";
+fn initialize_blockchain(config: &NodeConfig) -> std::io::Result<blockchain::Blockchain> {
+    let data_dir = config.data_dir.as_str();
+    let options = blockchain::storage::StorageOptions {
+        compress: config.storage_compression,
+        flush_policy: config.flush_policy,
+    };
 
     // Create data directory if it doesn't exist
     std::fs::create_dir_all(data_dir).unwrap_or_else(|e| {
         warn!("Failed to create data directory: {}", e);
     });
 
+    let policy = blockchain::permissions::PermissionPolicy {
+        governors: config.governors.iter().cloned().map(blockchain::Address).collect(),
+        allowed: config.allowed_addresses.iter().cloned().map(blockchain::Address).collect(),
+    };
+    if !policy.is_open() {
+        info!(
+            "Permissioned mode: {} governors, {} allowed addresses",
+            policy.governors.len(),
+            policy.allowed.len()
+        );
+    }
+    if config.follower {
+        info!("Follower mode: blocks come from other nodes, transactions and mining are refused");
+    }
+    info!("Fee policy: {}", config.fee_policy);
+    let builder = blockchain::builder::NodeBuilder::new()
+        .with_genesis(config.genesis())
+        .with_randomized_proof_of_work(config.randomize_proof_of_work)
+        .with_mining_threads(config.mining_threads)
+        .as_follower(config.follower)
+        .with_fee_policy(config.fee_policy.clone())
+        .with_permission_policy(policy);
+
     // Try to load blockchain from storage
-    match blockchain::Blockchain::with_storage(data_dir) {
+    info!("Network: {} ({})", config.network, config.genesis().magic());
+    let blockchain = match builder.clone().with_storage_options(data_dir, options).build() {
         Ok(blockchain) => {
             info!("Loaded blockchain from storage at {}", data_dir);
 
             // Create a wallet for testing if not already created
-            create_test_wallet(&blockchain);
+            if !config.follower {
+                create_test_wallet(&blockchain);
+            }
 
             blockchain
         },
+        // Never run on the data of another network, not even in memory beside it
+        Err(err @ blockchain::chain::BlockchainError::StorageError(
+            blockchain::storage::StorageError::NetworkMismatch { .. },
+        )) => {
+            return Err(std::io::Error::other(format!("Refusing to open {}: {}", data_dir, err)));
+        }
         Err(err) => {
             warn!("Failed to load blockchain from storage: {}", err);
             warn!("Creating in-memory blockchain instead");
 
             // Create in-memory blockchain
-            let blockchain = blockchain::Blockchain::new();
+            let blockchain = builder.build().map_err(|err| std::io::Error::other(err.to_string()))?;
 
             // Create a wallet for testing
-            create_test_wallet(&blockchain);
+            if !config.follower {
+                create_test_wallet(&blockchain);
+            }
 
             blockchain
         }
+    };
+
+    Ok(blockchain)
+}
+
+/// Locks a data directory against other processes for as long as the lock is held
+///
+/// # Arguments
+///
+/// * `data_dir` - The data directory
+fn lock_data_dir(data_dir: &str) -> std::io::Result<blockchain::lockfile::DataDirLock> {
+    blockchain::lockfile::DataDirLock::acquire(data_dir).map_err(|err| std::io::Error::other(err.to_string()))
+}
+
+/// Checks the stored chain before it is loaded, repairing it if asked to
+///
+/// # Arguments
+///
+/// * `config` - The node configuration
+/// * `repair` - Whether to truncate an inconsistent chain instead of refusing to start
+fn check_storage_consistency(config: &NodeConfig, repair: bool) -> std::io::Result<()> {
+    use blockchain::consistency;
+
+    // A new node has nothing to check
+    if !Path::new(&config.data_dir).join("db").exists() {
+        return Ok(());
+    }
+
+    let options = blockchain::storage::StorageOptions {
+        compress: config.storage_compression,
+        flush_policy: config.flush_policy,
+    };
+    let error = |err: String| std::io::Error::other(format!("Storage consistency check failed: {}", err));
+    let storage = blockchain::storage::BlockchainStorage::open(&config.data_dir, options)
+        .map_err(|err| error(err.to_string()))?;
+
+    let report = consistency::check_storage(&storage, config.consistency_check_blocks)
+        .map_err(|err| error(err.to_string()))?;
+    if report.is_consistent() {
+        info!("Stored chain is consistent up to height {}", report.consistent_height.unwrap_or(0));
+        return Ok(());
     }
+
+    warn!("{}", report);
+    if !repair {
+        let hint = match report.consistent_height {
+            Some(height) => format!("start with --repair to truncate it to height {}", height),
+            None => "restore a snapshot or reset the chain".to_string(),
+        };
+        return Err(error(format!("the stored chain is inconsistent, {}", hint)));
+    }
+
+    let repaired = consistency::repair_storage(&storage, config.consistency_check_blocks)
+        .map_err(|err| error(err.to_string()))?;
+    warn!("{}", repaired);
+    Ok(())
 }
 
 // Create a test wallet with initial funds
@@ -55,10 +150,9 @@ fn create_test_wallet(blockchain: &blockchain::Blockchain) -> Option<blockchain:
             info!("Test wallet private key: {}", private_key_hex);
 
             // Add some initial funds to the wallet
-            let mut account = blockchain.get_account_state().get_account(wallet.address());
-            if let Ok(_) = account.deposit(1000.0) {
-                blockchain.get_account_state().update_account(account);
-                info!("Added 1000 coins to test wallet");
+            match blockchain.fund_account(wallet.address(), 1000.0) {
+                Ok(_) => info!("Added 1000 coins to test wallet"),
+                Err(err) => warn!("Failed to fund test wallet: {}", err),
             }
 
             Some(wallet)
@@ -70,36 +164,324 @@ fn create_test_wallet(blockchain: &blockchain::Blockchain) -> Option<blockchain:
     }
 }
 
+// Keystore name of the withdrawal hot wallet when no key is configured
+const HOT_WALLET_NAME: &str = "withdrawals";
+
+// Load the withdrawal hot wallet from the configured key, or from the keystore
+// where it is generated on first start so deposits aren't lost on restart
+fn load_hot_wallet(config: &NodeConfig, keystore: &blockchain::keystore::Keystore) -> blockchain::Wallet {
+    let wallet = match &config.withdrawal_wallet_key {
+        Some(key) => hex::decode(key)
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| blockchain::Wallet::from_secret_key(&bytes).map_err(|e| e.to_string()))
+            .unwrap_or_else(|err| panic!("Invalid withdrawal wallet key: {}", err)),
+        None => match keystore.signer(HOT_WALLET_NAME) {
+            Ok(wallet) => wallet,
+            Err(blockchain::keystore::KeystoreError::NotFound(_)) => {
+                let wallet = blockchain::Wallet::new().expect("failed to create withdrawal wallet");
+                keystore
+                    .add_wallet(HOT_WALLET_NAME, &wallet)
+                    .expect("failed to store withdrawal wallet in keystore");
+                info!("No withdrawal wallet configured, created keystore wallet '{}'", HOT_WALLET_NAME);
+                wallet
+            }
+            Err(err) => panic!("Failed to load withdrawal wallet from keystore: {}", err),
+        },
+    };
+
+    info!("Withdrawal hot wallet address: {}", wallet.address());
+    wallet
+}
+
+// Registers the bearer token scheme used by administrative endpoints
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "admin_token",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            );
+        }
+    }
+}
+
 #[derive(OpenApi)]
 #[openapi(
     paths(
         api::handlers::get_chain,
         api::handlers::get_pending_transactions,
         api::handlers::new_transaction,
+        api::handlers::submit_transaction_batch,
+        api::handlers::rebroadcast_transaction,
         api::handlers::mine_block,
+        api::handlers::get_mining_job,
+        api::handlers::get_block_template,
+        api::handlers::submit_block,
         api::handlers::validate_chain,
         api::handlers::create_wallet,
         api::handlers::fund_wallet,
         api::handlers::get_wallet_balance,
-        api::handlers::get_all_accounts
+        api::handlers::get_wallet_nonce,
+        api::handlers::get_all_accounts,
+        api::handlers::create_payment,
+        api::handlers::create_invoice,
+        api::handlers::get_payment,
+        api::handlers::request_withdrawal,
+        api::handlers::get_withdrawal_balance,
+        api::handlers::get_withdrawal,
+        api::handlers::list_withdrawals,
+        api::handlers::approve_withdrawal,
+        api::handlers::reject_withdrawal,
+        api::handlers::submit_withdrawals,
+        api::handlers::register_schedule,
+        api::handlers::list_schedules,
+        api::handlers::get_schedule,
+        api::handlers::cancel_schedule,
+        api::handlers::get_audit_log,
+        api::handlers::get_usage,
+        api::handlers::list_keystore_wallets,
+        api::handlers::add_keystore_wallet,
+        api::handlers::add_watch_only_wallet,
+        api::handlers::get_keystore_wallet,
+        api::handlers::send_from_keystore_wallet,
+        api::handlers::backup_keystore,
+        api::handlers::restore_keystore,
+        api::handlers::set_transaction_label,
+        api::handlers::get_transaction_label,
+        api::handlers::export_address_transactions,
+        api::handlers::get_address_counterparties,
+        api::handlers::export_transaction_graph,
+        api::handlers::get_activity_report,
+        api::handlers::get_reorgs,
+        api::handlers::get_maintenance,
+        api::handlers::pause_node,
+        api::handlers::resume_node,
+        api::handlers::reset_chain,
+        api::handlers::append_block,
+        api::handlers::list_snapshots,
+        api::handlers::get_storage_stats,
+        api::handlers::take_snapshot,
+        api::handlers::restore_snapshot,
+        api::handlers::list_node_keys,
+        api::handlers::rotate_node_key,
+        api::handlers::get_block_at,
+        api::handlers::get_next_block,
+        api::subscriptions::subscribe,
+        api::events::stream,
+        api::handlers::get_raw_block,
+        api::handlers::get_block_headers,
+        api::handlers::verify_hash,
+        api::handlers::simulate_difficulty,
+        api::handlers::simulate_attack,
+        api::handlers::query_transactions,
+        api::handlers::get_name,
+        api::handlers::register_name,
+        api::handlers::anchor_document,
+        api::handlers::get_anchor,
+        api::handlers::create_lock,
+        api::handlers::get_lock,
+        api::handlers::spend_lock,
+        api::handlers::get_address_locks,
+        api::handlers::authorize_session_key,
+        api::handlers::revoke_session_key,
+        api::handlers::get_address_session_keys,
+        api::handlers::designate_beneficiary,
+        api::handlers::cancel_inheritance,
+        api::handlers::claim_inheritance,
+        api::handlers::get_inheritance,
+        api::handlers::get_address_inheritances,
+        api::handlers::get_permissions,
+        api::handlers::change_permission,
+        api::handlers::list_freezes,
+        api::handlers::freeze_account,
+        api::handlers::get_chain_parameters,
+        api::handlers::list_proposals,
+        api::handlers::get_proposal,
+        api::handlers::create_proposal,
+        api::handlers::vote_on_proposal,
+        api::handlers::propose_disbursement,
+        api::handlers::get_fee_estimate,
+        api::handlers::get_supply,
+        api::handlers::export_ledger,
+        api::handlers::get_economics,
+        api::handlers::get_dashboard,
+        api::handlers::get_treasury,
+        api::handlers::get_network,
+        api::handlers::get_peers,
+        api::handlers::add_peer,
+        api::handlers::remove_peer
     ),
     components(
         schemas(
             blockchain::Block,
             blockchain::Transaction,
+            blockchain::transaction::FeePayer,
             blockchain::crypto::Address,
             blockchain::crypto::DigitalSignature,
             api::schema::DateTimeUtc,
             api::handlers::ChainResponse,
             api::handlers::TransactionRequest,
             api::handlers::TransactionResponse,
+            api::handlers::BatchSubmitRequest,
+            api::handlers::BatchSubmitResponse,
+            api::handlers::SubmissionResult,
+            api::handlers::SubmissionStatus,
             api::handlers::MineRequest,
             api::handlers::MineResponse,
+            api::mining::MiningJob,
+            api::mining::MiningJobStatus,
+            api::handlers::BlockTemplateResponse,
+            api::handlers::SubmitBlockRequest,
             api::handlers::WalletResponse,
             api::handlers::FundWalletRequest,
-            api::handlers::AccountResponse
+            api::handlers::FundWalletResponse,
+            api::handlers::BalanceResponse,
+            api::errors::ErrorCode,
+            api::errors::ErrorContext,
+            api::errors::ErrorResponse,
+            api::handlers::NonceResponse,
+            api::handlers::AccountResponse,
+            api::handlers::PaymentRequest,
+            api::handlers::InvoiceRequest,
+            blockchain::payments::Payment,
+            blockchain::payments::Invoice,
+            blockchain::payments::PaymentStatus,
+            blockchain::payments::PaymentReport,
+            api::handlers::WithdrawalRequest,
+            api::handlers::WithdrawalDecisionRequest,
+            blockchain::withdrawals::Withdrawal,
+            blockchain::withdrawals::WithdrawalStatus,
+            blockchain::withdrawals::WithdrawalBalance,
+            api::handlers::ScheduleRequest,
+            api::handlers::CancelScheduleRequest,
+            blockchain::schedules::Schedule,
+            blockchain::schedules::ScheduleTerms,
+            blockchain::schedules::ScheduleStatus,
+            blockchain::audit::AuditEntry,
+            blockchain::usage::KeyUsage,
+            blockchain::usage::DailyUsage,
+            api::handlers::KeystoreWalletRequest,
+            api::handlers::WatchOnlyWalletRequest,
+            api::handlers::KeystoreSendRequest,
+            api::handlers::HistoryEntry,
+            api::handlers::KeystoreWalletResponse,
+            blockchain::keystore::WalletInfo,
+            api::handlers::KeystoreBackupRequest,
+            api::handlers::KeystoreBackupResponse,
+            api::handlers::KeystoreRestoreRequest,
+            blockchain::backup::EncryptedBackup,
+            api::handlers::LabelRequest,
+            blockchain::labels::TransactionLabel,
+            blockchain::reports::Granularity,
+            blockchain::reports::ActivityPeriod,
+            blockchain::events::ReorgEvent,
+            blockchain::index::IndexedTransaction,
+            blockchain::index::TransactionPage,
+            blockchain::index::CounterpartySummary,
+            blockchain::graph::GraphFormat,
+            blockchain::graph::GraphNode,
+            blockchain::graph::GraphEdge,
+            blockchain::graph::TransactionGraph,
+            blockchain::maintenance::MaintenanceStatus,
+            api::handlers::PauseRequest,
+            api::handlers::ResumeRequest,
+            api::handlers::ResetRequest,
+            api::handlers::SnapshotRequest,
+            blockchain::snapshots::SnapshotInfo,
+            blockchain::storage::StorageStats,
+            blockchain::storage::TreeStats,
+            blockchain::storage::CacheStats,
+            blockchain::snapshots::SnapshotTrigger,
+            blockchain::node_keys::KeyPurpose,
+            blockchain::node_keys::NodeKeyInfo,
+            blockchain::node_keys::KeyRingInfo,
+            api::handlers::RotateKeyRequest,
+            blockchain::simulation::ConsensusRules,
+            blockchain::simulation::SimulationStep,
+            blockchain::simulation::SimulationReport,
+            blockchain::attack::AttackParameters,
+            blockchain::attack::Race,
+            blockchain::attack::ReorgDemonstration,
+            blockchain::attack::AttackReport,
+            api::handlers::RawBlockResponse,
+            api::handlers::RawTransactionResponse,
+            blockchain::block::BlockHeader,
+            api::handlers::NameRegistrationRequest,
+            blockchain::names::NameRecord,
+            api::handlers::AnchorRequest,
+            blockchain::anchors::AnchorProof,
+            blockchain::merkle::MerkleProof,
+            blockchain::merkle::ProofStep,
+            blockchain::merkle::Side,
+            api::handlers::LockRequest,
+            api::handlers::LockResponse,
+            api::handlers::SpendLockRequest,
+            blockchain::scripts::Condition,
+            blockchain::scripts::ScriptAction,
+            blockchain::scripts::ScriptLock,
+            blockchain::scripts::Witness,
+            blockchain::scripts::WitnessSignature,
+            api::handlers::SessionKeyRequest,
+            api::handlers::RevokeSessionKeyRequest,
+            blockchain::sessions::SessionAction,
+            blockchain::sessions::SessionKey,
+            api::handlers::InheritanceRequest,
+            api::handlers::CancelInheritanceRequest,
+            api::handlers::ClaimInheritanceRequest,
+            blockchain::inheritance::InheritanceAction,
+            blockchain::inheritance::InheritanceSwitch,
+            api::handlers::PermissionChangeRequest,
+            blockchain::permissions::PermissionChange,
+            blockchain::permissions::PermissionStatus,
+            api::handlers::FreezeRequest,
+            blockchain::permissions::FreezeOrder,
+            blockchain::permissions::FreezeRecord,
+            api::handlers::ParametersResponse,
+            api::handlers::ProposalRequest,
+            api::handlers::VoteRequest,
+            api::handlers::DisbursementRequest,
+            blockchain::governance::ChainParameters,
+            blockchain::governance::Parameter,
+            blockchain::governance::GovernanceAction,
+            blockchain::governance::Proposal,
+            blockchain::governance::ProposedChange,
+            blockchain::governance::ProposalStatus,
+            blockchain::governance::ParameterChange,
+            blockchain::fees::FeePolicy,
+            blockchain::fees::TreasuryPayout,
+            blockchain::fees::FeeEstimate,
+            blockchain::fees::SupplyReport,
+            blockchain::ledger::LedgerFormat,
+            blockchain::ledger::EntryKind,
+            blockchain::ledger::LedgerLine,
+            blockchain::economics::EmissionPeriod,
+            blockchain::economics::EconomicsReport,
+            blockchain::dashboard::Dashboard,
+            blockchain::dashboard::TipStatus,
+            blockchain::dashboard::MempoolStatus,
+            blockchain::dashboard::BlockIntervals,
+            blockchain::dashboard::IssuanceRate,
+            blockchain::dashboard::RecentBlockFees,
+            blockchain::dashboard::FeeTrend,
+            blockchain::dashboard::NetworkStatus,
+            blockchain::storage::StorageHealth,
+            blockchain::treasury::Disbursement,
+            blockchain::treasury::DisbursementStatus,
+            blockchain::treasury::TreasuryStatus,
+            blockchain::genesis::NetworkInfo,
+            blockchain::peers::PeerInfo,
+            blockchain::peers::PeerSource,
+            api::handlers::AddPeerRequest,
+            api::handlers::AddPeerResponse,
+            blockchain::rebroadcast::BroadcastRecord,
+            blockchain::mempool::PendingTransaction,
+            api::handlers::VerifyHashRequest,
+            api::handlers::VerifyHashResponse
         )
     ),
+    modifiers(&SecurityAddon),
     tags(
         (name = "blockchain", description = "Blockchain API endpoints")
     ),
@@ -119,17 +501,795 @@ fn create_test_wallet(blockchain: &blockchain::Blockchain) -> Option<blockchain:
 )]
 struct ApiDoc;
 
+/// Periodically persists the pending transactions
+///
+/// The journal is flushed often and cheaply, while full snapshots are
+/// written less frequently and truncate the journal.
+///
+/// # Arguments
+///
+/// * `blockchain` - The blockchain whose mempool to persist
+/// * `config` - The node configuration with the intervals
+fn spawn_mempool_persistence(blockchain: web::Data<blockchain::Blockchain>, config: &NodeConfig) {
+    let mut journal_interval = tokio::time::interval(Duration::from_millis(config.mempool_journal_interval_ms));
+    let mut snapshot_interval = tokio::time::interval(Duration::from_secs(config.mempool_snapshot_interval_secs));
+
+    tokio::spawn(async move {
+        loop {
+            // Waiting for the storage writer happens off the async runtime
+            let chain = blockchain.clone();
+            tokio::select! {
+                _ = journal_interval.tick() => match web::block(move || chain.flush_mempool_journal()).await {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(err)) => warn!("Failed to flush mempool journal: {}", err),
+                    Err(err) => warn!("Failed to flush mempool journal: {}", err),
+                },
+                _ = snapshot_interval.tick() => match web::block(move || chain.persist_mempool()).await {
+                    Ok(Ok(_)) => {}
+                    Ok(Err(err)) => warn!("Failed to persist mempool: {}", err),
+                    Err(err) => warn!("Failed to persist mempool: {}", err),
+                },
+            }
+        }
+    });
+}
+
+/// Periodically announces the local transactions stuck in the mempool to the peers again
+///
+/// # Arguments
+///
+/// * `blockchain` - The blockchain whose pending transactions to announce
+/// * `policy` - When transactions are announced again
+fn spawn_rebroadcasts(blockchain: web::Data<blockchain::Blockchain>, policy: blockchain::rebroadcast::RebroadcastPolicy) {
+    let period = policy.interval.to_std().unwrap_or(Duration::from_secs(1));
+    let mut schedule = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+
+    tokio::spawn(async move {
+        loop {
+            schedule.tick().await;
+            let announced = blockchain.rebroadcast_due(&policy);
+            if !announced.is_empty() {
+                info!("Announced {} stuck transactions to the peers again", announced.len());
+            }
+        }
+    });
+}
+
+/// Longest time between two checks of the mempool for expired transactions
+const MEMPOOL_EVICTION_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Periodically evicts the transactions that have waited in the mempool too long
+///
+/// # Arguments
+///
+/// * `blockchain` - The blockchain whose pending transactions to check
+/// * `max_age` - How long a transaction may wait in the mempool
+fn spawn_mempool_eviction(blockchain: web::Data<blockchain::Blockchain>, max_age: chrono::Duration) {
+    let period = max_age.to_std().unwrap_or(Duration::from_secs(1)).min(MEMPOOL_EVICTION_INTERVAL);
+    let mut schedule = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+
+    tokio::spawn(async move {
+        loop {
+            schedule.tick().await;
+            let evicted = blockchain.evict_expired(max_age);
+            if !evicted.is_empty() {
+                info!("Evicted {} transactions older than {}s from the mempool", evicted.len(), max_age.num_seconds());
+            }
+        }
+    });
+}
+
+/// Submits the scheduled payments that fall due with each new block
+///
+/// # Arguments
+///
+/// * `blockchain` - The blockchain to follow and submit the payments to
+/// * `scheduler` - The registered schedules
+fn spawn_scheduled_payments(
+    blockchain: web::Data<blockchain::Blockchain>,
+    scheduler: web::Data<blockchain::schedules::Scheduler>,
+) {
+    use blockchain::events::ChainEvent;
+    use tokio::sync::broadcast::error::RecvError;
+
+    let mut events = blockchain.subscribe();
+
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(ChainEvent::BlockAdded { .. } | ChainEvent::Reorg(_) | ChainEvent::Restored { .. })
+                | Err(RecvError::Lagged(_)) => {}
+                Ok(_) => continue,
+                Err(RecvError::Closed) => return,
+            }
+
+            let (chain, schedules) = (blockchain.clone(), scheduler.clone());
+            match web::block(move || schedules.submit_due(&chain)).await {
+                Ok(Ok(paid)) if !paid.is_empty() => info!("Processed {} scheduled payments", paid.len()),
+                Ok(Ok(_)) => {}
+                Ok(Err(err)) => warn!("Failed to submit scheduled payments: {}", err),
+                Err(err) => warn!("Failed to submit scheduled payments: {}", err),
+            }
+        }
+    });
+}
+
+/// Sweeps the funds of final invoices to their merchants as blocks arrive
+///
+/// # Arguments
+///
+/// * `blockchain` - The blockchain to follow and submit the sweeps to
+/// * `payments` - The registered payments
+/// * `keystore` - The keystore holding the merchant wallets
+fn spawn_invoice_sweeps(
+    blockchain: web::Data<blockchain::Blockchain>,
+    payments: web::Data<blockchain::payments::PaymentRegistry>,
+    keystore: web::Data<blockchain::keystore::Keystore>,
+) {
+    use blockchain::events::ChainEvent;
+    use tokio::sync::broadcast::error::RecvError;
+
+    let mut events = blockchain.subscribe();
+
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(ChainEvent::BlockAdded { .. } | ChainEvent::Reorg(_) | ChainEvent::Restored { .. })
+                | Err(RecvError::Lagged(_)) => {}
+                Ok(_) => continue,
+                Err(RecvError::Closed) => return,
+            }
+
+            let (chain, registry, wallets) = (blockchain.clone(), payments.clone(), keystore.clone());
+            match web::block(move || registry.sweep_final(&chain, &wallets)).await {
+                Ok(Ok(swept)) if !swept.is_empty() => info!("Swept {} invoice payments", swept.len()),
+                Ok(Ok(_)) => {}
+                Ok(Err(err)) => warn!("Failed to sweep invoice payments: {}", err),
+                Err(err) => warn!("Failed to sweep invoice payments: {}", err),
+            }
+        }
+    });
+}
+
+/// Takes storage snapshots every N blocks and every M minutes
+///
+/// Does nothing when both are disabled.
+///
+/// # Arguments
+///
+/// * `blockchain` - The blockchain whose storage to snapshot
+/// * `snapshots` - The store keeping the snapshots
+/// * `audit` - The audit log recording every snapshot
+/// * `config` - The node configuration with the intervals
+fn spawn_snapshot_scheduler(
+    blockchain: web::Data<blockchain::Blockchain>,
+    snapshots: web::Data<blockchain::snapshots::SnapshotStore>,
+    audit: web::Data<blockchain::audit::AuditLog>,
+    config: &NodeConfig,
+) {
+    use blockchain::events::ChainEvent;
+    use blockchain::snapshots::SnapshotTrigger;
+    use tokio::sync::broadcast::error::RecvError;
+
+    let every_blocks = config.snapshot_every_blocks;
+    let every_minutes = config.snapshot_every_minutes;
+    if every_blocks == 0 && every_minutes == 0 {
+        return;
+    }
+
+    let period = Duration::from_secs(every_minutes.max(1) * 60);
+    let mut schedule = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    let mut events = blockchain.subscribe();
+
+    tokio::spawn(async move {
+        loop {
+            let trigger = tokio::select! {
+                _ = schedule.tick(), if every_minutes > 0 => SnapshotTrigger::Schedule,
+                event = events.recv(), if every_blocks > 0 => match event {
+                    Ok(ChainEvent::BlockAdded { index, .. }) if index % every_blocks == 0 => SnapshotTrigger::Blocks,
+                    Err(RecvError::Closed) => return,
+                    _ => continue,
+                },
+            };
+
+            let (chain, store) = (blockchain.clone(), snapshots.clone());
+            match web::block(move || store.take(&chain, trigger)).await {
+                Ok(Ok(info)) => audit.record(
+                    "scheduler",
+                    "snapshot.created",
+                    &info.id,
+                    &format!("height: {}", info.height),
+                ),
+                Ok(Err(err)) => warn!("Failed to take scheduled snapshot: {}", err),
+                Err(err) => warn!("Failed to take scheduled snapshot: {}", err),
+            }
+        }
+    });
+}
+
+/// Time the background miner waits after a failed attempt
+const AUTO_MINE_RETRY: Duration = Duration::from_secs(5);
+
+/// Mines blocks in the background, crediting the configured address
+///
+/// With an interval, a block is mined every interval whether transactions
+/// are pending or not, like a devnet that keeps time. Without one, a block is
+/// mined as soon as transactions are pending, then again while any are left.
+/// A block that took none of them, e.g. because they all pay less than the
+/// base fee, waits for the next transaction to arrive instead of mining empty
+/// blocks in a loop. Mining shares the limiter with `/mine`, so the two never
+/// seal blocks at the same time.
+///
+/// Does nothing without an address.
+///
+/// # Arguments
+///
+/// * `blockchain` - The blockchain to mine on
+/// * `limiter` - The limiter letting one block be mined at a time
+/// * `config` - The node configuration with the address and the interval
+fn spawn_auto_miner(
+    blockchain: web::Data<blockchain::Blockchain>,
+    limiter: web::Data<api::limits::MiningLimiter>,
+    config: &NodeConfig,
+) {
+    use blockchain::events::ChainEvent;
+    use tokio::sync::broadcast::error::RecvError;
+
+    let address = match &config.auto_mine_address {
+        Some(address) => address.clone(),
+        None => return,
+    };
+    if config.follower {
+        warn!("Not mining to {} in the background, the node is a read-only follower", address);
+        return;
+    }
+
+    let interval_ms = config.auto_mine_interval_ms;
+    let period = Duration::from_millis(interval_ms.max(1));
+    let mut schedule = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    let mut events = blockchain.subscribe();
+    info!("Mining to {} in the background", address);
+
+    tokio::spawn(async move {
+        let mut idle = false;
+        loop {
+            if interval_ms > 0 {
+                schedule.tick().await;
+            } else if idle || blockchain.pending_count() == 0 {
+                match events.recv().await {
+                    Ok(ChainEvent::TransactionAdmitted { .. }) | Err(RecvError::Lagged(_)) => {}
+                    Ok(_) => continue,
+                    Err(RecvError::Closed) => return,
+                }
+            }
+
+            // Wait for a block requested over the API to be sealed first
+            let permit = loop {
+                if let Some(permit) = limiter.acquire().await {
+                    break permit;
+                }
+                tokio::time::sleep(AUTO_MINE_RETRY).await;
+            };
+            let (chain, miner) = (blockchain.clone(), address.clone());
+            let mined = web::block(move || {
+                let _permit = permit;
+                chain.mine_block(&miner)
+            })
+            .await;
+
+            idle = match mined {
+                Ok(Ok(block)) => {
+                    info!("Mined block {} with {} transactions in the background", block.index, block.transactions.len() - 1);
+                    block.transactions.len() == 1
+                }
+                Ok(Err(err)) => {
+                    warn!("Failed to mine in the background: {}", err);
+                    tokio::time::sleep(AUTO_MINE_RETRY).await;
+                    false
+                }
+                Err(err) => {
+                    warn!("Failed to mine in the background: {}", err);
+                    tokio::time::sleep(AUTO_MINE_RETRY).await;
+                    false
+                }
+            };
+        }
+    });
+}
+
+/// Command line of the node
+#[derive(Parser)]
+#[command(version, about = "A simple blockchain node with a REST API")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Truncate an inconsistent stored chain to its last consistent height instead of refusing to start
+    #[arg(long)]
+    repair: bool,
+
+    /// Run as a read-only replica of the node at this URL, e.g. http://primary:8080
+    #[arg(long, value_name = "URL")]
+    follow: Option<String>,
+
+    /// When storage writes are flushed to disk, overriding BLOCKCHAIN_FLUSH_POLICY
+    ///
+    /// every-write waits for the disk on every write: nothing acknowledged is
+    /// lost if the machine crashes, but writes are slowest.
+    ///
+    /// on-block-commit (the default) waits for the disk when a block is mined
+    /// or appended and flushes other writes every 500 ms.
+    ///
+    /// interval:<ms> flushes everything in the background, so mining never
+    /// waits for the disk, but a crash loses up to <ms> of writes, blocks
+    /// included.
+    #[arg(long, value_name = "POLICY")]
+    flush_policy: Option<blockchain::storage::FlushPolicy>,
+}
+
+/// Maintenance commands, the node runs the API server when none is given
+#[derive(Subcommand)]
+enum Command {
+    /// Copy the node's storage to a new path and verify every block and account
+    MigrateStorage {
+        /// Data directory to migrate, defaults to BLOCKCHAIN_DATA_DIR
+        #[arg(long)]
+        from: Option<String>,
+
+        /// Data directory to migrate to
+        #[arg(long)]
+        to: String,
+    },
+
+    /// Compress the blocks and transactions already in storage
+    CompressStorage {
+        /// Data directory to compress, defaults to BLOCKCHAIN_DATA_DIR
+        #[arg(long)]
+        data_dir: Option<String>,
+    },
+
+    /// Write a backup of the node's storage to a file, with the node stopped
+    ExportStorage {
+        /// Data directory to back up, defaults to BLOCKCHAIN_DATA_DIR
+        #[arg(long)]
+        data_dir: Option<String>,
+
+        /// Backup file to create
+        #[arg(long)]
+        to: String,
+    },
+
+    /// Restore a backup into an empty data directory, verifying every block
+    ImportStorage {
+        /// Backup file written by export-storage
+        #[arg(long)]
+        from: String,
+
+        /// Empty data directory to restore into, defaults to BLOCKCHAIN_DATA_DIR
+        #[arg(long)]
+        data_dir: Option<String>,
+    },
+
+    /// Replay a stored or exported chain through full validation
+    Verify {
+        /// Data directory to verify, defaults to BLOCKCHAIN_DATA_DIR
+        #[arg(long, conflicts_with = "chain")]
+        data_dir: Option<String>,
+
+        /// JSON file with an exported chain, as returned by GET /api/v1/chain
+        #[arg(long)]
+        chain: Option<String>,
+    },
+
+    /// Inspect the chain of running nodes
+    Chain {
+        #[command(subcommand)]
+        command: ChainCommand,
+    },
+
+    /// Fire concurrent transaction submissions and chain reads at a running node and report latencies
+    BenchApi {
+        /// Base URL of the node, defaults to this node's port on localhost
+        #[arg(long)]
+        node: Option<String>,
+
+        /// Number of clients sending requests at once
+        #[arg(long, default_value_t = 16)]
+        concurrency: usize,
+
+        /// Number of transactions to submit, from test-funded wallets
+        #[arg(long, default_value_t = 1000)]
+        transactions: usize,
+
+        /// Number of times to read the whole chain
+        #[arg(long, default_value_t = 200)]
+        reads: usize,
+    },
+}
+
+/// Commands that inspect the chain of running nodes
+#[derive(Subcommand)]
+enum ChainCommand {
+    /// Compare the chain of two nodes and show where they diverge
+    Diff {
+        /// Base URL of the local node, defaults to this node's port on localhost
+        #[arg(long)]
+        node: Option<String>,
+
+        /// Base URL of the node to compare with, e.g. http://nodeB:8080
+        #[arg(long)]
+        other: String,
+    },
+}
+
+/// Largest chain accepted from a node by the chain commands
+const MAX_CHAIN_RESPONSE_BYTES: usize = 512 * 1024 * 1024;
+
+/// Runs the migrate-storage command
+///
+/// # Arguments
+///
+/// * `from` - The data directory to migrate
+/// * `to` - The data directory to migrate to
+fn run_migrate_storage(from: &str, to: &str) -> std::io::Result<()> {
+    let _locks = (lock_data_dir(from)?, lock_data_dir(to)?);
+    match blockchain::migration::migrate_storage(Path::new(from), Path::new(to)) {
+        Ok(report) => {
+            println!("{}", report);
+            Ok(())
+        }
+        Err(err) => Err(std::io::Error::other(format!("Storage migration failed: {}", err))),
+    }
+}
+
+/// Runs the compress-storage command
+///
+/// # Arguments
+///
+/// * `data_dir` - The data directory to compress
+fn run_compress_storage(data_dir: &str) -> std::io::Result<()> {
+    let _lock = lock_data_dir(data_dir)?;
+    let options = blockchain::storage::StorageOptions { compress: true, ..Default::default() };
+    let report = blockchain::storage::BlockchainStorage::open(data_dir, options)
+        .and_then(|storage| storage.compress_existing())
+        .map_err(|err| std::io::Error::other(format!("Storage compression failed: {}", err)))?;
+
+    println!("Compressed {} records in {}", report.records, data_dir);
+    println!("  trained dictionary: {}", report.trained_dictionary);
+    println!("  size before: {} bytes", report.bytes_before);
+    println!("  size after:  {} bytes", report.bytes_after);
+    println!("Set BLOCKCHAIN_STORAGE_COMPRESSION=true so new records are compressed as well");
+    Ok(())
+}
+
+/// Runs the export-storage command
+///
+/// # Arguments
+///
+/// * `data_dir` - The data directory to back up
+/// * `to` - The backup file to create
+fn run_export_storage(data_dir: &str, to: &str) -> std::io::Result<()> {
+    // Opening a directory without data would create an empty database
+    if !Path::new(data_dir).join("db").exists() {
+        return Err(std::io::Error::other(format!("No blockchain data in {}", data_dir)));
+    }
+
+    let _lock = lock_data_dir(data_dir)?;
+    let report = blockchain::storage::BlockchainStorage::new(data_dir)
+        .and_then(|storage| storage.export_to(to))
+        .map_err(|err| std::io::Error::other(format!("Storage export failed: {}", err)))?;
+
+    println!("Exported {} to {}", data_dir, to);
+    println!("{}", report);
+    Ok(())
+}
+
+/// Runs the import-storage command
+///
+/// # Arguments
+///
+/// * `from` - The backup file
+/// * `data_dir` - The empty data directory to restore into
+fn run_import_storage(from: &str, data_dir: &str) -> std::io::Result<()> {
+    let _lock = lock_data_dir(data_dir)?;
+    let report = blockchain::storage::BlockchainStorage::new(data_dir)
+        .and_then(|storage| storage.import_from(from))
+        .map_err(|err| std::io::Error::other(format!("Storage import failed: {}", err)))?;
+
+    println!("Imported {} into {}", from, data_dir);
+    println!("{}", report);
+    Ok(())
+}
+
+/// Runs the verify command, exiting with status 1 at the first violation
+///
+/// # Arguments
+///
+/// * `data_dir` - The data directory to verify
+/// * `chain_file` - The exported chain to verify instead, if set
+/// * `fee_policy` - Where the fees of each block must go
+fn run_verify(data_dir: &str, chain_file: Option<&str>, fee_policy: &blockchain::fees::FeePolicy) -> std::io::Result<()> {
+    let blocks = match chain_file {
+        Some(path) => {
+            let contents = std::fs::read(path)?;
+            // Accept the chain endpoint's response as well as a plain list of blocks
+            serde_json::from_slice::<api::handlers::ChainResponse>(&contents)
+                .map(|response| response.chain)
+                .or_else(|_| serde_json::from_slice::<Vec<blockchain::Block>>(&contents))
+                .map_err(|err| std::io::Error::other(format!("Invalid chain file {}: {}", path, err)))?
+        }
+        // Opening a directory without data would create an empty database
+        None if !Path::new(data_dir).join("db").exists() => {
+            return Err(std::io::Error::other(format!("No blockchain data in {}", data_dir)));
+        }
+        None => blockchain::storage::BlockchainStorage::new(data_dir)
+            .and_then(|storage| storage.get_all_blocks())
+            .map_err(|err| std::io::Error::other(format!("Failed to read {}: {}", data_dir, err)))?,
+    };
+
+    match blockchain::verify::verify_chain(
+        &blocks,
+        blockchain::chain::DIFFICULTY,
+        blockchain::chain::MINING_REWARD,
+        fee_policy,
+    ) {
+        Ok(report) => {
+            println!("{}", report);
+            Ok(())
+        }
+        Err(violation) => {
+            eprintln!("{}", violation);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Fetches the chain of a running node
+///
+/// # Arguments
+///
+/// * `client` - The HTTP client
+/// * `base_url` - Base URL of the node
+///
+/// # Returns
+///
+/// The blocks of the node's chain, genesis first
+async fn fetch_chain(client: &awc::Client, base_url: &str) -> std::io::Result<Vec<blockchain::Block>> {
+    let url = format!("{}/api/v1/chain", base_url.trim_end_matches('/'));
+    let error = |err: String| std::io::Error::other(format!("Failed to fetch chain from {}: {}", url, err));
+
+    let mut response = client.get(&url).send().await.map_err(|err| error(err.to_string()))?;
+    if !response.status().is_success() {
+        return Err(error(format!("status {}", response.status())));
+    }
+
+    let chain: api::handlers::ChainResponse = response
+        .json()
+        .limit(MAX_CHAIN_RESPONSE_BYTES)
+        .await
+        .map_err(|err| error(err.to_string()))?;
+
+    Ok(chain.chain)
+}
+
+/// Runs the chain diff command
+///
+/// # Arguments
+///
+/// * `node` - Base URL of the local node
+/// * `other` - Base URL of the node to compare with
+async fn run_chain_diff(node: &str, other: &str) -> std::io::Result<()> {
+    let client = awc::Client::default();
+    let local = fetch_chain(&client, node).await?;
+    let remote = fetch_chain(&client, other).await?;
+
+    print!("{}", blockchain::diff::diff_chains(&local, &remote));
+    Ok(())
+}
+
+/// Runs the bench-api command
+///
+/// # Arguments
+///
+/// * `node` - Base URL of the node to benchmark
+/// * `options` - The workload
+async fn run_bench_api(node: &str, options: &api::bench::BenchOptions) -> std::io::Result<()> {
+    let report = api::bench::run(node, options)
+        .await
+        .map_err(|err| std::io::Error::other(err.to_string()))?;
+
+    print!("{}", report);
+    Ok(())
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    let cli = Cli::parse();
+
     // Initialize logger
     env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
 
+    // Load the node configuration
+    let mut config = NodeConfig::from_env();
+
+    match cli.command {
+        Some(Command::MigrateStorage { from, to }) => {
+            return run_migrate_storage(from.as_deref().unwrap_or(&config.data_dir), &to);
+        }
+        Some(Command::CompressStorage { data_dir }) => {
+            return run_compress_storage(data_dir.as_deref().unwrap_or(&config.data_dir));
+        }
+        Some(Command::ExportStorage { data_dir, to }) => {
+            return run_export_storage(data_dir.as_deref().unwrap_or(&config.data_dir), &to);
+        }
+        Some(Command::ImportStorage { from, data_dir }) => {
+            return run_import_storage(&from, data_dir.as_deref().unwrap_or(&config.data_dir));
+        }
+        Some(Command::Verify { data_dir, chain }) => {
+            return run_verify(data_dir.as_deref().unwrap_or(&config.data_dir), chain.as_deref(), &config.fee_policy);
+        }
+        Some(Command::Chain { command: ChainCommand::Diff { node, other } }) => {
+            let node = node.unwrap_or_else(|| format!("http://127.0.0.1:{}", config.port));
+            return run_chain_diff(&node, &other).await;
+        }
+        Some(Command::BenchApi { node, concurrency, transactions, reads }) => {
+            let node = node.unwrap_or_else(|| format!("http://127.0.0.1:{}", config.port));
+            let options = api::bench::BenchOptions { concurrency, transactions, reads };
+            return run_bench_api(&node, &options).await;
+        }
+        None => {}
+    }
+
+    // A replica only takes blocks from its primary
+    if cli.follow.is_some() {
+        config.follower = true;
+    }
+
+    if let Some(policy) = cli.flush_policy {
+        config.flush_policy = policy;
+    }
+
+    // One node per data directory, held until the node exits
+    let _data_dir_lock = lock_data_dir(&config.data_dir)?;
+
+    // Refuse to load a corrupt chain
+    check_storage_consistency(&config, cli.repair)?;
+
     // Create a new blockchain with initial data
-    let blockchain = web::Data::new(initialize_blockchain());
+    let blockchain = web::Data::new(initialize_blockchain(&config)?);
+
+    // Create the payment registry, persisted alongside the blockchain if possible
+    let payments = match blockchain::payments::PaymentRegistry::new(blockchain.get_storage()) {
+        Ok(payments) => payments,
+        Err(err) => {
+            warn!("Failed to load payments from storage: {}", err);
+            blockchain::payments::PaymentRegistry::new(None)
+                .expect("in-memory payment registry cannot fail")
+        }
+    };
+    let payments = web::Data::new(payments);
+
+    // Create the audit log
+    let audit = blockchain::audit::AuditLog::new(blockchain.get_storage()).unwrap_or_else(|err| {
+        warn!("Failed to load audit log from storage: {}", err);
+        blockchain::audit::AuditLog::new(None).expect("in-memory audit log cannot fail")
+    });
+
+    // Load the wallets held by the node
+    let keystore = blockchain::keystore::Keystore::new(blockchain.get_storage())
+        .expect("failed to load keystore from storage");
+    let keystore = web::Data::new(keystore);
+
+    // Create the withdrawal queue paid out from the hot wallet
+    let withdrawals = blockchain::withdrawals::WithdrawalQueue::new(
+        load_hot_wallet(&config, &keystore),
+        config.withdrawal_auto_approve_limit,
+        audit.clone(),
+        blockchain.get_storage(),
+    )
+    .expect("failed to load withdrawals from storage");
+    let withdrawals = web::Data::new(withdrawals);
+
+    // Load the recurring payments and pay them as blocks arrive
+    let scheduler = blockchain::schedules::Scheduler::new(blockchain.get_storage())
+        .expect("failed to load schedules from storage");
+    let scheduler = web::Data::new(scheduler);
+    spawn_scheduled_payments(blockchain.clone(), scheduler.clone());
+
+    // Load the keys the node signs with, generating them on first start
+    let node_keys = blockchain::node_keys::NodeKeys::new(
+        blockchain.get_storage(),
+        audit.clone(),
+        chrono::Duration::seconds(config.key_rotation_overlap_secs as i64),
+    )
+    .expect("failed to load node keys from storage");
+    let node_keys = web::Data::new(node_keys);
+    let audit = web::Data::new(audit);
+
+    // Guard administrative endpoints
+    if config.admin_token.is_none() {
+        warn!("BLOCKCHAIN_ADMIN_TOKEN is not set, administrative endpoints are disabled");
+    }
+    let admin = web::Data::new(api::auth::AdminAuth::new(config.admin_token.clone()));
+
+    // Mine one block at a time
+    let mining_limiter = web::Data::new(api::limits::MiningLimiter::new(config.mining_queue_limit));
+    let mining_jobs = web::Data::new(api::mining::MiningJobs::new());
+    let block_templates = web::Data::new(api::mining::BlockTemplates::new());
+
+    // Sweep final invoices to their merchants as blocks arrive
+    spawn_invoice_sweeps(blockchain.clone(), payments.clone(), keystore.clone());
+
+    // Load the private transaction labels
+    let labels = blockchain::labels::LabelStore::new(blockchain.get_storage())
+        .expect("failed to load labels from storage");
+    let labels = web::Data::new(labels);
+
+    // Meter the API keys and hold them to their monthly quotas
+    let usage = blockchain::usage::UsageMeter::new(blockchain.get_storage(), config.quota_policy())
+        .expect("failed to load API usage from storage");
+    let usage = web::Data::new(usage);
+
+    // Keep snapshots of the storage to undo operator mistakes
+    let snapshots = blockchain::snapshots::SnapshotStore::new(&config.snapshot_dir, config.snapshot_keep)
+        .expect("failed to create snapshot directory");
+    let snapshots = web::Data::new(snapshots);
+    if blockchain.get_storage().is_some() {
+        spawn_snapshot_scheduler(blockchain.clone(), snapshots.clone(), audit.clone(), &config);
+    }
 
-    info!("Starting HTTP server at http://localhost:8080");
+    // Persist pending transactions so they survive a crash
+    spawn_mempool_persistence(blockchain.clone(), &config);
+
+    // Mine without waiting for /mine requests, if configured
+    spawn_auto_miner(blockchain.clone(), mining_limiter.clone(), &config);
+
+    // Keep up with the primary, on this thread since the HTTP client is not Send
+    if let Some(primary) = &cli.follow {
+        info!("Replicating {}", primary);
+        actix_web::rt::spawn(api::replication::Replica::new(primary, blockchain.clone()).run());
+    }
+
+    // Gossip blocks and transactions with the peers of the network
+    if config.p2p_enabled() {
+        let service = network::NetworkConfig::parse(config.p2p_listen.as_deref(), &config.peers, config.mdns)
+            .and_then(|network| network::NetworkService::new(network, blockchain.clone().into_inner()))
+            .map_err(|err| std::io::Error::other(format!("Failed to start the P2P network: {}", err)))?;
+        tokio::spawn(service.run());
+
+        if let Some(policy) = config.rebroadcast_policy() {
+            spawn_rebroadcasts(blockchain.clone(), policy);
+        }
+    }
+
+    if let Some(max_age) = config.mempool_max_age() {
+        spawn_mempool_eviction(blockchain.clone(), max_age);
+    }
+
+    // Serve the same operations over gRPC for indexers
+    if let Some(address) = &config.grpc_listen {
+        #[cfg(feature = "grpc")]
+        match address.parse() {
+            Ok(address) => {
+                info!("Starting gRPC server at {}", address);
+                let service = api::grpc::NodeService::new(blockchain.clone().into_inner(), mining_limiter.get_ref().clone());
+                tokio::spawn(async move {
+                    if let Err(err) = service.serve(address).await {
+                        warn!("gRPC server failed: {}", err);
+                    }
+                });
+            }
+            Err(err) => warn!("Invalid gRPC address {}: {}", address, err),
+        }
+
+        #[cfg(not(feature = "grpc"))]
+        warn!("Not serving gRPC at {}, the node was built without the grpc feature", address);
+    }
+
+    info!("Starting HTTP server at http://{}:{}", config.host, config.port);
 
     // Start HTTP server
+    let server_blockchain = blockchain.clone();
     HttpServer::new(move || {
         // Configure CORS
         let cors = Cors::default()
@@ -142,9 +1302,23 @@ async fn main() -> std::io::Result<()> {
         let openapi = ApiDoc::openapi();
 
         App::new()
+            .wrap(middleware::from_fn(api::usage::meter))
             .wrap(middleware::Logger::default())
             .wrap(cors)
-            .app_data(blockchain.clone())
+            .app_data(server_blockchain.clone())
+            .app_data(payments.clone())
+            .app_data(withdrawals.clone())
+            .app_data(scheduler.clone())
+            .app_data(audit.clone())
+            .app_data(admin.clone())
+            .app_data(mining_limiter.clone())
+            .app_data(mining_jobs.clone())
+            .app_data(block_templates.clone())
+            .app_data(keystore.clone())
+            .app_data(labels.clone())
+            .app_data(usage.clone())
+            .app_data(node_keys.clone())
+            .app_data(snapshots.clone())
             // API routes
             .configure(api::configure_routes)
             // Swagger UI
@@ -153,7 +1327,15 @@ async fn main() -> std::io::Result<()> {
                     .url("/api-docs/openapi.json", openapi.clone())
             )
     })
-    .bind(("127.0.0.1", 8080))?
+    .bind((config.host.as_str(), config.port))?
     .run()
-    .await
+    .await?;
+
+    // Save the pending transactions on a clean shutdown
+    match blockchain.persist_mempool() {
+        Ok(count) => info!("Saved {} pending transactions", count),
+        Err(err) => warn!("Failed to save pending transactions: {}", err),
+    }
+
+    Ok(())
 }
@@ -0,0 +1,229 @@
+use libp2p::core::ConnectedPoint;
+use libp2p::multiaddr::Protocol;
+use libp2p::{Multiaddr, PeerId};
+
+use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
+
+use super::service::NetworkError;
+use crate::blockchain::peers::{PeerSet, PeerSource};
+
+/// Finds the peers of the node
+///
+/// Bootstrap peers come from the configuration and are dialed on start and
+/// whenever the node has no peers. With mDNS, nodes on the local network
+/// announce themselves and are dialed as soon as they are heard. Every peer
+/// found or connected is recorded in the blockchain's peer set, where the
+/// API reads it. Operators may add peers through the API too, and banned
+/// peers are never dialed.
+pub struct Discovery {
+    /// Addresses of the bootstrap peers
+    bootstrap: Vec<Multiaddr>,
+
+    /// Addresses operators added, without their peer ID
+    manual: HashSet<Multiaddr>,
+
+    /// The peers found so far
+    peers: PeerSet,
+}
+
+impl Discovery {
+    /// Creates the discovery of a node
+    ///
+    /// # Arguments
+    ///
+    /// * `bootstrap` - Addresses of the bootstrap peers
+    /// * `peers` - The peer set to record the peers in
+    pub fn new(bootstrap: Vec<Multiaddr>, peers: PeerSet) -> Self {
+        Discovery {
+            bootstrap,
+            manual: HashSet::new(),
+            peers,
+        }
+    }
+
+    /// Gets the addresses of the bootstrap peers
+    pub fn bootstrap(&self) -> &[Multiaddr] {
+        &self.bootstrap
+    }
+
+    /// Records peers announced over mDNS
+    ///
+    /// # Arguments
+    ///
+    /// * `found` - The peers and the addresses they announced
+    ///
+    /// # Returns
+    ///
+    /// The addresses of the peers the node is not connected to yet, to dial
+    pub fn mdns_discovered(&self, found: Vec<(PeerId, Multiaddr)>) -> Vec<Multiaddr> {
+        let mut dial = Vec::new();
+        for (peer, address) in found {
+            self.peers.discovered(&peer.to_string(), &address.to_string(), PeerSource::Mdns);
+            if !self.peers.is_connected(&peer.to_string()) && !self.peers.is_banned(&peer.to_string()) {
+                dial.push(address.with(Protocol::P2p(peer)));
+            }
+        }
+        dial
+    }
+
+    /// Records an address an operator added, so the peer found there is reported as added manually
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address, to dial
+    pub fn added(&mut self, address: &Multiaddr) {
+        self.manual.insert(without_peer_id(address));
+    }
+
+    /// Forgets addresses no longer announced over mDNS
+    ///
+    /// # Arguments
+    ///
+    /// * `expired` - The peers and the addresses that expired
+    pub fn mdns_expired(&self, expired: Vec<(PeerId, Multiaddr)>) {
+        for (peer, address) in expired {
+            self.peers.expired(&peer.to_string(), &address.to_string());
+        }
+    }
+
+    /// Records the first connection to a peer
+    ///
+    /// # Arguments
+    ///
+    /// * `peer` - The peer
+    /// * `endpoint` - Who dialed whom, and at which address
+    pub fn connected(&self, peer: &PeerId, endpoint: &ConnectedPoint) {
+        let (address, source) = match endpoint {
+            ConnectedPoint::Dialer { address, .. } if self.manual.contains(&without_peer_id(address)) => {
+                (address, PeerSource::Manual)
+            }
+            ConnectedPoint::Dialer { address, .. } => {
+                // Peers the node dialed come from its configuration unless mDNS found them first
+                (address, PeerSource::Bootstrap)
+            }
+            ConnectedPoint::Listener { send_back_addr, .. } => (send_back_addr, PeerSource::Inbound),
+        };
+        self.peers.connected(&peer.to_string(), &without_peer_id(address).to_string(), source);
+    }
+
+    /// Records that the last connection to a peer closed
+    pub fn disconnected(&self, peer: &PeerId) {
+        self.peers.disconnected(&peer.to_string());
+    }
+}
+
+/// Parses the address of a peer an operator adds
+///
+/// # Arguments
+///
+/// * `address` - A multiaddress such as /ip4/10.0.0.1/tcp/9000, or host:port
+///   such as 10.0.0.1:9000 or node.example.com:9000
+///
+/// # Returns
+///
+/// Result with the multiaddress, or NetworkError::InvalidAddress
+pub fn parse_peer_address(address: &str) -> Result<Multiaddr, NetworkError> {
+    let invalid = |reason: &str| NetworkError::InvalidAddress(format!("{}: {}", address, reason));
+
+    if address.starts_with('/') {
+        return address.parse().map_err(|err: libp2p::multiaddr::Error| invalid(&err.to_string()));
+    }
+
+    let (host, port) = match address.parse::<SocketAddr>() {
+        Ok(socket) => (Protocol::from(socket.ip()), socket.port()),
+        Err(_) => {
+            let (host, port) = address.rsplit_once(':').ok_or_else(|| invalid("expected host:port"))?;
+            let port = port.parse::<u16>().map_err(|_| invalid("invalid port"))?;
+            if host.is_empty() || host.contains(':') {
+                return Err(invalid("invalid host"));
+            }
+            match host.parse::<IpAddr>() {
+                Ok(ip) => (Protocol::from(ip), port),
+                Err(_) => (Protocol::Dns(host.into()), port),
+            }
+        }
+    };
+
+    Ok(Multiaddr::empty().with(host).with(Protocol::Tcp(port)))
+}
+
+/// Parses the ID of a peer an operator disconnects or bans
+///
+/// # Returns
+///
+/// Result with the peer ID, or NetworkError::InvalidAddress
+pub fn parse_peer_id(peer_id: &str) -> Result<PeerId, NetworkError> {
+    peer_id
+        .parse()
+        .map_err(|_| NetworkError::InvalidAddress(format!("Invalid peer ID: {}", peer_id)))
+}
+
+/// Strips the /p2p/<peer ID> part of an address, which the peer ID already tells
+fn without_peer_id(address: &Multiaddr) -> Multiaddr {
+    address
+        .iter()
+        .filter(|protocol| !matches!(protocol, Protocol::P2p(_)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mdns_peers_are_dialed_once() {
+        let peers = PeerSet::new();
+        let discovery = Discovery::new(Vec::new(), peers.clone());
+        let peer = PeerId::random();
+        let address: Multiaddr = "/ip4/192.168.1.7/tcp/9000".parse().unwrap();
+
+        let dial = discovery.mdns_discovered(vec![(peer, address.clone())]);
+        assert_eq!(dial, vec![address.clone().with(Protocol::P2p(peer))]);
+        assert_eq!(peers.list()[0].source, PeerSource::Mdns);
+
+        let endpoint = ConnectedPoint::Dialer {
+            address: dial[0].clone(),
+            role_override: libp2p::core::Endpoint::Dialer,
+        };
+        discovery.connected(&peer, &endpoint);
+        assert!(discovery.mdns_discovered(vec![(peer, address.clone())]).is_empty());
+        assert_eq!(peers.list()[0].addresses, vec![address.to_string()]);
+        assert_eq!(peers.list()[0].source, PeerSource::Mdns);
+
+        discovery.disconnected(&peer);
+        discovery.mdns_expired(vec![(peer, address)]);
+        assert!(peers.list().is_empty());
+    }
+
+    #[test]
+    fn test_peer_addresses_accept_host_and_port() {
+        let parsed = |address: &str| parse_peer_address(address).unwrap().to_string();
+        assert_eq!(parsed("/ip4/10.0.0.1/tcp/9000"), "/ip4/10.0.0.1/tcp/9000");
+        assert_eq!(parsed("10.0.0.1:9000"), "/ip4/10.0.0.1/tcp/9000");
+        assert_eq!(parsed("[::1]:9000"), "/ip6/::1/tcp/9000");
+        assert_eq!(parsed("node.example.com:9000"), "/dns/node.example.com/tcp/9000");
+
+        for invalid in ["/ip4/10.0.0.1/tcp", "10.0.0.1", "node:port", ":9000", "::1:9000"] {
+            assert!(parse_peer_address(invalid).is_err(), "{} was accepted", invalid);
+        }
+        assert!(parse_peer_id(&PeerId::random().to_string()).is_ok());
+        assert!(parse_peer_id("not-a-peer").is_err());
+    }
+
+    #[test]
+    fn test_added_peers_are_reported_as_manual() {
+        let peers = PeerSet::new();
+        let mut discovery = Discovery::new(Vec::new(), peers.clone());
+        let address = parse_peer_address("10.0.0.1:9000").unwrap();
+        discovery.added(&address);
+
+        let peer = PeerId::random();
+        let endpoint = ConnectedPoint::Dialer {
+            address: address.with(Protocol::P2p(peer)),
+            role_override: libp2p::core::Endpoint::Dialer,
+        };
+        discovery.connected(&peer, &endpoint);
+        assert_eq!(peers.list()[0].source, PeerSource::Manual);
+    }
+}
@@ -0,0 +1,14 @@
+// Network module
+//
+// Connects nodes of the same network over libp2p so they converge on one
+// chain:
+// - Topics, protocol names and messages nodes exchange
+// - Discovery of peers through bootstrap addresses and mDNS
+// - The service gossiping blocks and transactions and syncing from peers
+
+pub mod discovery;
+pub mod protocol;
+pub mod service;
+
+// Re-export main components for easier access
+pub use service::{NetworkConfig, NetworkError, NetworkService};
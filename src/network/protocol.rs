@@ -0,0 +1,88 @@
+use libp2p::gossipsub::IdentTopic;
+use libp2p::StreamProtocol;
+use serde::{Deserialize, Serialize};
+
+use crate::blockchain::block::BlockHeader;
+use crate::blockchain::genesis::NetworkMagic;
+use crate::blockchain::Block;
+
+/// Gets the topic new blocks of a network are gossiped on
+///
+/// Topics and protocols are named after the network magic, so nodes of
+/// different networks never take each other's blocks or transactions.
+///
+/// # Arguments
+///
+/// * `magic` - The magic bytes of the network
+pub fn blocks_topic(magic: &NetworkMagic) -> IdentTopic {
+    IdentTopic::new(format!("/my_blockchain/{}/blocks/1", magic))
+}
+
+/// Gets the topic new transactions of a network are gossiped on
+///
+/// # Arguments
+///
+/// * `magic` - The magic bytes of the network
+pub fn transactions_topic(magic: &NetworkMagic) -> IdentTopic {
+    IdentTopic::new(format!("/my_blockchain/{}/transactions/1", magic))
+}
+
+/// Gets the protocol peers of a network ask each other for headers and blocks with
+///
+/// # Arguments
+///
+/// * `magic` - The magic bytes of the network
+pub fn sync_protocol(magic: &NetworkMagic) -> StreamProtocol {
+    StreamProtocol::try_from_owned(format!("/my_blockchain/{}/sync/2", magic))
+        .expect("the sync protocol name starts with a slash")
+}
+
+/// What a node asks a peer while syncing, see `Blockchain::sync_from`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SyncRequest {
+    /// The headers after the last block both chains share
+    Headers {
+        /// Hashes of the asking node's chain, tip first, see `Blockchain::get_block_locator`
+        locator: Vec<String>,
+
+        /// Largest number of headers to send, capped at MAX_SYNC_HEADERS
+        limit: usize,
+    },
+
+    /// Blocks by their hashes, at most MAX_SYNC_BLOCKS are sent
+    Blocks {
+        /// The hashes of the blocks
+        hashes: Vec<String>,
+    },
+}
+
+/// What a peer sends in answer to a sync request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SyncResponse {
+    /// The headers after the shared block, oldest first, empty if the asking node is up to date
+    Headers(Vec<BlockHeader>),
+
+    /// The blocks asked for that the peer has, in the order asked
+    Blocks(Vec<Block>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::genesis::GenesisConfig;
+
+    #[test]
+    fn test_names_differ_between_networks() {
+        let devnet = GenesisConfig::default().magic();
+        let classroom = GenesisConfig {
+            network: "classroom".to_string(),
+            ..GenesisConfig::default()
+        }
+        .magic();
+
+        assert_eq!(blocks_topic(&devnet).hash(), blocks_topic(&devnet).hash());
+        assert_ne!(blocks_topic(&devnet).hash(), blocks_topic(&classroom).hash());
+        assert_ne!(blocks_topic(&devnet).hash(), transactions_topic(&devnet).hash());
+        assert_ne!(sync_protocol(&devnet), sync_protocol(&classroom));
+    }
+}
@@ -0,0 +1,747 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+use libp2p::gossipsub::{self, IdentTopic, MessageAcceptance, MessageAuthenticity, ValidationMode};
+use libp2p::request_response::{self, ProtocolSupport};
+use libp2p::swarm::behaviour::toggle::Toggle;
+use libp2p::swarm::{NetworkBehaviour, SwarmEvent};
+use libp2p::{allow_block_list, identity, mdns, noise, tcp, yamux, Multiaddr, PeerId, Swarm};
+use log::{debug, info, warn};
+use rand::seq::IteratorRandom;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::discovery::{self, Discovery};
+use super::protocol::{self, SyncRequest, SyncResponse};
+use crate::blockchain::block::BlockHeader;
+use crate::blockchain::chain::{BlockchainError, SyncPeer, MAX_SYNC_BLOCKS, MAX_SYNC_HEADERS};
+use crate::blockchain::events::ReorgEvent;
+use crate::blockchain::forks::BlockOutcome;
+use crate::blockchain::genesis::NetworkMagic;
+use crate::blockchain::mempool::Admission;
+use crate::blockchain::observers::ChainObserver;
+use crate::blockchain::peers::PeerCommand;
+use crate::blockchain::{Block, Blockchain, Transaction};
+
+/// How often the node asks a peer for blocks it may have missed, or redials its bootstrap peers when it has none
+const SYNC_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long a connection without any traffic stays open
+const IDLE_CONNECTION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Largest gossiped message, a block with its transactions
+const MAX_GOSSIP_BYTES: usize = 4 * 1024 * 1024;
+
+/// Where a node announcing itself over mDNS listens when no address is configured
+const MDNS_LISTEN_ADDRESS: &str = "/ip4/0.0.0.0/tcp/0";
+
+/// Errors that can occur while starting the network service
+#[derive(Debug, Error)]
+pub enum NetworkError {
+    #[error("Invalid peer address: {0}")]
+    InvalidAddress(String),
+
+    #[error("Transport error: {0}")]
+    Transport(String),
+}
+
+/// Where the node listens for peers and which peers it connects to
+#[derive(Debug, Clone, Default)]
+pub struct NetworkConfig {
+    /// Address to listen on, e.g. /ip4/0.0.0.0/tcp/9000, None to only connect out
+    pub listen: Option<Multiaddr>,
+
+    /// Bootstrap peers to connect to on start, and again whenever the node has none
+    pub bootstrap: Vec<Multiaddr>,
+
+    /// Whether to announce the node and find peers on the local network over mDNS
+    pub mdns: bool,
+}
+
+impl NetworkConfig {
+    /// Creates a network configuration from multiaddresses
+    ///
+    /// # Arguments
+    ///
+    /// * `listen` - Address to listen on, if any
+    /// * `bootstrap` - Addresses of the bootstrap peers
+    /// * `mdns` - Whether to find peers on the local network over mDNS
+    ///
+    /// # Returns
+    ///
+    /// Result with the configuration, or NetworkError::InvalidAddress for the first malformed address
+    pub fn parse(listen: Option<&str>, bootstrap: &[String], mdns: bool) -> Result<Self, NetworkError> {
+        let parse = |address: &str| {
+            address
+                .parse::<Multiaddr>()
+                .map_err(|err| NetworkError::InvalidAddress(format!("{}: {}", address, err)))
+        };
+
+        Ok(NetworkConfig {
+            listen: listen.map(parse).transpose()?,
+            bootstrap: bootstrap.iter().map(|peer| parse(peer)).collect::<Result<_, _>>()?,
+            mdns,
+        })
+    }
+}
+
+/// The protocols the node speaks with its peers
+#[derive(NetworkBehaviour)]
+struct Behaviour {
+    /// Announces new blocks and transactions
+    gossipsub: gossipsub::Behaviour,
+
+    /// Asks peers for the headers and blocks the node is missing
+    sync: request_response::json::Behaviour<SyncRequest, SyncResponse>,
+
+    /// Finds peers on the local network, if enabled
+    mdns: Toggle<mdns::tokio::Behaviour>,
+
+    /// Refuses the peers operators banned
+    blocked: allow_block_list::Behaviour<allow_block_list::BlockedPeers>,
+}
+
+/// What the local chain announces to the network
+#[derive(Debug)]
+enum Announcement {
+    Block(Block),
+    Transaction(Box<Transaction>),
+}
+
+/// Hands blocks and transactions of the local chain to the network service
+struct GossipObserver {
+    sender: mpsc::UnboundedSender<Announcement>,
+}
+
+impl ChainObserver for GossipObserver {
+    fn on_block_applied(&self, block: &Block) -> Result<(), BlockchainError> {
+        // A stopped service is no reason to refuse a block
+        let _ = self.sender.send(Announcement::Block(block.clone()));
+        Ok(())
+    }
+
+    fn on_reorg(&self, _reorg: &ReorgEvent, _orphaned: &[Block], chain: &[Block]) -> Result<(), BlockchainError> {
+        // Peers that don't have the new tip ask for its branch
+        if let Some(tip) = chain.last() {
+            let _ = self.sender.send(Announcement::Block(tip.clone()));
+        }
+        Ok(())
+    }
+
+    fn on_tx_admitted(&self, transaction: &Transaction) {
+        let _ = self.sender.send(Announcement::Transaction(Box::new(transaction.clone())));
+    }
+
+    fn on_tx_rebroadcast(&self, transaction: &Transaction) {
+        let _ = self.sender.send(Announcement::Transaction(Box::new(transaction.clone())));
+    }
+}
+
+/// A request of a sync task for the service to send to a peer
+struct PeerRequest {
+    /// The peer to ask
+    peer: PeerId,
+
+    /// What to ask
+    request: SyncRequest,
+
+    /// Where the answer goes
+    reply: oneshot::Sender<Result<SyncResponse, BlockchainError>>,
+}
+
+/// A peer a sync task asks through the service, which owns the swarm
+struct RemotePeer {
+    /// The peer
+    peer: PeerId,
+
+    /// The requests of the service
+    requests: mpsc::UnboundedSender<PeerRequest>,
+}
+
+impl RemotePeer {
+    /// Asks the peer and waits for its answer
+    async fn ask(&self, request: SyncRequest) -> Result<SyncResponse, BlockchainError> {
+        let stopped = || BlockchainError::SystemError("The network service stopped".to_string());
+        let (reply, answer) = oneshot::channel();
+        self.requests
+            .send(PeerRequest {
+                peer: self.peer,
+                request,
+                reply,
+            })
+            .map_err(|_| stopped())?;
+        answer.await.map_err(|_| stopped())?
+    }
+}
+
+#[async_trait]
+impl SyncPeer for RemotePeer {
+    async fn get_headers(&self, locator: Vec<String>, limit: usize) -> Result<Vec<BlockHeader>, BlockchainError> {
+        match self.ask(SyncRequest::Headers { locator, limit }).await? {
+            SyncResponse::Headers(headers) => Ok(headers),
+            SyncResponse::Blocks(_) => Err(BlockchainError::InvalidChain(format!(
+                "Peer {} answered a request for headers with blocks",
+                self.peer
+            ))),
+        }
+    }
+
+    async fn get_blocks(&self, hashes: Vec<String>) -> Result<Vec<Block>, BlockchainError> {
+        match self.ask(SyncRequest::Blocks { hashes }).await? {
+            SyncResponse::Blocks(blocks) => Ok(blocks),
+            SyncResponse::Headers(_) => Err(BlockchainError::InvalidChain(format!(
+                "Peer {} answered a request for blocks with headers",
+                self.peer
+            ))),
+        }
+    }
+}
+
+/// Connects the node to its peers so their chains converge
+///
+/// New blocks and transactions of the local chain are gossiped to the
+/// peers, and theirs go through the normal validation before they are
+/// appended or admitted. Gossip is relayed only once the node accepted it,
+/// so invalid blocks and blocks it already has go no further. A block that doesn't attach to the tip, a new
+/// connection and a timer make the node sync from a peer headers first, see
+/// `Blockchain::sync_from`. The sync runs as a task of its own that sends
+/// its requests through the service. Operators add, disconnect and ban
+/// peers through the blockchain's peer set.
+pub struct NetworkService {
+    /// The libp2p swarm
+    swarm: Swarm<Behaviour>,
+
+    /// The local node
+    blockchain: Arc<Blockchain>,
+
+    /// Finds the peers to connect to
+    discovery: Discovery,
+
+    /// The topic blocks are gossiped on
+    blocks_topic: IdentTopic,
+
+    /// The topic transactions are gossiped on
+    transactions_topic: IdentTopic,
+
+    /// Blocks and transactions of the local chain waiting to be announced
+    announcements: mpsc::UnboundedReceiver<Announcement>,
+
+    /// The peers the node is connected to
+    peers: HashSet<PeerId>,
+
+    /// Operators' commands, e.g. to ban a peer
+    commands: mpsc::UnboundedReceiver<PeerCommand>,
+
+    /// Requests of the sync task waiting to be sent
+    sync_requests: mpsc::UnboundedReceiver<PeerRequest>,
+
+    /// Hands out senders of sync requests to sync tasks
+    sync_sender: mpsc::UnboundedSender<PeerRequest>,
+
+    /// Sync requests sent, waiting for an answer
+    pending_requests: HashMap<request_response::RequestId, oneshot::Sender<Result<SyncResponse, BlockchainError>>>,
+
+    /// The sync task, one at a time so blocks arrive in order
+    sync: Option<JoinHandle<()>>,
+}
+
+impl NetworkService {
+    /// Creates the network service of a node and starts listening
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The listen address and how to find peers
+    /// * `blockchain` - The local node, whose peer set the found peers are recorded in
+    ///
+    /// # Returns
+    ///
+    /// Result with the service, which connects to the peers once run
+    pub fn new(config: NetworkConfig, blockchain: Arc<Blockchain>) -> Result<Self, NetworkError> {
+        let magic = blockchain.get_network_magic();
+        let mut swarm = libp2p::SwarmBuilder::with_new_identity()
+            .with_tokio()
+            .with_tcp(tcp::Config::default(), noise::Config::new, yamux::Config::default)
+            .map_err(|err| NetworkError::Transport(err.to_string()))?
+            .with_behaviour(|key| build_behaviour(key, &magic, config.mdns))
+            .map_err(|err| NetworkError::Transport(err.to_string()))?
+            .with_swarm_config(|swarm| swarm.with_idle_connection_timeout(IDLE_CONNECTION_TIMEOUT))
+            .build();
+
+        let blocks_topic = protocol::blocks_topic(&magic);
+        let transactions_topic = protocol::transactions_topic(&magic);
+        for topic in [&blocks_topic, &transactions_topic] {
+            swarm
+                .behaviour_mut()
+                .gossipsub
+                .subscribe(topic)
+                .map_err(|err| NetworkError::Transport(err.to_string()))?;
+        }
+
+        // Announcing the node over mDNS is pointless if peers can't connect to it
+        let listen = match &config.listen {
+            Some(address) => Some(address.clone()),
+            None if config.mdns => Some(MDNS_LISTEN_ADDRESS.parse().expect("the mDNS listen address is valid")),
+            None => None,
+        };
+        if let Some(address) = listen {
+            swarm
+                .listen_on(address.clone())
+                .map_err(|err| NetworkError::Transport(format!("{}: {}", address, err)))?;
+        }
+
+        let (sender, announcements) = mpsc::unbounded_channel();
+        blockchain.add_observer(Arc::new(GossipObserver { sender }));
+
+        let peer_set = blockchain.peer_set();
+        for peer_id in peer_set.banned() {
+            if let Ok(peer) = discovery::parse_peer_id(&peer_id) {
+                swarm.behaviour_mut().blocked.block_peer(peer);
+            }
+        }
+        let commands = peer_set.take_commands();
+        let discovery = Discovery::new(config.bootstrap, peer_set);
+        let (sync_sender, sync_requests) = mpsc::unbounded_channel();
+
+        Ok(NetworkService {
+            swarm,
+            blockchain,
+            discovery,
+            blocks_topic,
+            transactions_topic,
+            announcements,
+            peers: HashSet::new(),
+            commands,
+            sync_requests,
+            sync_sender,
+            pending_requests: HashMap::new(),
+            sync: None,
+        })
+    }
+
+    /// Gets the ID the node's peers know it by
+    pub fn local_peer_id(&self) -> PeerId {
+        *self.swarm.local_peer_id()
+    }
+
+    /// Connects to the peers and exchanges blocks and transactions until the task is dropped
+    pub async fn run(mut self) {
+        info!("P2P node {} on network {}", self.local_peer_id(), self.blockchain.get_network_magic());
+        self.dial_bootstrap_peers();
+
+        let mut sync_timer = tokio::time::interval(SYNC_INTERVAL);
+        sync_timer.tick().await;
+
+        loop {
+            tokio::select! {
+                event = self.swarm.select_next_some() => self.handle_swarm_event(event).await,
+                Some(announcement) = self.announcements.recv() => self.announce(announcement),
+                Some(request) = self.sync_requests.recv() => self.send_sync_request(request),
+                Some(command) = self.commands.recv() => self.handle_command(command),
+                _ = sync_timer.tick() => {
+                    if self.peers.is_empty() {
+                        self.dial_bootstrap_peers();
+                    } else {
+                        self.request_sync(None);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Dials every bootstrap peer
+    fn dial_bootstrap_peers(&mut self) {
+        for address in self.discovery.bootstrap().to_vec() {
+            self.dial(address);
+        }
+    }
+
+    /// Dials a peer, logging a failure
+    fn dial(&mut self, address: Multiaddr) {
+        if let Err(err) = self.swarm.dial(address.clone()) {
+            warn!("Failed to dial peer {}: {}", address, err);
+        }
+    }
+
+    /// Carries out an operator's command
+    fn handle_command(&mut self, command: PeerCommand) {
+        match command {
+            PeerCommand::Dial(address) => match discovery::parse_peer_address(&address) {
+                Ok(address) => {
+                    info!("Dialing peer {} added by an operator", address);
+                    self.discovery.added(&address);
+                    self.dial(address);
+                }
+                Err(err) => warn!("Not dialing peer: {}", err),
+            },
+            PeerCommand::Disconnect { peer_id, ban } => {
+                let peer = match discovery::parse_peer_id(&peer_id) {
+                    Ok(peer) => peer,
+                    Err(err) => {
+                        warn!("Not disconnecting peer: {}", err);
+                        return;
+                    }
+                };
+                if ban {
+                    // Also closes the connections to the peer
+                    info!("Banning peer {}", peer);
+                    self.swarm.behaviour_mut().blocked.block_peer(peer);
+                } else if self.swarm.disconnect_peer_id(peer).is_ok() {
+                    info!("Disconnecting from peer {}", peer);
+                }
+            }
+        }
+    }
+
+    /// Handles an event of the swarm
+    async fn handle_swarm_event<E>(&mut self, event: SwarmEvent<BehaviourEvent, E>) {
+        match event {
+            SwarmEvent::NewListenAddr { address, .. } => {
+                info!("Listening for peers on {}/p2p/{}", address, self.local_peer_id());
+            }
+            SwarmEvent::ConnectionEstablished { peer_id, endpoint, num_established, .. } if num_established.get() == 1 => {
+                info!("Connected to peer {} at {}", peer_id, endpoint.get_remote_address());
+                self.discovery.connected(&peer_id, &endpoint);
+                self.peers.insert(peer_id);
+                self.request_sync(Some(peer_id));
+            }
+            SwarmEvent::ConnectionClosed { peer_id, num_established: 0, .. } => {
+                info!("Disconnected from peer {}", peer_id);
+                self.discovery.disconnected(&peer_id);
+                self.peers.remove(&peer_id);
+            }
+            SwarmEvent::OutgoingConnectionError { error, .. } => {
+                warn!("Failed to connect to a peer: {}", error);
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::Gossipsub(gossipsub::Event::Message {
+                propagation_source,
+                message_id,
+                message,
+            })) => {
+                let acceptance = self.handle_gossip(propagation_source, message).await;
+                // False if the message left the cache meanwhile, then it is simply not relayed
+                let _ = self.swarm.behaviour_mut().gossipsub.report_message_validation_result(
+                    &message_id,
+                    &propagation_source,
+                    acceptance,
+                );
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::Sync(event)) => self.handle_sync_event(event),
+            SwarmEvent::Behaviour(BehaviourEvent::Mdns(mdns::Event::Discovered(found))) => {
+                for address in self.discovery.mdns_discovered(found) {
+                    debug!("Found peer {} on the local network", address);
+                    self.dial(address);
+                }
+            }
+            SwarmEvent::Behaviour(BehaviourEvent::Mdns(mdns::Event::Expired(expired))) => {
+                self.discovery.mdns_expired(expired);
+            }
+            _ => {}
+        }
+    }
+
+    /// Gossips a block or transaction of the local chain
+    fn announce(&mut self, announcement: Announcement) {
+        let (topic, data, what) = match &announcement {
+            Announcement::Block(block) => (
+                self.blocks_topic.clone(),
+                serde_json::to_vec(block),
+                format!("block {}", block.index),
+            ),
+            Announcement::Transaction(transaction) => (
+                self.transactions_topic.clone(),
+                serde_json::to_vec(transaction),
+                format!("transaction {}", transaction.id),
+            ),
+        };
+        let data = match data {
+            Ok(data) => data,
+            Err(err) => {
+                warn!("Failed to encode {}: {}", what, err);
+                return;
+            }
+        };
+
+        match self.swarm.behaviour_mut().gossipsub.publish(topic, data) {
+            // Blocks and transactions that came from peers went around already,
+            // and without peers there is nobody to tell: they sync when they connect
+            Ok(_) | Err(gossipsub::PublishError::Duplicate) | Err(gossipsub::PublishError::InsufficientPeers) => {}
+            Err(err) => warn!("Failed to gossip {}: {}", what, err),
+        }
+    }
+
+    /// Handles a block or transaction gossiped by a peer
+    ///
+    /// # Returns
+    ///
+    /// Whether to relay the message to the other peers, or to penalize its source
+    async fn handle_gossip(&mut self, source: PeerId, message: gossipsub::Message) -> MessageAcceptance {
+        if message.topic == self.blocks_topic.hash() {
+            match serde_json::from_slice::<Block>(&message.data) {
+                Ok(block) => self.handle_block(source, block).await,
+                Err(err) => {
+                    debug!("Ignoring malformed block from {}: {}", source, err);
+                    MessageAcceptance::Reject
+                }
+            }
+        } else if message.topic == self.transactions_topic.hash() {
+            let transaction = match serde_json::from_slice::<Transaction>(&message.data) {
+                Ok(transaction) => transaction,
+                Err(err) => {
+                    debug!("Ignoring malformed transaction from {}: {}", source, err);
+                    return MessageAcceptance::Reject;
+                }
+            };
+
+            let id = transaction.id.clone();
+            match self.apply(move |blockchain| blockchain.submit_relayed_transaction(transaction)).await {
+                // Peers relayed it already, relaying it again would only echo it
+                Ok(Admission::Known) => {
+                    debug!("Transaction {} from {} is already known", id, source);
+                    MessageAcceptance::Ignore
+                }
+                Ok(_) => {
+                    debug!("Transaction {} from {} entered the mempool", id, source);
+                    MessageAcceptance::Accept
+                }
+                // Also known or already mined transactions, which are no fault of the peer
+                Err(err) => {
+                    debug!("Transaction {} from {} was not admitted: {}", id, source, err);
+                    MessageAcceptance::Ignore
+                }
+            }
+        } else {
+            MessageAcceptance::Ignore
+        }
+    }
+
+    /// Hands a gossiped block to the fork choice, or syncs if the node is missing its parent
+    ///
+    /// # Returns
+    ///
+    /// Whether to relay the block: blocks the node took are, known and orphan
+    /// blocks are not, and invalid blocks penalize the peer
+    async fn handle_block(&mut self, source: PeerId, block: Block) -> MessageAcceptance {
+        let index = block.index;
+        match self.apply(move |blockchain| blockchain.handle_incoming_block(block)).await {
+            Ok(BlockOutcome::Extended) => {
+                info!("Appended block {} from {}", index, source);
+                MessageAcceptance::Accept
+            }
+            Ok(BlockOutcome::Reorganized(reorg)) => {
+                info!(
+                    "Switched to the branch of block {} from {}, {} blocks replaced",
+                    index, source, reorg.depth
+                );
+                MessageAcceptance::Accept
+            }
+            Ok(BlockOutcome::Stored) => {
+                debug!("Kept block {} from {} on a competing branch", index, source);
+                MessageAcceptance::Accept
+            }
+            Ok(BlockOutcome::Known) => MessageAcceptance::Ignore,
+            // The node missed blocks, peers that have them need no relay
+            Ok(BlockOutcome::Orphan) => {
+                self.request_sync(Some(source));
+                MessageAcceptance::Ignore
+            }
+            Err(err) => {
+                warn!("Rejected block {} from {}: {}", index, source, err);
+                MessageAcceptance::Reject
+            }
+        }
+    }
+
+    /// Starts syncing from a peer, unless a sync is in progress
+    ///
+    /// # Arguments
+    ///
+    /// * `peer` - The peer to sync from, a random connected one if None
+    fn request_sync(&mut self, peer: Option<PeerId>) {
+        if self.sync.as_ref().is_some_and(|sync| !sync.is_finished()) {
+            return;
+        }
+        let peer = match peer.or_else(|| self.peers.iter().choose(&mut rand::thread_rng()).copied()) {
+            Some(peer) => peer,
+            None => return,
+        };
+
+        let remote = RemotePeer {
+            peer,
+            requests: self.sync_sender.clone(),
+        };
+        let blockchain = self.blockchain.clone();
+        self.sync = Some(tokio::spawn(async move {
+            match blockchain.sync_from(&remote).await {
+                Ok(report) if report.blocks > 0 => info!(
+                    "Synced {} blocks from {} up to height {}, {} reorganizations",
+                    report.blocks,
+                    peer,
+                    report.height,
+                    report.reorgs.len()
+                ),
+                Ok(_) => debug!("Up to date with peer {}", peer),
+                Err(err) => warn!("Sync with peer {} failed: {}", peer, err),
+            }
+        }));
+    }
+
+    /// Sends a request of the sync task to its peer
+    fn send_sync_request(&mut self, request: PeerRequest) {
+        let id = self.swarm.behaviour_mut().sync.send_request(&request.peer, request.request);
+        self.pending_requests.insert(id, request.reply);
+    }
+
+    /// Answers a sync request of a peer, or hands the answer to one of the node's to the sync task
+    fn handle_sync_event(&mut self, event: request_response::Event<SyncRequest, SyncResponse>) {
+        match event {
+            request_response::Event::Message {
+                peer,
+                message: request_response::Message::Request { request, channel, .. },
+            } => {
+                let response = match request {
+                    SyncRequest::Headers { locator, limit } => {
+                        match self.blockchain.get_headers_after_locator(&locator, limit.min(MAX_SYNC_HEADERS)) {
+                            Ok(headers) => SyncResponse::Headers(headers),
+                            Err(err) => {
+                                warn!("Failed to read the headers peer {} asked for: {}", peer, err);
+                                return;
+                            }
+                        }
+                    }
+                    SyncRequest::Blocks { hashes } => {
+                        let hashes = &hashes[..hashes.len().min(MAX_SYNC_BLOCKS)];
+                        SyncResponse::Blocks(self.blockchain.get_blocks_by_hash(hashes))
+                    }
+                };
+                if self.swarm.behaviour_mut().sync.send_response(channel, response).is_err() {
+                    debug!("Peer {} went away before its sync request was answered", peer);
+                }
+            }
+            request_response::Event::Message {
+                message: request_response::Message::Response { request_id, response },
+                ..
+            } => {
+                if let Some(reply) = self.pending_requests.remove(&request_id) {
+                    let _ = reply.send(Ok(response));
+                }
+            }
+            request_response::Event::OutboundFailure { peer, request_id, error } => {
+                if let Some(reply) = self.pending_requests.remove(&request_id) {
+                    let _ = reply.send(Err(BlockchainError::SystemError(format!(
+                        "Request to peer {} failed: {}",
+                        peer, error
+                    ))));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Runs a blocking call of the blockchain off the async runtime
+    ///
+    /// The returned future doesn't borrow the service, whose swarm can't be
+    /// shared between threads, so the service stays spawnable.
+    fn apply<T, F>(&self, call: F) -> impl std::future::Future<Output = Result<T, BlockchainError>> + Send + 'static
+    where
+        T: Send + 'static,
+        F: FnOnce(&Blockchain) -> Result<T, BlockchainError> + Send + 'static,
+    {
+        let blockchain = self.blockchain.clone();
+        async move {
+            tokio::task::spawn_blocking(move || call(&blockchain))
+                .await
+                .map_err(|err| BlockchainError::SystemError(err.to_string()))?
+        }
+    }
+}
+
+/// Builds the protocols of a node of a network
+///
+/// # Arguments
+///
+/// * `key` - The node's identity key, signing its gossip
+/// * `magic` - The magic bytes of the network the protocols are named after
+/// * `enable_mdns` - Whether to find peers on the local network
+fn build_behaviour(
+    key: &identity::Keypair,
+    magic: &NetworkMagic,
+    enable_mdns: bool,
+) -> Result<Behaviour, Box<dyn std::error::Error + Send + Sync>> {
+    // Messages are identified by their content, so a block or transaction is
+    // relayed once however many nodes announce it, and only after the node
+    // validated it
+    let config = gossipsub::ConfigBuilder::default()
+        .validation_mode(ValidationMode::Strict)
+        .validate_messages()
+        .message_id_fn(|message: &gossipsub::Message| gossipsub::MessageId::new(&Sha256::digest(&message.data)))
+        .max_transmit_size(MAX_GOSSIP_BYTES)
+        .build()?;
+    let gossipsub = gossipsub::Behaviour::new(MessageAuthenticity::Signed(key.clone()), config)?;
+
+    let sync = request_response::json::Behaviour::new(
+        [(protocol::sync_protocol(magic), ProtocolSupport::Full)],
+        request_response::Config::default(),
+    );
+
+    let mdns = if enable_mdns {
+        Some(mdns::tokio::Behaviour::new(mdns::Config::default(), key.public().to_peer_id())?)
+    } else {
+        None
+    };
+
+    Ok(Behaviour {
+        gossipsub,
+        sync,
+        mdns: mdns.into(),
+        blocked: allow_block_list::Behaviour::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blockchain::peers::PeerSource;
+
+    /// Waits until a condition holds, failing the test after a while
+    async fn wait_until(condition: impl Fn() -> bool) {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(20);
+        while !condition() {
+            assert!(tokio::time::Instant::now() < deadline, "the nodes did not converge");
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_nodes_converge_on_one_chain() {
+        let ahead = Arc::new(Blockchain::new());
+        ahead.mine_block("miner").unwrap();
+        ahead.mine_block("miner").unwrap();
+
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let address = format!("/ip4/127.0.0.1/tcp/{}", port);
+        let config = NetworkConfig::parse(Some(&address), &[], false).unwrap();
+        tokio::spawn(NetworkService::new(config, ahead.clone()).unwrap().run());
+
+        // A new node catches up as soon as it connects
+        let behind = Arc::new(Blockchain::new());
+        let config = NetworkConfig::parse(None, &[address], false).unwrap();
+        tokio::spawn(NetworkService::new(config, behind.clone()).unwrap().run());
+        wait_until(|| behind.get_last_block().hash == ahead.get_last_block().hash).await;
+
+        // and then follows the blocks gossiped to it
+        let mined = ahead.clone();
+        let block = tokio::task::spawn_blocking(move || mined.mine_block("miner")).await.unwrap().unwrap();
+        wait_until(|| behind.get_last_block().hash == block.hash).await;
+        assert_eq!(behind.get_block_locator(), ahead.get_block_locator());
+
+        // Both nodes know each other, the new one found the other through its configuration
+        assert_eq!(behind.get_peers().len(), 1);
+        assert_eq!(behind.get_peers()[0].source, PeerSource::Bootstrap);
+        assert_eq!(ahead.get_peers()[0].source, PeerSource::Inbound);
+    }
+}
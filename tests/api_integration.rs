@@ -0,0 +1,368 @@
+// End-to-end tests of the REST API
+//
+// Each test runs the actix app in-process around an in-memory node, with the
+// same components the node binary registers, and talks to it over HTTP.
+
+use actix_web::{test, web, App};
+use serde_json::{json, Value};
+
+use my_blockchain::api::{self, auth::AdminAuth, limits::MiningLimiter, mining::{BlockTemplates, MiningJobs}};
+use my_blockchain::blockchain::audit::AuditLog;
+use my_blockchain::blockchain::keystore::Keystore;
+use my_blockchain::blockchain::labels::LabelStore;
+use my_blockchain::blockchain::node_keys::NodeKeys;
+use my_blockchain::blockchain::payments::PaymentRegistry;
+use my_blockchain::blockchain::snapshots::SnapshotStore;
+use my_blockchain::blockchain::withdrawals::WithdrawalQueue;
+use my_blockchain::blockchain::{Address, Block, Blockchain, Transaction, Wallet};
+
+/// Token the administrative endpoints of the test app accept
+const ADMIN_TOKEN: &str = "integration-test-token";
+
+/// Starts the app around a fresh in-memory node, or around the node given
+macro_rules! test_app {
+    () => {
+        test_app!(Blockchain::new())
+    };
+    ($blockchain:expr) => {{
+        let audit = AuditLog::new(None).unwrap();
+        let withdrawals = WithdrawalQueue::new(Wallet::new().unwrap(), 0.0, audit.clone(), None).unwrap();
+        let node_keys = NodeKeys::new(None, audit.clone(), chrono::Duration::seconds(60)).unwrap();
+        let snapshot_dir = tempfile::tempdir().unwrap();
+        let snapshots = SnapshotStore::new(snapshot_dir.path(), 1).unwrap();
+
+        test::init_service(
+            App::new()
+                .app_data(web::Data::new($blockchain))
+                .app_data(web::Data::new(PaymentRegistry::new(None).unwrap()))
+                .app_data(web::Data::new(withdrawals))
+                .app_data(web::Data::new(audit))
+                .app_data(web::Data::new(AdminAuth::new(Some(ADMIN_TOKEN.to_string()))))
+                .app_data(web::Data::new(MiningLimiter::new(1)))
+                .app_data(web::Data::new(MiningJobs::new()))
+                .app_data(web::Data::new(BlockTemplates::new()))
+                .app_data(web::Data::new(Keystore::new(None).unwrap()))
+                .app_data(web::Data::new(LabelStore::new(None).unwrap()))
+                .app_data(web::Data::new(node_keys))
+                .app_data(web::Data::new(snapshots))
+                // Removes the snapshot directory along with the app
+                .app_data(snapshot_dir)
+                .configure(api::configure_routes),
+        )
+        .await
+    }};
+}
+
+/// Sends a request and returns the status and the JSON body
+macro_rules! call {
+    ($app:expr, $request:expr) => {{
+        let response = test::call_service(&$app, $request.to_request()).await;
+        let status = response.status().as_u16();
+        let body = test::read_body(response).await;
+        (status, serde_json::from_slice::<Value>(&body).unwrap_or(Value::Null))
+    }};
+}
+
+#[actix_web::test]
+async fn test_payment_flow_from_wallet_to_valid_chain() {
+    let app = test_app!();
+
+    // Create wallets
+    let (status, alice) = call!(app, test::TestRequest::post().uri("/api/v1/wallet/new"));
+    assert_eq!(status, 201);
+    let (_, bob) = call!(app, test::TestRequest::post().uri("/api/v1/wallet/new"));
+    let (_, miner) = call!(app, test::TestRequest::post().uri("/api/v1/wallet/new"));
+
+    // Fund the sender
+    let (status, funded) = call!(
+        app,
+        test::TestRequest::post()
+            .uri("/api/v1/wallet/fund")
+            .set_json(json!({ "address": alice["address"], "amount": 100.0 }))
+    );
+    assert_eq!(status, 200);
+    assert_eq!(funded["new_balance"], 100.0);
+
+    // Submit a transaction
+    let (status, submitted) = call!(
+        app,
+        test::TestRequest::post().uri("/api/v1/transactions/new").set_json(json!({
+            "sender": alice["address"],
+            "recipient": bob["address"],
+            "amount": 10.0,
+            "fee": 0.5,
+            "private_key": alice["private_key"],
+        }))
+    );
+    assert_eq!(status, 201, "{}", submitted);
+    assert_eq!(submitted["block_index"], 1);
+    let (_, pending) = call!(app, test::TestRequest::get().uri("/api/v1/transactions/pending"));
+    assert_eq!(pending.as_array().unwrap().len(), 1);
+    assert!(pending[0]["received_at"].is_string(), "{}", pending);
+
+    // Mine it
+    let (status, mined) = call!(
+        app,
+        test::TestRequest::post()
+            .uri("/api/v1/mine")
+            .set_json(json!({ "miner_address": miner["address"] }))
+    );
+    assert_eq!(status, 200, "{}", mined);
+    assert_eq!(mined["block"]["index"], 1);
+    assert_eq!(mined["block"]["transactions"].as_array().unwrap().len(), 2);
+    let (_, pending) = call!(app, test::TestRequest::get().uri("/api/v1/transactions/pending"));
+    assert!(pending.as_array().unwrap().is_empty());
+
+    // Verify balances, the base fee is burned by default and the rest of the fee is the miner's tip
+    let balance = |wallet: &Value| format!("/api/v1/wallet/balance/{}", wallet["address"].as_str().unwrap());
+    let (_, account) = call!(app, test::TestRequest::get().uri(&balance(&alice)));
+    assert_eq!(account["balance"], 89.5);
+    assert_eq!(account["nonce"], 1);
+    let (_, account) = call!(app, test::TestRequest::get().uri(&balance(&bob)));
+    assert_eq!(account["balance"], 10.0);
+    let (_, account) = call!(app, test::TestRequest::get().uri(&balance(&miner)));
+    assert!((account["balance"].as_f64().unwrap() - 50.49).abs() < 1e-9);
+
+    // Validate the chain
+    let (status, valid) = call!(app, test::TestRequest::get().uri("/api/v1/validate"));
+    assert_eq!(status, 200);
+    assert_eq!(valid, true);
+    let (_, chain) = call!(app, test::TestRequest::get().uri("/api/v1/chain"));
+    assert_eq!(chain["length"], 2);
+    assert_eq!(chain["is_valid"], true);
+
+    // The ledger balances out and agrees with the balances
+    let response = test::call_service(
+        &app,
+        test::TestRequest::get().uri("/api/v1/ledger/export?format=jsonl").to_request(),
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+    let lines: Vec<Value> = body.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    let sum = |field: &str, account: Option<&str>| -> f64 {
+        lines
+            .iter()
+            .filter(|line| account.is_none_or(|account| line["account"] == account))
+            .map(|line| line[field].as_f64().unwrap())
+            .sum()
+    };
+    assert!((sum("credit", None) - sum("debit", None)).abs() < 1e-9);
+    let alice_address = alice["address"].as_str().unwrap();
+    assert!((sum("credit", Some(alice_address)) - sum("debit", Some(alice_address)) - 89.5).abs() < 1e-9);
+    assert!((sum("credit", Some("@burn")) - 0.01).abs() < 1e-9);
+}
+
+#[actix_web::test]
+async fn test_invalid_requests_are_rejected() {
+    let app = test_app!();
+    let (_, alice) = call!(app, test::TestRequest::post().uri("/api/v1/wallet/new"));
+    let (_, mallory) = call!(app, test::TestRequest::post().uri("/api/v1/wallet/new"));
+
+    let transfer = |private_key: &Value| {
+        test::TestRequest::post().uri("/api/v1/transactions/new").set_json(json!({
+            "sender": alice["address"],
+            "recipient": mallory["address"],
+            "amount": 10.0,
+            "fee": 0.5,
+            "private_key": private_key,
+        }))
+    };
+
+    // Nothing to spend yet
+    let (status, rejected) = call!(app, transfer(&alice["private_key"]));
+    assert_eq!(status, 400);
+    assert_eq!(rejected["code"], "INSUFFICIENT_FUNDS");
+    assert_eq!(rejected["available"], 0.0);
+
+    // Someone else's key can't spend the funds
+    call!(
+        app,
+        test::TestRequest::post()
+            .uri("/api/v1/wallet/fund")
+            .set_json(json!({ "address": alice["address"], "amount": 100.0 }))
+    );
+    let (status, rejected) = call!(app, transfer(&mallory["private_key"]));
+    assert_eq!(status, 400);
+    assert_eq!(rejected["code"], "INVALID_KEY");
+    let (status, _) = call!(app, transfer(&json!("not hex")));
+    assert_eq!(status, 400);
+    let (_, pending) = call!(app, test::TestRequest::get().uri("/api/v1/transactions/pending"));
+    assert!(pending.as_array().unwrap().is_empty());
+
+    // Administrative endpoints need the token
+    let (status, rejected) = call!(app, test::TestRequest::get().uri("/api/v1/admin/audit"));
+    assert_eq!(status, 401);
+    assert_eq!(rejected["code"], "UNAUTHORIZED");
+    let (status, _) = call!(
+        app,
+        test::TestRequest::get()
+            .uri("/api/v1/admin/audit")
+            .insert_header(("Authorization", format!("Bearer {}", ADMIN_TOKEN)))
+    );
+    assert_eq!(status, 200);
+}
+
+#[actix_web::test]
+async fn test_batch_reports_each_transaction() {
+    let app = test_app!();
+    let sender = Wallet::new().unwrap();
+    call!(
+        app,
+        test::TestRequest::post()
+            .uri("/api/v1/wallet/fund")
+            .set_json(json!({ "address": sender.address().0, "amount": 5.0 }))
+    );
+
+    let sign = |nonce: u64, amount: f64| {
+        let mut transaction = Transaction::new(sender.address().clone(), Address("bob".to_string()), amount, 0.1, nonce);
+        transaction.sign(&sender).unwrap();
+        transaction
+    };
+    let batch = |transactions: Vec<Transaction>| {
+        test::TestRequest::post()
+            .uri("/api/v1/transactions/batch")
+            .set_json(json!({ "transactions": transactions }))
+    };
+
+    // The second nonce overdraws, so the third waits for it
+    let (status, report) = call!(app, batch(vec![sign(0, 1.0), sign(1, 10.0), sign(2, 1.0)]));
+    assert_eq!(status, 200, "{}", report);
+    let statuses: Vec<&Value> = report["results"].as_array().unwrap().iter().map(|result| &result["status"]).collect();
+    assert_eq!(statuses, vec!["accepted", "rejected", "queued"]);
+    assert_eq!(report["results"][1]["code"], "INSUFFICIENT_FUNDS");
+    assert_eq!(report["rejected"], 1);
+
+    // Fixing it admits the queued transaction too
+    let (_, report) = call!(app, batch(vec![sign(1, 1.0)]));
+    assert_eq!(report["admitted"], 1);
+    let (_, pending) = call!(app, test::TestRequest::get().uri("/api/v1/transactions/pending"));
+    assert_eq!(pending.as_array().unwrap().len(), 3);
+
+    let (status, rejected) = call!(app, batch(Vec::new()));
+    assert_eq!(status, 400);
+    assert_eq!(rejected["code"], "INVALID_REQUEST");
+}
+
+#[actix_web::test]
+async fn test_block_headers_filter_by_address() {
+    let app = test_app!();
+    let (_, alice) = call!(app, test::TestRequest::post().uri("/api/v1/wallet/new"));
+    let (_, miner) = call!(app, test::TestRequest::post().uri("/api/v1/wallet/new"));
+    for _ in 0..2 {
+        call!(
+            app,
+            test::TestRequest::post()
+                .uri("/api/v1/mine")
+                .set_json(json!({ "miner_address": miner["address"] }))
+        );
+    }
+
+    let (status, headers) = call!(app, test::TestRequest::get().uri("/api/v1/blocks/headers?from=1"));
+    assert_eq!(status, 200, "{}", headers);
+    assert_eq!(headers.as_array().unwrap().len(), 2);
+    assert_eq!(headers[0]["index"], 1);
+    assert_eq!(headers[0]["transaction_count"], 1);
+    assert_eq!(headers[0]["address_bloom"].as_str().unwrap().len(), 512);
+
+    // Only the miner's blocks may involve the miner, none involve a fresh wallet
+    let uri = |wallet: &Value| format!("/api/v1/blocks/headers?address={}", wallet["address"].as_str().unwrap());
+    let (_, headers) = call!(app, test::TestRequest::get().uri(&uri(&miner)));
+    let indexes: Vec<&Value> = headers.as_array().unwrap().iter().map(|header| &header["index"]).collect();
+    assert_eq!(indexes, vec![1, 2]);
+    let (_, headers) = call!(app, test::TestRequest::get().uri(&uri(&alice)));
+    assert!(headers.as_array().unwrap().is_empty());
+
+    let (status, rejected) = call!(app, test::TestRequest::get().uri("/api/v1/blocks/headers?count=0"));
+    assert_eq!(status, 400);
+    assert_eq!(rejected["code"], "INVALID_REQUEST");
+}
+
+#[actix_web::test]
+async fn test_background_mining_reports_its_job() {
+    // An easy difficulty so the block is sealed while the test follows it
+    let mut node = Blockchain::new();
+    node.set_difficulty(1);
+    let app = test_app!(node);
+    let (_, miner) = call!(app, test::TestRequest::post().uri("/api/v1/wallet/new"));
+
+    let (status, job) = call!(
+        app,
+        test::TestRequest::post()
+            .uri("/api/v1/mine?background=true")
+            .set_json(json!({ "miner_address": miner["address"] }))
+    );
+    assert_eq!(status, 202, "{}", job);
+    assert_eq!(job["status"], "queued");
+
+    // Follow the job until its block is sealed
+    let uri = format!("/api/v1/mine/status/{}", job["id"].as_str().unwrap());
+    let mut job = job;
+    for _ in 0..200 {
+        let (status, current) = call!(app, test::TestRequest::get().uri(&uri));
+        assert_eq!(status, 200, "{}", current);
+        job = current;
+        if job["status"] == "mined" || job["status"] == "failed" {
+            break;
+        }
+        actix_web::rt::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert_eq!(job["status"], "mined", "{}", job);
+    assert_eq!(job["block_index"], 1);
+    let (_, chain) = call!(app, test::TestRequest::get().uri("/api/v1/chain"));
+    assert_eq!(chain["length"], 2);
+
+    let (status, missing) = call!(app, test::TestRequest::get().uri("/api/v1/mine/status/unknown"));
+    assert_eq!(status, 404);
+    assert_eq!(missing["code"], "NOT_FOUND");
+}
+
+#[actix_web::test]
+async fn test_external_miner_seals_a_template() {
+    // An easy difficulty, the test is the miner
+    let mut node = Blockchain::new();
+    node.set_difficulty(1);
+    let app = test_app!(node);
+    let (_, miner) = call!(app, test::TestRequest::post().uri("/api/v1/wallet/new"));
+    let uri = format!("/api/v1/mining/template?miner_address={}", miner["address"].as_str().unwrap());
+    let (status, template) = call!(app, test::TestRequest::get().uri(&uri));
+    assert_eq!(status, 200, "{}", template);
+    assert_eq!(template["block"]["index"], 1);
+    assert_eq!(template["target"], "0".repeat(template["difficulty"].as_u64().unwrap() as usize));
+
+    // The miner searches for the proof on its own
+    let mut block: Block = serde_json::from_value(template["block"].clone()).unwrap();
+    let target = template["target"].as_str().unwrap();
+    while !block.calculate_hash().starts_with(target) {
+        block.proof += 1;
+    }
+    let solution = json!({
+        "template_id": template["template_id"],
+        "proof": block.proof,
+        "hash": block.calculate_hash(),
+    });
+
+    let mut wrong = solution.clone();
+    wrong["hash"] = json!("00");
+    let (status, wrong) = call!(app, test::TestRequest::post().uri("/api/v1/mining/submit").set_json(&wrong));
+    assert_eq!(status, 400, "{}", wrong);
+    assert_eq!(wrong["code"], "INVALID_BLOCK");
+
+    let (status, accepted) = call!(app, test::TestRequest::post().uri("/api/v1/mining/submit").set_json(&solution));
+    assert_eq!(status, 200, "{}", accepted);
+    assert_eq!(accepted["block"]["hash"], solution["hash"]);
+    let (_, balance) = call!(
+        app,
+        test::TestRequest::get().uri(&format!("/api/v1/wallet/balance/{}", miner["address"].as_str().unwrap()))
+    );
+    assert_eq!(balance["balance"], 50.0);
+
+    // The block took the tip, so the template is stale
+    let (status, stale) = call!(app, test::TestRequest::post().uri("/api/v1/mining/submit").set_json(&solution));
+    assert_eq!(status, 409, "{}", stale);
+    assert_eq!(stale["code"], "STALE_TIP");
+
+    let unknown = json!({ "template_id": "unknown", "proof": 0, "hash": "00" });
+    let (status, _) = call!(app, test::TestRequest::post().uri("/api/v1/mining/submit").set_json(&unknown));
+    assert_eq!(status, 404);
+}